@@ -1,18 +1,606 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
-use std::path::PathBuf;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+#[path = "build/zone_table.rs"]
+mod zone_table;
+
+const TRANSLATIONS_DIR: &str = "translations";
+/// Catalog entries with this context are looked up by `i18n::tr`; every other
+/// context belongs to a Slint component.
+const RUST_CONTEXT: &str = "rust";
+const CJK_FONT: &str = "fonts/NotoSansSC-Regular.otf";
+const BOARDS_DIR: &str = "boards";
+const ZONES_FILE: &str = "timezones.txt";
+const DEFAULT_BOARD: &str = "n16r8";
+/// GPIOs the ESP32-S3 exposes.
+const MAX_GPIO: u8 = 48;
+/// Wired to the in-package flash and PSRAM.
+const RESERVED_GPIOS: std::ops::RangeInclusive<u8> = 26..=32;
+/// ADC1 only; ADC2 is unusable while the radio is on.
+const ADC1_GPIOS: std::ops::RangeInclusive<u8> = 1..=10;
+/// CST816S, which owns the touch bus the IMU shares.
+const TOUCH_I2C_ADDR: u8 = 0x15;
+/// The SPI peripheral tops out at the 80 MHz APB clock.
+const DISPLAY_SPI_KHZ: std::ops::RangeInclusive<u32> = 1_000..=80_000;
+/// Base sizes of the `Theme` and `Marquees` text tokens, in px.
+const SCALED_FONT_SIZES: [u32; 7] = [9, 10, 11, 12, 13, 14, 15];
+/// `theme::UiScale` in percent; the sizes are rounded as `theme.slint` does.
+const UI_SCALES: [u32; 4] = [90, 100, 115, 130];
+/// `theme::MIN_FONT` choices.
+const MIN_FONT_SIZES: std::ops::RangeInclusive<u32> = 9..=12;
 
 fn main() {
     emit_priv_cfg_flag();
 
     embuild::espidf::sysenv::output();
 
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set"));
+    write_board_config(&out_dir);
+    write_zone_table(&out_dir);
+    let catalogs = load_catalogs();
+    write_rust_catalog(&out_dir, &catalogs);
+    write_glyph_subset(&out_dir, &catalogs);
+    embed_scaled_font_sizes();
+
     let config = slint_build::CompilerConfiguration::new()
-        .embed_resources(slint_build::EmbedResourcesKind::EmbedForSoftwareRenderer);
+        .embed_resources(slint_build::EmbedResourcesKind::EmbedForSoftwareRenderer)
+        .with_bundled_translations(TRANSLATIONS_DIR)
+        .with_include_paths(vec![out_dir]);
 
     slint_build::compile_with_config("src/gui/app.slint", config)
         .expect("slint UI compilation failed");
 }
 
+/// The Slint compiler embeds glyphs for the font sizes it can evaluate at
+/// build time plus those in `SLINT_FONT_SIZES`. The text tokens depend on
+/// the UI scale at run time, so every size they can take is listed here.
+fn embed_scaled_font_sizes() {
+    println!("cargo:rerun-if-env-changed=SLINT_FONT_SIZES");
+    let mut sizes: BTreeSet<u32> = UI_SCALES
+        .iter()
+        .flat_map(|scale| {
+            SCALED_FONT_SIZES
+                .iter()
+                .map(move |size| (f64::from(size * scale) / 100.0).round() as u32)
+        })
+        .chain(MIN_FONT_SIZES)
+        .collect();
+    if let Ok(extra) = env::var("SLINT_FONT_SIZES") {
+        sizes.extend(extra.split(',').filter_map(|size| size.trim().parse().ok()));
+    }
+    let list: Vec<String> = sizes.iter().map(u32::to_string).collect();
+    env::set_var("SLINT_FONT_SIZES", list.join(","));
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoardManifest {
+    name: String,
+    panel: String,
+    psram_mb: u32,
+    #[serde(default)]
+    features: Vec<String>,
+    battery_adc: Option<u8>,
+    piezo: Option<u8>,
+    side_key: Option<u8>,
+    /// 7-bit address of an accelerometer on the touch I2C bus.
+    imu_addr: Option<u8>,
+    display: DisplayManifest,
+    /// Left out on boards that navigate with the analog stick alone.
+    touch: Option<TouchManifest>,
+    encoder: Option<EncoderManifest>,
+    analog: Option<AnalogManifest>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DisplayManifest {
+    backlight: u8,
+    rst: u8,
+    dc: u8,
+    cs: u8,
+    mosi: u8,
+    sclk: u8,
+    #[serde(default = "default_spi_khz")]
+    spi_khz: u32,
+    #[serde(default)]
+    swap_bytes: bool,
+    #[serde(default)]
+    swap_rb: bool,
+}
+
+fn default_spi_khz() -> u32 {
+    40_000
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TouchManifest {
+    sda: u8,
+    scl: u8,
+    int: u8,
+    rst: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EncoderManifest {
+    a: u8,
+    b: u8,
+    button: Option<u8>,
+}
+
+/// A two-axis stick on ADC1 with an optional click to GND.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnalogManifest {
+    x: u8,
+    y: u8,
+    click: Option<u8>,
+    #[serde(default)]
+    invert_x: bool,
+    #[serde(default)]
+    invert_y: bool,
+    /// Share of each half-axis ignored around the center.
+    #[serde(default = "default_deadzone_pct")]
+    deadzone_pct: u8,
+    /// Raw `[min, center, max]` until the stick is calibrated on the device.
+    #[serde(default = "default_axis_calibration")]
+    x_cal: [u16; 3],
+    #[serde(default = "default_axis_calibration")]
+    y_cal: [u16; 3],
+}
+
+fn default_deadzone_pct() -> u8 {
+    15
+}
+
+fn default_axis_calibration() -> [u16; 3] {
+    [0, 2048, 4095]
+}
+
+impl BoardManifest {
+    /// Every pin the board claims, labelled the way the manifest spells it.
+    fn pins(&self) -> Vec<(String, u8)> {
+        let d = &self.display;
+        let mut pins = vec![
+            ("display.backlight".to_string(), d.backlight),
+            ("display.rst".to_string(), d.rst),
+            ("display.dc".to_string(), d.dc),
+            ("display.cs".to_string(), d.cs),
+            ("display.mosi".to_string(), d.mosi),
+            ("display.sclk".to_string(), d.sclk),
+        ];
+        if let Some(t) = &self.touch {
+            pins.push(("touch.sda".to_string(), t.sda));
+            pins.push(("touch.scl".to_string(), t.scl));
+            pins.push(("touch.int".to_string(), t.int));
+            pins.push(("touch.rst".to_string(), t.rst));
+        }
+        pins.extend(self.battery_adc.map(|pin| ("battery_adc".to_string(), pin)));
+        pins.extend(self.piezo.map(|pin| ("piezo".to_string(), pin)));
+        pins.extend(self.side_key.map(|pin| ("side_key".to_string(), pin)));
+        if let Some(encoder) = &self.encoder {
+            pins.push(("encoder.a".to_string(), encoder.a));
+            pins.push(("encoder.b".to_string(), encoder.b));
+            pins.extend(
+                encoder
+                    .button
+                    .map(|pin| ("encoder.button".to_string(), pin)),
+            );
+        }
+        if let Some(analog) = &self.analog {
+            pins.push(("analog.x".to_string(), analog.x));
+            pins.push(("analog.y".to_string(), analog.y));
+            pins.extend(analog.click.map(|pin| ("analog.click".to_string(), pin)));
+        }
+        pins
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if panel_variant(&self.panel).is_none() {
+            problems.push(format!("panel \"{}\" is not supported", self.panel));
+        }
+        let mut owners: BTreeMap<u8, String> = BTreeMap::new();
+        for (label, pin) in self.pins() {
+            if pin > MAX_GPIO {
+                problems.push(format!("{label}: GPIO{pin} does not exist"));
+            } else if RESERVED_GPIOS.contains(&pin) {
+                problems.push(format!("{label}: GPIO{pin} is wired to flash/PSRAM"));
+            }
+            if let Some(owner) = owners.get(&pin) {
+                problems.push(format!("{label}: GPIO{pin} is already used by {owner}"));
+            } else {
+                owners.insert(pin, label);
+            }
+        }
+        if let Some(pin) = self.battery_adc {
+            if !ADC1_GPIOS.contains(&pin) {
+                problems.push(format!("battery_adc: GPIO{pin} is not an ADC1 pin"));
+            }
+        }
+        if self.touch.is_none() && self.analog.is_none() {
+            problems.push("needs [touch] or [analog] for input".to_string());
+        }
+        if let Some(analog) = &self.analog {
+            for (axis, pin, cal) in [("x", analog.x, analog.x_cal), ("y", analog.y, analog.y_cal)] {
+                if !ADC1_GPIOS.contains(&pin) {
+                    problems.push(format!("analog.{axis}: GPIO{pin} is not an ADC1 pin"));
+                }
+                if !(cal[0] < cal[1] && cal[1] < cal[2] && cal[2] <= 4095) {
+                    problems.push(format!(
+                        "analog.{axis}_cal: {cal:?} is not increasing within 0-4095"
+                    ));
+                }
+            }
+            if analog.deadzone_pct >= 100 {
+                problems.push(format!(
+                    "analog.deadzone_pct: {} leaves no travel",
+                    analog.deadzone_pct
+                ));
+            }
+        }
+        if let Some(addr) = self.imu_addr {
+            if self.touch.is_none() {
+                problems.push("imu_addr: the IMU sits on the touch I2C bus".to_string());
+            } else if !(0x08..=0x77).contains(&addr) {
+                problems.push(format!(
+                    "imu_addr: 0x{addr:02X} is not a 7-bit device address"
+                ));
+            } else if addr == TOUCH_I2C_ADDR {
+                problems.push(format!("imu_addr: 0x{addr:02X} is the touch controller"));
+            }
+        }
+        if !DISPLAY_SPI_KHZ.contains(&self.display.spi_khz) {
+            problems.push(format!(
+                "display.spi_khz: {} is outside {}-{} kHz",
+                self.display.spi_khz,
+                DISPLAY_SPI_KHZ.start(),
+                DISPLAY_SPI_KHZ.end()
+            ));
+        }
+        problems
+    }
+}
+
+fn panel_variant(panel: &str) -> Option<&'static str> {
+    match panel {
+        "gc9a01" => Some("Gc9a01"),
+        _ => None,
+    }
+}
+
+/// `ASTROBOX_BOARD` wins, then a `board-<stem>` cargo feature, then the
+/// reference board.
+fn selected_board() -> String {
+    println!("cargo:rerun-if-env-changed=ASTROBOX_BOARD");
+    if let Ok(board) = env::var("ASTROBOX_BOARD") {
+        return board;
+    }
+    let features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_BOARD_")
+                .map(|stem| stem.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    match features.as_slice() {
+        [] => DEFAULT_BOARD.to_string(),
+        [board] => board.clone(),
+        _ => panic!(
+            "more than one board feature enabled: {}",
+            features.join(", ")
+        ),
+    }
+}
+
+fn load_board(board: &str) -> BoardManifest {
+    println!("cargo:rerun-if-changed={BOARDS_DIR}");
+    let path = Path::new(BOARDS_DIR).join(format!("{board}.toml"));
+    println!("cargo:rerun-if-changed={}", path.display());
+    let source = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("board \"{board}\": cannot read {}: {err}", path.display()));
+    let manifest: BoardManifest =
+        toml::from_str(&source).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+    let problems = manifest.problems();
+    if !problems.is_empty() {
+        panic!(
+            "{} is invalid:\n  - {}",
+            path.display(),
+            problems.join("\n  - ")
+        );
+    }
+    for feature in &manifest.features {
+        let key = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+        if env::var_os(key).is_none() {
+            println!("cargo:warning=board {board} expects feature `{feature}`, which is disabled");
+        }
+    }
+    manifest
+}
+
+/// Emits the `CURRENT` constant `board.rs` includes; the types live there.
+fn write_board_config(out_dir: &Path) {
+    let board = selected_board();
+    let manifest = load_board(&board);
+    let panel = panel_variant(&manifest.panel).expect("validated above");
+    let optional = |pin: Option<u8>| match pin {
+        Some(pin) => format!("Some({pin})"),
+        None => "None".to_string(),
+    };
+    let encoder = match &manifest.encoder {
+        Some(encoder) => format!(
+            "Some(EncoderGpios {{ a: {}, b: {}, button: {} }})",
+            encoder.a,
+            encoder.b,
+            optional(encoder.button)
+        ),
+        None => "None".to_string(),
+    };
+    let touch = match &manifest.touch {
+        Some(t) => format!(
+            "Some(TouchGpios {{ sda: {}, scl: {}, int: {}, rst: {} }})",
+            t.sda, t.scl, t.int, t.rst
+        ),
+        None => "None".to_string(),
+    };
+    let calibration = |[min, center, max]: [u16; 3]| {
+        format!("AxisCalibration {{ min: {min}, center: {center}, max: {max} }}")
+    };
+    let analog = match &manifest.analog {
+        Some(analog) => format!(
+            "Some(AnalogGpios {{ x: {}, y: {}, click: {}, invert_x: {}, invert_y: {}, \
+             deadzone_pct: {}, x_cal: {}, y_cal: {} }})",
+            analog.x,
+            analog.y,
+            optional(analog.click),
+            analog.invert_x,
+            analog.invert_y,
+            analog.deadzone_pct,
+            calibration(analog.x_cal),
+            calibration(analog.y_cal)
+        ),
+        None => "None".to_string(),
+    };
+    let d = &manifest.display;
+    let source = format!(
+        "/// Generated from {BOARDS_DIR}/{board}.toml.\n\
+         pub const CURRENT: BoardConfig = BoardConfig {{\n    \
+             id: {board:?},\n    \
+             name: {name:?},\n    \
+             panel: PanelKind::{panel},\n    \
+             psram_bytes: {psram} * 1024 * 1024,\n    \
+             features: &{features:?},\n    \
+             display: DisplayGpios {{ backlight: {}, rst: {}, dc: {}, cs: {}, mosi: {}, sclk: {} }},\n    \
+             display_spi_hz: {spi_khz} * 1000,\n    \
+             pixel_fixup: PixelFixup {{ swap_bytes: {swap_bytes}, swap_rb: {swap_rb} }},\n    \
+             touch: {touch},\n    \
+             encoder: {encoder},\n    \
+             analog: {analog},\n    \
+             side_key: {side_key},\n    \
+             battery_adc: {battery},\n    \
+             piezo: {piezo},\n    \
+             imu_addr: {imu_addr},\n\
+         }};\n",
+        d.backlight,
+        d.rst,
+        d.dc,
+        d.cs,
+        d.mosi,
+        d.sclk,
+        name = manifest.name,
+        psram = manifest.psram_mb,
+        spi_khz = d.spi_khz,
+        swap_bytes = d.swap_bytes,
+        swap_rb = d.swap_rb,
+        features = manifest.features,
+        battery = optional(manifest.battery_adc),
+        piezo = optional(manifest.piezo),
+        side_key = optional(manifest.side_key),
+        imu_addr = optional(manifest.imu_addr),
+    );
+    fs::write(out_dir.join("board_config.rs"), source).expect("write board config");
+}
+
+/// Emits the `ZONES` table `timesync/zones.rs` includes, from the POSIX
+/// rules in `timezones.txt`; the types live there.
+fn write_zone_table(out_dir: &Path) {
+    println!("cargo:rerun-if-changed={ZONES_FILE}");
+    println!("cargo:rerun-if-changed=build/zone_table.rs");
+    println!("cargo:rerun-if-env-changed=ASTROBOX_ZONES");
+    let source =
+        fs::read_to_string(ZONES_FILE).unwrap_or_else(|err| panic!("read {ZONES_FILE}: {err}"));
+    let wanted: Option<Vec<String>> = env::var("ASTROBOX_ZONES").ok().map(|list| {
+        list.split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect()
+    });
+    let selected = |name: &str| {
+        name == zone_table::UTC_ZONE
+            || wanted.as_ref().map_or(true, |wanted| {
+                wanted.iter().any(|entry| match entry.strip_suffix('/') {
+                    Some(region) => name
+                        .strip_prefix(region)
+                        .is_some_and(|rest| rest.starts_with('/')),
+                    None => name == entry,
+                })
+            })
+    };
+
+    let table = zone_table::generate(&source, ZONES_FILE, selected);
+    fs::write(out_dir.join("zones.rs"), table).expect("write zone table");
+}
+
+struct CatalogEntry {
+    language: String,
+    context: String,
+    msgid: String,
+    msgstr: String,
+}
+
+/// Reads every `translations/<lang>/LC_MESSAGES/*.po`.
+fn load_catalogs() -> Vec<CatalogEntry> {
+    println!("cargo:rerun-if-changed={TRANSLATIONS_DIR}");
+    let mut entries = Vec::new();
+    let Ok(languages) = fs::read_dir(TRANSLATIONS_DIR) else {
+        return entries;
+    };
+    for language in languages.flatten() {
+        let language_name = language.file_name().to_string_lossy().into_owned();
+        let Ok(files) = fs::read_dir(language.path().join("LC_MESSAGES")) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let path = file.path();
+            if path.extension().is_some_and(|ext| ext == "po") {
+                println!("cargo:rerun-if-changed={}", path.display());
+                let source = fs::read_to_string(&path)
+                    .unwrap_or_else(|err| panic!("read {}: {err}", path.display()));
+                entries.extend(parse_po(&language_name, &source));
+            }
+        }
+    }
+    entries
+}
+
+/// Just enough of the PO format for hand-written catalogs: single-form
+/// entries, optional `msgctxt`, and continuation lines.
+fn parse_po(language: &str, source: &str) -> Vec<CatalogEntry> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Field {
+        None,
+        Context,
+        Id,
+        Str,
+    }
+
+    let mut entries = Vec::new();
+    let (mut context, mut msgid, mut msgstr) = (String::new(), String::new(), String::new());
+    let mut field = Field::None;
+    let mut flush = |context: &mut String, msgid: &mut String, msgstr: &mut String| {
+        if !msgid.is_empty() && !msgstr.is_empty() {
+            entries.push(CatalogEntry {
+                language: language.to_string(),
+                context: std::mem::take(context),
+                msgid: std::mem::take(msgid),
+                msgstr: std::mem::take(msgstr),
+            });
+        }
+        context.clear();
+        msgid.clear();
+        msgstr.clear();
+    };
+
+    for line in source.lines().map(str::trim) {
+        let (next, rest) = if let Some(rest) = line.strip_prefix("msgctxt ") {
+            (Field::Context, rest)
+        } else if let Some(rest) = line.strip_prefix("msgid ") {
+            (Field::Id, rest)
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            (Field::Str, rest)
+        } else if line.starts_with('"') {
+            (field, line)
+        } else {
+            continue;
+        };
+        if next != field && matches!(next, Field::Context | Field::Id) && field == Field::Str {
+            flush(&mut context, &mut msgid, &mut msgstr);
+        }
+        field = next;
+        let text = unquote(rest);
+        match field {
+            Field::Context => context.push_str(&text),
+            Field::Id => msgid.push_str(&text),
+            Field::Str => msgstr.push_str(&text),
+            Field::None => {}
+        }
+    }
+    flush(&mut context, &mut msgid, &mut msgstr);
+    entries
+}
+
+fn unquote(quoted: &str) -> String {
+    let inner = quoted
+        .trim()
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or("");
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+fn write_rust_catalog(out_dir: &Path, catalogs: &[CatalogEntry]) {
+    let mut source =
+        String::from("/// (language, msgid, msgstr) for Rust-side strings.\npub static CATALOG: &[(&str, &str, &str)] = &[\n");
+    for entry in catalogs
+        .iter()
+        .filter(|entry| entry.context == RUST_CONTEXT)
+    {
+        let _ = writeln!(
+            source,
+            "    ({:?}, {:?}, {:?}),",
+            entry.language, entry.msgid, entry.msgstr
+        );
+    }
+    source.push_str("];\n");
+    fs::write(out_dir.join("i18n_catalog.rs"), source).expect("write i18n catalog");
+}
+
+/// The software renderer only embeds glyphs for characters it sees in `.slint`
+/// literals. Translated text (including strings formatted in Rust) is not
+/// visible to it, so list every non-ASCII character from the catalogs in a
+/// hidden element, and import the CJK font to rasterize them from.
+fn write_glyph_subset(out_dir: &Path, catalogs: &[CatalogEntry]) {
+    let glyphs: BTreeSet<char> = catalogs
+        .iter()
+        .flat_map(|entry| entry.msgstr.chars())
+        .filter(|ch| !ch.is_ascii() && !ch.is_control())
+        .collect();
+    let glyphs: String = glyphs.into_iter().collect();
+
+    println!("cargo:rerun-if-env-changed=ASTROBOX_CJK_FONT");
+    println!("cargo:rerun-if-changed={CJK_FONT}");
+    let font = env::var("ASTROBOX_CJK_FONT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(CJK_FONT));
+    let import = match font.canonicalize() {
+        Ok(path) => format!("import \"{}\";\n", path.display()),
+        Err(_) => {
+            if !glyphs.is_empty() {
+                println!(
+                    "cargo:warning=CJK font not found at {}; translated text will render as boxes",
+                    font.display()
+                );
+            }
+            String::new()
+        }
+    };
+
+    let source = format!(
+        "{import}\nexport component TranslationGlyphs inherits Rectangle {{\n    visible: false;\n    Text {{\n        text: \"{glyphs}\";\n    }}\n}}\n"
+    );
+    fs::write(out_dir.join("i18n_glyphs.slint"), source).expect("write glyph subset");
+}
+
 fn emit_priv_cfg_flag() {
     if let Some(marker) = find_existing_marker() {
         println!("cargo:rerun-if-changed={}", marker.display());
@@ -0,0 +1,137 @@
+//! The `ZONES` table `src/timesync/zones.rs` includes, generated from the
+//! POSIX rules in `timezones.txt`. Std-only, so `host-tests` generates the
+//! same table for its build.
+
+use std::fmt::Write as _;
+
+/// Kept whatever `ASTROBOX_ZONES` selects: it is the `timezone` default.
+pub const UTC_ZONE: &str = "UTC";
+
+/// The table source for the zones of `source` that `selected` keeps;
+/// `file` names `source` in panics and the generated doc comment.
+pub fn generate(source: &str, file: &str, selected: impl Fn(&str) -> bool) -> String {
+    let mut table = format!("/// Generated from {file}.\npub static ZONES: &[Zone] = &[\n");
+    let mut has_utc = false;
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, rule) = line
+            .split_once(char::is_whitespace)
+            .unwrap_or_else(|| panic!("{file}:{}: expected a name and a rule", number + 1));
+        if !selected(name) {
+            continue;
+        }
+        has_utc |= name == UTC_ZONE;
+        let fields = parse_posix_tz(rule.trim())
+            .unwrap_or_else(|err| panic!("{file}:{}: {name}: {err}", number + 1));
+        let _ = writeln!(table, "    Zone {{ name: {name:?}, {fields} }},");
+    }
+    assert!(has_utc, "{file} must list {UTC_ZONE}");
+    table.push_str("];\n");
+    table
+}
+
+/// The `Zone` fields after `name` for a POSIX TZ rule such as
+/// `CET-1CEST,M3.5.0,M10.5.0/3`. Only the `Mm.w.d` transition form is
+/// accepted; tzdata uses nothing else for the zones worth listing.
+fn parse_posix_tz(rule: &str) -> Result<String, String> {
+    let mut rest = rule;
+    let std_abbr = take_abbr(&mut rest)?;
+    // POSIX offsets count hours west of UTC; the table stores minutes east.
+    let std_west = take_offset(&mut rest)?.ok_or("missing the UTC offset")?;
+    if rest.is_empty() {
+        return Ok(format!(
+            "std: {}, abbr: ({std_abbr:?}, {std_abbr:?}), dst: None",
+            -std_west
+        ));
+    }
+    let dst_abbr = take_abbr(&mut rest)?;
+    let dst_west = take_offset(&mut rest)?.unwrap_or(std_west - 60);
+    let (start, end) = rest
+        .strip_prefix(',')
+        .and_then(|rules| rules.split_once(','))
+        .ok_or("DST needs a start and an end rule")?;
+    let save = std_west - dst_west;
+    if save <= 0 {
+        return Err("DST must move clocks forward".to_string());
+    }
+    Ok(format!(
+        "std: {}, abbr: ({std_abbr:?}, {dst_abbr:?}), dst: Some(Dst {{ save: {save}, start: {}, end: {} }})",
+        -std_west,
+        parse_transition(start)?,
+        parse_transition(end)?
+    ))
+}
+
+/// `CET`, or `<+03>` for a numeric abbreviation, which is stored bare.
+fn take_abbr<'a>(rest: &mut &'a str) -> Result<&'a str, String> {
+    if let Some(quoted) = rest.strip_prefix('<') {
+        let (abbr, after) = quoted.split_once('>').ok_or("unclosed <abbreviation>")?;
+        *rest = after;
+        return Ok(abbr);
+    }
+    let len = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    if len < 3 {
+        return Err(format!("bad abbreviation at {rest:?}"));
+    }
+    let (abbr, after) = rest.split_at(len);
+    *rest = after;
+    Ok(abbr)
+}
+
+/// `[+-]hh[:mm]` in minutes, or `None` when no offset follows.
+fn take_offset(rest: &mut &str) -> Result<Option<i16>, String> {
+    let len = rest
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '-' | ':')))
+        .unwrap_or(rest.len());
+    if len == 0 {
+        return Ok(None);
+    }
+    let (offset, after) = rest.split_at(len);
+    *rest = after;
+    hours_minutes(offset).map(Some)
+}
+
+/// `Mm.w.d[/time]`, time defaulting to 02:00.
+fn parse_transition(rule: &str) -> Result<String, String> {
+    let (date, time) = rule.split_once('/').unwrap_or((rule, "2"));
+    let fields: Vec<u8> = date
+        .strip_prefix('M')
+        .ok_or_else(|| format!("only Mm.w.d transitions are supported, not {rule:?}"))?
+        .split('.')
+        .map(|field| {
+            field
+                .parse()
+                .map_err(|_| format!("bad transition {rule:?}"))
+        })
+        .collect::<Result<_, _>>()?;
+    let [month, week, weekday] = fields[..] else {
+        return Err(format!("bad transition {rule:?}"));
+    };
+    if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+        return Err(format!("transition {rule:?} out of range"));
+    }
+    Ok(format!(
+        "Transition {{ month: {month}, week: {week}, weekday: {weekday}, at: {} }}",
+        hours_minutes(time)?
+    ))
+}
+
+/// Signed `hh[:mm[:ss]]` in minutes; seconds are dropped.
+fn hours_minutes(text: &str) -> Result<i16, String> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let mut parts = digits.split(':').map(|part| {
+        part.parse::<i16>()
+            .map_err(|_| format!("bad time {text:?}"))
+    });
+    let hours = parts.next().unwrap_or(Err(format!("bad time {text:?}")))?;
+    let minutes = parts.next().transpose()?.unwrap_or(0);
+    Ok(sign * (hours * 60 + minutes))
+}
@@ -0,0 +1,19 @@
+//! Generates the zone table `timesync/zones.rs` includes, as the firmware's
+//! build.rs does, with every zone in `timezones.txt`.
+
+use std::{env, fs, path::PathBuf};
+
+#[path = "../build/zone_table.rs"]
+mod zone_table;
+
+const ZONES_FILE: &str = "../timezones.txt";
+
+fn main() {
+    println!("cargo:rerun-if-changed={ZONES_FILE}");
+    println!("cargo:rerun-if-changed=../build/zone_table.rs");
+    let source =
+        fs::read_to_string(ZONES_FILE).unwrap_or_else(|err| panic!("read {ZONES_FILE}: {err}"));
+    let table = zone_table::generate(&source, "timezones.txt", |_| true);
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set"));
+    fs::write(out_dir.join("zones.rs"), table).expect("write zone table");
+}
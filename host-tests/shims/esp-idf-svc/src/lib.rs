@@ -0,0 +1,20 @@
+//! The `esp-idf-svc` items the shared firmware modules touch, with host
+//! threads standing in for FreeRTOS tasks.
+
+pub mod sys {
+    use std::ffi::c_void;
+
+    /// A distinct handle per thread: the address of a thread-local, which
+    /// never allocates, like the allocator needs.
+    ///
+    /// # Safety
+    ///
+    /// Always safe; `unsafe` to match the FreeRTOS binding.
+    #[allow(non_snake_case)]
+    pub unsafe fn xTaskGetCurrentTaskHandle() -> *mut c_void {
+        thread_local! {
+            static TASK: u8 = const { 0 };
+        }
+        TASK.with(|task| task as *const u8 as *mut c_void)
+    }
+}
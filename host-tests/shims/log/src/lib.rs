@@ -0,0 +1,43 @@
+//! The `log` macros the shared firmware modules use, printing to stderr so
+//! test output keeps them.
+
+#[doc(hidden)]
+pub fn __print(level: &str, args: std::fmt::Arguments<'_>) {
+    eprintln!("[{level}] {args}");
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:literal, target: $target:expr, $($arg:tt)+) => {{
+        let _ = $target;
+        $crate::__print($level, format_args!($($arg)+))
+    }};
+    ($level:literal, $($arg:tt)+) => {
+        $crate::__print($level, format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!("ERROR", $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!("WARN", $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!("INFO", $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!("DEBUG", $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!("TRACE", $($arg)+) };
+}
@@ -0,0 +1,95 @@
+//! Fixed data for the tests, standing in for the firmware's live stores.
+
+use std::sync::Mutex;
+
+use crate::{
+    ancs::source::{EventPayload, NotifySink},
+    protocol::{Lookup, NotificationContent},
+};
+
+/// The UID [`FixedLookup`] knows.
+pub const KNOWN_UID: u32 = 7;
+
+/// One stored notification under [`KNOWN_UID`] and one named app.
+pub struct FixedLookup {
+    pub entry: NotificationContent,
+}
+
+impl Default for FixedLookup {
+    fn default() -> Self {
+        Self {
+            entry: NotificationContent {
+                app_id: "com.tencent.mm".to_string(),
+                title: "Dinner tonight".to_string(),
+                subtitle: "Family".to_string(),
+                message: "See you at eight tonight".to_string(),
+                date: "20250101T193000".to_string(),
+                positive_label: None,
+                negative_label: None,
+            },
+        }
+    }
+}
+
+impl Lookup for FixedLookup {
+    fn notification(&self, uid: u32) -> Option<NotificationContent> {
+        (uid == KNOWN_UID).then(|| self.entry.clone())
+    }
+
+    fn app_name(&self, app_id: &[u8]) -> Vec<u8> {
+        match app_id {
+            b"com.tencent.mm" => "微信".as_bytes().to_vec(),
+            other => other.to_vec(),
+        }
+    }
+}
+
+/// Splits the `id, u16 LE length, value` tuples after a response header.
+/// Panics on a tuple that runs past the end.
+pub fn attributes(mut bytes: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut out = Vec::new();
+    while !bytes.is_empty() {
+        let id = bytes[0];
+        let len = usize::from(u16::from_le_bytes([bytes[1], bytes[2]]));
+        out.push((id, bytes[3..3 + len].to_vec()));
+        bytes = &bytes[3 + len..];
+    }
+    out
+}
+
+/// A Notification Source sink that records what reaches it. Notifies to
+/// `failing` return an error instead.
+#[derive(Default)]
+pub struct RecordingSink {
+    pub failing: Option<u16>,
+    pub value: Mutex<Option<EventPayload>>,
+    pub sent: Mutex<Vec<(u16, EventPayload)>>,
+}
+
+impl RecordingSink {
+    pub fn sent(&self) -> Vec<(u16, EventPayload)> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    pub fn value(&self) -> Option<EventPayload> {
+        *self.value.lock().unwrap()
+    }
+}
+
+impl NotifySink for RecordingSink {
+    fn set_value(&self, payload: &EventPayload) {
+        *self.value.lock().unwrap() = Some(*payload);
+    }
+
+    fn notify(&self, conn_handle: u16, payload: &EventPayload) -> Result<(), String> {
+        if self.failing == Some(conn_handle) {
+            return Err(format!("conn {conn_handle} gone"));
+        }
+        self.sent.lock().unwrap().push((conn_handle, *payload));
+        Ok(())
+    }
+
+    fn subscribed_count(&self) -> usize {
+        1
+    }
+}
@@ -0,0 +1,98 @@
+//! Std-only firmware modules, included by path so the host build and the
+//! firmware compile the same source.
+
+#[path = "../../src/miwear/ancs/app_names.rs"]
+pub mod app_names;
+
+#[path = "../../src/miwear/ancs/protocol.rs"]
+pub mod protocol;
+
+#[path = "../../src/miwear/ancs/clients/record.rs"]
+pub mod client_record;
+
+pub mod fixtures;
+
+#[path = "../../src/allocator/placement.rs"]
+mod placement;
+
+/// Placement scopes, and the override the global allocator reads.
+pub mod allocator {
+    pub use crate::placement::{overflowed, with_placement, InternalBox, Placement, PsramVec};
+
+    /// What `alloc` would see on the calling thread.
+    pub fn current() -> Option<Placement> {
+        crate::placement::current()
+    }
+}
+
+#[path = "../../src/gui/line_batch.rs"]
+pub mod line_batch;
+
+#[path = "../../src/gui/setup/wizard.rs"]
+pub mod setup_wizard;
+
+#[path = "../../src/gui/backlight/idle.rs"]
+pub mod backlight_idle;
+
+#[path = "../../src/miwear/logging/filter.rs"]
+pub mod log_filter;
+
+#[path = "../../src/ota/check.rs"]
+pub mod ota_check;
+
+#[path = "../../src/board/pixel_fixup.rs"]
+pub mod pixel_fixup;
+
+#[path = "../../src/gui/slint_ui/nav.rs"]
+pub mod nav_stack;
+
+#[path = "../../src/gui/backlight/limits.rs"]
+pub mod backlight_limits;
+
+#[path = "../../src/miwear/ancs/advertising/limits.rs"]
+pub mod advertising_limits;
+
+#[path = "../../src/miwear/send/watchdog.rs"]
+pub mod send_watchdog;
+
+#[path = "../../src/miwear/send/burst/controller.rs"]
+pub mod send_burst;
+
+#[path = "../../src/miwear/session/uuids.rs"]
+pub mod session_uuids;
+
+#[path = "../../src/miwear/targets/selection.rs"]
+pub mod target_selection;
+
+#[path = "../../src/metrics/date.rs"]
+mod date;
+
+/// `timesync::zones` names [`date::Date`] by its firmware path.
+pub mod metrics {
+    pub use crate::date::Date;
+}
+
+#[path = "../../src/timesync/zones.rs"]
+pub mod zones;
+
+#[path = "../../src/settings/revision.rs"]
+pub mod settings_revision;
+
+#[path = "../../src/nvs/batch.rs"]
+pub mod nvs_batch;
+
+/// The scheduler core, at the path the ANCS server imports `JobHandle` by.
+#[path = "../../src/periodic"]
+pub mod periodic {
+    pub mod scheduler;
+
+    pub use scheduler::JobHandle;
+}
+
+/// The ANCS runtime's std-only parts, under the names they use for each
+/// other.
+#[path = "../../src/miwear/ancs"]
+pub mod ancs {
+    pub mod server;
+    pub mod source;
+}
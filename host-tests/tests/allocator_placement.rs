@@ -0,0 +1,151 @@
+//! Placement scopes: the override the allocator sees, its restoration when
+//! scopes close, and what happens once every slot is taken. Threads stand
+//! in for FreeRTOS tasks.
+
+use std::{
+    panic,
+    sync::{Barrier, Mutex, MutexGuard},
+    thread,
+};
+
+use host_tests::allocator::{
+    current, overflowed, with_placement, InternalBox, Placement, PsramVec,
+};
+
+/// The slots are shared by the whole process; tests take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[test]
+fn a_scope_sets_the_placement_for_its_task() {
+    let _serial = serial();
+    assert_eq!(current(), None);
+    let seen = with_placement(Placement::Psram, current);
+    assert_eq!(seen, Some(Placement::Psram));
+    assert_eq!(current(), None);
+}
+
+#[test]
+fn nested_scopes_restore_the_outer_placement() {
+    let _serial = serial();
+    with_placement(Placement::Psram, || {
+        with_placement(Placement::Internal, || {
+            assert_eq!(current(), Some(Placement::Internal));
+            with_placement(Placement::Psram, || {
+                assert_eq!(current(), Some(Placement::Psram));
+            });
+            assert_eq!(current(), Some(Placement::Internal));
+        });
+        assert_eq!(current(), Some(Placement::Psram));
+    });
+    assert_eq!(current(), None);
+}
+
+#[test]
+fn an_unwinding_scope_still_restores() {
+    let _serial = serial();
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(|| {
+        with_placement(Placement::Psram, || {
+            with_placement(Placement::Internal, || panic!("allocation failed"));
+        })
+    });
+    panic::set_hook(hook);
+    assert!(result.is_err());
+    assert_eq!(current(), None);
+}
+
+#[test]
+fn other_tasks_keep_the_size_policy() {
+    let _serial = serial();
+    with_placement(Placement::Internal, || {
+        let other = thread::spawn(current).join().unwrap();
+        assert_eq!(other, None);
+        assert_eq!(current(), Some(Placement::Internal));
+    });
+}
+
+#[test]
+fn a_scope_opened_with_every_slot_taken_is_ignored() {
+    let _serial = serial();
+    // Four tasks hold the four slots until the fifth has tried.
+    let opened = Barrier::new(5);
+    let tried = Barrier::new(5);
+    let before = overflowed();
+    thread::scope(|scope| {
+        let holders: Vec<_> = (0..4)
+            .map(|_| {
+                scope.spawn(|| {
+                    with_placement(Placement::Psram, || {
+                        opened.wait();
+                        tried.wait();
+                        current()
+                    })
+                })
+            })
+            .collect();
+        opened.wait();
+        // Ignored, not an error: this task keeps the size policy.
+        let seen = with_placement(Placement::Internal, current);
+        tried.wait();
+        assert_eq!(seen, None);
+        for holder in holders {
+            assert_eq!(holder.join().unwrap(), Some(Placement::Psram));
+        }
+    });
+    assert_eq!(overflowed(), before + 1);
+
+    // With the slots free again, scopes take effect.
+    assert_eq!(
+        with_placement(Placement::Internal, current),
+        Some(Placement::Internal)
+    );
+    assert_eq!(overflowed(), before + 1);
+}
+
+#[test]
+fn a_nested_scope_reuses_its_tasks_slot() {
+    let _serial = serial();
+    let opened = Barrier::new(4);
+    let done = Barrier::new(4);
+    let before = overflowed();
+    thread::scope(|scope| {
+        for _ in 0..3 {
+            scope.spawn(|| {
+                with_placement(Placement::Psram, || {
+                    opened.wait();
+                    done.wait();
+                })
+            });
+        }
+        opened.wait();
+        // The fourth slot is this task's; nesting needs no fifth.
+        with_placement(Placement::Psram, || {
+            assert_eq!(
+                with_placement(Placement::Internal, current),
+                Some(Placement::Internal)
+            );
+            assert_eq!(current(), Some(Placement::Psram));
+        });
+        done.wait();
+    });
+    assert_eq!(overflowed(), before);
+}
+
+#[test]
+fn the_placed_containers_hold_their_values() {
+    let _serial = serial();
+    let boxed = InternalBox::new([7u8; 32]);
+    assert_eq!(boxed[31], 7);
+    let mut buffer = PsramVec::with_capacity(64);
+    buffer.extend_from_slice(&[1u16, 2, 3]);
+    assert!(buffer.capacity() >= 64);
+    assert_eq!(*PsramVec::filled(9u8, 4), [9, 9, 9, 9]);
+    assert_eq!(current(), None);
+}
@@ -0,0 +1,217 @@
+//! `AncsServer` start and stop over the real scheduler core, stepped by
+//! hand instead of slept: once `stop` returns, none of its jobs ticks again.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use host_tests::{
+    ancs::{
+        server::{AncsServer, Runtime},
+        source::{encode_event, EventId, Links, NotifySink, Outlet},
+    },
+    fixtures::RecordingSink,
+    periodic::{
+        scheduler::{run_due, Scheduler},
+        JobHandle,
+    },
+};
+
+const STEP: Duration = Duration::from_millis(100);
+
+/// A runtime over its own scheduler, outlet and links, with a clock that
+/// only moves when a test steps it.
+struct Mock {
+    scheduler: Mutex<Scheduler>,
+    outlet: Outlet,
+    links: Mutex<Links>,
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    ticks: Arc<AtomicU32>,
+    /// For the first job to stop the server from its own callback.
+    server: Arc<Mutex<Option<AncsServer>>>,
+    stop_on_tick: Arc<AtomicBool>,
+}
+
+impl Mock {
+    fn leak() -> &'static Mock {
+        Box::leak(Box::new(Mock {
+            scheduler: Mutex::new(Scheduler::new()),
+            outlet: Outlet::new(),
+            links: Mutex::new(Links::new()),
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+            ticks: Arc::new(AtomicU32::new(0)),
+            server: Arc::new(Mutex::new(None)),
+            stop_on_tick: Arc::new(AtomicBool::new(false)),
+        }))
+    }
+
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    /// Moves the clock `by`, running the scheduler at every step.
+    fn run_for(&self, by: Duration) {
+        let steps = by.as_millis() / STEP.as_millis();
+        for _ in 0..steps {
+            *self.elapsed.lock().unwrap() += STEP;
+            run_due(&self.scheduler, self.now());
+        }
+    }
+
+    fn ticks(&self) -> u32 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    fn live_jobs(&self) -> usize {
+        self.scheduler.lock().unwrap().jobs().count()
+    }
+
+    fn register(&self, name: &'static str, period: Duration) -> JobHandle {
+        let ticks = self.ticks.clone();
+        let callback = Box::new(move || {
+            ticks.fetch_add(1, Ordering::Relaxed);
+        });
+        self.scheduler
+            .lock()
+            .unwrap()
+            .register(name, period, callback, self.now())
+    }
+}
+
+impl Runtime for Mock {
+    fn attach(&self, sink: Option<Arc<dyn NotifySink>>) {
+        self.outlet.attach(sink);
+    }
+
+    fn start_jobs(&self, relaxed: bool) -> Vec<JobHandle> {
+        let (server, stop_on_tick) = (self.server.clone(), self.stop_on_tick.clone());
+        let stopper = self.scheduler.lock().unwrap().register(
+            "stopper",
+            Duration::from_secs(1),
+            Box::new(move || {
+                if stop_on_tick.load(Ordering::Relaxed) {
+                    let server = server.lock().unwrap().take();
+                    if let Some(server) = server {
+                        server.stop();
+                    }
+                }
+            }),
+            self.now(),
+        );
+        let mut jobs = vec![
+            stopper,
+            self.register("advertising", Duration::from_secs(1)),
+            self.register("pairing", Duration::from_millis(500)),
+        ];
+        if !relaxed {
+            jobs.push(self.register("nudge", Duration::from_secs(1)));
+            jobs.push(self.register("phantom", Duration::from_secs(2)));
+        }
+        jobs
+    }
+
+    fn cancel(&self, job: JobHandle) {
+        self.scheduler.lock().unwrap().cancel(job);
+    }
+
+    fn clear_held(&self) {
+        self.links.lock().unwrap().clear();
+    }
+}
+
+#[test]
+fn no_job_ticks_after_stop() {
+    let mock = Mock::leak();
+    let server = AncsServer::start(mock, Arc::new(RecordingSink::default()), false);
+    mock.run_for(Duration::from_secs(4));
+    // Per second: advertising, nudge, two pairing runs, and phantom every other.
+    assert_eq!(mock.ticks(), 18);
+
+    server.stop();
+    mock.run_for(Duration::from_secs(10));
+    assert_eq!(mock.ticks(), 18);
+    assert_eq!(mock.live_jobs(), 0);
+    assert_eq!(mock.scheduler.lock().unwrap().next_due(), None);
+}
+
+#[test]
+fn stop_from_a_job_skips_the_jobs_due_with_it() {
+    let mock = Mock::leak();
+    let server = AncsServer::start(mock, Arc::new(RecordingSink::default()), false);
+    *mock.server.lock().unwrap() = Some(server);
+    mock.run_for(Duration::from_millis(900));
+    let before = mock.ticks();
+    assert_eq!(before, 1);
+
+    // The stopper is due at 1 s with advertising, pairing and nudge behind it.
+    mock.stop_on_tick.store(true, Ordering::Relaxed);
+    mock.run_for(Duration::from_secs(5));
+    assert_eq!(mock.ticks(), before);
+    assert!(mock.server.lock().unwrap().is_none());
+    assert_eq!(mock.live_jobs(), 0);
+}
+
+#[test]
+fn relaxed_servers_leave_out_the_traffic_jobs() {
+    let mock = Mock::leak();
+    let server = AncsServer::start(mock, Arc::new(RecordingSink::default()), true);
+    let names: Vec<_> = mock
+        .scheduler
+        .lock()
+        .unwrap()
+        .jobs()
+        .map(|job| job.name)
+        .collect();
+    assert_eq!(names, ["stopper", "advertising", "pairing"]);
+    server.stop();
+    assert_eq!(mock.live_jobs(), 0);
+}
+
+#[test]
+fn a_restart_ticks_again_with_the_same_sink() {
+    let mock = Mock::leak();
+    let sink: Arc<dyn NotifySink> = Arc::new(RecordingSink::default());
+    let returned = AncsServer::start(mock, sink.clone(), true).stop();
+    assert!(Arc::ptr_eq(&returned, &sink));
+    mock.run_for(Duration::from_secs(2));
+    assert_eq!(mock.ticks(), 0);
+
+    let server = AncsServer::start(mock, returned, true);
+    mock.run_for(Duration::from_secs(2));
+    assert_eq!(mock.ticks(), 6);
+    server.stop();
+}
+
+#[test]
+fn stop_detaches_the_sink_and_drops_held_events() {
+    let mock = Mock::leak();
+    let sink = Arc::new(RecordingSink::default());
+    let server = AncsServer::start(mock, sink.clone(), false);
+    let added = encode_event(EventId::Added, 0, 0, 1, 1);
+    let route = |events: &[_]| mock.links.lock().unwrap().route(events, mock.now()).0;
+
+    mock.links.lock().unwrap().subscribe(1, 23);
+    mock.outlet.publish(&[added], route, |_, _| {});
+    assert_eq!(sink.sent(), [(1, added)]);
+
+    // A response in flight holds the next Added back; stop drops it.
+    mock.links
+        .lock()
+        .unwrap()
+        .on_response(1, 23, Duration::from_millis(30), 8, mock.now());
+    mock.outlet.publish(&[added], route, |_, _| {});
+    assert!(mock.links.lock().unwrap().holding());
+    server.stop();
+    assert!(mock.outlet.sink().is_none());
+    assert!(!mock.links.lock().unwrap().holding());
+
+    mock.links.lock().unwrap().subscribe(1, 23);
+    mock.outlet.publish(&[added], route, |_, _| {});
+    assert_eq!(sink.sent(), [(1, added)]);
+}
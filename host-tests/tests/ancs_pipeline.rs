@@ -0,0 +1,183 @@
+//! Notification Source events from emission to the sink: `Links` decides
+//! what goes out when, `Outlet` hands it to a recording sink.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use host_tests::{
+    ancs::source::{encode_event, Delivery, EventId, EventPayload, Links, Outlet, MAX_HOLD},
+    fixtures::RecordingSink,
+};
+
+const INTERVAL: Duration = Duration::from_millis(30);
+
+fn event(id: EventId, uid: u32) -> EventPayload {
+    encode_event(id, 0, 0, 1, uid)
+}
+
+fn attached(sink: RecordingSink) -> (Outlet, Arc<RecordingSink>) {
+    let sink = Arc::new(sink);
+    let outlet = Outlet::new();
+    outlet.attach(Some(sink.clone()));
+    (outlet, sink)
+}
+
+/// `events` through `links` at `now`, with the failures `sent` reported.
+fn publish(
+    outlet: &Outlet,
+    links: &mut Links,
+    events: &[EventPayload],
+    now: Instant,
+) -> Vec<(u16, Result<(), String>)> {
+    let mut results = Vec::new();
+    outlet.publish(
+        events,
+        |events| links.route(events, now).0,
+        |delivery, result| results.push((delivery.conn_handle, result)),
+    );
+    results
+}
+
+#[test]
+fn encoded_events_carry_the_uid_little_endian() {
+    assert_eq!(
+        encode_event(EventId::Removed, 0x10, 4, 2, 0x0102_0304),
+        [2, 0x10, 4, 2, 4, 3, 2, 1]
+    );
+}
+
+#[test]
+fn every_subscriber_gets_every_event_and_reads_the_last() {
+    let (outlet, sink) = attached(RecordingSink::default());
+    let mut links = Links::new();
+    links.subscribe(1, 23);
+    links.subscribe(2, 185);
+    let (added, removed) = (event(EventId::Added, 5), event(EventId::Removed, 4));
+    publish(&outlet, &mut links, &[removed, added], Instant::now());
+    assert_eq!(
+        sink.sent(),
+        [(1, removed), (1, added), (2, removed), (2, added)]
+    );
+    assert_eq!(sink.value(), Some(added));
+}
+
+#[test]
+fn added_waits_for_a_response_in_flight_and_keeps_its_order() {
+    let (outlet, sink) = attached(RecordingSink::default());
+    let mut links = Links::new();
+    let start = Instant::now();
+    links.subscribe(1, 23);
+    // Four fragments at one per 30 ms connection event.
+    links.on_response(1, 23, INTERVAL, 4, start);
+    let (added, modified) = (event(EventId::Added, 9), event(EventId::Modified, 8));
+
+    // Only Added is held back, but Modified may not overtake it.
+    publish(&outlet, &mut links, &[added, modified], start);
+    assert!(sink.sent().is_empty());
+    assert_eq!(links.next_release(), Some(start + INTERVAL * 4));
+    assert!(links.due(start + INTERVAL * 3).is_empty());
+
+    let due = links.due(start + INTERVAL * 4);
+    assert_eq!(
+        due,
+        [
+            Delivery {
+                conn_handle: 1,
+                payload: added,
+                held: INTERVAL * 4
+            },
+            Delivery {
+                conn_handle: 1,
+                payload: modified,
+                held: INTERVAL * 4
+            },
+        ]
+    );
+    outlet.deliver(&due, |_, result| result.unwrap());
+    assert_eq!(sink.sent(), [(1, added), (1, modified)]);
+    assert!(!links.holding());
+}
+
+#[test]
+fn nothing_waits_longer_than_max_hold() {
+    let mut links = Links::new();
+    let start = Instant::now();
+    links.subscribe(1, 23);
+    links.on_response(1, 23, Duration::from_millis(100), 40, start);
+    links.route(&[event(EventId::Added, 1)], start);
+    assert_eq!(links.next_release(), Some(start + MAX_HOLD));
+    assert_eq!(links.due(start + MAX_HOLD).len(), 1);
+}
+
+#[test]
+fn a_busy_link_does_not_hold_up_the_others() {
+    let (outlet, sink) = attached(RecordingSink::default());
+    let mut links = Links::new();
+    let start = Instant::now();
+    links.subscribe(1, 23);
+    links.subscribe(2, 23);
+    links.on_response(1, 23, INTERVAL, 4, start);
+    let added = event(EventId::Added, 3);
+    publish(&outlet, &mut links, &[added], start);
+    assert_eq!(sink.sent(), [(2, added)]);
+    assert!(links.holding());
+}
+
+#[test]
+fn a_failed_notify_is_reported_and_the_rest_still_go_out() {
+    let (outlet, sink) = attached(RecordingSink {
+        failing: Some(1),
+        ..RecordingSink::default()
+    });
+    let mut links = Links::new();
+    links.subscribe(1, 23);
+    links.subscribe(2, 23);
+    let added = event(EventId::Added, 6);
+    let results = publish(&outlet, &mut links, &[added], Instant::now());
+    assert_eq!(results, [(1, Err("conn 1 gone".to_string())), (2, Ok(()))]);
+    assert_eq!(sink.sent(), [(2, added)]);
+}
+
+#[test]
+fn a_detached_outlet_sends_nothing() {
+    let (outlet, sink) = attached(RecordingSink::default());
+    let mut links = Links::new();
+    links.subscribe(1, 23);
+    outlet.attach(None);
+    let results = publish(
+        &outlet,
+        &mut links,
+        &[event(EventId::Added, 2)],
+        Instant::now(),
+    );
+    assert!(results.is_empty());
+    assert!(sink.sent().is_empty());
+    assert_eq!(sink.value(), None);
+}
+
+#[test]
+fn forgetting_a_link_drops_what_it_held() {
+    let mut links = Links::new();
+    let start = Instant::now();
+    links.subscribe(1, 23);
+    links.on_response(1, 23, INTERVAL, 4, start);
+    links.route(&[event(EventId::Added, 1)], start);
+    links.forget(1);
+    assert!(!links.holding());
+    assert!(links.due(start + MAX_HOLD).is_empty());
+    // A resubscribe starts clean, whatever the old link held.
+    links.subscribe(1, 23);
+    assert_eq!(links.next_release(), None);
+}
+
+#[test]
+fn an_mtu_change_is_reported_once() {
+    let mut links = Links::new();
+    let start = Instant::now();
+    links.subscribe(1, 23);
+    assert_eq!(links.on_response(1, 185, INTERVAL, 1, start), Some(23));
+    assert_eq!(links.on_response(1, 185, INTERVAL, 1, start), None);
+    assert_eq!(links.on_response(9, 185, INTERVAL, 1, start), None);
+}
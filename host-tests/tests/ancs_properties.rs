@@ -0,0 +1,256 @@
+//! Property checks over `parse` then `respond`: random and structured
+//! Control Point writes, a fixed seed per case so a failure names the input
+//! that reproduces it. `ANCS_CASES` raises the case count for a longer run.
+
+use host_tests::{
+    fixtures::{attributes, FixedLookup, KNOWN_UID},
+    protocol::{
+        fragments, parse, respond, AttributeRequest, Request, INVALID_COMMAND, INVALID_PARAMETER,
+        MAX_RESPONSE, UNKNOWN_COMMAND,
+    },
+};
+
+const DEFAULT_CASES: u64 = 20_000;
+
+/// xorshift64*, enough to spread inputs; no crates are available offline.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+fn cases() -> u64 {
+    std::env::var("ANCS_CASES")
+        .ok()
+        .and_then(|cases| cases.parse().ok())
+        .unwrap_or(DEFAULT_CASES)
+}
+
+/// Bytes a watch could plausibly send, and some it could not.
+fn write(rng: &mut Rng) -> Vec<u8> {
+    match rng.below(4) {
+        0 => (0..rng.below(48)).map(|_| rng.byte()).collect(),
+        1 => encode(&request(rng)),
+        // A valid write cut short or with junk after it.
+        2 => {
+            let mut bytes = encode(&request(rng));
+            if rng.chance(50) {
+                bytes.truncate(rng.below(bytes.len() as u64 + 1) as usize);
+            } else {
+                bytes.extend((0..rng.below(8)).map(|_| rng.byte()));
+            }
+            bytes
+        }
+        _ => {
+            let mut bytes = encode(&request(rng));
+            if !bytes.is_empty() {
+                let at = rng.below(bytes.len() as u64) as usize;
+                bytes[at] = rng.byte();
+            }
+            bytes
+        }
+    }
+}
+
+fn request(rng: &mut Rng) -> Request<'static> {
+    let uid = if rng.chance(50) {
+        KNOWN_UID
+    } else {
+        rng.next() as u32
+    };
+    match rng.below(3) {
+        0 => Request::NotificationAttributes {
+            uid,
+            attributes: (0..rng.below(10))
+                .map(|_| {
+                    let id = rng.below(10) as u8;
+                    let max_len = if matches!(id, 1..=3) { length(rng) } else { 0 };
+                    AttributeRequest { id, max_len }
+                })
+                .collect(),
+        },
+        1 => {
+            const IDS: [&[u8]; 3] = [b"com.tencent.mm", b"org.example", b""];
+            Request::AppAttributes {
+                app_id: IDS[rng.below(IDS.len() as u64) as usize],
+                attributes: (0..rng.below(4))
+                    .map(|_| AttributeRequest {
+                        id: rng.below(3) as u8,
+                        max_len: length(rng),
+                    })
+                    .collect(),
+            }
+        }
+        _ => Request::PerformAction {
+            uid,
+            action: rng.below(2) as u8,
+        },
+    }
+}
+
+fn length(rng: &mut Rng) -> u16 {
+    match rng.below(4) {
+        0 => 0,
+        1 => rng.below(8) as u16,
+        2 => rng.below(600) as u16,
+        _ => rng.next() as u16,
+    }
+}
+
+/// The bytes `parse` reads back as `request`.
+fn encode(request: &Request<'_>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    match request {
+        Request::NotificationAttributes { uid, attributes } => {
+            bytes.push(0x00);
+            bytes.extend_from_slice(&uid.to_le_bytes());
+            for attribute in attributes {
+                bytes.push(attribute.id);
+                if matches!(attribute.id, 1..=3) {
+                    bytes.extend_from_slice(&attribute.max_len.to_le_bytes());
+                }
+            }
+        }
+        Request::AppAttributes { app_id, attributes } => {
+            bytes.push(0x01);
+            bytes.extend_from_slice(app_id);
+            bytes.push(0);
+            for attribute in attributes {
+                bytes.push(attribute.id);
+                bytes.extend_from_slice(&attribute.max_len.to_le_bytes());
+            }
+        }
+        Request::PerformAction { uid, action } => {
+            bytes.push(0x02);
+            bytes.extend_from_slice(&uid.to_le_bytes());
+            bytes.push(*action);
+        }
+    }
+    bytes
+}
+
+/// Everything the contract promises about one response.
+fn check_response(request: &Request<'_>, response: &[u8]) {
+    assert!(response.len() <= MAX_RESPONSE, "{} bytes", response.len());
+    let (header, requested) = match request {
+        Request::NotificationAttributes { uid, attributes } => {
+            let mut header = vec![0x00];
+            header.extend_from_slice(&uid.to_le_bytes());
+            (header, attributes.as_slice())
+        }
+        Request::AppAttributes { app_id, attributes } => {
+            let mut header = vec![0x01];
+            header.extend_from_slice(app_id);
+            header.push(0);
+            (header, attributes.as_slice())
+        }
+        Request::PerformAction { uid, action } => {
+            let mut ack = vec![0x02];
+            ack.extend_from_slice(&uid.to_le_bytes());
+            ack.push(*action);
+            assert_eq!(response, ack);
+            return;
+        }
+    };
+    assert_eq!(&response[..header.len()], header);
+    let answered = attributes(&response[header.len()..]);
+    if requested.is_empty() {
+        assert_eq!(answered.len(), 1);
+        assert_eq!(answered[0].0, 0);
+        return;
+    }
+    // In order, possibly cut short by the size cap, never reordered.
+    assert!(answered.len() <= requested.len());
+    for ((id, value), wanted) in answered.iter().zip(requested) {
+        assert_eq!(*id, wanted.id);
+        if wanted.max_len > 0 {
+            assert!(value.len() <= usize::from(wanted.max_len));
+        }
+    }
+    if answered.len() < requested.len() {
+        assert!(response.len() + 3 > MAX_RESPONSE);
+    }
+}
+
+#[test]
+fn parse_then_respond_holds_the_contract_for_any_write() {
+    let lookup = FixedLookup::default();
+    for seed in 0..cases() {
+        let mut rng = Rng::new(seed);
+        let bytes = write(&mut rng);
+        let outcome =
+            std::panic::catch_unwind(|| match parse(&bytes) {
+                Ok(request) => check_response(&request, &respond(&request, &lookup)),
+                Err(err) => assert!([UNKNOWN_COMMAND, INVALID_COMMAND, INVALID_PARAMETER]
+                    .contains(&err.att_error())),
+            });
+        if outcome.is_err() {
+            panic!("seed {seed} failed on write {bytes:02X?}");
+        }
+    }
+}
+
+#[test]
+fn well_formed_requests_parse_back_unchanged() {
+    for seed in 0..cases() {
+        let mut rng = Rng::new(seed);
+        let request = request(&mut rng);
+        let bytes = encode(&request);
+        assert_eq!(parse(&bytes), Ok(request), "seed {seed}: {bytes:02X?}");
+    }
+}
+
+#[test]
+fn long_entries_stay_under_the_cap() {
+    let mut lookup = FixedLookup::default();
+    for seed in 0..cases() / 10 {
+        let mut rng = Rng::new(seed);
+        lookup.entry.title = "標題".repeat(rng.below(200) as usize);
+        lookup.entry.message = "m".repeat(rng.below(1200) as usize);
+        let request = Request::NotificationAttributes {
+            uid: KNOWN_UID,
+            attributes: (0..rng.below(10) + 1)
+                .map(|_| AttributeRequest {
+                    id: rng.below(8) as u8,
+                    max_len: length(&mut rng),
+                })
+                .collect(),
+        };
+        check_response(&request, &respond(&request, &lookup));
+    }
+}
+
+#[test]
+fn fragments_reassemble_to_the_response() {
+    let lookup = FixedLookup::default();
+    for seed in 0..cases() / 10 {
+        let mut rng = Rng::new(seed);
+        let request = request(&mut rng);
+        let response = respond(&request, &lookup);
+        let mtu = 23 + rng.below(512 - 23 + 1) as u16;
+        let parts: Vec<&[u8]> = fragments(&response, mtu).collect();
+        assert!(parts.iter().all(|part| part.len() <= usize::from(mtu) - 3));
+        assert_eq!(parts.concat(), response, "seed {seed} at MTU {mtu}");
+    }
+}
@@ -0,0 +1,230 @@
+//! Control Point writes as watches send them, checked byte for byte against
+//! the contract at the top of `protocol.rs`.
+
+use host_tests::{
+    fixtures::{attributes, FixedLookup, KNOWN_UID},
+    protocol::{
+        control_point_response, fragment_len, fragments, parse, AttributeRequest,
+        NotificationContent, ParseError, Request, DUMMY_APP_IDENTIFIER, DUMMY_MESSAGE_BODY,
+        DUMMY_MESSAGE_TITLE, INVALID_COMMAND, INVALID_PARAMETER, MAX_RESPONSE, UNKNOWN_COMMAND,
+    },
+};
+
+#[test]
+fn notification_attributes_echo_uid_and_honour_max_lengths() {
+    // Title (max 8), message (max 16), date.
+    let write = [0x00, 7, 0, 0, 0, 1, 8, 0, 3, 16, 0, 5];
+    let response = control_point_response(&write, &FixedLookup::default()).unwrap();
+    assert_eq!(&response[..5], &[0x00, 7, 0, 0, 0]);
+    assert_eq!(
+        attributes(&response[5..]),
+        vec![
+            (1, b"Dinner t".to_vec()),
+            (3, b"See you at eight".to_vec()),
+            (5, b"20250101T193000".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn empty_attribute_list_answers_the_app_identifier() {
+    let response = control_point_response(&[0x00, 7, 0, 0, 0], &FixedLookup::default()).unwrap();
+    assert_eq!(
+        attributes(&response[5..]),
+        vec![(0, b"com.tencent.mm".to_vec())]
+    );
+}
+
+#[test]
+fn max_length_zero_means_unlimited() {
+    let response =
+        control_point_response(&[0x00, 7, 0, 0, 0, 3, 0, 0], &FixedLookup::default()).unwrap();
+    assert_eq!(
+        attributes(&response[5..]),
+        vec![(3, b"See you at eight tonight".to_vec())]
+    );
+}
+
+#[test]
+fn unknown_uid_is_answered_from_the_placeholder() {
+    let write = [0x00, 0xEF, 0xBE, 0xAD, 0xDE, 0, 1, 32, 0, 3, 0, 0];
+    let response = control_point_response(&write, &FixedLookup::default()).unwrap();
+    assert_eq!(&response[..5], &[0x00, 0xEF, 0xBE, 0xAD, 0xDE]);
+    assert_eq!(
+        attributes(&response[5..]),
+        vec![
+            (0, DUMMY_APP_IDENTIFIER.as_bytes().to_vec()),
+            (1, DUMMY_MESSAGE_TITLE.as_bytes().to_vec()),
+            (3, DUMMY_MESSAGE_BODY.as_bytes().to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn action_labels_come_from_the_entry_or_the_placeholder() {
+    let mut lookup = FixedLookup::default();
+    let write = [0x00, 7, 0, 0, 0, 6, 7];
+    let response = control_point_response(&write, &lookup).unwrap();
+    assert_eq!(
+        attributes(&response[5..]),
+        vec![(6, b"Open".to_vec()), (7, b"Ignore".to_vec())]
+    );
+
+    lookup.entry.positive_label = Some("Reply".to_string());
+    lookup.entry.negative_label = Some("Mute".to_string());
+    let response = control_point_response(&write, &lookup).unwrap();
+    assert_eq!(
+        attributes(&response[5..]),
+        vec![(6, b"Reply".to_vec()), (7, b"Mute".to_vec())]
+    );
+}
+
+#[test]
+fn message_size_is_the_byte_length_of_the_message() {
+    let response = control_point_response(&[0x00, 7, 0, 0, 0, 4], &FixedLookup::default()).unwrap();
+    assert_eq!(attributes(&response[5..]), vec![(4, b"24".to_vec())]);
+}
+
+#[test]
+fn app_attributes_echo_the_identifier_and_answer_the_display_name() {
+    let mut write = vec![0x01];
+    write.extend_from_slice(b"com.tencent.mm\0");
+    write.push(0);
+    let response = control_point_response(&write, &FixedLookup::default()).unwrap();
+    assert_eq!(&response[..16], b"\x01com.tencent.mm\0");
+    assert_eq!(
+        attributes(&response[16..]),
+        vec![(0, "微信".as_bytes().to_vec())]
+    );
+}
+
+#[test]
+fn app_display_name_is_cut_on_a_character_boundary() {
+    let mut write = vec![0x01];
+    write.extend_from_slice(b"com.tencent.mm\0");
+    // Four bytes leave room for one three-byte character only.
+    write.extend_from_slice(&[0, 4, 0]);
+    let response = control_point_response(&write, &FixedLookup::default()).unwrap();
+    assert_eq!(
+        attributes(&response[16..]),
+        vec![(0, "微".as_bytes().to_vec())]
+    );
+}
+
+#[test]
+fn unknown_app_is_answered_with_its_identifier() {
+    let mut write = vec![0x01];
+    write.extend_from_slice(b"org.example\0");
+    write.push(0);
+    let response = control_point_response(&write, &FixedLookup::default()).unwrap();
+    assert_eq!(
+        attributes(&response[13..]),
+        vec![(0, b"org.example".to_vec())]
+    );
+}
+
+#[test]
+fn perform_action_is_acknowledged() {
+    for action in [0, 1] {
+        let write = [0x02, 7, 0, 0, 0, action];
+        let response = control_point_response(&write, &FixedLookup::default()).unwrap();
+        assert_eq!(response, write);
+    }
+}
+
+#[test]
+fn malformed_writes_are_refused_with_the_spec_errors() {
+    let cases: &[(&[u8], u8)] = &[
+        (&[], INVALID_COMMAND),
+        (&[0x05], UNKNOWN_COMMAND),
+        (&[0x00, 7, 0], INVALID_COMMAND),
+        (&[0x00, 7, 0, 0, 0, 1, 8], INVALID_COMMAND),
+        (b"\x01com.tencent.mm", INVALID_COMMAND),
+        (&[0x02, 7, 0, 0, 0], INVALID_COMMAND),
+        (&[0x02, 7, 0, 0, 0, 2], INVALID_PARAMETER),
+    ];
+    for (write, expected) in cases {
+        let err = control_point_response(write, &FixedLookup::default()).unwrap_err();
+        assert_eq!(err.att_error(), *expected, "write {write:02X?} gave {err}");
+    }
+}
+
+#[test]
+fn truncation_names_the_field_and_offset() {
+    assert_eq!(
+        parse(&[0x00, 7, 0, 0, 0, 1, 8]),
+        Err(ParseError::Truncated {
+            field: "attribute max length",
+            at: 6
+        })
+    );
+    assert_eq!(
+        parse(&[0x00, 7, 0, 0, 0, 6, 1, 32, 0]),
+        Ok(Request::NotificationAttributes {
+            uid: KNOWN_UID,
+            attributes: vec![
+                AttributeRequest { id: 6, max_len: 0 },
+                AttributeRequest { id: 1, max_len: 32 },
+            ],
+        })
+    );
+}
+
+#[test]
+fn responses_stop_at_the_attribute_value_limit() {
+    let mut lookup = FixedLookup::default();
+    lookup.entry.message = "x".repeat(2 * MAX_RESPONSE);
+    // Message unlimited, then title: the message fills the response.
+    let write = [0x00, 7, 0, 0, 0, 3, 0, 0, 1, 0, 0];
+    let response = control_point_response(&write, &lookup).unwrap();
+    assert_eq!(response.len(), MAX_RESPONSE);
+    let attributes = attributes(&response[5..]);
+    assert_eq!(attributes.len(), 1);
+    assert_eq!(attributes[0].0, 3);
+    assert_eq!(attributes[0].1.len(), MAX_RESPONSE - 5 - 3);
+}
+
+#[test]
+fn entry_contents_are_served_verbatim() {
+    let lookup = FixedLookup {
+        entry: NotificationContent {
+            app_id: "com.apple.MobileSMS".to_string(),
+            title: "Ünïcode".to_string(),
+            ..FixedLookup::default().entry
+        },
+    };
+    let response = control_point_response(&[0x00, 7, 0, 0, 0, 0, 1, 0, 0], &lookup).unwrap();
+    assert_eq!(
+        attributes(&response[5..]),
+        vec![
+            (0, b"com.apple.MobileSMS".to_vec()),
+            (1, "Ünïcode".as_bytes().to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn fragment_len_leaves_room_for_the_notify_header() {
+    assert_eq!(fragment_len(23), 20);
+    assert_eq!(fragment_len(158), 155);
+    assert_eq!(fragment_len(512), 509);
+    // Never zero, even for an MTU no stack negotiates.
+    assert_eq!(fragment_len(0), 1);
+    assert_eq!(fragment_len(3), 1);
+}
+
+#[test]
+fn fragments_split_a_full_response_at_each_mtu() {
+    let response: Vec<u8> = (0..MAX_RESPONSE).map(|i| i as u8).collect();
+    for (mtu, len) in [(23, 20), (158, 155), (512, 509)] {
+        let parts: Vec<&[u8]> = fragments(&response, mtu).collect();
+        assert_eq!(parts.len(), MAX_RESPONSE.div_ceil(len), "MTU {mtu}");
+        let (last, full) = parts.split_last().unwrap();
+        assert!(full.iter().all(|part| part.len() == len), "MTU {mtu}");
+        assert!(!last.is_empty() && last.len() <= len, "MTU {mtu}");
+        assert_eq!(parts.concat(), response, "MTU {mtu}");
+    }
+    // A response that fits goes out whole; an empty one sends nothing.
+    assert_eq!(fragments(&response[..20], 23).count(), 1);
+    assert_eq!(fragments(&[], 23).count(), 0);
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use host_tests::app_names::{display_name, learn, lookup, set_overrides};
+
+// The tables are process-wide, so every test uses identifiers of its own
+// and only adds overrides, never clears them.
+fn add_override(app_id: &str, name: &str) {
+    set_overrides(HashMap::from([(app_id.to_string(), name.to_string())]));
+}
+
+#[test]
+fn built_in_answers_known_identifiers() {
+    assert_eq!(lookup("com.tencent.mm").as_deref(), Some("WeChat"));
+    assert_eq!(lookup("com.apple.MobileSMS").as_deref(), Some("Messages"));
+}
+
+#[test]
+fn learned_name_beats_built_in() {
+    learn("com.whatsapp", "WhatsApp Business");
+    assert_eq!(lookup("com.whatsapp").as_deref(), Some("WhatsApp Business"));
+}
+
+#[test]
+fn override_beats_learned_and_built_in() {
+    learn("com.discord", "Discord Canary");
+    add_override("com.discord", "Chat");
+    assert_eq!(lookup("com.discord").as_deref(), Some("Chat"));
+    learn("com.discord", "Discord PTB");
+    assert_eq!(lookup("com.discord").as_deref(), Some("Chat"));
+}
+
+#[test]
+fn later_learned_name_replaces_earlier() {
+    learn("test.relearn", "First");
+    learn("test.relearn", "Second");
+    assert_eq!(lookup("test.relearn").as_deref(), Some("Second"));
+}
+
+#[test]
+fn empty_ids_and_names_are_not_learned() {
+    learn("test.empty-name", "");
+    learn("", "Nameless");
+    assert_eq!(lookup("test.empty-name"), None);
+    assert_eq!(lookup(""), None);
+}
+
+#[test]
+fn unknown_identifier_is_echoed() {
+    assert_eq!(lookup("test.unknown"), None);
+    assert_eq!(display_name(b"test.unknown"), b"test.unknown");
+}
+
+#[test]
+fn non_ascii_names_round_trip_as_utf8() {
+    learn("test.cjk", "微信");
+    learn("test.emoji", "Chat 💬");
+    assert_eq!(display_name(b"test.cjk"), "微信".as_bytes());
+    assert_eq!(display_name(b"test.emoji"), "Chat 💬".as_bytes());
+}
+
+#[test]
+fn non_ascii_identifiers_are_looked_up() {
+    learn("測試.应用", "Ünïcode");
+    assert_eq!(display_name("測試.应用".as_bytes()), "Ünïcode".as_bytes());
+}
+
+#[test]
+fn identifiers_that_are_not_utf8_are_echoed() {
+    let id = [b'a', 0xFF, b'b'];
+    assert_eq!(display_name(&id), id);
+}
@@ -0,0 +1,173 @@
+//! The screen timeout: dim, then off without input, and a wake from off
+//! that stays dim until the next input or `full_after_wake`.
+
+use std::time::{Duration, Instant};
+
+use host_tests::backlight_idle::{Idle, IdleConfig, Stage};
+
+const CONFIG: IdleConfig = IdleConfig {
+    dim_after: Duration::from_secs(20),
+    off_after: Duration::from_secs(60),
+    dim_percent: 30,
+    full_after_wake: Duration::from_secs(3),
+};
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+/// The stage after a tick at each of `offsets` from `start`.
+fn stages(idle: &mut Idle, start: Instant, offsets: &[u64]) -> Vec<Stage> {
+    offsets
+        .iter()
+        .map(|offset| {
+            idle.tick(start + secs(*offset), Some(&CONFIG));
+            idle.stage()
+        })
+        .collect()
+}
+
+#[test]
+fn dims_and_then_turns_off_without_input() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    assert_eq!(
+        stages(&mut idle, start, &[0, 19, 20, 59, 60, 600]),
+        [
+            Stage::Full,
+            Stage::Full,
+            Stage::Dim,
+            Stage::Dim,
+            Stage::Off,
+            Stage::Off
+        ]
+    );
+}
+
+#[test]
+fn tick_reports_only_changes() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    assert!(!idle.tick(start + secs(10), Some(&CONFIG)));
+    assert!(idle.tick(start + secs(20), Some(&CONFIG)));
+    assert!(!idle.tick(start + secs(30), Some(&CONFIG)));
+    assert!(idle.tick(start + secs(60), Some(&CONFIG)));
+    assert!(!idle.tick(start + secs(61), Some(&CONFIG)));
+}
+
+#[test]
+fn a_late_tick_goes_straight_to_off() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    assert!(idle.tick(start + secs(90), Some(&CONFIG)));
+    assert_eq!(idle.stage(), Stage::Off);
+}
+
+#[test]
+fn input_while_dim_restores_full_and_reaches_the_ui() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    idle.tick(start + secs(25), Some(&CONFIG));
+    assert_eq!(idle.stage(), Stage::Dim);
+
+    assert!(!idle.on_input(start + secs(25), Some(&CONFIG)));
+    assert_eq!(idle.stage(), Stage::Full);
+    // The timeout restarts from that input.
+    assert_eq!(
+        stages(&mut idle, start, &[44, 45, 84, 85]),
+        [Stage::Full, Stage::Dim, Stage::Dim, Stage::Off]
+    );
+}
+
+#[test]
+fn input_while_off_only_wakes_to_dim() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    idle.tick(start + secs(60), Some(&CONFIG));
+
+    assert!(idle.on_input(start + secs(100), Some(&CONFIG)));
+    assert_eq!(idle.stage(), Stage::Waking);
+    assert_eq!(idle.percent(80, Some(&CONFIG)), 30);
+}
+
+#[test]
+fn a_woken_screen_goes_full_after_full_after_wake() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    idle.tick(start + secs(60), Some(&CONFIG));
+    idle.on_input(start + secs(100), Some(&CONFIG));
+
+    assert_eq!(
+        stages(&mut idle, start, &[101, 102, 103]),
+        [Stage::Waking, Stage::Waking, Stage::Full]
+    );
+    // And the timeout then runs from the waking input.
+    assert_eq!(
+        stages(&mut idle, start, &[119, 120, 160]),
+        [Stage::Full, Stage::Dim, Stage::Off]
+    );
+}
+
+#[test]
+fn a_second_input_after_a_wake_goes_full_at_once() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    idle.tick(start + secs(60), Some(&CONFIG));
+    assert!(idle.on_input(start + secs(100), Some(&CONFIG)));
+    assert!(!idle.on_input(start + secs(101), Some(&CONFIG)));
+    assert_eq!(idle.stage(), Stage::Full);
+}
+
+#[test]
+fn without_a_config_nothing_times_out_and_a_wake_is_full() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    assert!(!idle.tick(start + secs(3_600), None));
+    assert_eq!(idle.stage(), Stage::Full);
+
+    idle.turn_off();
+    assert!(!idle.tick(start + secs(3_601), None));
+    assert_eq!(idle.stage(), Stage::Off);
+    assert!(idle.on_input(start + secs(3_602), None));
+    assert_eq!(idle.stage(), Stage::Full);
+}
+
+#[test]
+fn turning_the_timeout_off_lifts_a_dim_screen() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    idle.tick(start + secs(25), Some(&CONFIG));
+    assert_eq!(idle.stage(), Stage::Dim);
+    assert!(idle.tick(start + secs(26), None));
+    assert_eq!(idle.stage(), Stage::Full);
+}
+
+#[test]
+fn the_side_key_turns_off_and_on() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    idle.turn_off();
+    assert_eq!(idle.stage(), Stage::Off);
+    // Off stays off on time alone.
+    assert!(!idle.tick(start + secs(1), Some(&CONFIG)));
+
+    idle.turn_on(start + secs(50));
+    assert_eq!(idle.stage(), Stage::Full);
+    assert_eq!(
+        stages(&mut idle, start, &[69, 70]),
+        [Stage::Full, Stage::Dim]
+    );
+}
+
+#[test]
+fn the_dim_level_never_exceeds_full_brightness() {
+    let start = Instant::now();
+    let mut idle = Idle::new(start);
+    assert_eq!(idle.percent(80, Some(&CONFIG)), 80);
+    idle.tick(start + secs(20), Some(&CONFIG));
+    assert_eq!(idle.percent(80, Some(&CONFIG)), 30);
+    assert_eq!(idle.percent(10, Some(&CONFIG)), 10);
+    assert_eq!(idle.percent(80, None), 80);
+    idle.tick(start + secs(60), Some(&CONFIG));
+    assert_eq!(idle.percent(80, Some(&CONFIG)), 0);
+}
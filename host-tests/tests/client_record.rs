@@ -0,0 +1,74 @@
+use host_tests::client_record::{
+    cap_name, decode, encode, KnownClient, KNOWN_BUFFER_LEN, MAX_KNOWN_CLIENTS, MAX_LINE_LEN,
+    MAX_NAME_LEN,
+};
+
+fn client(name: Option<&str>) -> KnownClient {
+    KnownClient {
+        addr: "AA:BB:CC:DD:EE:FF".into(),
+        name: name.map(str::to_string),
+        first_seen: 1_700_000_000,
+        last_seen: 1_700_000_500,
+    }
+}
+
+#[test]
+fn round_trips_with_and_without_a_name() {
+    for client in [client(Some("Pixel 8")), client(None)] {
+        let line = encode(&client);
+        assert_eq!(decode(line.trim_end_matches('\n')), Some(client));
+    }
+}
+
+#[test]
+fn tabs_and_newlines_in_names_do_not_split_the_record() {
+    let line = encode(&client(Some("a\tb\nc")));
+    assert_eq!(line.matches('\n').count(), 1);
+    let decoded = decode(line.trim_end_matches('\n')).unwrap();
+    assert_eq!(decoded.name.as_deref(), Some("a b c"));
+}
+
+#[test]
+fn names_are_capped_on_a_char_boundary() {
+    assert_eq!(cap_name("short"), "short");
+    let ascii = "x".repeat(MAX_NAME_LEN + 10);
+    assert_eq!(cap_name(&ascii).len(), MAX_NAME_LEN);
+    // 64 bytes of garbage decode to 64 three-byte replacement chars.
+    let replaced = "\u{FFFD}".repeat(MAX_NAME_LEN);
+    let capped = cap_name(&replaced);
+    assert!(capped.len() <= MAX_NAME_LEN);
+    assert_eq!(capped.len(), MAX_NAME_LEN / 3 * 3);
+    assert!(capped.chars().all(|c| c == '\u{FFFD}'));
+}
+
+#[test]
+fn the_longest_records_fit_the_read_buffer() {
+    let worst = KnownClient {
+        addr: "AA:BB:CC:DD:EE:FF".into(),
+        name: Some("\u{FFFD}".repeat(MAX_NAME_LEN)),
+        first_seen: u64::MAX,
+        last_seen: u64::MAX,
+    };
+    let line = encode(&worst);
+    assert!(
+        line.len() <= MAX_LINE_LEN,
+        "{} > {MAX_LINE_LEN}",
+        line.len()
+    );
+    let all: String = (0..MAX_KNOWN_CLIENTS).map(|_| encode(&worst)).collect();
+    // NVS needs one more byte for the terminating NUL.
+    assert!(all.len() < KNOWN_BUFFER_LEN);
+
+    let ascii = KnownClient {
+        name: Some("x".repeat(MAX_NAME_LEN * 2)),
+        ..worst
+    };
+    assert_eq!(encode(&ascii).len(), MAX_LINE_LEN);
+}
+
+#[test]
+fn malformed_lines_are_skipped() {
+    assert_eq!(decode(""), None);
+    assert_eq!(decode("AA:BB:CC:DD:EE:FF\tsoon\t1\tname"), None);
+    assert_eq!(decode("AA:BB:CC:DD:EE:FF\t1"), None);
+}
@@ -0,0 +1,151 @@
+use std::{convert::Infallible, ops::Range};
+
+use host_tests::line_batch::{Batch, LineAccumulator};
+
+const WIDTH: usize = 240;
+const MAX_LINES: usize = 4;
+
+/// A sent batch, reduced to its rectangle and pixels.
+#[derive(Debug, PartialEq, Eq)]
+struct Sent {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    pixels: Vec<u16>,
+}
+
+/// Pixels for `line` over `range` that say where they came from.
+fn line_pixels(line: usize, range: &Range<usize>) -> Vec<u16> {
+    range.clone().map(|x| (line * 1000 + x) as u16).collect()
+}
+
+fn run(lines: &[(usize, Range<usize>)]) -> Vec<Sent> {
+    let mut sent = Vec::new();
+    let mut send = |batch: Batch<'_, u16>| -> Result<(), Infallible> {
+        assert_eq!(batch.pixels.len(), batch.width * batch.line_count);
+        sent.push(Sent {
+            x: batch.x,
+            y: batch.start_line,
+            width: batch.width,
+            height: batch.line_count,
+            pixels: batch.pixels.to_vec(),
+        });
+        Ok(())
+    };
+    let mut accumulator = LineAccumulator::new(WIDTH, MAX_LINES);
+    for (line, range) in lines {
+        let pixels = line_pixels(*line, range);
+        accumulator
+            .push_line(*line, range.clone(), &pixels, &mut send)
+            .unwrap();
+    }
+    accumulator.flush(&mut send).unwrap();
+    sent
+}
+
+fn rect(x: usize, y: usize, width: usize, height: usize) -> (usize, usize, usize, usize) {
+    (x, y, width, height)
+}
+
+fn rects(sent: &[Sent]) -> Vec<(usize, usize, usize, usize)> {
+    sent.iter()
+        .map(|sent| rect(sent.x, sent.y, sent.width, sent.height))
+        .collect()
+}
+
+#[test]
+fn consecutive_lines_over_the_same_columns_share_a_rectangle() {
+    let sent = run(&[(10, 20..60), (11, 20..60), (12, 20..60)]);
+    assert_eq!(rects(&sent), vec![rect(20, 10, 40, 3)]);
+    let expected: Vec<u16> = (10..13)
+        .flat_map(|line| line_pixels(line, &(20..60)))
+        .collect();
+    assert_eq!(sent[0].pixels, expected);
+}
+
+#[test]
+fn a_full_batch_is_sent_before_the_next_line() {
+    let lines: Vec<_> = (0..10).map(|line| (line, 0..WIDTH)).collect();
+    assert_eq!(
+        rects(&run(&lines)),
+        vec![
+            rect(0, 0, WIDTH, 4),
+            rect(0, 4, WIDTH, 4),
+            rect(0, 8, WIDTH, 2)
+        ]
+    );
+}
+
+#[test]
+fn a_gap_in_lines_starts_a_new_rectangle() {
+    let sent = run(&[(0, 0..8), (1, 0..8), (3, 0..8)]);
+    assert_eq!(rects(&sent), vec![rect(0, 0, 8, 2), rect(0, 3, 8, 1)]);
+}
+
+#[test]
+fn a_change_of_columns_starts_a_new_rectangle() {
+    let sent = run(&[(0, 0..8), (1, 4..12), (2, 4..10), (3, 4..10)]);
+    assert_eq!(
+        rects(&sent),
+        vec![rect(0, 0, 8, 1), rect(4, 1, 8, 1), rect(4, 2, 6, 2)]
+    );
+}
+
+#[test]
+fn going_back_up_the_screen_starts_a_new_rectangle() {
+    let sent = run(&[(5, 0..8), (6, 0..8), (0, 0..8)]);
+    assert_eq!(rects(&sent), vec![rect(0, 5, 8, 2), rect(0, 0, 8, 1)]);
+}
+
+#[test]
+fn empty_lines_are_skipped_without_breaking_a_run() {
+    let sent = run(&[(0, 0..8), (1, 5..5), (1, 0..8)]);
+    assert_eq!(rects(&sent), vec![rect(0, 0, 8, 2)]);
+}
+
+#[test]
+fn nothing_pending_sends_nothing() {
+    assert!(run(&[]).is_empty());
+}
+
+#[test]
+fn every_pixel_is_sent_once_in_line_order() {
+    let lines: Vec<_> = (0..37)
+        .map(|line| (line, (line % 3) * 10..(line % 3) * 10 + 50 + line % 2))
+        .collect();
+    let sent = run(&lines);
+    let flattened: Vec<u16> = sent.iter().flat_map(|sent| sent.pixels.clone()).collect();
+    let expected: Vec<u16> = lines
+        .iter()
+        .flat_map(|(line, range)| line_pixels(*line, range))
+        .collect();
+    assert_eq!(flattened, expected);
+    assert!(sent.iter().all(|sent| sent.height <= MAX_LINES));
+}
+
+#[test]
+fn a_failed_send_drops_the_batch_and_later_ones_stay_aligned() {
+    let mut calls = 0;
+    let mut sent = Vec::new();
+    let mut send = |batch: Batch<'_, u16>| -> Result<(), &'static str> {
+        calls += 1;
+        if calls == 1 {
+            return Err("transfer failed");
+        }
+        sent.push((batch.start_line, batch.line_count, batch.pixels.to_vec()));
+        Ok(())
+    };
+    let mut accumulator = LineAccumulator::new(WIDTH, MAX_LINES);
+    let range = 0..4;
+    for line in 0..4 {
+        let result =
+            accumulator.push_line(line, range.clone(), &line_pixels(line, &range), &mut send);
+        assert_eq!(result.is_err(), line == 3);
+    }
+    accumulator
+        .push_line(4, range.clone(), &line_pixels(4, &range), &mut send)
+        .unwrap();
+    accumulator.flush(&mut send).unwrap();
+    assert_eq!(sent, vec![(4, 1, line_pixels(4, &range))]);
+}
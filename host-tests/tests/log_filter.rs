@@ -0,0 +1,39 @@
+use host_tests::log_filter::session_lines;
+
+const DUMP: &str = "\
+[     1.000] I astrobox::miwear::session: Target Band 8 addr = aa:bb (s1)
+[     2.000] I miwear::aa:bb: s1 subscribed notify on 0x005E
+[     3.000] I astrobox::wifi: Connected
+[     4.000] I astrobox::miwear::session: Target Band 8 addr = aa:bb (s12)
+[     5.000] W miwear::aa:bb: BLE disconnected (s12, reason: 531)
+[     6.000] I astrobox::miwear::session: Watch Band 8 dropped (s1): timeout
+[     7.000] I astrobox::boot: reasons1 are not a tag
+";
+
+#[test]
+fn keeps_only_the_sessions_lines_in_order() {
+    let lines = session_lines(DUMP, 1);
+    assert_eq!(
+        lines.lines().map(|line| &line[..12]).collect::<Vec<_>>(),
+        ["[     1.000]", "[     2.000]", "[     6.000]"]
+    );
+    assert!(lines.ends_with('\n'));
+}
+
+#[test]
+fn a_longer_id_does_not_match_its_prefix() {
+    let lines = session_lines(DUMP, 12);
+    assert_eq!(lines.lines().count(), 2);
+    assert!(lines.lines().all(|line| line.contains("s12")));
+}
+
+#[test]
+fn an_unknown_session_gives_nothing() {
+    assert_eq!(session_lines(DUMP, 7), "");
+    assert_eq!(session_lines("", 1), "");
+}
+
+#[test]
+fn a_last_line_without_newline_is_kept_as_is() {
+    assert_eq!(session_lines("a (s2)\nb (s3)", 3), "b (s3)");
+}
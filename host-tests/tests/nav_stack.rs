@@ -0,0 +1,121 @@
+use host_tests::nav_stack::{Layer, NavStack};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Page {
+    Home,
+    Shade,
+    Settings,
+    Networks,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Overlay {
+    Keyboard,
+    Pairing,
+}
+
+fn stack() -> NavStack<Page, Overlay> {
+    NavStack::new(Page::Home)
+}
+
+/// What the UI's back action does to the stack once the overlay's own
+/// handler has run.
+fn back(stack: &mut NavStack<Page, Overlay>) -> bool {
+    match stack.top() {
+        Some(Layer::Overlay(kind)) => {
+            stack.remove_overlay(kind);
+            true
+        }
+        Some(Layer::Page(_)) => stack.pop().is_some(),
+        None => false,
+    }
+}
+
+#[test]
+fn starts_on_home() {
+    let stack = stack();
+    assert_eq!(stack.page(), Page::Home);
+    assert_eq!(stack.top(), None);
+    assert_eq!(stack.top_overlay(), None);
+}
+
+#[test]
+fn keyboard_over_settings_over_shade_backs_out_in_order() {
+    let mut stack = stack();
+    stack.navigate(Page::Shade);
+    stack.navigate(Page::Settings);
+    assert!(stack.push_overlay(Overlay::Keyboard));
+    assert_eq!(stack.page(), Page::Settings);
+    assert_eq!(stack.top_overlay(), Some(Overlay::Keyboard));
+
+    assert!(back(&mut stack));
+    assert_eq!((stack.page(), stack.top_overlay()), (Page::Settings, None));
+    assert!(back(&mut stack));
+    assert_eq!((stack.page(), stack.top_overlay()), (Page::Shade, None));
+    assert!(back(&mut stack));
+    assert_eq!((stack.page(), stack.top_overlay()), (Page::Home, None));
+    assert!(!back(&mut stack));
+}
+
+#[test]
+fn pages_open_beneath_overlays() {
+    let mut stack = stack();
+    stack.navigate(Page::Settings);
+    stack.push_overlay(Overlay::Keyboard);
+    stack.navigate(Page::Networks);
+    assert_eq!(stack.page(), Page::Networks);
+    assert_eq!(stack.top(), Some(Layer::Overlay(Overlay::Keyboard)));
+
+    back(&mut stack);
+    assert_eq!(stack.page(), Page::Networks);
+    back(&mut stack);
+    assert_eq!(stack.page(), Page::Settings);
+}
+
+#[test]
+fn revisiting_a_page_moves_it_up_instead_of_duplicating_it() {
+    let mut stack = stack();
+    stack.navigate(Page::Shade);
+    stack.navigate(Page::Settings);
+    stack.navigate(Page::Shade);
+    assert_eq!(stack.page(), Page::Shade);
+    back(&mut stack);
+    assert_eq!(stack.page(), Page::Settings);
+    back(&mut stack);
+    assert_eq!(stack.page(), Page::Home);
+}
+
+#[test]
+fn home_clears_the_pages_but_keeps_overlays() {
+    let mut stack = stack();
+    stack.navigate(Page::Shade);
+    stack.navigate(Page::Settings);
+    stack.push_overlay(Overlay::Pairing);
+    stack.navigate(Page::Home);
+    assert_eq!(stack.page(), Page::Home);
+    assert_eq!(stack.top(), Some(Layer::Overlay(Overlay::Pairing)));
+    back(&mut stack);
+    assert_eq!(stack.top(), None);
+}
+
+#[test]
+fn a_refreshed_overlay_keeps_its_place() {
+    let mut stack = stack();
+    stack.push_overlay(Overlay::Pairing);
+    stack.push_overlay(Overlay::Keyboard);
+    assert!(!stack.push_overlay(Overlay::Pairing));
+    assert_eq!(stack.top_overlay(), Some(Overlay::Keyboard));
+}
+
+#[test]
+fn an_overlay_can_close_from_under_another() {
+    let mut stack = stack();
+    stack.navigate(Page::Settings);
+    stack.push_overlay(Overlay::Pairing);
+    stack.push_overlay(Overlay::Keyboard);
+    assert!(stack.remove_overlay(Overlay::Pairing));
+    assert!(!stack.remove_overlay(Overlay::Pairing));
+    assert_eq!(stack.top_overlay(), Some(Overlay::Keyboard));
+    back(&mut stack);
+    assert_eq!((stack.page(), stack.top_overlay()), (Page::Settings, None));
+}
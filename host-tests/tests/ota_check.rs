@@ -0,0 +1,54 @@
+use host_tests::ota_check::{check_size, check_start, Rejection, IMAGE_MAGIC};
+
+const SLOT: u32 = 0x1E_0000;
+
+#[test]
+fn an_image_up_to_the_slot_size_is_accepted() {
+    assert_eq!(check_size(Some(1), SLOT), Ok(1));
+    assert_eq!(check_size(Some(u64::from(SLOT)), SLOT), Ok(u64::from(SLOT)));
+}
+
+#[test]
+fn one_byte_over_the_slot_is_refused_with_the_overage() {
+    let rejection = check_size(Some(u64::from(SLOT) + 1), SLOT).unwrap_err();
+    assert_eq!(
+        rejection,
+        Rejection::TooLarge {
+            len: u64::from(SLOT) + 1,
+            slot: SLOT
+        }
+    );
+    assert_eq!(rejection.status(), 413);
+    assert!(rejection.to_string().contains("1 bytes over"));
+}
+
+#[test]
+fn a_missing_or_zero_length_is_refused() {
+    assert_eq!(check_size(None, SLOT), Err(Rejection::NoLength));
+    assert_eq!(Rejection::NoLength.status(), 411);
+    assert_eq!(check_size(Some(0), SLOT), Err(Rejection::Empty));
+}
+
+#[test]
+fn only_esp_images_are_written() {
+    assert_eq!(check_start(&[IMAGE_MAGIC, 0x04, 0x02]), Ok(()));
+    assert_eq!(check_start(&[IMAGE_MAGIC]), Ok(()));
+    assert_eq!(
+        check_start(b"PK\x03\x04"),
+        Err(Rejection::NotAnImage { first: b'P' })
+    );
+    assert_eq!(check_start(&[]), Err(Rejection::Empty));
+}
+
+#[test]
+fn gzip_is_named_in_the_refusal() {
+    let rejection = check_start(&[0x1F, 0x8B, 0x08, 0x00]).unwrap_err();
+    assert_eq!(rejection, Rejection::Gzip);
+    assert_eq!(rejection.status(), 415);
+    assert!(rejection.to_string().contains("gzip"));
+    // A lone 0x1F is just not an image.
+    assert_eq!(
+        check_start(&[0x1F]),
+        Err(Rejection::NotAnImage { first: 0x1F })
+    );
+}
@@ -0,0 +1,73 @@
+use host_tests::pixel_fixup::{swap_rb, PixelFixup};
+
+const RED: u16 = 0xF800;
+const GREEN: u16 = 0x07E0;
+const BLUE: u16 = 0x001F;
+
+/// Bytes in the order the panel latches them; mipidsi sends the word
+/// high byte first.
+fn wire(fixup: PixelFixup, pixel: u16) -> [u8; 2] {
+    fixup.apply(pixel).to_be_bytes()
+}
+
+fn fixup(swap_bytes: bool, swap_rb: bool) -> PixelFixup {
+    PixelFixup {
+        swap_bytes,
+        swap_rb,
+    }
+}
+
+#[test]
+fn none_sends_the_word_unchanged() {
+    let none = fixup(false, false);
+    assert_eq!(wire(none, RED), [0xF8, 0x00]);
+    assert_eq!(wire(none, GREEN), [0x07, 0xE0]);
+    assert_eq!(wire(none, BLUE), [0x00, 0x1F]);
+    assert_eq!(wire(none, 0x1234), [0x12, 0x34]);
+}
+
+#[test]
+fn swap_bytes_sends_the_low_byte_first() {
+    let bytes = fixup(true, false);
+    assert_eq!(wire(bytes, RED), [0x00, 0xF8]);
+    assert_eq!(wire(bytes, GREEN), [0xE0, 0x07]);
+    assert_eq!(wire(bytes, BLUE), [0x1F, 0x00]);
+    assert_eq!(wire(bytes, 0x1234), [0x34, 0x12]);
+}
+
+#[test]
+fn swap_rb_trades_red_and_blue_and_keeps_green() {
+    let rb = fixup(false, true);
+    assert_eq!(wire(rb, RED), [0x00, 0x1F]);
+    assert_eq!(wire(rb, GREEN), [0x07, 0xE0]);
+    assert_eq!(wire(rb, BLUE), [0xF8, 0x00]);
+    assert_eq!(wire(rb, 0x1234), [0xA2, 0x22]);
+}
+
+#[test]
+fn both_swap_red_and_blue_before_the_bytes() {
+    let both = fixup(true, true);
+    assert_eq!(wire(both, RED), [0x1F, 0x00]);
+    assert_eq!(wire(both, GREEN), [0xE0, 0x07]);
+    assert_eq!(wire(both, BLUE), [0x00, 0xF8]);
+    assert_eq!(wire(both, 0x1234), [0x22, 0xA2]);
+    // Swapping the bytes first would mix green into red and blue.
+    assert_ne!(both.apply(0x1234), swap_rb(0x1234u16.swap_bytes()));
+}
+
+#[test]
+fn swap_rb_undoes_itself() {
+    for pixel in [0x0000, 0xFFFF, RED, GREEN, BLUE, 0x1234, 0xA5C3] {
+        assert_eq!(swap_rb(swap_rb(pixel)), pixel, "{pixel:#06x}");
+    }
+}
+
+#[test]
+fn all_lists_every_combination_once_in_pattern_order() {
+    let names: Vec<String> = PixelFixup::ALL.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        names,
+        ["none", "swap_bytes", "swap_rb", "swap_bytes + swap_rb"]
+    );
+    assert_eq!(PixelFixup::default(), PixelFixup::NONE);
+}
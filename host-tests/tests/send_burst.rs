@@ -0,0 +1,132 @@
+//! The write burst controller fed synthetic traces of acknowledged rounds
+//! and backoffs, the way the send worker's pacer feeds it.
+
+use host_tests::send_burst::{BurstPolicy, Controller, BURST_RANGE};
+
+#[derive(Clone, Copy, Debug)]
+enum Round {
+    Acked,
+    BackedOff,
+}
+
+use Round::{Acked, BackedOff};
+
+/// The burst after each round of `trace`.
+fn run(controller: &mut Controller, trace: &[Round]) -> Vec<u32> {
+    trace
+        .iter()
+        .map(|round| {
+            match round {
+                Acked => controller.acked(),
+                BackedOff => controller.back_off(),
+            };
+            controller.burst()
+        })
+        .collect()
+}
+
+#[test]
+fn auto_starts_at_one_and_doubles_until_the_first_backoff() {
+    let mut controller = Controller::new(BurstPolicy::Auto, 64);
+    assert_eq!(controller.burst(), 1);
+    assert_eq!(
+        run(&mut controller, &[Acked, Acked, Acked, Acked]),
+        [2, 4, 8, 16]
+    );
+}
+
+#[test]
+fn after_a_backoff_growth_is_one_per_clean_round() {
+    let mut controller = Controller::new(BurstPolicy::Auto, 64);
+    run(&mut controller, &[Acked, Acked, Acked]);
+    assert_eq!(controller.burst(), 8);
+    assert_eq!(
+        run(&mut controller, &[BackedOff, Acked, Acked, Acked]),
+        [4, 5, 6, 7]
+    );
+    // Slow start does not come back on its own.
+    assert_eq!(run(&mut controller, &[BackedOff, Acked]), [3, 4]);
+}
+
+#[test]
+fn a_backoff_halves_rounding_down() {
+    let mut controller = Controller::new(BurstPolicy::Auto, 64);
+    run(
+        &mut controller,
+        &[Acked, Acked, BackedOff, Acked, Acked, Acked],
+    );
+    assert_eq!(controller.burst(), 5);
+    assert_eq!(controller.back_off(), Some(2));
+    assert_eq!(controller.back_off(), Some(1));
+}
+
+#[test]
+fn the_burst_never_drops_under_one() {
+    let mut controller = Controller::new(BurstPolicy::Auto, 64);
+    assert_eq!(controller.back_off(), None);
+    assert_eq!(run(&mut controller, &[BackedOff, BackedOff]), [1, 1]);
+}
+
+#[test]
+fn growth_stops_at_the_ceiling() {
+    let mut controller = Controller::new(BurstPolicy::Auto, 12);
+    assert_eq!(
+        run(&mut controller, &[Acked, Acked, Acked, Acked, Acked]),
+        [2, 4, 8, 12, 12]
+    );
+    assert_eq!(controller.acked(), None);
+
+    controller.back_off();
+    assert_eq!(
+        run(&mut controller, &[Acked; 8]),
+        [7, 8, 9, 10, 11, 12, 12, 12]
+    );
+}
+
+#[test]
+fn the_ceiling_is_clamped_to_the_burst_range() {
+    let mut controller = Controller::new(BurstPolicy::Auto, 1_000);
+    run(&mut controller, &[Acked; 10]);
+    assert_eq!(controller.burst(), *BURST_RANGE.end());
+
+    let mut controller = Controller::new(BurstPolicy::Auto, 0);
+    assert_eq!(controller.acked(), None);
+    assert_eq!(controller.burst(), *BURST_RANGE.start());
+}
+
+#[test]
+fn a_fixed_burst_ignores_the_trace() {
+    let mut controller = Controller::new(BurstPolicy::Fixed(6), 64);
+    assert_eq!(controller.burst(), 6);
+    assert_eq!(controller.acked(), None);
+    assert_eq!(controller.back_off(), None);
+    assert_eq!(
+        run(&mut controller, &[Acked, BackedOff, BackedOff, Acked]),
+        [6, 6, 6, 6]
+    );
+}
+
+#[test]
+fn a_flapping_link_settles_between_the_floor_and_the_ceiling() {
+    let mut controller = Controller::new(BurstPolicy::Auto, 16);
+    let mut trace = Vec::new();
+    for _ in 0..50 {
+        trace.extend([Acked, Acked, Acked, BackedOff]);
+    }
+    let bursts = run(&mut controller, &trace);
+    assert!(bursts.iter().all(|burst| (1..=16).contains(burst)));
+    // Three clean rounds then a halving settle into a 3, 4, 5, 6 cycle.
+    assert_eq!(&bursts[bursts.len() - 4..], [4, 5, 6, 3]);
+}
+
+#[test]
+fn the_policy_round_trips_through_its_setting_text() {
+    assert_eq!("auto".parse(), Ok(BurstPolicy::Auto));
+    assert_eq!("1".parse(), Ok(BurstPolicy::Fixed(1)));
+    assert_eq!("64".parse(), Ok(BurstPolicy::Fixed(64)));
+    assert_eq!("0".parse::<BurstPolicy>(), Err(()));
+    assert_eq!("65".parse::<BurstPolicy>(), Err(()));
+    assert_eq!("fast".parse::<BurstPolicy>(), Err(()));
+    assert_eq!(BurstPolicy::Fixed(8).to_string(), "8");
+    assert_eq!(BurstPolicy::Auto.to_string(), "auto");
+}
@@ -0,0 +1,91 @@
+//! The send supervisor's verdicts, with writers run on threads standing in
+//! for the worker task.
+
+use std::{panic, thread};
+
+use host_tests::send_watchdog::{verdict, Budget, Exit, Verdict};
+
+/// Runs `writer` the way the supervisor awaits its worker.
+fn run_worker(writer: impl FnOnce() + Send + 'static) -> Exit {
+    match thread::spawn(writer).join() {
+        Ok(()) => Exit::Returned,
+        Err(payload) => Exit::Panicked(payload),
+    }
+}
+
+fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = f();
+    panic::set_hook(hook);
+    result
+}
+
+#[test]
+fn a_panicking_writer_is_a_death_with_its_message() {
+    let exit = quietly(|| run_worker(|| panic!("write to 0x005F exploded")));
+    assert_eq!(
+        verdict(exit, true),
+        Verdict::Died("write to 0x005F exploded".to_string())
+    );
+}
+
+#[test]
+fn a_formatted_panic_keeps_its_message() {
+    let exit = quietly(|| run_worker(|| panic!("batch of {} bytes", 244)));
+    assert_eq!(
+        verdict(exit, false),
+        Verdict::Died("batch of 244 bytes".to_string())
+    );
+}
+
+#[test]
+fn a_non_string_panic_is_still_a_death() {
+    let exit = quietly(|| run_worker(|| panic::panic_any(42u8)));
+    assert_eq!(
+        verdict(exit, true),
+        Verdict::Died("non-string panic".to_string())
+    );
+}
+
+#[test]
+fn a_writer_that_stops_with_the_queue_open_died() {
+    // What a panic looks like when it cannot be caught is the session going
+    // on without a worker; the supervisor sees the task end early.
+    let exit = run_worker(|| {});
+    assert!(matches!(verdict(exit, true), Verdict::Died(_)));
+}
+
+#[test]
+fn a_writer_that_stops_after_the_queue_closed_is_done() {
+    assert_eq!(verdict(run_worker(|| {}), false), Verdict::Done);
+}
+
+#[test]
+fn a_cancelled_worker_is_done() {
+    assert_eq!(verdict(Exit::Cancelled, true), Verdict::Done);
+}
+
+#[test]
+fn restarts_run_out_after_the_budget() {
+    let mut budget = Budget::new(3);
+    assert_eq!(budget.restart(), Some(1));
+    assert_eq!(budget.restart(), Some(2));
+    assert_eq!(budget.restart(), Some(3));
+    assert_eq!(budget.restart(), None);
+    assert_eq!(budget.used(), 3);
+}
+
+#[test]
+fn repeated_panics_escalate() {
+    let mut budget = Budget::new(3);
+    let mut escalated = false;
+    for _ in 0..4 {
+        let exit = quietly(|| run_worker(|| panic!("still broken")));
+        assert!(matches!(verdict(exit, true), Verdict::Died(_)));
+        if budget.restart().is_none() {
+            escalated = true;
+        }
+    }
+    assert!(escalated);
+}
@@ -0,0 +1,99 @@
+//! Finding 0x0050, 0x005E and 0x005F among discovered characteristics, as
+//! 16-bit UUIDs or inside 128-bit ones.
+
+use host_tests::session_uuids::{find, Found, SeenUuid, RECV, SENT, SERVICE_FLAG};
+
+fn short(uuid: u16) -> SeenUuid {
+    SeenUuid::new(Some(uuid), &format!("0x{uuid:04X}"))
+}
+
+fn long(text: &str) -> SeenUuid {
+    SeenUuid::new(None, text)
+}
+
+#[test]
+fn sixteen_bit_uuids_match_exactly() {
+    let uuids = [short(SENT), short(0x2A05), short(SERVICE_FLAG), short(RECV)];
+    assert_eq!(
+        find(&uuids),
+        Ok(Found {
+            service_flag: 2,
+            recv: 3,
+            sent: 0,
+        })
+    );
+}
+
+#[test]
+fn a_128_bit_uuid_matches_by_substring_ignoring_dashes_and_case() {
+    let uuids = [
+        long("0000005E-0000-1000-8000-00805F9B34FB"),
+        long("0000005f-0000-1000-8000-00805f9b34fb"),
+        long("00000050-0000-1000-8000-00805F9B34FB"),
+    ];
+    assert_eq!(
+        find(&uuids),
+        Ok(Found {
+            service_flag: 2,
+            recv: 0,
+            sent: 1,
+        })
+    );
+}
+
+#[test]
+fn an_exact_match_wins_over_an_earlier_substring() {
+    let uuids = [
+        long("0000005e-0000-1000-8000-00805f9b34fb"),
+        short(RECV),
+        short(SENT),
+        short(SERVICE_FLAG),
+    ];
+    assert_eq!(find(&uuids).map(|found| found.recv), Ok(1));
+}
+
+#[test]
+fn only_the_missing_ones_are_looked_up_by_substring() {
+    let uuids = [
+        short(RECV),
+        short(SENT),
+        long("00000050-0000-1000-8000-00805f9b34fb"),
+    ];
+    assert_eq!(
+        find(&uuids),
+        Ok(Found {
+            service_flag: 2,
+            recv: 0,
+            sent: 1,
+        })
+    );
+}
+
+#[test]
+fn one_characteristic_is_claimed_once_receive_first() {
+    // Holds both 005e and 0050; receive takes it, the flag takes the next.
+    let uuids = [
+        long("0000005e-0050-1000-8000-00805f9b34fb"),
+        long("00000050-0000-1000-8000-00805f9b34fb"),
+        short(SENT),
+    ];
+    assert_eq!(
+        find(&uuids),
+        Ok(Found {
+            service_flag: 1,
+            recv: 0,
+            sent: 2,
+        })
+    );
+}
+
+#[test]
+fn the_first_missing_uuid_is_reported_flag_first() {
+    assert_eq!(find(&[]), Err(SERVICE_FLAG));
+    assert_eq!(find(&[short(SERVICE_FLAG)]), Err(RECV));
+    assert_eq!(find(&[short(SERVICE_FLAG), short(RECV)]), Err(SENT));
+    assert_eq!(
+        find(&[short(0x2A00), short(RECV), short(SENT)]),
+        Err(SERVICE_FLAG)
+    );
+}
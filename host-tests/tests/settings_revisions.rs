@@ -0,0 +1,185 @@
+//! Setting revisions: the 409 path of `set_at_revision` and what reaches
+//! flash once the NVS writer has coalesced a burst of stores.
+
+use std::collections::HashMap;
+
+use host_tests::{
+    nvs_batch::Batch,
+    settings_revision::{update, Stamped, Update},
+};
+
+const NAMESPACE: &str = "settings_v2";
+
+/// The flash a batch commits to, keyed like NVS.
+type Flash = HashMap<(&'static str, &'static str), String>;
+
+fn stamped(revision: u32, value: &str) -> Stamped {
+    Stamped {
+        revision,
+        value: value.to_string(),
+    }
+}
+
+/// `settings::store` for one key: decide against what is stored, then queue
+/// the record.
+fn store(
+    batch: &mut Batch,
+    current: &mut Option<Stamped>,
+    key: &'static str,
+    value: &str,
+    expected: Option<u32>,
+) -> Update {
+    let outcome = update(current.as_ref(), value, expected);
+    if let Update::Store(next) = &outcome {
+        batch.push(NAMESPACE, key, Some(next.encode()));
+        *current = Some(next.clone());
+    }
+    outcome
+}
+
+fn commit(batch: &Batch, flash: &mut Flash) {
+    for (namespace, key, value) in batch.iter() {
+        match value {
+            Some(value) => flash.insert((namespace, key), value.to_string()),
+            None => flash.remove(&(namespace, key)),
+        };
+    }
+}
+
+/// What a reboot reads back for `key`.
+fn reload(flash: &Flash, key: &'static str) -> Option<Stamped> {
+    flash
+        .get(&(NAMESPACE, key))
+        .and_then(|raw| Stamped::decode(raw))
+}
+
+#[test]
+fn stale_revision_conflicts_with_the_current_one() {
+    let current = stamped(3, "dark");
+    assert_eq!(
+        update(Some(&current), "light", Some(2)),
+        Update::Conflict(3)
+    );
+    assert_eq!(
+        update(Some(&current), "light", Some(4)),
+        Update::Conflict(3)
+    );
+    // Even a write of the stored value: the editor has not seen revision 3.
+    assert_eq!(update(Some(&current), "dark", Some(2)), Update::Conflict(3));
+    assert_eq!(
+        update(Some(&current), "light", Some(3)),
+        Update::Store(stamped(4, "light"))
+    );
+}
+
+#[test]
+fn a_setting_never_stored_is_at_revision_zero() {
+    assert_eq!(update(None, "on", Some(1)), Update::Conflict(0));
+    assert_eq!(update(None, "on", Some(0)), Update::Store(stamped(1, "on")));
+}
+
+#[test]
+fn unconditional_writes_always_win_and_skip_unchanged_values() {
+    let current = stamped(7, "20");
+    assert_eq!(update(Some(&current), "20", None), Update::Unchanged(7));
+    assert_eq!(
+        update(Some(&current), "25", None),
+        Update::Store(stamped(8, "25"))
+    );
+    assert_eq!(update(Some(&current), "20", Some(7)), Update::Unchanged(7));
+}
+
+#[test]
+fn records_round_trip_values_holding_colons() {
+    let record = stamped(12, "07:30");
+    assert_eq!(record.encode(), "12:07:30");
+    assert_eq!(Stamped::decode("12:07:30"), Some(record));
+    assert_eq!(
+        Stamped::decode(&stamped(0, "").encode()),
+        Some(stamped(0, ""))
+    );
+    assert_eq!(Stamped::decode("07:30x"), Some(stamped(7, "30x")));
+    assert_eq!(Stamped::decode("dark"), None);
+    assert_eq!(Stamped::decode("-1:dark"), None);
+}
+
+#[test]
+fn coalesced_stores_persist_the_latest_revision_with_its_value() {
+    let mut flash = Flash::new();
+    let mut batch = Batch::default();
+    let (mut brightness, mut theme) = (None, None);
+    store(&mut batch, &mut brightness, "brightness", "40", None);
+    store(&mut batch, &mut theme, "theme", "dark", None);
+    store(&mut batch, &mut brightness, "brightness", "60", Some(1));
+    store(&mut batch, &mut brightness, "brightness", "80", None);
+    // One entry per key, the revision riding with the value it wrote.
+    assert_eq!(batch.len(), 2);
+    commit(&batch, &mut flash);
+
+    assert_eq!(reload(&flash, "brightness"), Some(stamped(3, "80")));
+    assert_eq!(reload(&flash, "theme"), Some(stamped(1, "dark")));
+}
+
+#[test]
+fn revisions_survive_a_reboot_between_batches() {
+    let mut flash = Flash::new();
+    let mut batch = Batch::default();
+    let mut current = None;
+    for value in ["1", "2", "3"] {
+        store(&mut batch, &mut current, "volume", value, None);
+    }
+    commit(&batch, &mut flash);
+
+    // An editor that read revision 3 before the reboot still matches it;
+    // one that read revision 2 gets the 409 with what flash holds.
+    let mut current = reload(&flash, "volume");
+    let mut batch = Batch::default();
+    assert_eq!(
+        store(&mut batch, &mut current, "volume", "9", Some(2)),
+        Update::Conflict(3)
+    );
+    assert!(batch.is_empty());
+    assert_eq!(
+        store(&mut batch, &mut current, "volume", "4", Some(3)),
+        Update::Store(stamped(4, "4"))
+    );
+    commit(&batch, &mut flash);
+    assert_eq!(reload(&flash, "volume"), Some(stamped(4, "4")));
+}
+
+#[test]
+fn a_batch_cut_short_never_pairs_a_value_with_another_revision() {
+    let mut batch = Batch::default();
+    let (mut a, mut b) = (Some(stamped(5, "x")), None);
+    store(&mut batch, &mut a, "a", "y", Some(5));
+    store(&mut batch, &mut b, "b", "z", None);
+    store(&mut batch, &mut a, "a", "w", Some(6));
+    let written: Vec<_> = batch.iter().collect();
+    // A reset after any number of the batch's writes.
+    for committed in 0..=written.len() {
+        let mut flash = Flash::new();
+        flash.insert((NAMESPACE, "a"), stamped(5, "x").encode());
+        for (namespace, key, value) in &written[..committed] {
+            flash.insert((namespace, key), value.unwrap().to_string());
+        }
+        let a = reload(&flash, "a").unwrap();
+        assert!(
+            a == stamped(5, "x") || a == stamped(7, "w"),
+            "{a:?} after {committed} writes"
+        );
+    }
+}
+
+#[test]
+fn coalescing_keeps_the_first_queued_order() {
+    let mut batch = Batch::default();
+    assert!(!batch.push(NAMESPACE, "a", Some("1:a".into())));
+    assert!(!batch.push("settings", "a", None));
+    assert!(batch.push(NAMESPACE, "a", Some("2:b".into())));
+    assert!(batch.push("settings", "a", None));
+    let keys: Vec<_> = batch.iter().collect();
+    assert_eq!(
+        keys,
+        vec![(NAMESPACE, "a", Some("2:b")), ("settings", "a", None)]
+    );
+}
@@ -0,0 +1,115 @@
+use host_tests::{advertising_limits as adv, backlight_limits as backlight, send_burst as burst};
+
+#[test]
+fn brightness_accepts_min_to_full() {
+    assert!(backlight::check_brightness(4).is_err());
+    assert!(backlight::check_brightness(backlight::MIN_BRIGHTNESS_PERCENT).is_ok());
+    assert!(backlight::check_brightness(100).is_ok());
+    assert!(backlight::check_brightness(101).is_err());
+}
+
+#[test]
+fn dim_level_accepts_min_to_full() {
+    assert!(backlight::check_dim_percent(4).is_err());
+    assert!(backlight::check_dim_percent(5).is_ok());
+    assert!(backlight::check_dim_percent(100).is_ok());
+    assert!(backlight::check_dim_percent(101).is_err());
+}
+
+#[test]
+fn dim_timeout_must_be_nonzero_and_below_off() {
+    assert_eq!(
+        backlight::check_dim_after(0, 30),
+        Err("dim timeout must be nonzero".into())
+    );
+    assert!(backlight::check_dim_after(1, 30).is_ok());
+    assert!(backlight::check_dim_after(29, 30).is_ok());
+    assert_eq!(
+        backlight::check_dim_after(30, 30),
+        Err("dim timeout must be below the 30 s off timeout".into())
+    );
+}
+
+#[test]
+fn off_timeout_must_be_nonzero_and_above_dim() {
+    assert_eq!(
+        backlight::check_off_after(0, 0),
+        Err("off timeout must be nonzero".into())
+    );
+    assert_eq!(
+        backlight::check_off_after(20, 20),
+        Err("off timeout must be above the 20 s dim timeout".into())
+    );
+    assert!(backlight::check_off_after(21, 20).is_ok());
+}
+
+#[test]
+fn wake_delay_is_capped() {
+    assert!(backlight::check_full_after_wake(0).is_ok());
+    assert!(backlight::check_full_after_wake(backlight::MAX_FULL_AFTER_WAKE_MS).is_ok());
+    assert_eq!(
+        backlight::check_full_after_wake(5_001),
+        Err("wake delay is at most 5000 ms".into())
+    );
+}
+
+#[test]
+fn advertising_interval_bounds() {
+    assert!(adv::check_interval(19).is_err());
+    assert!(adv::check_interval(20).is_ok());
+    assert!(adv::check_interval(10_240).is_ok());
+    assert_eq!(
+        adv::check_interval(10_241),
+        Err("interval is 20 to 10240 ms".into())
+    );
+}
+
+#[test]
+fn advertising_min_may_meet_but_not_pass_max() {
+    assert!(adv::check_min_interval(211, 211).is_ok());
+    assert_eq!(
+        adv::check_min_interval(212, 211),
+        Err("above the maximum interval of 211 ms".into())
+    );
+    // The range check comes first, even when the order is fine.
+    assert!(adv::check_min_interval(19, 211).is_err());
+}
+
+#[test]
+fn advertising_max_may_meet_but_not_pass_min() {
+    assert!(adv::check_max_interval(152, 152).is_ok());
+    assert_eq!(
+        adv::check_max_interval(151, 152),
+        Err("below the minimum interval of 152 ms".into())
+    );
+    assert!(adv::check_max_interval(10_241, 152).is_err());
+}
+
+#[test]
+fn tx_power_takes_only_controller_levels() {
+    for dbm in adv::TX_LEVELS_DBM {
+        assert!(adv::check_tx_power(dbm).is_ok(), "{dbm} dBm");
+    }
+    for dbm in [-13, -11, 1, 10] {
+        assert!(adv::check_tx_power(dbm).is_err(), "{dbm} dBm");
+    }
+}
+
+#[test]
+fn burst_ceiling_bounds() {
+    assert!(burst::check_max_burst(0).is_err());
+    assert!(burst::check_max_burst(1).is_ok());
+    assert!(burst::check_max_burst(64).is_ok());
+    assert_eq!(
+        burst::check_max_burst(65),
+        Err("burst is 1 to 64 writes".into())
+    );
+}
+
+#[test]
+fn rssi_floor_bounds() {
+    assert!(burst::check_rssi_floor(-128).is_err());
+    assert!(burst::check_rssi_floor(-127).is_ok());
+    assert!(burst::check_rssi_floor(0).is_ok());
+    assert!(burst::check_rssi_floor(1).is_err());
+}
@@ -0,0 +1,158 @@
+//! The first-run wizard's state machine: what Next, Skip and Back do for
+//! each step, and what the summary reports.
+
+use host_tests::setup_wizard::{Facts, Outcome, Step, Wizard};
+
+const ALL: [Step; 6] = [
+    Step::Language,
+    Step::Wifi,
+    Step::Time,
+    Step::Watch,
+    Step::Brightness,
+    Step::Summary,
+];
+
+fn wizard() -> Wizard {
+    Wizard::new(ALL.to_vec())
+}
+
+const NOTHING: Facts = Facts {
+    wifi_connected: false,
+    clock_set: false,
+    watch_added: false,
+};
+
+const EVERYTHING: Facts = Facts {
+    wifi_connected: true,
+    clock_set: true,
+    watch_added: true,
+};
+
+#[test]
+fn the_summary_is_always_last_and_only_once() {
+    let wizard = Wizard::new(vec![Step::Summary, Step::Language, Step::Brightness]);
+    assert_eq!(wizard.position(), (0, 3));
+    assert_eq!(wizard.step(), Step::Language);
+
+    let wizard = Wizard::new(Vec::new());
+    assert_eq!(wizard.position(), (0, 1));
+    assert_eq!(wizard.step(), Step::Summary);
+}
+
+#[test]
+fn steps_the_build_cannot_offer_are_left_out() {
+    let wizard = Wizard::new(vec![Step::Language, Step::Time, Step::Brightness]);
+    assert!(!wizard.has(Step::Wifi));
+    assert!(!wizard.has(Step::Watch));
+    assert!(wizard.has(Step::Summary));
+}
+
+#[test]
+fn next_walks_every_step_when_each_condition_holds() {
+    let mut wizard = wizard();
+    for (index, step) in ALL.iter().enumerate() {
+        assert_eq!(wizard.step(), *step);
+        assert_eq!(wizard.position(), (index, ALL.len()));
+        assert!(!wizard.finished());
+        assert!(wizard.next(&EVERYTHING));
+    }
+    assert!(wizard.finished());
+    for step in ALL {
+        assert_eq!(wizard.outcome(step), Some(Outcome::Done));
+    }
+}
+
+#[test]
+fn next_waits_for_the_condition_of_each_optional_step() {
+    let mut wizard = wizard();
+    assert!(wizard.next(&NOTHING));
+
+    assert_eq!(wizard.step(), Step::Wifi);
+    assert!(!wizard.next(&NOTHING));
+    assert_eq!(wizard.step(), Step::Wifi);
+    let wifi = Facts {
+        wifi_connected: true,
+        ..NOTHING
+    };
+    assert!(wizard.next(&wifi));
+
+    assert_eq!(wizard.step(), Step::Time);
+    assert!(!wizard.next(&wifi));
+    let time = Facts {
+        clock_set: true,
+        ..wifi
+    };
+    assert!(wizard.next(&time));
+
+    assert_eq!(wizard.step(), Step::Watch);
+    assert!(!wizard.next(&time));
+    assert!(wizard.next(&EVERYTHING));
+    assert_eq!(wizard.step(), Step::Brightness);
+}
+
+#[test]
+fn only_unmet_optional_steps_can_be_skipped() {
+    let mut wizard = wizard();
+    assert!(!wizard.can_skip(&NOTHING));
+    assert!(!wizard.skip(&NOTHING));
+    assert_eq!(wizard.step(), Step::Language);
+    assert!(wizard.next(&NOTHING));
+
+    // Met: Next is the way on, so Skip is not offered.
+    assert!(!wizard.can_skip(&EVERYTHING));
+    assert!(!wizard.skip(&EVERYTHING));
+    assert_eq!(wizard.step(), Step::Wifi);
+
+    for step in [Step::Wifi, Step::Time, Step::Watch] {
+        assert_eq!(wizard.step(), step);
+        assert!(wizard.can_skip(&NOTHING));
+        assert!(wizard.skip(&NOTHING));
+        assert_eq!(wizard.outcome(step), Some(Outcome::Skipped));
+    }
+
+    assert_eq!(wizard.step(), Step::Brightness);
+    assert!(!wizard.skip(&NOTHING));
+    assert!(wizard.next(&NOTHING));
+    assert_eq!(wizard.step(), Step::Summary);
+    assert!(!wizard.skip(&NOTHING));
+    assert!(wizard.next(&NOTHING));
+    assert!(wizard.finished());
+}
+
+#[test]
+fn back_stops_at_the_first_step() {
+    let mut wizard = wizard();
+    assert!(!wizard.back());
+    assert!(wizard.next(&NOTHING));
+    assert!(wizard.back());
+    assert_eq!(wizard.step(), Step::Language);
+    assert!(!wizard.back());
+}
+
+#[test]
+fn the_outcome_is_the_last_way_a_step_was_left_forwards() {
+    let mut wizard = wizard();
+    assert!(wizard.next(&NOTHING));
+    assert!(wizard.skip(&NOTHING));
+    assert_eq!(wizard.outcome(Step::Wifi), Some(Outcome::Skipped));
+
+    // Back to Wi-Fi, which has connected since.
+    assert!(wizard.back());
+    assert_eq!(wizard.step(), Step::Wifi);
+    assert_eq!(wizard.outcome(Step::Wifi), Some(Outcome::Skipped));
+    assert!(wizard.next(&EVERYTHING));
+    assert_eq!(wizard.outcome(Step::Wifi), Some(Outcome::Done));
+    assert_eq!(wizard.outcome(Step::Time), None);
+}
+
+#[test]
+fn back_from_the_summary_leaves_the_wizard_unfinished() {
+    let mut wizard = Wizard::new(vec![Step::Language]);
+    assert!(wizard.next(&NOTHING));
+    assert_eq!(wizard.step(), Step::Summary);
+    assert!(wizard.back());
+    assert!(!wizard.finished());
+    assert!(wizard.next(&NOTHING));
+    assert!(wizard.next(&NOTHING));
+    assert!(wizard.finished());
+}
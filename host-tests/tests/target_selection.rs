@@ -0,0 +1,125 @@
+//! Which stored watch targets a scan tries, in what order, and which of
+//! them an advertisement is.
+
+use host_tests::target_selection::{candidates, matches, rank, Target};
+
+#[derive(Clone, Debug, PartialEq)]
+struct Watch {
+    id: u32,
+    enabled: bool,
+    key: &'static str,
+    name: &'static str,
+    addr: Option<&'static str>,
+}
+
+impl Target for Watch {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn auth_key(&self) -> &str {
+        self.key
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn addr(&self) -> Option<&str> {
+        self.addr
+    }
+}
+
+fn watch(id: u32, name: &'static str) -> Watch {
+    Watch {
+        id,
+        enabled: true,
+        key: "",
+        name,
+        addr: None,
+    }
+}
+
+fn ids(watches: &[Watch]) -> Vec<u32> {
+    watches.iter().map(|watch| watch.id).collect()
+}
+
+#[test]
+fn the_name_matches_as_a_substring_of_the_advertised_one() {
+    let daily = watch(1, "Watch S4");
+    assert!(matches(&daily, Some("Xiaomi Watch S4 41mm"), "aa:bb"));
+    assert!(!matches(&daily, Some("Xiaomi Watch S3"), "aa:bb"));
+    assert!(!matches(&daily, None, "aa:bb"));
+}
+
+#[test]
+fn an_address_wins_over_the_name() {
+    let band = Watch {
+        addr: Some("AA:BB:CC:DD:EE:FF"),
+        ..watch(2, "Band")
+    };
+    assert!(matches(&band, None, "aa:bb:cc:dd:ee:ff"));
+    assert!(!matches(&band, Some("Band 9"), "aa:bb:cc:dd:ee:00"));
+}
+
+#[test]
+fn disabled_targets_and_refused_keys_are_left_out() {
+    let targets = vec![
+        Watch {
+            enabled: false,
+            ..watch(1, "A")
+        },
+        Watch {
+            key: "refused",
+            ..watch(2, "B")
+        },
+        Watch {
+            key: "good",
+            ..watch(3, "C")
+        },
+        watch(4, "D"),
+    ];
+    let kept = candidates(targets, &["refused".to_string()], None);
+    assert_eq!(ids(&kept), [3, 4]);
+}
+
+#[test]
+fn a_requested_target_goes_first_and_the_rest_keep_their_order() {
+    let targets = vec![watch(1, "A"), watch(2, "B"), watch(3, "C")];
+    assert_eq!(ids(&candidates(targets.clone(), &[], Some(3))), [3, 1, 2]);
+    assert_eq!(ids(&candidates(targets.clone(), &[], Some(1))), [1, 2, 3]);
+    assert_eq!(ids(&candidates(targets, &[], None)), [1, 2, 3]);
+}
+
+#[test]
+fn a_request_for_a_target_that_is_not_a_candidate_changes_nothing() {
+    let targets = vec![
+        watch(1, "A"),
+        Watch {
+            enabled: false,
+            ..watch(2, "B")
+        },
+    ];
+    assert_eq!(ids(&candidates(targets.clone(), &[], Some(2))), [1]);
+    assert_eq!(ids(&candidates(targets, &[], Some(9))), [1]);
+}
+
+#[test]
+fn the_rank_is_the_first_candidate_the_advertisement_is() {
+    let list = vec![
+        Watch {
+            addr: Some("11:22:33:44:55:66"),
+            ..watch(1, "S4")
+        },
+        watch(2, "S4"),
+        watch(3, "Watch"),
+    ];
+    assert_eq!(rank(&list, Some("Watch S4"), "11:22:33:44:55:66"), Some(0));
+    assert_eq!(rank(&list, Some("Watch S4"), "66:55:44:33:22:11"), Some(1));
+    assert_eq!(rank(&list, Some("Watch 3"), "66:55:44:33:22:11"), Some(2));
+    assert_eq!(rank(&list, Some("Band"), "66:55:44:33:22:11"), None);
+}
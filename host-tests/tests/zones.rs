@@ -0,0 +1,97 @@
+//! DST boundaries from the rules in `timezones.txt`, each checked one second
+//! before and at the changing instant. Expected local times are tzdata's.
+
+use host_tests::zones;
+
+/// `unix` in `zone`, as `LocalTime` displays it, and its offset in minutes.
+fn local(zone: &str, unix: i64) -> (String, i32) {
+    let local = zones::find(zone).unwrap().local(unix);
+    (local.to_string(), local.offset)
+}
+
+fn assert_change(zone: &str, unix: i64, before: (&str, i32), after: (&str, i32)) {
+    assert_eq!(
+        local(zone, unix - 1),
+        (before.0.to_string(), before.1),
+        "{zone} before {unix}"
+    );
+    assert_eq!(
+        local(zone, unix),
+        (after.0.to_string(), after.1),
+        "{zone} at {unix}"
+    );
+}
+
+#[test]
+fn new_york_springs_forward_and_falls_back_at_two() {
+    assert_change(
+        "America/New_York",
+        1_741_503_600,
+        ("2025-03-09 01:59:59 EST", -300),
+        ("2025-03-09 03:00:00 EDT", -240),
+    );
+    assert_change(
+        "America/New_York",
+        1_762_063_200,
+        ("2025-11-02 01:59:59 EDT", -240),
+        ("2025-11-02 01:00:00 EST", -300),
+    );
+    assert_change(
+        "America/New_York",
+        1_772_953_200,
+        ("2026-03-08 01:59:59 EST", -300),
+        ("2026-03-08 03:00:00 EDT", -240),
+    );
+}
+
+#[test]
+fn sydney_keeps_dst_across_new_year() {
+    assert_change(
+        "Australia/Sydney",
+        1_743_868_800,
+        ("2025-04-06 02:59:59 AEDT", 660),
+        ("2025-04-06 02:00:00 AEST", 600),
+    );
+    assert_change(
+        "Australia/Sydney",
+        1_759_593_600,
+        ("2025-10-05 01:59:59 AEST", 600),
+        ("2025-10-05 03:00:00 AEDT", 660),
+    );
+    assert_eq!(
+        local("Australia/Sydney", 1_767_225_600),
+        ("2026-01-01 11:00:00 AEDT".to_string(), 660)
+    );
+}
+
+#[test]
+fn santiago_changes_at_midnight_on_saturday() {
+    // `M4.1.6/24`: the end of the first Saturday in April, in DST.
+    assert_change(
+        "America/Santiago",
+        1_743_908_400,
+        ("2025-04-05 23:59:59 -03", -180),
+        ("2025-04-05 23:00:00 -04", -240),
+    );
+    // `M9.1.6/24`: the end of the first Saturday in September, standard.
+    assert_change(
+        "America/Santiago",
+        1_757_217_600,
+        ("2025-09-06 23:59:59 -04", -240),
+        ("2025-09-07 01:00:00 -03", -180),
+    );
+    assert_eq!(
+        local("America/Santiago", 1_767_182_400),
+        ("2025-12-31 09:00:00 -03".to_string(), -180)
+    );
+}
+
+#[test]
+fn dst_flag_and_abbreviation_follow_the_offset() {
+    let zone = zones::find("america/new_york").unwrap();
+    let summer = zone.local(1_741_503_600);
+    assert!(summer.dst && summer.abbr == "EDT");
+    let winter = zone.local(1_762_063_200);
+    assert!(!winter.dst && winter.abbr == "EST");
+    assert_eq!(winter.ancs_date(), "20251102T010000");
+}
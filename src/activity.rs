@@ -0,0 +1,51 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static OTA_COUNT: AtomicUsize = AtomicUsize::new(0);
+static TRANSFER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Long-running operations that other subsystems must not disturb
+/// (e.g. Wi-Fi scans that briefly drop the STA link).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Ota,
+    WatchTransfer,
+}
+
+impl Activity {
+    fn counter(self) -> &'static AtomicUsize {
+        match self {
+            Activity::Ota => &OTA_COUNT,
+            Activity::WatchTransfer => &TRANSFER_COUNT,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Activity::Ota => "OTA update",
+            Activity::WatchTransfer => "watch transfer",
+        }
+    }
+}
+
+pub struct ActivityGuard(Activity);
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        self.0.counter().fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub fn begin(activity: Activity) -> ActivityGuard {
+    activity.counter().fetch_add(1, Ordering::AcqRel);
+    ActivityGuard(activity)
+}
+
+pub fn is_active(activity: Activity) -> bool {
+    activity.counter().load(Ordering::Acquire) > 0
+}
+
+pub fn busy() -> Option<Activity> {
+    [Activity::Ota, Activity::WatchTransfer]
+        .into_iter()
+        .find(|activity| is_active(*activity))
+}
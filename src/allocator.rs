@@ -1,18 +1,158 @@
 use core::{
     alloc::{GlobalAlloc, Layout},
     ptr,
+    sync::atomic::{AtomicU32, AtomicU8, Ordering},
 };
 use esp_idf_svc::sys::{
-    heap_caps_aligned_alloc, heap_caps_free, heap_caps_malloc, MALLOC_CAP_8BIT,
-    MALLOC_CAP_INTERNAL, MALLOC_CAP_SPIRAM,
+    heap_caps_aligned_alloc, heap_caps_free, heap_caps_get_total_size, heap_caps_malloc,
+    MALLOC_CAP_8BIT, MALLOC_CAP_INTERNAL, MALLOC_CAP_SPIRAM,
 };
 
+pub mod placement;
+pub mod stress;
+pub mod trace;
+
+pub use placement::{with_placement, InternalBox, Placement, PsramVec};
+
 pub struct PsramFirstAllocator;
 
 const PSRAM_CAPS: u32 = (MALLOC_CAP_SPIRAM | MALLOC_CAP_8BIT) as u32;
 const INTERNAL_CAPS: u32 = (MALLOC_CAP_INTERNAL | MALLOC_CAP_8BIT) as u32;
 const DEFAULT_ALIGNMENT: usize = core::mem::size_of::<usize>();
 
+/// Allocations at or below this size prefer internal RAM: they are latency
+/// sensitive and would otherwise fragment the PSRAM needed for framebuffers.
+/// A [`with_placement`] scope overrides this for its task.
+pub const SMALL_OBJECT_THRESHOLD: usize = 128;
+
+const PSRAM_UNKNOWN: u8 = 0;
+const PSRAM_PRESENT: u8 = 1;
+const PSRAM_ABSENT: u8 = 2;
+/// Present but rejected by `memory::verify_psram`.
+const PSRAM_DISABLED: u8 = 3;
+
+static PSRAM_STATE: AtomicU8 = AtomicU8::new(PSRAM_UNKNOWN);
+static COUNTERS: [BucketCounters; 2] = [BucketCounters::new(), BucketCounters::new()];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bucket {
+    /// `<= SMALL_OBJECT_THRESHOLD`, internal RAM first.
+    Small,
+    /// Everything else, PSRAM first.
+    Large,
+}
+
+impl Bucket {
+    pub const ALL: [Bucket; 2] = [Bucket::Small, Bucket::Large];
+
+    #[inline(always)]
+    fn for_size(size: usize) -> Self {
+        if size <= SMALL_OBJECT_THRESHOLD {
+            Bucket::Small
+        } else {
+            Bucket::Large
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Bucket::Small => "small",
+            Bucket::Large => "large",
+        }
+    }
+}
+
+struct BucketCounters {
+    preferred: AtomicU32,
+    fallback: AtomicU32,
+    failed: AtomicU32,
+}
+
+impl BucketCounters {
+    const fn new() -> Self {
+        Self {
+            preferred: AtomicU32::new(0),
+            fallback: AtomicU32::new(0),
+            failed: AtomicU32::new(0),
+        }
+    }
+}
+
+/// Where a bucket's allocations ended up since boot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BucketStats {
+    pub preferred: u32,
+    pub fallback: u32,
+    pub failed: u32,
+}
+
+impl BucketStats {
+    pub fn total(&self) -> u32 {
+        self.preferred
+            .saturating_add(self.fallback)
+            .saturating_add(self.failed)
+    }
+
+    /// Share of requests served from the bucket's preferred region, in percent.
+    pub fn hit_rate(&self) -> Option<f32> {
+        let total = self.total();
+        (total > 0).then(|| self.preferred as f32 * 100.0 / total as f32)
+    }
+
+    pub fn since(&self, earlier: &BucketStats) -> BucketStats {
+        BucketStats {
+            preferred: self.preferred.wrapping_sub(earlier.preferred),
+            fallback: self.fallback.wrapping_sub(earlier.fallback),
+            failed: self.failed.wrapping_sub(earlier.failed),
+        }
+    }
+}
+
+pub fn bucket_stats(bucket: Bucket) -> BucketStats {
+    let counters = &COUNTERS[bucket as usize];
+    BucketStats {
+        preferred: counters.preferred.load(Ordering::Relaxed),
+        fallback: counters.fallback.load(Ordering::Relaxed),
+        failed: counters.failed.load(Ordering::Relaxed),
+    }
+}
+
+/// Whether the heap has any SPIRAM region. Probed once, so boards without
+/// PSRAM skip the doomed PSRAM attempt on every allocation.
+#[inline(always)]
+pub fn psram_available() -> bool {
+    match PSRAM_STATE.load(Ordering::Relaxed) {
+        PSRAM_PRESENT => true,
+        PSRAM_ABSENT | PSRAM_DISABLED => false,
+        _ => {
+            let present = unsafe { heap_caps_get_total_size(MALLOC_CAP_SPIRAM as u32) } > 0;
+            let state = if present { PSRAM_PRESENT } else { PSRAM_ABSENT };
+            PSRAM_STATE.store(state, Ordering::Relaxed);
+            present
+        }
+    }
+}
+
+/// Routes every allocation to internal RAM from now on. Blocks already in
+/// PSRAM stay valid; `dealloc` frees them wherever they live.
+pub fn disable_psram() {
+    PSRAM_STATE.store(PSRAM_DISABLED, Ordering::Relaxed);
+}
+
+pub fn log_mode() {
+    if psram_available() {
+        log::info!(
+            "Allocator: internal-first up to {SMALL_OBJECT_THRESHOLD} bytes, PSRAM-first above"
+        );
+    } else if PSRAM_STATE.load(Ordering::Relaxed) == PSRAM_DISABLED {
+        log::warn!(
+            "Allocator: PSRAM failed verification, serving all allocations from internal RAM"
+        );
+    } else {
+        log::warn!("Allocator: no PSRAM detected, serving all allocations from internal RAM");
+    }
+}
+
 impl PsramFirstAllocator {
     #[inline(always)]
     fn non_null_for_zero(layout: &Layout) -> *mut u8 {
@@ -49,18 +189,50 @@ unsafe impl GlobalAlloc for PsramFirstAllocator {
             return Self::non_null_for_zero(&layout);
         }
 
-        let ptr = Self::alloc_with_caps(&layout, PSRAM_CAPS);
+        let bucket = Bucket::for_size(layout.size());
+        let counters = &COUNTERS[bucket as usize];
+        let (preferred, fallback) = match (placement::current(), bucket, psram_available()) {
+            (_, _, false) => (INTERNAL_CAPS, None),
+            (Some(Placement::Internal), _, true) | (None, Bucket::Small, true) => {
+                (INTERNAL_CAPS, Some(PSRAM_CAPS))
+            }
+            (Some(Placement::Psram), _, true) | (None, Bucket::Large, true) => {
+                (PSRAM_CAPS, Some(INTERNAL_CAPS))
+            }
+        };
+
+        let ptr = Self::alloc_with_caps(&layout, preferred);
         if !ptr.is_null() {
+            counters.preferred.fetch_add(1, Ordering::Relaxed);
+            if trace::armed() {
+                trace::record_alloc(ptr, layout.size(), preferred);
+            }
             return ptr;
         }
 
-        Self::alloc_with_caps(&layout, INTERNAL_CAPS)
+        if let Some(caps) = fallback {
+            let ptr = Self::alloc_with_caps(&layout, caps);
+            if !ptr.is_null() {
+                counters.fallback.fetch_add(1, Ordering::Relaxed);
+                if trace::armed() {
+                    trace::record_alloc(ptr, layout.size(), caps);
+                }
+                return ptr;
+            }
+        }
+
+        counters.failed.fetch_add(1, Ordering::Relaxed);
+        ptr::null_mut()
     }
 
+    /// Region-agnostic: `heap_caps_free` finds the owning heap itself.
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         if ptr.is_null() || layout.size() == 0 {
             return;
         }
+        if trace::armed() {
+            trace::record_free(ptr, layout.size());
+        }
         heap_caps_free(ptr.cast());
     }
 }
@@ -0,0 +1,187 @@
+//! Per-task placement overrides for the global allocator. Inside
+//! [`with_placement`] every allocation made by the calling task prefers the
+//! given region regardless of size; other tasks keep the size policy. The
+//! other region is still the fallback, so a full preferred heap degrades
+//! instead of failing.
+//!
+//! The allocator cannot use `thread_local!` (its first touch may allocate),
+//! so scopes live in a few slots keyed by FreeRTOS task handle. With no
+//! scope open anywhere, `alloc` pays one atomic load.
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, AtomicU8, AtomicUsize, Ordering},
+};
+
+use esp_idf_svc::sys::xTaskGetCurrentTaskHandle;
+
+/// Tasks that can hold a scope at the same time; a scope opened while all
+/// are taken does nothing and is counted in [`overflowed`].
+const SLOTS: usize = 4;
+const NONE: u8 = 0;
+
+static OPEN: AtomicU32 = AtomicU32::new(0);
+static OVERFLOWED: AtomicU32 = AtomicU32::new(0);
+static TASKS: [AtomicUsize; SLOTS] = [const { AtomicUsize::new(0) }; SLOTS];
+static PLACEMENTS: [AtomicU8; SLOTS] = [const { AtomicU8::new(NONE) }; SLOTS];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Placement {
+    /// Latency-critical buffers touched every frame or every event.
+    Internal = 1,
+    /// Large caches that should never take internal RAM.
+    Psram = 2,
+}
+
+/// Runs `f` with this task's allocations preferring `placement`. Nests; the
+/// previous placement is back when `f` returns or unwinds. Allocations made
+/// by other tasks, and reallocations after `f` returns, are not affected.
+pub fn with_placement<R>(placement: Placement, f: impl FnOnce() -> R) -> R {
+    let _scope = Scope::open(placement);
+    f()
+}
+
+/// The calling task's placement, if it has a scope open.
+#[inline(always)]
+pub(super) fn current() -> Option<Placement> {
+    if OPEN.load(Ordering::Acquire) == 0 {
+        return None;
+    }
+    let slot = slot_of(task())?;
+    match PLACEMENTS[slot].load(Ordering::Relaxed) {
+        1 => Some(Placement::Internal),
+        2 => Some(Placement::Psram),
+        _ => None,
+    }
+}
+
+/// Scopes that found every slot taken and were ignored, since boot.
+pub fn overflowed() -> u32 {
+    OVERFLOWED.load(Ordering::Relaxed)
+}
+
+struct Scope {
+    /// The slot and the placement to put back; `None` when ignored.
+    restore: Option<(usize, u8)>,
+    /// Must be dropped on the task that opened it.
+    _task: PhantomData<*const ()>,
+}
+
+impl Scope {
+    fn open(placement: Placement) -> Self {
+        let task = task();
+        let slot = slot_of(task).or_else(|| claim(task));
+        let restore = match slot {
+            Some(slot) => {
+                let previous = PLACEMENTS[slot].swap(placement as u8, Ordering::Relaxed);
+                if previous == NONE {
+                    OPEN.fetch_add(1, Ordering::Release);
+                }
+                Some((slot, previous))
+            }
+            None => {
+                OVERFLOWED.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        };
+        Self {
+            restore,
+            _task: PhantomData,
+        }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let Some((slot, previous)) = self.restore else {
+            return;
+        };
+        PLACEMENTS[slot].store(previous, Ordering::Relaxed);
+        if previous == NONE {
+            TASKS[slot].store(0, Ordering::Release);
+            OPEN.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+#[inline(always)]
+fn task() -> usize {
+    unsafe { xTaskGetCurrentTaskHandle() as usize }
+}
+
+#[inline(always)]
+fn slot_of(task: usize) -> Option<usize> {
+    TASKS
+        .iter()
+        .position(|slot| slot.load(Ordering::Acquire) == task)
+}
+
+fn claim(task: usize) -> Option<usize> {
+    TASKS.iter().position(|slot| {
+        slot.compare_exchange(0, task, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    })
+}
+
+/// A `Box` allocated in internal RAM.
+pub struct InternalBox<T>(Box<T>);
+
+impl<T> InternalBox<T> {
+    pub fn new(value: T) -> Self {
+        Self(with_placement(Placement::Internal, || Box::new(value)))
+    }
+}
+
+impl<T> Deref for InternalBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for InternalBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// A `Vec` whose buffer was allocated in PSRAM. Growing it past that buffer
+/// reallocates under the size policy again, so size it up front.
+#[derive(Default)]
+pub struct PsramVec<T>(Vec<T>);
+
+impl<T> PsramVec<T> {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(with_placement(Placement::Psram, || {
+            Vec::with_capacity(capacity)
+        }))
+    }
+}
+
+impl<T: Clone> PsramVec<T> {
+    /// `len` copies of `value`.
+    pub fn filled(value: T, len: usize) -> Self {
+        Self(with_placement(Placement::Psram, || vec![value; len]))
+    }
+}
+
+impl<T> Deref for PsramVec<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for PsramVec<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::Write as _;
+
+use anyhow::{bail, Result};
+use esp_idf_svc::sys::esp_random;
+
+use super::{bucket_stats, Bucket, BucketStats};
+use crate::statlogger::{self, HeapSnapshot};
+
+const DEFAULT_ROUNDS: usize = 2_000;
+const MAX_ROUNDS: usize = 100_000;
+const LIVE_SLOTS: usize = 64;
+/// Keeps the live set well under the internal heap even when PSRAM is absent.
+const MAX_SIZE: usize = 4_096;
+
+pub fn register_commands() {
+    crate::console::register("alloc", "allocator bucket hit rates", |_| {
+        let mut out = format_buckets(&Bucket::ALL.map(bucket_stats));
+        let overflowed = super::placement::overflowed();
+        if overflowed > 0 {
+            let _ = write!(
+                out,
+                "\nplacement scopes ignored (no free slot): {overflowed}"
+            );
+        }
+        Ok(out)
+    });
+    crate::console::register(
+        "alloc-stress",
+        "[rounds] churn mixed-size allocations, report fragmentation",
+        |args| {
+            let rounds = match args.first() {
+                Some(arg) => arg.parse()?,
+                None => DEFAULT_ROUNDS,
+            };
+            if rounds == 0 || rounds > MAX_ROUNDS {
+                bail!("rounds must be 1..={MAX_ROUNDS}");
+            }
+            Ok(run(rounds))
+        },
+    );
+}
+
+/// Replaces random slots of a fixed live set with mixed small/large blocks,
+/// then frees everything and compares heap shape with the starting point.
+pub fn run(rounds: usize) -> String {
+    let before = statlogger::heap_snapshot();
+    let stats_before = Bucket::ALL.map(bucket_stats);
+
+    let mut rng = XorShift(unsafe { esp_random() } | 1);
+    let mut live: Vec<Option<Vec<u8>>> = (0..LIVE_SLOTS).map(|_| None).collect();
+    for _ in 0..rounds {
+        let slot = rng.next() as usize % LIVE_SLOTS;
+        let size = if rng.next() % 4 == 0 {
+            1 + rng.next() as usize % MAX_SIZE
+        } else {
+            1 + rng.next() as usize % 96
+        };
+        let mut block = Vec::with_capacity(size);
+        block.resize(size, slot as u8);
+        live[slot] = Some(block);
+    }
+    let during = statlogger::heap_snapshot();
+    drop(live);
+
+    let after = statlogger::heap_snapshot();
+    let stats_after = Bucket::ALL.map(bucket_stats);
+    let delta = [
+        stats_after[0].since(&stats_before[0]),
+        stats_after[1].since(&stats_before[1]),
+    ];
+
+    let mut out = format!("{rounds} rounds, {LIVE_SLOTS} live slots\n");
+    write_heap(&mut out, "before", &before);
+    write_heap(&mut out, "peak", &during);
+    write_heap(&mut out, "after", &after);
+    out.push_str(&format_buckets(&delta));
+    out
+}
+
+fn write_heap(out: &mut String, label: &str, heap: &HeapSnapshot) {
+    let _ = writeln!(
+        out,
+        "{label:<6} internal free={} largest={}  psram free={} largest={}",
+        heap.internal, heap.internal_largest, heap.psram, heap.psram_largest
+    );
+}
+
+fn format_buckets(stats: &[BucketStats; 2]) -> String {
+    Bucket::ALL
+        .iter()
+        .zip(stats)
+        .map(|(bucket, stats)| {
+            let rate = stats
+                .hit_rate()
+                .map(|rate| format!("{rate:.1}%"))
+                .unwrap_or_else(|| "--".to_string());
+            format!(
+                "{:<5} preferred={} fallback={} failed={} hit={}",
+                bucket.label(),
+                stats.preferred,
+                stats.fallback,
+                stats.failed,
+                rate
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+struct XorShift(u32);
+
+impl XorShift {
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+}
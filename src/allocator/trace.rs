@@ -0,0 +1,332 @@
+//! Opt-in allocation tracing, attributing heap use to call sites.
+//!
+//! Call sites are printed as raw addresses. Symbolize them on the host with
+//! the ELF that was flashed:
+//!
+//! ```text
+//! xtensa-esp32s3-elf-addr2line -pfiaC \
+//!     -e target/xtensa-esp32s3-espidf/release/app_esp32s3 0x42012345 0x42016789
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::sys::{
+    esp_backtrace_frame_t, esp_backtrace_get_next_frame, esp_backtrace_get_start, heap_caps_free,
+    heap_caps_malloc,
+};
+use log::{info, warn};
+
+use super::{psram_available, INTERNAL_CAPS, PSRAM_CAPS};
+
+const PSRAM_CAPACITY: usize = 8_192;
+const INTERNAL_CAPACITY: usize = 1_024;
+/// Frames belonging to the allocator itself and `__rust_alloc`.
+const SKIP_FRAMES: usize = 2;
+const SITE_DEPTH: usize = 2;
+const DEFAULT_TOP: usize = 10;
+const MAX_CAPTURE_SECS: u64 = 300;
+
+static ARMED: AtomicBool = AtomicBool::new(false);
+static TRUNCATED: AtomicBool = AtomicBool::new(false);
+static BUFFER: AtomicPtr<Record> = AtomicPtr::new(core::ptr::null_mut());
+static CAPACITY: AtomicUsize = AtomicUsize::new(0);
+static CURSOR: AtomicUsize = AtomicUsize::new(0);
+static CAPTURE_ID: AtomicU32 = AtomicU32::new(0);
+static LAST_REPORT: Mutex<Option<TraceReport>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Region {
+    Internal,
+    Psram,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Kind {
+    Alloc,
+    Free,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Record {
+    ptr: usize,
+    size: u32,
+    kind: Kind,
+    region: Region,
+    site: [u32; SITE_DEPTH],
+}
+
+#[derive(Clone, Debug)]
+pub struct SiteStats {
+    pub site: [u32; SITE_DEPTH],
+    pub allocs: u32,
+    pub total_bytes: u64,
+    /// Allocated during the window and not freed before it closed.
+    pub live_bytes: u64,
+    pub psram_allocs: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceReport {
+    pub records: usize,
+    pub truncated: bool,
+    pub sites: Vec<SiteStats>,
+}
+
+#[inline(always)]
+pub fn armed() -> bool {
+    ARMED.load(Ordering::Relaxed)
+}
+
+#[inline(never)]
+pub fn record_alloc(ptr: *mut u8, size: usize, caps: u32) {
+    let region = if caps == PSRAM_CAPS {
+        Region::Psram
+    } else {
+        Region::Internal
+    };
+    push(Kind::Alloc, ptr, size, region, caller_site());
+}
+
+#[inline(never)]
+pub fn record_free(ptr: *mut u8, size: usize) {
+    push(Kind::Free, ptr, size, Region::Internal, [0; SITE_DEPTH]);
+}
+
+fn push(kind: Kind, ptr: *mut u8, size: usize, region: Region, site: [u32; SITE_DEPTH]) {
+    let buffer = BUFFER.load(Ordering::Acquire);
+    if buffer.is_null() {
+        return;
+    }
+    let index = CURSOR.fetch_add(1, Ordering::Relaxed);
+    if index >= CAPACITY.load(Ordering::Relaxed) {
+        // Stop rather than silently dropping samples; the report says so.
+        TRUNCATED.store(true, Ordering::Relaxed);
+        ARMED.store(false, Ordering::Relaxed);
+        return;
+    }
+    unsafe {
+        buffer.add(index).write(Record {
+            ptr: ptr as usize,
+            size: size.min(u32::MAX as usize) as u32,
+            kind,
+            region,
+            site,
+        });
+    }
+}
+
+fn caller_site() -> [u32; SITE_DEPTH] {
+    let mut site = [0; SITE_DEPTH];
+    unsafe {
+        let mut frame: esp_backtrace_frame_t = core::mem::zeroed();
+        esp_backtrace_get_start(&mut frame.pc, &mut frame.sp, &mut frame.next_pc);
+        let mut depth = 0;
+        while depth < SKIP_FRAMES + SITE_DEPTH {
+            if depth >= SKIP_FRAMES {
+                site[depth - SKIP_FRAMES] = call_addr(frame.pc);
+            }
+            if frame.next_pc == 0 || !esp_backtrace_get_next_frame(&mut frame) {
+                break;
+            }
+            depth += 1;
+        }
+    }
+    site
+}
+
+/// Windowed-ABI return address to the address of the call instruction, as
+/// `esp_cpu_process_stack_pc` followed by `esp_cpu_get_call_addr` would do.
+fn call_addr(pc: u32) -> u32 {
+    if pc == 0 {
+        return 0;
+    }
+    ((pc & 0x3FFF_FFFF) | 0x4000_0000).wrapping_sub(3)
+}
+
+/// Arms a capture and stops it after `window`.
+pub fn start(window: Duration) -> Result<usize> {
+    if armed() || !BUFFER.load(Ordering::Acquire).is_null() {
+        bail!("a capture is already running");
+    }
+    let (capacity, caps) = if psram_available() {
+        (PSRAM_CAPACITY, PSRAM_CAPS)
+    } else {
+        (INTERNAL_CAPACITY, INTERNAL_CAPS)
+    };
+    let buffer =
+        unsafe { heap_caps_malloc(capacity * core::mem::size_of::<Record>(), caps) } as *mut Record;
+    if buffer.is_null() {
+        bail!("no room for a {capacity}-record trace buffer");
+    }
+
+    TRUNCATED.store(false, Ordering::Relaxed);
+    CURSOR.store(0, Ordering::Relaxed);
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    BUFFER.store(buffer, Ordering::Release);
+    let capture = CAPTURE_ID.fetch_add(1, Ordering::Relaxed) + 1;
+    ARMED.store(true, Ordering::Release);
+    info!("Alloc trace armed for {window:?} ({capacity} records)");
+
+    thread::Builder::new()
+        .name("alloctrace".into())
+        .stack_size(4 * 1024)
+        .spawn(move || {
+            thread::sleep(window);
+            // A manual stop followed by a new start must not be cut short.
+            if CAPTURE_ID.load(Ordering::Relaxed) != capture {
+                return;
+            }
+            if let Err(err) = stop(DEFAULT_TOP) {
+                warn!("Alloc trace stop failed: {err:?}");
+            }
+        })
+        .map_err(|err| {
+            ARMED.store(false, Ordering::Release);
+            // As in stop(): let allocations that passed the armed check finish.
+            thread::sleep(Duration::from_millis(10));
+            let buffer = BUFFER.swap(core::ptr::null_mut(), Ordering::AcqRel);
+            if !buffer.is_null() {
+                unsafe { heap_caps_free(buffer.cast()) };
+            }
+            anyhow!("spawn alloc trace timer: {err}")
+        })?;
+    Ok(capacity)
+}
+
+/// Disarms, aggregates by call site and logs the top offenders.
+pub fn stop(top: usize) -> Result<TraceReport> {
+    ARMED.store(false, Ordering::Release);
+    if BUFFER.load(Ordering::Acquire).is_null() {
+        return last_report().ok_or_else(|| anyhow!("no capture has run"));
+    }
+    // Let allocations that passed the armed check finish writing.
+    thread::sleep(Duration::from_millis(10));
+    let buffer = BUFFER.swap(core::ptr::null_mut(), Ordering::AcqRel);
+    if buffer.is_null() {
+        return last_report().ok_or_else(|| anyhow!("no capture has run"));
+    }
+
+    let records = CURSOR
+        .load(Ordering::Relaxed)
+        .min(CAPACITY.load(Ordering::Relaxed));
+    let samples = unsafe { core::slice::from_raw_parts(buffer, records) }.to_vec();
+    unsafe { heap_caps_free(buffer.cast()) };
+
+    let report = aggregate(&samples, TRUNCATED.load(Ordering::Relaxed), top);
+    info!("{}", format_report(&report));
+    if let Ok(mut slot) = LAST_REPORT.lock() {
+        *slot = Some(report.clone());
+    }
+    Ok(report)
+}
+
+pub fn last_report() -> Option<TraceReport> {
+    LAST_REPORT.lock().ok().and_then(|report| report.clone())
+}
+
+fn aggregate(samples: &[Record], truncated: bool, top: usize) -> TraceReport {
+    let mut sites: HashMap<[u32; SITE_DEPTH], SiteStats> = HashMap::new();
+    let mut live: HashMap<usize, ([u32; SITE_DEPTH], u32)> = HashMap::new();
+
+    for record in samples {
+        match record.kind {
+            Kind::Alloc => {
+                let stats = sites.entry(record.site).or_insert_with(|| SiteStats {
+                    site: record.site,
+                    allocs: 0,
+                    total_bytes: 0,
+                    live_bytes: 0,
+                    psram_allocs: 0,
+                });
+                stats.allocs += 1;
+                stats.total_bytes += record.size as u64;
+                if record.region == Region::Psram {
+                    stats.psram_allocs += 1;
+                }
+                live.insert(record.ptr, (record.site, record.size));
+            }
+            Kind::Free => {
+                live.remove(&record.ptr);
+            }
+        }
+    }
+    for (site, size) in live.into_values() {
+        if let Some(stats) = sites.get_mut(&site) {
+            stats.live_bytes += size as u64;
+        }
+    }
+
+    let mut sites: Vec<SiteStats> = sites.into_values().collect();
+    sites.sort_by(|a, b| {
+        b.live_bytes
+            .cmp(&a.live_bytes)
+            .then(b.total_bytes.cmp(&a.total_bytes))
+    });
+    sites.truncate(top);
+    TraceReport {
+        records: samples.len(),
+        truncated,
+        sites,
+    }
+}
+
+pub fn format_report(report: &TraceReport) -> String {
+    let mut out = format!(
+        "Alloc trace: {} records{}",
+        report.records,
+        if report.truncated {
+            " (TRUNCATED, buffer full)"
+        } else {
+            ""
+        }
+    );
+    for stats in &report.sites {
+        let _ = write!(
+            out,
+            "\n  {:#010x} {:#010x} live={} total={} allocs={} psram={}",
+            stats.site[0],
+            stats.site[1],
+            stats.live_bytes,
+            stats.total_bytes,
+            stats.allocs,
+            stats.psram_allocs
+        );
+    }
+    out
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "alloctrace",
+        "start <secs> | stop [top] | report: attribute allocations to call sites",
+        |args| match args {
+            ["start", secs] => {
+                let secs: u64 = secs.parse()?;
+                if secs == 0 || secs > MAX_CAPTURE_SECS {
+                    bail!("window must be 1..={MAX_CAPTURE_SECS} s");
+                }
+                let capacity = start(Duration::from_secs(secs))?;
+                Ok(format!("tracing for {secs} s, {capacity} records max"))
+            }
+            ["stop"] => Ok(format_report(&stop(DEFAULT_TOP)?)),
+            ["stop", top] => Ok(format_report(&stop(top.parse()?)?)),
+            ["report"] => last_report()
+                .map(|report| format_report(&report))
+                .ok_or_else(|| anyhow!("no capture has run")),
+            _ => bail!("usage: alloctrace start <secs> | stop [top] | report"),
+        },
+    );
+}
@@ -0,0 +1,6 @@
+#[cfg(feature = "ancs")]
+pub mod hid;
+pub mod link;
+pub mod resources;
+#[cfg(feature = "ancs")]
+pub mod standard_services;
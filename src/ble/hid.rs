@@ -0,0 +1,317 @@
+//! Optional HID-over-GATT remote on the fake ANCS peripheral: volume up and
+//! down, play/pause and page up/down, for use as a presentation clicker or a
+//! camera shutter. Pairing, bonds and security are the peripheral's, shared
+//! with ANCS.
+//!
+//! GATT services can only be added before the server starts, so
+//! [`HID_ENABLED`] takes effect at the next boot. The primary advertisement
+//! is full with the ANCS payload; the HID UUID, appearance and
+//! [`DEVICE_NAME`] go in the scan response, and only while the service is
+//! registered.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Result};
+use esp32_nimble::{
+    utilities::{mutex::Mutex as NimbleMutex, BleUuid},
+    BLECharacteristic, BLEDevice, DescriptorProperties, NimbleProperties, OnWriteArgs,
+};
+use log::{debug, info};
+
+use crate::settings::{self, SettingKey};
+
+pub const HID_ENABLED: SettingKey<bool> = SettingKey::new("hid_enabled", "false");
+/// The GAP name while the remote is on, and the scan response name.
+pub const DEVICE_NAME: &str = "AstroBox Remote";
+pub const HID_SERVICE: u16 = 0x1812;
+/// Generic HID.
+pub const APPEARANCE: u16 = 0x03C0;
+
+const HID_INFORMATION: u16 = 0x2A4A;
+const REPORT_MAP: u16 = 0x2A4B;
+const HID_CONTROL_POINT: u16 = 0x2A4C;
+const REPORT: u16 = 0x2A4D;
+const PROTOCOL_MODE: u16 = 0x2A4E;
+const REPORT_REFERENCE: u16 = 0x2908;
+
+/// bcdHID 1.11, no country code, normally connectable.
+const HID_INFO: [u8; 4] = [0x11, 0x01, 0x00, 0x02];
+const REPORT_PROTOCOL: u8 = 0x01;
+const INPUT_REPORT: u8 = 0x01;
+const CONSUMER_REPORT_ID: u8 = 1;
+const KEYBOARD_REPORT_ID: u8 = 2;
+
+#[rustfmt::skip]
+const REPORT_MAP_DATA: &[u8] = &[
+    // Consumer control: volume up, volume down, play/pause as bits.
+    0x05, 0x0C,       // Usage Page (Consumer)
+    0x09, 0x01,       // Usage (Consumer Control)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, CONSUMER_REPORT_ID,
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x03,       //   Report Count (3)
+    0x09, 0xE9,       //   Usage (Volume Increment)
+    0x09, 0xEA,       //   Usage (Volume Decrement)
+    0x09, 0xCD,       //   Usage (Play/Pause)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0x95, 0x05,       //   Report Count (5)
+    0x81, 0x03,       //   Input (Constant) padding
+    0xC0,             // End Collection
+    // Keyboard: one key code at a time, enough for Page Up / Page Down.
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xA1, 0x01,       // Collection (Application)
+    0x85, KEYBOARD_REPORT_ID,
+    0x05, 0x07,       //   Usage Page (Keyboard)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x65,       //   Logical Maximum (101)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x29, 0x65,       //   Usage Maximum (101)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x00,       //   Input (Data, Array, Absolute)
+    0xC0,             // End Collection
+];
+
+/// A legacy scan response holds 31 bytes of AD structures: here the
+/// complete name, one 16-bit service UUID and the appearance.
+pub const SCAN_RESPONSE_LEN: usize = (2 + DEVICE_NAME.len()) + (2 + 2) + (2 + 2);
+const _: () = assert!(SCAN_RESPONSE_LEN <= 31, "HID scan response over 31 bytes");
+
+static REPORTS: Mutex<Option<Reports>> = Mutex::new(None);
+
+struct Reports {
+    consumer: Arc<NimbleMutex<BLECharacteristic>>,
+    keyboard: Arc<NimbleMutex<BLECharacteristic>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoteKey {
+    VolumeUp,
+    VolumeDown,
+    PlayPause,
+    PageUp,
+    PageDown,
+}
+
+impl RemoteKey {
+    const ALL: [RemoteKey; 5] = [
+        RemoteKey::VolumeUp,
+        RemoteKey::VolumeDown,
+        RemoteKey::PlayPause,
+        RemoteKey::PageUp,
+        RemoteKey::PageDown,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            RemoteKey::VolumeUp => "vol_up",
+            RemoteKey::VolumeDown => "vol_down",
+            RemoteKey::PlayPause => "play_pause",
+            RemoteKey::PageUp => "page_up",
+            RemoteKey::PageDown => "page_down",
+        }
+    }
+
+    /// Report ID and the pressed report; all zero is the release.
+    fn report(self) -> (u8, u8) {
+        match self {
+            RemoteKey::VolumeUp => (CONSUMER_REPORT_ID, 0x01),
+            RemoteKey::VolumeDown => (CONSUMER_REPORT_ID, 0x02),
+            RemoteKey::PlayPause => (CONSUMER_REPORT_ID, 0x04),
+            RemoteKey::PageUp => (KEYBOARD_REPORT_ID, 0x4B),
+            RemoteKey::PageDown => (KEYBOARD_REPORT_ID, 0x4E),
+        }
+    }
+}
+
+impl fmt::Display for RemoteKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for RemoteKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RemoteKey::ALL
+            .into_iter()
+            .find(|key| key.code() == s)
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    On,
+    Off,
+    /// The setting and this boot's GATT table disagree.
+    RebootRequired,
+}
+
+impl Mode {
+    pub fn code(self) -> &'static str {
+        match self {
+            Mode::On => "on",
+            Mode::Off => "off",
+            Mode::RebootRequired => "reboot_required",
+        }
+    }
+}
+
+pub fn mode() -> Mode {
+    match (settings::get(&HID_ENABLED), registered()) {
+        (true, true) => Mode::On,
+        (false, false) => Mode::Off,
+        _ => Mode::RebootRequired,
+    }
+}
+
+pub fn registered() -> bool {
+    REPORTS.lock().is_ok_and(|reports| reports.is_some())
+}
+
+/// Whether a host has input reports enabled, i.e. a key press goes somewhere.
+pub fn connected() -> bool {
+    with_reports(|reports| {
+        reports.consumer.lock().subscribed_count() > 0
+            || reports.keyboard.lock().subscribed_count() > 0
+    })
+    .unwrap_or(false)
+}
+
+/// Adds the HID service when [`HID_ENABLED`] is set. Must run before
+/// `server.start()`.
+pub fn init(ble: &mut BLEDevice) -> Result<()> {
+    if !settings::get(&HID_ENABLED) {
+        return Ok(());
+    }
+    BLEDevice::set_device_name(DEVICE_NAME)
+        .map_err(|err| anyhow!("set GAP name for HID: {err:?}"))?;
+    let server = ble.get_server();
+    let service = server.create_service(BleUuid::from_uuid16(HID_SERVICE));
+    let mut service = service.lock();
+
+    service
+        .create_characteristic(
+            BleUuid::from_uuid16(HID_INFORMATION),
+            NimbleProperties::READ | NimbleProperties::READ_ENC,
+        )
+        .lock()
+        .set_value(&HID_INFO);
+    service
+        .create_characteristic(
+            BleUuid::from_uuid16(REPORT_MAP),
+            NimbleProperties::READ | NimbleProperties::READ_ENC,
+        )
+        .lock()
+        .set_value(REPORT_MAP_DATA);
+    service
+        .create_characteristic(
+            BleUuid::from_uuid16(HID_CONTROL_POINT),
+            NimbleProperties::WRITE_NO_RSP | NimbleProperties::WRITE_ENC,
+        )
+        .lock()
+        .on_write(|args: &mut OnWriteArgs| debug!("HID control point: {:02X?}", args.recv_data()));
+    service
+        .create_characteristic(
+            BleUuid::from_uuid16(PROTOCOL_MODE),
+            NimbleProperties::READ
+                | NimbleProperties::READ_ENC
+                | NimbleProperties::WRITE_NO_RSP
+                | NimbleProperties::WRITE_ENC,
+        )
+        .lock()
+        .set_value(&[REPORT_PROTOCOL]);
+
+    let mut input_report = |id: u8| {
+        let report = service.create_characteristic(
+            BleUuid::from_uuid16(REPORT),
+            NimbleProperties::READ | NimbleProperties::READ_ENC | NimbleProperties::NOTIFY,
+        );
+        {
+            let mut chr = report.lock();
+            chr.set_value(&[0]);
+            chr.create_descriptor(
+                BleUuid::from_uuid16(REPORT_REFERENCE),
+                DescriptorProperties::READ | DescriptorProperties::READ_ENC,
+            )
+            .lock()
+            .set_value(&[id, INPUT_REPORT]);
+        }
+        report
+    };
+    let reports = Reports {
+        consumer: input_report(CONSUMER_REPORT_ID),
+        keyboard: input_report(KEYBOARD_REPORT_ID),
+    };
+    if let Ok(mut slot) = REPORTS.lock() {
+        *slot = Some(reports);
+    }
+    info!("HID remote registered as \"{DEVICE_NAME}\"");
+    Ok(())
+}
+
+/// Sends a press and its release. Call from the UI thread's handlers;
+/// returns false when the remote is off or no host is listening.
+pub fn tap(key: RemoteKey) -> bool {
+    let (id, pressed) = key.report();
+    with_reports(|reports| {
+        let report = if id == CONSUMER_REPORT_ID {
+            &reports.consumer
+        } else {
+            &reports.keyboard
+        };
+        let mut chr = report.lock();
+        if chr.subscribed_count() == 0 {
+            return false;
+        }
+        for value in [pressed, 0] {
+            chr.set_value(&[value]);
+            chr.notify();
+        }
+        debug!("HID {key}");
+        true
+    })
+    .unwrap_or(false)
+}
+
+fn with_reports<R>(f: impl FnOnce(&Reports) -> R) -> Option<R> {
+    let reports = REPORTS.lock().ok()?;
+    reports.as_ref().map(f)
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "hid",
+        "BLE HID remote: hid [on | off | tap <vol_up|vol_down|play_pause|page_up|page_down>]; \
+         on/off apply after reboot",
+        |args| {
+            match args {
+                [] => {}
+                ["on"] => settings::set(&HID_ENABLED, &true)?,
+                ["off"] => settings::set(&HID_ENABLED, &false)?,
+                ["tap", key] => {
+                    let key: RemoteKey =
+                        key.parse().map_err(|()| anyhow!("unknown key {key:?}"))?;
+                    if !tap(key) {
+                        bail!("no HID host listening");
+                    }
+                }
+                _ => bail!("usage: hid [on | off | tap <key>]"),
+            }
+            Ok(format!(
+                "hid mode={} host={}",
+                mode().code(),
+                if connected() { "connected" } else { "none" }
+            ))
+        },
+    );
+}
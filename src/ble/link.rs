@@ -0,0 +1,140 @@
+//! Link-layer queries and PHY negotiation for both the MiWear central link and
+//! peripheral-side ANCS links. esp32-nimble does not surface PHY update
+//! events, so callers read the PHY back after giving the procedure time to
+//! finish.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::sys::{
+    ble_gap_conn_desc, ble_gap_conn_find, ble_gap_read_le_phy, ble_gap_set_prefered_default_le_phy,
+    ble_gap_set_prefered_le_phy, BLE_GAP_LE_PHY_1M, BLE_GAP_LE_PHY_1M_MASK, BLE_GAP_LE_PHY_2M,
+    BLE_GAP_LE_PHY_2M_MASK, BLE_GAP_LE_PHY_CODED, BLE_GAP_LE_PHY_CODED_ANY,
+};
+
+/// Connection interval unit defined by the Core spec.
+const INTERVAL_UNIT_MS: f32 = 1.25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phy {
+    OneM,
+    TwoM,
+    Coded,
+}
+
+impl Phy {
+    fn from_raw(raw: u8) -> Option<Self> {
+        match raw as u32 {
+            BLE_GAP_LE_PHY_1M => Some(Phy::OneM),
+            BLE_GAP_LE_PHY_2M => Some(Phy::TwoM),
+            BLE_GAP_LE_PHY_CODED => Some(Phy::Coded),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Phy::OneM => "1M",
+            Phy::TwoM => "2M",
+            Phy::Coded => "Coded",
+        }
+    }
+}
+
+impl fmt::Display for Phy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// What we ask for after connecting. `Force1M` is for watches that drop the
+/// link or stall once moved to 2M.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhyPreference {
+    Auto2M,
+    Force1M,
+}
+
+impl fmt::Display for PhyPreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PhyPreference::Auto2M => "auto2m",
+            PhyPreference::Force1M => "force1m",
+        })
+    }
+}
+
+impl FromStr for PhyPreference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto2m" => Ok(PhyPreference::Auto2M),
+            "force1m" => Ok(PhyPreference::Force1M),
+            other => Err(anyhow!("unknown PHY preference {other:?}")),
+        }
+    }
+}
+
+/// Starts a PHY update on `conn_handle`. The controller falls back to 1M on
+/// its own when the peer lacks 2M.
+pub fn request_phy(conn_handle: u16, preference: PhyPreference) -> Result<()> {
+    let mask = match preference {
+        PhyPreference::Auto2M => BLE_GAP_LE_PHY_1M_MASK | BLE_GAP_LE_PHY_2M_MASK,
+        PhyPreference::Force1M => BLE_GAP_LE_PHY_1M_MASK,
+    } as u8;
+    let rc = unsafe {
+        ble_gap_set_prefered_le_phy(conn_handle, mask, mask, BLE_GAP_LE_PHY_CODED_ANY as u16)
+    };
+    if rc != 0 {
+        bail!("PHY update request failed (conn={conn_handle} rc={rc})");
+    }
+    Ok(())
+}
+
+/// Lets peers move links we did not initiate to 2M.
+pub fn accept_2m_by_default() -> Result<()> {
+    let mask = (BLE_GAP_LE_PHY_1M_MASK | BLE_GAP_LE_PHY_2M_MASK) as u8;
+    let rc = unsafe { ble_gap_set_prefered_default_le_phy(mask, mask) };
+    if rc != 0 {
+        bail!("default PHY preference rejected (rc={rc})");
+    }
+    Ok(())
+}
+
+/// The PHY the controller reports for our transmit direction.
+pub fn read_phy(conn_handle: u16) -> Option<Phy> {
+    let (mut tx, mut rx) = (0u8, 0u8);
+    let rc = unsafe { ble_gap_read_le_phy(conn_handle, &mut tx, &mut rx) };
+    if rc != 0 {
+        return None;
+    }
+    if tx != rx {
+        log::debug!("Asymmetric PHY on conn={conn_handle}: tx={tx} rx={rx}");
+    }
+    Phy::from_raw(tx)
+}
+
+pub fn interval_ms(conn_handle: u16) -> Option<f32> {
+    let mut desc = unsafe { core::mem::zeroed::<ble_gap_conn_desc>() };
+    let rc = unsafe { ble_gap_conn_find(conn_handle, &mut desc) };
+    (rc == 0).then(|| desc.conn_itvl as f32 * INTERVAL_UNIT_MS)
+}
+
+/// Whether the link is encrypted; `None` once it is gone.
+pub fn encrypted(conn_handle: u16) -> Option<bool> {
+    let mut desc = unsafe { core::mem::zeroed::<ble_gap_conn_desc>() };
+    let rc = unsafe { ble_gap_conn_find(conn_handle, &mut desc) };
+    (rc == 0).then(|| desc.sec_state.encrypted() != 0)
+}
+
+/// "2M 30.0ms"-style summary for status lines; "--" parts when unknown.
+pub fn describe(phy: Option<Phy>, interval_ms: Option<f32>) -> String {
+    format!(
+        "{} {}",
+        phy.map(Phy::label).unwrap_or("--"),
+        interval_ms
+            .map(|ms| format!("{ms:.1}ms"))
+            .unwrap_or_else(|| "--".to_string())
+    )
+}
@@ -0,0 +1,127 @@
+//! NimBLE host memory pools, sampled so a pool running dry shows up by name
+//! instead of as a generic BLE error. NimBLE keeps each pool's low-water
+//! mark itself; a minimum of zero means an allocation from it has failed or
+//! was about to, and the fix is that pool's block count in sdkconfig (e.g.
+//! `CONFIG_BT_NIMBLE_MSYS_1_BLOCK_COUNT` for `msys_1`).
+//!
+//! The controller's own buffers are not exposed by the bindings, so only
+//! host-side pools are covered.
+
+use std::{ffi::CStr, ptr, sync::Mutex, time::Duration};
+
+use esp_idf_svc::sys::{os_mempool, os_mempool_info, os_mempool_info_get_next};
+use log::warn;
+
+use crate::{
+    events::{self, SystemEvent},
+    periodic,
+};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+static POOLS: Mutex<Vec<PoolStats>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolStats {
+    pub name: String,
+    pub block_size: u32,
+    pub blocks: u32,
+    pub free: u32,
+    /// Lowest `free` since boot, as NimBLE tracks it.
+    pub min_free: u32,
+}
+
+impl PoolStats {
+    pub fn exhausted(&self) -> bool {
+        self.blocks > 0 && self.min_free == 0
+    }
+}
+
+/// Starts sampling; call once the NimBLE host is up.
+pub fn start() {
+    sample();
+    periodic::register("ble_pools", SAMPLE_INTERVAL, sample);
+}
+
+fn sample() {
+    let pools = read_pools();
+    let Ok(mut previous) = POOLS.lock() else {
+        return;
+    };
+    for pool in pools.iter().filter(|pool| pool.exhausted()) {
+        let known = previous
+            .iter()
+            .any(|seen| seen.name == pool.name && seen.exhausted());
+        if known {
+            continue;
+        }
+        warn!(
+            "BLE pool {} ran dry ({} blocks of {} bytes); raise its count in sdkconfig",
+            pool.name, pool.blocks, pool.block_size
+        );
+        crate::journal!("BLE pool {} exhausted", pool.name);
+        events::publish(SystemEvent::BlePoolExhausted {
+            pool: pool.name.clone(),
+        });
+    }
+    *previous = pools;
+}
+
+fn read_pools() -> Vec<PoolStats> {
+    let mut pools = Vec::new();
+    let mut info: os_mempool_info = unsafe { std::mem::zeroed() };
+    let mut cursor: *mut os_mempool = ptr::null_mut();
+    loop {
+        // Pools are registered at host init and never removed, so walking
+        // the list from this task is safe.
+        cursor = unsafe { os_mempool_info_get_next(cursor, &mut info) };
+        if cursor.is_null() {
+            break;
+        }
+        let name = unsafe { CStr::from_ptr(info.omi_name.as_ptr()) };
+        pools.push(PoolStats {
+            name: name.to_string_lossy().into_owned(),
+            block_size: info.omi_block_size.max(0) as u32,
+            blocks: info.omi_num_blocks.max(0) as u32,
+            free: info.omi_num_free.max(0) as u32,
+            min_free: info.omi_min_free.max(0) as u32,
+        });
+    }
+    pools
+}
+
+/// The pools as of the last sample.
+pub fn snapshot() -> Vec<PoolStats> {
+    POOLS.lock().map(|pools| pools.clone()).unwrap_or_default()
+}
+
+/// The exhausted pool with the fewest blocks free now, if any ran dry.
+/// Cheap while none has: the render loop asks every frame.
+pub fn worst_exhausted() -> Option<PoolStats> {
+    POOLS
+        .lock()
+        .ok()?
+        .iter()
+        .filter(|pool| pool.exhausted())
+        .min_by_key(|pool| pool.free)
+        .cloned()
+}
+
+/// Pools read right now, one `name free/blocks (min n)` each, for log
+/// lines that need the state at the moment of a failure.
+pub fn summary() -> String {
+    let pools = read_pools();
+    if pools.is_empty() {
+        return "no NimBLE pools".to_string();
+    }
+    pools
+        .iter()
+        .map(|pool| {
+            format!(
+                "{} {}/{} (min {})",
+                pool.name, pool.free, pool.blocks, pool.min_free
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
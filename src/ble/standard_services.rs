@@ -0,0 +1,99 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::{Duration, Instant},
+};
+
+use esp32_nimble::{utilities::BleUuid, BLEDevice, NimbleProperties};
+use log::{debug, info};
+
+use crate::{periodic, power::battery, version::DeviceInfo};
+
+pub const BATTERY_SERVICE: u16 = 0x180F;
+pub const DEVICE_INFORMATION_SERVICE: u16 = 0x180A;
+const BATTERY_LEVEL: u16 = 0x2A19;
+const MANUFACTURER_NAME: u16 = 0x2A29;
+const MODEL_NUMBER: u16 = 0x2A24;
+const FIRMWARE_REVISION: u16 = 0x2A26;
+const PNP_ID: u16 = 0x2A50;
+/// USB-IF vendor source, Espressif's vendor ID, product 0x0001, version 1.0.
+/// HID hosts look for it next to the HID service.
+const PNP_ID_VALUE: [u8; 7] = [0x02, 0x3A, 0x30, 0x01, 0x00, 0x00, 0x01];
+
+/// Used when the board has no battery sensing.
+const FALLBACK_LEVEL: u8 = 100;
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+const MIN_NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+static PUBLISHED_LEVEL: AtomicU8 = AtomicU8::new(FALLBACK_LEVEL);
+
+/// Adds Battery and Device Information services to the peripheral's GATT
+/// server. Must run before `server.start()`; nothing is added to the
+/// advertisement.
+pub fn init(ble: &mut BLEDevice, info: &DeviceInfo) {
+    let server = ble.get_server();
+
+    let dis = server.create_service(BleUuid::from_uuid16(DEVICE_INFORMATION_SERVICE));
+    for (uuid, value) in [
+        (MANUFACTURER_NAME, info.manufacturer),
+        (MODEL_NUMBER, info.model),
+        (FIRMWARE_REVISION, info.firmware),
+    ] {
+        dis.lock()
+            .create_characteristic(BleUuid::from_uuid16(uuid), NimbleProperties::READ)
+            .lock()
+            .set_value(value.as_bytes());
+    }
+    dis.lock()
+        .create_characteristic(BleUuid::from_uuid16(PNP_ID), NimbleProperties::READ)
+        .lock()
+        .set_value(&PNP_ID_VALUE);
+
+    let level = current_level();
+    PUBLISHED_LEVEL.store(level, Ordering::Relaxed);
+    let battery_service = server.create_service(BleUuid::from_uuid16(BATTERY_SERVICE));
+    let battery_level = battery_service.lock().create_characteristic(
+        BleUuid::from_uuid16(BATTERY_LEVEL),
+        NimbleProperties::READ | NimbleProperties::NOTIFY,
+    );
+    battery_level.lock().set_value(&[level]);
+
+    let mut last_notify: Option<Instant> = None;
+    periodic::register("battery_level", SAMPLE_INTERVAL, move || {
+        let level = current_level();
+        if level == PUBLISHED_LEVEL.load(Ordering::Relaxed) {
+            return;
+        }
+        if last_notify.is_some_and(|at| at.elapsed() < MIN_NOTIFY_INTERVAL) {
+            return;
+        }
+
+        let mut chr = battery_level.lock();
+        chr.set_value(&[level]);
+        if chr.subscribed_count() > 0 {
+            chr.notify();
+            debug!("Battery level notified: {level}%");
+        }
+        PUBLISHED_LEVEL.store(level, Ordering::Relaxed);
+        last_notify = Some(Instant::now());
+    });
+
+    REGISTERED.store(true, Ordering::Relaxed);
+    info!(
+        "Standard GATT services registered: battery={}% firmware={}",
+        level, info.firmware
+    );
+}
+
+pub fn registered() -> bool {
+    REGISTERED.load(Ordering::Relaxed)
+}
+
+/// Level currently exposed through the Battery Level characteristic.
+pub fn published_level() -> u8 {
+    PUBLISHED_LEVEL.load(Ordering::Relaxed)
+}
+
+fn current_level() -> u8 {
+    battery::level().unwrap_or(FALLBACK_LEVEL)
+}
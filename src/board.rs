@@ -0,0 +1,190 @@
+//! Facts about the board the firmware is built for, generated by `build.rs`
+//! from `boards/<stem>.toml`. The reference hardware is n16r8; another board
+//! is picked with `ASTROBOX_BOARD=<stem>` or a `board-<stem>` feature.
+
+use std::{fmt, str::FromStr};
+
+use esp_idf_svc::hal::gpio::{AnyIOPin, AnyOutputPin};
+use log::{info, warn};
+
+mod pixel_fixup;
+
+pub use pixel_fixup::PixelFixup;
+
+/// Display controllers `gui::display` can drive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanelKind {
+    Gc9a01,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayGpios {
+    pub backlight: i32,
+    pub rst: i32,
+    pub dc: i32,
+    pub cs: i32,
+    pub mosi: i32,
+    pub sclk: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TouchGpios {
+    pub sda: i32,
+    pub scl: i32,
+    pub int: i32,
+    pub rst: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EncoderGpios {
+    pub a: i32,
+    pub b: i32,
+    pub button: Option<i32>,
+}
+
+/// Raw ADC readings at the ends and the rest position of one stick axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AxisCalibration {
+    pub min: u16,
+    pub center: u16,
+    pub max: u16,
+}
+
+impl fmt::Display for AxisCalibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.min, self.center, self.max)
+    }
+}
+
+/// `min/center/max`, strictly increasing.
+impl FromStr for AxisCalibration {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/').map(|part| part.trim().parse::<u16>());
+        let (Some(Ok(min)), Some(Ok(center)), Some(Ok(max)), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(());
+        };
+        if min < center && center < max {
+            Ok(Self { min, center, max })
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// A two-axis stick on ADC1, see `input::analog`.
+#[derive(Clone, Copy, Debug)]
+pub struct AnalogGpios {
+    pub x: i32,
+    pub y: i32,
+    /// Active-low click to GND.
+    pub click: Option<i32>,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    pub deadzone_pct: u8,
+    /// Used until the stick is calibrated on the device.
+    pub x_cal: AxisCalibration,
+    pub y_cal: AxisCalibration,
+}
+
+pub struct BoardConfig {
+    /// Manifest file stem.
+    pub id: &'static str,
+    pub name: &'static str,
+    pub panel: PanelKind,
+    /// Physical PSRAM fitted; 0 for modules without any.
+    pub psram_bytes: usize,
+    /// Cargo features the manifest expects; informational only.
+    pub features: &'static [&'static str],
+    pub display: DisplayGpios,
+    /// Panel SPI clock the board is known to work at; see
+    /// `gui::display::SPI_CLOCK_KHZ` for the runtime override.
+    pub display_spi_hz: u32,
+    pub pixel_fixup: PixelFixup,
+    /// None on boards that navigate with the analog stick alone.
+    pub touch: Option<TouchGpios>,
+    pub encoder: Option<EncoderGpios>,
+    pub analog: Option<AnalogGpios>,
+    /// Active-low push button, see `input::button`.
+    pub side_key: Option<i32>,
+    pub battery_adc: Option<i32>,
+    pub piezo: Option<i32>,
+    /// Accelerometer on the touch bus, see `sensors::imu`.
+    pub imu_addr: Option<u8>,
+}
+
+include!(concat!(env!("OUT_DIR"), "/board_config.rs"));
+
+pub fn log_summary() {
+    let board = &CURRENT;
+    let gpio = |pin: Option<i32>| pin.map_or_else(|| "-".to_string(), |pin| format!("GPIO{pin}"));
+    info!(
+        "Board {} ({}), panel {:?} (pixel fixup {}), PSRAM {} MB",
+        board.name,
+        board.id,
+        board.panel,
+        board.pixel_fixup,
+        board.psram_bytes / (1024 * 1024)
+    );
+    let encoder = board.encoder.map_or_else(
+        || "-".to_string(),
+        |encoder| {
+            format!(
+                "GPIO{}/GPIO{} button {}",
+                encoder.a,
+                encoder.b,
+                gpio(encoder.button)
+            )
+        },
+    );
+    let analog = board.analog.map_or_else(
+        || "-".to_string(),
+        |analog| {
+            format!(
+                "GPIO{}/GPIO{} click {}",
+                analog.x,
+                analog.y,
+                gpio(analog.click)
+            )
+        },
+    );
+    info!(
+        "Board extras: touch {}, encoder {encoder}, analog {analog}, side key {}, \
+         battery ADC {}, piezo {}, IMU {}",
+        if board.touch.is_some() { "yes" } else { "-" },
+        gpio(board.side_key),
+        gpio(board.battery_adc),
+        gpio(board.piezo),
+        board
+            .imu_addr
+            .map_or_else(|| "-".to_string(), |addr| format!("0x{addr:02X}"))
+    );
+    let missing: Vec<_> = board
+        .features
+        .iter()
+        .filter(|feature| !crate::boot::enabled_features().any(|enabled| enabled == **feature))
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        warn!(
+            "Board {} expects features missing from this build: [{}]",
+            board.id,
+            missing.join(", ")
+        );
+    }
+}
+
+/// The manifest's pins replace the typed `Pins` from `Peripherals`, which
+/// callers leave untouched. build.rs rejects manifests that name a pin twice,
+/// so each GPIO handed out here has a single owner as long as every manifest
+/// entry is claimed once.
+pub fn io_pin(gpio: i32) -> AnyIOPin {
+    unsafe { AnyIOPin::new(gpio) }
+}
+
+pub fn output_pin(gpio: i32) -> AnyOutputPin {
+    unsafe { AnyOutputPin::new(gpio) }
+}
@@ -0,0 +1,62 @@
+//! The per-pixel fixup, apart from the generated board facts so the host
+//! can check its output.
+
+use std::fmt;
+
+/// What the panel needs done to the renderer's native RGB565 words before
+/// they go out: `swap_rb` for a panel wired BGR, `swap_bytes` for one that
+/// latches the low byte first. `display colorbars` shows all four choices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PixelFixup {
+    pub swap_bytes: bool,
+    pub swap_rb: bool,
+}
+
+impl PixelFixup {
+    pub const NONE: PixelFixup = PixelFixup {
+        swap_bytes: false,
+        swap_rb: false,
+    };
+    /// Every combination, in the order the test pattern draws them.
+    pub const ALL: [PixelFixup; 4] = [
+        PixelFixup::NONE,
+        PixelFixup {
+            swap_bytes: true,
+            swap_rb: false,
+        },
+        PixelFixup {
+            swap_bytes: false,
+            swap_rb: true,
+        },
+        PixelFixup {
+            swap_bytes: true,
+            swap_rb: true,
+        },
+    ];
+
+    /// One pixel; red and blue trade places before the bytes do.
+    pub fn apply(self, pixel: u16) -> u16 {
+        let pixel = if self.swap_rb { swap_rb(pixel) } else { pixel };
+        if self.swap_bytes {
+            pixel.swap_bytes()
+        } else {
+            pixel
+        }
+    }
+}
+
+impl fmt::Display for PixelFixup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.swap_bytes, self.swap_rb) {
+            (false, false) => f.write_str("none"),
+            (true, false) => f.write_str("swap_bytes"),
+            (false, true) => f.write_str("swap_rb"),
+            (true, true) => f.write_str("swap_bytes + swap_rb"),
+        }
+    }
+}
+
+/// Trades the 5-bit red and blue fields, leaving green.
+pub fn swap_rb(pixel: u16) -> u16 {
+    (pixel & 0x07E0) | (pixel >> 11) | (pixel << 11)
+}
@@ -0,0 +1,318 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use tokio::sync::oneshot;
+
+static REPORT: Mutex<Vec<StageRecord>> = Mutex::new(Vec::new());
+static MILESTONES: Mutex<Vec<Milestone>> = Mutex::new(Vec::new());
+static NOTES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Boot-time budgets from power-on, checked as each milestone lands.
+const MILESTONE_TARGETS: &[(&str, Duration)] = &[
+    ("first_frame", Duration::from_millis(1500)),
+    ("ancs_advertising", Duration::from_millis(2500)),
+];
+
+/// Cargo features compiled into this image, in declaration order.
+pub const COMPILED_FEATURES: &[(&str, bool)] = &[
+    ("httpd", cfg!(feature = "httpd")),
+    ("mdns", cfg!(feature = "mdns")),
+    ("ota", cfg!(feature = "ota")),
+    ("ancs", cfg!(feature = "ancs")),
+    ("storage", cfg!(feature = "storage")),
+    ("gui-extras", cfg!(feature = "gui-extras")),
+    ("ancs-testmode", cfg!(feature = "ancs-testmode")),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Criticality {
+    /// Boot aborts when the stage fails.
+    Required,
+    /// Failure is logged and recorded; boot continues without the subsystem.
+    Optional,
+}
+
+#[derive(Clone, Debug)]
+pub struct StageRecord {
+    pub name: &'static str,
+    pub criticality: Criticality,
+    pub duration: Duration,
+    pub error: Option<String>,
+    /// More than 1 only for stages retried after boot; see `record_attempt`.
+    pub attempts: u32,
+    /// Ran on its own thread alongside the main sequence; see [`spawn`].
+    pub parallel: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Milestone {
+    pub name: &'static str,
+    /// Since power-on, not since `main`.
+    pub at: Duration,
+    pub target: Option<Duration>,
+}
+
+/// A stage started with [`spawn`]; [`Parallel::join`] collects its outcome.
+pub struct Parallel<T> {
+    name: &'static str,
+    criticality: Criticality,
+    outcome: oneshot::Receiver<Result<T>>,
+}
+
+pub fn enabled_features() -> impl Iterator<Item = &'static str> {
+    COMPILED_FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+}
+
+pub fn log_features() {
+    let enabled: Vec<_> = enabled_features().collect();
+    let disabled: Vec<_> = COMPILED_FEATURES
+        .iter()
+        .filter(|(_, enabled)| !*enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    info!("Features enabled: [{}]", enabled.join(", "));
+    if !disabled.is_empty() {
+        info!("Features compiled out: [{}]", disabled.join(", "));
+    }
+}
+
+/// Runs a stage whose failure aborts boot.
+pub fn required<T>(name: &'static str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let (result, duration) = timed(f);
+    record(name, Criticality::Required, duration, result.as_ref().err());
+    match result {
+        Ok(value) => {
+            info!("Boot stage {name} ok ({} ms)", duration.as_millis());
+            Ok(value)
+        }
+        Err(err) => {
+            error!("Boot stage {name} failed: {err:?}");
+            Err(err.context(format!("boot stage {name} failed")))
+        }
+    }
+}
+
+/// Runs a stage whose failure only disables its subsystem.
+pub fn optional<T>(name: &'static str, f: impl FnOnce() -> Result<T>) -> Option<T> {
+    let (result, duration) = timed(f);
+    record(name, Criticality::Optional, duration, result.as_ref().err());
+    match result {
+        Ok(value) => {
+            info!("Boot stage {name} ok ({} ms)", duration.as_millis());
+            Some(value)
+        }
+        Err(err) => {
+            warn!("Boot stage {name} failed, continuing without it: {err:?}");
+            None
+        }
+    }
+}
+
+/// Starts a stage on its own thread so it overlaps the main sequence. The
+/// stage is logged and recorded when it finishes; if the thread cannot be
+/// created it runs inline instead. `f` must not touch UI thread-locals.
+pub fn spawn<T: Send + 'static>(
+    name: &'static str,
+    criticality: Criticality,
+    stack_size: usize,
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Parallel<T> {
+    let (tx, outcome) = oneshot::channel();
+    let run = move || {
+        let (result, duration) = timed(f);
+        record_parallel(name, criticality, duration, result.as_ref().err());
+        match &result {
+            Ok(_) => info!(
+                "Boot stage {name} ok ({} ms, parallel)",
+                duration.as_millis()
+            ),
+            Err(err) => error!("Boot stage {name} failed (parallel): {err:?}"),
+        }
+        let _ = tx.send(result);
+    };
+    // Shared so a failed spawn still leaves the job here to run inline.
+    let job = Arc::new(Mutex::new(Some(run)));
+    let take = |job: &Mutex<Option<_>>| job.lock().ok().and_then(|mut slot| slot.take());
+    let spawned = thread::Builder::new()
+        .name(format!("boot-{name}"))
+        .stack_size(stack_size)
+        .spawn({
+            let job = Arc::clone(&job);
+            move || {
+                if let Some(run) = take(&job) {
+                    run();
+                }
+            }
+        });
+    if let Err(err) = spawned {
+        warn!("Boot stage {name}: no thread ({err}), running inline");
+        if let Some(run) = take(&job) {
+            run();
+        }
+    }
+    Parallel {
+        name,
+        criticality,
+        outcome,
+    }
+}
+
+impl<T> Parallel<T> {
+    /// Waits for the stage without blocking the calling task's thread. A
+    /// failure comes back as `Err` either way: required stages carry the
+    /// same context as [`required`], optional ones were already logged as
+    /// skipped and the caller just leaves the subsystem off.
+    pub async fn join(self) -> Result<T> {
+        let result = self
+            .outcome
+            .await
+            .map_err(|_| anyhow!("stage thread exited without a result"))
+            .and_then(|result| result);
+        match self.criticality {
+            Criticality::Required => {
+                result.map_err(|err| err.context(format!("boot stage {} failed", self.name)))
+            }
+            Criticality::Optional => result,
+        }
+    }
+}
+
+/// Notes how long after power-on `name` happened, once per boot.
+pub fn milestone(name: &'static str) {
+    let at = since_power_on();
+    let target = MILESTONE_TARGETS
+        .iter()
+        .find(|(target_name, _)| *target_name == name)
+        .map(|(_, target)| *target);
+    let Ok(mut milestones) = MILESTONES.lock() else {
+        return;
+    };
+    if milestones.iter().any(|milestone| milestone.name == name) {
+        return;
+    }
+    milestones.push(Milestone { name, at, target });
+    match target {
+        Some(target) if at > target => warn!(
+            "Boot milestone {name} at {} ms, over the {} ms target",
+            at.as_millis(),
+            target.as_millis()
+        ),
+        _ => info!("Boot milestone {name} at {} ms", at.as_millis()),
+    }
+}
+
+pub fn milestones() -> Vec<Milestone> {
+    MILESTONES
+        .lock()
+        .map(|milestones| milestones.clone())
+        .unwrap_or_default()
+}
+
+/// Records a condition worth surfacing next to the boot report, such as a
+/// subsystem running degraded.
+pub fn note(text: String) {
+    info!("Boot note: {text}");
+    if let Ok(mut notes) = NOTES.lock() {
+        notes.push(text);
+    }
+}
+
+pub fn notes() -> Vec<String> {
+    NOTES.lock().map(|notes| notes.clone()).unwrap_or_default()
+}
+
+/// Main-thread time the parallel stages would have cost if run in sequence.
+pub fn parallel_savings() -> Duration {
+    report()
+        .iter()
+        .filter(|stage| stage.parallel)
+        .map(|stage| stage.duration)
+        .sum()
+}
+
+fn since_power_on() -> Duration {
+    let micros = unsafe { esp_idf_svc::sys::esp_timer_get_time() };
+    Duration::from_micros(micros.max(0) as u64)
+}
+
+/// Records a stage that runs outside the boot sequence and may be retried.
+/// Repeated attempts update one entry instead of growing the report.
+pub fn record_attempt(name: &'static str, duration: Duration, error: Option<&anyhow::Error>) {
+    let Ok(mut report) = REPORT.lock() else {
+        return;
+    };
+    let error = error.map(|err| format!("{err:#}"));
+    match report.iter_mut().find(|stage| stage.name == name) {
+        Some(stage) => {
+            stage.duration = duration;
+            stage.error = error;
+            stage.attempts += 1;
+        }
+        None => report.push(StageRecord {
+            name,
+            criticality: Criticality::Optional,
+            duration,
+            error,
+            attempts: 1,
+            parallel: false,
+        }),
+    }
+}
+
+pub fn report() -> Vec<StageRecord> {
+    REPORT
+        .lock()
+        .map(|report| report.clone())
+        .unwrap_or_default()
+}
+
+fn timed<T>(f: impl FnOnce() -> Result<T>) -> (Result<T>, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn record(
+    name: &'static str,
+    criticality: Criticality,
+    duration: Duration,
+    error: Option<&anyhow::Error>,
+) {
+    push_record(name, criticality, duration, error, false);
+}
+
+fn record_parallel(
+    name: &'static str,
+    criticality: Criticality,
+    duration: Duration,
+    error: Option<&anyhow::Error>,
+) {
+    push_record(name, criticality, duration, error, true);
+}
+
+fn push_record(
+    name: &'static str,
+    criticality: Criticality,
+    duration: Duration,
+    error: Option<&anyhow::Error>,
+    parallel: bool,
+) {
+    if let Ok(mut report) = REPORT.lock() {
+        report.push(StageRecord {
+            name,
+            criticality,
+            duration,
+            error: error.map(|err| format!("{err:#}")),
+            attempts: 1,
+            parallel,
+        });
+    }
+}
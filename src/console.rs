@@ -0,0 +1,127 @@
+use std::{
+    io::{self, Read, Write},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+
+const STACK_SIZE: usize = 8 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const MAX_LINE: usize = 256;
+
+/// Receives the whitespace-split arguments after the command name and
+/// returns the text to print.
+pub type Handler = fn(&[&str]) -> Result<String>;
+
+struct Command {
+    name: &'static str,
+    help: &'static str,
+    handler: Handler,
+}
+
+static COMMANDS: Mutex<Vec<Command>> = Mutex::new(Vec::new());
+
+/// Registers a command; a later registration with the same name replaces it.
+pub fn register(name: &'static str, help: &'static str, handler: Handler) {
+    if let Ok(mut commands) = COMMANDS.lock() {
+        commands.retain(|command| command.name != name);
+        commands.push(Command {
+            name,
+            help,
+            handler,
+        });
+    }
+}
+
+/// Starts the line reader on the UART console.
+pub fn start() -> Result<()> {
+    register("help", "list commands", help);
+    thread::Builder::new()
+        .name("console".into())
+        .stack_size(STACK_SIZE)
+        .spawn(read_loop)
+        .map(|_| ())
+        .context("spawn console thread")
+}
+
+pub fn execute(line: &str) -> Result<String> {
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        return Ok(String::new());
+    };
+    let args: Vec<&str> = words.collect();
+    let handler = COMMANDS
+        .lock()
+        .map_err(|_| anyhow!("console registry poisoned"))?
+        .iter()
+        .find(|command| command.name == name)
+        .map(|command| command.handler)
+        .ok_or_else(|| anyhow!("unknown command '{name}', try 'help'"))?;
+    handler(&args)
+}
+
+fn help(_: &[&str]) -> Result<String> {
+    let commands = COMMANDS
+        .lock()
+        .map_err(|_| anyhow!("console registry poisoned"))?;
+    let mut lines: Vec<String> = commands
+        .iter()
+        .map(|command| format!("{:<16} {}", command.name, command.help))
+        .collect();
+    lines.sort();
+    Ok(lines.join("\n"))
+}
+
+fn read_loop() {
+    info!("Console ready, type 'help'");
+    let mut stdin = io::stdin();
+    let mut line = Vec::with_capacity(MAX_LINE);
+    let mut chunk = [0u8; 32];
+
+    loop {
+        let read = match stdin.read(&mut chunk) {
+            Ok(0) => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                warn!("Console read failed: {err}");
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        for &byte in &chunk[..read] {
+            match byte {
+                b'\r' | b'\n' => {
+                    if !line.is_empty() {
+                        run_line(&String::from_utf8_lossy(&line));
+                        line.clear();
+                    }
+                }
+                _ if line.len() < MAX_LINE => line.push(byte),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn run_line(line: &str) {
+    let output = match execute(line) {
+        Ok(output) => output,
+        Err(err) => format!("error: {err:#}"),
+    };
+    let mut stdout = io::stdout();
+    if !output.is_empty() {
+        let _ = writeln!(stdout, "{output}");
+    }
+    let _ = stdout.flush();
+}
@@ -0,0 +1,205 @@
+//! Contention on corelib's ECS runtime. `with_rt_mut` serializes every
+//! access, so one slow holder (usually corelib itself mid-transfer) delays
+//! every caller queued behind it. [`timed_with_rt_mut!`] wraps the call and
+//! records, per label, how long the caller waited for the runtime and how
+//! long its closure held it, into the buckets of [`BOUNDS_MS`]. Either one
+//! past [`WARN_AFTER`] is logged.
+//!
+//! It is a macro rather than a function because the runtime type the
+//! closure receives is not nameable from this crate. All timing goes
+//! through [`Probe`], the place a try-lock-with-timeout for readers that
+//! can do without fresh data would go.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+pub const WARN_AFTER: Duration = Duration::from_millis(20);
+/// Upper bounds of the histogram buckets; a last bucket takes the rest.
+pub const BOUNDS_MS: [u32; 5] = [1, 5, 20, 50, 200];
+const BUCKETS: usize = BOUNDS_MS.len() + 1;
+
+static STATS: Mutex<Vec<LabelStats>> = Mutex::new(Vec::new());
+/// Longest wait under any label since boot, for the stats overlay.
+static WORST_WAIT_US: AtomicU32 = AtomicU32::new(0);
+
+/// `corelib::ecs::with_rt_mut`, timed under `label`. Takes the closure in
+/// the same `move |rt| ...` form and returns the same future.
+#[macro_export]
+macro_rules! timed_with_rt_mut {
+    ($label:expr, move |$rt:ident| $body:expr) => {{
+        let probe = $crate::ecs::Probe::start($label);
+        ::corelib::ecs::with_rt_mut(move |$rt| {
+            let held = probe.enter();
+            #[allow(clippy::redundant_closure_call)]
+            let result = (move || $body)();
+            held.finish();
+            result
+        })
+    }};
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Histogram {
+    pub buckets: [u32; BUCKETS],
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Histogram {
+    fn record(&mut self, took: Duration) {
+        let ms = took.as_millis();
+        let bucket = BOUNDS_MS
+            .iter()
+            .position(|&bound| ms < u128::from(bound))
+            .unwrap_or(BOUNDS_MS.len());
+        self.buckets[bucket] += 1;
+        self.total += took;
+        self.max = self.max.max(took);
+    }
+
+    pub fn average(&self) -> Duration {
+        let count: u32 = self.buckets.iter().sum();
+        if count == 0 {
+            Duration::ZERO
+        } else {
+            self.total / count
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LabelStats {
+    pub label: &'static str,
+    pub calls: u32,
+    pub wait: Histogram,
+    pub hold: Histogram,
+    /// Calls where either time passed [`WARN_AFTER`].
+    pub slow: u32,
+}
+
+/// One call, from the request for the runtime until the closure returns.
+pub struct Probe {
+    label: &'static str,
+    requested: Instant,
+}
+
+impl Probe {
+    pub fn start(label: &'static str) -> Self {
+        Self {
+            label,
+            requested: Instant::now(),
+        }
+    }
+
+    /// The closure got the runtime.
+    pub fn enter(self) -> Held {
+        Held {
+            label: self.label,
+            wait: self.requested.elapsed(),
+            entered: Instant::now(),
+        }
+    }
+}
+
+pub struct Held {
+    label: &'static str,
+    wait: Duration,
+    entered: Instant,
+}
+
+impl Held {
+    pub fn finish(self) {
+        record(self.label, self.wait, self.entered.elapsed());
+    }
+}
+
+fn record(label: &'static str, wait: Duration, hold: Duration) {
+    let slow = wait > WARN_AFTER || hold > WARN_AFTER;
+    if slow {
+        warn!(
+            "ECS {label}: waited {} ms, held {} ms",
+            wait.as_millis(),
+            hold.as_millis()
+        );
+    }
+    WORST_WAIT_US.fetch_max(wait.as_micros() as u32, Ordering::Relaxed);
+    let Ok(mut stats) = STATS.lock() else {
+        return;
+    };
+    let at = match stats.iter().position(|entry| entry.label == label) {
+        Some(at) => at,
+        None => {
+            stats.push(LabelStats {
+                label,
+                calls: 0,
+                wait: Histogram::default(),
+                hold: Histogram::default(),
+                slow: 0,
+            });
+            stats.len() - 1
+        }
+    };
+    let entry = &mut stats[at];
+    entry.calls += 1;
+    entry.wait.record(wait);
+    entry.hold.record(hold);
+    entry.slow += u32::from(slow);
+}
+
+pub fn snapshot() -> Vec<LabelStats> {
+    STATS.lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+pub fn worst_wait() -> Duration {
+    Duration::from_micros(u64::from(WORST_WAIT_US.load(Ordering::Relaxed)))
+}
+
+pub fn reset() {
+    if let Ok(mut stats) = STATS.lock() {
+        stats.clear();
+    }
+    WORST_WAIT_US.store(0, Ordering::Relaxed);
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "ecs",
+        "[reset]: ECS runtime wait and hold times per caller",
+        |args| match args {
+            [] => {
+                let stats = snapshot();
+                if stats.is_empty() {
+                    return Ok("no ECS calls timed yet".to_string());
+                }
+                let lines: Vec<String> = stats
+                    .iter()
+                    .map(|entry| {
+                        format!(
+                            "{}: {} calls, {} slow; wait avg {} us max {} us; hold avg {} us max {} us",
+                            entry.label,
+                            entry.calls,
+                            entry.slow,
+                            entry.wait.average().as_micros(),
+                            entry.wait.max.as_micros(),
+                            entry.hold.average().as_micros(),
+                            entry.hold.max.as_micros()
+                        )
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            ["reset"] => {
+                reset();
+                Ok("ECS timings cleared".to_string())
+            }
+            _ => anyhow::bail!("usage: ecs [reset]"),
+        },
+    );
+}
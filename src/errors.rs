@@ -0,0 +1,235 @@
+//! Background failures the user should see. Tasks that log and carry on (or
+//! stop) also [`report`] here; the Errors page, the Home badge and
+//! `/status` all show the same bounded journal.
+//!
+//! A repeat of an unresolved report within [`DEDUP_WINDOW`] bumps its count
+//! instead of adding a row. A subsystem that recovers calls [`resolve`],
+//! which marks its rows resolved; they stay until cleared or until the
+//! journal needs the room. Where the subsystem can be kicked, it registers
+//! a retry with [`register_retry`].
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::bail;
+use log::info;
+
+use crate::{
+    events::{self, SystemEvent},
+    statlogger,
+};
+
+pub const CAPACITY: usize = 16;
+const DEDUP_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+static JOURNAL: Mutex<VecDeque<UserFacingError>> = Mutex::new(VecDeque::new());
+static RETRIES: Mutex<Vec<(&'static str, fn())>> = Mutex::new(Vec::new());
+/// Bumped whenever the journal changes.
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Degraded, still working or retrying on its own.
+    Warning,
+    /// Stopped until retried or rebooted.
+    Error,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UserFacingError {
+    /// Short code, e.g. `watch` or `touch`; retries are keyed by it.
+    pub subsystem: &'static str,
+    pub message: String,
+    pub severity: Severity,
+    /// Uptime at the first and the latest report.
+    pub first: Duration,
+    pub timestamp: Duration,
+    /// Reports folded into this row.
+    pub count: u32,
+    pub resolved: bool,
+}
+
+/// Records a failure of `subsystem`.
+pub fn report(subsystem: &'static str, severity: Severity, message: impl Into<String>) {
+    let message = message.into();
+    let now = statlogger::uptime();
+    let Ok(mut journal) = JOURNAL.lock() else {
+        return;
+    };
+    let repeat = journal.iter_mut().rev().find(|entry| {
+        !entry.resolved
+            && entry.subsystem == subsystem
+            && entry.message == message
+            && now.saturating_sub(entry.timestamp) < DEDUP_WINDOW
+    });
+    match repeat {
+        Some(entry) => {
+            entry.count += 1;
+            entry.timestamp = now;
+            entry.severity = entry.severity.max(severity);
+        }
+        None => {
+            if journal.len() == CAPACITY {
+                // Resolved rows go first; otherwise the oldest.
+                let index = journal.iter().position(|entry| entry.resolved);
+                journal.remove(index.unwrap_or(0));
+            }
+            journal.push_back(UserFacingError {
+                subsystem,
+                message,
+                severity,
+                first: now,
+                timestamp: now,
+                count: 1,
+                resolved: false,
+            });
+        }
+    }
+    changed(&journal);
+}
+
+/// Marks `subsystem`'s open rows resolved; cheap when there are none.
+pub fn resolve(subsystem: &'static str) {
+    let Ok(mut journal) = JOURNAL.lock() else {
+        return;
+    };
+    let mut any = false;
+    for entry in journal
+        .iter_mut()
+        .filter(|entry| entry.subsystem == subsystem && !entry.resolved)
+    {
+        entry.resolved = true;
+        any = true;
+    }
+    if any {
+        info!("Errors: {subsystem} reported healthy");
+        changed(&journal);
+    }
+}
+
+/// Drops the resolved rows.
+pub fn clear_resolved() {
+    if let Ok(mut journal) = JOURNAL.lock() {
+        let before = journal.len();
+        journal.retain(|entry| !entry.resolved);
+        if journal.len() != before {
+            changed(&journal);
+        }
+    }
+}
+
+/// Lets the Errors page retry `subsystem`. `retry` may be called from any
+/// thread, so it should only poke the subsystem's own task.
+pub fn register_retry(subsystem: &'static str, retry: fn()) {
+    if let Ok(mut retries) = RETRIES.lock() {
+        retries.retain(|(name, _)| *name != subsystem);
+        retries.push((subsystem, retry));
+    }
+}
+
+pub fn can_retry(subsystem: &str) -> bool {
+    retry_for(subsystem).is_some()
+}
+
+/// Runs `subsystem`'s retry; false when it has none. Rows stay open until
+/// the subsystem reports healthy.
+pub fn retry(subsystem: &str) -> bool {
+    let Some(retry) = retry_for(subsystem) else {
+        return false;
+    };
+    info!("Errors: retrying {subsystem}");
+    retry();
+    true
+}
+
+fn retry_for(subsystem: &str) -> Option<fn()> {
+    RETRIES
+        .lock()
+        .ok()?
+        .iter()
+        .find(|(name, _)| *name == subsystem)
+        .map(|(_, retry)| *retry)
+}
+
+/// Oldest first.
+pub fn snapshot() -> Vec<UserFacingError> {
+    JOURNAL
+        .lock()
+        .map(|journal| journal.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn unresolved() -> usize {
+    JOURNAL
+        .lock()
+        .map(|journal| count_unresolved(&journal))
+        .unwrap_or(0)
+}
+
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+fn count_unresolved(journal: &VecDeque<UserFacingError>) -> usize {
+    journal.iter().filter(|entry| !entry.resolved).count()
+}
+
+fn changed(journal: &VecDeque<UserFacingError>) {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    events::publish(SystemEvent::ErrorsChanged {
+        unresolved: count_unresolved(journal),
+    });
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "errors",
+        "user-facing errors: errors [retry <subsystem>|clear]",
+        |args| {
+            match args {
+                [] => {}
+                ["retry", subsystem] => {
+                    if !retry(subsystem) {
+                        bail!("{subsystem} has no retry");
+                    }
+                }
+                ["clear"] => clear_resolved(),
+                _ => bail!("usage: errors [retry <subsystem>|clear]"),
+            }
+            let now = statlogger::uptime();
+            let lines: Vec<String> = snapshot()
+                .iter()
+                .map(|entry| {
+                    format!(
+                        "{} {} x{} {}s ago{}: {}",
+                        entry.subsystem,
+                        entry.severity.label(),
+                        entry.count,
+                        now.saturating_sub(entry.timestamp).as_secs(),
+                        if entry.resolved { " (resolved)" } else { "" },
+                        entry.message
+                    )
+                })
+                .collect();
+            if lines.is_empty() {
+                return Ok("no errors".to_string());
+            }
+            Ok(lines.join("\n"))
+        },
+    );
+}
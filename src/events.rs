@@ -0,0 +1,77 @@
+use std::sync::OnceLock;
+
+use tokio::sync::broadcast;
+
+use crate::{
+    memory::pressure::PressureLevel, miwear::ring::RingPhase, sensors::imu::ScreenRotation,
+    settings::rollback::Subsystem,
+};
+
+const CAPACITY: usize = 32;
+
+static BUS: OnceLock<broadcast::Sender<SystemEvent>> = OnceLock::new();
+
+/// Cross-module notifications. Subscribers that fall behind skip ahead
+/// (`RecvError::Lagged`) rather than blocking publishers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SystemEvent {
+    /// Die temperature crossed the warning threshold (`active`) or dropped
+    /// back below it minus hysteresis.
+    ThermalWarning { active: bool, celsius: f32 },
+    /// Emitted just before the device powers down on its own.
+    ShuttingDown { reason: &'static str },
+    /// Internal heap moved to another `memory::pressure` level.
+    MemoryPressure { level: PressureLevel },
+    /// The watch connection phase, name or link parameters changed; the
+    /// details are in `miwear::status`.
+    WatchStatusChanged,
+    /// The connected watch's battery level changed, or became unknown.
+    WatchBatteryChanged { level: Option<u8> },
+    /// A watch transfer started or advanced (0.0..=1.0), or ended (`None`).
+    TransferProgress { fraction: Option<f32> },
+    /// The find-device ring started, is waiting on the watch, or stopped.
+    RingChanged { phase: RingPhase },
+    /// A notification was queued for ANCS clients.
+    NotificationPublished {
+        uid: u32,
+        app_id: String,
+        title: String,
+    },
+    /// A connectivity setting change did not validate and was restored.
+    SettingsRolledBack { subsystem: Subsystem },
+    /// A setting stored a new value, from any editor; see
+    /// `settings::revision`.
+    SettingChanged { name: &'static str, revision: u32 },
+    /// Auto-rotation settled on a new screen orientation, or was turned off
+    /// (`Deg0`).
+    OrientationChanged { rotation: ScreenRotation },
+    /// The user-facing error journal changed; see `errors`.
+    ErrorsChanged { unresolved: usize },
+    /// The notification relay canary went unanswered `failures` times in a
+    /// row; see `ancs::canary`.
+    RelayDegraded { failures: u32 },
+    /// A NimBLE host memory pool hit zero free blocks for the first time
+    /// since boot; see `ble::resources`.
+    BlePoolExhausted { pool: String },
+    /// A notification waiting in `ancs::outbox` ran out of TTL before a
+    /// watch subscribed.
+    OutboxExpired {
+        id: u32,
+        app_id: String,
+        title: String,
+    },
+}
+
+fn bus() -> &'static broadcast::Sender<SystemEvent> {
+    BUS.get_or_init(|| broadcast::channel(CAPACITY).0)
+}
+
+pub fn publish(event: SystemEvent) {
+    log::debug!("Event: {event:?}");
+    // No subscribers is fine; the event is simply dropped.
+    let _ = bus().send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<SystemEvent> {
+    bus().subscribe()
+}
@@ -1,2 +1,45 @@
+#[cfg(feature = "gui-extras")]
+pub mod alarms_page;
+pub mod backlight;
+pub mod bounded_model;
+#[cfg(feature = "gui-extras")]
+pub mod devices;
 pub mod display;
+pub mod errors_page;
+pub mod fallback;
+pub mod flashing;
+pub mod focus;
+pub mod frame_cache;
+pub mod history_chart;
+#[cfg(feature = "gui-extras")]
+pub mod kinetic;
+pub mod lazy_pages;
+#[cfg(feature = "gui-extras")]
+pub mod level_page;
+pub mod line_batch;
+pub mod marquee;
+#[cfg(feature = "gui-extras")]
+pub mod media_page;
+#[cfg(feature = "gui-extras")]
+pub mod networks;
+pub mod pages;
+#[cfg(feature = "ancs")]
+pub mod pairing;
+pub mod pixel_shift;
+pub mod pixels;
+#[cfg(feature = "ancs")]
+pub mod reminders_page;
+#[cfg(feature = "ancs")]
+pub mod remote_page;
+pub mod render_profile;
+pub mod settings_page;
+pub mod setup;
 pub mod slint_ui;
+pub mod stats_page;
+#[cfg(feature = "gui-extras")]
+pub mod targets_page;
+pub mod theme;
+pub mod timezone_page;
+pub mod toast;
+pub mod touch_trace;
+pub mod watch;
@@ -0,0 +1,171 @@
+use std::{cell::RefCell, time::Duration};
+
+use slint::SharedString;
+
+use super::{
+    bounded_model::BoundedModel,
+    slint_ui::{self, AlarmEntry, App, Page},
+    toast,
+};
+use crate::{
+    i18n,
+    miwear::alarms::{self, Alarm, AlarmError, AlarmList, AlarmTime, NewAlarm, Weekdays},
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Watches report their own limit, well under this.
+const MAX_ALARM_ROWS: usize = 32;
+/// Changes made on the watch show up within this while the page is open.
+const REFETCH_AFTER: Duration = Duration::from_secs(30);
+/// The page's repeat choices, in the order the repeat line cycles them.
+const REPEATS: [Weekdays; 4] = [
+    Weekdays::ONCE,
+    Weekdays::DAILY,
+    Weekdays::WORKDAYS,
+    Weekdays::WEEKENDS,
+];
+
+thread_local! {
+    static ALARM_MODEL: BoundedModel<AlarmEntry> = BoundedModel::new("alarms", MAX_ALARM_ROWS);
+    /// Why the last fetch failed; cleared by the next one that works.
+    static FETCH_ERROR: RefCell<Option<AlarmError>> = const { RefCell::new(None) };
+}
+
+pub fn install(app: &App) {
+    app.set_alarms(ALARM_MODEL.with(BoundedModel::model));
+
+    app.on_alarm_toggle(|id| {
+        let Some(alarm) = alarm(id) else {
+            return;
+        };
+        tokio::task::spawn_local(async move {
+            report(alarms::set_enabled(alarm.id, !alarm.enabled).await);
+        });
+    });
+    app.on_alarm_delete(|id| {
+        let Some(alarm) = alarm(id) else {
+            return;
+        };
+        tokio::task::spawn_local(async move {
+            if report(alarms::delete(alarm.id).await) {
+                toast::show(i18n::trf("Deleted {} alarm", &[&alarm.time]));
+            }
+        });
+    });
+    app.on_alarm_add(|hour, minute, repeat| {
+        let time = AlarmTime::new(hour.clamp(0, 23) as u8, minute.clamp(0, 59) as u8);
+        let repeat = REPEATS[repeat.clamp(0, 3) as usize];
+        tokio::task::spawn_local(async move {
+            let added = match time {
+                Ok(time) => {
+                    let alarm = NewAlarm {
+                        time,
+                        repeat,
+                        label: String::new(),
+                    };
+                    alarms::add(alarm).await
+                }
+                Err(err) => Err(err),
+            };
+            if report(added) {
+                if let Ok(time) = time {
+                    toast::show(i18n::trf("Alarm set for {}", &[&time]));
+                }
+            }
+        });
+    });
+
+    tokio::task::spawn_local(async {
+        let mut was_visible = false;
+        let mut seen = None;
+        loop {
+            let visible = slint_ui::current_page() == Some(Page::Alarms);
+            // The watch does not report its own edits, so opening the page
+            // and staying on it are what bring them in.
+            if visible && (!was_visible || alarms::fetched_within(REFETCH_AFTER).is_none()) {
+                let result = alarms::list().await.err();
+                FETCH_ERROR.with(|error| *error.borrow_mut() = result);
+                seen = None;
+            }
+            was_visible = visible;
+            let current = (alarms::generation(), i18n::active());
+            if visible && seen != Some(current) {
+                show(&alarms::cached());
+                seen = Some(current);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+fn alarm(id: i32) -> Option<Alarm> {
+    let id = u32::try_from(id).ok()?;
+    alarms::cached()
+        .alarms
+        .into_iter()
+        .find(|alarm| alarm.id == id)
+}
+
+/// Toasts a failure; true when the request went through.
+fn report(result: Result<AlarmList, AlarmError>) -> bool {
+    match result {
+        Ok(_) => true,
+        Err(err) => {
+            toast::show(error_text(&err));
+            false
+        }
+    }
+}
+
+fn show(list: &AlarmList) {
+    let error = FETCH_ERROR.with(|error| error.borrow().clone());
+    let summary = match &error {
+        Some(err) => error_text(err),
+        None => i18n::trf("{} of {} alarms", &[&list.alarms.len(), &list.limit]),
+    };
+    let can_add = error.is_none() && list.alarms.len() < usize::from(list.limit);
+    let entries: Vec<AlarmEntry> = list
+        .alarms
+        .iter()
+        .map(|alarm| AlarmEntry {
+            id: alarm.id as i32,
+            time: SharedString::from(alarm.time.to_string()),
+            detail: SharedString::from(detail(alarm)),
+            enabled: alarm.enabled,
+        })
+        .collect();
+    ALARM_MODEL.with(|model| model.set_vec(entries));
+    slint_ui::with_app(|app| {
+        app.set_alarms_summary(SharedString::from(summary));
+        app.set_alarms_can_add(can_add);
+    });
+}
+
+fn detail(alarm: &Alarm) -> String {
+    let repeat = match alarm.repeat {
+        Weekdays::ONCE => i18n::tr("Once").to_string(),
+        Weekdays::DAILY => i18n::tr("Daily").to_string(),
+        Weekdays::WORKDAYS => i18n::tr("Workdays").to_string(),
+        Weekdays::WEEKENDS => i18n::tr("Weekends").to_string(),
+        days => days.to_string(),
+    };
+    if alarm.label.is_empty() {
+        repeat
+    } else {
+        format!("{repeat} · {}", alarm.label)
+    }
+}
+
+fn error_text(err: &AlarmError) -> String {
+    match err {
+        AlarmError::NotConnected => i18n::tr("Watch not connected").to_string(),
+        AlarmError::Unsupported => i18n::tr("This watch has no alarms").to_string(),
+        AlarmError::Full { limit } => i18n::trf("The watch holds at most {} alarms", &[limit]),
+        AlarmError::Timeout => i18n::tr("Watch did not answer").to_string(),
+        AlarmError::NotFound(_)
+        | AlarmError::InvalidTime
+        | AlarmError::InvalidRepeat(_)
+        | AlarmError::Unavailable
+        | AlarmError::Failed(_) => i18n::trf("Alarm failed: {}", &[err]),
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use esp_idf_svc::hal::ledc::LedcDriver;
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    events::{self, SystemEvent},
+    settings::{self, SettingKey},
+};
+use idle::{Idle, IdleConfig, Stage};
+
+pub mod idle;
+pub mod limits;
+
+/// At least [`limits::MIN_BRIGHTNESS_PERCENT`].
+pub const BRIGHTNESS_PERCENT: SettingKey<u8> =
+    SettingKey::new("gui_brightness", "50").validated(|percent| limits::check_brightness(*percent));
+
+/// Dims and then turns the screen off without input; see [`idle`].
+pub const SCREEN_TIMEOUT: SettingKey<bool> = SettingKey::new("scr_timeout", "false");
+/// Seconds without input before dimming.
+pub const DIM_AFTER_SECS: SettingKey<u32> = SettingKey::new("scr_dim_s", "20")
+    .validated(|secs| limits::check_dim_after(*secs, settings::get(&OFF_AFTER_SECS)));
+/// Seconds without input before turning off, counted like the dim timeout.
+pub const OFF_AFTER_SECS: SettingKey<u32> = SettingKey::new("scr_off_s", "30")
+    .validated(|secs| limits::check_off_after(*secs, settings::get(&DIM_AFTER_SECS)));
+pub const DIM_PERCENT: SettingKey<u8> =
+    SettingKey::new("scr_dim_pct", "10").validated(|percent| limits::check_dim_percent(*percent));
+/// Milliseconds a wake from off stays dim without further input.
+pub const FULL_AFTER_WAKE_MS: SettingKey<u32> =
+    SettingKey::new("scr_wake_ms", "500").validated(|ms| limits::check_full_after_wake(*ms));
+const IDLE_TICK: Duration = Duration::from_millis(250);
+
+/// Upper bound while the die is running hot.
+const THERMAL_CAP_PERCENT: u8 = 60;
+
+struct Backlight {
+    driver: LedcDriver<'static>,
+    cap: Option<u8>,
+    blanked: bool,
+    /// The screen timeout, and off at the user's request, e.g. with the
+    /// side key.
+    idle: Idle,
+}
+
+thread_local! {
+    static BACKLIGHT: RefCell<Option<Backlight>> = const { RefCell::new(None) };
+}
+
+/// Takes ownership of the backlight PWM and applies brightness changes
+/// published on the event bus. Must be called on the UI thread.
+pub fn spawn(driver: LedcDriver<'static>) {
+    let mut events = events::subscribe();
+    BACKLIGHT.with(|cell| {
+        *cell.borrow_mut() = Some(Backlight {
+            driver,
+            cap: None,
+            blanked: false,
+            idle: Idle::new(Instant::now()),
+        })
+    });
+    update(|_| {});
+    tokio::task::spawn_local(async move {
+        loop {
+            match events.recv().await {
+                Ok(SystemEvent::ThermalWarning { active, .. }) => {
+                    update(|backlight| backlight.cap = active.then_some(THERMAL_CAP_PERCENT));
+                }
+                Ok(SystemEvent::ShuttingDown { .. }) => {
+                    update(|backlight| backlight.blanked = true)
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+    tokio::task::spawn_local(async {
+        let mut ticker = tokio::time::interval(IDLE_TICK);
+        loop {
+            ticker.tick().await;
+            let config = idle_config();
+            let changed = BACKLIGHT.with(|cell| {
+                cell.borrow_mut()
+                    .as_mut()
+                    .is_some_and(|backlight| backlight.idle.tick(Instant::now(), config.as_ref()))
+            });
+            if changed {
+                update(|_| {});
+            }
+        }
+    });
+}
+
+/// The timeout settings, or `None` while [`SCREEN_TIMEOUT`] is off.
+fn idle_config() -> Option<IdleConfig> {
+    settings::get(&SCREEN_TIMEOUT).then(|| IdleConfig {
+        dim_after: Duration::from_secs(settings::get(&DIM_AFTER_SECS).into()),
+        off_after: Duration::from_secs(settings::get(&OFF_AFTER_SECS).into()),
+        dim_percent: settings::get(&DIM_PERCENT),
+        full_after_wake: Duration::from_millis(settings::get(&FULL_AFTER_WAKE_MS).into()),
+    })
+}
+
+/// Forces the backlight off (for example while the panel is being reset)
+/// or back to its normal level. Takes effect immediately.
+pub fn set_blanked(blanked: bool) {
+    update(|backlight| backlight.blanked = blanked);
+}
+
+/// Turns the screen off or back on at the user's request, independent of
+/// the blanking the display driver does around panel resets.
+pub fn set_screen_on(on: bool) {
+    update(|backlight| {
+        if on {
+            backlight.idle.turn_on(Instant::now());
+        } else {
+            backlight.idle.turn_off();
+        }
+    });
+}
+
+/// Call on every touch and key input. Returns true when the input only
+/// woke the screen and should go no further.
+pub fn on_input() -> bool {
+    let config = idle_config();
+    let (woke, stage_changed) = BACKLIGHT.with(|cell| {
+        cell.borrow_mut()
+            .as_mut()
+            .map_or((false, false), |backlight| {
+                let before = backlight.idle.stage();
+                let woke = backlight.idle.on_input(Instant::now(), config.as_ref());
+                (woke, backlight.idle.stage() != before)
+            })
+    });
+    if stage_changed {
+        update(|_| {});
+    }
+    woke
+}
+
+pub fn screen_on() -> bool {
+    BACKLIGHT.with(|cell| {
+        cell.borrow()
+            .as_ref()
+            .map_or(true, |backlight| backlight.idle.stage() != Stage::Off)
+    })
+}
+
+pub fn effective_percent(requested: u8, cap: Option<u8>) -> u8 {
+    requested.min(cap.unwrap_or(100)).min(100)
+}
+
+fn update(f: impl FnOnce(&mut Backlight)) {
+    BACKLIGHT.with(|cell| {
+        let mut slot = cell.borrow_mut();
+        let Some(backlight) = slot.as_mut() else {
+            return;
+        };
+        f(backlight);
+        let percent = if backlight.blanked {
+            0
+        } else {
+            let full = settings::get(&BRIGHTNESS_PERCENT);
+            let percent = backlight.idle.percent(full, idle_config().as_ref());
+            effective_percent(percent, backlight.cap)
+        };
+        let duty = backlight.driver.get_max_duty() * percent as u32 / 100;
+        match backlight.driver.set_duty(duty) {
+            Ok(()) => info!("Backlight at {percent}%"),
+            Err(err) => warn!("Backlight update failed: {err:?}"),
+        }
+    });
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "screen",
+        "screen timeout: screen [timeout on|off] [dim <s>] [off <s>] [dimpct <5-100>] [wake <ms>]",
+        |args| {
+            for pair in args.chunks(2) {
+                match pair {
+                    ["timeout", "on"] => settings::set(&SCREEN_TIMEOUT, &true)?,
+                    ["timeout", "off"] => settings::set(&SCREEN_TIMEOUT, &false)?,
+                    ["dim", secs] => settings::set(&DIM_AFTER_SECS, &secs.parse()?)?,
+                    ["off", secs] => settings::set(&OFF_AFTER_SECS, &secs.parse()?)?,
+                    ["dimpct", percent] => settings::set(&DIM_PERCENT, &percent.parse()?)?,
+                    ["wake", ms] => settings::set(&FULL_AFTER_WAKE_MS, &ms.parse()?)?,
+                    _ => bail!(
+                        "usage: screen [timeout on|off] [dim <s>] [off <s>] [dimpct <n>] [wake <ms>]"
+                    ),
+                }
+            }
+            Ok(format!(
+                "timeout={} dim={}s off={}s dimpct={}% wake={}ms",
+                if settings::get(&SCREEN_TIMEOUT) {
+                    "on"
+                } else {
+                    "off"
+                },
+                settings::get(&DIM_AFTER_SECS),
+                settings::get(&OFF_AFTER_SECS),
+                settings::get(&DIM_PERCENT),
+                settings::get(&FULL_AFTER_WAKE_MS)
+            ))
+        },
+    );
+}
@@ -0,0 +1,122 @@
+//! The screen timeout as a pure state machine: full brightness, a dim stage
+//! after [`IdleConfig::dim_after`] without input, off at
+//! [`IdleConfig::off_after`]. Input during the dim stage restores full
+//! brightness at once. Input while off only wakes the screen, to the dim
+//! level, and full brightness follows on the next input or after
+//! [`IdleConfig::full_after_wake`], so a wake in the dark is not blinding.
+//!
+//! Time is passed in, so the caller owns the clock and the ticking.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdleConfig {
+    pub dim_after: Duration,
+    /// Counted from the last input, like `dim_after`, and longer than it.
+    pub off_after: Duration,
+    pub dim_percent: u8,
+    pub full_after_wake: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Full,
+    Dim,
+    Off,
+    /// Woken from off and still at the dim level.
+    Waking,
+}
+
+pub struct Idle {
+    stage: Stage,
+    last_input: Instant,
+    woke_at: Instant,
+}
+
+impl Idle {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            stage: Stage::Full,
+            last_input: now,
+            woke_at: now,
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// Records input. Returns true when it only woke the screen and should
+    /// not reach the UI. With no `config` a wake goes straight to full.
+    pub fn on_input(&mut self, now: Instant, config: Option<&IdleConfig>) -> bool {
+        self.last_input = now;
+        match self.stage {
+            Stage::Off => {
+                self.stage = if config.is_some() {
+                    Stage::Waking
+                } else {
+                    Stage::Full
+                };
+                self.woke_at = now;
+                true
+            }
+            Stage::Dim | Stage::Waking => {
+                self.stage = Stage::Full;
+                false
+            }
+            Stage::Full => false,
+        }
+    }
+
+    /// Turns the screen off now, as the side key does.
+    pub fn turn_off(&mut self) {
+        self.stage = Stage::Off;
+    }
+
+    /// Turns the screen on at full brightness, restarting the timeout.
+    pub fn turn_on(&mut self, now: Instant) {
+        self.stage = Stage::Full;
+        self.last_input = now;
+    }
+
+    /// Advances on time alone; returns true when the stage changed. With no
+    /// `config` the timeout is off and only an explicit turn-off darkens.
+    pub fn tick(&mut self, now: Instant, config: Option<&IdleConfig>) -> bool {
+        let next = match (self.stage, config) {
+            (Stage::Dim | Stage::Waking, None) => Stage::Full,
+            (stage, None) => stage,
+            (Stage::Waking, Some(config)) => {
+                if now.saturating_duration_since(self.woke_at) >= config.full_after_wake {
+                    Stage::Full
+                } else {
+                    Stage::Waking
+                }
+            }
+            (Stage::Full | Stage::Dim, Some(config)) => {
+                let idle = now.saturating_duration_since(self.last_input);
+                if idle >= config.off_after {
+                    Stage::Off
+                } else if idle >= config.dim_after {
+                    Stage::Dim
+                } else {
+                    Stage::Full
+                }
+            }
+            (Stage::Off, Some(_)) => Stage::Off,
+        };
+        let changed = next != self.stage;
+        self.stage = next;
+        changed
+    }
+
+    /// The brightness to show for a `full` brightness setting.
+    pub fn percent(&self, full: u8, config: Option<&IdleConfig>) -> u8 {
+        match self.stage {
+            Stage::Full => full,
+            Stage::Off => 0,
+            Stage::Dim | Stage::Waking => {
+                config.map_or(full, |config| config.dim_percent.min(full))
+            }
+        }
+    }
+}
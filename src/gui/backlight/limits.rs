@@ -0,0 +1,51 @@
+//! What the backlight settings accept. A check that depends on another
+//! setting takes its value, so the rules stay pure.
+
+/// Never dark: with no ambient sensor, a 0 written from outside would leave
+/// no way to see the UI to fix it.
+pub const MIN_BRIGHTNESS_PERCENT: u8 = 5;
+pub const MAX_FULL_AFTER_WAKE_MS: u32 = 5_000;
+
+pub fn check_brightness(percent: u8) -> Result<(), String> {
+    if (MIN_BRIGHTNESS_PERCENT..=100).contains(&percent) {
+        Ok(())
+    } else {
+        Err(format!("brightness is {MIN_BRIGHTNESS_PERCENT} to 100%"))
+    }
+}
+
+pub fn check_dim_after(secs: u32, off_secs: u32) -> Result<(), String> {
+    match secs {
+        0 => Err("dim timeout must be nonzero".into()),
+        secs if secs >= off_secs => Err(format!(
+            "dim timeout must be below the {off_secs} s off timeout"
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn check_off_after(secs: u32, dim_secs: u32) -> Result<(), String> {
+    match secs {
+        0 => Err("off timeout must be nonzero".into()),
+        secs if secs <= dim_secs => Err(format!(
+            "off timeout must be above the {dim_secs} s dim timeout"
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn check_dim_percent(percent: u8) -> Result<(), String> {
+    if (MIN_BRIGHTNESS_PERCENT..=100).contains(&percent) {
+        Ok(())
+    } else {
+        Err(format!("dim level is {MIN_BRIGHTNESS_PERCENT} to 100%"))
+    }
+}
+
+pub fn check_full_after_wake(ms: u32) -> Result<(), String> {
+    if ms <= MAX_FULL_AFTER_WAKE_MS {
+        Ok(())
+    } else {
+        Err(format!("wake delay is at most {MAX_FULL_AFTER_WAKE_MS} ms"))
+    }
+}
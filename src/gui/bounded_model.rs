@@ -0,0 +1,111 @@
+//! `VecModel` with a hard row cap, for every list the UI shows. Replacing
+//! the rows keeps the first `capacity` of them (lists are
+//! sorted most relevant first); pushing past the cap evicts the oldest row.
+//! Rows dropped either way are counted per model for `models` and the soak
+//! report, so a cap that bites shows up instead of silently truncating.
+
+use std::{rc::Rc, sync::Mutex};
+
+use slint::{Model, ModelRc, VecModel};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ModelStats {
+    pub name: &'static str,
+    pub capacity: usize,
+    pub rows: usize,
+    pub peak: usize,
+    pub dropped: u64,
+}
+
+static STATS: Mutex<Vec<ModelStats>> = Mutex::new(Vec::new());
+
+pub struct BoundedModel<T> {
+    name: &'static str,
+    capacity: usize,
+    rows: Rc<VecModel<T>>,
+}
+
+impl<T: Clone + 'static> BoundedModel<T> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        if let Ok(mut stats) = STATS.lock() {
+            if !stats.iter().any(|entry| entry.name == name) {
+                stats.push(ModelStats {
+                    name,
+                    capacity,
+                    rows: 0,
+                    peak: 0,
+                    dropped: 0,
+                });
+            }
+        }
+        Self {
+            name,
+            capacity,
+            rows: Rc::new(VecModel::default()),
+        }
+    }
+
+    /// The handle to give the `App` property.
+    pub fn model(&self) -> ModelRc<T> {
+        ModelRc::from(self.rows.clone())
+    }
+
+    pub fn set_vec(&self, mut rows: Vec<T>) {
+        let dropped = rows.len().saturating_sub(self.capacity);
+        rows.truncate(self.capacity);
+        rows.shrink_to_fit();
+        self.rows.set_vec(rows);
+        self.note(dropped);
+    }
+
+    pub fn push(&self, row: T) {
+        let dropped = if self.rows.row_count() >= self.capacity {
+            self.rows.remove(0);
+            1
+        } else {
+            0
+        };
+        self.rows.push(row);
+        self.note(dropped);
+    }
+
+    pub fn clear(&self) {
+        self.set_vec(Vec::new());
+    }
+
+    fn note(&self, dropped: usize) {
+        let rows = self.rows.row_count();
+        let Ok(mut stats) = STATS.lock() else {
+            return;
+        };
+        if let Some(entry) = stats.iter_mut().find(|entry| entry.name == self.name) {
+            entry.rows = rows;
+            entry.peak = entry.peak.max(rows);
+            entry.dropped += dropped as u64;
+        }
+    }
+}
+
+/// Every bounded model created so far, in creation order.
+pub fn stats() -> Vec<ModelStats> {
+    STATS.lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+pub fn register_commands() {
+    crate::console::register("models", "UI list models: rows, peak, cap, dropped", |_| {
+        let stats = stats();
+        if stats.is_empty() {
+            return Ok("no models yet".to_string());
+        }
+        Ok(stats
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{}: {}/{} rows, peak {}, dropped {}",
+                    entry.name, entry.rows, entry.capacity, entry.peak, entry.dropped
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    });
+}
@@ -0,0 +1,270 @@
+#[cfg(feature = "ancs")]
+use std::cell::Cell;
+use std::time::Duration;
+
+use slint::SharedString;
+
+use super::{
+    bounded_model::BoundedModel,
+    pages::{Create, PageDescriptor},
+    slint_ui::{self, App, ClientEntry, Page},
+    toast,
+};
+#[cfg(feature = "ancs")]
+use super::{
+    marquee::{self, Line},
+    theme,
+};
+#[cfg(feature = "ancs")]
+use crate::miwear::ancs::{self, advertising, clients};
+use crate::{
+    ble::link,
+    i18n,
+    miwear::{
+        liveness, media,
+        ring::{self, RingError, RingPhase},
+        send_queue, status,
+    },
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_CLIENT_ROWS: usize = 16;
+
+pub const PAGE: PageDescriptor =
+    PageDescriptor::new("devices", "Devices", Create::Slint(Page::Devices));
+#[cfg(feature = "ancs")]
+const BURST_COUNT: u32 = 5;
+#[cfg(feature = "ancs")]
+const BURST_SPACING: Duration = Duration::from_millis(500);
+
+thread_local! {
+    static CLIENT_MODEL: BoundedModel<ClientEntry> = BoundedModel::new("clients", MAX_CLIENT_ROWS);
+    #[cfg(feature = "ancs")]
+    static TEST_SEQ: Cell<u32> = const { Cell::new(0) };
+}
+
+pub fn install(app: &App) {
+    app.set_clients(CLIENT_MODEL.with(BoundedModel::model));
+
+    #[cfg(feature = "ancs")]
+    app.on_test_notification(|burst| {
+        if burst {
+            tokio::task::spawn_local(send_burst());
+        } else {
+            send_test(None);
+        }
+    });
+    #[cfg(not(feature = "ancs"))]
+    app.set_test_notify_reason(SharedString::from(i18n::tr("ANCS is not built in")));
+    app.on_ring_watch(|ring| {
+        tokio::task::spawn_local(ring_watch(ring));
+    });
+
+    tokio::task::spawn_local(async {
+        #[cfg(feature = "ancs")]
+        let mut seen_generation = None;
+        #[cfg(feature = "ancs")]
+        let mut seen_blocked = None;
+        #[cfg(feature = "ancs")]
+        let mut seen_advertising = None;
+        let mut seen_link = None;
+        let mut seen_ring = None;
+        loop {
+            #[cfg(feature = "ancs")]
+            clients::refresh_links();
+            let visible = slint_ui::current_page() == Some(Page::Devices);
+            #[cfg(feature = "ancs")]
+            {
+                let generation = (clients::generation(), theme::text_size());
+                if visible && seen_generation != Some(generation) {
+                    refresh();
+                    seen_generation = Some(generation);
+                }
+                let blocked = test_blocked_reason();
+                if visible && seen_blocked != Some(blocked) {
+                    slint_ui::with_app(|app| {
+                        app.set_test_notify_enabled(blocked.is_none());
+                        app.set_test_notify_reason(SharedString::from(blocked.unwrap_or("")));
+                    });
+                    seen_blocked = Some(blocked);
+                }
+                let advertising = (advertising::active(), ancs::mode(), i18n::active());
+                if visible && seen_advertising != Some(advertising) {
+                    let label = match advertising.1 {
+                        ancs::Mode::Phantom => i18n::tr(advertising.0.label()),
+                        mode => i18n::tr(mode.label()),
+                    };
+                    slint_ui::with_app(|app| app.set_advertising_mode(SharedString::from(label)));
+                    seen_advertising = Some(advertising);
+                }
+            }
+            let watch_link = (
+                status::link(),
+                media::latency(),
+                liveness::stats(),
+                send_queue::restart_stats(),
+                i18n::active(),
+            );
+            if visible && seen_link != Some(watch_link) {
+                let text = watch_link_line(watch_link.0, watch_link.1, watch_link.2, watch_link.3);
+                slint_ui::with_app(|app| app.set_watch_link(SharedString::from(text)));
+                seen_link = Some(watch_link);
+            }
+            let ring_phase = ring::phase();
+            if visible && seen_ring != Some(ring_phase) {
+                show_ring_phase(ring_phase);
+                seen_ring = Some(ring_phase);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+fn show_ring_phase(phase: RingPhase) {
+    slint_ui::with_app(|app| {
+        app.set_ring_busy(phase == RingPhase::Sending);
+        app.set_ring_active(phase == RingPhase::Ringing);
+    });
+}
+
+/// The button is disabled while a command is out, so presses cannot pile
+/// up; the worker serializes anything arriving from the console or HTTP.
+async fn ring_watch(ring: bool) {
+    show_ring_phase(RingPhase::Sending);
+    let result = ring::request(ring).await;
+    show_ring_phase(ring::phase());
+    if let Err(err) = result {
+        toast::show(ring_error_text(&err));
+    }
+}
+
+fn ring_error_text(err: &RingError) -> String {
+    match err {
+        RingError::NotConnected => i18n::tr("Watch not connected").to_string(),
+        RingError::Unsupported => i18n::tr("This watch cannot ring").to_string(),
+        RingError::Timeout => i18n::tr("Watch did not answer").to_string(),
+        RingError::Unavailable | RingError::Failed(_) => i18n::trf("Ring failed: {}", &[err]),
+    }
+}
+
+/// Media command round trips double as a latency probe for the link.
+/// Stall recoveries and send worker restarts only show up once there has
+/// been one.
+fn watch_link_line(
+    watch_link: Option<status::WatchLink>,
+    latency: Option<media::Latency>,
+    stalls: liveness::StallStats,
+    workers: send_queue::RestartStats,
+) -> String {
+    let mut line = match watch_link {
+        Some(watch_link) => i18n::trf(
+            "Watch link: {}",
+            &[&link::describe(watch_link.phy, watch_link.interval_ms)],
+        ),
+        None => return i18n::tr("Watch link: down").to_string(),
+    };
+    if let Some(latency) = latency {
+        line = format!(
+            "{line} · {}",
+            i18n::trf(
+                "RTT {} ms (avg {})",
+                &[&latency.last.as_millis(), &latency.average.as_millis()]
+            )
+        );
+    }
+    if stalls.recovered > 0 || stalls.disconnects > 0 {
+        line = format!(
+            "{line} · {}",
+            i18n::trf(
+                "Link stalls recovered: {} ({} dropped)",
+                &[&stalls.recovered, &stalls.disconnects]
+            )
+        );
+    }
+    if workers.restarts > 0 || workers.escalations > 0 {
+        line = format!(
+            "{line} · {}",
+            i18n::trf(
+                "Send worker restarts: {} ({} dropped)",
+                &[&workers.restarts, &workers.escalations]
+            )
+        );
+    }
+    line
+}
+
+#[cfg(feature = "ancs")]
+fn refresh() {
+    let known = clients::known_clients().len() as i32;
+    let entries: Vec<ClientEntry> = slint_ui::with_app(|app| {
+        app.set_known_clients(known);
+        clients::connected()
+            .iter()
+            .map(|client| ClientEntry {
+                name: SharedString::from(client.label()),
+                name_scroll: marquee::measure(app, Line::ClientName, client.label()),
+                addr: SharedString::from(client.addr.as_str()),
+                encrypted: client.encrypted,
+                link: SharedString::from(link::describe(client.phy, client.interval_ms)),
+            })
+            .collect()
+    })
+    .unwrap_or_default();
+    let longest = entries
+        .iter()
+        .map(|entry| entry.name_scroll)
+        .fold(0.0, f32::max);
+
+    marquee::note(Line::ClientName, longest);
+    CLIENT_MODEL.with(|model| model.set_vec(entries));
+}
+
+/// Why the test button is greyed out, or `None` when a send would reach a
+/// watch.
+#[cfg(feature = "ancs")]
+fn test_blocked_reason() -> Option<&'static str> {
+    let sessions = clients::connected();
+    if sessions.is_empty() {
+        Some(i18n::tr("No ANCS client connected"))
+    } else if !sessions.iter().any(|client| client.encrypted) {
+        Some(i18n::tr("Link not encrypted yet"))
+    } else if !ancs::encrypted_subscriber() {
+        Some(i18n::tr("Watch has not subscribed"))
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "ancs")]
+fn send_test(burst_index: Option<u32>) {
+    let seq = TEST_SEQ.with(|seq| {
+        seq.set(seq.get().wrapping_add(1));
+        seq.get()
+    });
+    let published = ancs::publish_notification(ancs::test_notification(seq));
+    log::info!(
+        "Test notification #{seq}: uid={} {}",
+        published.uid,
+        published.delivery.label()
+    );
+    let outcome = i18n::trf(
+        "Test #{}: UID {}, {}",
+        &[&seq, &published.uid, &i18n::tr(published.delivery.label())],
+    );
+    toast::show(match burst_index {
+        Some(index) => format!("{index}/{BURST_COUNT} {outcome}"),
+        None => outcome,
+    });
+}
+
+/// Long-press variant: rapid arrivals exercise the data-source queue and the
+/// watch's notification rendering.
+#[cfg(feature = "ancs")]
+async fn send_burst() {
+    for index in 1..=BURST_COUNT {
+        send_test(Some(index));
+        if index < BURST_COUNT {
+            tokio::time::sleep(BURST_SPACING).await;
+        }
+    }
+}
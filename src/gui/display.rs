@@ -1,32 +1,469 @@
-use anyhow::{anyhow, Result};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicI8, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
 use esp_idf_svc::hal::{
     delay::Delay,
-    gpio::{Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, PinDriver},
+    gpio::{AnyIOPin, AnyOutputPin, PinDriver},
     ledc::{config::TimerConfig, LedcDriver, LedcTimerDriver, LEDC},
     spi::{config::DriverConfig, Dma, SpiConfig, SpiDeviceDriver, SpiDriver, SPI2},
 };
+use log::{error, info, warn};
 use mipidsi::{
     interface::SpiInterface,
     models::GC9A01,
-    options::{ColorInversion, ColorOrder, Orientation, RefreshOrder},
+    options::{
+        ColorInversion, ColorOrder, HorizontalRefreshOrder, Orientation, RefreshOrder, Rotation,
+        VerticalRefreshOrder,
+    },
     Builder,
 };
 
-type DisplayDcPin<'d> = PinDriver<'d, Gpio4, esp_idf_svc::hal::gpio::Output>;
-type DisplayRstPin<'d> = PinDriver<'d, Gpio3, esp_idf_svc::hal::gpio::Output>;
+use super::{backlight, pixels, slint_ui};
+use crate::{
+    board::{self, PanelKind, PixelFixup},
+    boot,
+    sensors::imu::{self, ScreenRotation},
+    settings::{self, SettingKey},
+};
+
+type DisplayDcPin<'d> = PinDriver<'d, AnyOutputPin, esp_idf_svc::hal::gpio::Output>;
+type DisplayRstPin<'d> = PinDriver<'d, AnyOutputPin, esp_idf_svc::hal::gpio::Output>;
 type DisplayInterface<'d> = SpiInterface<'d, SpiDeviceDriver<'d, SpiDriver<'d>>, DisplayDcPin<'d>>;
 pub type DisplayType<'d> = mipidsi::Display<DisplayInterface<'d>, GC9A01, DisplayRstPin<'d>>;
 
 const DISPLAY_SPI_BUFFER_SIZE: usize = 1024;
 static mut DISPLAY_SPI_BUFFER: [u8; DISPLAY_SPI_BUFFER_SIZE] = [0; DISPLAY_SPI_BUFFER_SIZE];
 
+/// Recovery attempts in a row that may fail to produce a good frame before
+/// the display is declared dead.
+const MAX_CONSECUTIVE_RECOVERIES: u32 = 3;
+
+/// Panel SPI clock in kHz; 0 uses the board manifest's `spi_khz`.
+pub const SPI_CLOCK_KHZ: SettingKey<u32> = SettingKey::new("disp_spi_khz", "0");
+/// Set when the fallback below lowered [`SPI_CLOCK_KHZ`], so later boots can
+/// report it.
+const SPI_CLOCK_LOWERED: SettingKey<bool> = SettingKey::new("disp_spi_auto", "false");
+/// Notches the fallback steps down through: the 80 MHz APB clock divided by
+/// 2, 3 and 4.
+const SPI_STEPS_HZ: [u32; 3] = [40_000_000, 26_666_667, 20_000_000];
+/// More recoveries than this within [`DOWNGRADE_WINDOW`] lower the clock.
+const DOWNGRADE_AFTER: usize = 2;
+const DOWNGRADE_WINDOW: Duration = Duration::from_secs(60);
+const MAX_SPI_HZ: u32 = 80_000_000;
+/// RGB565 bits in one full frame.
+const FRAME_BITS: u64 = 240 * 240 * 16;
+
+static RECOVERIES: AtomicU32 = AtomicU32::new(0);
+static SPI_HZ: AtomicU32 = AtomicU32::new(0);
+/// Set by `display spiclock`; the render loop applies it before its next frame.
+static REQUESTED_SPI_HZ: AtomicU32 = AtomicU32::new(0);
+/// The rate in effect before the fallback lowered it, this boot or earlier.
+static LOWERED_FROM_HZ: AtomicU32 = AtomicU32::new(0);
+static FAILED: AtomicBool = AtomicBool::new(false);
+/// Set by `display colorbars`; the render loop draws the pattern next.
+static COLORBARS_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// How long the colour bars stay up before the UI comes back.
+const COLORBARS_HOLD: Duration = Duration::from_secs(20);
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+/// Where the UI is drawn on the panel relative to its layout; see
+/// [`set_panel_offset`].
+static PANEL_DX: AtomicI8 = AtomicI8::new(0);
+static PANEL_DY: AtomicI8 = AtomicI8::new(0);
+
+/// An SPI/DMA transfer to the panel failed. Anything else coming out of the
+/// render path is a software fault that recovery cannot fix.
+#[derive(Debug)]
+pub struct TransportError(pub String);
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "display transfer failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+#[derive(Clone, Debug)]
+pub struct DisplayHealth {
+    pub recoveries: u32,
+    pub failed: bool,
+    pub last_error: Option<String>,
+    pub spi_hz: u32,
+    /// Set once transfer errors made the fallback lower the clock.
+    pub spi_lowered_from_hz: Option<u32>,
+}
+
+pub fn health() -> DisplayHealth {
+    DisplayHealth {
+        recoveries: RECOVERIES.load(Ordering::Relaxed),
+        failed: FAILED.load(Ordering::Relaxed),
+        last_error: LAST_ERROR.lock().ok().and_then(|slot| slot.clone()),
+        spi_hz: spi_hz(),
+        spi_lowered_from_hz: Some(LOWERED_FROM_HZ.load(Ordering::Relaxed)).filter(|hz| *hz > 0),
+    }
+}
+
+/// Moves the UI `dx` pixels right and `dy` down on the panel, for
+/// `pixel_shift`. The next frame is drawn in full at the new offset and
+/// touch points are moved back by the same amount; what falls off the
+/// panel is clipped by mipidsi.
+pub fn set_panel_offset(dx: i8, dy: i8) {
+    PANEL_DX.store(dx, Ordering::Relaxed);
+    PANEL_DY.store(dy, Ordering::Relaxed);
+}
+
+pub fn panel_offset() -> (i8, i8) {
+    (
+        PANEL_DX.load(Ordering::Relaxed),
+        PANEL_DY.load(Ordering::Relaxed),
+    )
+}
+
+/// The clock the panel is driven at.
+pub fn spi_hz() -> u32 {
+    SPI_HZ.load(Ordering::Relaxed)
+}
+
+/// Shortest time one full frame can take on the wire at the current clock,
+/// which caps the frame rate whatever the renderer does.
+pub fn full_frame_time() -> Option<Duration> {
+    let hz = u64::from(spi_hz());
+    (hz > 0).then(|| Duration::from_micros(FRAME_BITS * 1_000_000 / hz))
+}
+
+fn configured_spi_hz() -> u32 {
+    match settings::get(&SPI_CLOCK_KHZ) {
+        0 => board::CURRENT.display_spi_hz,
+        khz => khz.saturating_mul(1_000).min(MAX_SPI_HZ),
+    }
+}
+
+/// The next notch below `hz`, if any.
+fn lower_step(hz: u32) -> Option<u32> {
+    SPI_STEPS_HZ.into_iter().find(|step| *step < hz)
+}
+
+fn mhz(hz: u32) -> f32 {
+    hz as f32 / 1_000_000.0
+}
+
+/// Panel configuration kept around so the same init sequence can be replayed
+/// after a transport fault.
+pub struct DisplayRecovery {
+    orientation: Orientation,
+    inversion: ColorInversion,
+    color_order: ColorOrder,
+    refresh_order: RefreshOrder,
+    size: (u16, u16),
+    offset: (u16, u16),
+}
+
+impl DisplayRecovery {
+    pub const GC9A01: DisplayRecovery = DisplayRecovery {
+        orientation: Orientation {
+            rotation: Rotation::Deg0,
+            mirrored: false,
+        },
+        inversion: ColorInversion::Inverted,
+        color_order: ColorOrder::Rgb,
+        refresh_order: RefreshOrder {
+            vertical: VerticalRefreshOrder::TopToBottom,
+            horizontal: HorizontalRefreshOrder::LeftToRight,
+        },
+        size: (240, 240),
+        offset: (0, 0),
+    };
+
+    pub fn for_panel(panel: PanelKind) -> &'static DisplayRecovery {
+        match panel {
+            PanelKind::Gc9a01 => &Self::GC9A01,
+        }
+    }
+
+    /// The panel's own orientation turned by the auto-rotation.
+    fn orientation(&self, rotation: ScreenRotation) -> Orientation {
+        self.orientation.rotate(match rotation {
+            ScreenRotation::Deg0 => Rotation::Deg0,
+            ScreenRotation::Deg90 => Rotation::Deg90,
+            ScreenRotation::Deg180 => Rotation::Deg180,
+            ScreenRotation::Deg270 => Rotation::Deg270,
+        })
+    }
+
+    /// Hardware reset through `rst`, then the full mipidsi init sequence,
+    /// which also reloads the panel's gamma tables.
+    fn init(
+        &self,
+        di: DisplayInterface<'static>,
+        rst: DisplayRstPin<'static>,
+    ) -> Result<DisplayType<'static>> {
+        let mut delay = Delay::new_default();
+        Builder::new(GC9A01, di)
+            .reset_pin(rst)
+            .invert_colors(self.inversion)
+            .color_order(self.color_order)
+            .orientation(self.orientation(imu::rotation()))
+            .refresh_order(self.refresh_order)
+            .display_size(self.size.0, self.size.1)
+            .display_offset(self.offset.0, self.offset.1)
+            .init(&mut delay)
+            .map_err(|e| anyhow!("display init failed: {:?}", e))
+    }
+
+    /// Tears the driver down to its parts and initializes it again, on a
+    /// freshly opened SPI bus when `spi_hz` is given. The parts are consumed
+    /// either way, so a failure here is final.
+    pub fn reinit(
+        &self,
+        display: DisplayType<'static>,
+        spi_hz: Option<u32>,
+    ) -> Result<DisplayType<'static>> {
+        let (di, _model, rst) = display.release();
+        let rst = rst.ok_or_else(|| anyhow!("display has no reset pin"))?;
+        let di = match spi_hz {
+            Some(hz) => {
+                let (spi_dev, dc) = di.release();
+                // Frees the bus and its pins before they are claimed again.
+                drop(spi_dev);
+                let gpios = board::CURRENT.display;
+                let spi_dev = open_spi(
+                    // Safety: the only other SPI2 handle was just dropped.
+                    unsafe { SPI2::new() },
+                    board::output_pin(gpios.sclk),
+                    board::output_pin(gpios.mosi),
+                    board::output_pin(gpios.cs),
+                    hz,
+                )?;
+                SpiInterface::new(spi_dev, dc, spi_buffer())
+            }
+            None => di,
+        };
+        self.init(di, rst)
+    }
+}
+
+/// Owns the panel for the render loop and turns transport faults into
+/// retries and reinitialization instead of a dead screen.
+pub struct RenderSupervisor {
+    display: Option<DisplayType<'static>>,
+    recovery: &'static DisplayRecovery,
+    consecutive: u32,
+    /// Backlight stays off after a reinit until a full frame has landed.
+    blanked: bool,
+    /// Recent recoveries, for the clock fallback.
+    recent: VecDeque<Instant>,
+    /// The colour bars are up until then; the UI does not render.
+    colorbars_until: Option<Instant>,
+}
+
+impl RenderSupervisor {
+    pub fn new(display: DisplayType<'static>) -> Self {
+        Self {
+            display: Some(display),
+            recovery: DisplayRecovery::for_panel(board::CURRENT.panel),
+            consecutive: 0,
+            blanked: false,
+            recent: VecDeque::new(),
+            colorbars_until: None,
+        }
+    }
+
+    /// Renders one frame. Only non-display errors are returned; once the
+    /// display has failed this does nothing.
+    pub fn frame(&mut self) -> Result<()> {
+        let requested = REQUESTED_SPI_HZ.swap(0, Ordering::Relaxed);
+        if requested > 0 && requested != spi_hz() {
+            self.change_clock(requested);
+        }
+        let Some(display) = self.display.as_mut() else {
+            return Ok(());
+        };
+        if COLORBARS_REQUESTED.swap(false, Ordering::Relaxed) {
+            match pixels::draw_colorbars(display) {
+                Ok(()) => self.colorbars_until = Some(Instant::now() + COLORBARS_HOLD),
+                Err(err) => {
+                    warn!("Colour bars failed: {err:#}");
+                    slint_ui::force_full_redraw();
+                }
+            }
+        }
+        if let Some(until) = self.colorbars_until {
+            if Instant::now() < until {
+                return Ok(());
+            }
+            self.colorbars_until = None;
+            slint_ui::force_full_redraw();
+        }
+        let mut failure = None;
+        for attempt in 0..2 {
+            match slint_ui::render_hello_world(display) {
+                Ok(()) => {
+                    self.consecutive = 0;
+                    if self.blanked {
+                        self.blanked = false;
+                        backlight::set_blanked(false);
+                    }
+                    return Ok(());
+                }
+                Err(err) if !is_transport(&err) => return Err(err),
+                Err(err) => {
+                    if attempt == 0 {
+                        warn!("Display frame failed, retrying: {err:#}");
+                        slint_ui::force_full_redraw();
+                    }
+                    failure = Some(err);
+                }
+            }
+        }
+        if let Some(err) = failure {
+            self.recover(err);
+        }
+        Ok(())
+    }
+
+    /// Turns the panel for auto-rotation. The next frame is drawn in full;
+    /// a reinit later picks the rotation up from `sensors::imu`.
+    pub fn set_rotation(&mut self, rotation: ScreenRotation) {
+        let Some(display) = self.display.as_mut() else {
+            return;
+        };
+        if let Err(err) = display.set_orientation(self.recovery.orientation(rotation)) {
+            warn!("Display rotation to {rotation} failed: {err:?}");
+        }
+        slint_ui::force_full_redraw();
+    }
+
+    fn recover(&mut self, cause: anyhow::Error) {
+        record_error(&cause);
+        self.consecutive += 1;
+        if self.consecutive > MAX_CONSECUTIVE_RECOVERIES {
+            self.give_up(format!(
+                "{MAX_CONSECUTIVE_RECOVERIES} recoveries in a row did not help"
+            ));
+            return;
+        }
+        let Some(display) = self.display.take() else {
+            return;
+        };
+
+        warn!("Display recovery {} after: {cause:#}", self.consecutive);
+        let now = Instant::now();
+        self.recent
+            .retain(|at| now.duration_since(*at) < DOWNGRADE_WINDOW);
+        self.recent.push_back(now);
+        let lower = (self.recent.len() > DOWNGRADE_AFTER)
+            .then(|| lower_step(spi_hz()))
+            .flatten();
+        // The panel shows garbage between reset and the first full frame.
+        self.blanked = true;
+        backlight::set_blanked(true);
+        match self.recovery.reinit(display, lower) {
+            Ok(display) => {
+                self.display = Some(display);
+                let total = RECOVERIES.fetch_add(1, Ordering::Relaxed) + 1;
+                info!("Display reinitialized ({total} recoveries since boot)");
+                if let Some(hz) = lower {
+                    self.recent.clear();
+                    lowered(spi_hz(), hz);
+                }
+                slint_ui::force_full_redraw();
+            }
+            Err(err) => {
+                record_error(&err);
+                self.give_up(format!("reinit failed: {err:#}"));
+            }
+        }
+    }
+
+    fn change_clock(&mut self, hz: u32) {
+        let Some(display) = self.display.take() else {
+            return;
+        };
+        self.blanked = true;
+        backlight::set_blanked(true);
+        match self.recovery.reinit(display, Some(hz)) {
+            Ok(display) => {
+                self.display = Some(display);
+                SPI_HZ.store(hz, Ordering::Relaxed);
+                info!("Display SPI clock now {:.2} MHz", mhz(hz));
+                slint_ui::force_full_redraw();
+            }
+            Err(err) => {
+                record_error(&err);
+                self.give_up(format!("SPI clock change failed: {err:#}"));
+            }
+        }
+    }
+
+    fn give_up(&mut self, reason: String) {
+        error!("Display failed, running headless: {reason}");
+        self.display = None;
+        FAILED.store(true, Ordering::Relaxed);
+        backlight::set_blanked(true);
+    }
+}
+
+/// Persists the lowered clock so the next boot starts from it.
+fn lowered(from: u32, to: u32) {
+    SPI_HZ.store(to, Ordering::Relaxed);
+    if LOWERED_FROM_HZ.load(Ordering::Relaxed) == 0 {
+        LOWERED_FROM_HZ.store(from, Ordering::Relaxed);
+    }
+    let note = format!(
+        "display SPI clock lowered from {:.2} to {:.2} MHz after repeated transfer errors",
+        mhz(from),
+        mhz(to)
+    );
+    warn!("{note}");
+    boot::note(note.clone());
+    crate::journal!("{note}");
+    if let Err(err) = settings::set(&SPI_CLOCK_KHZ, &(to / 1_000)) {
+        warn!("Lowered display clock not saved: {err:#}");
+    }
+    if let Err(err) = settings::set(&SPI_CLOCK_LOWERED, &true) {
+        warn!("Lowered display clock flag not saved: {err:#}");
+    }
+}
+
+fn is_transport(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<TransportError>().is_some()
+}
+
+fn record_error(err: &anyhow::Error) {
+    if let Ok(mut slot) = LAST_ERROR.lock() {
+        *slot = Some(format!("{err:#}"));
+    }
+}
+
 pub struct DisplayPins {
-    pub backlight: Gpio2,
-    pub rst: Gpio3,
-    pub dc: Gpio4,
-    pub cs: Gpio5,
-    pub mosi: Gpio6,
-    pub sclk: Gpio7,
+    pub backlight: AnyOutputPin,
+    pub rst: AnyOutputPin,
+    pub dc: AnyOutputPin,
+    pub cs: AnyOutputPin,
+    pub mosi: AnyOutputPin,
+    pub sclk: AnyOutputPin,
+}
+
+impl DisplayPins {
+    pub fn from_board() -> Self {
+        let gpios = board::CURRENT.display;
+        Self {
+            backlight: board::output_pin(gpios.backlight),
+            rst: board::output_pin(gpios.rst),
+            dc: board::output_pin(gpios.dc),
+            cs: board::output_pin(gpios.cs),
+            mosi: board::output_pin(gpios.mosi),
+            sclk: board::output_pin(gpios.sclk),
+        }
+    }
 }
 
 pub fn init_display_gc9a01(
@@ -53,40 +490,127 @@ pub fn init_display_gc9a01(
     let mut backlight = LedcDriver::new(channel0, ledc_timer, backlight)?;
     backlight.set_duty(backlight.get_max_duty() / 2)?;
 
+    let spi_hz = configured_spi_hz();
+    let spi_dev = open_spi(spi2, sclk, mosi, cs, spi_hz)?;
+    SPI_HZ.store(spi_hz, Ordering::Relaxed);
+    if settings::get(&SPI_CLOCK_LOWERED) && spi_hz < board::CURRENT.display_spi_hz {
+        LOWERED_FROM_HZ.store(board::CURRENT.display_spi_hz, Ordering::Relaxed);
+        boot::note(format!(
+            "display SPI clock at {:.2} MHz, lowered from the board's {:.2} MHz by an earlier fallback",
+            mhz(spi_hz),
+            mhz(board::CURRENT.display_spi_hz)
+        ));
+    }
+    info!("Display SPI clock {:.2} MHz", mhz(spi_hz));
+    let di = SpiInterface::new(spi_dev, dc, spi_buffer());
+
+    let display = DisplayRecovery::for_panel(board::CURRENT.panel).init(di, rst)?;
+
+    Ok((display, backlight))
+}
+
+fn open_spi(
+    spi2: SPI2,
+    sclk: AnyOutputPin,
+    mosi: AnyOutputPin,
+    cs: AnyOutputPin,
+    hz: u32,
+) -> Result<SpiDeviceDriver<'static, SpiDriver<'static>>> {
     let spi_driver = SpiDriver::new(
         spi2,
         sclk, // SCLK
         mosi, // MOSI (SDO)
-        Option::<esp_idf_svc::hal::gpio::Gpio8>::None,
+        Option::<AnyIOPin>::None,
         &DriverConfig {
             dma: Dma::Auto(DISPLAY_SPI_BUFFER_SIZE),
             ..Default::default()
         },
     )?;
-    let spi_dev = SpiDeviceDriver::new(
+    Ok(SpiDeviceDriver::new(
         spi_driver,
         Some(cs), // CS
-        &SpiConfig::new().baudrate(40_000_000.into()),
-    )?;
+        &SpiConfig::new().baudrate(hz.into()),
+    )?)
+}
 
+/// Only one `SpiInterface` exists at a time; a new one is made after the
+/// previous one has been released.
+fn spi_buffer() -> &'static mut [u8] {
     #[allow(static_mut_refs)]
-    let buffer: &'static mut [u8] = unsafe { &mut DISPLAY_SPI_BUFFER };
-    let di = SpiInterface::new(spi_dev, dc, buffer);
-
-    let mut delay = Delay::new_default();
-    let display = Builder::new(GC9A01, di)
-        .reset_pin(rst)
-        .invert_colors(ColorInversion::Inverted)
-        .color_order(ColorOrder::Rgb)
-        .orientation(Orientation::new().rotate(mipidsi::options::Rotation::Deg0))
-        .refresh_order(RefreshOrder::new(
-            mipidsi::options::VerticalRefreshOrder::TopToBottom,
-            mipidsi::options::HorizontalRefreshOrder::LeftToRight,
-        ))
-        .display_size(240, 240)
-        .display_offset(0, 0)
-        .init(&mut delay)
-        .map_err(|e| anyhow!("display init failed: {:?}", e))?;
+    unsafe {
+        &mut DISPLAY_SPI_BUFFER
+    }
+}
 
-    Ok((display, backlight))
+pub fn register_commands() {
+    crate::console::register(
+        "display",
+        "spiclock [<mhz>]: show or set the panel SPI clock; cache: page frame cache; intervals: frame interval histogram; colorbars: pixel fixup test pattern",
+        |args| match args {
+            ["spiclock"] => {
+                let health = health();
+                let lowered = health
+                    .spi_lowered_from_hz
+                    .map(|hz| format!(", lowered from {:.2} MHz", mhz(hz)))
+                    .unwrap_or_default();
+                Ok(format!(
+                    "SPI clock {:.2} MHz (board {:.2} MHz{lowered})",
+                    mhz(health.spi_hz),
+                    mhz(board::CURRENT.display_spi_hz)
+                ))
+            }
+            ["spiclock", raw] => {
+                let mhz_value: f32 = raw.parse().map_err(|_| anyhow!("not a number: {raw}"))?;
+                let hz = (mhz_value * 1_000_000.0).round() as u32;
+                if hz == 0 || hz > MAX_SPI_HZ {
+                    bail!("clock must be within 0-{} MHz", MAX_SPI_HZ / 1_000_000);
+                }
+                settings::set(&SPI_CLOCK_KHZ, &(hz / 1_000))?;
+                settings::set(&SPI_CLOCK_LOWERED, &false)?;
+                REQUESTED_SPI_HZ.store(hz, Ordering::Relaxed);
+                Ok(format!("SPI clock {:.2} MHz from the next frame", mhz(hz)))
+            }
+            ["cache"] => Ok(format!(
+                "{}; {} lines flushed",
+                super::frame_cache::describe(),
+                slint_ui::lines_flushed()
+            )),
+            ["intervals"] => {
+                let buckets = slint_ui::frame_intervals();
+                if buckets.is_empty() {
+                    return Ok("no frame intervals yet".to_string());
+                }
+                let lines: Vec<String> = buckets
+                    .into_iter()
+                    .map(|(ms, count)| {
+                        let plus = if ms == slint_ui::FRAME_INTERVAL_MAX_MS {
+                            "+"
+                        } else {
+                            ""
+                        };
+                        format!("{ms:>3}{plus} ms: {count}")
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            ["colorbars"] => {
+                COLORBARS_REQUESTED.store(true, Ordering::Relaxed);
+                let bands: Vec<String> = PixelFixup::ALL
+                    .iter()
+                    .enumerate()
+                    .map(|(index, fixup)| format!("{}: {fixup}", index + 1))
+                    .collect();
+                Ok(format!(
+                    "colour bars for {} s, bands top to bottom {}. The right band shows red, \
+                     green and blue under R, G, B and a smooth grey ramp; put its swap_bytes \
+                     and swap_rb under [display] in boards/{}.toml. This build uses {}.",
+                    COLORBARS_HOLD.as_secs(),
+                    bands.join(", "),
+                    board::CURRENT.id,
+                    board::CURRENT.pixel_fixup
+                ))
+            }
+            _ => bail!("usage: display spiclock [<mhz>] | cache | intervals | colorbars"),
+        },
+    );
 }
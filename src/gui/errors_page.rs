@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use slint::SharedString;
+
+use super::{
+    bounded_model::BoundedModel,
+    slint_ui::{self, App, ErrorEntry, Page},
+    toast,
+};
+use crate::{
+    errors::{self, Severity, UserFacingError},
+    i18n, statlogger,
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the open page redraws just to age its rows.
+const AGE_REFRESH: Duration = Duration::from_secs(30);
+
+thread_local! {
+    static ERROR_MODEL: BoundedModel<ErrorEntry> = BoundedModel::new("errors", errors::CAPACITY);
+}
+
+pub fn install(app: &App) {
+    app.set_errors(ERROR_MODEL.with(BoundedModel::model));
+
+    app.on_error_retry(|subsystem| {
+        if errors::retry(&subsystem) {
+            toast::show(i18n::trf("Retrying {}", &[&title(&subsystem)]));
+        }
+    });
+    app.on_errors_clear(errors::clear_resolved);
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        let mut shown_at = Instant::now();
+        loop {
+            let current = (errors::generation(), i18n::active());
+            let visible = slint_ui::current_page() == Some(Page::Errors);
+            if seen != Some(current) || (visible && shown_at.elapsed() >= AGE_REFRESH) {
+                show(&errors::snapshot());
+                seen = Some(current);
+                shown_at = Instant::now();
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+/// Newest first, since that is what the user came to see.
+fn show(journal: &[UserFacingError]) {
+    let now = statlogger::uptime();
+    let unresolved = journal.iter().filter(|entry| !entry.resolved).count();
+    let summary = if unresolved == 0 {
+        i18n::tr("No open errors").to_string()
+    } else {
+        i18n::trf("{} open", &[&unresolved])
+    };
+    let entries: Vec<ErrorEntry> = journal
+        .iter()
+        .rev()
+        .map(|entry| ErrorEntry {
+            subsystem: SharedString::from(entry.subsystem),
+            title: SharedString::from(title(entry.subsystem)),
+            message: SharedString::from(entry.message.as_str()),
+            detail: SharedString::from(detail(entry, now)),
+            severe: entry.severity == Severity::Error,
+            resolved: entry.resolved,
+            can_retry: errors::can_retry(entry.subsystem),
+        })
+        .collect();
+    ERROR_MODEL.with(|model| model.set_vec(entries));
+    slint_ui::with_app(|app| {
+        app.set_errors_summary(SharedString::from(summary));
+        app.set_errors_unresolved(unresolved as i32);
+    });
+}
+
+fn title(subsystem: &str) -> String {
+    match subsystem {
+        "watch" => i18n::tr("Watch").to_string(),
+        "ble" => i18n::tr("Bluetooth").to_string(),
+        "touch" => i18n::tr("Touch").to_string(),
+        "render" => i18n::tr("Display").to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn detail(entry: &UserFacingError, now: Duration) -> String {
+    let age = now.saturating_sub(entry.timestamp).as_secs();
+    let age = match age {
+        0..=59 => i18n::tr("just now").to_string(),
+        60..=3599 => i18n::trf("{} min ago", &[&(age / 60)]),
+        _ => i18n::trf("{} h ago", &[&(age / 3600)]),
+    };
+    if entry.count > 1 {
+        format!("{}x, {age}", entry.count)
+    } else {
+        age
+    }
+}
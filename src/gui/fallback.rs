@@ -0,0 +1,114 @@
+//! Error screen for when the Slint `App` cannot be created. Drawn straight
+//! onto the panel with embedded-graphics and driven by the touch task, so it
+//! works with no Slint state at all.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::*,
+    text::{Alignment, Text},
+};
+use log::warn;
+
+use super::display::{DisplayType, TransportError};
+use crate::statlogger;
+
+const HOLD_TO_REBOOT: Duration = Duration::from_secs(3);
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+/// Characters per line that stay inside the round panel around mid-height.
+const WRAP_COLUMNS: usize = 30;
+const MAX_ERROR_LINES: usize = 4;
+const LINE_HEIGHT: i32 = 12;
+const CENTER_X: i32 = 120;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// When the current touch went down, while the fallback is showing.
+static HOLD_SINCE: Mutex<Option<Instant>> = Mutex::new(None);
+static LAST_DRAW: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+pub fn set_active(active: bool) {
+    ACTIVE.store(active, Ordering::Relaxed);
+    if !active {
+        if let Ok(mut last) = LAST_DRAW.lock() {
+            *last = None;
+        }
+    }
+}
+
+/// Feeds touch state from the touch task; reboots after a long hold.
+pub fn on_touch(pressed: bool, now: Instant) {
+    let Ok(mut since) = HOLD_SINCE.lock() else {
+        return;
+    };
+    if !pressed {
+        *since = None;
+        return;
+    }
+    let started = *since.get_or_insert(now);
+    if now.saturating_duration_since(started) >= HOLD_TO_REBOOT {
+        warn!("Rebooting from the UI fallback screen");
+        unsafe { esp_idf_svc::sys::esp_restart() };
+    }
+}
+
+/// Draws the error screen, at most once per second so the heap numbers
+/// stay current without flooding the SPI bus.
+pub fn draw(display: &mut DisplayType<'static>, error: &str, attempts: u32) -> Result<()> {
+    {
+        let Ok(mut last) = LAST_DRAW.lock() else {
+            return Ok(());
+        };
+        if last.is_some_and(|at| at.elapsed() < REDRAW_INTERVAL) {
+            return Ok(());
+        }
+        *last = Some(Instant::now());
+    }
+
+    let heap = statlogger::heap_snapshot();
+    let title = MonoTextStyle::new(&FONT_6X10, Rgb565::RED);
+    let body = MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE);
+    let hint = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_LIGHT_GRAY);
+
+    let mut lines: Vec<(String, MonoTextStyle<'_, Rgb565>)> = vec![
+        ("UI failed to start".to_string(), title),
+        (String::new(), body),
+    ];
+    let chars: Vec<char> = error.chars().collect();
+    for chunk in chars.chunks(WRAP_COLUMNS).take(MAX_ERROR_LINES) {
+        lines.push((chunk.iter().collect(), body));
+    }
+    lines.push((String::new(), body));
+    lines.push((format!("internal {} KB free", heap.internal / 1024), body));
+    lines.push((format!("psram {} KB free", heap.psram / 1024), body));
+    lines.push((format!("retry #{attempts} every 10 s"), hint));
+    lines.push((String::new(), body));
+    lines.push(("Hold screen 3 s to reboot".to_string(), hint));
+
+    let top = 120 - lines.len() as i32 * LINE_HEIGHT / 2;
+    display
+        .clear(Rgb565::BLACK)
+        .map_err(|e| TransportError(format!("fallback clear: {e:?}")))?;
+    for (index, (text, style)) in lines.iter().enumerate() {
+        if text.is_empty() {
+            continue;
+        }
+        let y = top + index as i32 * LINE_HEIGHT;
+        Text::with_alignment(text, Point::new(CENTER_X, y), *style, Alignment::Center)
+            .draw(display)
+            .map_err(|e| TransportError(format!("fallback text: {e:?}")))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Static frame shown while rebooting into USB download mode. Drawn once
+//! with embedded-graphics; the ROM loader leaves the panel untouched, so it
+//! stays up until the next power cycle.
+
+use std::cell::Cell;
+
+use anyhow::Result;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::*,
+    text::{Alignment, Text},
+};
+
+use super::display::{DisplayType, TransportError};
+use crate::power::download_mode;
+
+const LINE_HEIGHT: i32 = 14;
+const CENTER_X: i32 = 120;
+
+thread_local! {
+    static DRAWN: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn draw(display: &mut DisplayType<'static>) -> Result<()> {
+    if DRAWN.with(Cell::get) {
+        return Ok(());
+    }
+    let title = MonoTextStyle::new(&FONT_6X10, Rgb565::YELLOW);
+    let body = MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE);
+    let hint = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_LIGHT_GRAY);
+    let lines = [
+        ("FLASHING MODE", title),
+        ("", body),
+        ("USB download mode", body),
+        ("Reconnect USB and flash", body),
+        ("", body),
+        ("Power cycle to boot normally", hint),
+    ];
+
+    let top = 120 - lines.len() as i32 * LINE_HEIGHT / 2;
+    display
+        .clear(Rgb565::BLACK)
+        .map_err(|e| TransportError(format!("flashing clear: {e:?}")))?;
+    for (index, (text, style)) in lines.iter().enumerate() {
+        if text.is_empty() {
+            continue;
+        }
+        let y = top + index as i32 * LINE_HEIGHT;
+        Text::with_alignment(text, Point::new(CENTER_X, y), *style, Alignment::Center)
+            .draw(display)
+            .map_err(|e| TransportError(format!("flashing text: {e:?}")))?;
+    }
+    DRAWN.with(|drawn| drawn.set(true));
+    download_mode::mark_frame_shown();
+    Ok(())
+}
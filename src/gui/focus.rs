@@ -0,0 +1,114 @@
+//! Focus for boards with an analog stick (see `input::analog`). A page puts
+//! a `FocusRing` on each element the stick should reach and gives their
+//! number as its descriptor's `focus_chain`. Directions walk the chain and
+//! select activates the ring under focus. A direction past either end, or
+//! any direction on a page without a chain, becomes the swipe that moves
+//! content the same way, so scrolling and carousel paging work as by touch.
+//!
+//! The ring stays hidden until the first direction. Unless the stick is the
+//! primary input, the next touch hides it again.
+
+use std::cell::Cell;
+
+use log::debug;
+
+use super::{
+    backlight, pages,
+    slint_ui::{self, App, Focus, Gesture},
+};
+use crate::input::{analog, button::ButtonPress};
+
+thread_local! {
+    static INDEX: Cell<usize> = const { Cell::new(0) };
+    static VISIBLE: Cell<bool> = const { Cell::new(false) };
+    static ACTIVATIONS: Cell<i32> = const { Cell::new(0) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+}
+
+impl Nav {
+    /// The swipe that moves content the way the stick points.
+    fn gesture(self) -> Gesture {
+        match self {
+            Nav::Up => Gesture::SwipeDown,
+            Nav::Down => Gesture::SwipeUp,
+            Nav::Left => Gesture::SwipeRight,
+            Nav::Right => Gesture::SwipeLeft,
+            Nav::Select => Gesture::Button(ButtonPress::Short),
+        }
+    }
+}
+
+/// Shows the ring from the start when the stick is the primary input.
+pub(super) fn install(app: &App) {
+    if analog::primary() {
+        VISIBLE.set(true);
+        app.global::<Focus>().set_visible(true);
+    }
+}
+
+/// A new page starts at its first element.
+pub(super) fn on_navigate(app: &App, scope: &str) {
+    INDEX.set(0);
+    let focus = app.global::<Focus>();
+    focus.set_scope(scope.into());
+    focus.set_index(0);
+}
+
+/// Called on every touch press.
+pub fn on_touch() {
+    if VISIBLE.get() && !analog::primary() {
+        VISIBLE.set(false);
+        slint_ui::with_app(|app| app.global::<Focus>().set_visible(false));
+    }
+}
+
+/// One stick event. Call on the UI thread.
+pub fn navigate(nav: Nav) {
+    if backlight::on_input() {
+        return;
+    }
+    // Dialogs have no chain; left backs out of them like the edge swipe.
+    if slint_ui::top_overlay().is_some() {
+        if nav == Nav::Left {
+            slint_ui::back();
+        }
+        return;
+    }
+    let chain = slint_ui::with_app(pages::focus_chain).unwrap_or(0);
+    if chain > 0 && !VISIBLE.get() {
+        // The first event only reveals where focus is.
+        VISIBLE.set(true);
+        slint_ui::with_app(|app| app.global::<Focus>().set_visible(true));
+        return;
+    }
+    let index = INDEX.get().min(chain.saturating_sub(1));
+    let next = match nav {
+        _ if chain == 0 => None,
+        Nav::Select => {
+            let activations = ACTIVATIONS.get().wrapping_add(1);
+            ACTIVATIONS.set(activations);
+            slint_ui::with_app(|app| app.global::<Focus>().set_activations(activations));
+            debug!("Focus: activate {index}");
+            return;
+        }
+        Nav::Up | Nav::Left => index.checked_sub(1),
+        Nav::Down | Nav::Right => Some(index + 1).filter(|next| *next < chain),
+    };
+    if let Some(next) = next {
+        INDEX.set(next);
+        slint_ui::with_app(|app| app.global::<Focus>().set_index(next as i32));
+        return;
+    }
+    INDEX.set(index);
+    if !slint_ui::dispatch_gesture(nav.gesture()) && nav == Nav::Left {
+        slint_ui::back();
+    }
+}
@@ -0,0 +1,305 @@
+//! Rendered frames of recently left pages, kept in PSRAM so a swipe back to
+//! one shows it at once instead of waiting out a full render.
+//!
+//! A shadow copy of the panel follows every flushed line. Once a page has
+//! settled (a frame with nothing to draw, no animation, nothing on top of
+//! it) the shadow is copied into that page's slot. Revisiting the page
+//! blits the slot as one full frame; the real render follows on the next
+//! frame, and because lines equal to the shadow are skipped it only flushes
+//! what changed meanwhile.
+//!
+//! Rust-side writes through `slint_ui::with_app` may touch any page, so they
+//! drop the slots of hidden pages; theme and language changes go through the
+//! same path. The cache is shed at memory pressure Warning.
+
+use std::{cell::RefCell, ops::Range};
+
+use anyhow::Result;
+use slint::platform::software_renderer::Rgb565Pixel;
+
+use super::slint_ui::{self, Page, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::{
+    allocator::{self, PsramVec},
+    settings::{self, SettingKey},
+};
+
+/// Pages kept at most, least recently used evicted first; 0 turns the
+/// cache off. Each costs one full frame of PSRAM.
+pub const CACHED_PAGES: SettingKey<u32> = SettingKey::new("ui_frame_cache", "2");
+
+const FRAME_PIXELS: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
+
+thread_local! {
+    static CACHE: RefCell<Cache> = const { RefCell::new(Cache::new()) };
+}
+
+struct Slot {
+    page: Page,
+    pixels: PsramVec<Rgb565Pixel>,
+    /// False once the page may have changed; the buffer is kept for reuse.
+    valid: bool,
+    last_used: u64,
+}
+
+struct Cache {
+    /// What the panel shows; empty while the cache is off.
+    shadow: PsramVec<Rgb565Pixel>,
+    /// The shadow matches the panel. False until a full frame went through.
+    shadow_valid: bool,
+    /// Lines matching the shadow are skipped in the current frame.
+    skipping: bool,
+    slots: Vec<Slot>,
+    page: Option<Page>,
+    clock: u64,
+    shed: bool,
+    hits: u32,
+    skipped_lines: u32,
+}
+
+impl Cache {
+    const fn new() -> Self {
+        Self {
+            shadow: PsramVec::new(),
+            shadow_valid: false,
+            skipping: false,
+            slots: Vec::new(),
+            page: None,
+            clock: 0,
+            shed: false,
+            hits: 0,
+            skipped_lines: 0,
+        }
+    }
+
+    fn active(&self) -> bool {
+        !self.shadow.is_empty()
+    }
+
+    fn off(&mut self) {
+        self.shadow = PsramVec::new();
+        self.shadow_valid = false;
+        self.skipping = false;
+        self.slots = Vec::new();
+    }
+
+    fn valid_slot(&mut self, page: Page) -> Option<&mut Slot> {
+        self.slots
+            .iter_mut()
+            .find(|slot| slot.page == page && slot.valid)
+    }
+}
+
+/// Called at the start of every frame with the page about to be drawn.
+/// On a switch to a page with a cached frame, hands that frame to `blit`
+/// and returns true; the caller then skips rendering for this frame.
+pub(super) fn on_frame_start(
+    page: Page,
+    blit: impl FnOnce(&[Rgb565Pixel]) -> Result<()>,
+) -> Result<bool> {
+    let limit = settings::get(&CACHED_PAGES) as usize;
+    CACHE.with(|cell| {
+        let mut cache = cell.borrow_mut();
+        let wanted = limit > 0 && !cache.shed && allocator::psram_available();
+        if !wanted {
+            if cache.active() {
+                cache.off();
+            }
+            cache.page = Some(page);
+            return Ok(false);
+        }
+        if !cache.active() {
+            cache.shadow = PsramVec::filled(Rgb565Pixel(0), FRAME_PIXELS);
+            // The shadow only becomes trustworthy after a full frame.
+            slint_ui::force_full_redraw();
+        }
+        evict_to(&mut cache.slots, limit);
+
+        let switched = cache.page.replace(page) != Some(page);
+        if !switched || !cache.shadow_valid {
+            return Ok(false);
+        }
+        cache.clock += 1;
+        let clock = cache.clock;
+        let Cache { shadow, slots, .. } = &mut *cache;
+        let Some(slot) = slots
+            .iter_mut()
+            .find(|slot| slot.page == page && slot.valid)
+        else {
+            return Ok(false);
+        };
+        slot.last_used = clock;
+        shadow.copy_from_slice(&slot.pixels);
+        if let Err(err) = blit(shadow) {
+            cache.shadow_valid = false;
+            return Err(err);
+        }
+        cache.hits = cache.hits.saturating_add(1);
+        Ok(true)
+    })
+}
+
+/// Called before rendering; `full` frames repaint every line.
+pub(super) fn begin_render(full: bool) {
+    CACHE.with(|cell| {
+        let mut cache = cell.borrow_mut();
+        cache.skipping = cache.active() && cache.shadow_valid && !full;
+    });
+}
+
+/// Records a rendered line in the shadow. Returns false when the panel
+/// already shows exactly these pixels and the line need not be sent.
+pub(super) fn line(line: usize, range: Range<usize>, pixels: &[Rgb565Pixel]) -> bool {
+    CACHE.with(|cell| {
+        let mut cache = cell.borrow_mut();
+        if !cache.active() || line >= DISPLAY_HEIGHT || range.end > DISPLAY_WIDTH {
+            return true;
+        }
+        let at = line * DISPLAY_WIDTH;
+        let skipping = cache.skipping;
+        let shadow = &mut cache.shadow[at + range.start..at + range.end];
+        if skipping && shadow == pixels {
+            cache.skipped_lines = cache.skipped_lines.saturating_add(1);
+            return false;
+        }
+        shadow.copy_from_slice(pixels);
+        true
+    })
+}
+
+/// Called after the frame. `rendered` counts lines the renderer produced;
+/// `settled` says nothing covers the page and nothing is animating.
+pub(super) fn end_render(ok: bool, full: bool, rendered: usize, settled: bool) {
+    CACHE.with(|cell| {
+        let mut cache = cell.borrow_mut();
+        if !cache.active() {
+            return;
+        }
+        if !ok {
+            cache.shadow_valid = false;
+            return;
+        }
+        if full {
+            cache.shadow_valid = true;
+        }
+        let Some(page) = cache.page else {
+            return;
+        };
+        if rendered > 0 {
+            if let Some(slot) = cache.valid_slot(page) {
+                slot.valid = false;
+            }
+            return;
+        }
+        if settled && cache.shadow_valid && cache.valid_slot(page).is_none() {
+            capture(&mut cache, page);
+        }
+    });
+}
+
+/// Drops the slots of every page but the one on screen.
+pub fn invalidate_hidden() {
+    CACHE.with(|cell| {
+        let mut cache = cell.borrow_mut();
+        let current = cache.page;
+        for slot in &mut cache.slots {
+            if Some(slot.page) != current {
+                slot.valid = false;
+            }
+        }
+    });
+}
+
+pub fn invalidate(page: Page) {
+    CACHE.with(|cell| {
+        if let Some(slot) = cell.borrow_mut().valid_slot(page) {
+            slot.valid = false;
+        }
+    });
+}
+
+/// Memory pressure shed: frees every buffer until [`restore`].
+pub fn shed() -> Result<()> {
+    CACHE.with(|cell| {
+        let mut cache = cell.borrow_mut();
+        cache.shed = true;
+        cache.off();
+    });
+    Ok(())
+}
+
+pub fn restore() -> Result<()> {
+    CACHE.with(|cell| cell.borrow_mut().shed = false);
+    Ok(())
+}
+
+/// One line for the `display` console command.
+pub fn describe() -> String {
+    CACHE.with(|cell| {
+        let cache = cell.borrow();
+        if !cache.active() {
+            let why = if cache.shed { "shed" } else { "off" };
+            return format!("frame cache {why}");
+        }
+        let pages: Vec<String> = cache
+            .slots
+            .iter()
+            .map(|slot| format!("{:?}{}", slot.page, if slot.valid { "" } else { "*" }))
+            .collect();
+        format!(
+            "frame cache [{}] (* = stale), {} hits, {} lines skipped",
+            pages.join(", "),
+            cache.hits,
+            cache.skipped_lines
+        )
+    })
+}
+
+fn capture(cache: &mut Cache, page: Page) {
+    cache.clock += 1;
+    let clock = cache.clock;
+    let limit = (settings::get(&CACHED_PAGES) as usize).max(1);
+    let reuse = cache.slots.iter().position(|slot| slot.page == page);
+    let index = match reuse {
+        Some(index) => index,
+        None if cache.slots.len() < limit => {
+            cache.slots.push(Slot {
+                page,
+                pixels: PsramVec::filled(Rgb565Pixel(0), FRAME_PIXELS),
+                valid: false,
+                last_used: clock,
+            });
+            cache.slots.len() - 1
+        }
+        None => {
+            let Some(lru) = least_recently_used(&cache.slots) else {
+                return;
+            };
+            cache.slots[lru].page = page;
+            lru
+        }
+    };
+    let Cache { shadow, slots, .. } = cache;
+    let slot = &mut slots[index];
+    slot.pixels.copy_from_slice(shadow);
+    slot.valid = true;
+    slot.last_used = clock;
+}
+
+fn evict_to(slots: &mut Vec<Slot>, limit: usize) {
+    while slots.len() > limit {
+        match least_recently_used(slots) {
+            Some(lru) => {
+                slots.swap_remove(lru);
+            }
+            None => break,
+        }
+    }
+}
+
+fn least_recently_used(slots: &[Slot]) -> Option<usize> {
+    slots
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, slot)| (slot.valid, slot.last_used))
+        .map(|(index, _)| index)
+}
@@ -0,0 +1,126 @@
+//! Rasterizes a filled history chart into a pixel buffer. The software
+//! renderer would re-tessellate a 120-segment Path on every dirty frame; an
+//! image only costs a blit and is redrawn when a new sample arrives.
+
+use slint::{Image, Rgba8Pixel, SharedPixelBuffer};
+
+use super::theme;
+
+pub const WIDTH: u32 = 200;
+pub const HEIGHT: u32 = 96;
+/// Columns the series is reduced to before drawing.
+pub const POINTS: usize = 120;
+
+/// Share of the line color in the area under it; the rest is background.
+const FILL_WEIGHT: u16 = 30;
+
+pub struct Rendered {
+    pub image: Image,
+    /// Raw extremes of the series, before padding the scale.
+    pub min: f32,
+    pub max: f32,
+    pub last: Option<f32>,
+}
+
+/// Per-column (min, max) so a one-sample spike survives the reduction.
+fn envelope(values: &[f32], points: usize) -> Vec<(f32, f32)> {
+    if values.len() <= points {
+        return values.iter().map(|&value| (value, value)).collect();
+    }
+    (0..points)
+        .map(|column| {
+            let start = column * values.len() / points;
+            let end = ((column + 1) * values.len() / points).max(start + 1);
+            values[start..end]
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(lo, hi), &value| {
+                    (lo.min(value), hi.max(value))
+                })
+        })
+        .collect()
+}
+
+/// Draws `values` (oldest first) right-aligned, so a partly filled buffer
+/// grows in from the right edge like a scrolling trace.
+pub fn render(values: &[f32]) -> Rendered {
+    let mut buffer = SharedPixelBuffer::<Rgba8Pixel>::new(WIDTH, HEIGHT);
+    let (width, height) = (WIDTH as usize, HEIGHT as usize);
+    let palette = theme::palette();
+    let background = theme::rgba(palette.background);
+    let line = theme::rgba(palette.primary);
+    let fill = blend(line, background, FILL_WEIGHT);
+    let pixels = buffer.make_mut_slice();
+    pixels.fill(background);
+    for row in [0, height / 2, height - 1] {
+        pixels[row * width..(row + 1) * width].fill(theme::rgba(palette.raised));
+    }
+
+    let columns = envelope(values, POINTS);
+    let (min, max) = columns
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &(col_lo, col_hi)| {
+            (lo.min(col_lo), hi.max(col_hi))
+        });
+    let last = values.last().copied();
+    if columns.is_empty() {
+        return Rendered {
+            image: Image::from_rgba8(buffer),
+            min: 0.0,
+            max: 0.0,
+            last,
+        };
+    }
+
+    // Pad the scale so a flat series sits mid-chart instead of on an edge.
+    let pad = ((max - min) * 0.1).max(max.abs() * 0.01).max(0.5);
+    let (floor, ceil) = (min - pad, max + pad);
+    let to_row = |value: f32| {
+        let t = (value - floor) / (ceil - floor);
+        ((1.0 - t) * (height - 1) as f32)
+            .round()
+            .clamp(0.0, (height - 1) as f32) as usize
+    };
+
+    let offset = POINTS - columns.len();
+    let mut previous: Option<(usize, usize)> = None;
+    for x in 0..width {
+        let slot = x * POINTS / width;
+        let Some(&(lo, hi)) = slot
+            .checked_sub(offset)
+            .and_then(|index| columns.get(index))
+        else {
+            continue;
+        };
+        let (mut top, mut bottom) = (to_row(hi), to_row(lo));
+        // Bridge to the previous column so steps read as a continuous line.
+        if let Some((prev_top, prev_bottom)) = previous {
+            top = top.min(prev_bottom);
+            bottom = bottom.max(prev_top);
+        }
+        for row in bottom + 1..height {
+            pixels[row * width + x] = fill;
+        }
+        for row in top..=bottom {
+            pixels[row * width + x] = line;
+        }
+        previous = Some((to_row(hi), to_row(lo)));
+    }
+
+    Rendered {
+        image: Image::from_rgba8(buffer),
+        min,
+        max,
+        last,
+    }
+}
+
+/// `weight` percent of `top` over `bottom`.
+fn blend(top: Rgba8Pixel, bottom: Rgba8Pixel, weight: u16) -> Rgba8Pixel {
+    let mix = |a: u8, b: u8| ((u16::from(a) * weight + u16::from(b) * (100 - weight)) / 100) as u8;
+    Rgba8Pixel::new(
+        mix(top.r, bottom.r),
+        mix(top.g, bottom.g),
+        mix(top.b, bottom.b),
+        0xFF,
+    )
+}
@@ -0,0 +1,89 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use super::slint_ui::{self, PointerAction};
+use crate::settings::{self, SettingKey};
+
+/// Exponential decay time constant of a fling. The list travels roughly
+/// release velocity × this, so distance scales with gesture speed.
+pub const FLING_TIME_CONSTANT_MS: SettingKey<u32> = SettingKey::new("gui_fling_ms", "325");
+
+const VELOCITY_WINDOW: Duration = Duration::from_millis(100);
+const MAX_SAMPLES: usize = 8;
+const MIN_FLING_VELOCITY: f32 = 150.0;
+const STOP_VELOCITY: f32 = 20.0;
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+thread_local! {
+    static SAMPLES: RefCell<VecDeque<(Instant, f32)>> =
+        RefCell::new(VecDeque::with_capacity(MAX_SAMPLES));
+    static FLING_ID: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Feeds a pointer event, stamped with its capture time, to the fling tracker.
+pub fn on_pointer(action: PointerAction, y: f32, at: Instant) {
+    SAMPLES.with(|cell| {
+        let mut samples = cell.borrow_mut();
+        match action {
+            PointerAction::Press => {
+                samples.clear();
+                // A new touch stops a running fling.
+                FLING_ID.with(|id| id.set(id.get().wrapping_add(1)));
+            }
+            PointerAction::Move | PointerAction::Release => {}
+        }
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((at, y));
+    });
+
+    if action == PointerAction::Release {
+        let velocity = SAMPLES.with(|cell| release_velocity(&cell.borrow()));
+        if let Some(velocity) = velocity.filter(|v| v.abs() >= MIN_FLING_VELOCITY) {
+            start_fling(velocity);
+        }
+    }
+}
+
+/// Finger velocity in px/s over the last `VELOCITY_WINDOW` before release.
+fn release_velocity(samples: &VecDeque<(Instant, f32)>) -> Option<f32> {
+    let &(last_at, last_y) = samples.back()?;
+    let &(first_at, first_y) = samples
+        .iter()
+        .find(|(at, _)| last_at.saturating_duration_since(*at) <= VELOCITY_WINDOW)?;
+    let elapsed = last_at.saturating_duration_since(first_at).as_secs_f32();
+    (elapsed > 0.0).then(|| (last_y - first_y) / elapsed)
+}
+
+fn start_fling(initial_velocity: f32) {
+    let id = FLING_ID.with(|id| {
+        let next = id.get().wrapping_add(1);
+        id.set(next);
+        next
+    });
+    let time_constant = settings::get(&FLING_TIME_CONSTANT_MS).max(1) as f32 / 1_000.0;
+
+    tokio::task::spawn_local(async move {
+        let mut velocity = initial_velocity;
+        let mut last = Instant::now();
+        loop {
+            tokio::time::sleep(FRAME_INTERVAL).await;
+            if FLING_ID.with(Cell::get) != id {
+                break;
+            }
+            let dt = last.elapsed().as_secs_f32();
+            last = Instant::now();
+
+            let moved =
+                slint_ui::with_app(|app| app.invoke_fling_step(velocity * dt)).unwrap_or(false);
+            velocity *= (-dt / time_constant).exp();
+            if !moved || velocity.abs() < STOP_VELOCITY {
+                break;
+            }
+        }
+    });
+}
@@ -0,0 +1,127 @@
+//! Pages instantiated on first visit instead of with the `App`. Each sits
+//! behind an `if <page>-live` in `app.slint`; navigation sets the flag
+//! before showing the page, and memory pressure Warning clears it for every
+//! page not on screen. State that must survive a drop (the network list's
+//! scroll offset) lives in `App` properties rather than in the page.
+//!
+//! Internal heap is noted in the boot report before the `App` is created,
+//! after the first frame, and once every lazy page has been visited.
+
+use std::cell::RefCell;
+
+use anyhow::Result;
+use log::info;
+
+use super::slint_ui::{self, App, Page};
+use crate::{boot, statlogger};
+
+const LAZY: [Page; 4] = [Page::Networks, Page::Media, Page::Settings, Page::Setup];
+
+thread_local! {
+    static STATE: RefCell<State> = const { RefCell::new(State::new()) };
+}
+
+struct State {
+    visited: Vec<Page>,
+    before_app: Option<usize>,
+    all_visited_noted: bool,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            visited: Vec::new(),
+            before_app: None,
+            all_visited_noted: false,
+        }
+    }
+}
+
+fn set_live(app: &App, page: Page, live: bool) {
+    match page {
+        Page::Networks => app.set_networks_live(live),
+        Page::Media => app.set_media_live(live),
+        Page::Settings => app.set_settings_live(live),
+        Page::Setup => app.set_setup_live(live),
+        _ => {}
+    }
+}
+
+fn is_live(app: &App, page: Page) -> bool {
+    match page {
+        Page::Networks => app.get_networks_live(),
+        Page::Media => app.get_media_live(),
+        Page::Settings => app.get_settings_live(),
+        Page::Setup => app.get_setup_live(),
+        _ => true,
+    }
+}
+
+/// Create hook: instantiates `page` if it is lazy and not live yet. Called
+/// by navigation before the page is shown.
+pub(super) fn on_show(app: &App, page: Page) {
+    if is_live(app, page) {
+        return;
+    }
+    set_live(app, page, true);
+    info!("Page {page:?} created");
+    let all_visited = STATE.with(|cell| {
+        let mut state = cell.borrow_mut();
+        if !state.visited.contains(&page) {
+            state.visited.push(page);
+        }
+        // Most boots never open the setup wizard, so it is not waited for.
+        let all = !state.all_visited_noted
+            && LAZY
+                .iter()
+                .filter(|p| **p != Page::Setup)
+                .all(|p| state.visited.contains(p));
+        state.all_visited_noted |= all;
+        all
+    });
+    if all_visited {
+        boot::note(format!(
+            "UI heap after visiting every page: {} KB internal free",
+            statlogger::heap_snapshot().internal / 1024
+        ));
+    }
+}
+
+/// Called right before the `App` is created.
+pub(super) fn before_app() {
+    let free = statlogger::heap_snapshot().internal;
+    STATE.with(|cell| cell.borrow_mut().before_app.get_or_insert(free));
+}
+
+/// Called once the first frame is on the panel.
+pub fn after_first_frame() {
+    let after = statlogger::heap_snapshot().internal;
+    let Some(before) = STATE.with(|cell| cell.borrow().before_app) else {
+        return;
+    };
+    boot::note(format!(
+        "UI heap: {} KB internal free before the App, {} KB after the first frame",
+        before / 1024,
+        after / 1024
+    ));
+}
+
+/// Memory pressure shed, the destroy hook: drops every lazy page but the
+/// one on screen. They come back on their next visit.
+pub fn shed() -> Result<()> {
+    let current = slint_ui::current_page();
+    slint_ui::with_app_untracked(|app| {
+        for page in LAZY {
+            if Some(page) != current && is_live(app, page) {
+                set_live(app, page, false);
+                info!("Page {page:?} dropped");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Nothing to do: dropped pages are recreated on demand.
+pub fn restore() -> Result<()> {
+    Ok(())
+}
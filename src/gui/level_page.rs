@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use slint::SharedString;
+
+use super::slint_ui::{self, App, Page};
+use crate::{
+    i18n,
+    sensors::imu::{self, ScreenRotation, Tilt},
+};
+
+/// Matches the IMU's sampling rate.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+/// Within this of flat the bubble turns green.
+const LEVEL_WITHIN_DEG: f32 = 1.0;
+/// Tilt that puts the bubble against the ring.
+const FULL_SCALE_DEG: f32 = 20.0;
+
+pub fn install(_app: &App) {
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            let visible = slint_ui::current_page() == Some(Page::Level);
+            let state = (imu::samples(), imu::rotation(), i18n::active());
+            if visible && seen != Some(state) {
+                refresh();
+                seen = Some(state);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+fn refresh() {
+    let tilt = imu::latest();
+    let hint = match tilt {
+        _ if !imu::present() => i18n::tr("No accelerometer"),
+        None => i18n::tr("Waiting for the sensor..."),
+        Some(_) => "",
+    };
+    let (x, y) = tilt.map_or((0.0, 0.0), |tilt| bubble(&tilt, imu::rotation()));
+    let incline = tilt.map_or(0.0, |tilt| tilt.incline_deg());
+    slint_ui::with_app(|app| {
+        app.set_level_bubble_x(x);
+        app.set_level_bubble_y(y);
+        app.set_level_flat(tilt.is_some() && incline <= LEVEL_WITHIN_DEG);
+        app.set_level_incline(SharedString::from(format!("{incline:.1}°")));
+        app.set_level_hint(SharedString::from(hint));
+    });
+}
+
+/// The bubble sits opposite the gravity component in the glass plane, in
+/// the UI's axes rather than the panel's. Capped to the ring.
+fn bubble(tilt: &Tilt, rotation: ScreenRotation) -> (f32, f32) {
+    let gain = 1.0 / FULL_SCALE_DEG.to_radians().sin();
+    let (x, y) = match rotation {
+        ScreenRotation::Deg0 => (tilt.x, tilt.y),
+        ScreenRotation::Deg90 => (tilt.y, -tilt.x),
+        ScreenRotation::Deg180 => (-tilt.x, -tilt.y),
+        ScreenRotation::Deg270 => (-tilt.y, tilt.x),
+    };
+    let (x, y) = (x * gain, y * gain);
+    let length = x.hypot(y);
+    let scale = if length > 1.0 { 1.0 / length } else { 1.0 };
+    (-x * scale, -y * scale)
+}
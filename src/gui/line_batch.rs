@@ -0,0 +1,100 @@
+//! Groups the renderer's lines into rectangles for the panel. Kept free of
+//! display types so the batching rules can be checked on the host.
+
+use std::ops::Range;
+
+/// Whole lines covering the same columns, ready for one transfer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Batch<'a, P> {
+    pub x: usize,
+    pub start_line: usize,
+    pub width: usize,
+    pub line_count: usize,
+    /// `width * line_count` pixels in line order.
+    pub pixels: &'a [P],
+}
+
+/// Batches consecutive lines that cover the same columns into one
+/// rectangle. Any change of columns, a gap in lines or a full batch flushes
+/// first, so a batch is always `width * line_count` pixels in line order.
+/// The width comes from the pixels themselves rather than the range, which
+/// keeps the rectangle and its data in step. `buffer` never holds more than
+/// `max_width * max_lines` pixels.
+pub struct LineAccumulator<P> {
+    start_line: usize,
+    x: usize,
+    width: usize,
+    line_count: usize,
+    max_lines: usize,
+    buffer: Vec<P>,
+}
+
+impl<P: Copy> LineAccumulator<P> {
+    pub fn new(max_width: usize, max_lines: usize) -> Self {
+        Self {
+            start_line: 0,
+            x: 0,
+            width: 0,
+            line_count: 0,
+            max_lines: max_lines.max(1),
+            buffer: Vec::with_capacity(max_width * max_lines),
+        }
+    }
+
+    /// Adds one rendered line, handing any batch it completes to `send`.
+    pub fn push_line<E>(
+        &mut self,
+        line: usize,
+        range: Range<usize>,
+        pixels: &[P],
+        send: &mut impl FnMut(Batch<'_, P>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        debug_assert_eq!(pixels.len(), range.len(), "line {line} pixels vs range");
+        if pixels.is_empty() {
+            return Ok(());
+        }
+
+        let continues = self.line_count > 0
+            && line == self.start_line + self.line_count
+            && range.start == self.x
+            && pixels.len() == self.width;
+        if !continues {
+            self.flush(send)?;
+            self.start_line = line;
+            self.x = range.start;
+            self.width = pixels.len();
+        }
+
+        self.buffer.extend_from_slice(pixels);
+        self.line_count += 1;
+
+        if self.line_count >= self.max_lines {
+            self.flush(send)?;
+        }
+        Ok(())
+    }
+
+    /// Hands the pending batch, if any, to `send`.
+    pub fn flush<E>(
+        &mut self,
+        send: &mut impl FnMut(Batch<'_, P>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if self.line_count == 0 {
+            return Ok(());
+        }
+        debug_assert_eq!(self.buffer.len(), self.width * self.line_count);
+
+        let result = send(Batch {
+            x: self.x,
+            start_line: self.start_line,
+            width: self.width,
+            line_count: self.line_count,
+            pixels: &self.buffer,
+        });
+        // Cleared whatever the outcome: pixels left over from a failed
+        // transfer would shift every later batch.
+        self.buffer.clear();
+        self.line_count = 0;
+        result
+    }
+}
@@ -0,0 +1,158 @@
+//! Scrolls lines that do not fit: the toast, the Media title and the client
+//! names on Devices. Before a string is shown it is measured through the
+//! probe `Text` in `App`, at the size and against the width the `Marquees`
+//! global gives its line; only a real overflow sets a scroll distance.
+//!
+//! All marquees share one clock, which [`run`] advances while a line with a
+//! distance is on screen. It stops, and the lines snap back to the start,
+//! when nothing visible overflows, the screen is off or rendering is
+//! throttled for heat or memory, so a marquee never keeps the frame dirty
+//! on its own.
+
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use slint::ComponentHandle;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{
+    backlight,
+    slint_ui::{self, App, Marquees, Page},
+};
+use crate::{
+    events::{self, SystemEvent},
+    memory::pressure::PressureLevel,
+};
+
+/// About 30 fps while scrolling; one pixel per step at the Slint speed.
+const STEP: Duration = Duration::from_millis(33);
+/// How often a stopped clock checks whether a line came on screen.
+const IDLE_POLL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line {
+    Toast,
+    TrackTitle,
+    /// The longest name in the Devices client list.
+    ClientName,
+}
+
+thread_local! {
+    static TOAST: Cell<f32> = const { Cell::new(0.0) };
+    static TRACK_TITLE: Cell<f32> = const { Cell::new(0.0) };
+    static CLIENT_NAME: Cell<f32> = const { Cell::new(0.0) };
+    /// Bumped whenever a distance changes, so scrolling restarts at the start.
+    static CHANGES: Cell<u32> = const { Cell::new(0) };
+}
+
+fn slot(line: Line) -> &'static std::thread::LocalKey<Cell<f32>> {
+    match line {
+        Line::Toast => &TOAST,
+        Line::TrackTitle => &TRACK_TITLE,
+        Line::ClientName => &CLIENT_NAME,
+    }
+}
+
+/// How far `text` overflows `line`, in logical pixels; zero when it fits.
+pub fn measure(app: &App, line: Line, text: &str) -> f32 {
+    let marquees = app.global::<Marquees>();
+    let (size, width) = match line {
+        Line::Toast => (marquees.get_toast_size(), marquees.get_toast_width()),
+        Line::TrackTitle => (marquees.get_title_size(), marquees.get_title_width()),
+        Line::ClientName => (marquees.get_name_size(), marquees.get_name_width()),
+    };
+    app.set_marquee_probe_size(size);
+    app.set_marquee_probe_text(text.into());
+    let overflow = app.get_marquee_probe_width() - width;
+    // Sub-pixel overflow is left to the elision.
+    if overflow >= 1.0 {
+        overflow.ceil()
+    } else {
+        0.0
+    }
+}
+
+/// Records the distance just given to `line`'s marquee.
+pub fn note(line: Line, distance: f32) {
+    if slot(line).with(|slot| slot.replace(distance)) != distance {
+        restart();
+    }
+}
+
+/// Starts every marquee over from its first pause, e.g. for a new toast.
+pub fn restart() {
+    CHANGES.with(|changes| changes.set(changes.get().wrapping_add(1)));
+}
+
+/// One pause, the travel out, and the pause at the far end.
+pub fn pass_time(app: &App, distance: f32) -> Duration {
+    let marquees = app.global::<Marquees>();
+    let pause = Duration::from_millis(marquees.get_pause_ms().max(0) as u64);
+    let travel = Duration::from_secs_f32(distance.max(0.0) / marquees.get_speed().max(1.0));
+    pause * 2 + travel
+}
+
+/// A line with a distance is visible right now.
+fn on_screen() -> bool {
+    let overflows = |line: Line| slot(line).with(Cell::get) > 0.0;
+    if overflows(Line::Toast) && slint_ui::toast_visible() {
+        return true;
+    }
+    if slint_ui::top_overlay().is_some() {
+        return false;
+    }
+    match slint_ui::current_page() {
+        Some(Page::Media) => overflows(Line::TrackTitle),
+        Some(Page::Devices) => overflows(Line::ClientName),
+        _ => false,
+    }
+}
+
+/// Spawn once on the main `LocalSet`, after the backlight.
+pub async fn run() {
+    let mut events = events::subscribe();
+    let (mut hot, mut low_memory) = (false, false);
+    let mut clock = Duration::ZERO;
+    let mut last = Instant::now();
+    let mut seen = CHANGES.with(Cell::get);
+    loop {
+        let running = !hot && !low_memory && backlight::screen_on() && on_screen();
+        let changes = CHANGES.with(Cell::get);
+        if running && changes == seen {
+            clock += last.elapsed();
+            set_clock(clock, false);
+        } else if clock != Duration::ZERO {
+            clock = Duration::ZERO;
+            set_clock(clock, true);
+        }
+        seen = changes;
+        last = Instant::now();
+        tokio::select! {
+            _ = tokio::time::sleep(if running { STEP } else { IDLE_POLL }) => {}
+            event = events.recv() => match event {
+                Ok(SystemEvent::ThermalWarning { active, .. }) => hot = active,
+                Ok(SystemEvent::MemoryPressure { level }) => {
+                    low_memory = level >= PressureLevel::Critical;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+/// Steps only touch the page on screen. Stopping also resets the marquees
+/// on hidden pages, so their cached frames go too.
+fn set_clock(clock: Duration, stopping: bool) {
+    let set = |app: &App| {
+        app.global::<Marquees>()
+            .set_clock_ms(clock.as_millis() as i32)
+    };
+    if stopping {
+        slint_ui::with_app(set);
+    } else {
+        slint_ui::with_app_untracked(set);
+    }
+}
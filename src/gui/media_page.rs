@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use slint::SharedString;
+
+use super::{
+    marquee::{self, Line},
+    pages::{Create, PageDescriptor, PageEvent},
+    slint_ui::{self, App, Gesture, Page},
+    theme, toast,
+};
+use crate::{
+    i18n,
+    input::button::ButtonPress,
+    miwear::media::{self, MediaCommand, MediaError, Playback},
+};
+
+/// Fast enough that a watch report lands on screen shortly after a command.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+pub const PAGE: PageDescriptor = PageDescriptor {
+    on_event: Some(on_event),
+    ..PageDescriptor::new("media", "Media", Create::Slint(Page::Media))
+};
+
+pub fn install(app: &App) {
+    app.on_media_play_pause(|| send(MediaCommand::PlayPause));
+    app.on_media_set_volume(|level| send(MediaCommand::Volume(level.clamp(0, 100) as u8)));
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            let visible = slint_ui::current_page() == Some(Page::Media);
+            if visible {
+                media::refresh().await;
+                let current = (
+                    media::generation(),
+                    media::connected(),
+                    i18n::active(),
+                    theme::text_size(),
+                );
+                if seen != Some(current) {
+                    show(media::connected(), media::playback());
+                    seen = Some(current);
+                }
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+/// Left and right swipes skip tracks, and a double press of the side key
+/// toggles playback.
+fn on_event(_app: &App, event: &PageEvent) -> bool {
+    let PageEvent::Gesture(gesture) = *event else {
+        return false;
+    };
+    match gesture {
+        Gesture::SwipeLeft => send(MediaCommand::Next),
+        Gesture::SwipeRight => send(MediaCommand::Previous),
+        Gesture::Button(ButtonPress::Double) => send(MediaCommand::PlayPause),
+        Gesture::SwipeUp | Gesture::SwipeDown | Gesture::Button(_) => return false,
+    }
+    true
+}
+
+fn send(command: MediaCommand) {
+    let acknowledged = media::request(command);
+    show(media::connected(), media::playback());
+    tokio::task::spawn_local(async move {
+        let result = acknowledged.await;
+        show(media::connected(), media::playback());
+        match result {
+            Ok(()) | Err(MediaError::Debounced) => {}
+            Err(err) => toast::show(error_text(&err)),
+        }
+    });
+}
+
+fn show(connected: bool, playback: Option<Playback>) {
+    let hint = match (connected, &playback) {
+        (false, _) => i18n::tr("Watch not connected"),
+        (true, None) => i18n::tr("Nothing playing"),
+        (true, Some(_)) => "",
+    };
+    let playback = playback.unwrap_or(Playback {
+        title: String::new(),
+        artist: String::new(),
+        playing: false,
+        volume: 0,
+    });
+    slint_ui::with_app(|app| {
+        app.set_media_available(hint.is_empty());
+        app.set_media_hint(SharedString::from(hint));
+        let line = if hint.is_empty() {
+            playback.title.as_str()
+        } else {
+            hint
+        };
+        let scroll = marquee::measure(app, Line::TrackTitle, line);
+        app.set_media_title_scroll(scroll);
+        marquee::note(Line::TrackTitle, scroll);
+        app.set_media_title(SharedString::from(playback.title.as_str()));
+        app.set_media_artist(SharedString::from(playback.artist.as_str()));
+        app.set_media_playing(playback.playing);
+        app.set_media_volume(i32::from(playback.volume));
+    });
+}
+
+fn error_text(err: &MediaError) -> String {
+    match err {
+        MediaError::NotConnected => i18n::tr("Watch not connected").to_string(),
+        MediaError::NoSession => i18n::tr("Nothing playing").to_string(),
+        MediaError::Unsupported => i18n::tr("This watch has no music control").to_string(),
+        MediaError::Timeout => i18n::tr("Watch did not answer").to_string(),
+        MediaError::Debounced | MediaError::Unavailable | MediaError::Failed(_) => {
+            i18n::trf("Media failed: {}", &[err])
+        }
+    }
+}
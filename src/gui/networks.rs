@@ -0,0 +1,214 @@
+use std::cell::RefCell;
+
+use slint::SharedString;
+
+use super::{
+    bounded_model::BoundedModel,
+    pages::{Create, PageDescriptor, PageEvent},
+    slint_ui::{self, App, Gesture, NetworkEntry, Overlay, Page},
+};
+use crate::{
+    i18n,
+    wifi::{self, country, ScannedNetwork},
+};
+
+/// Scans are sorted strongest first, so a crowded band loses the weakest.
+const MAX_NETWORK_ROWS: usize = 32;
+
+pub const PAGE: PageDescriptor = PageDescriptor {
+    on_event: Some(on_event),
+    ..PageDescriptor::new("networks", "Networks", Create::Slint(Page::Networks))
+};
+
+#[derive(Default)]
+struct CredentialDraft {
+    ssid: String,
+    password: String,
+    reveal: bool,
+}
+
+thread_local! {
+    static NETWORK_MODEL: BoundedModel<NetworkEntry> = BoundedModel::new("networks", MAX_NETWORK_ROWS);
+    static SCANNING: RefCell<bool> = const { RefCell::new(false) };
+    static DRAFT: RefCell<Option<CredentialDraft>> = const { RefCell::new(None) };
+}
+
+pub fn install(app: &App) {
+    app.set_networks(NETWORK_MODEL.with(BoundedModel::model));
+
+    show_region(app);
+    app.on_networks_scan(request_scan);
+    app.on_network_selected(|entry| open_editor(entry.ssid.to_string()));
+    app.on_credentials_key(|text| {
+        edit_draft(|draft| draft.password.push_str(text.as_str()));
+    });
+    app.on_credentials_backspace(|| {
+        edit_draft(|draft| {
+            draft.password.pop();
+        });
+    });
+    app.on_credentials_toggle_reveal(|| edit_draft(|draft| draft.reveal = !draft.reveal));
+    app.on_credentials_cancel(close_editor);
+    app.on_credentials_connect(submit_draft);
+}
+
+/// Pull-to-refresh: a downward swipe while the list is scrolled to the top.
+fn on_event(app: &App, event: &PageEvent) -> bool {
+    if matches!(event, PageEvent::Gesture(Gesture::SwipeDown)) && app.get_networks_at_top() {
+        request_scan();
+        return true;
+    }
+    false
+}
+
+pub fn request_scan() {
+    let already_scanning = SCANNING.with(|flag| flag.replace(true));
+    if already_scanning {
+        return;
+    }
+
+    slint_ui::with_app(|app| {
+        app.set_networks_scanning(true);
+        app.set_networks_status(SharedString::from(i18n::tr("Scanning...")));
+    });
+
+    tokio::task::spawn_local(async {
+        let status = match wifi::scan().await {
+            Ok(outcome) => {
+                let count = outcome.networks.len();
+                update_model(&outcome.networks);
+                if outcome.reconnected {
+                    i18n::trf("{} networks (Wi-Fi was briefly disconnected)", &[&count])
+                } else {
+                    i18n::trf("{} networks", &[&count])
+                }
+            }
+            Err(err) => {
+                log::warn!("Wi-Fi scan failed: {err:?}");
+                i18n::trf("Scan failed: {}", &[&err])
+            }
+        };
+
+        SCANNING.with(|flag| *flag.borrow_mut() = false);
+        slint_ui::with_app(|app| {
+            app.set_networks_scanning(false);
+            app.set_networks_status(SharedString::from(status));
+            show_region(app);
+        });
+    });
+}
+
+/// Refreshed with every scan, which is also when a new country shows.
+fn show_region(app: &App) {
+    app.set_networks_region(SharedString::from(i18n::trf(
+        "Region {}",
+        &[&country::describe()],
+    )));
+    let warning = country::excluded_ap_channel()
+        .map(|channel| i18n::trf("Saved AP on channel {} is outside this region", &[&channel]));
+    app.set_networks_region_warning(SharedString::from(warning.unwrap_or_default()));
+}
+
+fn update_model(networks: &[ScannedNetwork]) {
+    let entries: Vec<NetworkEntry> = networks
+        .iter()
+        .map(|net| NetworkEntry {
+            ssid: SharedString::from(net.ssid.as_str()),
+            rssi: net.rssi as i32,
+            bars: rssi_bars(net.rssi),
+            channel: net.channel as i32,
+            auth: SharedString::from(wifi::auth_label(net.auth)),
+            secured: net.is_secured(),
+        })
+        .collect();
+    NETWORK_MODEL.with(|model| model.set_vec(entries));
+}
+
+fn rssi_bars(rssi: i8) -> i32 {
+    match rssi {
+        r if r >= -55 => 4,
+        r if r >= -65 => 3,
+        r if r >= -75 => 2,
+        r if r >= -85 => 1,
+        _ => 0,
+    }
+}
+
+fn open_editor(ssid: String) {
+    DRAFT.with(|cell| {
+        *cell.borrow_mut() = Some(CredentialDraft {
+            ssid,
+            ..Default::default()
+        })
+    });
+    refresh_editor();
+}
+
+fn close_editor() {
+    DRAFT.with(|cell| cell.borrow_mut().take());
+    slint_ui::with_app(|app| app.set_credentials_busy(false));
+    slint_ui::remove_overlay(Overlay::Credentials);
+}
+
+fn edit_draft(f: impl FnOnce(&mut CredentialDraft)) {
+    DRAFT.with(|cell| {
+        if let Some(draft) = cell.borrow_mut().as_mut() {
+            f(draft);
+        }
+    });
+    refresh_editor();
+}
+
+fn refresh_editor() {
+    let view = DRAFT.with(|cell| {
+        cell.borrow().as_ref().map(|draft| {
+            let display = if draft.reveal {
+                draft.password.clone()
+            } else {
+                "*".repeat(draft.password.chars().count())
+            };
+            (draft.ssid.clone(), display)
+        })
+    });
+
+    match view {
+        Some((ssid, display)) => {
+            slint_ui::with_app(|app| {
+                app.set_credentials_ssid(SharedString::from(ssid));
+                app.set_credentials_password_display(SharedString::from(display));
+            });
+            slint_ui::push_overlay(Overlay::Credentials);
+        }
+        None => slint_ui::remove_overlay(Overlay::Credentials),
+    }
+}
+
+fn submit_draft() {
+    let Some((ssid, password)) = DRAFT.with(|cell| {
+        cell.borrow()
+            .as_ref()
+            .map(|draft| (draft.ssid.clone(), draft.password.clone()))
+    }) else {
+        return;
+    };
+
+    slint_ui::with_app(|app| {
+        app.set_credentials_busy(true);
+        app.set_networks_status(SharedString::from(i18n::trf(
+            "Connecting to {}...",
+            &[&ssid],
+        )));
+    });
+
+    tokio::task::spawn_local(async move {
+        let status = match wifi::apply_credentials(ssid.clone(), password).await {
+            Ok(()) => i18n::trf("Connected to {}", &[&ssid]),
+            Err(err) => {
+                log::warn!("applying Wi-Fi credentials for {ssid} failed: {err:?}");
+                i18n::trf("Connect failed: {}", &[&err])
+            }
+        };
+        close_editor();
+        slint_ui::with_app(|app| app.set_networks_status(SharedString::from(status)));
+    });
+}
@@ -0,0 +1,540 @@
+//! Page registry. Every screen reachable from the Home carousel is described
+//! by a [`PageDescriptor`]; the built-in ones are listed in [`builtin`] and a
+//! fork adds its own with [`register`] from its init code, before or after
+//! the UI comes up, without touching `app.slint` or `slint_ui`.
+//!
+//! A descriptor either names a page compiled into `app.slint` or creates a
+//! draw callback for the generic canvas page (`Page.custom`), which shows a
+//! Rust-drawn image under the page title. Slint component factories need
+//! the interpreter, which this firmware does not ship, so a fork's page is
+//! always a canvas.
+//!
+//! The carousel follows `PAGE_ORDER` and then registration order. Gestures
+//! and side-key presses go to the showing page's `on_event`; event-bus
+//! messages do too when it `wants_events`. A page with a `focus_chain` can
+//! be walked with the analog stick, see `focus`.
+
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::info;
+use slint::{Image, Model, Rgba8Pixel, SharedPixelBuffer, SharedString};
+use tokio::sync::broadcast::error::RecvError;
+
+#[cfg(feature = "ancs")]
+use super::remote_page;
+use super::{
+    bounded_model::BoundedModel,
+    focus, frame_cache, settings_page,
+    slint_ui::{self, App, Gesture, Page, PageLink},
+    stats_page, theme,
+};
+#[cfg(feature = "gui-extras")]
+use super::{devices, media_page, networks};
+use crate::{
+    events::{self, SystemEvent},
+    i18n,
+    settings::{self, SettingKey},
+};
+
+/// Comma-separated page ids; these lead the carousel in this order.
+pub const PAGE_ORDER: SettingKey<String> = SettingKey::new("page_order", "").validated(|order| {
+    match order.split(',').map(str::trim).find(|id| !valid_id(id)) {
+        Some(id) if !order.is_empty() => Err(format!("not a page id: \"{id}\"")),
+        _ => Ok(()),
+    }
+});
+
+pub const CANVAS_WIDTH: u32 = 200;
+pub const CANVAS_HEIGHT: u32 = 120;
+/// Home shows this many carousel entries at a time.
+const CAROUSEL_SLOTS: usize = 3;
+const MAX_PAGES: usize = 24;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static REGISTERED: Mutex<Vec<PageDescriptor>> = Mutex::new(Vec::new());
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+static REDRAW: AtomicBool = AtomicBool::new(false);
+/// Set off the UI thread by `pages open`; opened by the poll loop.
+static PENDING_OPEN: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    static CAROUSEL: BoundedModel<PageLink> = BoundedModel::new("carousel", CAROUSEL_SLOTS);
+    static CAROUSEL_START: Cell<usize> = const { Cell::new(0) };
+    /// The canvas page `Page.custom` stands for.
+    static CANVAS_ID: Cell<Option<&'static str>> = const { Cell::new(None) };
+    /// Its draw callback, only while it is showing.
+    static DRAW: RefCell<Option<DrawFn>> = const { RefCell::new(None) };
+}
+
+pub type DrawFn = Box<dyn FnMut(&mut Canvas)>;
+
+#[derive(Clone, Copy)]
+pub enum Create {
+    /// A page compiled into `app.slint`.
+    Slint(Page),
+    /// Called on each visit; the callback draws the canvas until the page
+    /// is left, and is dropped with the canvas image then.
+    Canvas(fn() -> DrawFn),
+}
+
+/// Delivered to the showing page's `on_event`, which returns whether it
+/// consumed it. Unconsumed gestures and presses keep their default action.
+#[derive(Clone, Debug)]
+pub enum PageEvent {
+    Gesture(Gesture),
+    /// A tap on the canvas, in canvas pixels.
+    Tap {
+        x: i32,
+        y: i32,
+    },
+    /// Only for pages that set `wants_events`.
+    Bus(SystemEvent),
+}
+
+#[derive(Clone, Copy)]
+pub struct PageDescriptor {
+    /// Stable name for `PAGE_ORDER`, the console and logs.
+    pub id: &'static str,
+    /// English; looked up with `i18n::tr`.
+    pub title: &'static str,
+    /// A glyph shown before the title in the carousel, or empty.
+    pub icon: &'static str,
+    pub create_fn: Create,
+    pub on_show: Option<fn(&App)>,
+    pub on_hide: Option<fn(&App)>,
+    pub on_event: Option<fn(&App, &PageEvent) -> bool>,
+    /// Also deliver event-bus messages while showing.
+    pub wants_events: bool,
+    /// In the Home carousel. Pages reached from another page say false.
+    pub listed: bool,
+    /// How many `FocusRing`s the page numbers under its id. Without one, the
+    /// stick sends the page swipes.
+    pub focus_chain: Option<fn(&App) -> usize>,
+}
+
+impl PageDescriptor {
+    /// A listed page with no hooks.
+    pub const fn new(id: &'static str, title: &'static str, create_fn: Create) -> Self {
+        Self {
+            id,
+            title,
+            icon: "",
+            create_fn,
+            on_show: None,
+            on_hide: None,
+            on_event: None,
+            wants_events: false,
+            listed: true,
+            focus_chain: None,
+        }
+    }
+
+    fn slint_page(&self) -> Option<Page> {
+        match self.create_fn {
+            Create::Slint(page) => Some(page),
+            Create::Canvas(_) => None,
+        }
+    }
+}
+
+/// An RGBA image the size of the canvas page's drawing area, cleared to
+/// the theme background before each draw.
+pub struct Canvas {
+    buffer: SharedPixelBuffer<Rgba8Pixel>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        let mut buffer = SharedPixelBuffer::new(CANVAS_WIDTH, CANVAS_HEIGHT);
+        buffer
+            .make_mut_slice()
+            .fill(theme::rgba(theme::palette().background));
+        Self { buffer }
+    }
+
+    pub fn width(&self) -> u32 {
+        CANVAS_WIDTH
+    }
+
+    pub fn height(&self) -> u32 {
+        CANVAS_HEIGHT
+    }
+
+    /// Row-major pixels.
+    pub fn pixels(&mut self) -> &mut [Rgba8Pixel] {
+        self.buffer.make_mut_slice()
+    }
+
+    /// Fills the rectangle, clipped to the canvas.
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Rgba8Pixel) {
+        let clip = |start: i32, len: u32, max: u32| {
+            let from = start.clamp(0, max as i32) as usize;
+            let end = start.saturating_add(i32::try_from(len).unwrap_or(i32::MAX));
+            let to = end.clamp(0, max as i32) as usize;
+            from..to
+        };
+        let (columns, rows) = (clip(x, width, CANVAS_WIDTH), clip(y, height, CANVAS_HEIGHT));
+        let stride = CANVAS_WIDTH as usize;
+        let pixels = self.buffer.make_mut_slice();
+        for row in rows {
+            pixels[row * stride + columns.start..row * stride + columns.end].fill(color);
+        }
+    }
+
+    fn into_image(self) -> Image {
+        Image::from_rgba8(self.buffer)
+    }
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// Adds a page. Ids are lowercase ASCII, digits, `-` and `_`, unique among
+/// built-in and registered pages.
+pub fn register(descriptor: PageDescriptor) -> Result<()> {
+    if !valid_id(descriptor.id) {
+        bail!("not a page id: \"{}\"", descriptor.id);
+    }
+    let mut registered = REGISTERED
+        .lock()
+        .map_err(|_| anyhow!("page registry poisoned"))?;
+    if builtin()
+        .iter()
+        .chain(registered.iter())
+        .any(|page| page.id == descriptor.id)
+    {
+        bail!("page \"{}\" is already registered", descriptor.id);
+    }
+    if builtin().len() + registered.len() >= MAX_PAGES {
+        bail!("at most {MAX_PAGES} pages");
+    }
+    info!("Page \"{}\" registered", descriptor.id);
+    registered.push(descriptor);
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Asks for the canvas to be drawn again. Callable from any thread; only
+/// the showing canvas page is drawn.
+pub fn request_redraw() {
+    REDRAW.store(true, Ordering::Relaxed);
+}
+
+fn home_event(_app: &App, event: &PageEvent) -> bool {
+    match event {
+        PageEvent::Gesture(Gesture::SwipeLeft) => scroll_carousel(1),
+        PageEvent::Gesture(Gesture::SwipeRight) => scroll_carousel(-1),
+        _ => false,
+    }
+}
+
+/// The carousel buttons, left to right.
+fn home_focus_chain(app: &App) -> usize {
+    app.get_carousel().row_count()
+}
+
+fn builtin() -> Vec<PageDescriptor> {
+    let mut pages = vec![PageDescriptor {
+        on_event: Some(home_event),
+        listed: false,
+        focus_chain: Some(home_focus_chain),
+        ..PageDescriptor::new("home", "Home", Create::Slint(Page::Home))
+    }];
+    #[cfg(feature = "gui-extras")]
+    pages.extend([networks::PAGE, devices::PAGE]);
+    pages.extend([settings_page::PAGE, stats_page::PAGE]);
+    #[cfg(feature = "gui-extras")]
+    pages.push(media_page::PAGE);
+    #[cfg(feature = "ancs")]
+    pages.push(remote_page::PAGE);
+    pages
+}
+
+/// Built-in then registered pages, in carousel order: `PAGE_ORDER` first,
+/// the rest in registration order.
+pub fn all() -> Vec<PageDescriptor> {
+    let mut pages = builtin();
+    if let Ok(registered) = REGISTERED.lock() {
+        pages.extend(registered.iter().copied());
+    }
+    let order = settings::get(&PAGE_ORDER);
+    let rank = |page: &PageDescriptor| {
+        order
+            .split(',')
+            .map(str::trim)
+            .position(|id| id == page.id)
+            .unwrap_or(usize::MAX)
+    };
+    pages.sort_by_key(rank);
+    pages
+}
+
+fn find(id: &str) -> Option<PageDescriptor> {
+    all().into_iter().find(|page| page.id == id)
+}
+
+/// The descriptor of what is on screen, if it has one.
+fn showing(page: Page) -> Option<PageDescriptor> {
+    if page == Page::Custom {
+        return CANVAS_ID.get().and_then(find);
+    }
+    all()
+        .into_iter()
+        .find(|descriptor| descriptor.slint_page() == Some(page))
+}
+
+/// Opens the page with this id.
+pub fn open(id: &str) -> Result<()> {
+    let descriptor = find(id).ok_or_else(|| anyhow!("no page \"{id}\""))?;
+    if let Create::Slint(page) = descriptor.create_fn {
+        slint_ui::navigate(page);
+        return Ok(());
+    }
+    let on_canvas = slint_ui::current_page() == Some(Page::Custom);
+    if on_canvas && CANVAS_ID.get() != Some(descriptor.id) {
+        // Switching canvases keeps `Page.custom` up, so navigation would
+        // not see the change.
+        slint_ui::with_app(|app| {
+            leave_canvas(app);
+            CANVAS_ID.set(Some(descriptor.id));
+            enter_canvas(app);
+        });
+    } else if !on_canvas {
+        CANVAS_ID.set(Some(descriptor.id));
+    }
+    slint_ui::navigate(Page::Custom);
+    Ok(())
+}
+
+fn enter_canvas(app: &App) {
+    let Some(descriptor) = showing(Page::Custom) else {
+        return;
+    };
+    if let Create::Canvas(create) = descriptor.create_fn {
+        DRAW.with(|draw| *draw.borrow_mut() = Some(create()));
+    }
+    app.set_canvas_title(SharedString::from(i18n::tr(descriptor.title)));
+    frame_cache::invalidate(Page::Custom);
+    request_redraw();
+    if let Some(show) = descriptor.on_show {
+        show(app);
+    }
+}
+
+/// The canvas image is a full RGBA buffer; it is not kept while hidden.
+fn leave_canvas(app: &App) {
+    if let Some(hide) = showing(Page::Custom).and_then(|page| page.on_hide) {
+        hide(app);
+    }
+    DRAW.with(|draw| draw.borrow_mut().take());
+    app.set_canvas_image(Image::default());
+}
+
+/// Navigation hook: runs the hide and show hooks when the page changes.
+pub(super) fn on_navigate(app: &App, from: Page, to: Page) {
+    if from == to {
+        return;
+    }
+    focus::on_navigate(app, showing(to).map_or("", |page| page.id));
+    if from == Page::Custom {
+        leave_canvas(app);
+    } else if let Some(hide) = showing(from).and_then(|page| page.on_hide) {
+        hide(app);
+    }
+    if to == Page::Custom {
+        enter_canvas(app);
+    } else if let Some(show) = showing(to).and_then(|page| page.on_show) {
+        show(app);
+    }
+}
+
+/// The showing page's focus chain length; 0 without one.
+pub(super) fn focus_chain(app: &App) -> usize {
+    showing(app.get_page())
+        .and_then(|page| page.focus_chain)
+        .map_or(0, |chain| chain(app))
+}
+
+/// Offers `event` to the showing page. Canvas pages are redrawn after
+/// consuming one.
+pub(super) fn deliver(app: &App, event: &PageEvent) -> bool {
+    let page = app.get_page();
+    let Some(descriptor) = showing(page) else {
+        return false;
+    };
+    if matches!(event, PageEvent::Bus(_)) && !descriptor.wants_events {
+        return false;
+    }
+    let Some(on_event) = descriptor.on_event else {
+        return false;
+    };
+    let consumed = on_event(app, event);
+    if consumed && page == Page::Custom {
+        request_redraw();
+    }
+    consumed
+}
+
+fn draw() {
+    let image = DRAW.with(|draw| {
+        let mut draw = draw.borrow_mut();
+        let draw = draw.as_mut()?;
+        let mut target = Canvas::new();
+        draw(&mut target);
+        Some(target.into_image())
+    });
+    if let Some(image) = image {
+        slint_ui::with_app(|app| app.set_canvas_image(image));
+    }
+}
+
+fn scroll_carousel(step: isize) -> bool {
+    let listed = all().iter().filter(|page| page.listed).count();
+    let last = listed.saturating_sub(CAROUSEL_SLOTS);
+    let start = CAROUSEL_START.get();
+    let next = start.saturating_add_signed(step).min(last);
+    if next == start {
+        return false;
+    }
+    CAROUSEL_START.set(next);
+    show_carousel();
+    true
+}
+
+fn show_carousel() {
+    let listed: Vec<PageDescriptor> = all().into_iter().filter(|page| page.listed).collect();
+    let start = CAROUSEL_START
+        .get()
+        .min(listed.len().saturating_sub(CAROUSEL_SLOTS));
+    CAROUSEL_START.set(start);
+    let links = listed
+        .iter()
+        .skip(start)
+        .take(CAROUSEL_SLOTS)
+        .map(|page| PageLink {
+            id: SharedString::from(page.id),
+            label: SharedString::from(match page.icon {
+                "" => i18n::tr(page.title).to_string(),
+                icon => format!("{icon} {}", i18n::tr(page.title)),
+            }),
+        })
+        .collect();
+    CAROUSEL.with(|model| model.set_vec(links));
+    slint_ui::with_app(|app| {
+        app.set_carousel_more_left(start > 0);
+        app.set_carousel_more_right(start + CAROUSEL_SLOTS < listed.len());
+    });
+}
+
+pub fn install(app: &App) {
+    app.set_carousel(CAROUSEL.with(BoundedModel::model));
+    focus::on_navigate(app, showing(app.get_page()).map_or("", |page| page.id));
+    app.on_open_page(|id| {
+        if let Err(err) = open(&id) {
+            log::warn!("{err:#}");
+        }
+    });
+    app.on_carousel_scroll(|step| {
+        scroll_carousel(step as isize);
+    });
+    app.on_canvas_tap(|x, y| {
+        slint_ui::with_app(|app| deliver(app, &PageEvent::Tap { x, y }));
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            let current = (
+                GENERATION.load(Ordering::Relaxed),
+                settings::generation(),
+                i18n::active(),
+            );
+            if seen != Some(current) {
+                show_carousel();
+                // Theme and language changes reach the canvas this way too.
+                if let Some(canvas) = showing(Page::Custom) {
+                    slint_ui::with_app(|app| {
+                        app.set_canvas_title(SharedString::from(i18n::tr(canvas.title)))
+                    });
+                }
+                request_redraw();
+                seen = Some(current);
+            }
+            let pending = PENDING_OPEN.lock().ok().and_then(|mut id| id.take());
+            if let Some(id) = pending {
+                if let Err(err) = open(&id) {
+                    log::warn!("{err:#}");
+                }
+            }
+            if REDRAW.swap(false, Ordering::Relaxed)
+                && slint_ui::current_page() == Some(Page::Custom)
+            {
+                draw();
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+
+    tokio::task::spawn_local(async {
+        let mut events = events::subscribe();
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let wants = slint_ui::current_page()
+                        .and_then(showing)
+                        .is_some_and(|page| page.wants_events);
+                    if wants {
+                        slint_ui::with_app(|app| deliver(app, &PageEvent::Bus(event)));
+                    }
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "pages",
+        "carousel pages: pages [order <id,id,...> | open <id>]",
+        |args| {
+            match args {
+                [] => {}
+                ["order", order] => settings::set(&PAGE_ORDER, &order.to_string())?,
+                ["open", id] => {
+                    find(id).ok_or_else(|| anyhow!("no page \"{id}\""))?;
+                    // The console runs off the UI thread.
+                    *PENDING_OPEN
+                        .lock()
+                        .map_err(|_| anyhow!("page registry poisoned"))? = Some(id.to_string());
+                }
+                _ => bail!("usage: pages [order <id,id,...> | open <id>]"),
+            }
+            Ok(all()
+                .iter()
+                .map(|page| {
+                    format!(
+                        "{}{}: {}",
+                        page.id,
+                        if page.listed { "" } else { " (unlisted)" },
+                        page.title
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        },
+    );
+}
@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+use log::warn;
+use slint::SharedString;
+
+use super::slint_ui::{self, App, Overlay};
+use crate::{
+    i18n,
+    miwear::ancs::{
+        self,
+        pairing::{self, PromptKind},
+    },
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub fn install(app: &App) {
+    refresh_mode(app);
+
+    app.on_pairing_accept(|| answer(true));
+    app.on_pairing_reject(|| answer(false));
+    app.on_pairing_mode_cycle(|| {
+        let next = pairing::configured().next();
+        let hint = match ancs::set_io_capability(next) {
+            Ok(()) => i18n::tr("Bonds cleared; re-pair devices").to_string(),
+            Err(err) => {
+                warn!("Failed to change pairing mode: {err:?}");
+                i18n::trf("Save failed: {}", &[&err])
+            }
+        };
+        slint_ui::with_app(|app| {
+            refresh_mode(app);
+            app.set_pairing_mode_hint(SharedString::from(hint));
+        });
+    });
+
+    tokio::task::spawn_local(async {
+        let mut shown = None;
+        loop {
+            let prompt = pairing::current_prompt();
+            match prompt {
+                Some(prompt) => {
+                    let seconds_left = prompt
+                        .deadline
+                        .saturating_duration_since(Instant::now())
+                        .as_secs() as i32;
+                    let fresh = shown != Some(prompt.id);
+                    shown = Some(prompt.id);
+                    slint_ui::with_app(|app| {
+                        if fresh {
+                            app.set_pairing_code(SharedString::from(format_code(prompt.passkey)));
+                            app.set_pairing_confirm(prompt.kind == PromptKind::Confirm);
+                        }
+                        app.set_pairing_seconds_left(seconds_left);
+                    });
+                    if fresh {
+                        slint_ui::push_overlay(Overlay::Pairing);
+                    }
+                }
+                None if shown.is_some() => {
+                    shown = None;
+                    slint_ui::remove_overlay(Overlay::Pairing);
+                }
+                None => {}
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+pub fn refresh_mode(app: &App) {
+    let label = i18n::tr(pairing::configured().label());
+    app.set_pairing_mode(SharedString::from(label));
+}
+
+fn answer(accepted: bool) {
+    if let Some(prompt) = pairing::current_prompt() {
+        pairing::respond(prompt.id, accepted);
+    }
+    slint_ui::remove_overlay(Overlay::Pairing);
+}
+
+/// Six digits split in two groups, matching how phones render the code.
+fn format_code(passkey: u32) -> String {
+    let digits = format!("{passkey:06}");
+    format!("{} {}", &digits[..3], &digits[3..])
+}
@@ -0,0 +1,82 @@
+//! Burn-in mitigation for a screen left on one page for days. Every
+//! [`SHIFT_MINUTES`] the whole UI moves to the next step of [`PATTERN`],
+//! never more than 2 px from where it was laid out, through
+//! [`display::set_panel_offset`]. Slint lays out and hit-tests as before;
+//! only the lines sent to the panel and the touch points coming back move.
+//!
+//! A step waits for a settled screen: nothing animating, nothing on top of
+//! the page, and no input for [`QUIET`], so a shift never lands mid-swipe
+//! or mid-transition.
+
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use super::display;
+use crate::settings::{self, SettingKey};
+
+pub const PIXEL_SHIFT: SettingKey<bool> = SettingKey::new("gui_pxshift", "true");
+pub const SHIFT_MINUTES: SettingKey<u32> =
+    SettingKey::new("gui_pxshift_min", "5").validated(|minutes| {
+        if (1..=60).contains(minutes) {
+            Ok(())
+        } else {
+            Err("pixel shift interval is 1 to 60 minutes".into())
+        }
+    });
+
+/// One pixel per step, circling the laid-out position.
+const PATTERN: [(i8, i8); 8] = [
+    (0, 0),
+    (1, 1),
+    (2, 0),
+    (1, -1),
+    (0, -2),
+    (-1, -1),
+    (-2, 0),
+    (-1, 1),
+];
+const QUIET: Duration = Duration::from_secs(10);
+
+struct State {
+    step: usize,
+    shifted: Instant,
+    input: Instant,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State {
+        step: 0,
+        shifted: Instant::now(),
+        input: Instant::now(),
+    });
+}
+
+/// Called on every touch and key input.
+pub(super) fn on_input() {
+    STATE.with(|state| state.borrow_mut().input = Instant::now());
+}
+
+/// Called before every frame; `settled` says nothing animates and nothing
+/// covers the page.
+pub(super) fn on_frame(settled: bool) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !settings::get(&PIXEL_SHIFT) {
+            if state.step != 0 {
+                state.step = 0;
+                display::set_panel_offset(0, 0);
+            }
+            return;
+        }
+        let period = Duration::from_secs(u64::from(settings::get(&SHIFT_MINUTES)) * 60);
+        if !settled || state.shifted.elapsed() < period || state.input.elapsed() < QUIET {
+            return;
+        }
+        state.step = (state.step + 1) % PATTERN.len();
+        state.shifted = Instant::now();
+        let (dx, dy) = PATTERN[state.step];
+        display::set_panel_offset(dx, dy);
+    });
+}
@@ -0,0 +1,132 @@
+//! The last step before the panel: Slint's native RGB565 words, put through
+//! the board's [`PixelFixup`], handed to mipidsi. The colour-bar pattern
+//! for picking the fixup lives here too.
+
+use std::fmt;
+
+use anyhow::Result;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+    text::{Alignment, Text},
+};
+use slint::platform::software_renderer::Rgb565Pixel;
+
+use super::{
+    display::{DisplayType, TransportError},
+    slint_ui::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+};
+use crate::board::PixelFixup;
+
+/// The test pattern keeps to a centred square the round panel shows whole.
+const PATTERN_TOP: usize = 30;
+const PATTERN_LEFT: usize = 60;
+const PATTERN_WIDTH: usize = DISPLAY_WIDTH - 2 * PATTERN_LEFT;
+const BAND_HEIGHT: usize = (DISPLAY_HEIGHT - 2 * PATTERN_TOP) / PixelFixup::ALL.len();
+/// Rows of each band: label, bars, grey ramp; the rest stays black.
+const LABEL_ROWS: usize = 11;
+const BAR_ROWS: usize = 20;
+const RAMP_ROWS: usize = 12;
+/// Red, green, blue, left to right, as the letters say.
+const BARS: [(u16, &str); 3] = [(0xF800, "R"), (0x07E0, "G"), (0x001F, "B")];
+
+/// Sends `pixels` to `area` with `fixup`. The fixup is matched once per
+/// call and each arm passes [`PixelFixup::apply`] constant flags, so it runs
+/// its own branch-free loop and `NONE` costs nothing over the plain
+/// conversion.
+pub fn fill<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    area: &Rectangle,
+    pixels: &[Rgb565Pixel],
+    fixup: PixelFixup,
+) -> Result<(), D::Error> {
+    fn send<D: DrawTarget<Color = Rgb565>>(
+        target: &mut D,
+        area: &Rectangle,
+        pixels: &[Rgb565Pixel],
+        fix: impl Fn(u16) -> u16,
+    ) -> Result<(), D::Error> {
+        let colors = pixels
+            .iter()
+            .map(|Rgb565Pixel(pixel)| Rgb565::from(RawU16::new(fix(*pixel))));
+        target.fill_contiguous(area, colors)
+    }
+
+    fn fixed<const SWAP_BYTES: bool, const SWAP_RB: bool>(pixel: u16) -> u16 {
+        PixelFixup {
+            swap_bytes: SWAP_BYTES,
+            swap_rb: SWAP_RB,
+        }
+        .apply(pixel)
+    }
+
+    match (fixup.swap_bytes, fixup.swap_rb) {
+        (false, false) => send(target, area, pixels, fixed::<false, false>),
+        (true, false) => send(target, area, pixels, fixed::<true, false>),
+        (false, true) => send(target, area, pixels, fixed::<false, true>),
+        (true, true) => send(target, area, pixels, fixed::<true, true>),
+    }
+}
+
+/// One band per [`PixelFixup::ALL`] entry, top to bottom, each sent with
+/// that fixup instead of the board's: red, green and blue bars under their
+/// letters, then a grey ramp. The right fixup is the band whose bars match
+/// their letters and whose ramp is smooth. Text is white or black, which
+/// reads the same under every fixup.
+pub fn draw_colorbars(display: &mut DisplayType<'static>) -> Result<()> {
+    let bar_width = PATTERN_WIDTH / BARS.len();
+    let bars: Vec<Rgb565Pixel> = (0..PATTERN_WIDTH)
+        .map(|x| Rgb565Pixel(BARS[x / bar_width].0))
+        .collect();
+    let ramp: Vec<Rgb565Pixel> = (0..PATTERN_WIDTH)
+        .map(|x| {
+            let level = (x * 31 / (PATTERN_WIDTH - 1)) as u16;
+            Rgb565Pixel(level << 11 | level << 6 | level)
+        })
+        .collect();
+    let label = MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE);
+    let letter = MonoTextStyle::new(&FONT_6X10, Rgb565::BLACK);
+    let center = (DISPLAY_WIDTH / 2) as i32;
+
+    display.clear(Rgb565::BLACK).map_err(transport("clear"))?;
+    for (band, fixup) in PixelFixup::ALL.into_iter().enumerate() {
+        let top = PATTERN_TOP + band * BAND_HEIGHT;
+        Text::with_alignment(
+            &format!("{}: {fixup}", band + 1),
+            Point::new(center, (top + LABEL_ROWS - 3) as i32),
+            label,
+            Alignment::Center,
+        )
+        .draw(display)
+        .map_err(transport("label"))?;
+
+        let bars_top = top + LABEL_ROWS;
+        let ramp_top = bars_top + BAR_ROWS;
+        for y in bars_top..ramp_top + RAMP_ROWS {
+            let row = if y < ramp_top { &bars } else { &ramp };
+            let area = Rectangle::new(
+                Point::new(PATTERN_LEFT as i32, y as i32),
+                Size::new(PATTERN_WIDTH as u32, 1),
+            );
+            fill(display, &area, row, fixup).map_err(transport("rows"))?;
+        }
+        for (index, (_, name)) in BARS.iter().enumerate() {
+            let x = PATTERN_LEFT + index * bar_width + bar_width / 2;
+            Text::with_alignment(
+                name,
+                Point::new(x as i32, (bars_top + BAR_ROWS / 2 + 3) as i32),
+                letter,
+                Alignment::Center,
+            )
+            .draw(display)
+            .map_err(transport("letters"))?;
+        }
+    }
+    Ok(())
+}
+
+fn transport<E: fmt::Debug>(what: &'static str) -> impl Fn(E) -> TransportError {
+    move |e| TransportError(format!("colour bars {what}: {e:?}"))
+}
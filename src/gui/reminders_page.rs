@@ -0,0 +1,182 @@
+use std::{cell::Cell, time::Duration};
+
+use slint::SharedString;
+
+use super::{
+    backlight,
+    bounded_model::BoundedModel,
+    slint_ui::{self, App, Overlay, Page, ReminderEntry},
+    toast,
+};
+use crate::{
+    i18n,
+    reminders::{self, Fired, Outcome, Reminder, Schedule, MAX_REMINDERS},
+    timesync,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+thread_local! {
+    static REMINDER_MODEL: BoundedModel<ReminderEntry> = BoundedModel::new("reminders", MAX_REMINDERS);
+    /// The reminder in the dialog; later firings wait in `reminders`.
+    static SHOWING: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+pub fn install(app: &App) {
+    app.set_reminders(REMINDER_MODEL.with(BoundedModel::model));
+
+    app.on_reminder_toggle(|id| {
+        let Some(reminder) = reminder(id) else {
+            return;
+        };
+        report(reminders::set_enabled(reminder.id, !reminder.enabled));
+    });
+    app.on_reminder_delete(|id| {
+        let Some(reminder) = reminder(id) else {
+            return;
+        };
+        if report(reminders::delete(reminder.id)) {
+            toast::show(i18n::trf("Deleted \"{}\"", &[&reminder.label]));
+        }
+    });
+    app.on_reminder_save(|id, every, hour, minute, label| {
+        let schedule = if every {
+            let minutes = (hour.clamp(0, 23) * 60 + minute.clamp(0, 59)) as u32;
+            Ok(Schedule::Every { minutes })
+        } else {
+            reminders::next_at(hour.clamp(0, 23) as u8, minute.clamp(0, 59) as u8)
+                .map(|at| Schedule::Once { at })
+        };
+        let saved = schedule.and_then(|schedule| match u32::try_from(id) {
+            Ok(id) => reminders::update(id, &label, schedule).map(|()| schedule),
+            Err(_) => reminders::add(&label, schedule).map(|added| added.schedule),
+        });
+        match saved {
+            Ok(schedule) => toast::show(i18n::trf("Reminder {}", &[&describe(&schedule)])),
+            Err(err) => {
+                report(Err(err));
+            }
+        }
+    });
+    app.on_reminder_snooze(|| {
+        if let Some(id) = SHOWING.take() {
+            if report(reminders::snooze(id)) {
+                toast::show(i18n::trf(
+                    "Snoozed for {} min",
+                    &[&(reminders::SNOOZE.as_secs() / 60)],
+                ));
+            }
+        }
+        slint_ui::remove_overlay(Overlay::Reminder);
+    });
+    app.on_reminder_dismiss(|| {
+        SHOWING.set(None);
+        slint_ui::remove_overlay(Overlay::Reminder);
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            if SHOWING.get().is_none() {
+                if let Some(fired) = reminders::take_fired() {
+                    alert(&fired);
+                }
+            }
+            let current = (reminders::generation(), i18n::active());
+            if slint_ui::current_page() == Some(Page::Reminders) && seen != Some(current) {
+                show(&reminders::list());
+                seen = Some(current);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+fn alert(fired: &Fired) {
+    SHOWING.set(Some(fired.id));
+    let detail = match fired.outcome {
+        Outcome::Watch => i18n::tr("Sent to the watch"),
+        Outcome::LocalOnly => i18n::tr("Watch not connected; shown here only"),
+        Outcome::Queued => i18n::tr("Watch not connected; sent when it is"),
+    };
+    slint_ui::with_app(|app| {
+        app.set_reminder_alert_label(SharedString::from(fired.label.as_str()));
+        app.set_reminder_alert_detail(SharedString::from(detail));
+    });
+    backlight::set_screen_on(true);
+    slint_ui::push_overlay(Overlay::Reminder);
+}
+
+fn reminder(id: i32) -> Option<Reminder> {
+    let id = u32::try_from(id).ok()?;
+    reminders::list()
+        .into_iter()
+        .find(|reminder| reminder.id == id)
+}
+
+/// Toasts a failure; true when it went through.
+fn report(result: anyhow::Result<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            toast::show(i18n::trf("Reminder failed: {}", &[&err]));
+            false
+        }
+    }
+}
+
+fn show(list: &[Reminder]) {
+    let entries: Vec<ReminderEntry> = list
+        .iter()
+        .map(|reminder| {
+            let (every, hour, minute) = match reminder.schedule {
+                Schedule::Once { at } => {
+                    let local = timesync::zone().local(at);
+                    (false, local.hour.into(), local.minute.into())
+                }
+                Schedule::Every { minutes } => (true, (minutes / 60) as i32, (minutes % 60) as i32),
+            };
+            ReminderEntry {
+                id: reminder.id as i32,
+                label: SharedString::from(reminder.label.as_str()),
+                detail: SharedString::from(detail(reminder)),
+                enabled: reminder.enabled,
+                every,
+                hour,
+                minute,
+            }
+        })
+        .collect();
+    let summary = i18n::trf("{} of {} reminders", &[&list.len(), &MAX_REMINDERS]);
+    REMINDER_MODEL.with(|model| model.set_vec(entries));
+    slint_ui::with_app(|app| {
+        app.set_reminders_summary(SharedString::from(summary));
+        app.set_reminders_can_add(list.len() < MAX_REMINDERS);
+    });
+}
+
+fn describe(schedule: &Schedule) -> String {
+    match *schedule {
+        Schedule::Once { at } => {
+            let local = timesync::zone().local(at);
+            let time = format!("{:02}:{:02}", local.hour, local.minute);
+            i18n::trf("at {}", &[&time])
+        }
+        Schedule::Every { minutes } if minutes % 60 == 0 => {
+            i18n::trf("every {} h", &[&(minutes / 60)])
+        }
+        Schedule::Every { minutes } => i18n::trf("every {} min", &[&minutes]),
+    }
+}
+
+fn detail(reminder: &Reminder) -> String {
+    let schedule = describe(&reminder.schedule);
+    match reminder.last.map(|last| last.outcome) {
+        None => schedule,
+        Some(Outcome::Watch) => format!("{schedule} · {}", i18n::tr("last sent to watch")),
+        Some(Outcome::LocalOnly) => {
+            format!("{schedule} · {}", i18n::tr("last delivered locally only"))
+        }
+        Some(Outcome::Queued) => format!("{schedule} · {}", i18n::tr("last queued for watch")),
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use slint::SharedString;
+
+use super::{
+    pages::{Create, PageDescriptor, PageEvent},
+    slint_ui::{self, App, Gesture, Page},
+    toast,
+};
+use crate::{
+    ble::hid::{self, RemoteKey},
+    i18n,
+    input::button::ButtonPress,
+};
+
+/// Host subscriptions come and go with pairing; this is quick enough to
+/// follow them.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Opened from Settings, so not in the carousel.
+pub const PAGE: PageDescriptor = PageDescriptor {
+    on_event: Some(on_event),
+    listed: false,
+    ..PageDescriptor::new("remote", "Remote", Create::Slint(Page::Remote))
+};
+
+pub fn install(app: &App) {
+    app.on_remote_key(|code| {
+        if let Ok(key) = code.parse() {
+            tap(key);
+        }
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            if slint_ui::current_page() == Some(Page::Remote) {
+                let state = (hid::connected(), i18n::active());
+                if seen != Some(state) {
+                    show(state.0);
+                    seen = Some(state);
+                }
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+/// Swipes turn slides and the side key is a camera shutter. A long press
+/// still falls through to its own action.
+fn on_event(_app: &App, event: &PageEvent) -> bool {
+    let PageEvent::Gesture(gesture) = *event else {
+        return false;
+    };
+    let key = match gesture {
+        Gesture::SwipeLeft => RemoteKey::PageDown,
+        Gesture::SwipeRight => RemoteKey::PageUp,
+        Gesture::SwipeUp => RemoteKey::VolumeUp,
+        Gesture::SwipeDown => RemoteKey::VolumeDown,
+        Gesture::Button(ButtonPress::Short) => RemoteKey::VolumeUp,
+        Gesture::Button(ButtonPress::Double) => RemoteKey::PlayPause,
+        Gesture::Button(ButtonPress::Long) => return false,
+    };
+    tap(key);
+    true
+}
+
+fn tap(key: RemoteKey) {
+    if !hid::tap(key) {
+        toast::show(i18n::tr("No device connected to the remote"));
+    }
+}
+
+fn show(connected: bool) {
+    let hint = if connected {
+        i18n::tr("Connected").to_string()
+    } else {
+        i18n::trf("Pair {} from your phone", &[&hid::DEVICE_NAME])
+    };
+    slint_ui::with_app(|app| {
+        app.set_remote_connected(connected);
+        app.set_remote_hint(SharedString::from(hint));
+    });
+}
@@ -0,0 +1,233 @@
+//! On-device render profiling. While enabled, every drawn frame is timed
+//! per band of [`BAND_LINES`] lines, with rendering and flushing counted
+//! apart, and its dirty extent and line counts are noted. The
+//! [`KEEP`] slowest frames since enabling are kept for
+//! `render profile dump` and `/debug/render`.
+//!
+//! The flag is read once per frame; with it off the line provider takes no
+//! timestamps at all. Profiling only observes and never requests a redraw,
+//! so dirty regions are the same either way.
+
+use std::{
+    fmt::Write as _,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use super::{display, slint_ui::DISPLAY_HEIGHT};
+
+pub const BAND_LINES: usize = 16;
+pub const BANDS: usize = DISPLAY_HEIGHT.div_ceil(BAND_LINES);
+const KEEP: usize = 10;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PROFILED: AtomicU32 = AtomicU32::new(0);
+/// Slowest first.
+static WORST: Mutex<Vec<FrameProfile>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug)]
+pub struct FrameProfile {
+    /// Page, plus the overlay on top of it if any.
+    pub view: String,
+    /// Since boot, on the UI clock.
+    pub at: Duration,
+    pub total: Duration,
+    pub render: Duration,
+    pub flush: Duration,
+    pub band_render_us: [u32; BANDS],
+    /// A batched flush lands in the band of the line that completed it.
+    pub band_flush_us: [u32; BANDS],
+    pub lines_rendered: u32,
+    /// Lines sent to the panel; the frame cache skips unchanged ones.
+    pub lines_flushed: u32,
+    pub extent: Option<Extent>,
+}
+
+/// Bounding box of the lines the renderer produced, in pixels, inclusive.
+#[derive(Clone, Copy, Debug)]
+pub struct Extent {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+/// Collects one frame; the line provider holds one while profiling is on.
+pub(super) struct Recorder {
+    started: Duration,
+    last_line: usize,
+    profile: FrameProfile,
+}
+
+impl Recorder {
+    pub(super) fn start(now: Duration) -> Self {
+        Self {
+            started: now,
+            last_line: 0,
+            profile: FrameProfile {
+                view: String::new(),
+                at: now,
+                total: Duration::ZERO,
+                render: Duration::ZERO,
+                flush: Duration::ZERO,
+                band_render_us: [0; BANDS],
+                band_flush_us: [0; BANDS],
+                lines_rendered: 0,
+                lines_flushed: 0,
+                extent: None,
+            },
+        }
+    }
+
+    pub(super) fn rendered(&mut self, line: usize, range: &Range<usize>, took: Duration) {
+        let profile = &mut self.profile;
+        profile.render += took;
+        profile.lines_rendered += 1;
+        add_micros(&mut profile.band_render_us[band(line)], took);
+        let right = range.end.saturating_sub(1);
+        profile.extent = Some(match profile.extent {
+            Some(extent) => Extent {
+                top: extent.top.min(line),
+                bottom: extent.bottom.max(line),
+                left: extent.left.min(range.start),
+                right: extent.right.max(right),
+            },
+            None => Extent {
+                top: line,
+                bottom: line,
+                left: range.start,
+                right,
+            },
+        });
+        self.last_line = line;
+    }
+
+    pub(super) fn flushed(&mut self, line: usize, took: Duration) {
+        self.profile.flush += took;
+        self.profile.lines_flushed += 1;
+        add_micros(&mut self.profile.band_flush_us[band(line)], took);
+    }
+
+    /// The final partial batch, sent after the last line.
+    pub(super) fn flushed_tail(&mut self, took: Duration) {
+        self.profile.flush += took;
+        add_micros(&mut self.profile.band_flush_us[band(self.last_line)], took);
+    }
+
+    /// Keeps the frame if it is among the slowest. Frames that drew
+    /// nothing are dropped.
+    pub(super) fn finish(mut self, now: Duration, view: String) {
+        if self.profile.lines_rendered == 0 {
+            return;
+        }
+        self.profile.total = now.saturating_sub(self.started);
+        self.profile.view = view;
+        PROFILED.fetch_add(1, Ordering::Relaxed);
+        let Ok(mut worst) = WORST.lock() else {
+            return;
+        };
+        let at = worst
+            .iter()
+            .position(|kept| kept.total < self.profile.total)
+            .unwrap_or(worst.len());
+        if at < KEEP {
+            worst.insert(at, self.profile);
+            worst.truncate(KEEP);
+        }
+    }
+}
+
+fn band(line: usize) -> usize {
+    (line / BAND_LINES).min(BANDS - 1)
+}
+
+fn add_micros(slot: &mut u32, took: Duration) {
+    *slot = slot.saturating_add(took.as_micros() as u32);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turning profiling on starts a fresh worst-frames list.
+pub fn set_enabled(on: bool) {
+    if on && !enabled() {
+        if let Ok(mut worst) = WORST.lock() {
+            worst.clear();
+        }
+        PROFILED.store(0, Ordering::Relaxed);
+    }
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+/// Frames profiled since enabling.
+pub fn profiled() -> u32 {
+    PROFILED.load(Ordering::Relaxed)
+}
+
+/// The slowest frames since enabling, slowest first.
+pub fn worst() -> Vec<FrameProfile> {
+    WORST.lock().map(|worst| worst.clone()).unwrap_or_default()
+}
+
+fn dump() -> String {
+    let worst = worst();
+    let (dx, dy) = display::panel_offset();
+    let mut out = format!(
+        "render profile {}, {} frames profiled, panel offset {dx:+},{dy:+}",
+        if enabled() { "on" } else { "off" },
+        profiled()
+    );
+    for (rank, frame) in worst.iter().enumerate() {
+        let extent = frame.extent.map_or_else(
+            || "-".to_string(),
+            |e| format!("x {}-{} y {}-{}", e.left, e.right, e.top, e.bottom),
+        );
+        let _ = write!(
+            out,
+            "\n#{} {} at {} ms: {} us (render {}, flush {}), lines {}/{} flushed, {extent}",
+            rank + 1,
+            frame.view,
+            frame.at.as_millis(),
+            frame.total.as_micros(),
+            frame.render.as_micros(),
+            frame.flush.as_micros(),
+            frame.lines_flushed,
+            frame.lines_rendered,
+        );
+        for band in 0..BANDS {
+            let (render, flush) = (frame.band_render_us[band], frame.band_flush_us[band]);
+            if render > 0 || flush > 0 {
+                let _ = write!(
+                    out,
+                    "\n    y {:>3}: render {render} us, flush {flush} us",
+                    band * BAND_LINES
+                );
+            }
+        }
+    }
+    out
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "render",
+        "profile on|off|dump: per-band render timing, slowest frames",
+        |args| match args {
+            ["profile", "on"] => {
+                set_enabled(true);
+                Ok("render profile on".to_string())
+            }
+            ["profile", "off"] => {
+                set_enabled(false);
+                Ok("render profile off; dump still shows the last run".to_string())
+            }
+            ["profile", "dump"] => Ok(dump()),
+            _ => anyhow::bail!("usage: render profile on|off|dump"),
+        },
+    );
+}
@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use log::warn;
+use slint::SharedString;
+
+use super::{
+    pages::{Create, PageDescriptor},
+    pixel_shift,
+    slint_ui::{self, App, Overlay, Page},
+    toast,
+};
+#[cfg(feature = "ancs")]
+use crate::{
+    ble::hid,
+    miwear::ancs::{self, Mode},
+};
+use crate::{
+    i18n::{self, Language},
+    power::download_mode,
+    sensors::imu,
+    settings::{self, rollback},
+};
+
+/// How quickly a console `ancs on|off` or `hid on|off` shows on the open
+/// page.
+#[cfg(feature = "ancs")]
+const ANCS_POLL: Duration = Duration::from_secs(1);
+const ROLLBACK_POLL: Duration = Duration::from_secs(1);
+/// Picks up the IMU once its boot stage ran, and `imu autorotate` changes.
+const IMU_POLL: Duration = Duration::from_secs(1);
+/// How quickly a setting written over HTTP shows.
+const SETTINGS_POLL: Duration = Duration::from_millis(500);
+
+pub const PAGE: PageDescriptor =
+    PageDescriptor::new("settings", "Settings", Create::Slint(Page::Settings));
+
+pub fn install(app: &App) {
+    apply(i18n::active());
+    app.set_language_name(SharedString::from(i18n::active().native_name()));
+
+    app.on_language_cycle(|| {
+        let next = i18n::active().next();
+        if let Err(err) = i18n::set_language(next) {
+            warn!("Failed to save language: {err:?}");
+            return;
+        }
+        apply(next);
+        slint_ui::with_app(|app| {
+            app.set_language_name(SharedString::from(next.native_name()));
+            #[cfg(feature = "ancs")]
+            super::pairing::refresh_mode(app);
+        });
+    });
+
+    #[cfg(feature = "ancs")]
+    install_ancs(app);
+    #[cfg(feature = "ancs")]
+    install_hid(app);
+
+    install_rollback(app);
+    install_imu(app);
+    install_pixel_shift(app);
+    follow_settings();
+
+    app.on_download_mode_armed(|| slint_ui::push_overlay(Overlay::DownloadMode));
+    app.on_download_mode_cancel(|| slint_ui::remove_overlay(Overlay::DownloadMode));
+    app.on_download_mode_confirm(|| {
+        slint_ui::remove_overlay(Overlay::DownloadMode);
+        if let Err(err) = download_mode::request("settings page") {
+            toast::show(err.to_string());
+        }
+    });
+}
+
+fn install_rollback(app: &App) {
+    app.on_settings_undo(|| match rollback::undo() {
+        Ok(()) => toast::show(i18n::tr("Restoring previous settings")),
+        Err(err) => toast::show(err.to_string()),
+    });
+    app.on_rollback_notice_dismiss(rollback::dismiss_notice);
+
+    // Not tied to the Settings page: the notice shows everywhere.
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            let state = (rollback::generation(), i18n::active());
+            if seen != Some(state) {
+                let undo = rollback::pending()
+                    .map(|(subsystem, _)| i18n::tr(subsystem.label()))
+                    .unwrap_or("");
+                let notice = rollback::notice()
+                    .map(|notice| {
+                        i18n::trf(
+                            "{} change undone: no connection",
+                            &[&i18n::tr(notice.subsystem.label())],
+                        )
+                    })
+                    .unwrap_or_default();
+                slint_ui::with_app(|app| {
+                    app.set_undo_subsystem(SharedString::from(undo));
+                    app.set_rollback_notice(SharedString::from(notice));
+                });
+                seen = Some(state);
+            }
+            tokio::time::sleep(ROLLBACK_POLL).await;
+        }
+    });
+}
+
+fn install_imu(app: &App) {
+    app.on_auto_rotate_toggle(|| {
+        let enable = !settings::get(&imu::AUTO_ROTATE);
+        if let Err(err) = settings::set(&imu::AUTO_ROTATE, &enable) {
+            warn!("Failed to save auto-rotate: {err:#}");
+            return;
+        }
+        if enable {
+            toast::show(i18n::trf(
+                "Hold a new position {} s to rotate",
+                &[&imu::STABLE_FOR.as_secs()],
+            ));
+        }
+        slint_ui::with_app(|app| app.set_auto_rotate(enable));
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            if slint_ui::current_page() == Some(Page::Settings) {
+                let state = (imu::present(), settings::get(&imu::AUTO_ROTATE));
+                if seen != Some(state) {
+                    slint_ui::with_app(|app| {
+                        app.set_imu_present(state.0);
+                        app.set_auto_rotate(state.1);
+                    });
+                    seen = Some(state);
+                }
+            }
+            tokio::time::sleep(IMU_POLL).await;
+        }
+    });
+}
+
+fn install_pixel_shift(app: &App) {
+    app.set_pixel_shift(settings::get(&pixel_shift::PIXEL_SHIFT));
+    app.on_pixel_shift_toggle(|| {
+        let enable = !settings::get(&pixel_shift::PIXEL_SHIFT);
+        if let Err(err) = settings::set(&pixel_shift::PIXEL_SHIFT, &enable) {
+            warn!("Failed to save pixel shift: {err:#}");
+            return;
+        }
+        slint_ui::with_app(|app| app.set_pixel_shift(enable));
+    });
+}
+
+/// Rows whose setting can change behind the page's back (`/settings`, the
+/// console). The theme, text size and auto-rotate have their own loops.
+fn follow_settings() {
+    tokio::task::spawn_local(async {
+        let mut seen = settings::generation();
+        loop {
+            tokio::time::sleep(SETTINGS_POLL).await;
+            let generation = settings::generation();
+            if generation == seen {
+                continue;
+            }
+            seen = generation;
+            let language = settings::get(&i18n::LANGUAGE);
+            if language != i18n::active() {
+                i18n::init();
+                apply(language);
+            }
+            slint_ui::with_app(|app| {
+                app.set_language_name(SharedString::from(language.native_name()));
+                #[cfg(feature = "ancs")]
+                super::pairing::refresh_mode(app);
+                app.set_pixel_shift(settings::get(&pixel_shift::PIXEL_SHIFT));
+            });
+        }
+    });
+}
+
+#[cfg(feature = "ancs")]
+fn install_ancs(app: &App) {
+    app.set_ancs_mode(SharedString::from(ancs::mode().code()));
+    // Keyed on the setting so a pending reboot can still be switched off.
+    app.on_ancs_toggle(|| {
+        let enable = !settings::get(&ancs::ANCS_ENABLED);
+        match ancs::set_enabled(enable, false) {
+            Ok(Mode::Off) => toast::show(i18n::tr("Phantom phone off; bonds kept")),
+            Ok(_) => {}
+            Err(err) => {
+                warn!("Failed to switch the fake ANCS service: {err:#}");
+                toast::show(i18n::trf("Phantom phone: {}", &[&err]));
+            }
+        }
+        slint_ui::with_app(|app| app.set_ancs_mode(SharedString::from(ancs::mode().code())));
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            if slint_ui::current_page() == Some(Page::Settings) {
+                let mode = ancs::mode();
+                if seen != Some(mode) {
+                    slint_ui::with_app(|app| app.set_ancs_mode(SharedString::from(mode.code())));
+                    seen = Some(mode);
+                }
+            }
+            tokio::time::sleep(ANCS_POLL).await;
+        }
+    });
+}
+
+/// The HID service is only added at boot, so the row flips the setting and
+/// asks for a reboot whenever it disagrees with what is registered.
+#[cfg(feature = "ancs")]
+fn install_hid(app: &App) {
+    app.set_hid_mode(SharedString::from(hid::mode().code()));
+    app.on_hid_toggle(|| {
+        let enable = !settings::get(&hid::HID_ENABLED);
+        if let Err(err) = settings::set(&hid::HID_ENABLED, &enable) {
+            warn!("Failed to save the BLE remote setting: {err:#}");
+            return;
+        }
+        let mode = hid::mode();
+        if mode == hid::Mode::RebootRequired {
+            toast::show(i18n::tr("Reboot to apply"));
+        }
+        slint_ui::with_app(|app| app.set_hid_mode(SharedString::from(mode.code())));
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            if slint_ui::current_page() == Some(Page::Settings) {
+                let mode = hid::mode();
+                if seen != Some(mode) {
+                    slint_ui::with_app(|app| app.set_hid_mode(SharedString::from(mode.code())));
+                    seen = Some(mode);
+                }
+            }
+            tokio::time::sleep(ANCS_POLL).await;
+        }
+    });
+}
+
+/// Switches `@tr()` strings; Rust-formatted text follows on its next refresh.
+fn apply(language: Language) {
+    if let Err(err) = slint::select_bundled_translation(language.code()) {
+        warn!("No bundled translation for {language}: {err:?}");
+    }
+}
@@ -0,0 +1,445 @@
+//! The first-run setup wizard: language, Wi-Fi, time, watch, brightness and
+//! a summary, one full-screen step at a time. It opens at boot until
+//! [`SETUP_COMPLETE`] is set, which finishing or skipping it does, and can
+//! be opened again from Settings or with the `setup` console command.
+//!
+//! The step order and the Next/Skip rules live in [`wizard`]; this module
+//! feeds it the device state and drives the page. Each step uses the
+//! capability that already exists for it: the Networks page for Wi-Fi, the
+//! SNTP client for time, a BLE scan and `miwear::targets` for the watch.
+
+mod wizard;
+
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use anyhow::bail;
+use log::{info, warn};
+use slint::SharedString;
+
+use self::wizard::{Facts, Outcome, Step, Wizard};
+use super::{
+    backlight,
+    bounded_model::BoundedModel,
+    slint_ui::{self, App, Page, SetupStep, SetupWatch},
+    toast,
+};
+use crate::{
+    i18n,
+    miwear::{
+        self,
+        scan::{Scan, Sighting, Verdict},
+        targets::{self, MiWearTarget},
+    },
+    settings::{self, SettingKey},
+    timesync, wifi,
+};
+
+pub const SETUP_COMPLETE: SettingKey<bool> = SettingKey::new("setup_complete", "false");
+
+/// Wi-Fi and the clock have no events; the open page checks at this rate.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+const WATCH_SCAN: Duration = Duration::from_secs(10);
+const MAX_WATCHES: usize = 8;
+const BRIGHTNESS_STEP: i32 = 10;
+/// The wizard's floor; a darker screen is still a setting away.
+const MIN_BRIGHTNESS: i32 = 10;
+const AUTH_KEY_LEN: usize = 32;
+/// Key characters shown while typing; the head scrolls off.
+const KEY_TAIL: usize = 16;
+
+/// Set by the console, which runs on its own thread.
+static RELAUNCH: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static WIZARD: RefCell<Option<Wizard>> = const { RefCell::new(None) };
+    static WATCH_MODEL: BoundedModel<SetupWatch> = BoundedModel::new("setup_watches", MAX_WATCHES);
+    static WATCH: RefCell<WatchStep> = RefCell::new(WatchStep::default());
+}
+
+#[derive(Default)]
+struct WatchStep {
+    sightings: Vec<Sighting>,
+    scanning: bool,
+    /// The last scan's error, shown until the next scan.
+    failure: Option<String>,
+    /// Label of the target added in this run.
+    added: Option<String>,
+    /// The sighting whose key is being typed, and the key so far.
+    draft: Option<(usize, String)>,
+}
+
+pub fn install(app: &App) {
+    app.set_setup_watches(WATCH_MODEL.with(BoundedModel::model));
+    app.set_setup_wifi_available(cfg!(feature = "gui-extras"));
+
+    app.on_setup_next(|| step(|wizard, facts| wizard.next(facts)));
+    app.on_setup_skip(|| step(|wizard, facts| wizard.skip(facts)));
+    app.on_setup_back(|| step(|wizard, _| wizard.back()));
+    app.on_setup_quit(|| {
+        info!("Setup wizard skipped");
+        complete();
+    });
+    app.on_setup_relaunch(open);
+    app.on_setup_scan(scan_watches);
+    app.on_setup_watch_selected(|index| {
+        let Ok(index) = usize::try_from(index) else {
+            return;
+        };
+        edit_watch(|watch| {
+            if index < watch.sightings.len() {
+                watch.draft = Some((index, String::new()));
+            }
+        });
+    });
+    app.on_setup_key(|text| {
+        edit_watch(|watch| {
+            if let Some((_, key)) = &mut watch.draft {
+                // Auth keys are hex; anything else on the keyboard is ignored.
+                key.extend(
+                    text.chars()
+                        .filter(char::is_ascii_hexdigit)
+                        .map(|c| c.to_ascii_lowercase()),
+                );
+                key.truncate(AUTH_KEY_LEN);
+            }
+        });
+    });
+    app.on_setup_key_backspace(|| {
+        edit_watch(|watch| {
+            if let Some((_, key)) = &mut watch.draft {
+                key.pop();
+            }
+        });
+    });
+    app.on_setup_key_cancel(|| edit_watch(|watch| watch.draft = None));
+    app.on_setup_key_save(save_watch);
+    app.on_setup_brightness_step(|direction| {
+        let current = i32::from(settings::get(&backlight::BRIGHTNESS_PERCENT));
+        let target = (current + direction.signum() * BRIGHTNESS_STEP).clamp(MIN_BRIGHTNESS, 100);
+        if let Err(err) = settings::set(&backlight::BRIGHTNESS_PERCENT, &(target as u8)) {
+            warn!("Failed to save brightness: {err:#}");
+        }
+        show();
+    });
+
+    tokio::task::spawn_local(async {
+        // After the App exists: navigation needs it.
+        if !settings::get(&SETUP_COMPLETE) {
+            info!("First run, opening the setup wizard");
+            open();
+        }
+        let mut seen = None;
+        loop {
+            if RELAUNCH.swap(false, Ordering::Relaxed) {
+                open();
+            }
+            if slint_ui::current_page() == Some(Page::Setup) {
+                let current = (facts(), i18n::active());
+                if seen != Some(current) {
+                    show();
+                    seen = Some(current);
+                }
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+/// Opens the wizard from its first step; safe from any thread.
+pub fn relaunch() {
+    RELAUNCH.store(true, Ordering::Relaxed);
+}
+
+fn open() {
+    let mut steps = vec![Step::Language];
+    if cfg!(feature = "gui-extras") {
+        steps.push(Step::Wifi);
+    }
+    steps.push(Step::Time);
+    if !miwear::demo::enabled() {
+        steps.push(Step::Watch);
+    }
+    steps.extend([Step::Brightness, Step::Summary]);
+    WIZARD.with(|cell| *cell.borrow_mut() = Some(Wizard::new(steps)));
+    WATCH.with(|cell| *cell.borrow_mut() = WatchStep::default());
+    WATCH_MODEL.with(BoundedModel::clear);
+    slint_ui::navigate(Page::Setup);
+    show();
+}
+
+fn complete() {
+    if let Err(err) = settings::set(&SETUP_COMPLETE, &true) {
+        warn!("Failed to save {}: {err:#}", SETUP_COMPLETE.name);
+    }
+    WIZARD.with(|cell| *cell.borrow_mut() = None);
+    WATCH.with(|cell| *cell.borrow_mut() = WatchStep::default());
+    slint_ui::navigate(Page::Home);
+}
+
+/// Runs a Next, Skip or Back against the live wizard and shows the result.
+fn step(action: impl FnOnce(&mut Wizard, &Facts) -> bool) {
+    let facts = facts();
+    let Some((moved, finished, current)) = WIZARD.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let wizard = cell.as_mut()?;
+        let moved = action(wizard, &facts);
+        Some((moved, wizard.finished(), wizard.step()))
+    }) else {
+        return;
+    };
+    if finished {
+        info!("Setup wizard finished");
+        complete();
+        return;
+    }
+    if moved && current == Step::Watch && !facts.watch_added {
+        scan_watches();
+    }
+    show();
+}
+
+fn facts() -> Facts {
+    Facts {
+        wifi_connected: wifi::connected(),
+        clock_set: timesync::source().source.is_some(),
+        watch_added: WATCH.with(|cell| cell.borrow().added.is_some())
+            || targets::active().is_some(),
+    }
+}
+
+fn edit_watch(f: impl FnOnce(&mut WatchStep)) {
+    WATCH.with(|cell| f(&mut cell.borrow_mut()));
+    show();
+}
+
+fn scan_watches() {
+    let already = WATCH.with(|cell| {
+        let mut watch = cell.borrow_mut();
+        let already = watch.scanning;
+        if !already {
+            watch.scanning = true;
+            watch.failure = None;
+            watch.sightings.clear();
+        }
+        already
+    });
+    if already {
+        return;
+    }
+    WATCH_MODEL.with(BoundedModel::clear);
+    show();
+    tokio::task::spawn_local(async {
+        // Only named devices can be told apart on this screen.
+        let mut scan = Scan::start("setup", WATCH_SCAN, Some(MAX_WATCHES), |sighting| {
+            if sighting.name.is_some() && !sighting.repeat {
+                Verdict::Report
+            } else {
+                Verdict::Skip
+            }
+        });
+        while let Some(sighting) = scan.results_stream().recv().await {
+            WATCH_MODEL.with(|model| model.push(watch_entry(&sighting)));
+            WATCH.with(|cell| cell.borrow_mut().sightings.push(sighting));
+            show();
+        }
+        let result = scan.finish().await;
+        WATCH.with(|cell| {
+            let mut watch = cell.borrow_mut();
+            watch.scanning = false;
+            watch.failure = result.err().map(|err| format!("{err:#}"));
+        });
+        show();
+    });
+}
+
+fn watch_entry(sighting: &Sighting) -> SetupWatch {
+    SetupWatch {
+        name: SharedString::from(sighting.name.clone().unwrap_or_default()),
+        detail: SharedString::from(format!("{}  {} dBm", sighting.addr, sighting.rssi)),
+    }
+}
+
+fn save_watch() {
+    let result = WATCH.with(|cell| {
+        let mut watch = cell.borrow_mut();
+        let Some((index, key)) = watch.draft.clone() else {
+            return Ok(None);
+        };
+        let Some(sighting) = watch.sightings.get(index) else {
+            bail!("that watch is gone from the list");
+        };
+        let target = MiWearTarget {
+            id: 0,
+            enabled: true,
+            name: sighting.name.clone().unwrap_or_default(),
+            addr: Some(sighting.addr.to_string()),
+            nickname: String::new(),
+            auth_key: key,
+            phy: None,
+        };
+        targets::add(target.clone())?;
+        watch.added = Some(target.label().to_string());
+        watch.draft = None;
+        Ok(Some(target))
+    });
+    match result {
+        Ok(Some(target)) => {
+            info!("Setup added watch {}", target.label());
+            toast::show(i18n::trf("Added {}", &[&target.label()]));
+        }
+        Ok(None) => {}
+        Err(err) => toast::show(err.to_string()),
+    }
+    show();
+}
+
+fn show() {
+    let facts = facts();
+    let Some(view) = WIZARD.with(|cell| {
+        let cell = cell.borrow();
+        let wizard = cell.as_ref()?;
+        let (index, count) = wizard.position();
+        Some((
+            wizard.step(),
+            index,
+            count,
+            wizard.ready(&facts),
+            wizard.can_skip(&facts),
+            summary(wizard),
+        ))
+    }) else {
+        return;
+    };
+    let (current, index, count, can_next, can_skip, summary) = view;
+    let (status, scanning, draft) = WATCH.with(|cell| {
+        let watch = cell.borrow();
+        let status = match current {
+            Step::Wifi => wifi_status(&facts),
+            Step::Time => time_status(),
+            Step::Watch => watch_status(&watch),
+            _ => String::new(),
+        };
+        let draft = watch.draft.as_ref().map(|(index, key)| {
+            let name = watch
+                .sightings
+                .get(*index)
+                .and_then(|sighting| sighting.name.clone())
+                .unwrap_or_default();
+            (name, key.clone())
+        });
+        (status, watch.scanning, draft)
+    });
+    slint_ui::with_app(|app| {
+        app.set_setup_step(match current {
+            Step::Language => SetupStep::Language,
+            Step::Wifi => SetupStep::Wifi,
+            Step::Time => SetupStep::Time,
+            Step::Watch => SetupStep::Watch,
+            Step::Brightness => SetupStep::Brightness,
+            Step::Summary => SetupStep::Summary,
+        });
+        app.set_setup_index(index as i32);
+        app.set_setup_count(count as i32);
+        app.set_setup_can_next(can_next);
+        app.set_setup_can_skip(can_skip);
+        app.set_setup_status(SharedString::from(status));
+        app.set_setup_scanning(scanning);
+        app.set_setup_brightness(i32::from(settings::get(&backlight::BRIGHTNESS_PERCENT)));
+        app.set_setup_summary(SharedString::from(summary));
+        app.set_setup_key_entry(draft.is_some());
+        if let Some((name, key)) = draft {
+            app.set_setup_key_watch(SharedString::from(name));
+            app.set_setup_key_display(SharedString::from(key_display(&key)));
+            app.set_setup_key_complete(key.len() == AUTH_KEY_LEN);
+        }
+    });
+}
+
+fn key_display(key: &str) -> String {
+    if key.is_empty() {
+        return String::new();
+    }
+    let tail = match key.len().checked_sub(KEY_TAIL) {
+        Some(cut) if cut > 0 => format!("...{}", &key[cut..]),
+        _ => key.to_string(),
+    };
+    format!("{tail}  {}/{AUTH_KEY_LEN}", key.len())
+}
+
+fn wifi_status(facts: &Facts) -> String {
+    if facts.wifi_connected {
+        i18n::trf("Connected to {}", &[&settings::get(&wifi::SSID)])
+    } else {
+        i18n::tr("Not connected. Choose a network, or skip and set it up later.").to_string()
+    }
+}
+
+fn time_status() -> String {
+    match timesync::source().source {
+        Some(timesync::TimeSource::Sntp) => i18n::tr("Clock set from the network").to_string(),
+        Some(timesync::TimeSource::Resumed) => {
+            i18n::tr("Clock restored from before power-off; it is corrected once online")
+                .to_string()
+        }
+        None => i18n::tr("Waiting for network time. Connect Wi-Fi, or skip.").to_string(),
+    }
+}
+
+fn watch_status(watch: &WatchStep) -> String {
+    if let Some(label) = &watch.added {
+        return i18n::trf("{} added", &[label]);
+    }
+    if watch.scanning {
+        return i18n::tr("Scanning...").to_string();
+    }
+    if let Some(err) = &watch.failure {
+        return i18n::trf("Scan failed: {}", &[err]);
+    }
+    i18n::trf("{} found, tap to scan again", &[&watch.sightings.len()])
+}
+
+fn summary(wizard: &Wizard) -> String {
+    let outcome = |step| match wizard.outcome(step) {
+        Some(Outcome::Done) => i18n::tr("done"),
+        Some(Outcome::Skipped) | None => i18n::tr("skipped"),
+    };
+    let mut lines = vec![i18n::trf("Language: {}", &[&i18n::active().native_name()])];
+    for (step, label) in [
+        (Step::Wifi, i18n::tr("Wi-Fi")),
+        (Step::Time, i18n::tr("Time")),
+        (Step::Watch, i18n::tr("Watch")),
+    ] {
+        if wizard.has(step) {
+            lines.push(format!("{label}: {}", outcome(step)));
+        }
+    }
+    lines.push(i18n::trf("Time zone: {}", &[&timesync::zone().city()]));
+    lines.push(i18n::trf(
+        "Brightness: {}%",
+        &[&settings::get(&backlight::BRIGHTNESS_PERCENT)],
+    ));
+    lines.join("\n")
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "setup",
+        "first-run wizard: setup [run|done]",
+        |args| match args {
+            [] => Ok(format!("setup_complete={}", settings::get(&SETUP_COMPLETE))),
+            ["run"] => {
+                relaunch();
+                Ok("opening the setup wizard".to_string())
+            }
+            ["done"] => {
+                settings::set(&SETUP_COMPLETE, &true)?;
+                Ok("setup_complete=true".to_string())
+            }
+            _ => bail!("usage: setup [run|done]"),
+        },
+    );
+}
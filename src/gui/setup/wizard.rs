@@ -0,0 +1,133 @@
+//! The first-run wizard's steps and the rules for moving between them. The
+//! page passes in what it currently observes as [`Facts`]; this decides
+//! what Next, Skip and Back do, and remembers how each step ended for the
+//! summary.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Language,
+    Wifi,
+    Time,
+    Watch,
+    Brightness,
+    Summary,
+}
+
+impl Step {
+    /// Steps that can be skipped without meeting their condition.
+    pub fn optional(self) -> bool {
+        matches!(self, Step::Wifi | Step::Time | Step::Watch)
+    }
+}
+
+/// What the device looks like right now, as far as the steps care.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Facts {
+    pub wifi_connected: bool,
+    pub clock_set: bool,
+    pub watch_added: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    Skipped,
+}
+
+pub struct Wizard {
+    steps: Vec<Step>,
+    at: usize,
+    outcomes: Vec<(Step, Outcome)>,
+    finished: bool,
+}
+
+impl Wizard {
+    /// `steps` in order, without the ones this build cannot offer. The
+    /// summary is always last.
+    pub fn new(mut steps: Vec<Step>) -> Self {
+        steps.retain(|step| *step != Step::Summary);
+        steps.push(Step::Summary);
+        Self {
+            steps,
+            at: 0,
+            outcomes: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn step(&self) -> Step {
+        self.steps[self.at]
+    }
+
+    /// Zero-based index of the current step, and the number of steps.
+    pub fn position(&self) -> (usize, usize) {
+        (self.at, self.steps.len())
+    }
+
+    pub fn has(&self, step: Step) -> bool {
+        self.steps.contains(&step)
+    }
+
+    /// Next is allowed: the step's condition holds.
+    pub fn ready(&self, facts: &Facts) -> bool {
+        match self.step() {
+            Step::Wifi => facts.wifi_connected,
+            Step::Time => facts.clock_set,
+            Step::Watch => facts.watch_added,
+            Step::Language | Step::Brightness | Step::Summary => true,
+        }
+    }
+
+    pub fn can_skip(&self, facts: &Facts) -> bool {
+        self.step().optional() && !self.ready(facts)
+    }
+
+    /// Moves on when the step is ready; on the summary this finishes.
+    pub fn next(&mut self, facts: &Facts) -> bool {
+        if !self.ready(facts) {
+            return false;
+        }
+        self.advance(Outcome::Done);
+        true
+    }
+
+    pub fn skip(&mut self, facts: &Facts) -> bool {
+        if !self.can_skip(facts) {
+            return false;
+        }
+        self.advance(Outcome::Skipped);
+        true
+    }
+
+    /// False on the first step.
+    pub fn back(&mut self) -> bool {
+        if self.at == 0 {
+            return false;
+        }
+        self.at -= 1;
+        true
+    }
+
+    /// How `step` ended the last time it was left forwards.
+    pub fn outcome(&self, step: Step) -> Option<Outcome> {
+        self.outcomes
+            .iter()
+            .find(|(done, _)| *done == step)
+            .map(|(_, outcome)| *outcome)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn advance(&mut self, outcome: Outcome) {
+        let step = self.step();
+        self.outcomes.retain(|(done, _)| *done != step);
+        self.outcomes.push((step, outcome));
+        if self.at + 1 < self.steps.len() {
+            self.at += 1;
+        } else {
+            self.finished = true;
+        }
+    }
+}
@@ -1,18 +1,18 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     ops::Range,
     rc::Rc,
+    sync::atomic::{AtomicU32, Ordering},
     time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
 use embedded_graphics_core::{
-    draw_target::DrawTarget,
-    pixelcolor::{raw::RawU16, Rgb565},
     prelude::{Point, Size},
     primitives::Rectangle,
 };
-use esp_idf_svc::sys::esp_get_free_heap_size;
+use esp_idf_svc::sys::{esp_get_free_heap_size, esp_timer_get_time};
+use log::{error, info};
 use slint::{
     platform::{
         self,
@@ -24,55 +24,155 @@ use slint::{
     LogicalPosition, PhysicalSize, SharedString,
 };
 
-use super::display::DisplayType;
+#[cfg(feature = "gui-extras")]
+use super::{alarms_page, devices, kinetic, level_page, media_page, networks, targets_page};
+use super::{
+    display::{self, DisplayType, TransportError},
+    errors_page, fallback, flashing, focus, frame_cache, lazy_pages,
+    line_batch::{Batch, LineAccumulator},
+    pages::{self, PageEvent},
+    pixel_shift, pixels,
+    render_profile::{self, Recorder},
+    settings_page, setup, stats_page, theme, timezone_page, touch_trace, watch,
+};
+#[cfg(feature = "ancs")]
+use super::{pairing, reminders_page, remote_page};
+use crate::{
+    allocator::InternalBox, board, boot, i18n, input::button::ButtonPress, power::download_mode,
+};
 
 slint::include_modules!();
 
+mod nav;
+
+use nav::{Layer, NavStack};
+
 pub const DISPLAY_WIDTH: usize = 240;
 pub const DISPLAY_HEIGHT: usize = 240;
+const APP_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+/// Frame intervals are counted in 1 ms buckets up to this one, which also
+/// collects everything longer.
+pub const FRAME_INTERVAL_MAX_MS: usize = 40;
+const INTERVAL_BUCKETS: usize = FRAME_INTERVAL_MAX_MS + 1;
+
+static LINES_FLUSHED: AtomicU32 = AtomicU32::new(0);
+
 thread_local! {
     static PLATFORM_WINDOW: RefCell<Option<Rc<MinimalSoftwareWindow>>> =
         const { RefCell::new(None) };
     static FRAME_STATS: RefCell<FrameStats> = RefCell::new(FrameStats::new());
     static APP_INSTANCE: RefCell<Option<App>> = const { RefCell::new(None) };
+    /// Kept between frames and pinned in internal RAM: every rendered line
+    /// goes through it.
+    static LINE_BUFFER: Cell<Option<InternalBox<[Rgb565Pixel; DISPLAY_WIDTH]>>> =
+        const { Cell::new(None) };
+    /// Set after a failed or reset frame; the panel content is unknown.
+    static FULL_REDRAW: Cell<bool> = const { Cell::new(false) };
+    /// The panel offset the last frame was drawn at.
+    static DRAWN_OFFSET: Cell<(i8, i8)> = const { Cell::new((0, 0)) };
+    /// Capture time (on the [`clock`]) of the pointer event being
+    /// dispatched, so Slint's velocity estimation sees touch timing rather
+    /// than dispatch timing.
+    static EVENT_TIME: Cell<Option<Duration>> = const { Cell::new(None) };
+    static LAST_TICK: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    /// Set while `App::new` keeps failing; cleared once a retry succeeds.
+    static APP_FAILURE: RefCell<Option<AppFailure>> = const { RefCell::new(None) };
+    /// Layers above the home page, bottom first. See `navigate`.
+    static NAV_STACK: RefCell<NavStack<Page, Overlay>> =
+        const { RefCell::new(NavStack::new(Page::Home)) };
 }
 
 pub fn render_hello_world(display: &mut DisplayType<'static>) -> Result<()> {
+    if download_mode::entering() {
+        return flashing::draw(display);
+    }
     let window = ensure_platform_window()?;
     window.set_size(PhysicalSize::new(DISPLAY_WIDTH as _, DISPLAY_HEIGHT as _));
     window.request_redraw();
 
-    ensure_app()?;
+    if let Err(failure) = ensure_app() {
+        return fallback::draw(display, &failure.error, failure.attempts);
+    }
+    pixel_shift::on_frame(!window.has_active_animations() && nothing_on_top());
+    let offset = display::panel_offset();
+    if DRAWN_OFFSET.get() != offset {
+        clear_margins(display, offset)?;
+        DRAWN_OFFSET.set(offset);
+        force_full_redraw();
+    }
+    if let Some(page) = current_page() {
+        if frame_cache::on_frame_start(page, |pixels| blit_frame(display, pixels))? {
+            return Ok(());
+        }
+    }
 
-    let frame_start = Instant::now();
-    let (displayed_fps, last_render_duration) =
+    let frame_start = clock();
+    let (displayed_fps, fps_ceiling, last_render_duration) =
         FRAME_STATS.with(|cell| cell.borrow().snapshot_for_display());
 
     let heap_bytes = unsafe { esp_get_free_heap_size() };
-    let fps_display = if displayed_fps > f32::EPSILON {
+    let mut fps_display = if displayed_fps > f32::EPSILON {
         format!("{displayed_fps:.1}")
     } else {
         "--".to_string()
     };
+    if let Some(ceiling) = fps_ceiling {
+        fps_display.push_str(&format!(" (max {ceiling:.0})"));
+    }
     let render_display = if let Some(duration) = last_render_duration {
         format!("{:.2}", duration.as_secs_f32() * 1_000.0)
     } else {
         "--".to_string()
     };
     let heap_kb = heap_bytes as f32 / 1024.0;
-    let stats_text = SharedString::from(format!(
-        "FPS: {fps}\nRender: {render} ms\nHeap: {heap:.1} KB",
+    let mut stats_text = format!(
+        "FPS: {fps}\n{render_label}: {render} ms\n{heap_label}: {heap:.1} KB",
         fps = fps_display,
+        render_label = i18n::tr("Render"),
+        heap_label = i18n::tr("Heap"),
         render = render_display,
         heap = heap_kb
-    ));
-    set_stats_text(stats_text);
+    );
+    if let Some(celsius) = crate::sensors::temperature::current_celsius() {
+        stats_text.push_str(&format!("\n{}: {celsius:.1} C", i18n::tr("Temp")));
+    }
+    // Only once a caller has actually been held up.
+    let ecs_wait = crate::ecs::worst_wait();
+    if ecs_wait > crate::ecs::WARN_AFTER {
+        stats_text.push_str(&format!(
+            "\n{}: {} ms",
+            i18n::tr("ECS wait"),
+            ecs_wait.as_millis()
+        ));
+    }
+    // Only once a NimBLE pool has run dry; see `ble::resources`.
+    if let Some(pool) = crate::ble::resources::worst_exhausted() {
+        stats_text.push_str(&format!(
+            "\n{}: {} {}/{}",
+            i18n::tr("BLE pool"),
+            pool.name,
+            pool.free,
+            pool.blocks
+        ));
+    }
+    if offset != (0, 0) {
+        stats_text.push_str(&format!(
+            "\n{}: {:+},{:+}",
+            i18n::tr("Shift"),
+            offset.0,
+            offset.1
+        ));
+    }
+    set_stats_text(SharedString::from(stats_text));
 
     platform::update_timers_and_animations();
 
+    let profiling = render_profile::enabled();
     let render_error = RefCell::<Option<anyhow::Error>>::new(None);
     let display_ptr: *mut DisplayType<'static> = display;
-    let mut line_buffer = [Rgb565Pixel(0); DISPLAY_WIDTH];
+    let mut line_buffer = LINE_BUFFER
+        .take()
+        .unwrap_or_else(|| InternalBox::new([Rgb565Pixel(0); DISPLAY_WIDTH]));
 
     while window.draw_if_needed(|renderer| {
         if render_error.borrow().is_some() {
@@ -81,20 +181,43 @@ pub fn render_hello_world(display: &mut DisplayType<'static>) -> Result<()> {
 
         // Safety: the draw loop is single-threaded and guarantees no aliasing with other uses.
         let display_ref = unsafe { &mut *display_ptr };
-        let mut provider = DisplayLineProvider::new(display_ref, &mut line_buffer, &render_error);
+        let mut provider = DisplayLineProvider::new(display_ref, &mut *line_buffer, &render_error);
+        provider.profile = profiling.then(|| Recorder::start(clock()));
+        let full = FULL_REDRAW.with(Cell::take);
+        frame_cache::begin_render(full);
+        if full {
+            // Changing the buffer type drops the partial-rendering cache.
+            renderer.set_repaint_buffer_type(RepaintBufferType::NewBuffer);
+        }
         renderer.render_by_line(&mut provider);
+        if full {
+            renderer.set_repaint_buffer_type(RepaintBufferType::ReusedBuffer);
+        }
         if let Err(err) = provider.finish() {
             *render_error.borrow_mut() = Some(err);
         }
+        if let Some(profile) = provider.profile.take() {
+            profile.finish(clock(), view_label());
+        }
+        // Frames with nothing dirty still get here: the window asks for a
+        // redraw every frame, which is when a settled page is captured.
+        let settled = !window.has_active_animations() && nothing_on_top();
+        frame_cache::end_render(
+            render_error.borrow().is_none(),
+            full,
+            provider.rendered,
+            settled,
+        );
     }) {
         platform::update_timers_and_animations();
     }
+    LINE_BUFFER.set(Some(line_buffer));
 
     if let Some(err) = render_error.into_inner() {
         return Err(err);
     }
 
-    let render_duration = frame_start.elapsed();
+    let render_duration = clock().saturating_sub(frame_start);
     FRAME_STATS.with(|cell| {
         cell.borrow_mut()
             .update_after_frame(frame_start, render_duration);
@@ -108,8 +231,12 @@ const MAX_BATCH_LINES: usize = 16;
 struct DisplayLineProvider<'a, 'b> {
     display: &'a mut DisplayType<'static>,
     line_buffer: &'b mut [Rgb565Pixel; DISPLAY_WIDTH],
-    accumulator: LineAccumulator,
+    accumulator: LineAccumulator<Rgb565Pixel>,
     error: &'b RefCell<Option<anyhow::Error>>,
+    /// Lines the renderer produced, sent or not.
+    rendered: usize,
+    /// Set for frames drawn while the render profiler is on.
+    profile: Option<Recorder>,
 }
 
 impl<'a, 'b> DisplayLineProvider<'a, 'b> {
@@ -121,13 +248,23 @@ impl<'a, 'b> DisplayLineProvider<'a, 'b> {
         Self {
             display,
             line_buffer,
-            accumulator: LineAccumulator::new(),
+            accumulator: LineAccumulator::new(DISPLAY_WIDTH, MAX_BATCH_LINES),
             error,
+            rendered: 0,
+            profile: None,
         }
     }
 
     fn finish(&mut self) -> Result<()> {
-        self.accumulator.flush(self.display)
+        let started = self.profile.is_some().then(clock);
+        let display = &mut *self.display;
+        let result = self
+            .accumulator
+            .flush(&mut |batch| send_batch(display, batch));
+        if let (Some(profile), Some(at)) = (&mut self.profile, started) {
+            profile.flushed_tail(clock().saturating_sub(at));
+        }
+        result
     }
 }
 
@@ -145,99 +282,99 @@ impl<'a, 'b, 'c> LineBufferProvider for &'c mut DisplayLineProvider<'a, 'b> {
         }
 
         let segment = &mut self.line_buffer[range.clone()];
+        let started = self.profile.is_some().then(clock);
         render_fn(segment);
+        if let (Some(profile), Some(at)) = (&mut self.profile, started) {
+            profile.rendered(line, &range, clock().saturating_sub(at));
+        }
+        self.rendered += 1;
+        if !frame_cache::line(line, range.clone(), segment) {
+            return;
+        }
 
+        let started = self.profile.is_some().then(clock);
+        let display = &mut *self.display;
         if let Err(err) = self
             .accumulator
-            .push_line(line, range, segment, self.display)
+            .push_line(line, range, segment, &mut |batch| {
+                send_batch(display, batch)
+            })
         {
             *self.error.borrow_mut() = Some(err);
         }
+        if let (Some(profile), Some(at)) = (&mut self.profile, started) {
+            profile.flushed(line, clock().saturating_sub(at));
+        }
     }
 }
 
-struct LineAccumulator {
-    start_line: usize,
-    range: Range<usize>,
-    line_count: usize,
-    buffer: Vec<Rgb565Pixel>,
+/// Sends one batch of lines to the panel.
+fn send_batch(display: &mut DisplayType<'static>, batch: Batch<'_, Rgb565Pixel>) -> Result<()> {
+    let (dx, dy) = display::panel_offset();
+    let rect = Rectangle::new(
+        Point::new(
+            batch.x as i32 + i32::from(dx),
+            batch.start_line as i32 + i32::from(dy),
+        ),
+        Size::new(batch.width as u32, batch.line_count as u32),
+    );
+    let result =
+        pixels::fill(display, &rect, batch.pixels, board::CURRENT.pixel_fixup).map_err(|e| {
+            anyhow::Error::new(TransportError(format!("refresh region {:?}: {e:?}", rect)))
+        });
+    LINES_FLUSHED.fetch_add(batch.line_count as u32, Ordering::Relaxed);
+    result
 }
 
-impl LineAccumulator {
-    fn new() -> Self {
-        Self {
-            start_line: 0,
-            range: 0..0,
-            line_count: 0,
-            buffer: Vec::with_capacity(DISPLAY_WIDTH * MAX_BATCH_LINES),
-        }
-    }
-
-    fn push_line(
-        &mut self,
-        line: usize,
-        range: Range<usize>,
-        pixels: &[Rgb565Pixel],
-        display: &mut DisplayType<'static>,
-    ) -> Result<()> {
-        if pixels.is_empty() {
-            return Ok(());
-        }
-
-        if self.line_count == 0 {
-            self.start_line = line;
-            self.range = range.clone();
-        } else {
-            let expected_line = self.start_line + self.line_count;
-            if line != expected_line
-                || range.start != self.range.start
-                || range.end != self.range.end
-            {
-                self.flush(display)?;
-                self.start_line = line;
-                self.range = range.clone();
-            }
-        }
-
-        self.buffer.extend_from_slice(pixels);
-        self.line_count += 1;
+/// Sends a whole cached frame in one transfer.
+fn blit_frame(display: &mut DisplayType<'static>, pixels: &[Rgb565Pixel]) -> Result<()> {
+    let (dx, dy) = display::panel_offset();
+    let rect = Rectangle::new(
+        Point::new(i32::from(dx), i32::from(dy)),
+        Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32),
+    );
+    pixels::fill(display, &rect, pixels, board::CURRENT.pixel_fixup)
+        .map_err(|e| anyhow::Error::new(TransportError(format!("cached frame: {e:?}"))))?;
+    LINES_FLUSHED.fetch_add(DISPLAY_HEIGHT as u32, Ordering::Relaxed);
+    Ok(())
+}
 
-        if self.line_count >= MAX_BATCH_LINES {
-            self.flush(display)?;
-        }
-        Ok(())
+/// Paints the strips the UI leaves uncovered at `offset` in the theme
+/// background; they still hold the previous offset's edge pixels.
+fn clear_margins(display: &mut DisplayType<'static>, (dx, dy): (i8, i8)) -> Result<()> {
+    let (width, height) = (DISPLAY_WIDTH as i32, DISPLAY_HEIGHT as i32);
+    let (dx, dy) = (i32::from(dx), i32::from(dy));
+    let strips = [
+        (dx > 0).then(|| (0, 0, dx, height)),
+        (dx < 0).then(|| (width + dx, 0, -dx, height)),
+        (dy > 0).then(|| (0, 0, width, dy)),
+        (dy < 0).then(|| (0, height + dy, width, -dy)),
+    ];
+    let [_, r, g, b] = theme::palette().background.to_be_bytes();
+    let background = Rgb565Pixel(
+        ((u16::from(r) & 0xF8) << 8) | ((u16::from(g) & 0xFC) << 3) | (u16::from(b) >> 3),
+    );
+    for (x, y, w, h) in strips.into_iter().flatten() {
+        let rect = Rectangle::new(Point::new(x, y), Size::new(w as u32, h as u32));
+        let fill = vec![background; (w * h) as usize];
+        pixels::fill(display, &rect, &fill, board::CURRENT.pixel_fixup)
+            .map_err(|e| anyhow::Error::new(TransportError(format!("margin {:?}: {e:?}", rect))))?;
     }
+    Ok(())
+}
 
-    fn flush(&mut self, display: &mut DisplayType<'static>) -> Result<()> {
-        if self.line_count == 0 {
-            return Ok(());
-        }
-
-        let rect = Rectangle::new(
-            Point::new(self.range.start as i32, self.start_line as i32),
-            Size::new(self.range.len() as u32, self.line_count as u32),
-        );
-
-        let colors = self
-            .buffer
-            .iter()
-            .take(self.range.len() * self.line_count)
-            .map(|Rgb565Pixel(pixel)| Rgb565::from(RawU16::new(*pixel)));
-
-        display
-            .fill_contiguous(&rect, colors)
-            .map_err(|e| anyhow!("Failed to refresh region {:?}: {e:?}", rect))?;
-
-        self.buffer.clear();
-        self.line_count = 0;
-        Ok(())
-    }
+/// The UI clock: `esp_timer` microseconds since boot. Finer than the tick
+/// behind `Instant` and monotonic across light sleep; drives Slint
+/// animations and the frame statistics.
+fn clock() -> Duration {
+    let micros = unsafe { esp_timer_get_time() };
+    Duration::from_micros(micros.max(0) as u64)
 }
 
 #[derive(Clone)]
 struct SimplePlatform {
     window: Rc<MinimalSoftwareWindow>,
-    start: Instant,
+    start: Duration,
 }
 
 impl Platform for SimplePlatform {
@@ -250,7 +387,14 @@ impl Platform for SimplePlatform {
     }
 
     fn duration_since_start(&self) -> std::time::Duration {
-        self.start.elapsed()
+        let now = EVENT_TIME.with(Cell::get).unwrap_or_else(clock);
+        let elapsed = now.saturating_sub(self.start);
+        // Never let an older capture timestamp move Slint's clock backwards.
+        LAST_TICK.with(|last| {
+            let tick = elapsed.max(last.get());
+            last.set(tick);
+            tick
+        })
     }
 }
 
@@ -263,7 +407,7 @@ fn ensure_platform_window() -> Result<Rc<MinimalSoftwareWindow>> {
         let window = MinimalSoftwareWindow::new(RepaintBufferType::ReusedBuffer);
         let platform = SimplePlatform {
             window: window.clone(),
-            start: Instant::now(),
+            start: clock(),
         };
         platform::set_platform(Box::new(platform))
             .map_err(|e| anyhow!("Failed to set Slint platform: {e:?}"))?;
@@ -272,12 +416,93 @@ fn ensure_platform_window() -> Result<Rc<MinimalSoftwareWindow>> {
     })
 }
 
-fn ensure_app() -> Result<()> {
+#[derive(Clone)]
+struct AppFailure {
+    error: String,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+/// Creates the `App` on first use. A failure is not fatal: the caller shows
+/// the fallback screen and creation is retried every `APP_RETRY_INTERVAL`,
+/// in case it was transient memory pressure.
+fn ensure_app() -> Result<(), AppFailure> {
+    if APP_INSTANCE.with(|cell| cell.borrow().is_some()) {
+        return Ok(());
+    }
+    if let Some(failure) = APP_FAILURE.with(|cell| cell.borrow().clone()) {
+        if Instant::now() < failure.next_attempt {
+            return Err(failure);
+        }
+    }
+
+    let started = Instant::now();
+    let result = create_app();
+    boot::record_attempt("ui", started.elapsed(), result.as_ref().err());
+    match result {
+        Ok(()) => {
+            if APP_FAILURE.with(|cell| cell.borrow_mut().take()).is_some() {
+                info!("Slint App created after retrying");
+                fallback::set_active(false);
+                force_full_redraw();
+            }
+            Ok(())
+        }
+        Err(err) => {
+            let attempts = APP_FAILURE
+                .with(|cell| cell.borrow().as_ref().map_or(0, |failure| failure.attempts))
+                + 1;
+            error!("Slint App creation failed (attempt {attempts}): {err:#}");
+            let failure = AppFailure {
+                error: format!("{err:#}"),
+                attempts,
+                next_attempt: Instant::now() + APP_RETRY_INTERVAL,
+            };
+            APP_FAILURE.with(|cell| *cell.borrow_mut() = Some(failure.clone()));
+            fallback::set_active(true);
+            Err(failure)
+        }
+    }
+}
+
+fn create_app() -> Result<()> {
     APP_INSTANCE.with(|cell| {
         if cell.borrow().is_none() {
+            lazy_pages::before_app();
             let app = App::new().map_err(|e| anyhow!("Failed to create Slint App: {:?}", e))?;
             app.show()
                 .map_err(|e| anyhow!("Failed to show Slint App: {:?}", e))?;
+            app.set_extras_enabled(cfg!(feature = "gui-extras"));
+            app.on_navigate(navigate);
+            app.on_back(|| {
+                back();
+            });
+            app.on_toast_dismiss(super::toast::dismiss);
+            theme::install(&app);
+            pages::install(&app);
+            focus::install(&app);
+            settings_page::install(&app);
+            stats_page::install(&app);
+            errors_page::install(&app);
+            setup::install(&app);
+            timezone_page::install(&app);
+            touch_trace::install(&app);
+            watch::install(&app);
+            #[cfg(feature = "gui-extras")]
+            {
+                networks::install(&app);
+                devices::install(&app);
+                targets_page::install(&app);
+                media_page::install(&app);
+                alarms_page::install(&app);
+                level_page::install(&app);
+            }
+            #[cfg(feature = "ancs")]
+            {
+                pairing::install(&app);
+                remote_page::install(&app);
+                reminders_page::install(&app);
+            }
             cell.replace(Some(app));
         }
         Ok(())
@@ -285,22 +510,103 @@ fn ensure_app() -> Result<()> {
 }
 
 fn set_stats_text(stats: SharedString) {
+    // Only the home page shows these; other pages keep their cached frames.
+    frame_cache::invalidate(Page::Home);
+    with_app_untracked(|app| app.set_stats_text(stats));
+}
+
+/// Instantaneous FPS of the last frame, 0 before two frames have rendered.
+pub fn current_fps() -> f32 {
+    FRAME_STATS.with(|cell| cell.borrow().last_fps)
+}
+
+/// Frame intervals seen since boot, as (milliseconds, frames) for every
+/// non-empty bucket.
+pub fn frame_intervals() -> Vec<(usize, u32)> {
+    FRAME_STATS.with(|cell| {
+        cell.borrow()
+            .intervals
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(ms, count)| (ms, *count))
+            .collect()
+    })
+}
+
+/// Repaints every pixel on the next frame instead of only dirty regions.
+pub fn force_full_redraw() {
+    FULL_REDRAW.with(|flag| flag.set(true));
+}
+
+/// Lines sent to the panel since boot, cached-frame blits included.
+pub fn lines_flushed() -> u32 {
+    LINES_FLUSHED.load(Ordering::Relaxed)
+}
+
+/// The page on screen, without the redraw and cache invalidation that
+/// [`with_app`] implies.
+pub fn current_page() -> Option<Page> {
+    APP_INSTANCE.with(|cell| cell.borrow().as_ref().map(App::get_page))
+}
+
+/// Like [`current_page`], for the toast.
+pub fn toast_visible() -> bool {
+    APP_INSTANCE.with(|cell| cell.borrow().as_ref().is_some_and(App::get_toast_visible))
+}
+
+/// What the render profiler files a frame under: the page, and the overlay
+/// on top of it if any.
+fn view_label() -> String {
     APP_INSTANCE.with(|cell| {
-        if let Some(app) = cell.borrow().as_ref() {
-            app.set_stats_text(stats.clone());
-            PLATFORM_WINDOW.with(|window_cell| {
-                if let Some(window) = window_cell.borrow().as_ref() {
-                    window.request_redraw();
-                }
-            });
+        cell.borrow()
+            .as_ref()
+            .map_or_else(String::new, |app| match app.get_overlay() {
+                Overlay::None => format!("{:?}", app.get_page()),
+                overlay => format!("{:?}+{overlay:?}", app.get_page()),
+            })
+    })
+}
+
+/// No overlay, toast or touch trace covers the page.
+fn nothing_on_top() -> bool {
+    APP_INSTANCE.with(|cell| {
+        cell.borrow().as_ref().is_some_and(|app| {
+            app.get_overlay() == Overlay::None
+                && !app.get_toast_visible()
+                && !app.get_touch_trace_enabled()
+        })
+    })
+}
+
+/// Runs `f` against the live `App` (if created) and schedules a redraw.
+/// Hidden pages lose their cached frames, since `f` may have changed them.
+/// Must be called from the UI thread.
+pub fn with_app<R>(f: impl FnOnce(&App) -> R) -> Option<R> {
+    let result = with_app_untracked(f)?;
+    frame_cache::invalidate_hidden();
+    Some(result)
+}
+
+/// [`with_app`] for writes known not to touch hidden pages.
+pub(super) fn with_app_untracked<R>(f: impl FnOnce(&App) -> R) -> Option<R> {
+    let result = APP_INSTANCE.with(|cell| cell.borrow().as_ref().map(f))?;
+    PLATFORM_WINDOW.with(|window_cell| {
+        if let Some(window) = window_cell.borrow().as_ref() {
+            window.request_redraw();
         }
     });
+    Some(result)
 }
 
 struct FrameStats {
-    last_frame_start: Option<Instant>,
+    /// On the [`clock`].
+    last_frame_start: Option<Duration>,
     last_render_time: Option<Duration>,
     last_fps: f32,
+    /// Frame rate a full-screen flush allows at the current SPI clock.
+    fps_ceiling: Option<f32>,
+    intervals: [u32; INTERVAL_BUCKETS],
 }
 
 impl FrameStats {
@@ -309,16 +615,20 @@ impl FrameStats {
             last_frame_start: None,
             last_render_time: None,
             last_fps: 0.0,
+            fps_ceiling: None,
+            intervals: [0; INTERVAL_BUCKETS],
         }
     }
 
-    fn snapshot_for_display(&self) -> (f32, Option<Duration>) {
-        (self.last_fps, self.last_render_time)
+    fn snapshot_for_display(&self) -> (f32, Option<f32>, Option<Duration>) {
+        (self.last_fps, self.fps_ceiling, self.last_render_time)
     }
 
-    fn update_after_frame(&mut self, frame_start: Instant, render_time: Duration) {
+    fn update_after_frame(&mut self, frame_start: Duration, render_time: Duration) {
         if let Some(previous_start) = self.last_frame_start {
-            if let Some(frame_interval) = frame_start.checked_duration_since(previous_start) {
+            if let Some(frame_interval) = frame_start.checked_sub(previous_start) {
+                let bucket = (frame_interval.as_millis() as usize).min(INTERVAL_BUCKETS - 1);
+                self.intervals[bucket] = self.intervals[bucket].saturating_add(1);
                 let frame_time = frame_interval.as_secs_f32();
                 if frame_time > f32::EPSILON {
                     self.last_fps = 1.0 / frame_time;
@@ -327,19 +637,37 @@ impl FrameStats {
         }
         self.last_frame_start = Some(frame_start);
         self.last_render_time = Some(render_time);
+        self.fps_ceiling = display::full_frame_time().map(|flush| 1.0 / flush.as_secs_f32());
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PointerAction {
     Press,
     Move,
     Release,
 }
 
-pub fn dispatch_pointer_action(action: PointerAction, position: (f32, f32)) -> Result<()> {
+/// Dispatches a pointer event. `captured_at` is when the touch sample was
+/// read; Slint's clock is advanced to it before dispatch.
+pub fn dispatch_pointer_action(
+    action: PointerAction,
+    position: (f32, f32),
+    captured_at: Option<Instant>,
+) -> Result<()> {
     let window = ensure_platform_window()?;
-    let logical_position = LogicalPosition::new(position.0, position.1);
+    if let Some(at) = captured_at {
+        // Touch samples are stamped with `Instant`; only the short age since
+        // the capture comes from it.
+        let captured = clock().saturating_sub(at.elapsed());
+        EVENT_TIME.with(|time| time.set(Some(captured)));
+        platform::update_timers_and_animations();
+    }
+    pixel_shift::on_input();
+    // The panel shows the UI moved by the offset; touch follows the panel.
+    let (dx, dy) = display::panel_offset();
+    let logical_position =
+        LogicalPosition::new(position.0 - f32::from(dx), position.1 - f32::from(dy));
     let event = match action {
         PointerAction::Press => slint::platform::WindowEvent::PointerPressed {
             position: logical_position,
@@ -354,19 +682,115 @@ pub fn dispatch_pointer_action(action: PointerAction, position: (f32, f32)) -> R
         },
     };
     window.dispatch_event(event);
+    EVENT_TIME.with(|time| time.set(None));
+    #[cfg(feature = "gui-extras")]
+    kinetic::on_pointer(action, position.1, captured_at.unwrap_or_else(Instant::now));
     window.request_redraw();
     Ok(())
 }
 
 pub fn set_touch_text(stats: SharedString) {
-    APP_INSTANCE.with(|cell| {
-        if let Some(app) = cell.borrow().as_ref() {
-            app.set_touch_text(stats.clone());
-            PLATFORM_WINDOW.with(|window_cell| {
-                if let Some(window) = window_cell.borrow().as_ref() {
-                    window.request_redraw();
-                }
+    with_app(|app| app.set_touch_text(stats));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    SwipeUp,
+    SwipeDown,
+    SwipeLeft,
+    SwipeRight,
+    /// The side key, see `input::button`.
+    Button(ButtonPress),
+}
+
+/// Offers a recognized gesture to the active page's `on_event` (see
+/// `pages`). Returns whether the page consumed it; a modal overlay swallows
+/// every gesture so nothing reaches the page beneath.
+pub fn dispatch_gesture(gesture: Gesture) -> bool {
+    pixel_shift::on_input();
+    if top_overlay().is_some() {
+        return true;
+    }
+    with_app(|app| pages::deliver(app, &PageEvent::Gesture(gesture))).unwrap_or(false)
+}
+
+// The Slint `page` and `overlay` properties are derived from the stack and
+// never set anywhere else.
+//
+// Pointer rules: the topmost overlay is modal and consumes every event
+// (each overlay component starts with a full-screen `TouchArea`); toasts sit
+// above everything but only take presses inside their own bounds.
+
+/// Opens `page`. Home empties the page history; any other page moves to the
+/// top of the pages but stays beneath open overlays.
+pub fn navigate(page: Page) {
+    NAV_STACK.with(|cell| cell.borrow_mut().navigate(page));
+    apply_navigation();
+}
+
+/// Shows `kind` above everything else. Already-open overlays keep their
+/// place, so refreshing one cannot bury a newer dialog.
+pub fn push_overlay(kind: Overlay) {
+    if kind == Overlay::None {
+        return;
+    }
+    if NAV_STACK.with(|cell| cell.borrow_mut().push_overlay(kind)) {
+        apply_navigation();
+    }
+}
+
+/// Closes `kind` wherever it is in the stack, e.g. a pairing prompt that
+/// timed out under the keyboard.
+pub fn remove_overlay(kind: Overlay) {
+    if NAV_STACK.with(|cell| cell.borrow_mut().remove_overlay(kind)) {
+        apply_navigation();
+    }
+}
+
+/// Drops the topmost layer without running its dismiss handler.
+pub fn pop() {
+    if NAV_STACK.with(|cell| cell.borrow_mut().pop()).is_some() {
+        apply_navigation();
+    }
+}
+
+/// The back action: cancels the topmost overlay the way its own cancel button
+/// would, or leaves the current page. Returns false on the home page.
+pub fn back() -> bool {
+    let Some(top) = NAV_STACK.with(|cell| cell.borrow().top()) else {
+        return false;
+    };
+    match top {
+        Layer::Overlay(kind) => {
+            with_app(|app| match kind {
+                Overlay::Credentials => app.invoke_credentials_cancel(),
+                Overlay::Pairing => app.invoke_pairing_reject(),
+                Overlay::DownloadMode => app.invoke_download_mode_cancel(),
+                Overlay::Reminder => app.invoke_reminder_dismiss(),
+                Overlay::None => {}
             });
+            // The handler normally removes it; make sure back always pops.
+            remove_overlay(kind);
         }
+        Layer::Page(_) => pop(),
+    }
+    true
+}
+
+pub fn top_overlay() -> Option<Overlay> {
+    NAV_STACK.with(|cell| cell.borrow().top_overlay())
+}
+
+fn apply_navigation() {
+    let (page, overlay) = NAV_STACK.with(|cell| {
+        let stack = cell.borrow();
+        (stack.page(), stack.top_overlay().unwrap_or(Overlay::None))
+    });
+    // Switching pages changes none of their content.
+    with_app_untracked(|app| {
+        lazy_pages::on_show(app, page);
+        pages::on_navigate(app, app.get_page(), page);
+        app.set_page(page);
+        app.set_overlay(overlay);
     });
 }
@@ -0,0 +1,85 @@
+//! The navigation stack's rules, generic over the page and overlay enums so
+//! host tests can drive them without the generated `App`.
+
+/// One entry of the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer<P, O> {
+    Page(P),
+    Overlay(O),
+}
+
+/// Layers above `home`, bottom first. Pages always sit beneath overlays.
+pub struct NavStack<P, O> {
+    home: P,
+    layers: Vec<Layer<P, O>>,
+}
+
+impl<P: Copy + PartialEq, O: Copy + PartialEq> NavStack<P, O> {
+    pub const fn new(home: P) -> Self {
+        Self {
+            home,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Home empties the page history; any other page moves to the top of
+    /// the pages but stays beneath open overlays.
+    pub fn navigate(&mut self, page: P) {
+        if page == self.home {
+            self.layers
+                .retain(|layer| matches!(layer, Layer::Overlay(_)));
+            return;
+        }
+        self.layers.retain(|layer| *layer != Layer::Page(page));
+        let at = self
+            .layers
+            .iter()
+            .position(|layer| matches!(layer, Layer::Overlay(_)))
+            .unwrap_or(self.layers.len());
+        self.layers.insert(at, Layer::Page(page));
+    }
+
+    /// Pushes `kind` unless it is already open, where it keeps its place.
+    /// Returns whether the stack changed.
+    pub fn push_overlay(&mut self, kind: O) -> bool {
+        let present = self.layers.contains(&Layer::Overlay(kind));
+        if !present {
+            self.layers.push(Layer::Overlay(kind));
+        }
+        !present
+    }
+
+    /// Removes `kind` wherever it is. Returns whether the stack changed.
+    pub fn remove_overlay(&mut self, kind: O) -> bool {
+        let before = self.layers.len();
+        self.layers.retain(|layer| *layer != Layer::Overlay(kind));
+        self.layers.len() != before
+    }
+
+    pub fn pop(&mut self) -> Option<Layer<P, O>> {
+        self.layers.pop()
+    }
+
+    pub fn top(&self) -> Option<Layer<P, O>> {
+        self.layers.last().copied()
+    }
+
+    /// The page on screen: the topmost page layer, else home.
+    pub fn page(&self) -> P {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| match layer {
+                Layer::Page(page) => Some(*page),
+                Layer::Overlay(_) => None,
+            })
+            .unwrap_or(self.home)
+    }
+
+    pub fn top_overlay(&self) -> Option<O> {
+        self.layers.iter().rev().find_map(|layer| match layer {
+            Layer::Overlay(kind) => Some(*kind),
+            Layer::Page(_) => None,
+        })
+    }
+}
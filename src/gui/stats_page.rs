@@ -0,0 +1,179 @@
+use std::{cell::Cell, time::Duration};
+
+use slint::SharedString;
+
+use super::{
+    history_chart,
+    pages::{Create, PageDescriptor},
+    slint_ui::{self, App, Page},
+    theme,
+};
+use crate::{
+    i18n,
+    memory::{self, pressure::PressureLevel},
+    metrics::{self, Counter, DayBucket},
+    miwear::net_meter::{self, Direction},
+    statlogger::heap_monitor::{self, Metric},
+    timesync::{self, TimeSource},
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub const PAGE: PageDescriptor = PageDescriptor::new("stats", "Stats", Create::Slint(Page::Stats));
+/// Rows that fit under the chart area, newest first.
+const USAGE_ROWS: usize = 7;
+
+thread_local! {
+    static METRIC: Cell<Metric> = const { Cell::new(Metric::InternalHeap) };
+    /// Daily usage table instead of the 5-minute charts.
+    static USAGE_VIEW: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn install(app: &App) {
+    app.set_psram_warning(memory::psram_failed());
+    app.on_stats_metric_toggle(|| {
+        METRIC.with(|metric| metric.set(metric.get().next()));
+        refresh();
+    });
+    app.on_stats_view_toggle(|| {
+        let usage = !USAGE_VIEW.with(Cell::get);
+        USAGE_VIEW.with(|view| view.set(usage));
+        slint_ui::with_app(|app| app.set_stats_usage_visible(usage));
+        refresh();
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            let visible = slint_ui::current_page() == Some(Page::Stats);
+            let state = (
+                heap_monitor::generation(),
+                net_meter::generation(),
+                metrics::generation(),
+                i18n::active(),
+                theme::generation(),
+                timesync::generation(),
+            );
+            if visible && seen != Some(state) {
+                refresh();
+                seen = Some(state);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+
+    // Not tied to the Stats page: the emergency banner shows everywhere.
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            let state = (memory::pressure::generation(), i18n::active());
+            if seen != Some(state) {
+                show_pressure(memory::pressure::level());
+                seen = Some(state);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+fn show_pressure(level: PressureLevel) {
+    let label = match level {
+        PressureLevel::Normal => "",
+        PressureLevel::Warning => i18n::tr("Mem: low"),
+        PressureLevel::Critical => i18n::tr("Mem: critical"),
+        PressureLevel::Emergency => i18n::tr("Mem: emergency"),
+    };
+    slint_ui::with_app(|app| {
+        app.set_memory_pressure(SharedString::from(label));
+        app.set_memory_emergency(level == PressureLevel::Emergency);
+    });
+}
+
+/// Redraws the chart for the selected metric and the link meter. Only called while the Stats
+/// page is showing; the image is left stale otherwise.
+fn refresh() {
+    if USAGE_VIEW.with(Cell::get) {
+        let text = usage_text(&metrics::days());
+        slint_ui::with_app(|app| {
+            app.set_stats_usage_text(SharedString::from(text));
+            app.set_stats_net_meter(SharedString::from(net_meter_text()));
+        });
+        return;
+    }
+    let metric = METRIC.with(Cell::get);
+    let chart = history_chart::render(&heap_monitor::series(metric));
+    let (title, format): (_, fn(f32) -> String) = match metric {
+        Metric::InternalHeap => (i18n::tr("Internal heap free (KB)"), |v| format!("{v:.0}")),
+        Metric::Fps => (i18n::tr("FPS"), |v| format!("{v:.1}")),
+    };
+    let label = |value: Option<f32>| {
+        SharedString::from(value.map(format).unwrap_or_else(|| "--".to_string()))
+    };
+    let has_data = chart.last.is_some();
+    slint_ui::with_app(|app| {
+        app.set_stats_chart(chart.image);
+        app.set_stats_chart_title(SharedString::from(title));
+        app.set_stats_chart_current(label(chart.last));
+        app.set_stats_chart_min(label(has_data.then_some(chart.min)));
+        app.set_stats_chart_max(label(has_data.then_some(chart.max)));
+        app.set_stats_net_meter(SharedString::from(net_meter_text()));
+    });
+}
+
+fn net_meter_text() -> String {
+    let Some(session) = net_meter::snapshot().session else {
+        return i18n::tr("No watch link").to_string();
+    };
+    let line = |label: &str, direction: &Direction| {
+        format!(
+            "{label} {:.1} KB/s  {} {:.1}  {} KB",
+            direction.current_bps / 1024.0,
+            i18n::tr("peak"),
+            direction.peak_bps / 1024.0,
+            direction.total_bytes / 1024
+        )
+    };
+    format!("{}\n{}", line("W", &session.tx), line("R", &session.rx))
+}
+
+/// One line per day, newest first: notifications published, watch traffic
+/// both ways, connected time, and BLE/Wi-Fi drops.
+fn usage_text(days: &[DayBucket]) -> String {
+    let mut lines = vec![i18n::tr("Day    Ntf   Data   Conn  BLE/WiFi").to_string()];
+    lines.extend(days.iter().rev().take(USAGE_ROWS).map(|bucket| {
+        let day = bucket
+            .date
+            .map(|date| format!("{:02}-{:02}", date.month, date.day))
+            .unwrap_or_else(|| "??-??".to_string());
+        let bytes = bucket.get(Counter::BytesToWatch) + bucket.get(Counter::BytesFromWatch);
+        let connected = bucket.get(Counter::ConnectedSeconds);
+        format!(
+            "{day}  {:>4}  {:>4.1}M  {:>2}:{:02}  {}/{}",
+            bucket.get(Counter::NotificationsPublished),
+            bytes as f32 / (1024.0 * 1024.0),
+            connected / 3600,
+            connected % 3600 / 60,
+            bucket.get(Counter::BleReconnects),
+            bucket.get(Counter::WifiDisconnects)
+        )
+    }));
+    if days.is_empty() {
+        lines.push(i18n::tr("No usage recorded yet").to_string());
+    }
+    lines.push(clock_text());
+    lines.join("\n")
+}
+
+/// Where the day boundaries above come from, with the error bound.
+fn clock_text() -> String {
+    let info = timesync::source();
+    let source = match info.source {
+        None => i18n::tr("unset").to_string(),
+        Some(TimeSource::Resumed) => i18n::tr("resumed, stale").to_string(),
+        Some(TimeSource::Sntp) => {
+            let error = info.error.unwrap_or_default();
+            format!("SNTP \u{b1}{:.1} s", error.as_secs_f32())
+        }
+    };
+    i18n::trf("Clock: {}", &[&source])
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use slint::SharedString;
+
+use super::{
+    bounded_model::BoundedModel,
+    slint_ui::{self, App, Page, TargetEntry},
+    toast,
+};
+use crate::{
+    i18n,
+    miwear::targets::{self, MiWearTarget, MAX_TARGETS},
+    settings,
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+thread_local! {
+    static TARGET_MODEL: BoundedModel<TargetEntry> = BoundedModel::new("targets", MAX_TARGETS);
+}
+
+pub fn install(app: &App) {
+    app.set_targets(TARGET_MODEL.with(BoundedModel::model));
+    app.set_targets_switch(settings::get(&targets::SWITCH_TO_PREFERRED));
+
+    app.on_target_connect(|id| {
+        let Some(target) = target(id) else {
+            return;
+        };
+        match targets::request_connect(target.id) {
+            Ok(()) => toast::show(i18n::trf("Switching to {}", &[&target.label()])),
+            Err(err) => toast::show(err.to_string()),
+        }
+    });
+    app.on_target_toggle(|id| {
+        if let Some(target) = target(id) {
+            report(targets::set_enabled(target.id, !target.enabled));
+        }
+    });
+    app.on_target_raise(|id| {
+        if let Some(target) = target(id) {
+            report(targets::raise(target.id));
+        }
+    });
+    app.on_target_delete(|id| {
+        let Some(target) = target(id) else {
+            return;
+        };
+        match targets::remove(target.id) {
+            Ok(removed) => toast::show(i18n::trf("Removed {}", &[&removed.label()])),
+            Err(err) => toast::show(err.to_string()),
+        }
+    });
+    app.on_targets_switch_toggle(|| {
+        let enabled = !settings::get(&targets::SWITCH_TO_PREFERRED);
+        if let Err(err) = settings::set(&targets::SWITCH_TO_PREFERRED, &enabled) {
+            log::warn!(
+                "Failed to save {}: {err:?}",
+                targets::SWITCH_TO_PREFERRED.name
+            );
+            return;
+        }
+        slint_ui::with_app(|app| app.set_targets_switch(enabled));
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            let visible = slint_ui::current_page() == Some(Page::Targets);
+            let current = (targets::generation(), i18n::active());
+            if visible && seen != Some(current) {
+                refresh();
+                seen = Some(current);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+fn target(id: i32) -> Option<MiWearTarget> {
+    u32::try_from(id).ok().and_then(targets::get)
+}
+
+fn report(result: anyhow::Result<()>) {
+    if let Err(err) = result {
+        toast::show(err.to_string());
+    }
+}
+
+fn refresh() {
+    let active = targets::active();
+    let entries: Vec<TargetEntry> = targets::list()
+        .iter()
+        .map(|target| TargetEntry {
+            id: target.id as i32,
+            label: SharedString::from(target.label()),
+            detail: SharedString::from(detail(target, active == Some(target.id))),
+            enabled: target.enabled,
+            active: active == Some(target.id),
+        })
+        .collect();
+    TARGET_MODEL.with(|model| model.set_vec(entries));
+}
+
+fn detail(target: &MiWearTarget, active: bool) -> String {
+    if active {
+        return i18n::tr("Connected").to_string();
+    }
+    let matcher = target.addr.as_deref().unwrap_or(&target.name);
+    match target.phy {
+        Some(phy) => format!("{matcher} · {phy}"),
+        None => matcher.to_string(),
+    }
+}
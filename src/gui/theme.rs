@@ -0,0 +1,488 @@
+//! Color presets for the Slint `Theme` global. The choice is stored in
+//! [`THEME`]; `auto` follows the local clock, black at night and light by
+//! day, and stays black until the clock has been set.
+//!
+//! The text size lives here too: [`UI_SCALE`] scales every font and row
+//! token in `Theme`, and [`MIN_FONT`] is the floor under them.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+    time::Duration,
+};
+
+use log::{info, warn};
+use slint::{Color, ComponentHandle, Rgba8Pixel, SharedString};
+
+use super::slint_ui::{self, App, Theme};
+use crate::{
+    settings::{self, SettingKey},
+    timesync,
+};
+
+/// `black` keeps the original look; it is also the cheapest on OLED panels.
+pub const THEME: SettingKey<ThemeMode> = SettingKey::new("ui_theme", "black");
+/// Local hours in which `auto` shows the light preset.
+pub const DAY_START_HOUR: SettingKey<u32> =
+    SettingKey::new("ui_day_start", "7").validated(check_hour);
+pub const DAY_END_HOUR: SettingKey<u32> = SettingKey::new("ui_day_end", "19").validated(check_hour);
+pub const UI_SCALE: SettingKey<UiScale> = SettingKey::new("ui_scale", "100");
+/// Smallest font size in px; 9 leaves every size as designed.
+pub const MIN_FONT: SettingKey<u32> = SettingKey::new("ui_min_font", "9").validated(|px| {
+    if MIN_FONT_RANGE.contains(px) {
+        Ok(())
+    } else {
+        Err("minfont is 9 to 12 px".into())
+    }
+});
+/// The sizes build.rs embeds glyphs for; keep in step with it.
+pub const MIN_FONT_RANGE: std::ops::RangeInclusive<u32> = 9..=12;
+
+/// Picks up edits from the console or HTTP and the `auto` day boundary.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+static ACTIVE: AtomicU8 = AtomicU8::new(Preset::Black as u8);
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+/// Outline flag << 16 | scale percent << 8 | min font, as last applied.
+static TEXT_SIZE: AtomicU32 = AtomicU32::new(100 << 8 | 9);
+static SHOW_TOUCH_TARGETS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThemeMode {
+    Dark,
+    Light,
+    Black,
+    Auto,
+}
+
+impl ThemeMode {
+    pub const ALL: [ThemeMode; 4] = [
+        ThemeMode::Black,
+        ThemeMode::Dark,
+        ThemeMode::Light,
+        ThemeMode::Auto,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            ThemeMode::Dark => "dark",
+            ThemeMode::Light => "light",
+            ThemeMode::Black => "black",
+            ThemeMode::Auto => "auto",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+impl fmt::Display for ThemeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for ThemeMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ThemeMode::ALL
+            .into_iter()
+            .find(|mode| mode.code() == s)
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UiScale {
+    Small,
+    Normal,
+    Large,
+    Larger,
+}
+
+impl UiScale {
+    pub const ALL: [UiScale; 4] = [
+        UiScale::Normal,
+        UiScale::Large,
+        UiScale::Larger,
+        UiScale::Small,
+    ];
+
+    pub fn percent(self) -> u32 {
+        match self {
+            UiScale::Small => 90,
+            UiScale::Normal => 100,
+            UiScale::Large => 115,
+            UiScale::Larger => 130,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|scale| *scale == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+impl fmt::Display for UiScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.percent())
+    }
+}
+
+impl FromStr for UiScale {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent: u32 = s.trim().trim_end_matches('%').parse().map_err(|_| ())?;
+        UiScale::ALL
+            .into_iter()
+            .find(|scale| scale.percent() == percent)
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Preset {
+    Dark,
+    Light,
+    Black,
+}
+
+impl Preset {
+    pub fn palette(self) -> &'static Palette {
+        match self {
+            Preset::Dark => &DARK,
+            Preset::Light => &LIGHT,
+            Preset::Black => &BLACK,
+        }
+    }
+}
+
+/// One color per `Theme` property, as `0xAARRGGBB`.
+pub struct Palette {
+    pub background: u32,
+    pub surface: u32,
+    pub surface_pressed: u32,
+    pub surface_disabled: u32,
+    pub surface_warning: u32,
+    pub raised: u32,
+    pub border: u32,
+    pub scrim: u32,
+    pub primary: u32,
+    pub button: u32,
+    pub button_pressed: u32,
+    pub positive_button: u32,
+    pub caution_button: u32,
+    pub on_button: u32,
+    pub text: u32,
+    pub text_secondary: u32,
+    pub text_tertiary: u32,
+    pub text_muted: u32,
+    pub text_disabled: u32,
+    pub accent: u32,
+    pub accent_dim: u32,
+    pub danger: u32,
+    pub danger_strong: u32,
+    pub success: u32,
+    pub overlay_text: u32,
+}
+
+/// Pure black background: unlit pixels on OLED variants, less smearing on
+/// the LCD. Matches the defaults in theme.slint.
+const BLACK: Palette = Palette {
+    background: 0xFF00_0000,
+    surface: 0xFF1A_1A1A,
+    surface_pressed: 0xFF33_3333,
+    surface_disabled: 0xFF11_1111,
+    surface_warning: 0xFF40_2000,
+    raised: 0xFF22_2222,
+    border: 0xFF44_4444,
+    scrim: 0xE000_0000,
+    primary: 0xFF00_BFFF,
+    button: 0xFF00_7FAA,
+    button_pressed: 0xFF00_5F80,
+    positive_button: 0xFF00_6400,
+    caution_button: 0xFF8B_4000,
+    on_button: 0xFFFF_FFFF,
+    text: 0xFFFF_FFFF,
+    text_secondary: 0xFFAA_AAAA,
+    text_tertiary: 0xFF88_8888,
+    text_muted: 0xFF66_6666,
+    text_disabled: 0xFF55_5555,
+    accent: 0xFFFF_AA00,
+    accent_dim: 0xFFAA_7700,
+    danger: 0xFFFF_5555,
+    danger_strong: 0xFFAA_0000,
+    success: 0xFF00_FF00,
+    overlay_text: 0xFF00_FF00,
+};
+
+/// Grey rather than black, with softer accents.
+const DARK: Palette = Palette {
+    background: 0xFF12_1212,
+    surface: 0xFF24_2424,
+    surface_pressed: 0xFF3A_3A3A,
+    surface_disabled: 0xFF1A_1A1A,
+    surface_warning: 0xFF3D_2A10,
+    raised: 0xFF2C_2C2C,
+    border: 0xFF50_5050,
+    scrim: 0xE012_1212,
+    primary: 0xFF4F_C3F7,
+    button: 0xFF02_77BD,
+    button_pressed: 0xFF01_579B,
+    positive_button: 0xFF2E_7D32,
+    caution_button: 0xFF8B_4000,
+    on_button: 0xFFFF_FFFF,
+    text: 0xFFEE_EEEE,
+    text_secondary: 0xFFB0_B0B0,
+    text_tertiary: 0xFF90_9090,
+    text_muted: 0xFF70_7070,
+    text_disabled: 0xFF5A_5A5A,
+    accent: 0xFFFF_B74D,
+    accent_dim: 0xFFA0_700A,
+    danger: 0xFFEF_5350,
+    danger_strong: 0xFFB7_1C1C,
+    success: 0xFF66_BB6A,
+    overlay_text: 0xFF81_C784,
+};
+
+/// Daylight preset; the overlay green is darkened to stay readable.
+const LIGHT: Palette = Palette {
+    background: 0xFFF5_F5F5,
+    surface: 0xFFE0_E0E0,
+    surface_pressed: 0xFFC8_C8C8,
+    surface_disabled: 0xFFEE_EEEE,
+    surface_warning: 0xFFFF_E0B2,
+    raised: 0xFFDD_DDDD,
+    border: 0xFFBD_BDBD,
+    scrim: 0xE8F5_F5F5,
+    primary: 0xFF02_77BD,
+    button: 0xFF02_88D1,
+    button_pressed: 0xFF01_579B,
+    positive_button: 0xFF2E_7D32,
+    caution_button: 0xFFE6_5100,
+    on_button: 0xFFFF_FFFF,
+    text: 0xFF21_2121,
+    text_secondary: 0xFF55_5555,
+    text_tertiary: 0xFF6E_6E6E,
+    text_muted: 0xFF8A_8A8A,
+    text_disabled: 0xFFAA_AAAA,
+    accent: 0xFFE6_5100,
+    accent_dim: 0xFFF5_7C00,
+    danger: 0xFFD3_2F2F,
+    danger_strong: 0xFFC6_2828,
+    success: 0xFF2E_7D32,
+    overlay_text: 0xFF1B_5E20,
+};
+
+pub fn install(app: &App) {
+    apply(app, resolve(settings::get(&THEME)));
+    app.set_theme_mode(SharedString::from(settings::get(&THEME).code()));
+    apply_text_size(app);
+
+    app.on_ui_scale_cycle(|| {
+        let next = settings::get(&UI_SCALE).next();
+        if let Err(err) = settings::set(&UI_SCALE, &next) {
+            warn!("Failed to save UI scale: {err:?}");
+            return;
+        }
+        slint_ui::with_app(apply_text_size);
+    });
+
+    app.on_theme_cycle(|| {
+        let next = settings::get(&THEME).next();
+        if let Err(err) = settings::set(&THEME, &next) {
+            warn!("Failed to save theme: {err:?}");
+            return;
+        }
+        slint_ui::with_app(|app| {
+            app.set_theme_mode(SharedString::from(next.code()));
+            apply(app, resolve(next));
+        });
+    });
+
+    tokio::task::spawn_local(async {
+        loop {
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+            let mode = settings::get(&THEME);
+            let preset = resolve(mode);
+            slint_ui::with_app(|app| {
+                app.set_theme_mode(SharedString::from(mode.code()));
+                if preset != active() {
+                    apply(app, preset);
+                }
+                if TEXT_SIZE.load(Ordering::Relaxed) != wanted_text_size() {
+                    apply_text_size(app);
+                }
+            });
+        }
+    });
+}
+
+pub fn active() -> Preset {
+    match ACTIVE.load(Ordering::Relaxed) {
+        value if value == Preset::Dark as u8 => Preset::Dark,
+        value if value == Preset::Light as u8 => Preset::Light,
+        _ => Preset::Black,
+    }
+}
+
+pub fn palette() -> &'static Palette {
+    active().palette()
+}
+
+/// Scale percent and minimum font as applied; changes when text is resized,
+/// so pages that measure strings can measure again.
+pub fn text_size() -> (u32, u32) {
+    let packed = TEXT_SIZE.load(Ordering::Relaxed);
+    ((packed >> 8) & 0xFF, packed & 0xFF)
+}
+
+/// The settings and the outline flag, packed the way [`TEXT_SIZE`] is.
+fn wanted_text_size() -> u32 {
+    let percent = settings::get(&UI_SCALE).percent();
+    let min_font = settings::get(&MIN_FONT).clamp(*MIN_FONT_RANGE.start(), *MIN_FONT_RANGE.end());
+    let outlines = u32::from(SHOW_TOUCH_TARGETS.load(Ordering::Relaxed));
+    outlines << 16 | percent << 8 | min_font
+}
+
+fn apply_text_size(app: &App) {
+    let wanted = wanted_text_size();
+    let (percent, min_font, outlines) = (wanted >> 8 & 0xFF, wanted & 0xFF, wanted >> 16 != 0);
+    let theme = app.global::<Theme>();
+    theme.set_scale_percent(percent as i32);
+    theme.set_min_font(min_font as f32);
+    theme.set_show_touch_targets(outlines);
+    app.set_ui_scale(percent as i32);
+    if TEXT_SIZE.swap(wanted, Ordering::Relaxed) & 0xFFFF != wanted & 0xFFFF {
+        info!("UI text size: {percent}%, at least {min_font} px");
+    }
+}
+
+/// Console edits land on the next refresh tick, since the console runs on
+/// its own thread.
+pub fn register_commands() {
+    crate::console::register(
+        "ui",
+        "scale [90|100|115|130] | minfont [9-12] | targets on|off: text size and touch target outlines",
+        |args| {
+            match args {
+                ["scale"] | ["minfont"] => {}
+                ["scale", scale] => {
+                    let scale: UiScale = scale
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("scale is one of 90, 100, 115, 130"))?;
+                    settings::set(&UI_SCALE, &scale)?;
+                }
+                ["minfont", px] => {
+                    let px: u32 = px
+                        .parse()
+                        .ok()
+                        .filter(|px| MIN_FONT_RANGE.contains(px))
+                        .ok_or_else(|| anyhow::anyhow!("minfont is 9 to 12 px"))?;
+                    settings::set(&MIN_FONT, &px)?;
+                }
+                ["targets", state @ ("on" | "off")] => {
+                    SHOW_TOUCH_TARGETS.store(*state == "on", Ordering::Relaxed);
+                }
+                _ => anyhow::bail!(
+                    "usage: ui scale [90|100|115|130] | minfont [9-12] | targets on|off"
+                ),
+            }
+            let wanted = wanted_text_size();
+            Ok(format!(
+                "text {}%, at least {} px, touch targets {}",
+                wanted >> 8 & 0xFF,
+                wanted & 0xFF,
+                if wanted >> 16 != 0 { "outlined" } else { "plain" }
+            ))
+        },
+    );
+}
+
+/// Bumped on every preset change, for images rasterized in Rust.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+pub fn rgba(color: u32) -> Rgba8Pixel {
+    let [a, r, g, b] = color.to_be_bytes();
+    Rgba8Pixel::new(r, g, b, a)
+}
+
+fn resolve(mode: ThemeMode) -> Preset {
+    match mode {
+        ThemeMode::Dark => Preset::Dark,
+        ThemeMode::Light => Preset::Light,
+        ThemeMode::Black => Preset::Black,
+        ThemeMode::Auto => match local_hour() {
+            Some(hour) if is_day(hour) => Preset::Light,
+            _ => Preset::Black,
+        },
+    }
+}
+
+fn check_hour(hour: &u32) -> Result<(), String> {
+    if *hour < 24 {
+        Ok(())
+    } else {
+        Err("hour is 0 to 23".into())
+    }
+}
+
+fn is_day(hour: u32) -> bool {
+    let (start, end) = (settings::get(&DAY_START_HOUR), settings::get(&DAY_END_HOUR));
+    if start <= end {
+        (start..end).contains(&hour)
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+/// `None` until the clock has been set.
+fn local_hour() -> Option<u32> {
+    timesync::now_local().map(|local| u32::from(local.hour))
+}
+
+fn apply(app: &App, preset: Preset) {
+    let p = preset.palette();
+    let theme = app.global::<Theme>();
+    let color = Color::from_argb_encoded;
+    theme.set_background(color(p.background));
+    theme.set_surface(color(p.surface));
+    theme.set_surface_pressed(color(p.surface_pressed));
+    theme.set_surface_disabled(color(p.surface_disabled));
+    theme.set_surface_warning(color(p.surface_warning));
+    theme.set_raised(color(p.raised));
+    theme.set_border(color(p.border));
+    theme.set_scrim(color(p.scrim));
+    theme.set_primary(color(p.primary));
+    theme.set_button(color(p.button));
+    theme.set_button_pressed(color(p.button_pressed));
+    theme.set_positive_button(color(p.positive_button));
+    theme.set_caution_button(color(p.caution_button));
+    theme.set_on_button(color(p.on_button));
+    theme.set_text(color(p.text));
+    theme.set_text_secondary(color(p.text_secondary));
+    theme.set_text_tertiary(color(p.text_tertiary));
+    theme.set_text_muted(color(p.text_muted));
+    theme.set_text_disabled(color(p.text_disabled));
+    theme.set_accent(color(p.accent));
+    theme.set_accent_dim(color(p.accent_dim));
+    theme.set_danger(color(p.danger));
+    theme.set_danger_strong(color(p.danger_strong));
+    theme.set_success(color(p.success));
+    theme.set_overlay_text(color(p.overlay_text));
+    if ACTIVE.swap(preset as u8, Ordering::Relaxed) != preset as u8 {
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+        info!("UI theme: {preset:?}");
+    }
+}
@@ -0,0 +1,148 @@
+use std::{cell::Cell, time::Duration};
+
+use log::warn;
+use slint::SharedString;
+
+use super::{
+    bounded_model::BoundedModel,
+    slint_ui::{self, App, Page, TimezoneEntry},
+    toast,
+};
+use crate::{
+    i18n, settings,
+    timesync::{
+        self,
+        zones::{self, Zone},
+        TIMEZONE,
+    },
+};
+
+/// Picks up `tz` from the console and DST starting or ending.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// More than the regions or any one region's cities.
+const MAX_ZONE_ROWS: usize = 32;
+
+thread_local! {
+    static ENTRY_MODEL: BoundedModel<TimezoneEntry> = BoundedModel::new("timezones", MAX_ZONE_ROWS);
+    /// The region whose cities are listed; `None` lists the regions.
+    static REGION: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+pub fn install(app: &App) {
+    app.set_timezone_entries(ENTRY_MODEL.with(BoundedModel::model));
+
+    app.on_timezone_selected(|index| {
+        let Ok(index) = usize::try_from(index) else {
+            return;
+        };
+        match REGION.get() {
+            None => {
+                let Some(region) = zones::regions().get(index).copied() else {
+                    return;
+                };
+                let mut cities = zones::in_region(region);
+                match (cities.next(), cities.next()) {
+                    // A region of one zone, such as UTC, is picked outright.
+                    (Some(zone), None) => choose(zone),
+                    _ => {
+                        REGION.set(Some(region));
+                        show();
+                    }
+                }
+            }
+            Some(region) => {
+                if let Some(zone) = zones::in_region(region).nth(index) {
+                    choose(zone);
+                }
+            }
+        }
+    });
+    app.on_timezone_back(|| {
+        if REGION.take().is_some() {
+            show();
+        } else {
+            slint_ui::back();
+        }
+    });
+
+    tokio::task::spawn_local(async {
+        let mut seen = None;
+        loop {
+            // Left some other way than Back: open at the regions next time.
+            if slint_ui::current_page() != Some(Page::Timezone) && REGION.get().is_some() {
+                REGION.set(None);
+                seen = None;
+            }
+            let zone = timesync::zone();
+            let current = (zone.name, offset_now(zone), i18n::active());
+            if seen != Some(current) {
+                show();
+                seen = Some(current);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
+
+fn choose(zone: &'static Zone) {
+    if let Err(err) = settings::set(&TIMEZONE, &zone.name.to_string()) {
+        warn!("Failed to save the time zone: {err:#}");
+        toast::show(err.to_string());
+        return;
+    }
+    REGION.set(None);
+    show();
+    toast::show(i18n::trf("Time zone: {}", &[&zone.city()]));
+    slint_ui::back();
+}
+
+fn show() {
+    let current = timesync::zone();
+    let region = REGION.get();
+    let entries: Vec<TimezoneEntry> = match region {
+        None => zones::regions()
+            .into_iter()
+            .map(|region| {
+                let count = zones::in_region(region).count();
+                TimezoneEntry {
+                    label: SharedString::from(region),
+                    detail: SharedString::from(if count == 1 {
+                        String::new()
+                    } else {
+                        i18n::trf("{} zones", &[&count])
+                    }),
+                    current: current.region() == region,
+                }
+            })
+            .collect(),
+        Some(region) => zones::in_region(region)
+            .map(|zone| TimezoneEntry {
+                label: SharedString::from(zone.city()),
+                detail: SharedString::from(zones::offset_label(offset_now(zone))),
+                current: zone.name == current.name,
+            })
+            .collect(),
+    };
+    ENTRY_MODEL.with(|model| model.set_vec(entries));
+    slint_ui::with_app(|app| {
+        app.set_timezone_region(SharedString::from(region.unwrap_or("")));
+        app.set_timezone_name(SharedString::from(label(current)));
+    });
+}
+
+/// `Berlin (UTC+2)`, or just `UTC`.
+fn label(zone: &Zone) -> String {
+    if zone.name == zones::UTC {
+        return zones::UTC.to_string();
+    }
+    format!(
+        "{} ({})",
+        zone.city(),
+        zones::offset_label(offset_now(zone))
+    )
+}
+
+/// The zone's offset now, or its standard offset while the clock is unset.
+fn offset_now(zone: &Zone) -> i32 {
+    timesync::unix_now().map_or(zone.std_offset(), |now| zone.local(now).offset)
+}
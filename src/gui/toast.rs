@@ -0,0 +1,47 @@
+use std::{cell::Cell, time::Duration};
+
+use slint::SharedString;
+
+use super::{
+    marquee::{self, Line},
+    slint_ui,
+};
+
+const VISIBLE_FOR: Duration = Duration::from_secs(3);
+
+thread_local! {
+    /// Bumped per toast so an older timer cannot hide a newer message.
+    static SHOWN: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Shows a one-line message above every page for a few seconds. UI thread only.
+pub fn show(text: impl Into<SharedString>) {
+    let id = SHOWN.with(|shown| {
+        shown.set(shown.get().wrapping_add(1));
+        shown.get()
+    });
+    let text = text.into();
+    // A line that scrolls stays up for at least one full pass.
+    let visible_for = slint_ui::with_app(|app| {
+        let scroll = marquee::measure(app, Line::Toast, &text);
+        app.set_toast_scroll(scroll);
+        app.set_toast_text(text);
+        app.set_toast_visible(true);
+        marquee::note(Line::Toast, scroll);
+        VISIBLE_FOR.max(marquee::pass_time(app, scroll))
+    })
+    .unwrap_or(VISIBLE_FOR);
+    marquee::restart();
+    tokio::task::spawn_local(async move {
+        tokio::time::sleep(visible_for).await;
+        if SHOWN.with(Cell::get) == id {
+            slint_ui::with_app(|app| app.set_toast_visible(false));
+        }
+    });
+}
+
+/// Hides the current toast early; bound to a tap on the toast itself.
+pub fn dismiss() {
+    SHOWN.with(|shown| shown.set(shown.get().wrapping_add(1)));
+    slint_ui::with_app(|app| app.set_toast_visible(false));
+}
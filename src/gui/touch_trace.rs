@@ -0,0 +1,288 @@
+//! Input debug overlay: the last couple of seconds of touch samples, drawn
+//! over whatever page is showing. Samples are recorded before dispatch, so
+//! touches consumed by widgets still show up.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use log::info;
+use slint::SharedString;
+
+use super::{
+    bounded_model::BoundedModel,
+    slint_ui::{self, App, Gesture, TraceMark, TraceStroke},
+};
+
+const TRACE_WINDOW: Duration = Duration::from_secs(2);
+const AUTO_DISABLE: Duration = Duration::from_secs(5 * 60);
+const REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+const IDLE_POLL: Duration = Duration::from_millis(250);
+/// Opacity steps across `TRACE_WINDOW`; each step is a separate `Path`.
+const FADE_STEPS: u32 = 4;
+const MAX_SAMPLES: usize = 256;
+const MAX_STROKES: usize = 128;
+const MAX_MARKS: usize = 64;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ENABLED_AT: Mutex<Option<Instant>> = Mutex::new(None);
+static SAMPLES: Mutex<VecDeque<Sample>> = Mutex::new(VecDeque::new());
+
+thread_local! {
+    static STROKE_MODEL: BoundedModel<TraceStroke> = BoundedModel::new("trace_strokes", MAX_STROKES);
+    static MARK_MODEL: BoundedModel<TraceMark> = BoundedModel::new("trace_marks", MAX_MARKS);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SampleKind {
+    Down,
+    Move,
+    Up,
+    Gesture(Gesture),
+    /// Dropped by the touch loop instead of being dispatched.
+    Rejected,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    at: Instant,
+    kind: SampleKind,
+    /// Controller coordinates before clamping to the panel.
+    raw: (f32, f32),
+    /// Coordinates handed to Slint.
+    mapped: (f32, f32),
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Safe to call from any thread; the overlay follows within `IDLE_POLL`.
+pub fn set_enabled(enabled: bool) {
+    if let Ok(mut at) = ENABLED_AT.lock() {
+        *at = enabled.then(Instant::now);
+    }
+    if let Ok(mut samples) = SAMPLES.lock() {
+        samples.clear();
+    }
+    ENABLED.store(enabled, Ordering::Relaxed);
+    info!("Touch trace {}", if enabled { "on" } else { "off" });
+}
+
+pub fn record_press(raw: (f32, f32), mapped: (f32, f32)) {
+    record(SampleKind::Down, raw, mapped);
+}
+
+pub fn record_move(raw: (f32, f32), mapped: (f32, f32)) {
+    record(SampleKind::Move, raw, mapped);
+}
+
+pub fn record_release(raw: (f32, f32), mapped: (f32, f32)) {
+    record(SampleKind::Up, raw, mapped);
+}
+
+pub fn record_gesture(gesture: Gesture, mapped: (f32, f32)) {
+    record(SampleKind::Gesture(gesture), mapped, mapped);
+}
+
+pub fn record_rejected(raw: (f32, f32), mapped: (f32, f32)) {
+    record(SampleKind::Rejected, raw, mapped);
+}
+
+fn record(kind: SampleKind, raw: (f32, f32), mapped: (f32, f32)) {
+    if !enabled() {
+        return;
+    }
+    if let Ok(mut samples) = SAMPLES.lock() {
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(Sample {
+            at: Instant::now(),
+            kind,
+            raw,
+            mapped,
+        });
+    }
+}
+
+pub fn install(app: &App) {
+    app.set_touch_trace_strokes(STROKE_MODEL.with(BoundedModel::model));
+    app.set_touch_trace_marks(MARK_MODEL.with(BoundedModel::model));
+    app.on_touch_trace_toggle(|| set_enabled(!enabled()));
+
+    tokio::task::spawn_local(async {
+        let mut shown = false;
+        loop {
+            let active = enabled();
+            if active && expired() {
+                set_enabled(false);
+                continue;
+            }
+            if active != shown {
+                shown = active;
+                if !active {
+                    STROKE_MODEL.with(|model| model.clear());
+                    MARK_MODEL.with(|model| model.clear());
+                }
+                slint_ui::with_app(|app| app.set_touch_trace_enabled(active));
+            }
+            if active {
+                refresh();
+                tokio::time::sleep(REFRESH_INTERVAL).await;
+            } else {
+                tokio::time::sleep(IDLE_POLL).await;
+            }
+        }
+    });
+}
+
+fn expired() -> bool {
+    ENABLED_AT
+        .lock()
+        .ok()
+        .and_then(|at| *at)
+        .is_some_and(|at| at.elapsed() >= AUTO_DISABLE)
+}
+
+fn refresh() {
+    let now = Instant::now();
+    let samples: Vec<Sample> = match SAMPLES.lock() {
+        Ok(mut samples) => {
+            while samples
+                .front()
+                .is_some_and(|sample| now.duration_since(sample.at) > TRACE_WINDOW)
+            {
+                samples.pop_front();
+            }
+            samples.iter().copied().collect()
+        }
+        Err(_) => return,
+    };
+
+    let mut fingers: Vec<Vec<&Sample>> = Vec::new();
+    for sample in &samples {
+        match (sample.kind, fingers.last_mut()) {
+            (SampleKind::Down, _) | (SampleKind::Move | SampleKind::Up, None) => {
+                fingers.push(vec![sample])
+            }
+            (SampleKind::Move | SampleKind::Up, Some(finger)) => finger.push(sample),
+            _ => {}
+        }
+    }
+    let strokes: Vec<TraceStroke> = fingers
+        .iter()
+        .flat_map(|points| {
+            let mut strokes = polylines(points, now, true);
+            strokes.extend(polylines(points, now, false));
+            strokes
+        })
+        .collect();
+
+    let marks: Vec<TraceMark> = samples
+        .iter()
+        .filter_map(|sample| {
+            let label = match sample.kind {
+                SampleKind::Gesture(gesture) => gesture_label(gesture),
+                SampleKind::Rejected => "rej",
+                _ => return None,
+            };
+            Some(TraceMark {
+                x: sample.raw.0,
+                y: sample.raw.1,
+                label: SharedString::from(label),
+                rejected: sample.kind == SampleKind::Rejected,
+                opacity: fade(now, sample.at),
+            })
+        })
+        .collect();
+
+    STROKE_MODEL.with(|model| model.set_vec(strokes));
+    MARK_MODEL.with(|model| model.set_vec(marks));
+}
+
+/// Splits one finger's path into `Path`s at fade-step boundaries so older
+/// segments can be drawn fainter.
+fn polylines(points: &[&Sample], now: Instant, raw: bool) -> Vec<TraceStroke> {
+    let position = |sample: &Sample| if raw { sample.raw } else { sample.mapped };
+    let mut strokes = Vec::new();
+    let mut current: Option<(u32, String)> = None;
+    for pair in points.windows(2) {
+        let (from, to) = (position(pair[0]), position(pair[1]));
+        let step = fade_step(now, pair[1].at);
+        match &mut current {
+            Some((current_step, commands)) if *current_step == step => {
+                let _ = write!(commands, " L {:.0} {:.0}", to.0, to.1);
+            }
+            _ => {
+                if let Some((step, commands)) = current.take() {
+                    strokes.push(stroke(commands, raw, step));
+                }
+                current = Some((
+                    step,
+                    format!("M {:.0} {:.0} L {:.0} {:.0}", from.0, from.1, to.0, to.1),
+                ));
+            }
+        }
+    }
+    if let Some((step, commands)) = current {
+        strokes.push(stroke(commands, raw, step));
+    }
+    strokes
+}
+
+fn stroke(commands: String, raw: bool, step: u32) -> TraceStroke {
+    TraceStroke {
+        commands: SharedString::from(commands),
+        raw,
+        opacity: 1.0 - step as f32 / FADE_STEPS as f32,
+    }
+}
+
+fn fade_step(now: Instant, at: Instant) -> u32 {
+    let age = now.duration_since(at).as_secs_f32() / TRACE_WINDOW.as_secs_f32();
+    ((age * FADE_STEPS as f32) as u32).min(FADE_STEPS - 1)
+}
+
+fn fade(now: Instant, at: Instant) -> f32 {
+    1.0 - fade_step(now, at) as f32 / FADE_STEPS as f32
+}
+
+fn gesture_label(gesture: Gesture) -> &'static str {
+    match gesture {
+        Gesture::SwipeUp => "up",
+        Gesture::SwipeDown => "down",
+        Gesture::SwipeLeft => "left",
+        Gesture::SwipeRight => "right",
+        Gesture::Button(_) => "button",
+    }
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "touchtrace",
+        "on | off: draw recent touches over the UI (auto-off after 5 min)",
+        |args| match args {
+            ["on"] => {
+                set_enabled(true);
+                Ok("touch trace on".to_string())
+            }
+            ["off"] => {
+                set_enabled(false);
+                Ok("touch trace off".to_string())
+            }
+            [] => Ok(format!(
+                "touch trace {}",
+                if enabled() { "on" } else { "off" }
+            )),
+            _ => bail!("usage: touchtrace on | off"),
+        },
+    );
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use slint::SharedString;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::slint_ui::{self, App};
+use crate::{
+    events::{self, SystemEvent},
+    i18n,
+    miwear::{
+        net_meter::{self, Session},
+        status::{self, ConnectionPhase, FailureKind, WatchTelemetry},
+    },
+};
+
+/// The throughput figures and the language have no events; they are
+/// checked at this rate.
+const METER_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn install(_app: &App) {
+    let mut events = events::subscribe();
+    tokio::task::spawn_local(async move {
+        let mut ticker = tokio::time::interval(METER_INTERVAL);
+        let mut seen_meter = None;
+        let mut stale = true;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let meter = (net_meter::generation(), i18n::active());
+                    stale |= seen_meter != Some(meter);
+                    seen_meter = Some(meter);
+                }
+                event = events.recv() => match event {
+                    Ok(SystemEvent::WatchStatusChanged)
+                    | Ok(SystemEvent::WatchBatteryChanged { .. })
+                    | Err(RecvError::Lagged(_)) => stale = true,
+                    Ok(SystemEvent::TransferProgress { .. }) => show_transfer(),
+                    Err(RecvError::Closed) => break,
+                    Ok(_) => {}
+                },
+            }
+            if stale {
+                show_status();
+                show_transfer();
+                stale = false;
+            }
+        }
+    });
+}
+
+fn show_status() {
+    let phase = status::phase();
+    let error = matches!(phase, ConnectionPhase::Failed { .. });
+    let text = match (&phase, status::telemetry(), net_meter::snapshot().session) {
+        (ConnectionPhase::Ready { .. }, Some(watch), Some(session)) => {
+            telemetry_line(&watch, &session)
+        }
+        _ => status_line(&phase).to_string(),
+    };
+    slint_ui::with_app(|app| {
+        app.set_watch_status(SharedString::from(text));
+        app.set_watch_error(error);
+    });
+}
+
+fn show_transfer() {
+    let transfer = status::transfer();
+    slint_ui::with_app(|app| {
+        app.set_transfer_visible(transfer.is_some());
+        if let Some(transfer) = &transfer {
+            app.set_transfer_label(SharedString::from(transfer.label.as_str()));
+            app.set_transfer_progress(transfer.fraction);
+        }
+    });
+}
+
+fn status_line(phase: &ConnectionPhase) -> &'static str {
+    match phase {
+        ConnectionPhase::Idle => i18n::tr("Watch: idle"),
+        ConnectionPhase::Scanning => i18n::tr("Watch: scanning"),
+        ConnectionPhase::Connecting => i18n::tr("Watch: connecting"),
+        ConnectionPhase::Handshaking => i18n::tr("Watch: authenticating"),
+        ConnectionPhase::Ready { .. } => i18n::tr("Watch: connected"),
+        ConnectionPhase::Failed {
+            kind: FailureKind::AuthRejected,
+            ..
+        } => i18n::tr("Auth key rejected, check key"),
+        ConnectionPhase::Failed {
+            kind: FailureKind::Timeout,
+            ..
+        } => i18n::tr("Watch: no response, retrying"),
+        ConnectionPhase::Failed { .. } => i18n::tr("Watch: failed, retrying"),
+    }
+}
+
+fn telemetry_line(watch: &WatchTelemetry, session: &Session) -> String {
+    let battery = watch
+        .battery
+        .map(|level| format!(" {level}%"))
+        .unwrap_or_default();
+    format!(
+        "{}{battery}  W {:.1} R {:.1} KB/s",
+        watch.name,
+        session.tx.current_bps / 1024.0,
+        session.rx.current_bps / 1024.0
+    )
+}
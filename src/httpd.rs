@@ -0,0 +1,780 @@
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::{
+    http::{
+        server::{Configuration, EspHttpConnection, EspHttpServer, Request},
+        Headers, Method,
+    },
+    io::{Read, Write},
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::{
+    allocator, board, boot, ecs, errors, gui, memory, miwear, nvs, periodic, power, statlogger,
+    version,
+};
+
+mod alarms;
+mod events;
+mod metrics;
+#[cfg(feature = "ancs")]
+mod notify;
+#[cfg(feature = "ota")]
+mod ota;
+mod settings;
+mod targets;
+
+const MAX_BODY: usize = 2048;
+
+/// The running server; `None` before boot or while paused.
+static SERVER: Mutex<Option<EspHttpServer<'static>>> = Mutex::new(None);
+
+pub fn start() -> Result<()> {
+    let server = build()?;
+    *SERVER
+        .lock()
+        .map_err(|_| anyhow!("HTTP server state poisoned"))? = Some(server);
+    start_event_stream();
+    Ok(())
+}
+
+/// Stops the server to hand its task stack and socket buffers back to the
+/// heap. A no-op if it never started.
+pub fn pause() -> Result<()> {
+    let server = SERVER
+        .lock()
+        .map_err(|_| anyhow!("HTTP server state poisoned"))?
+        .take();
+    drop(server);
+    events::stop();
+    Ok(())
+}
+
+/// Brings the server back after [`pause`].
+pub fn resume() -> Result<()> {
+    let mut server = SERVER
+        .lock()
+        .map_err(|_| anyhow!("HTTP server state poisoned"))?;
+    if server.is_none() {
+        *server = Some(build()?);
+    }
+    start_event_stream();
+    Ok(())
+}
+
+/// The event stream is an extra; the server is useful without it.
+fn start_event_stream() {
+    if let Err(err) = events::start() {
+        log::warn!("Event stream unavailable: {err:#}");
+    }
+}
+
+fn build() -> Result<EspHttpServer<'static>> {
+    let mut server = EspHttpServer::new(&Configuration {
+        uri_match_wildcard: true,
+        ..Default::default()
+    })?;
+
+    // Every route but the `/events` redirect goes through `protected`; the
+    // stream checks the token itself.
+    protected(&mut server, "/status", Method::Get, |req| {
+        send_json(req, 200, &status_json())
+    })?;
+    protected(
+        &mut server,
+        "/alloctrace",
+        Method::Get,
+        |req| match allocator::trace::last_report() {
+            Some(report) => send_json(req, 200, &trace_json(&report)),
+            None => send_json(req, 404, &json!({ "error": "no capture has run" })),
+        },
+    )?;
+    #[cfg(feature = "storage")]
+    protected(&mut server, "/logs/persistent", Method::Get, |req| {
+        send_text(req, &statlogger::flash_journal::read_all())
+    })?;
+    protected(&mut server, "/logs/recent", Method::Get, |req| {
+        send_log(req, &statlogger::ring::read_recent())
+    })?;
+    protected(&mut server, "/logs/errors", Method::Get, |req| {
+        send_log(req, &statlogger::ring::read_errors())
+    })?;
+    #[cfg(feature = "ancs")]
+    protected(&mut server, "/debug/ancs/sessions", Method::Get, |req| {
+        send_json(req, 200, &ancs_sessions_json())
+    })?;
+    protected(&mut server, "/debug/render", Method::Get, |req| {
+        send_json(req, 200, &render_profile_json())
+    })?;
+    protected(&mut server, "/debug/periodic", Method::Get, |req| {
+        send_json(req, 200, &periodic_json())
+    })?;
+    protected(&mut server, "/debug/ecs/contention", Method::Get, |req| {
+        send_json(req, 200, &ecs_contention_json())
+    })?;
+    protected(&mut server, "/watch/ring", Method::Post, |req| {
+        ring_response(req, true)
+    })?;
+    protected(&mut server, "/watch/ring", Method::Delete, |req| {
+        ring_response(req, false)
+    })?;
+    protected(
+        &mut server,
+        "/maintenance/download-mode",
+        Method::Post,
+        download_mode_response,
+    )?;
+    settings::register(&mut server)?;
+    targets::register(&mut server)?;
+    alarms::register(&mut server)?;
+    metrics::register(&mut server)?;
+    events::register(&mut server)?;
+    #[cfg(feature = "ancs")]
+    notify::register(&mut server)?;
+    #[cfg(feature = "ota")]
+    ota::register(&mut server)?;
+
+    Ok(server)
+}
+
+pub fn read_json<T: DeserializeOwned>(req: &mut Request<&mut EspHttpConnection>) -> Result<T> {
+    let len = req.content_len().unwrap_or(0) as usize;
+    if len > MAX_BODY {
+        bail!("body too large ({len} > {MAX_BODY} bytes)");
+    }
+    let mut body = vec![0u8; len];
+    req.read_exact(&mut body)
+        .map_err(|e| anyhow!("read body: {e:?}"))?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+pub fn send_json(req: Request<&mut EspHttpConnection>, status: u16, body: &Value) -> Result<()> {
+    let payload = body.to_string();
+    let mut resp = req.into_response(status, None, &[("Content-Type", "application/json")])?;
+    resp.write_all(payload.as_bytes())?;
+    Ok(())
+}
+
+/// Registers `handler` behind the bearer token set with `downloadmode
+/// token`; requests without it are answered by [`auth_error`] instead.
+fn protected<F>(
+    server: &mut EspHttpServer<'static>,
+    uri: &str,
+    method: Method,
+    handler: F,
+) -> Result<()>
+where
+    F: for<'r> Fn(Request<&mut EspHttpConnection<'r>>) -> Result<()> + Send + 'static,
+{
+    server.fn_handler(uri, method, move |req| {
+        let token = bearer(req.header("Authorization"));
+        if let Some((status, error)) = auth_error(token) {
+            return send_json(req, status, &json!({ "error": error }));
+        }
+        handler(req)
+    })?;
+    Ok(())
+}
+
+/// The token in an `Authorization: Bearer` header value.
+fn bearer(authorization: Option<&str>) -> Option<&str> {
+    authorization.and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Why a request presenting `token` may not be served, as a status and
+/// message; `None` when it matches the one set with `downloadmode token`.
+fn auth_error(token: Option<&str>) -> Option<(u16, &'static str)> {
+    use power::download_mode;
+    if !download_mode::http_enabled() {
+        return Some((
+            403,
+            "set a token with `downloadmode token` on the console first",
+        ));
+    }
+    if !download_mode::token_matches(token.unwrap_or("")) {
+        return Some((401, "bad or missing bearer token"));
+    }
+    None
+}
+
+fn send_text(req: Request<&mut EspHttpConnection>, body: &str) -> Result<()> {
+    let mut resp =
+        req.into_response(200, None, &[("Content-Type", "text/plain; charset=utf-8")])?;
+    resp.write_all(body.as_bytes())?;
+    Ok(())
+}
+
+/// `text`, or with `?session=<id>` only the lines tagged with that MiWear
+/// session (`3` or `s3`).
+fn send_log(req: Request<&mut EspHttpConnection>, text: &str) -> Result<()> {
+    match session_query(req.uri()) {
+        None => send_text(req, text),
+        Some(Ok(id)) => send_text(req, &miwear::logging::filter::session_lines(text, id)),
+        Some(Err(_)) => send_json(
+            req,
+            400,
+            &json!({ "error": "session is a session id, e.g. session=3" }),
+        ),
+    }
+}
+
+fn session_query(uri: &str) -> Option<Result<u32, std::num::ParseIntError>> {
+    let (_, query) = uri.split_once('?')?;
+    let raw = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("session="))?;
+    Some(raw.strip_prefix('s').unwrap_or(raw).parse())
+}
+
+fn status_json() -> Value {
+    let heap = statlogger::heap_snapshot();
+    let tx = miwear::send_queue::stats();
+    let flushed = miwear::send_queue::flush_stats();
+    let burst = miwear::send::burst::stats();
+    let stages: Vec<Value> = boot::report()
+        .iter()
+        .map(|stage| {
+            json!({
+                "name": stage.name,
+                "required": stage.criticality == boot::Criticality::Required,
+                "duration_ms": stage.duration.as_millis() as u64,
+                "error": stage.error,
+                "attempts": stage.attempts,
+                "parallel": stage.parallel,
+            })
+        })
+        .collect();
+    let milestones: Vec<Value> = boot::milestones()
+        .iter()
+        .map(|milestone| {
+            json!({
+                "name": milestone.name,
+                "at_ms": milestone.at.as_millis() as u64,
+                "target_ms": milestone.target.map(|target| target.as_millis() as u64),
+            })
+        })
+        .collect();
+
+    json!({
+        "version": version::FIRMWARE,
+        "uptime_ms": statlogger::uptime().as_millis() as u64,
+        "features": boot::enabled_features().collect::<Vec<_>>(),
+        "psram_present": allocator::psram_available(),
+        "psram": psram_json(),
+        "heap": {
+            "internal_free": heap.internal,
+            "dma_free": heap.dma,
+            "free_8bit": heap.eight_bit,
+            "psram_free": heap.psram,
+        },
+        "boot_stages": stages,
+        "boot_milestones": milestones,
+        "boot_notes": boot::notes(),
+        "boot_parallel_saved_ms": boot::parallel_savings().as_millis() as u64,
+        "watch": miwear::status::phase().to_string(),
+        "watch_link": watch_link_json(),
+        "link_stalls": link_stalls_json(),
+        "ble_pools": ble_pools_json(),
+        "watch_ring": miwear::ring::phase().label(),
+        "net_meter": net_meter_json(),
+        "watch_tx": {
+            "merged_packets": tx.merged_packets,
+            "bytes_saved": tx.bytes_saved,
+            "flushed_on_disconnect": flushed.total,
+            "flushed_last_session": flushed.last,
+            "burst": burst.burst,
+            "burst_policy": crate::settings::get(&miwear::send::burst::BURST).to_string(),
+            "burst_backoffs": burst.backoffs,
+            "last_backoff": burst.last_backoff.map(|why| why.label()),
+        },
+        "demo": miwear::demo::enabled(),
+        "display": display_json(),
+        "errors": errors_json(),
+        "journal": journal_json(),
+        "log_rings": log_rings_json(),
+        "nvs": nvs_json(),
+        "peripheral": peripheral_json(),
+        "ancs_mode": ancs_mode_json(),
+        "ancs_lifetime": ancs_lifetime_json(),
+        "memory_pressure": memory_pressure_json(),
+        "settings_rollback": settings_rollback_json(),
+        "event_stream": event_stream_json(),
+        "partitions": partitions_json(),
+    })
+}
+
+/// The Errors page's journal, oldest first.
+fn errors_json() -> Value {
+    let now = statlogger::uptime();
+    let entries: Vec<Value> = errors::snapshot()
+        .iter()
+        .map(|entry| {
+            json!({
+                "subsystem": entry.subsystem,
+                "message": entry.message,
+                "severity": entry.severity.label(),
+                "first_ms": entry.first.as_millis() as u64,
+                "timestamp_ms": entry.timestamp.as_millis() as u64,
+                "ago_s": now.saturating_sub(entry.timestamp).as_secs(),
+                "count": entry.count,
+                "resolved": entry.resolved,
+                "retry": errors::can_retry(entry.subsystem),
+            })
+        })
+        .collect();
+    json!({
+        "unresolved": errors::unresolved(),
+        "entries": entries,
+    })
+}
+
+fn settings_rollback_json() -> Value {
+    use crate::settings::rollback;
+
+    json!({
+        "pending": rollback::pending().map(|(subsystem, saved_at)| json!({
+            "subsystem": subsystem.code(),
+            "saved_at": saved_at,
+        })),
+        "rolled_back": rollback::notice().map(|notice| json!({
+            "subsystem": notice.subsystem.code(),
+            "ago_s": notice.at.elapsed().as_secs(),
+        })),
+    })
+}
+
+fn event_stream_json() -> Value {
+    let stream = events::stats();
+    json!({
+        "port": events::PORT,
+        "clients": stream.clients,
+        "dropped": stream.dropped,
+    })
+}
+
+fn memory_pressure_json() -> Value {
+    use memory::pressure::{self, PressureLevel};
+    json!({
+        "level": pressure::level().label(),
+        "floors": {
+            "warning": PressureLevel::Warning.floor(),
+            "critical": PressureLevel::Critical.floor(),
+            "emergency": PressureLevel::Emergency.floor(),
+        },
+        "hysteresis": pressure::HYSTERESIS,
+    })
+}
+
+/// POST rings, DELETE stops; blocks this handler until the watch answers.
+fn ring_response(req: Request<&mut EspHttpConnection>, ring: bool) -> Result<()> {
+    use miwear::ring::RingError;
+    match miwear::ring::request_blocking(ring) {
+        Ok(()) => send_json(req, 200, &json!({ "ring": miwear::ring::phase().label() })),
+        Err(err) => {
+            let status = match err {
+                RingError::NotConnected => 409,
+                RingError::Unsupported => 501,
+                RingError::Timeout => 504,
+                RingError::Unavailable => 503,
+                RingError::Failed(_) => 502,
+            };
+            send_json(req, status, &json!({ "error": err.to_string() }))
+        }
+    }
+}
+
+/// Answers before the reboot starts; the device drops off the network
+/// a moment later and reappears as a USB ROM loader.
+fn download_mode_response(req: Request<&mut EspHttpConnection>) -> Result<()> {
+    match power::download_mode::request("http") {
+        Ok(()) => send_json(req, 202, &json!({ "download_mode": "rebooting" })),
+        Err(err) => send_json(req, 409, &json!({ "error": format!("{err:#}") })),
+    }
+}
+
+fn psram_json() -> Value {
+    let report = memory::psram_report();
+    json!({
+        "board": board::CURRENT.name,
+        "expected_bytes": report.expected_bytes,
+        "heap_bytes": report.heap_bytes,
+        "healthy": report.healthy,
+        "error": report.error,
+    })
+}
+
+fn net_meter_json() -> Value {
+    let meter = miwear::net_meter::snapshot();
+    let direction = |direction: &miwear::net_meter::Direction| {
+        json!({
+            "current_bps": direction.current_bps,
+            "peak_bps": direction.peak_bps,
+            "total_bytes": direction.total_bytes,
+        })
+    };
+    json!({
+        "smoothing": meter.smoothing,
+        "session": meter.session.as_ref().map(|session| json!({
+            "addr": session.addr,
+            "tx": direction(&session.tx),
+            "rx": direction(&session.rx),
+        })),
+        "lifetime": {
+            "tx_bytes": meter.lifetime_tx_bytes,
+            "rx_bytes": meter.lifetime_rx_bytes,
+        },
+    })
+}
+
+fn watch_link_json() -> Value {
+    match miwear::status::link() {
+        Some(link) => json!({
+            "phy": link.phy.map(|phy| phy.label()),
+            "interval_ms": link.interval_ms,
+        }),
+        None => Value::Null,
+    }
+}
+
+fn ble_pools_json() -> Value {
+    let pools: Vec<Value> = crate::ble::resources::snapshot()
+        .iter()
+        .map(|pool| {
+            json!({
+                "name": pool.name,
+                "block_size": pool.block_size,
+                "blocks": pool.blocks,
+                "free": pool.free,
+                "min_free": pool.min_free,
+                "exhausted": pool.exhausted(),
+            })
+        })
+        .collect();
+    Value::Array(pools)
+}
+
+fn link_stalls_json() -> Value {
+    let stats = miwear::liveness::stats();
+    json!({
+        "resubscribes": stats.resubscribes,
+        "reads": stats.reads,
+        "disconnects": stats.disconnects,
+        "recovered": stats.recovered,
+    })
+}
+
+#[cfg(feature = "storage")]
+fn journal_json() -> Value {
+    let stats = statlogger::flash_journal::stats();
+    json!({
+        "mounted": crate::storage::mounted(),
+        "dropped_lines": stats.dropped_lines,
+        "write_failures": stats.write_failures,
+    })
+}
+
+#[cfg(not(feature = "storage"))]
+fn journal_json() -> Value {
+    Value::Null
+}
+
+fn log_rings_json() -> Value {
+    let report = statlogger::ring::ring_stats();
+    let ring = |stats: &statlogger::ring::RingStats| {
+        let [error, warn, info, debug, trace] = stats.per_level;
+        json!({
+            "capacity_bytes": stats.capacity,
+            "used_bytes": stats.used,
+            "lines": stats.lines,
+            "oldest_age_ms": stats.oldest_age.map(|age| age.as_millis() as u64),
+            "bytes_written": stats.bytes_written,
+            "lines_written": stats.lines_written,
+            "per_level": {
+                "error": error,
+                "warn": warn,
+                "info": info,
+                "debug": debug,
+                "trace": trace,
+            },
+            "dropped_lines": stats.dropped_lines,
+            "rejected_lines": stats.rejected_lines,
+            "wraps": stats.wraps,
+            "summary": stats.to_string(),
+        })
+    };
+    json!({
+        "main": ring(&report.main),
+        "errors": ring(&report.errors),
+    })
+}
+
+fn nvs_json() -> Value {
+    let stats = nvs::stats();
+    json!({
+        "queue_depth": stats.queue_depth,
+        "commits": stats.commits,
+        "coalesced": stats.coalesced,
+    })
+}
+
+fn display_json() -> Value {
+    let health = gui::display::health();
+    json!({
+        "state": if health.failed { "failed" } else { "ok" },
+        "recoveries": health.recoveries,
+        "last_error": health.last_error,
+        "spi_mhz": f64::from(health.spi_hz) / 1e6,
+        "spi_lowered_from_mhz": health.spi_lowered_from_hz.map(|hz| f64::from(hz) / 1e6),
+    })
+}
+
+#[cfg(feature = "ancs")]
+fn peripheral_json() -> Value {
+    use crate::ble::standard_services;
+    use miwear::ancs::advertising;
+
+    let mut services = vec![json!({ "name": "ancs", "uuid": miwear::ancs::SERVICE_UUID })];
+    if standard_services::registered() {
+        services.push(json!({
+            "name": "battery",
+            "uuid": format!("{:04x}", standard_services::BATTERY_SERVICE),
+            "level": standard_services::published_level(),
+        }));
+        services.push(json!({
+            "name": "device_information",
+            "uuid": format!("{:04x}", standard_services::DEVICE_INFORMATION_SERVICE),
+            "manufacturer": version::MANUFACTURER,
+            "model": version::MODEL,
+            "firmware": version::FIRMWARE,
+        }));
+    }
+    let preset = advertising::active();
+    let params = preset.params();
+    json!({
+        "advertised_name": miwear::ancs::ADVERTISED_NAME,
+        "advertising": {
+            "policy": crate::settings::get(&advertising::ADV_POLICY).to_string(),
+            "preset": preset.code(),
+            "min_interval_ms": params.min_ms,
+            "max_interval_ms": params.max_ms,
+            "tx_power_dbm": params.tx_level_dbm(),
+        },
+        "services": services,
+    })
+}
+
+#[cfg(not(feature = "ancs"))]
+fn peripheral_json() -> Value {
+    Value::Null
+}
+
+#[cfg(feature = "ancs")]
+fn ancs_mode_json() -> Value {
+    json!({
+        "mode": miwear::ancs::mode().code(),
+        "enabled_setting": crate::settings::get(&miwear::ancs::ANCS_ENABLED),
+    })
+}
+
+#[cfg(not(feature = "ancs"))]
+fn ancs_mode_json() -> Value {
+    Value::Null
+}
+
+#[cfg(feature = "ota")]
+fn partitions_json() -> Value {
+    let Some(layout) = crate::ota::layout() else {
+        return Value::Null;
+    };
+    let slot = |slot: &crate::ota::Slot| json!({ "label": slot.label, "address": slot.address, "size": slot.size });
+    json!({
+        "running": layout.running.label,
+        "update": layout.update.as_ref().map(|update| update.label.as_str()),
+        "update_size": layout.update.as_ref().map(|update| update.size),
+        "apps": layout.apps.iter().map(slot).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(not(feature = "ota"))]
+fn partitions_json() -> Value {
+    Value::Null
+}
+
+#[cfg(feature = "ancs")]
+fn ancs_lifetime_json() -> Value {
+    let lifetime = miwear::ancs::sessions::lifetime();
+    json!({
+        "sessions": lifetime.sessions,
+        "subscribes": lifetime.subscribes,
+        "commands": lifetime.commands,
+        "attribute_requests": lifetime.attribute_requests,
+        "parse_errors": lifetime.parse_errors,
+        "responses_sent": lifetime.responses_sent,
+        "rejected_unencrypted": lifetime.rejected_unencrypted,
+        "security_nudges": lifetime.security_nudges,
+        "notify_failures": lifetime.notify_failures,
+    })
+}
+
+#[cfg(not(feature = "ancs"))]
+fn ancs_lifetime_json() -> Value {
+    Value::Null
+}
+
+fn render_profile_json() -> Value {
+    use gui::render_profile::{self, BAND_LINES};
+
+    let frames: Vec<Value> = render_profile::worst()
+        .iter()
+        .map(|frame| {
+            json!({
+                "view": frame.view,
+                "at_ms": frame.at.as_millis() as u64,
+                "total_us": frame.total.as_micros() as u64,
+                "render_us": frame.render.as_micros() as u64,
+                "flush_us": frame.flush.as_micros() as u64,
+                "lines_rendered": frame.lines_rendered,
+                "lines_flushed": frame.lines_flushed,
+                "extent": frame.extent.map(|e| json!({
+                    "top": e.top,
+                    "bottom": e.bottom,
+                    "left": e.left,
+                    "right": e.right,
+                })),
+                "band_render_us": frame.band_render_us,
+                "band_flush_us": frame.band_flush_us,
+            })
+        })
+        .collect();
+    json!({
+        "enabled": render_profile::enabled(),
+        "profiled": render_profile::profiled(),
+        "band_lines": BAND_LINES,
+        "panel_offset": gui::display::panel_offset(),
+        "worst": frames,
+    })
+}
+
+fn periodic_json() -> Value {
+    let snapshot = periodic::snapshot();
+    let jobs: Vec<Value> = snapshot
+        .jobs
+        .iter()
+        .map(|job| {
+            json!({
+                "name": job.name,
+                "period_ms": job.period.as_millis() as u64,
+                "runs": job.runs,
+                "last_us": job.last.as_micros() as u64,
+                "max_us": job.max.as_micros() as u64,
+                "overruns": job.overruns,
+                "skipped": job.skipped,
+            })
+        })
+        .collect();
+    json!({
+        "slot_us": periodic::SLOT.as_micros() as u64,
+        "wakeups": snapshot.wakeups,
+        "running_s": snapshot.running_for.as_secs(),
+        "jobs": jobs,
+    })
+}
+
+fn ecs_contention_json() -> Value {
+    let histogram = |histogram: &ecs::Histogram| {
+        json!({
+            "buckets": histogram.buckets,
+            "avg_us": histogram.average().as_micros() as u64,
+            "max_us": histogram.max.as_micros() as u64,
+        })
+    };
+    let labels: Vec<Value> = ecs::snapshot()
+        .iter()
+        .map(|entry| {
+            json!({
+                "label": entry.label,
+                "calls": entry.calls,
+                "slow": entry.slow,
+                "wait": histogram(&entry.wait),
+                "hold": histogram(&entry.hold),
+            })
+        })
+        .collect();
+    json!({
+        "bucket_bounds_ms": ecs::BOUNDS_MS,
+        "warn_after_ms": ecs::WARN_AFTER.as_millis() as u64,
+        "labels": labels,
+    })
+}
+
+#[cfg(feature = "ancs")]
+fn ancs_sessions_json() -> Value {
+    use miwear::ancs::sessions::{self, SessionStats};
+
+    let session_json = |session: &SessionStats| {
+        json!({
+            "conn_handle": session.conn_handle,
+            "addr": session.addr,
+            "duration_ms": session.duration.map(|duration| duration.as_millis() as u64),
+            "disconnect_reason": session.disconnect_reason,
+            "subscribes": session.subscribes,
+            "commands": {
+                "get_notification_attributes": session.get_notification_attributes,
+                "get_app_attributes": session.get_app_attributes,
+                "perform_action": session.perform_action,
+                "unknown": session.unknown_commands,
+            },
+            "parse_errors": session.parse_errors,
+            "last_parse_error": session.last_parse_error.map(|error| error.to_string()),
+            "attributes": {
+                "notification": session.notification_attributes,
+                "app_display_name": session.app_display_name,
+                "other": session.other_attributes,
+            },
+            "responses_sent": session.responses_sent,
+            "fragments_sent": session.fragments_sent,
+            "mtu": session.mtu,
+            "pacing": {
+                "held_events": session.paced_events,
+                "total_delay_ms": session.paced_total.as_millis() as u64,
+                "max_delay_ms": session.paced_max.as_millis() as u64,
+            },
+            "rejected_unencrypted": session.rejected_unencrypted,
+            "security_nudges": session.security_nudges,
+            "notify_failures": session.notify_failures,
+            "connect_to_encrypt_ms": session
+                .connect_to_encrypt
+                .map(|duration| duration.as_millis() as u64),
+            "encrypt_to_first_request_ms": session
+                .encrypt_to_first_request
+                .map(|duration| duration.as_millis() as u64),
+        })
+    };
+    json!({
+        "active": sessions::active().iter().map(session_json).collect::<Vec<_>>(),
+        "recent": sessions::recent().iter().map(session_json).collect::<Vec<_>>(),
+    })
+}
+
+fn trace_json(report: &allocator::trace::TraceReport) -> Value {
+    let sites: Vec<Value> = report
+        .sites
+        .iter()
+        .map(|stats| {
+            json!({
+                "site": stats.site.iter().map(|addr| format!("{addr:#010x}")).collect::<Vec<_>>(),
+                "allocs": stats.allocs,
+                "total_bytes": stats.total_bytes,
+                "live_bytes": stats.live_bytes,
+                "psram_allocs": stats.psram_allocs,
+            })
+        })
+        .collect();
+    json!({
+        "records": report.records,
+        "truncated": report.truncated,
+        "sites": sites,
+    })
+}
@@ -0,0 +1,110 @@
+use anyhow::{bail, Result};
+use esp_idf_svc::http::{
+    server::{EspHttpConnection, EspHttpServer, Request},
+    Method,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{protected, read_json, send_json};
+use crate::miwear::alarms::{self, AlarmError, AlarmList, AlarmTime, NewAlarm, Weekdays};
+
+#[derive(Deserialize)]
+struct AddBody {
+    /// `HH:MM`, 24-hour.
+    time: String,
+    /// `once`, `daily`, `workdays` or `mon,wed,...`.
+    #[serde(default)]
+    repeat: String,
+    #[serde(default)]
+    label: String,
+}
+
+pub fn register(server: &mut EspHttpServer<'static>) -> Result<()> {
+    protected(server, "/alarms", Method::Get, |req| {
+        respond(req, 200, alarms::list_blocking())
+    })?;
+
+    protected(server, "/alarms", Method::Post, |mut req| {
+        let body: AddBody = match read_json(&mut req) {
+            Ok(body) => body,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        let alarm = body.time.parse::<AlarmTime>().and_then(|time| {
+            Ok(NewAlarm {
+                time,
+                repeat: body.repeat.parse::<Weekdays>()?,
+                label: body.label,
+            })
+        });
+        match alarm {
+            Ok(alarm) => respond(req, 201, alarms::add_blocking(alarm)),
+            Err(err) => send_json(req, 400, &json!({ "error": err.to_string() })),
+        }
+    })?;
+
+    protected(server, "/alarms/*", Method::Delete, |req| {
+        let id = match id_from_uri(req.uri()) {
+            Ok(id) => id,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        respond(req, 200, alarms::delete_blocking(id))
+    })?;
+
+    Ok(())
+}
+
+fn respond(
+    req: Request<&mut EspHttpConnection>,
+    ok: u16,
+    result: Result<AlarmList, AlarmError>,
+) -> Result<()> {
+    match result {
+        Ok(list) => send_json(req, ok, &list_json(&list)),
+        Err(err) => {
+            let status = match err {
+                AlarmError::NotConnected | AlarmError::Full { .. } => 409,
+                AlarmError::NotFound(_) => 404,
+                AlarmError::InvalidTime | AlarmError::InvalidRepeat(_) => 400,
+                AlarmError::Unsupported => 501,
+                AlarmError::Timeout => 504,
+                AlarmError::Unavailable => 503,
+                AlarmError::Failed(_) => 502,
+            };
+            let mut body = json!({ "error": err.to_string() });
+            if let AlarmError::Full { limit } = err {
+                body["limit"] = json!(limit);
+            }
+            send_json(req, status, &body)
+        }
+    }
+}
+
+fn id_from_uri(uri: &str) -> Result<u32> {
+    let path = uri.split('?').next().unwrap_or(uri);
+    let Some(raw) = path.strip_prefix("/alarms/") else {
+        bail!("expected /alarms/<id>");
+    };
+    Ok(raw.trim_end_matches('/').parse()?)
+}
+
+fn list_json(list: &AlarmList) -> Value {
+    let entries: Vec<Value> = list
+        .alarms
+        .iter()
+        .map(|alarm| {
+            json!({
+                "id": alarm.id,
+                "time": alarm.time.to_string(),
+                "repeat": alarm.repeat.to_string(),
+                "label": alarm.label,
+                "enabled": alarm.enabled,
+            })
+        })
+        .collect();
+    json!({
+        "alarms": entries,
+        "count": list.alarms.len(),
+        "limit": list.limit,
+    })
+}
@@ -0,0 +1,586 @@
+//! Server-sent event stream of the system event bus.
+//!
+//! esp_http_server runs every handler on its one task, so a stream held open
+//! there would stall `/status` and everything else. The stream gets its own
+//! listener on [`PORT`] with a thread per client instead; `GET /events` on
+//! the main server redirects there.
+//!
+//! `GET /events[?types=miwear,notification]` needs the same bearer token as
+//! `/maintenance/download-mode`, either in `Authorization` or, for browsers
+//! whose `EventSource` cannot set headers, as `?token=`. Every response
+//! carries `Access-Control-Allow-Origin: *` and `OPTIONS` answers the
+//! preflight, so a page served from the main port can follow the redirect
+//! to this one. Each event is one `data:` line holding
+//! `{"type", "category", "ts_ms", "uptime_ms", "payload"}`; `types` matches
+//! either field. A comment every [`HEARTBEAT`] keeps proxies from closing an
+//! idle stream. A client that falls behind the bus loses the oldest events;
+//! the next event carries how many in `dropped`.
+//!
+//! Log records are only streamed to clients that name `logs` in `types`,
+//! as `log` events at `min_level` (default `info`) and above; see
+//! `statlogger::tail` for how a level above the console's is served. Each
+//! client gets at most `log tail <lines/s>` of them; the rest are counted
+//! and reported once a second in a `log_suppressed` event.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use esp_idf_svc::http::{server::EspHttpServer, Headers, Method};
+use log::{info, warn, LevelFilter};
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{error::TryRecvError, Receiver};
+
+use crate::{
+    events::{self, SystemEvent},
+    metrics,
+    statlogger::{
+        self,
+        tail::{self, LogLine, Tail},
+    },
+};
+
+pub const PORT: u16 = 8081;
+const MAX_CLIENTS: usize = 2;
+const HEARTBEAT: Duration = Duration::from_secs(15);
+/// How often a client thread checks the bus and whether its peer left.
+const POLL: Duration = Duration::from_millis(100);
+/// A peer that takes longer than this to accept a write is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// For the 503 written from the accept thread itself.
+const REJECT_TIMEOUT: Duration = Duration::from_millis(500);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST: usize = 2048;
+const STACK_SIZE: usize = 6 * 1024;
+/// How often a throttled log tail reports what it held back.
+const SUMMARY_EVERY: Duration = Duration::from_secs(1);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static CLIENTS: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug)]
+pub struct StreamStats {
+    pub clients: usize,
+    /// Events lost to slow clients since boot.
+    pub dropped: u32,
+}
+
+pub fn stats() -> StreamStats {
+    StreamStats {
+        clients: CLIENTS.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+    }
+}
+
+pub fn register(server: &mut EspHttpServer<'static>) -> Result<()> {
+    server.fn_handler("/events", Method::Get, |req| {
+        let host = req
+            .header("Host")
+            .map(|host| host.split(':').next().unwrap_or(host).to_string())
+            .unwrap_or_default();
+        let query = req.uri().split_once('?').map(|(_, query)| query);
+        let location = match query {
+            Some(query) => format!("http://{host}:{PORT}/events?{query}"),
+            None => format!("http://{host}:{PORT}/events"),
+        };
+        req.into_response(307, None, &[("Location", location.as_str())])?;
+        Ok::<(), anyhow::Error>(())
+    })?;
+    Ok(())
+}
+
+/// Starts the listener; a no-op while it is already running.
+pub fn start() -> Result<()> {
+    if RUNNING.swap(true, Ordering::Relaxed) {
+        return Ok(());
+    }
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, PORT))
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener));
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(err) => {
+            RUNNING.store(false, Ordering::Relaxed);
+            return Err(err).context("bind event stream port");
+        }
+    };
+    thread::Builder::new()
+        .name("sse-accept".into())
+        .stack_size(STACK_SIZE)
+        .spawn(move || accept_loop(listener))
+        .context("spawn event stream listener")?;
+    info!("Event stream on port {PORT}");
+    Ok(())
+}
+
+/// Closes the listener and every stream within [`POLL`].
+pub fn stop() {
+    RUNNING.store(false, Ordering::Relaxed);
+}
+
+fn accept_loop(listener: TcpListener) {
+    while RUNNING.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((mut stream, peer)) => {
+                // Claimed before anything is read, so a client thread (and
+                // its stack) only ever exists for a slot, authenticated or not.
+                let Some(slot) = Slot::claim() else {
+                    if let Err(err) = reject_busy(&mut stream) {
+                        info!("Event stream busy reply to {peer} failed: {err:#}");
+                    }
+                    continue;
+                };
+                let spawned = thread::Builder::new()
+                    .name("sse-client".into())
+                    .stack_size(STACK_SIZE)
+                    .spawn(move || {
+                        if let Err(err) = serve(stream, slot) {
+                            info!("Event stream to {peer} ended: {err:#}");
+                        }
+                    });
+                if let Err(err) = spawned {
+                    warn!("Event stream client not served: {err}");
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(POLL),
+            Err(err) => {
+                warn!("Event stream accept failed: {err}");
+                thread::sleep(POLL);
+            }
+        }
+    }
+}
+
+/// Releases a client slot however the stream ends.
+struct Slot;
+
+impl Slot {
+    fn claim() -> Option<Self> {
+        CLIENTS
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < MAX_CLIENTS).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Slot)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        CLIENTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Answers 503 without reading the request; every slot is taken.
+fn reject_busy(stream: &mut TcpStream) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(REJECT_TIMEOUT))?;
+    respond(
+        stream,
+        "503 Service Unavailable",
+        "too many event stream clients",
+    )
+}
+
+/// Serves one client; `_slot` is held until the stream ends.
+fn serve(mut stream: TcpStream, _slot: Slot) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let request = read_request(&mut stream)?;
+    let Some((method, path, query)) = request_target(&request) else {
+        return respond(&mut stream, "400 Bad Request", "malformed request");
+    };
+    if path != "/events" {
+        return respond(&mut stream, "404 Not Found", "only /events is served here");
+    }
+    match method {
+        "GET" => {}
+        "OPTIONS" => return preflight(&mut stream),
+        _ => return respond(&mut stream, "405 Method Not Allowed", "only GET is served"),
+    }
+    let token = super::bearer(header(&request, "authorization"))
+        .map(str::to_string)
+        .or_else(|| query_token(query));
+    if let Some((status, error)) = super::auth_error(token.as_deref()) {
+        let status = match status {
+            401 => "401 Unauthorized",
+            _ => "403 Forbidden",
+        };
+        return respond(&mut stream, status, error);
+    }
+    let filter = Filter::parse(query);
+    let mut logs = match filter.log_level() {
+        Some(min) => match Tail::open(min) {
+            Some(tail) => Some(LogStream::new(tail)),
+            None => {
+                return respond(
+                    &mut stream,
+                    "409 Conflict",
+                    "another client is already tailing above the configured log level",
+                )
+            }
+        },
+        None => None,
+    };
+
+    let mut events = events::subscribe();
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+          Cache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\n\
+          Connection: close\r\n\r\n: connected\n\n",
+    )?;
+    stream.set_read_timeout(Some(POLL))?;
+    stream_events(&mut stream, &mut events, &filter, logs.as_mut())
+}
+
+fn stream_events(
+    stream: &mut TcpStream,
+    events: &mut Receiver<SystemEvent>,
+    filter: &Filter,
+    mut logs: Option<&mut LogStream>,
+) -> Result<()> {
+    let mut last_write = Instant::now();
+    let mut dropped = 0u64;
+    let mut scratch = [0u8; 64];
+    while RUNNING.load(Ordering::Relaxed) {
+        loop {
+            match events.try_recv() {
+                Ok(event) => {
+                    let (kind, category, payload) = describe(&event);
+                    if !filter.allows(kind, category) {
+                        continue;
+                    }
+                    let mut body = json!({
+                        "type": kind,
+                        "category": category,
+                        "ts_ms": wall_clock_ms(SystemTime::now()),
+                        "uptime_ms": statlogger::uptime().as_millis() as u64,
+                        "payload": payload,
+                    });
+                    if dropped > 0 {
+                        body["dropped"] = json!(std::mem::take(&mut dropped));
+                    }
+                    stream.write_all(format!("data: {body}\n\n").as_bytes())?;
+                    last_write = Instant::now();
+                }
+                Err(TryRecvError::Lagged(missed)) => {
+                    dropped += missed;
+                    DROPPED.fetch_add(missed as u32, Ordering::Relaxed);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => return Ok(()),
+            }
+        }
+        if let Some(logs) = logs.as_deref_mut() {
+            if logs.drain(stream)? {
+                last_write = Instant::now();
+            }
+        }
+        if last_write.elapsed() >= HEARTBEAT {
+            stream.write_all(b": heartbeat\n\n")?;
+            last_write = Instant::now();
+        }
+        // Doubles as the poll delay; a closed peer reads as end of stream.
+        match stream.read(&mut scratch) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+/// A log tail's share of one client's stream: its rate budget and what
+/// went unsent since the last summary.
+struct LogStream {
+    tail: Tail,
+    /// Lines that may still go out; refills at the configured rate, up to
+    /// one second's worth.
+    budget: f32,
+    refilled: Instant,
+    suppressed: u64,
+    /// Lost because the client thread fell behind the logger.
+    lost: u64,
+    summarized: Instant,
+}
+
+impl LogStream {
+    fn new(tail: Tail) -> Self {
+        let now = Instant::now();
+        Self {
+            tail,
+            budget: tail::rate() as f32,
+            refilled: now,
+            suppressed: 0,
+            lost: 0,
+            summarized: now,
+        }
+    }
+
+    /// Writes what the budget allows; `true` when anything was written.
+    fn drain(&mut self, stream: &mut TcpStream) -> Result<bool> {
+        let rate = tail::rate() as f32;
+        self.budget = (self.budget + self.refilled.elapsed().as_secs_f32() * rate).min(rate);
+        self.refilled = Instant::now();
+        let mut wrote = false;
+        loop {
+            match self.tail.try_recv() {
+                Ok(line) if self.budget >= 1.0 => {
+                    self.budget -= 1.0;
+                    stream.write_all(format!("data: {}\n\n", log_json(&line)).as_bytes())?;
+                    wrote = true;
+                }
+                Ok(_) => self.suppressed += 1,
+                Err(TryRecvError::Lagged(missed)) => self.lost += missed,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        if (self.suppressed > 0 || self.lost > 0) && self.summarized.elapsed() >= SUMMARY_EVERY {
+            let body = json!({
+                "type": "log_suppressed",
+                "category": "logs",
+                "ts_ms": wall_clock_ms(SystemTime::now()),
+                "uptime_ms": statlogger::uptime().as_millis() as u64,
+                "payload": {
+                    "suppressed": std::mem::take(&mut self.suppressed),
+                    "lost": std::mem::take(&mut self.lost),
+                    "lines_per_s": tail::rate(),
+                },
+            });
+            stream.write_all(format!("data: {body}\n\n").as_bytes())?;
+            self.summarized = Instant::now();
+            wrote = true;
+        }
+        Ok(wrote)
+    }
+}
+
+fn log_json(line: &LogLine) -> Value {
+    json!({
+        "type": "log",
+        "category": "logs",
+        "ts_ms": wall_clock_ms(line.at),
+        "uptime_ms": line.uptime.as_millis() as u64,
+        "payload": {
+            "level": line.level.as_str(),
+            "target": line.target,
+            "message": line.message,
+        },
+    })
+}
+
+/// `types=` entries, matched against an event's type or category, and the
+/// `min_level=` of a log tail.
+struct Filter {
+    types: Option<Vec<String>>,
+    min_level: Option<LevelFilter>,
+}
+
+impl Filter {
+    fn parse(query: Option<&str>) -> Self {
+        let pairs = || query.into_iter().flat_map(|query| query.split('&'));
+        let min_level = pairs()
+            .find_map(|pair| pair.strip_prefix("min_level="))
+            .and_then(|level| level.parse().ok());
+        let types = pairs()
+            .find_map(|pair| pair.strip_prefix("types="))
+            .map(|list| {
+                list.to_ascii_lowercase()
+                    .replace("%2c", ",")
+                    .split(',')
+                    .filter(|entry| !entry.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            });
+        Filter { types, min_level }
+    }
+
+    fn allows(&self, kind: &str, category: &str) -> bool {
+        match &self.types {
+            None => true,
+            Some(types) => types.iter().any(|entry| entry == kind || entry == category),
+        }
+    }
+
+    /// The tail level, when `types` asks for logs explicitly; a stream of
+    /// everything leaves them out.
+    fn log_level(&self) -> Option<LevelFilter> {
+        let types = self.types.as_ref()?;
+        types
+            .iter()
+            .any(|entry| entry == "logs" || entry == "log")
+            .then(|| self.min_level.unwrap_or(LevelFilter::Info))
+    }
+}
+
+/// Type, category and payload of an event as the stream shows it.
+fn describe(event: &SystemEvent) -> (&'static str, &'static str, Value) {
+    match event {
+        SystemEvent::ThermalWarning { active, celsius } => (
+            "thermal_warning",
+            "system",
+            json!({ "active": active, "celsius": celsius }),
+        ),
+        SystemEvent::ShuttingDown { reason } => {
+            ("shutting_down", "system", json!({ "reason": reason }))
+        }
+        SystemEvent::MemoryPressure { level } => (
+            "memory_pressure",
+            "system",
+            json!({ "level": level.label() }),
+        ),
+        SystemEvent::WatchStatusChanged => (
+            "watch_status",
+            "miwear",
+            json!({ "phase": crate::miwear::status::phase().label() }),
+        ),
+        SystemEvent::WatchBatteryChanged { level } => {
+            ("watch_battery", "miwear", json!({ "level": level }))
+        }
+        SystemEvent::TransferProgress { fraction } => (
+            "transfer_progress",
+            "miwear",
+            json!({ "fraction": fraction }),
+        ),
+        SystemEvent::RingChanged { phase } => ("ring", "miwear", json!({ "phase": phase.label() })),
+        SystemEvent::NotificationPublished { uid, app_id, title } => (
+            "notification",
+            "notification",
+            json!({ "uid": uid, "app_id": app_id, "title": title }),
+        ),
+        SystemEvent::SettingsRolledBack { subsystem } => (
+            "settings_rolled_back",
+            "system",
+            json!({ "subsystem": subsystem.code() }),
+        ),
+        SystemEvent::SettingChanged { name, revision } => (
+            "setting",
+            "system",
+            json!({ "name": name, "revision": revision }),
+        ),
+        SystemEvent::OrientationChanged { rotation } => (
+            "orientation",
+            "system",
+            json!({ "degrees": rotation.degrees() }),
+        ),
+        SystemEvent::ErrorsChanged { unresolved } => {
+            ("errors", "system", json!({ "unresolved": unresolved }))
+        }
+        SystemEvent::RelayDegraded { failures } => (
+            "relay_degraded",
+            "notification",
+            json!({ "failures": failures }),
+        ),
+        SystemEvent::BlePoolExhausted { pool } => {
+            ("ble_pool_exhausted", "system", json!({ "pool": pool }))
+        }
+        SystemEvent::OutboxExpired { id, app_id, title } => (
+            "outbox_expired",
+            "notification",
+            json!({ "id": id, "app_id": app_id, "title": title }),
+        ),
+    }
+}
+
+/// `at` in Unix milliseconds; `None` until the clock has been set.
+fn wall_clock_ms(at: SystemTime) -> Option<u64> {
+    metrics::today()?;
+    at.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_millis() as u64)
+}
+
+/// Reads up to the blank line that ends the request head.
+fn read_request(stream: &mut TcpStream) -> Result<String> {
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 256];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST {
+            anyhow::bail!("request head over {MAX_REQUEST} bytes");
+        }
+        let read = stream.read(&mut chunk).context("read request")?;
+        if read == 0 {
+            anyhow::bail!("peer closed before the request ended");
+        }
+        head.extend_from_slice(&chunk[..read]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Method, path and query of the request line.
+fn request_target(request: &str) -> Option<(&str, &str, Option<&str>)> {
+    let line = request.lines().next()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    Some(match target.split_once('?') {
+        Some((path, query)) => (method, path, Some(query)),
+        None => (method, target, None),
+    })
+}
+
+/// The percent-decoded `token=` of `query`.
+fn query_token(query: Option<&str>) -> Option<String> {
+    let raw = query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))?;
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut rest = raw.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(if byte == b'+' { b' ' } else { byte });
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+/// Lets a page on another origin send `Authorization` with its request.
+fn preflight(stream: &mut TcpStream) -> Result<()> {
+    stream.write_all(
+        b"HTTP/1.1 204 No Content\r\nAccess-Control-Allow-Origin: *\r\n\
+          Access-Control-Allow-Methods: GET\r\n\
+          Access-Control-Allow-Headers: Authorization\r\n\
+          Access-Control-Max-Age: 600\r\nConnection: close\r\n\r\n",
+    )?;
+    Ok(())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, error: &str) -> Result<()> {
+    let body = json!({ "error": error }).to_string();
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::fmt::Write as _;
+
+use anyhow::Result;
+use esp_idf_svc::{
+    http::{server::EspHttpServer, Method},
+    io::Write,
+};
+use serde_json::{json, Map, Value};
+
+use super::{protected, send_json};
+use crate::metrics::{self, Counter, DayBucket};
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// `GET /metrics` answers JSON; `?format=prometheus` answers the Prometheus
+/// text format for scrapers.
+pub fn register(server: &mut EspHttpServer<'static>) -> Result<()> {
+    protected(server, "/metrics", Method::Get, |req| {
+        let days = metrics::days();
+        if !wants_prometheus(req.uri()) {
+            return send_json(req, 200, &metrics_json(&days));
+        }
+        let body = prometheus_text(&days);
+        let mut resp =
+            req.into_response(200, None, &[("Content-Type", PROMETHEUS_CONTENT_TYPE)])?;
+        resp.write_all(body.as_bytes())?;
+        Ok::<(), anyhow::Error>(())
+    })?;
+    Ok(())
+}
+
+fn wants_prometheus(uri: &str) -> bool {
+    uri.split_once('?').is_some_and(|(_, query)| {
+        query
+            .split('&')
+            .any(|pair| pair == "format=prometheus" || pair == "format=prom")
+    })
+}
+
+fn metrics_json(days: &[DayBucket]) -> Value {
+    let entries: Vec<Value> = days
+        .iter()
+        .map(|bucket| {
+            let mut entry = Map::new();
+            entry.insert(
+                "date".into(),
+                json!(bucket.date.map(|date| date.to_string())),
+            );
+            for counter in Counter::ALL {
+                entry.insert(counter.key().into(), json!(bucket.get(counter)));
+            }
+            Value::Object(entry)
+        })
+        .collect();
+    json!({
+        "today": metrics::today().map(|date| date.to_string()),
+        "keep_days": metrics::KEEP_DAYS,
+        "days": entries,
+    })
+}
+
+/// One gauge per counter, labelled by day; the undated bucket is `unknown`.
+fn prometheus_text(days: &[DayBucket]) -> String {
+    let mut out = String::new();
+    for counter in Counter::ALL {
+        let name = format!("astrobox_{}", counter.key());
+        let _ = writeln!(out, "# HELP {name} {} per UTC day", counter.help());
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        for bucket in days {
+            let _ = writeln!(
+                out,
+                "{name}{{day=\"{}\"}} {}",
+                bucket.label(),
+                bucket.get(counter)
+            );
+        }
+    }
+    out
+}
@@ -0,0 +1,236 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use esp_idf_svc::http::{server::EspHttpServer, Method};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{protected, read_json, send_json};
+use crate::miwear::ancs::{
+    self,
+    outbox::{self, Entry, Fate, Finished},
+    store::{self, ActionLabels, NewNotification, NotificationChanges, StoredNotification},
+    webhooks,
+};
+use crate::timesync;
+
+#[derive(Deserialize)]
+struct CreateBody {
+    #[serde(default = "default_app_id")]
+    app_id: String,
+    app_name: Option<String>,
+    title: String,
+    #[serde(default)]
+    subtitle: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    category: u8,
+    #[serde(default)]
+    silent: bool,
+    /// Gets a POST when the watch performs an action on this entry.
+    callback_url: Option<String>,
+    #[serde(default)]
+    actions: ActionsBody,
+    /// Seconds the entry may wait in the outbox; see `?queue=true`.
+    ttl: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+struct ActionsBody {
+    positive: Option<String>,
+    negative: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PatchBody {
+    title: Option<String>,
+    subtitle: Option<String>,
+    message: Option<String>,
+    category: Option<u8>,
+}
+
+fn default_app_id() -> String {
+    "com.astrobox.http".to_string()
+}
+
+pub fn register(server: &mut EspHttpServer<'static>) -> Result<()> {
+    protected(server, "/notify", Method::Get, |req| {
+        let entries: Vec<Value> = store::list().iter().map(entry_json).collect();
+        send_json(req, 200, &json!({ "notifications": entries }))
+    })?;
+
+    protected(server, "/notify", Method::Post, |mut req| {
+        let body: CreateBody = match read_json(&mut req) {
+            Ok(body) => body,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        if body.category > store::MAX_CATEGORY {
+            return send_json(req, 400, &json!({ "error": "unknown category" }));
+        }
+        if body
+            .ttl
+            .is_some_and(|ttl| ttl == 0 || ttl > outbox::MAX_TTL.as_secs())
+        {
+            return send_json(req, 400, &json!({ "error": "ttl out of range" }));
+        }
+        if let Some(url) = &body.callback_url {
+            if let Err(err) = webhooks::check_url(url).and_then(|()| webhooks::ensure_started()) {
+                return send_json(req, 400, &json!({ "error": format!("{err:#}") }));
+            }
+        }
+        let new = NewNotification {
+            app_id: body.app_id,
+            app_name: body.app_name,
+            title: body.title,
+            subtitle: body.subtitle,
+            message: body.message,
+            category: body.category,
+            silent: body.silent,
+            callback_url: body.callback_url,
+            actions: ActionLabels {
+                positive: body.actions.positive,
+                negative: body.actions.negative,
+            },
+        };
+        // With `?queue=true` and no encrypted watch, the outbox rather than
+        // a store entry, which is lost on reboot and after an hour.
+        if wants_queue(req.uri()) && !ancs::encrypted_subscriber() {
+            let ttl = body.ttl.map_or(outbox::DEFAULT_TTL, Duration::from_secs);
+            return match outbox::queue(new, ttl) {
+                Ok(id) => send_json(req, 202, &json!({ "queued": id })),
+                Err(err) => send_json(req, 503, &json!({ "error": format!("{err:#}") })),
+            };
+        }
+        let published = ancs::publish_notification(new);
+        send_json(req, 201, &json!({ "uid": published.uid }))
+    })?;
+
+    protected(server, "/notify/queue", Method::Get, |req| {
+        let now = timesync::unix_now();
+        let instant = Instant::now();
+        let pending: Vec<Value> = outbox::pending()
+            .iter()
+            .map(|entry| queued_json(entry, now, instant))
+            .collect();
+        let recent: Vec<Value> = outbox::recent().iter().map(finished_json).collect();
+        send_json(req, 200, &json!({ "pending": pending, "recent": recent }))
+    })?;
+
+    // Ahead of `/notify/*`, which would otherwise take these deletes.
+    protected(server, "/notify/queue/*", Method::Delete, |req| {
+        let path = req.uri().split('?').next().unwrap_or_default();
+        let id = match path.trim_start_matches("/notify/queue/").parse::<u32>() {
+            Ok(id) => id,
+            Err(_) => {
+                return send_json(req, 400, &json!({ "error": "expected /notify/queue/<id>" }))
+            }
+        };
+        match outbox::remove(id) {
+            Ok(()) => send_json(req, 200, &json!({ "removed": id })),
+            Err(err) => send_json(req, 404, &json!({ "error": format!("{err:#}") })),
+        }
+    })?;
+
+    protected(server, "/notify/*", Method::Patch, |mut req| {
+        let uid = match uid_from_uri(req.uri()) {
+            Ok(uid) => uid,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        let body: PatchBody = match read_json(&mut req) {
+            Ok(body) => body,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        if body
+            .category
+            .is_some_and(|category| category > store::MAX_CATEGORY)
+        {
+            return send_json(req, 400, &json!({ "error": "unknown category" }));
+        }
+        let changes = NotificationChanges {
+            title: body.title,
+            subtitle: body.subtitle,
+            message: body.message,
+            category: body.category,
+        };
+        if !store::update_notification(uid, changes) {
+            return send_json(req, 404, &json!({ "error": "unknown or expired uid" }));
+        }
+        match store::get(uid) {
+            Some(entry) => send_json(req, 200, &entry_json(&entry)),
+            None => send_json(req, 404, &json!({ "error": "unknown or expired uid" })),
+        }
+    })?;
+
+    protected(server, "/notify/*", Method::Delete, |req| {
+        let uid = match uid_from_uri(req.uri()) {
+            Ok(uid) => uid,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        if store::remove(uid) {
+            send_json(req, 200, &json!({ "removed": uid }))
+        } else {
+            send_json(req, 404, &json!({ "error": "unknown or expired uid" }))
+        }
+    })?;
+
+    Ok(())
+}
+
+fn uid_from_uri(uri: &str) -> Result<u32> {
+    let path = uri.split('?').next().unwrap_or(uri);
+    let Some(raw) = path.strip_prefix("/notify/") else {
+        bail!("expected /notify/<uid>");
+    };
+    Ok(raw.trim_end_matches('/').parse()?)
+}
+
+fn wants_queue(uri: &str) -> bool {
+    uri.split_once('?')
+        .is_some_and(|(_, query)| query.split('&').any(|pair| pair == "queue=true"))
+}
+
+fn queued_json(entry: &Entry, now: Option<i64>, instant: Instant) -> Value {
+    json!({
+        "id": entry.id,
+        "queued_at": entry.queued_at,
+        "expires_in": entry.expires_in(now, instant),
+        "app_id": entry.notification.app_id,
+        "title": entry.notification.title,
+        "subtitle": entry.notification.subtitle,
+        "message": entry.notification.message,
+        "category": entry.notification.category,
+    })
+}
+
+fn finished_json(finished: &Finished) -> Value {
+    let uid = match finished.fate {
+        Fate::Delivered { uid } => Some(uid),
+        Fate::Expired => None,
+    };
+    json!({
+        "id": finished.id,
+        "app_id": finished.app_id,
+        "title": finished.title,
+        "state": finished.fate.code(),
+        "uid": uid,
+    })
+}
+
+fn entry_json(entry: &StoredNotification) -> Value {
+    json!({
+        "uid": entry.uid,
+        "app_id": entry.app_id,
+        "app_name": entry.app_name,
+        "title": entry.title,
+        "subtitle": entry.subtitle,
+        "message": entry.message,
+        "category": entry.category,
+        "silent": entry.flags & store::FLAG_SILENT != 0,
+        "callback_url": entry.callback_url,
+        "actions": {
+            "positive": entry.actions.positive,
+            "negative": entry.actions.negative,
+        },
+    })
+}
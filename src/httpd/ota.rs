@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use esp_idf_svc::{
+    http::{
+        server::{EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    io::{Read, Write},
+    ota::EspOta,
+};
+use log::{info, warn};
+use serde_json::json;
+
+use super::{protected, send_json};
+use crate::{
+    activity::{self, Activity},
+    ota::{self, check},
+};
+
+/// Bytes read from the socket and written to flash at a time.
+const CHUNK: usize = 4096;
+
+pub fn register(server: &mut EspHttpServer<'static>) -> Result<()> {
+    protected(server, "/ota", Method::Post, upload)
+}
+
+/// Writes the body to the update slot and marks it to boot next. Nothing is
+/// erased until the length and the image magic have been checked; a failed
+/// write aborts the update, so the slot is never marked bootable.
+fn upload(mut req: Request<&mut EspHttpConnection>) -> Result<()> {
+    let Some(slot) = ota::layout().and_then(|layout| layout.update.as_ref()) else {
+        return send_json(
+            req,
+            409,
+            &json!({ "error": "the partition table has no update slot" }),
+        );
+    };
+    let len = match check::check_size(req.content_len(), slot.size) {
+        Ok(len) => len as usize,
+        Err(rejection) => return reject(req, rejection),
+    };
+    let _busy = activity::begin(Activity::Ota);
+
+    let mut buf = vec![0u8; CHUNK];
+    let head = len.min(CHUNK);
+    req.read_exact(&mut buf[..head])
+        .map_err(|e| anyhow!("read image: {e:?}"))?;
+    if let Err(rejection) = check::check_start(&buf[..head]) {
+        return reject(req, rejection);
+    }
+
+    info!("OTA: writing {len} bytes to {}", slot.label);
+    let mut ota = EspOta::new()?;
+    let mut update = ota.initiate_update()?;
+    let written = (|| {
+        update
+            .write_all(&buf[..head])
+            .map_err(|e| anyhow!("write update slot: {e:?}"))?;
+        let mut done = head;
+        while done < len {
+            let n = (len - done).min(CHUNK);
+            req.read_exact(&mut buf[..n])
+                .map_err(|e| anyhow!("read image at byte {done}: {e:?}"))?;
+            update
+                .write_all(&buf[..n])
+                .map_err(|e| anyhow!("write update slot at byte {done}: {e:?}"))?;
+            done += n;
+        }
+        Ok::<_, anyhow::Error>(())
+    })();
+    if let Err(err) = written {
+        if let Err(abort) = update.abort() {
+            warn!("OTA abort failed: {abort:?}");
+        }
+        warn!("OTA: {err:#}");
+        return send_json(req, 500, &json!({ "error": format!("{err:#}") }));
+    }
+    // Validates the image and points the bootloader at the slot.
+    if let Err(err) = update.complete() {
+        warn!("OTA: image rejected: {err:?}");
+        return send_json(
+            req,
+            422,
+            &json!({ "error": format!("image failed validation: {err:?}") }),
+        );
+    }
+    info!("OTA: {} ready; boots on the next restart", slot.label);
+    send_json(
+        req,
+        200,
+        &json!({ "slot": slot.label, "bytes": len, "restart_required": true }),
+    )
+}
+
+fn reject(req: Request<&mut EspHttpConnection>, rejection: check::Rejection) -> Result<()> {
+    warn!("OTA: refused: {rejection}");
+    send_json(
+        req,
+        rejection.status(),
+        &json!({ "error": rejection.to_string() }),
+    )
+}
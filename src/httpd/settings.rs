@@ -0,0 +1,132 @@
+//! The settings the touch UI also edits, by name. Writes are conditional:
+//! the client sends the revision it read, in `If-Match` or the body, and
+//! gets 409 with the current value when the UI or another client changed
+//! the setting since. The `setting` event on `/events` says when to re-read.
+
+use anyhow::Result;
+use esp_idf_svc::http::{
+    server::{EspHttpConnection, EspHttpServer, Request},
+    Headers, Method,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{protected, read_json, send_json};
+use crate::{
+    gui::{backlight, pages, pixel_shift, theme},
+    i18n,
+    sensors::imu,
+    settings::{self, Conflict, Editable, Rejected},
+};
+
+const EDITABLE: &[&dyn Editable] = &[
+    &i18n::LANGUAGE,
+    &theme::THEME,
+    &theme::UI_SCALE,
+    &theme::DAY_END_HOUR,
+    &imu::AUTO_ROTATE,
+    &pixel_shift::PIXEL_SHIFT,
+    &backlight::SCREEN_TIMEOUT,
+    &backlight::DIM_AFTER_SECS,
+    &backlight::OFF_AFTER_SECS,
+    &backlight::DIM_PERCENT,
+    &pages::PAGE_ORDER,
+];
+
+#[derive(Deserialize)]
+struct PutBody {
+    value: String,
+    /// Used when there is no `If-Match` header.
+    revision: Option<u32>,
+}
+
+pub fn register(server: &mut EspHttpServer<'static>) -> Result<()> {
+    protected(server, "/settings", Method::Get, |req| {
+        let entries: Vec<Value> = EDITABLE
+            .iter()
+            .map(|setting| entry_json(*setting))
+            .collect();
+        send_json(req, 200, &json!({ "settings": entries }))
+    })?;
+
+    protected(server, "/settings/*", Method::Get, |req| {
+        match find(req.uri()) {
+            Some(setting) => send_json(req, 200, &entry_json(setting)),
+            None => send_json(req, 404, &json!({ "error": "unknown setting" })),
+        }
+    })?;
+
+    protected(server, "/settings/*", Method::Put, |mut req| {
+        let Some(setting) = find(req.uri()) else {
+            return send_json(req, 404, &json!({ "error": "unknown setting" }));
+        };
+        let if_match = req
+            .header("If-Match")
+            .map(|raw| raw.trim_matches('"').parse());
+        let body: PutBody = match read_json(&mut req) {
+            Ok(body) => body,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        let expected = match (if_match, body.revision) {
+            (Some(Ok(revision)), _) | (None, Some(revision)) => revision,
+            (Some(Err(_)), _) => {
+                return send_json(req, 400, &json!({ "error": "If-Match is not a revision" }))
+            }
+            (None, None) => {
+                return send_json(
+                    req,
+                    428,
+                    &json!({ "error": "send the revision read, in If-Match or the body" }),
+                )
+            }
+        };
+        respond(req, setting, setting.set_encoded(&body.value, expected))
+    })?;
+
+    Ok(())
+}
+
+fn find(uri: &str) -> Option<&'static dyn Editable> {
+    let path = uri.split('?').next().unwrap_or(uri);
+    let name = path.strip_prefix("/settings/")?.trim_end_matches('/');
+    EDITABLE
+        .iter()
+        .copied()
+        .find(|setting| setting.name() == name)
+}
+
+fn respond(
+    req: Request<&mut EspHttpConnection>,
+    setting: &dyn Editable,
+    result: Result<u32>,
+) -> Result<()> {
+    match result {
+        Ok(_) => send_json(req, 200, &entry_json(setting)),
+        Err(err) => {
+            if let Some(conflict) = err.downcast_ref::<Conflict>() {
+                return send_json(
+                    req,
+                    409,
+                    &json!({
+                        "error": "revision mismatch",
+                        "name": conflict.key,
+                        "value": conflict.value,
+                        "revision": conflict.revision,
+                    }),
+                );
+            }
+            if let Some(rejected) = err.downcast_ref::<Rejected>() {
+                return send_json(req, 400, &json!({ "error": rejected.to_string() }));
+            }
+            send_json(req, 500, &json!({ "error": format!("{err:#}") }))
+        }
+    }
+}
+
+fn entry_json(setting: &dyn Editable) -> Value {
+    json!({
+        "name": setting.name(),
+        "value": setting.encoded(),
+        "revision": settings::revision(setting.name()),
+    })
+}
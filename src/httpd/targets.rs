@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use esp_idf_svc::http::{server::EspHttpServer, Method};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{protected, read_json, send_json};
+use crate::{
+    ble::link::PhyPreference,
+    miwear::targets::{self, MiWearTarget},
+    settings,
+};
+
+#[derive(Deserialize)]
+struct ReplaceBody {
+    targets: Vec<TargetBody>,
+    switch_to_preferred: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct TargetBody {
+    /// 0 or absent for a new target.
+    #[serde(default)]
+    id: u32,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default)]
+    name: String,
+    addr: Option<String>,
+    #[serde(default)]
+    nickname: String,
+    /// `null` keeps the stored key of the target with the same id, since GET
+    /// only hands out masked keys.
+    auth_key: Option<String>,
+    phy: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+pub fn register(server: &mut EspHttpServer<'static>) -> Result<()> {
+    protected(server, "/miwear/targets", Method::Get, |req| {
+        send_json(req, 200, &targets_json())
+    })?;
+
+    protected(server, "/miwear/targets", Method::Put, |mut req| {
+        let body: ReplaceBody = match read_json(&mut req) {
+            Ok(body) => body,
+            Err(err) => return send_json(req, 400, &json!({ "error": format!("{err:#}") })),
+        };
+        let replaced = body
+            .targets
+            .into_iter()
+            .map(into_target)
+            .collect::<Result<Vec<_>>>()
+            .and_then(targets::replace_all);
+        if let Err(err) = replaced {
+            return send_json(req, 400, &json!({ "error": format!("{err:#}") }));
+        }
+        if let Some(enabled) = body.switch_to_preferred {
+            settings::set(&targets::SWITCH_TO_PREFERRED, &enabled)?;
+        }
+        send_json(req, 200, &targets_json())
+    })?;
+
+    Ok(())
+}
+
+fn into_target(body: TargetBody) -> Result<MiWearTarget> {
+    let auth_key = match body.auth_key {
+        Some(key) => key,
+        None => targets::get(body.id)
+            .filter(|_| body.id != 0)
+            .map(|existing| existing.auth_key)
+            .ok_or_else(|| anyhow!("target {} needs an auth_key", body.id))?,
+    };
+    let phy = body
+        .phy
+        .map(|phy| phy.parse::<PhyPreference>())
+        .transpose()?;
+    Ok(MiWearTarget {
+        id: body.id,
+        enabled: body.enabled,
+        name: body.name,
+        addr: body.addr.filter(|addr| !addr.is_empty()),
+        nickname: body.nickname,
+        auth_key,
+        phy,
+    })
+}
+
+fn targets_json() -> Value {
+    let active = targets::active();
+    let entries: Vec<Value> = targets::list()
+        .iter()
+        .map(|target| {
+            json!({
+                "id": target.id,
+                "enabled": target.enabled,
+                "name": target.name,
+                "addr": target.addr,
+                "nickname": target.nickname,
+                "auth_key": targets::masked_key(&target.auth_key),
+                "phy": target.phy.map(|phy| phy.to_string()),
+                "active": active == Some(target.id),
+            })
+        })
+        .collect();
+    json!({
+        "targets": entries,
+        "switch_to_preferred": settings::get(&targets::SWITCH_TO_PREFERRED),
+    })
+}
@@ -0,0 +1,123 @@
+//! UI language selection. Slint strings use `@tr()` with catalogs bundled by
+//! `build.rs`; strings formatted in Rust go through [`tr`], which reads the
+//! `rust` context of the same catalogs. Numbers and units are never
+//! translated.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use anyhow::Result;
+
+use crate::settings::{self, SettingKey};
+
+include!(concat!(env!("OUT_DIR"), "/i18n_catalog.rs"));
+
+pub const LANGUAGE: SettingKey<Language> = SettingKey::new("ui_lang", "en");
+
+static ACTIVE: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Language {
+    English,
+    Chinese,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::Chinese];
+
+    /// Catalog directory name under `translations/`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Chinese => "zh",
+        }
+    }
+
+    /// The language's own name, as shown in the picker.
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Chinese => "中文",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Language::English => Language::Chinese,
+            Language::Chinese => Language::English,
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Language {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Language::ALL
+            .into_iter()
+            .find(|language| language.code() == s)
+            .ok_or(())
+    }
+}
+
+pub fn init() {
+    ACTIVE.store(settings::get(&LANGUAGE) as u8, Ordering::Relaxed);
+}
+
+pub fn active() -> Language {
+    match ACTIVE.load(Ordering::Relaxed) {
+        value if value == Language::Chinese as u8 => Language::Chinese,
+        _ => Language::English,
+    }
+}
+
+/// Persists `language` and switches Rust-side lookups. The caller applies it
+/// to Slint, which must happen on the UI thread.
+pub fn set_language(language: Language) -> Result<()> {
+    settings::set(&LANGUAGE, &language)?;
+    ACTIVE.store(language as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Translation of `msgid` in the active language, or `msgid` itself.
+pub fn tr(msgid: &'static str) -> &'static str {
+    lookup(active(), msgid)
+}
+
+/// [`tr`] with each `{}` replaced by the next of `args`.
+pub fn trf(msgid: &'static str, args: &[&dyn fmt::Display]) -> String {
+    fill(tr(msgid), args)
+}
+
+fn lookup(language: Language, msgid: &'static str) -> &'static str {
+    CATALOG
+        .iter()
+        .find(|(code, id, _)| *code == language.code() && *id == msgid)
+        .map(|(_, _, msgstr)| *msgstr)
+        .unwrap_or(msgid)
+}
+
+fn fill(template: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut pieces = template.split("{}").peekable();
+    while let Some(piece) = pieces.next() {
+        out.push_str(piece);
+        if pieces.peek().is_some() {
+            if let Some(arg) = args.next() {
+                out.push_str(&arg.to_string());
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,4 @@
+//! Physical inputs besides the touch panel.
+
+pub mod analog;
+pub mod button;
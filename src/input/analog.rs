@@ -0,0 +1,392 @@
+//! The board's analog stick, when its manifest has an `[analog]` section:
+//! two ADC1 channels and an optional click to GND. Each axis is scaled from
+//! its calibration (the manifest's until `analog calibrate` has run on the
+//! device), with a dead zone around the center. Outside it the stick sends
+//! `focus::Nav` directions: one on entry, then repeats after
+//! [`REPEAT_DELAY`] that come faster the longer it is held. The click is
+//! select.
+//!
+//! Positive x is right and positive y is down once the manifest's
+//! `invert_x`/`invert_y` are applied. The click counts after two equal
+//! samples in a row, which is all the debouncing it needs at
+//! [`SAMPLE_INTERVAL`].
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::{
+    hal::gpio::{AnyIOPin, Input, PinDriver, Pull},
+    sys::{
+        adc_atten_t_ADC_ATTEN_DB_12, adc_bitwidth_t_ADC_BITWIDTH_12, adc_channel_t,
+        adc_oneshot_chan_cfg_t, adc_oneshot_config_channel, adc_oneshot_io_to_channel,
+        adc_oneshot_new_unit, adc_oneshot_read, adc_oneshot_unit_handle_t,
+        adc_oneshot_unit_init_cfg_t, adc_unit_t, esp,
+    },
+};
+use log::{error, info};
+
+use crate::{
+    board::{self, AnalogGpios, AxisCalibration},
+    gui::focus::{self, Nav},
+    settings::{self, SettingKey},
+};
+
+/// On boards with touch as well: keep the focus ring shown, even after a
+/// touch. Boards without touch always treat the stick as primary.
+pub const PRIMARY: SettingKey<bool> = SettingKey::new("analog_primary", "false");
+/// `min/center/max` raw readings from `analog calibrate`; empty for the
+/// manifest's.
+pub const CALIBRATION_X: SettingKey<String> =
+    SettingKey::new("analog_cal_x", "").validated(|raw| check_calibration(raw));
+pub const CALIBRATION_Y: SettingKey<String> =
+    SettingKey::new("analog_cal_y", "").validated(|raw| check_calibration(raw));
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+const REPEAT_DELAY: Duration = Duration::from_millis(400);
+const REPEAT_SLOWEST: Duration = Duration::from_millis(250);
+const REPEAT_FASTEST: Duration = Duration::from_millis(60);
+/// Each repeat follows the last after this share of its interval.
+const REPEAT_SPEEDUP_PCT: u32 = 80;
+/// Calibration: the stick rests for this long, then is swept for
+/// [`CALIBRATE_SWEEP`].
+const CALIBRATE_REST: Duration = Duration::from_secs(1);
+const CALIBRATE_SWEEP: Duration = Duration::from_secs(5);
+/// Raw counts the sweep has to reach on each side of the center.
+const MIN_TRAVEL: u16 = 400;
+
+static LATEST: Mutex<Option<(u16, u16)>> = Mutex::new(None);
+static CALIBRATE: AtomicBool = AtomicBool::new(false);
+
+fn check_calibration(raw: &str) -> Result<(), String> {
+    if raw.is_empty() || raw.parse::<AxisCalibration>().is_ok() {
+        Ok(())
+    } else {
+        Err("expected min/center/max, increasing".to_string())
+    }
+}
+
+/// Whether the focus ring stays up regardless of touches.
+pub fn primary() -> bool {
+    let board = &board::CURRENT;
+    board.analog.is_some() && (board.touch.is_none() || settings::get(&PRIMARY))
+}
+
+fn axis_calibration(key: &SettingKey<String>, manifest: AxisCalibration) -> AxisCalibration {
+    settings::get(key).parse().unwrap_or(manifest)
+}
+
+/// -1.0 at `min`, 0.0 at `center`, 1.0 at `max`, each half scaled on its own.
+fn normalize(raw: u16, cal: AxisCalibration) -> f32 {
+    let offset = f32::from(raw) - f32::from(cal.center);
+    let span = if offset >= 0.0 {
+        cal.max - cal.center
+    } else {
+        cal.center - cal.min
+    };
+    (offset / f32::from(span.max(1))).clamp(-1.0, 1.0)
+}
+
+/// The dominant axis past `threshold`, if any.
+fn direction(x: f32, y: f32, threshold: f32) -> Option<Nav> {
+    if x.abs().max(y.abs()) < threshold {
+        None
+    } else if x.abs() >= y.abs() {
+        Some(if x > 0.0 { Nav::Right } else { Nav::Left })
+    } else {
+        Some(if y > 0.0 { Nav::Down } else { Nav::Up })
+    }
+}
+
+/// ADC1 in one-shot mode, both stick channels configured.
+struct Adc {
+    unit: adc_oneshot_unit_handle_t,
+    x: adc_channel_t,
+    y: adc_channel_t,
+}
+
+impl Adc {
+    fn new(x_gpio: i32, y_gpio: i32) -> Result<Self> {
+        let mut unit_id: adc_unit_t = 0;
+        let mut x: adc_channel_t = 0;
+        let mut y: adc_channel_t = 0;
+        esp!(unsafe { adc_oneshot_io_to_channel(x_gpio, &mut unit_id, &mut x) })?;
+        esp!(unsafe { adc_oneshot_io_to_channel(y_gpio, &mut unit_id, &mut y) })?;
+        let init = adc_oneshot_unit_init_cfg_t {
+            unit_id,
+            ..Default::default()
+        };
+        let mut unit: adc_oneshot_unit_handle_t = std::ptr::null_mut();
+        esp!(unsafe { adc_oneshot_new_unit(&init, &mut unit) })?;
+        let config = adc_oneshot_chan_cfg_t {
+            atten: adc_atten_t_ADC_ATTEN_DB_12,
+            bitwidth: adc_bitwidth_t_ADC_BITWIDTH_12,
+        };
+        for channel in [x, y] {
+            esp!(unsafe { adc_oneshot_config_channel(unit, channel, &config) })?;
+        }
+        Ok(Self { unit, x, y })
+    }
+
+    fn read(&self, channel: adc_channel_t) -> Result<u16> {
+        let mut raw = 0;
+        esp!(unsafe { adc_oneshot_read(self.unit, channel, &mut raw) })?;
+        Ok(raw.clamp(0, 4095) as u16)
+    }
+
+    /// Raw x and y, with the manifest's inversions applied.
+    fn sample(&self, gpios: &AnalogGpios) -> Result<(u16, u16)> {
+        let flip = |raw: u16, invert: bool| if invert { 4095 - raw } else { raw };
+        Ok((
+            flip(self.read(self.x)?, gpios.invert_x),
+            flip(self.read(self.y)?, gpios.invert_y),
+        ))
+    }
+}
+
+/// A direction being held, and when it repeats.
+struct Hold {
+    nav: Nav,
+    next: Instant,
+    interval: Duration,
+}
+
+/// Collects `analog calibrate` samples.
+struct Calibration {
+    started: Instant,
+    rest: [(u32, u32); 2],
+    low: [u16; 2],
+    high: [u16; 2],
+}
+
+impl Calibration {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            rest: [(0, 0); 2],
+            low: [u16::MAX; 2],
+            high: [0; 2],
+        }
+    }
+
+    /// Takes a sample; returns true once the sweep is over.
+    fn sample(&mut self, now: Instant, raw: [u16; 2]) -> bool {
+        let elapsed = now.saturating_duration_since(self.started);
+        for (axis, value) in raw.into_iter().enumerate() {
+            if elapsed < CALIBRATE_REST {
+                self.rest[axis].0 += u32::from(value);
+                self.rest[axis].1 += 1;
+            } else {
+                self.low[axis] = self.low[axis].min(value);
+                self.high[axis] = self.high[axis].max(value);
+            }
+        }
+        elapsed >= CALIBRATE_REST + CALIBRATE_SWEEP
+    }
+
+    fn finish(&self) -> Result<[AxisCalibration; 2]> {
+        let axis = |index: usize| -> Result<AxisCalibration> {
+            let (sum, count) = self.rest[index];
+            let center = (sum / count.max(1)) as u16;
+            let (min, max) = (self.low[index], self.high[index]);
+            if center.saturating_sub(min) < MIN_TRAVEL || max.saturating_sub(center) < MIN_TRAVEL {
+                bail!(
+                    "{} axis only reached {min}..{max} around {center}",
+                    ["x", "y"][index]
+                );
+            }
+            Ok(AxisCalibration { min, center, max })
+        };
+        Ok([axis(0)?, axis(1)?])
+    }
+}
+
+/// Starts sampling; does nothing on boards without a stick. Call on the main
+/// `LocalSet` once the UI is up.
+pub fn start() -> Result<()> {
+    let Some(gpios) = board::CURRENT.analog else {
+        return Ok(());
+    };
+    let adc = Adc::new(gpios.x, gpios.y)?;
+    let click = match gpios.click {
+        Some(gpio) => {
+            let mut pin = PinDriver::input(board::io_pin(gpio))?;
+            pin.set_pull(Pull::Up)?;
+            Some(pin)
+        }
+        None => None,
+    };
+    tokio::task::spawn_local(async move {
+        if let Err(err) = run(adc, click, gpios).await {
+            error!("analog stick task exited: {err:?}");
+        }
+    });
+    info!(
+        "Analog stick on GPIO{}/GPIO{}{}",
+        gpios.x,
+        gpios.y,
+        if primary() { ", primary input" } else { "" }
+    );
+    Ok(())
+}
+
+async fn run(
+    adc: Adc,
+    click: Option<PinDriver<'static, AnyIOPin, Input>>,
+    gpios: AnalogGpios,
+) -> Result<()> {
+    let deadzone = f32::from(gpios.deadzone_pct) / 100.0;
+    let mut hold: Option<Hold> = None;
+    // Re-read when the settings generation moves.
+    let mut cal = (u32::MAX, gpios.x_cal, gpios.y_cal);
+    let mut calibrating: Option<Calibration> = None;
+    // The last two click samples; a press needs both down.
+    let mut click_samples = (false, false);
+    let mut clicked = false;
+    loop {
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+        let now = Instant::now();
+        let (raw_x, raw_y) = adc.sample(&gpios)?;
+        if let Ok(mut latest) = LATEST.lock() {
+            *latest = Some((raw_x, raw_y));
+        }
+
+        if CALIBRATE.swap(false, Ordering::AcqRel) {
+            crate::journal!("Analog stick calibration: let go, then sweep the edges");
+            calibrating = Some(Calibration::new(now));
+            hold = None;
+        }
+        if let Some(calibration) = calibrating.as_mut() {
+            if calibration.sample(now, [raw_x, raw_y]) {
+                finish_calibration(calibration);
+                calibrating = None;
+            }
+            continue;
+        }
+
+        if let Some(pin) = &click {
+            click_samples = (click_samples.1, pin.is_low());
+            let down = click_samples.0 && click_samples.1;
+            let up = !click_samples.0 && !click_samples.1;
+            if down && !clicked {
+                clicked = true;
+                focus::navigate(Nav::Select);
+            } else if up {
+                clicked = false;
+            }
+        }
+
+        if cal.0 != settings::generation() {
+            cal = (
+                settings::generation(),
+                axis_calibration(&CALIBRATION_X, gpios.x_cal),
+                axis_calibration(&CALIBRATION_Y, gpios.y_cal),
+            );
+        }
+        let (x, y) = (normalize(raw_x, cal.1), normalize(raw_y, cal.2));
+        // Half the dead zone lets go of a held direction, so a stick
+        // resting on the edge does not chatter.
+        let threshold = if hold.is_some() {
+            deadzone / 2.0
+        } else {
+            deadzone
+        };
+        match (direction(x, y, threshold), hold.as_mut()) {
+            (None, _) => hold = None,
+            (Some(nav), Some(current)) if current.nav == nav => {
+                if now >= current.next {
+                    focus::navigate(nav);
+                    current.next = now + current.interval;
+                    current.interval =
+                        (current.interval * REPEAT_SPEEDUP_PCT / 100).max(REPEAT_FASTEST);
+                }
+            }
+            (Some(nav), _) => {
+                focus::navigate(nav);
+                hold = Some(Hold {
+                    nav,
+                    next: now + REPEAT_DELAY,
+                    interval: REPEAT_SLOWEST,
+                });
+            }
+        }
+    }
+}
+
+fn finish_calibration(calibration: &Calibration) {
+    let result = calibration.finish().and_then(|[x, y]| {
+        settings::set(&CALIBRATION_X, &x.to_string())?;
+        settings::set(&CALIBRATION_Y, &y.to_string())?;
+        Ok((x, y))
+    });
+    match result {
+        Ok((x, y)) => crate::journal!("Analog stick calibrated: x {x}, y {y}"),
+        Err(err) => crate::journal!("Analog stick calibration failed: {err:#}"),
+    }
+}
+
+fn report() -> String {
+    let Some(gpios) = board::CURRENT.analog else {
+        return "no analog stick on this board".to_string();
+    };
+    let x_cal = axis_calibration(&CALIBRATION_X, gpios.x_cal);
+    let y_cal = axis_calibration(&CALIBRATION_Y, gpios.y_cal);
+    let mut lines = vec![format!(
+        "calibration x {x_cal}, y {y_cal} ({}); dead zone {}%; {}",
+        if settings::get(&CALIBRATION_X).is_empty() {
+            "manifest"
+        } else {
+            "calibrated"
+        },
+        gpios.deadzone_pct,
+        if primary() { "primary" } else { "secondary" }
+    )];
+    if let Some((x, y)) = LATEST.lock().ok().and_then(|latest| *latest) {
+        lines.push(format!(
+            "raw {x}/{y}, scaled {:+.2}/{:+.2}",
+            normalize(x, x_cal),
+            normalize(y, y_cal)
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "analog",
+        "analog stick: analog [calibrate | reset | primary on|off]",
+        |args| {
+            if board::CURRENT.analog.is_none() {
+                bail!("no analog stick on this board");
+            }
+            match args {
+                [] => {}
+                ["calibrate"] => {
+                    CALIBRATE.store(true, Ordering::Release);
+                    return Ok(format!(
+                        "let go of the stick for {} s, then sweep it around its edges for {} s",
+                        CALIBRATE_REST.as_secs(),
+                        CALIBRATE_SWEEP.as_secs()
+                    ));
+                }
+                ["reset"] => {
+                    settings::set(&CALIBRATION_X, &String::new())?;
+                    settings::set(&CALIBRATION_Y, &String::new())?;
+                }
+                ["primary", "on"] => settings::set(&PRIMARY, &true)?,
+                ["primary", "off"] => settings::set(&PRIMARY, &false)?,
+                _ => {
+                    return Err(anyhow!(
+                        "usage: analog [calibrate | reset | primary on|off]"
+                    ))
+                }
+            }
+            Ok(report())
+        },
+    );
+}
@@ -0,0 +1,221 @@
+//! The board's side key, when its manifest names one (`side_key = <gpio>`).
+//! Short, double and long presses each map to an action through a setting.
+//! A press is first offered to the page through `slint_ui::dispatch_gesture`,
+//! like a swipe; the mapped action runs only if no page takes it. While the
+//! screen is off, any press just turns it back on.
+//!
+//! The key is active-low with the internal pull-up. Edges arrive through
+//! esp-idf-hal's per-pin ISR notification, which wakes this task without
+//! allocating. A key already down at boot is ignored until released, so a
+//! boot-time hold never reaches the actions or the reboot hold;
+//! [`held_at_boot`] keeps it available to a boot-mode check.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use esp_idf_svc::hal::gpio::{AnyIOPin, Input, PinDriver, Pull};
+use log::{error, info, warn};
+use tokio::time;
+
+use crate::{
+    board,
+    events::{self, SystemEvent},
+    gui::{
+        backlight,
+        slint_ui::{self, Gesture},
+    },
+    settings::{self, SettingKey},
+};
+
+pub const SHORT_ACTION: SettingKey<ButtonAction> = SettingKey::new("btn_short", "back");
+pub const DOUBLE_ACTION: SettingKey<ButtonAction> = SettingKey::new("btn_double", "screen");
+pub const LONG_ACTION: SettingKey<ButtonAction> = SettingKey::new("btn_long", "test_notify");
+/// Reboot after [`REBOOT_HOLD`] of holding the key.
+pub const HOLD_REBOOT: SettingKey<bool> = SettingKey::new("btn_hold_reboot", "true");
+
+const DEBOUNCE: Duration = Duration::from_millis(20);
+/// A second press released within this of the first makes a double press.
+const DOUBLE_WINDOW: Duration = Duration::from_millis(350);
+const LONG_PRESS: Duration = Duration::from_millis(1_500);
+const REBOOT_HOLD: Duration = Duration::from_secs(10);
+
+static HELD_AT_BOOT: AtomicBool = AtomicBool::new(false);
+
+type Key = PinDriver<'static, AnyIOPin, Input>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonPress {
+    Short,
+    Double,
+    /// Released after at least [`LONG_PRESS`].
+    Long,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonAction {
+    None,
+    /// Turns the screen off; any press turns it back on.
+    Screen,
+    Back,
+    TestNotify,
+}
+
+impl ButtonAction {
+    const ALL: [ButtonAction; 4] = [
+        ButtonAction::None,
+        ButtonAction::Screen,
+        ButtonAction::Back,
+        ButtonAction::TestNotify,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            ButtonAction::None => "none",
+            ButtonAction::Screen => "screen",
+            ButtonAction::Back => "back",
+            ButtonAction::TestNotify => "test_notify",
+        }
+    }
+}
+
+impl fmt::Display for ButtonAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for ButtonAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ButtonAction::ALL
+            .into_iter()
+            .find(|action| action.code() == s)
+            .ok_or(())
+    }
+}
+
+/// The key was down when [`start`] ran.
+pub fn held_at_boot() -> bool {
+    HELD_AT_BOOT.load(Ordering::Relaxed)
+}
+
+/// Watches the side key; does nothing on boards without one. Call on the
+/// main `LocalSet` once the UI is up.
+pub fn start() -> Result<()> {
+    let Some(gpio) = board::CURRENT.side_key else {
+        return Ok(());
+    };
+    let mut key = PinDriver::input(board::io_pin(gpio))?;
+    key.set_pull(Pull::Up)?;
+    let held = key.is_low();
+    HELD_AT_BOOT.store(held, Ordering::Relaxed);
+    if held {
+        crate::journal!("Side key held at boot; ignored until released");
+    }
+    tokio::task::spawn_local(async move {
+        if let Err(err) = run(key, held).await {
+            error!("side key task exited: {err:?}");
+        }
+    });
+    info!("Side key on GPIO{gpio}");
+    Ok(())
+}
+
+async fn run(mut key: Key, mut ignoring: bool) -> Result<()> {
+    let mut pressed_at: Option<Instant> = None;
+    // Set after a short release, until the double-press window closes.
+    let mut short_until: Option<Instant> = None;
+    loop {
+        let deadline = match pressed_at {
+            Some(at) if !ignoring && settings::get(&HOLD_REBOOT) => Some(at + REBOOT_HOLD),
+            Some(_) => None,
+            None => short_until,
+        };
+        let edge = match deadline {
+            Some(at) => {
+                let at = time::Instant::from_std(at);
+                time::timeout_at(at, key.wait_for_any_edge()).await.ok()
+            }
+            None => Some(key.wait_for_any_edge().await),
+        };
+        let Some(edge) = edge else {
+            if let Some(at) = pressed_at {
+                if at.elapsed() >= REBOOT_HOLD {
+                    reboot().await;
+                }
+            } else if short_until.take().is_some() {
+                fire(ButtonPress::Short);
+            }
+            continue;
+        };
+        edge?;
+        time::sleep(DEBOUNCE).await;
+        let down = key.is_low();
+        if ignoring {
+            if !down {
+                ignoring = false;
+                info!("Side key released after the boot hold");
+            }
+            continue;
+        }
+        match (down, pressed_at) {
+            (true, None) => pressed_at = Some(Instant::now()),
+            (false, Some(at)) => {
+                pressed_at = None;
+                if at.elapsed() >= LONG_PRESS {
+                    short_until = None;
+                    fire(ButtonPress::Long);
+                } else if short_until.take().is_some() {
+                    fire(ButtonPress::Double);
+                } else {
+                    short_until = Some(Instant::now() + DOUBLE_WINDOW);
+                }
+            }
+            // A bounce that settled back where it was.
+            _ => {}
+        }
+    }
+}
+
+fn fire(press: ButtonPress) {
+    if backlight::on_input() {
+        return;
+    }
+    if slint_ui::top_overlay().is_none() && slint_ui::dispatch_gesture(Gesture::Button(press)) {
+        return;
+    }
+    let action = settings::get(match press {
+        ButtonPress::Short => &SHORT_ACTION,
+        ButtonPress::Double => &DOUBLE_ACTION,
+        ButtonPress::Long => &LONG_ACTION,
+    });
+    info!("Side key {press:?}: {action}");
+    match action {
+        ButtonAction::None => {}
+        ButtonAction::Screen => backlight::set_screen_on(false),
+        ButtonAction::Back => {
+            slint_ui::back();
+        }
+        // Same path as the Devices page button; a no-op without ANCS.
+        ButtonAction::TestNotify => {
+            slint_ui::with_app(|app| app.invoke_test_notification(false));
+        }
+    }
+}
+
+async fn reboot() {
+    crate::journal!("Rebooting: side key held for {}s", REBOOT_HOLD.as_secs());
+    events::publish(SystemEvent::ShuttingDown { reason: "side key" });
+    if let Err(err) = crate::nvs::flush().await {
+        warn!("NVS flush before reboot failed: {err:#}");
+    }
+    #[cfg(feature = "storage")]
+    crate::statlogger::flash_journal::flush();
+    unsafe { esp_idf_svc::sys::esp_restart() };
+}
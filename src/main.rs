@@ -1,34 +1,56 @@
-use core::convert::TryInto;
-
-use anyhow::anyhow;
-use corelib::{
-    device::xiaomi::{components::network::NetworkComponent, XiaomiDevice},
-    ecs::entity::EntityExt,
-};
 use esp_idf_svc::{
-    eventloop::EspSystemEventLoop,
-    hal::{gpio::Pins, modem::Modem, prelude::Peripherals},
-    io::vfs::MountedEventfs,
-    log::EspLogger,
-    nvs::EspDefaultNvsPartition,
-    sys::link_patches,
-    wifi::{AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi},
+    eventloop::EspSystemEventLoop, hal::prelude::Peripherals, io::vfs::MountedEventfs,
+    nvs::EspDefaultNvsPartition, sys::link_patches,
 };
 use std::time::Duration;
+use tokio::sync::Notify;
 
+pub mod activity;
 mod allocator;
+pub mod ble;
+pub mod board;
+pub mod boot;
+pub mod console;
+pub mod ecs;
+pub mod errors;
+pub mod events;
 pub mod gui;
+#[cfg(feature = "httpd")]
+pub mod httpd;
+pub mod i18n;
+pub mod input;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+pub mod memory;
+pub mod metrics;
 pub mod miwear;
+pub mod nvs;
+#[cfg(feature = "ota")]
+pub mod ota;
+pub mod periodic;
+pub mod power;
+#[cfg(feature = "ancs")]
+pub mod reminders;
+pub mod secrets;
+pub mod sensors;
+pub mod settings;
 pub mod statlogger;
+#[cfg(feature = "storage")]
+pub mod storage;
+pub mod timesync;
 pub mod touch;
+pub mod version;
+pub mod wifi;
 
-const WIFI_SSID: &str = "ASUS_AX86U";
-const WIFI_PASSWORD: &str = "reveries2005";
 const ECS_STACK_SIZE: usize = 32 * 1024;
+const BLE_INIT_STACK_SIZE: usize = 16 * 1024;
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+static RENDER_RETRY: Notify = Notify::const_new();
 
 fn main() -> anyhow::Result<()> {
     link_patches();
-    EspLogger::initialize_default();
+    statlogger::init_logger();
 
     let _mounted_eventfs = MountedEventfs::mount(5)?;
 
@@ -42,8 +64,8 @@ fn main() -> anyhow::Result<()> {
 }
 
 async fn run_app() -> anyhow::Result<()> {
+    // GPIOs come from the board manifest via `board`, not `pins`.
     let Peripherals {
-        pins,
         ledc,
         spi2,
         i2c0,
@@ -51,70 +73,204 @@ async fn run_app() -> anyhow::Result<()> {
         ..
     } = Peripherals::take()?;
 
-    let _wifi = init_wifi(modem)?;
+    boot::log_features();
+    board::log_summary();
+    boot::optional("psram", memory::verify_psram);
+    allocator::log_mode();
+
+    let sys_loop = EspSystemEventLoop::take()?;
+    let nvs = EspDefaultNvsPartition::take()?;
+    boot::required("settings", || settings::init(nvs.clone()))?;
+    timesync::resume();
+    miwear::targets::load();
+    metrics::load();
+    #[cfg(feature = "storage")]
+    if boot::optional("storage", storage::mount).is_some() {
+        boot::optional("journal", statlogger::flash_journal::start);
+    }
+    // Optional so a unit without a network still boots to the setup wizard.
+    boot::optional("wifi", || wifi::init(modem, sys_loop, nvs));
+    let _sntp = boot::optional("timesync", timesync::start);
+    miwear::demo::init();
+    // Overlaps display, touch and Slint init; joined before the first connect.
+    let ble_init = (!miwear::demo::enabled()).then(|| {
+        boot::spawn(
+            "ble",
+            boot::Criticality::Optional,
+            BLE_INIT_STACK_SIZE,
+            miwear::init_ble,
+        )
+    });
+    i18n::init();
+
+    allocator::stress::register_commands();
+    ecs::register_commands();
+    errors::register_commands();
+    allocator::trace::register_commands();
+    power::battery::register_commands();
+    power::download_mode::register_commands();
+    miwear::alarms::register_commands();
+    miwear::demo::register_commands();
+    miwear::media::register_commands();
+    miwear::request::register_commands();
+    miwear::ring::register_commands();
+    miwear::send::burst::register_commands();
+    miwear::targets::register_commands();
+    gui::backlight::register_commands();
+    gui::bounded_model::register_commands();
+    gui::display::register_commands();
+    gui::pages::register_commands();
+    gui::render_profile::register_commands();
+    gui::setup::register_commands();
+    gui::theme::register_commands();
+    gui::touch_trace::register_commands();
+    input::analog::register_commands();
+    memory::soak::register_commands();
+    periodic::register_commands();
+    secrets::register_commands();
+    sensors::imu::register_commands();
+    settings::rollback::register_commands();
+    statlogger::register_commands();
+    timesync::register_commands();
+    wifi::country::register_commands();
+    #[cfg(feature = "ancs")]
+    ble::hid::register_commands();
+    #[cfg(feature = "ancs")]
+    miwear::ancs::register_commands();
+    #[cfg(feature = "ancs")]
+    miwear::ancs::canary::register_commands();
+    #[cfg(feature = "ancs")]
+    miwear::ancs::nudge::register_commands();
+    #[cfg(feature = "ancs")]
+    miwear::ancs::outbox::register_commands();
+    #[cfg(feature = "ancs")]
+    miwear::ancs::sessions::register_commands();
+    #[cfg(feature = "ancs")]
+    miwear::ancs::webhooks::register_commands();
+    #[cfg(feature = "ancs-testmode")]
+    miwear::ancs::testmode::register_commands();
+    #[cfg(feature = "ancs")]
+    reminders::register_commands();
+    #[cfg(feature = "ota")]
+    ota::register_commands();
+    boot::optional("console", console::start);
+    boot::optional("temperature", sensors::temperature::start);
+    #[cfg(feature = "ota")]
+    boot::optional("ota", ota::init);
+
+    #[cfg(feature = "httpd")]
+    boot::optional("httpd", httpd::start);
+    #[cfg(feature = "mdns")]
+    let _mdns = boot::optional("mdns", mdns::start);
+    #[cfg(feature = "ancs")]
+    boot::optional("webhooks", miwear::ancs::webhooks::start);
+    #[cfg(feature = "ancs")]
+    boot::optional("outbox", miwear::ancs::outbox::start);
+    #[cfg(feature = "ancs")]
+    reminders::start();
 
     corelib::ecs::init_runtime_default_with_stack(ECS_STACK_SIZE);
-    tokio::task::spawn_local(async {
-        let mut ticker = tokio::time::interval(Duration::from_secs(1));
-        loop {
-            ticker.tick().await;
-            statlogger::log_heap_info();
-            log_network_meter().await;
-        }
+    tokio::task::spawn_local(periodic::run());
+    periodic::register("heap_log", Duration::from_secs(1), || {
+        statlogger::log_heap_info(gui::slint_ui::current_fps());
     });
+    periodic::register("net_meter", Duration::from_secs(1), log_network_meter);
+    tokio::task::spawn_local(miwear::observe::run());
 
-    let Pins {
-        gpio0,
-        gpio1,
-        gpio2,
-        gpio3,
-        gpio4,
-        gpio5,
-        gpio6,
-        gpio7,
-        gpio18,
-        gpio16,
-        ..
-    } = pins;
+    let (display, backlight) = boot::required("display", || {
+        gui::display::init_display_gc9a01(spi2, ledc, gui::display::DisplayPins::from_board())
+    })?;
 
-    let (mut display, mut backlight) = gui::display::init_display_gc9a01(
-        spi2,
-        ledc,
-        gui::display::DisplayPins {
-            backlight: gpio2,
-            rst: gpio3,
-            dc: gpio4,
-            cs: gpio5,
-            mosi: gpio6,
-            sclk: gpio7,
-        },
-    )?;
-
-    let _ = &mut backlight;
-
-    touch::spawn_touch_task(
-        i2c0,
-        touch::TouchPins {
-            sda: gpio18,
-            scl: gpio16,
-            interrupt: gpio1,
-            reset: gpio0,
-        },
-    )?;
-
-    tokio::task::spawn_local(async {
-        if let Err(err) = miwear::connect().await {
-            log::error!("miwear connect failed: {err:?}");
+    gui::backlight::spawn(backlight);
+    tokio::task::spawn_local(gui::marquee::run());
+
+    if let Some(pins) = touch::TouchPins::from_board() {
+        boot::required("touch", || touch::spawn_touch_task(i2c0, pins))?;
+    }
+    boot::optional("analog", input::analog::start);
+    boot::optional("side_key", input::button::start);
+    boot::optional("imu", sensors::imu::start);
+
+    tokio::task::spawn_local(miwear::ring::run());
+    tokio::task::spawn_local(miwear::alarms::run());
+    tokio::task::spawn_local(miwear::media::run());
+    #[cfg(feature = "ancs")]
+    tokio::task::spawn_local(miwear::ancs::pacing::run());
+    tokio::task::spawn_local(power::download_mode::run());
+    memory::pressure::start();
+    tokio::task::spawn_local(metrics::run());
+    tokio::task::spawn_local(timesync::run());
+    tokio::task::spawn_local(settings::rollback::run());
+    match ble_init {
+        None => {
+            tokio::task::spawn_local(miwear::demo::run());
         }
-    });
+        Some(ble_init) => {
+            tokio::task::spawn_local(async move {
+                if ble_init.join().await.is_err() {
+                    log::error!("BLE unavailable, watch connection disabled");
+                    errors::report(
+                        "ble",
+                        errors::Severity::Error,
+                        "BLE unavailable, watch connection disabled",
+                    );
+                    return;
+                }
+                ble::resources::start();
+                if let Err(err) = miwear::run_supervisor().await {
+                    log::error!("miwear supervisor exited: {err:?}");
+                    errors::report("watch", errors::Severity::Error, format!("{err:#}"));
+                }
+            });
+        }
+    }
 
+    let mut events = events::subscribe();
+    errors::register_retry("render", || RENDER_RETRY.notify_waiters());
+    let mut renderer = gui::display::RenderSupervisor::new(display);
     tokio::task::spawn_local(async move {
+        let mut frame_interval = FRAME_INTERVAL;
+        let mut first_frame = true;
+        let (mut hot, mut low_memory) = (false, false);
+        let mut stalled = false;
         loop {
-            if let Err(err) = gui::slint_ui::render_hello_world(&mut display) {
-                log::error!("render loop exited: {err:?}");
-                break;
+            while let Some(event) = next_pending(&mut events) {
+                match event {
+                    events::SystemEvent::ThermalWarning { active, .. } => hot = active,
+                    events::SystemEvent::MemoryPressure { level } => {
+                        low_memory = level >= memory::pressure::PressureLevel::Critical;
+                    }
+                    events::SystemEvent::OrientationChanged { rotation } => {
+                        renderer.set_rotation(rotation);
+                        continue;
+                    }
+                    _ => continue,
+                }
+                frame_interval = if hot || low_memory {
+                    FRAME_INTERVAL * 2
+                } else {
+                    FRAME_INTERVAL
+                };
+            }
+            if let Err(err) = renderer.frame() {
+                log::error!("render loop stopped: {err:?}");
+                errors::report("render", errors::Severity::Error, format!("{err:#}"));
+                // The screen is frozen, so this comes from the console.
+                RENDER_RETRY.notified().await;
+                stalled = true;
+                continue;
+            }
+            if stalled {
+                errors::resolve("render");
+                stalled = false;
+            }
+            if first_frame {
+                boot::milestone("first_frame");
+                gui::lazy_pages::after_first_frame();
+                secrets::notify_unreadable();
+                first_frame = false;
             }
-            tokio::time::sleep(Duration::from_millis(16)).await;
+            tokio::time::sleep(frame_interval).await;
         }
     })
     .await?;
@@ -122,64 +278,34 @@ async fn run_app() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn log_network_meter() {
-    let speeds = corelib::ecs::with_rt_mut(|rt| {
-        rt.entities
-            .values_mut()
-            .filter_map(|entity| {
-                let dev = entity.as_any_mut().downcast_mut::<XiaomiDevice>()?;
-                let name = dev.name().to_string();
-                let addr = dev.addr().to_string();
-                let speed = dev
-                    .get_component_as_mut::<NetworkComponent>(NetworkComponent::ID)
-                    .ok()
-                    .map(|comp| comp.last_speed)?;
-                Some((name, addr, speed))
-            })
-            .collect::<Vec<_>>()
-    })
-    .await;
-
-    if speeds.is_empty() {
-        log::info!("NET meter: no connected devices");
-        return;
-    }
-
-    for (name, addr, speed) in speeds {
-        log::info!(
-            "NET meter {name}({addr}) ↑{:.1} KB/s ↓{:.1} KB/s",
-            speed.write / 1024.0,
-            speed.read / 1024.0
-        );
+/// Drains the bus without waiting; lagging just skips to the oldest kept event.
+fn next_pending(
+    events: &mut tokio::sync::broadcast::Receiver<events::SystemEvent>,
+) -> Option<events::SystemEvent> {
+    use tokio::sync::broadcast::error::TryRecvError;
+    loop {
+        match events.try_recv() {
+            Ok(event) => return Some(event),
+            Err(TryRecvError::Lagged(_)) => continue,
+            Err(_) => return None,
+        }
     }
 }
 
-fn init_wifi(modem: Modem) -> anyhow::Result<BlockingWifi<EspWifi<'static>>> {
-    let sys_loop = EspSystemEventLoop::take()?;
-    let nvs = EspDefaultNvsPartition::take()?;
-
-    let mut wifi = BlockingWifi::wrap(EspWifi::new(modem, sys_loop.clone(), Some(nvs))?, sys_loop)?;
-
-    let wifi_configuration = Configuration::Client(ClientConfiguration {
-        ssid: WIFI_SSID
-            .try_into()
-            .map_err(|_| anyhow!("Wi-Fi SSID is too long"))?,
-        password: WIFI_PASSWORD
-            .try_into()
-            .map_err(|_| anyhow!("Wi-Fi password is too long"))?,
-        auth_method: AuthMethod::WPA2Personal,
-        ..Default::default()
-    });
-
-    wifi.set_configuration(&wifi_configuration)?;
-    wifi.start()?;
-    log::info!("Wi-Fi started");
-
-    wifi.connect()?;
-    log::info!("Wi-Fi connected to {}", WIFI_SSID);
-
-    wifi.wait_netif_up()?;
-    log::info!("Wi-Fi network interface is up");
-
-    Ok(wifi)
+fn log_network_meter() {
+    miwear::net_meter::sample();
+    let meter = miwear::net_meter::snapshot();
+    match (miwear::status::telemetry(), meter.session) {
+        (Some(watch), Some(session)) => log::info!(
+            "NET meter {} ↑{:.1} KB/s (peak {:.1}, {} KB) ↓{:.1} KB/s (peak {:.1}, {} KB)",
+            watch.name,
+            session.tx.current_bps / 1024.0,
+            session.tx.peak_bps / 1024.0,
+            session.tx.total_bytes / 1024,
+            session.rx.current_bps / 1024.0,
+            session.rx.peak_bps / 1024.0,
+            session.rx.total_bytes / 1024
+        ),
+        _ => log::info!("NET meter: no connected devices"),
+    }
 }
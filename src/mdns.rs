@@ -0,0 +1,15 @@
+use anyhow::Result;
+use esp_idf_svc::mdns::EspMdns;
+
+pub const HOSTNAME: &str = "astrobox";
+const INSTANCE_NAME: &str = "AstroBox Pocket";
+
+pub fn start() -> Result<EspMdns> {
+    let mut mdns = EspMdns::take()?;
+    mdns.set_hostname(HOSTNAME)?;
+    mdns.set_instance_name(INSTANCE_NAME)?;
+    #[cfg(feature = "httpd")]
+    mdns.add_service(None, "_http", "_tcp", 80, &[])?;
+    log::info!("mDNS responder up as {HOSTNAME}.local");
+    Ok(mdns)
+}
@@ -0,0 +1,155 @@
+//! Boot-time PSRAM check. A PSRAM chip that fails to come up leaves the heap
+//! without a SPIRAM region, and every large allocation quietly lands in
+//! internal RAM until something big fails much later. This stage makes that
+//! loud and switches the firmware into an internal-only configuration.
+//! Runtime shortage of internal RAM is handled by [`pressure`].
+
+use std::{
+    ptr,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{bail, Result};
+use esp_idf_svc::sys::{
+    esp_psram_get_size, esp_psram_is_initialized, heap_caps_free, heap_caps_get_total_size,
+    heap_caps_malloc, MALLOC_CAP_8BIT, MALLOC_CAP_SPIRAM,
+};
+use log::{error, info};
+
+use crate::{allocator, board};
+
+pub mod pressure;
+pub mod soak;
+
+/// Larger than the 64 KB data cache, so the readback has to come from the chip.
+const PATTERN_BYTES: usize = 128 * 1024;
+const PATTERNS: [u32; 2] = [0xA5A5_A5A5, 0x5A5A_5A5A];
+/// Part of the chip the heap must see; the rest may hold .ext_ram.bss or
+/// cache-mapped data.
+const MIN_HEAP_SHARE_PERCENT: usize = 50;
+
+const UNCHECKED: u8 = 0;
+const HEALTHY: u8 = 1;
+const FAILED: u8 = 2;
+
+static HEALTH: AtomicU8 = AtomicU8::new(UNCHECKED);
+static FAILURE: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Clone, Debug)]
+pub struct PsramReport {
+    pub expected_bytes: usize,
+    pub heap_bytes: usize,
+    pub healthy: Option<bool>,
+    pub error: Option<String>,
+}
+
+/// Validates PSRAM against the board's expected size. On failure the
+/// allocator is pinned to internal RAM for the rest of the boot.
+pub fn verify_psram() -> Result<()> {
+    match check() {
+        Ok(()) => {
+            HEALTH.store(HEALTHY, Ordering::Relaxed);
+            Ok(())
+        }
+        Err(err) => {
+            HEALTH.store(FAILED, Ordering::Relaxed);
+            if let Ok(mut failure) = FAILURE.lock() {
+                *failure = Some(format!("{err:#}"));
+            }
+            allocator::disable_psram();
+            error!("**************************************************");
+            error!("PSRAM check failed on {}: {err:#}", board::CURRENT.name);
+            error!("Running from internal RAM only; PSRAM features are off");
+            error!("**************************************************");
+            Err(err)
+        }
+    }
+}
+
+/// True once `verify_psram` has rejected the PSRAM.
+pub fn psram_failed() -> bool {
+    HEALTH.load(Ordering::Relaxed) == FAILED
+}
+
+pub fn psram_report() -> PsramReport {
+    PsramReport {
+        expected_bytes: board::CURRENT.psram_bytes,
+        heap_bytes: unsafe { heap_caps_get_total_size(MALLOC_CAP_SPIRAM as u32) },
+        healthy: match HEALTH.load(Ordering::Relaxed) {
+            HEALTHY => Some(true),
+            FAILED => Some(false),
+            _ => None,
+        },
+        error: FAILURE.lock().ok().and_then(|failure| failure.clone()),
+    }
+}
+
+fn check() -> Result<()> {
+    let expected = board::CURRENT.psram_bytes;
+    if expected == 0 {
+        info!("{} has no PSRAM; skipping check", board::CURRENT.name);
+        return Ok(());
+    }
+    if !unsafe { esp_psram_is_initialized() } {
+        bail!("PSRAM did not initialize (expected {} KB)", expected / 1024);
+    }
+    let size = unsafe { esp_psram_get_size() };
+    if size < expected {
+        bail!(
+            "PSRAM reports {} KB, expected {} KB",
+            size / 1024,
+            expected / 1024
+        );
+    }
+    let heap = unsafe { heap_caps_get_total_size(MALLOC_CAP_SPIRAM as u32) };
+    if heap * 100 < expected * MIN_HEAP_SHARE_PERCENT {
+        bail!("only {} KB of PSRAM reached the heap", heap / 1024);
+    }
+    pattern_test()?;
+    info!(
+        "PSRAM ok: {} KB fitted, {} KB in heap",
+        size / 1024,
+        heap / 1024
+    );
+    Ok(())
+}
+
+/// Writes address-dependent patterns over a PSRAM block and reads them back.
+fn pattern_test() -> Result<()> {
+    let caps = (MALLOC_CAP_SPIRAM | MALLOC_CAP_8BIT) as u32;
+    let block = unsafe { heap_caps_malloc(PATTERN_BYTES, caps) } as *mut u32;
+    if block.is_null() {
+        bail!(
+            "could not allocate a {} KB PSRAM test block",
+            PATTERN_BYTES / 1024
+        );
+    }
+    let words = PATTERN_BYTES / 4;
+    let expected_at =
+        |pattern: u32, index: usize| pattern ^ (index as u32).wrapping_mul(0x9E37_79B9);
+    let mut mismatch = None;
+    for pattern in PATTERNS {
+        for index in 0..words {
+            unsafe { ptr::write_volatile(block.add(index), expected_at(pattern, index)) };
+        }
+        mismatch = (0..words).find_map(|index| {
+            let read = unsafe { ptr::read_volatile(block.add(index)) };
+            let wanted = expected_at(pattern, index);
+            (read != wanted).then_some((index, read, wanted))
+        });
+        if mismatch.is_some() {
+            break;
+        }
+    }
+    unsafe { heap_caps_free(block.cast()) };
+    if let Some((index, read, wanted)) = mismatch {
+        bail!(
+            "readback mismatch at {:p}: read {read:#010X}, wrote {wanted:#010X}",
+            unsafe { block.add(index) }
+        );
+    }
+    Ok(())
+}
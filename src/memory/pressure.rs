@@ -0,0 +1,254 @@
+//! Internal-heap pressure levels and the features shed at each one. The BLE
+//! host allocates from internal RAM and fails unpredictably when starved, so
+//! the firmware gives things up in a fixed order instead of waiting for an
+//! allocation to fail somewhere important.
+//!
+//! Levels rise as soon as free internal heap crosses a floor and fall one
+//! step at a time once it is back above that floor plus [`HYSTERESIS`].
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, AtomicU8, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{info, warn};
+
+use crate::{
+    events::{self, SystemEvent},
+    statlogger,
+};
+
+/// Free internal heap below which each level starts.
+pub const WARNING_FLOOR: usize = 48 * 1024;
+pub const CRITICAL_FLOOR: usize = 32 * 1024;
+pub const EMERGENCY_FLOOR: usize = 20 * 1024;
+/// Extra headroom needed before a level is left again.
+pub const HYSTERESIS: usize = 8 * 1024;
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+static LEVEL: AtomicU8 = AtomicU8::new(PressureLevel::Normal as u8);
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum PressureLevel {
+    Normal = 0,
+    /// Caches and assets are evicted.
+    Warning = 1,
+    /// HTTP, ANCS advertising and full-rate rendering are paused.
+    Critical = 2,
+    /// Wi-Fi is torn down and a banner stays on screen.
+    Emergency = 3,
+}
+
+impl PressureLevel {
+    const ALL: [PressureLevel; 4] = [
+        PressureLevel::Normal,
+        PressureLevel::Warning,
+        PressureLevel::Critical,
+        PressureLevel::Emergency,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PressureLevel::Normal => "normal",
+            PressureLevel::Warning => "warning",
+            PressureLevel::Critical => "critical",
+            PressureLevel::Emergency => "emergency",
+        }
+    }
+
+    /// Free heap below which this level is entered; zero for `Normal`.
+    pub fn floor(self) -> usize {
+        match self {
+            PressureLevel::Normal => 0,
+            PressureLevel::Warning => WARNING_FLOOR,
+            PressureLevel::Critical => CRITICAL_FLOOR,
+            PressureLevel::Emergency => EMERGENCY_FLOOR,
+        }
+    }
+
+    fn from_u8(raw: u8) -> Self {
+        Self::ALL
+            .get(raw as usize)
+            .copied()
+            .unwrap_or(PressureLevel::Normal)
+    }
+
+    fn below(self) -> Self {
+        Self::from_u8((self as u8).saturating_sub(1))
+    }
+
+    /// The deepest level whose floor `free` is under.
+    fn for_free(free: usize) -> Self {
+        Self::ALL
+            .into_iter()
+            .rev()
+            .find(|level| free < level.floor())
+            .unwrap_or(PressureLevel::Normal)
+    }
+}
+
+impl fmt::Display for PressureLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// One reversible step, run when its level is entered and undone on the way
+/// back down. Runs on the UI thread.
+struct Shed {
+    level: PressureLevel,
+    name: &'static str,
+    shed: fn() -> Result<()>,
+    restore: fn() -> Result<()>,
+}
+
+/// In shedding order; restored in reverse.
+const SHEDS: &[Shed] = &[
+    Shed {
+        level: PressureLevel::Warning,
+        name: "page frame cache",
+        shed: crate::gui::frame_cache::shed,
+        restore: crate::gui::frame_cache::restore,
+    },
+    Shed {
+        level: PressureLevel::Warning,
+        name: "hidden pages",
+        shed: crate::gui::lazy_pages::shed,
+        restore: crate::gui::lazy_pages::restore,
+    },
+    #[cfg(feature = "storage")]
+    Shed {
+        level: PressureLevel::Warning,
+        name: "journal buffer",
+        shed: shed_journal_buffer,
+        restore: restore_journal_buffer,
+    },
+    Shed {
+        level: PressureLevel::Warning,
+        name: "log rings",
+        shed: || statlogger::ring::resize(PressureLevel::Warning),
+        restore: || statlogger::ring::resize(PressureLevel::Normal),
+    },
+    Shed {
+        level: PressureLevel::Critical,
+        name: "log rings",
+        shed: || statlogger::ring::resize(PressureLevel::Critical),
+        restore: || statlogger::ring::resize(PressureLevel::Warning),
+    },
+    #[cfg(feature = "httpd")]
+    Shed {
+        level: PressureLevel::Critical,
+        name: "HTTP server",
+        shed: crate::httpd::pause,
+        restore: crate::httpd::resume,
+    },
+    #[cfg(feature = "ancs")]
+    Shed {
+        level: PressureLevel::Critical,
+        name: "ANCS advertising",
+        shed: crate::miwear::ancs::pause_advertising,
+        restore: crate::miwear::ancs::resume_advertising,
+    },
+    Shed {
+        level: PressureLevel::Emergency,
+        name: "log rings",
+        shed: || statlogger::ring::resize(PressureLevel::Emergency),
+        restore: || statlogger::ring::resize(PressureLevel::Critical),
+    },
+    Shed {
+        level: PressureLevel::Emergency,
+        name: "Wi-Fi",
+        shed: crate::wifi::suspend,
+        restore: crate::wifi::resume,
+    },
+];
+
+pub fn level() -> PressureLevel {
+    PressureLevel::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Bumped on every level change.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Samples the heap once a second and moves between levels. Call once at
+/// boot. Render throttling follows the published event.
+pub fn start() {
+    crate::periodic::register("memory_pressure", SAMPLE_INTERVAL, sample);
+}
+
+fn sample() {
+    let current = level();
+    let free = statlogger::heap_snapshot().internal;
+    let next = next_level(current, free);
+    if next == current {
+        return;
+    }
+    crate::journal!("Memory pressure {current} -> {next} ({free} bytes internal free)");
+    if next > current {
+        for shed in SHEDS
+            .iter()
+            .filter(|shed| shed.level > current && shed.level <= next)
+        {
+            apply(shed, "shed", shed.shed);
+        }
+    } else {
+        for shed in SHEDS
+            .iter()
+            .rev()
+            .filter(|shed| shed.level > next && shed.level <= current)
+        {
+            apply(shed, "restored", shed.restore);
+        }
+    }
+    LEVEL.store(next as u8, Ordering::Relaxed);
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    events::publish(SystemEvent::MemoryPressure { level: next });
+}
+
+/// Rises straight to the level `free` calls for; falls one level per
+/// sample, and only with `HYSTERESIS` to spare.
+fn next_level(current: PressureLevel, free: usize) -> PressureLevel {
+    let wanted = PressureLevel::for_free(free);
+    if wanted >= current {
+        return wanted;
+    }
+    if free >= current.floor() + HYSTERESIS {
+        current.below().max(wanted)
+    } else {
+        current
+    }
+}
+
+fn apply(shed: &Shed, verb: &str, action: fn() -> Result<()>) {
+    let before = statlogger::heap_snapshot().internal;
+    let result = action();
+    let after = statlogger::heap_snapshot().internal;
+    match result {
+        Ok(()) => info!(
+            "Pressure {}: {verb} {} ({before} -> {after} bytes free)",
+            shed.level, shed.name
+        ),
+        Err(err) => warn!(
+            "Pressure {}: {} not {verb}: {err:#} ({before} -> {after} bytes free)",
+            shed.level, shed.name
+        ),
+    }
+}
+
+#[cfg(feature = "storage")]
+fn shed_journal_buffer() -> Result<()> {
+    statlogger::flash_journal::set_low_memory(true);
+    Ok(())
+}
+
+#[cfg(feature = "storage")]
+fn restore_journal_buffer() -> Result<()> {
+    statlogger::flash_journal::set_low_memory(false);
+    Ok(())
+}
@@ -0,0 +1,217 @@
+//! Heap soak for leaks that take days to show: `demo on`, reboot, then
+//! `soak start 24`. Demo mode drives the watch session, notifications and
+//! the pages' refresh loops the way a connected watch would, and this
+//! records the internal and PSRAM free-heap floors for every hour.
+//!
+//! The first hour is warm-up (fonts, caches and pools fill in). After it,
+//! a soak passes when each floor stays within its tolerance of the others;
+//! `soak` prints the hourly floors, the spread and the rows the UI's
+//! bounded models had to drop.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::{info, warn};
+
+use crate::{gui::bounded_model, miwear::demo, periodic, statlogger};
+
+const SAMPLE: Duration = Duration::from_secs(5);
+const HOUR: Duration = Duration::from_secs(3600);
+const MAX_HOURS: u32 = 72;
+const WARM_UP_HOURS: usize = 1;
+const INTERNAL_TOLERANCE: usize = 4 * 1024;
+const PSRAM_TOLERANCE: usize = 32 * 1024;
+
+static SOAK: Mutex<Option<Soak>> = Mutex::new(None);
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug)]
+struct Floors {
+    internal: usize,
+    psram: usize,
+}
+
+struct Soak {
+    started: Instant,
+    hours: u32,
+    /// One per hour begun.
+    floors: Vec<Floors>,
+    finished: bool,
+}
+
+struct Verdict {
+    internal_spread: usize,
+    psram_spread: usize,
+}
+
+impl Verdict {
+    fn flat(&self) -> bool {
+        self.internal_spread <= INTERNAL_TOLERANCE && self.psram_spread <= PSRAM_TOLERANCE
+    }
+}
+
+impl Soak {
+    /// Spread of the floors after warm-up, over finished hours only.
+    fn verdict(&self) -> Option<Verdict> {
+        let finished = if self.finished {
+            self.floors.len()
+        } else {
+            self.floors.len().saturating_sub(1)
+        };
+        let settled = self.floors.get(WARM_UP_HOURS..finished)?;
+        if settled.len() < 2 {
+            return None;
+        }
+        let spread = |floor: fn(&Floors) -> usize| {
+            let max = settled.iter().map(floor).max().unwrap_or(0);
+            let min = settled.iter().map(floor).min().unwrap_or(0);
+            max - min
+        };
+        Some(Verdict {
+            internal_spread: spread(|floors| floors.internal),
+            psram_spread: spread(|floors| floors.psram),
+        })
+    }
+}
+
+fn sample() {
+    let Ok(mut soak) = SOAK.lock() else {
+        return;
+    };
+    let Some(soak) = soak.as_mut().filter(|soak| !soak.finished) else {
+        return;
+    };
+    let hour = (soak.started.elapsed().as_secs() / HOUR.as_secs()) as usize;
+    if hour >= soak.hours as usize {
+        soak.finished = true;
+        match soak.verdict() {
+            Some(verdict) if verdict.flat() => crate::journal!(
+                "Soak of {} h passed: floor spread internal {} B, psram {} B",
+                soak.hours,
+                verdict.internal_spread,
+                verdict.psram_spread
+            ),
+            Some(verdict) => {
+                warn!(
+                    "Soak of {} h failed: floor spread internal {} B, psram {} B",
+                    soak.hours, verdict.internal_spread, verdict.psram_spread
+                );
+                crate::journal!("Soak of {} h failed", soak.hours);
+            }
+            None => info!(
+                "Soak of {} h ended without enough hours to judge",
+                soak.hours
+            ),
+        }
+        return;
+    }
+    let heap = statlogger::heap_snapshot();
+    let now = Floors {
+        internal: heap.internal,
+        psram: heap.psram,
+    };
+    match soak.floors.get_mut(hour) {
+        Some(floors) => {
+            floors.internal = floors.internal.min(now.internal);
+            floors.psram = floors.psram.min(now.psram);
+        }
+        None => soak.floors.push(now),
+    }
+}
+
+fn start(hours: u32) -> Result<()> {
+    if !(2..=MAX_HOURS).contains(&hours) {
+        bail!("a soak runs 2 to {MAX_HOURS} hours");
+    }
+    if !demo::enabled() {
+        bail!("soaks run on demo traffic: `demo on`, reboot, then start again");
+    }
+    let mut soak = SOAK.lock().map_err(|_| anyhow!("soak state poisoned"))?;
+    *soak = Some(Soak {
+        started: Instant::now(),
+        hours,
+        floors: Vec::new(),
+        finished: false,
+    });
+    if !REGISTERED.swap(true, Ordering::Relaxed) {
+        periodic::register("soak", SAMPLE, sample);
+    }
+    info!("Soak started for {hours} h");
+    Ok(())
+}
+
+fn report() -> String {
+    let Ok(soak) = SOAK.lock() else {
+        return "soak state poisoned".to_string();
+    };
+    let Some(soak) = soak.as_ref() else {
+        return "no soak; run `soak start <hours>` in demo mode".to_string();
+    };
+    let elapsed = soak.started.elapsed().as_secs();
+    let mut lines = vec![format!(
+        "soak {}: {}h{:02}m of {} h",
+        if soak.finished { "finished" } else { "running" },
+        elapsed / 3600,
+        elapsed % 3600 / 60,
+        soak.hours
+    )];
+    for (hour, floors) in soak.floors.iter().enumerate() {
+        lines.push(format!(
+            "h{hour}{}: internal floor {} B, psram floor {} B",
+            if hour < WARM_UP_HOURS {
+                " (warm-up)"
+            } else {
+                ""
+            },
+            floors.internal,
+            floors.psram
+        ));
+    }
+    lines.push(match soak.verdict() {
+        Some(verdict) => format!(
+            "spread: internal {} B (<= {INTERNAL_TOLERANCE}), psram {} B (<= {PSRAM_TOLERANCE}): {}",
+            verdict.internal_spread,
+            verdict.psram_spread,
+            if verdict.flat() { "flat" } else { "DRIFTING" }
+        ),
+        None => "spread: needs two finished hours after warm-up".to_string(),
+    });
+    let dropped: u64 = bounded_model::stats()
+        .iter()
+        .map(|model| model.dropped)
+        .sum();
+    lines.push(format!("UI model rows dropped: {dropped} (see `models`)"));
+    lines.join("\n")
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "soak",
+        "heap soak on demo traffic: soak [start <hours> | stop]",
+        |args| {
+            match args {
+                [] => {}
+                ["start", hours] => start(
+                    hours
+                        .parse()
+                        .map_err(|_| anyhow!("not a number: {hours}"))?,
+                )?,
+                ["stop"] => {
+                    if let Ok(mut soak) = SOAK.lock() {
+                        if let Some(soak) = soak.as_mut() {
+                            soak.finished = true;
+                        }
+                    }
+                }
+                _ => bail!("usage: soak [start <hours> | stop]"),
+            }
+            Ok(report())
+        },
+    );
+}
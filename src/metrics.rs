@@ -0,0 +1,339 @@
+//! Per-day usage counters for long-term evaluation, kept for the last
+//! [`KEEP_DAYS`] days in one NVS entry. Days are UTC dates from the system
+//! clock. Until the clock is set, counts go to an undated bucket, which is
+//! merged into the first real day.
+//!
+//! Recording only bumps an atomic, so BLE and Wi-Fi callbacks can call
+//! [`record`] from any thread; a periodic job folds the deltas into the day
+//! buckets once a second.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    events::{self, SystemEvent},
+    miwear::status::{self, ConnectionPhase},
+    periodic, settings,
+};
+
+mod date;
+
+pub use date::Date;
+
+pub const KEEP_DAYS: usize = 14;
+const FOLD_INTERVAL: Duration = Duration::from_secs(1);
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const NVS_NAMESPACE: &str = "metrics";
+const NVS_DAYS_KEY: &str = "days";
+const DAYS_BUFFER_LEN: usize = 2048;
+/// 2024-01-01; anything earlier means the clock was never set.
+const SYNCED_AFTER_SECS: u64 = 1_704_067_200;
+const UNKNOWN_DAY: &str = "unknown";
+
+/// Deltas since the last fold, indexed by `Counter as usize`.
+static PENDING: [AtomicU32; Counter::COUNT] = [
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+];
+static DAYS: Mutex<Vec<DayBucket>> = Mutex::new(Vec::new());
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    NotificationsPublished,
+    /// Published while an ANCS client was subscribed.
+    NotificationsDelivered,
+    BytesToWatch,
+    BytesFromWatch,
+    /// Watch links that came back after this boot's first one dropped.
+    BleReconnects,
+    WifiDisconnects,
+    ConnectedSeconds,
+    /// Request/response round trips to the watch, see `miwear::request`.
+    WatchRequests,
+    WatchRequestRetries,
+    /// Notification action callbacks delivered, see `ancs::webhooks`.
+    WebhooksSent,
+    /// Callbacks that failed after their retry or never got queued.
+    WebhookFailures,
+    /// Watch send workers restarted after dying mid-session.
+    SendWorkerRestarts,
+    /// Queued watch sends failed unwritten because the link dropped.
+    SendsFlushed,
+    /// Relay canaries the watch fetched in time, see `ancs::canary`.
+    CanaryPassed,
+    CanaryFailed,
+}
+
+impl Counter {
+    pub const COUNT: usize = 15;
+    pub const ALL: [Counter; Counter::COUNT] = [
+        Counter::NotificationsPublished,
+        Counter::NotificationsDelivered,
+        Counter::BytesToWatch,
+        Counter::BytesFromWatch,
+        Counter::BleReconnects,
+        Counter::WifiDisconnects,
+        Counter::ConnectedSeconds,
+        Counter::WatchRequests,
+        Counter::WatchRequestRetries,
+        Counter::WebhooksSent,
+        Counter::WebhookFailures,
+        Counter::SendWorkerRestarts,
+        Counter::SendsFlushed,
+        Counter::CanaryPassed,
+        Counter::CanaryFailed,
+    ];
+
+    /// JSON field and Prometheus metric stem.
+    pub fn key(self) -> &'static str {
+        match self {
+            Counter::NotificationsPublished => "notifications_published",
+            Counter::NotificationsDelivered => "notifications_delivered",
+            Counter::BytesToWatch => "bytes_to_watch",
+            Counter::BytesFromWatch => "bytes_from_watch",
+            Counter::BleReconnects => "ble_reconnects",
+            Counter::WifiDisconnects => "wifi_disconnects",
+            Counter::ConnectedSeconds => "connected_seconds",
+            Counter::WatchRequests => "watch_requests",
+            Counter::WatchRequestRetries => "watch_request_retries",
+            Counter::WebhooksSent => "webhooks_sent",
+            Counter::WebhookFailures => "webhook_failures",
+            Counter::SendWorkerRestarts => "send_worker_restarts",
+            Counter::SendsFlushed => "sends_flushed",
+            Counter::CanaryPassed => "canary_passed",
+            Counter::CanaryFailed => "canary_failed",
+        }
+    }
+
+    pub fn help(self) -> &'static str {
+        match self {
+            Counter::NotificationsPublished => "Notifications published to the ANCS store",
+            Counter::NotificationsDelivered => "Notifications published with a subscriber",
+            Counter::BytesToWatch => "Bytes written to the watch",
+            Counter::BytesFromWatch => "Bytes received from the watch",
+            Counter::BleReconnects => "Watch reconnects after a dropped link",
+            Counter::WifiDisconnects => "Wi-Fi station disconnects",
+            Counter::ConnectedSeconds => "Seconds with the watch connected",
+            Counter::WatchRequests => "Request/response round trips to the watch",
+            Counter::WatchRequestRetries => "Watch request attempts beyond the first",
+            Counter::WebhooksSent => "Notification action callbacks delivered",
+            Counter::WebhookFailures => "Notification action callbacks that failed",
+            Counter::SendWorkerRestarts => "Watch send worker restarts",
+            Counter::SendsFlushed => "Watch sends failed unwritten on disconnect",
+            Counter::CanaryPassed => "Relay canaries the watch fetched in time",
+            Counter::CanaryFailed => "Relay canaries the watch never fetched",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DayBucket {
+    /// `None` while the clock has not been set.
+    pub date: Option<Date>,
+    pub counts: [u64; Counter::COUNT],
+}
+
+impl DayBucket {
+    fn new(date: Option<Date>) -> Self {
+        Self {
+            date,
+            counts: [0; Counter::COUNT],
+        }
+    }
+
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.counts[counter as usize]
+    }
+
+    pub fn label(&self) -> String {
+        self.date
+            .map(|date| date.to_string())
+            .unwrap_or_else(|| UNKNOWN_DAY.to_string())
+    }
+}
+
+/// Counts `amount` towards today; cheap and callable from any thread.
+pub fn record(counter: Counter, amount: u32) {
+    PENDING[counter as usize].fetch_add(amount, Ordering::Relaxed);
+}
+
+/// Today's UTC date, or `None` while the clock is unset.
+pub fn today() -> Option<Date> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    (secs >= SYNCED_AFTER_SECS).then(|| Date::from_days((secs / 86_400) as i64))
+}
+
+/// Restores the stored days; call once the settings partition is open.
+pub fn load() {
+    let store = match settings::open_namespace(NVS_NAMESPACE) {
+        Ok(store) => store,
+        Err(err) => {
+            warn!("Usage metrics not restored: {err:#}");
+            return;
+        }
+    };
+    let mut buf = vec![0u8; DAYS_BUFFER_LEN];
+    let mut days = match store.get_str(NVS_DAYS_KEY, &mut buf) {
+        Ok(Some(raw)) => raw.lines().filter_map(decode).collect::<Vec<_>>(),
+        Ok(None) => Vec::new(),
+        Err(err) => {
+            warn!("Usage metrics unreadable, starting fresh: {err:?}");
+            Vec::new()
+        }
+    };
+    days.sort_by_key(|bucket| bucket.date);
+    info!("Usage metrics: {} stored days", days.len());
+    if let Ok(mut slot) = DAYS.lock() {
+        *slot = days;
+    }
+}
+
+/// Folds pending counts in once a second, adds connected time, and saves
+/// every [`PERSIST_INTERVAL`], at day rollover and before shutdown. Spawn
+/// once on the main `LocalSet`.
+pub async fn run() {
+    let mut last_persist = Instant::now();
+    let mut last_day = today();
+    periodic::register("metrics", FOLD_INTERVAL, move || {
+        if matches!(status::phase(), ConnectionPhase::Ready { .. }) {
+            record(Counter::ConnectedSeconds, FOLD_INTERVAL.as_secs() as u32);
+        }
+        let day = today();
+        fold(day);
+        // Also covers the clock being set, which merges the undated bucket.
+        if day != last_day || last_persist.elapsed() >= PERSIST_INTERVAL {
+            persist();
+            last_persist = Instant::now();
+            last_day = day;
+        }
+    });
+    let mut events = events::subscribe();
+    loop {
+        match events.recv().await {
+            Ok(SystemEvent::ShuttingDown { .. }) => {
+                fold(today());
+                persist();
+            }
+            Err(RecvError::Closed) => return,
+            _ => {}
+        }
+    }
+}
+
+/// Stored days plus today's live counts, oldest first.
+pub fn days() -> Vec<DayBucket> {
+    fold(today());
+    DAYS.lock().map(|days| days.clone()).unwrap_or_default()
+}
+
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Adds the pending deltas to `day`'s bucket, merging the undated bucket in
+/// once there is a date, and drops the oldest dated days beyond `KEEP_DAYS`.
+fn fold(day: Option<Date>) {
+    let Ok(mut days) = DAYS.lock() else {
+        return;
+    };
+    let mut changed = false;
+    if let Some(date) = day {
+        if let Some(index) = days.iter().position(|bucket| bucket.date.is_none()) {
+            let unknown = days.remove(index);
+            let bucket = bucket_for(&mut days, Some(date));
+            for (total, extra) in bucket.counts.iter_mut().zip(unknown.counts) {
+                *total += extra;
+            }
+            info!("Usage metrics: clock set, undated counts merged into {date}");
+            changed = true;
+        }
+    }
+    let deltas = Counter::ALL.map(|counter| PENDING[counter as usize].swap(0, Ordering::Relaxed));
+    if deltas.iter().any(|&delta| delta > 0) {
+        let bucket = bucket_for(&mut days, day);
+        for (total, delta) in bucket.counts.iter_mut().zip(deltas) {
+            *total += u64::from(delta);
+        }
+        changed = true;
+    }
+    let dated = days.iter().filter(|bucket| bucket.date.is_some()).count();
+    if dated > KEEP_DAYS {
+        // Sorted oldest first, with the undated bucket (if any) in front.
+        let first_dated = days.iter().position(|bucket| bucket.date.is_some());
+        if let Some(first) = first_dated {
+            days.drain(first..first + (dated - KEEP_DAYS));
+        }
+        changed = true;
+    }
+    if changed {
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Finds or inserts the bucket for `date`, keeping `days` sorted.
+fn bucket_for(days: &mut Vec<DayBucket>, date: Option<Date>) -> &mut DayBucket {
+    let index = match days.binary_search_by(|bucket| bucket.date.cmp(&date)) {
+        Ok(index) => index,
+        Err(index) => {
+            days.insert(index, DayBucket::new(date));
+            index
+        }
+    };
+    &mut days[index]
+}
+
+fn persist() {
+    let encoded: String = match DAYS.lock() {
+        Ok(days) => days.iter().map(encode).collect(),
+        Err(_) => return,
+    };
+    crate::nvs::write(NVS_NAMESPACE, NVS_DAYS_KEY, encoded);
+}
+
+/// `<date|unknown>\t<count>...\n`, counts in [`Counter::ALL`] order.
+fn encode(bucket: &DayBucket) -> String {
+    let mut line = bucket.label();
+    for count in bucket.counts {
+        line.push('\t');
+        line.push_str(&count.to_string());
+    }
+    line.push('\n');
+    line
+}
+
+fn decode(line: &str) -> Option<DayBucket> {
+    let mut fields = line.split('\t');
+    let label = fields.next()?;
+    let date = match label {
+        UNKNOWN_DAY => None,
+        raw => Some(Date::parse(raw)?),
+    };
+    let mut bucket = DayBucket::new(date);
+    // Counters added later read as zero from older entries.
+    for (slot, raw) in bucket.counts.iter_mut().zip(fields) {
+        *slot = raw.parse().ok()?;
+    }
+    Some(bucket)
+}
@@ -1,273 +1,202 @@
 use corelib::{
-    device::{
-        self,
-        xiaomi::{
-            components::{
-                resource::{ResourceComponent, ResourceSystem},
-                thirdparty_app::{AppInfo, ThirdpartyAppComponent, ThirdpartyAppSystem},
-            },
-            r#type::ConnectType,
-            SendError, XiaomiDevice,
+    device::xiaomi::{
+        components::{
+            alarm::{AlarmComponent, AlarmInfo, AlarmSystem},
+            music::{MusicComponent, MusicControl, MusicSystem},
+            resource::{ResourceComponent, ResourceSystem},
+            system::{SystemComponent, SystemSystem},
+            thirdparty_app::{AppInfo, ThirdpartyAppComponent, ThirdpartyAppSystem},
         },
+        XiaomiDevice,
     },
     ecs::{entity::EntityExt, logic_component::LogicComponent},
 };
-use esp32_nimble::{utilities::BleUuid, utilities::BleUuid::Uuid16, BLEDevice, BLEScan};
+use esp32_nimble::BLEDevice;
 use log::info;
-use std::{
-    sync::{Arc, Mutex},
-    time::Duration,
-};
-use tokio::{
-    sync::{mpsc, oneshot, Notify},
-    time,
-};
+use std::{fmt, time::Duration};
+use tokio::{sync::Notify, time};
 
+use crate::{
+    ble::link::PhyPreference,
+    errors::{self, Severity},
+    settings::SettingKey,
+};
+use alarms::{AlarmCall, AlarmError, WatchAlarm};
+use media::{MediaCommand, MediaError};
+use ring::RingError;
+use session::Session;
+use status::{ConnectionPhase, FailureKind};
+
+#[cfg(feature = "ancs")]
+pub mod alarms;
 pub mod ancs;
-
-const AUTO_LAUNCH_PACKAGE: &str = "com.searchstars.hyperbilibili";
-const AUTO_LAUNCH_DELAY_SECS: u64 = 10;
-
-fn u16_uuid(u: u16) -> BleUuid {
-    BleUuid::from(Uuid16(u))
-}
-fn uuid_contains(u: &BleUuid, needle: &str) -> bool {
-    let s = format!("{u:?}").replace('-', "").to_ascii_lowercase();
-    s.contains(&needle.to_ascii_lowercase())
-}
-
-pub async fn connect() -> anyhow::Result<()> {
-    let mi_service = u16_uuid(0xFE95);
-    let uuid_service_flag = u16_uuid(0x0050);
-    let uuid_recv = u16_uuid(0x005E);
-    let uuid_sent = u16_uuid(0x005F);
-
+pub mod demo;
+pub mod liveness;
+pub mod logging;
+pub mod media;
+pub mod net_meter;
+pub mod observe;
+pub mod reconnect;
+pub mod request;
+pub mod ring;
+pub mod scan;
+pub mod send;
+pub mod send_queue;
+pub mod session;
+pub mod status;
+pub mod targets;
+
+/// Seeds the first entry of `targets` on upgrade; keys now live per target.
+/// Kept in [`crate::secrets`].
+pub const AUTH_KEY: SettingKey<String> =
+    SettingKey::new("miwear_authkey", "fd0ce943010e5112c6a35cb3ea61b968");
+/// Covers device creation and the corelib auth exchange.
+pub const HANDSHAKE_TIMEOUT_SECS: SettingKey<u64> = SettingKey::new("miwear_hs_secs", "30");
+/// `auto2m` asks for 2M after connecting; `force1m` pins watches that misbehave on 2M.
+pub const PREFERRED_PHY: SettingKey<PhyPreference> = SettingKey::new("miwear_phy", "auto2m");
+/// Pauses the fake ANCS advertiser while scanning for a watch. Turn off when
+/// paired ANCS clients should reconnect as fast as possible.
+pub const QUIET_SCAN: SettingKey<bool> = SettingKey::new("miwear_quietscan", "true");
+
+const BACKOFF_MIN: Duration = Duration::from_secs(2);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+const TARGETS_POLL: Duration = Duration::from_secs(5);
+/// Per attempt of the quick app list fetch behind app launches.
+const QUICK_APP_LIST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The Errors page's retry: ends the current backoff, if any.
+static RETRY: Notify = Notify::const_new();
+
+/// Brings up the NimBLE host and, with `ancs`, the fake ANCS service. Runs
+/// as a parallel boot stage next to display init, so it must stay clear of
+/// UI state.
+pub fn init_ble() -> anyhow::Result<()> {
+    #[cfg_attr(not(feature = "ancs"), allow(unused_variables))]
     let ble = BLEDevice::take();
-    ancs::init_fake_ancs_service(&mut *ble)?;
-    let handle = tokio::runtime::Handle::current();
-
-    let mut scan = BLEScan::new();
-    scan.active_scan(true).interval(80).window(40);
-
-    let wanted_name = "Xiaomi Watch S4";
-    info!("Start scanning...");
-    let addr = scan
-        .start(&ble, 10_000, |dev, adv| {
-            let hit = adv
-                .name()
-                .map(|n| n.to_string().contains(wanted_name))
-                .unwrap_or(false)
-                || adv.service_uuids().any(|u| u == mi_service);
-
-            if hit {
-                info!("Found target: {:?} rssi={}", adv.name(), dev.rssi());
-                Some(dev.addr())
-            } else {
-                None
-            }
-        })
-        .await?
-        .ok_or_else(|| anyhow::anyhow!("Target device not found"))?;
-    info!("Target addr = {addr}");
-
-    let mut client: esp32_nimble::BLEClient = ble.new_client();
-    client.set_connection_params(12, 24, 0, 400, 16, 16);
-
-    let disconnect_notify = Arc::new(Notify::new());
-    let disconnect_reason = Arc::new(Mutex::new(None));
-    client.on_disconnect({
-        let disconnect_notify = Arc::clone(&disconnect_notify);
-        let disconnect_reason = Arc::clone(&disconnect_reason);
-        move |reason| {
-            log::warn!("BLE disconnected (reason: {})", reason);
-            if let Ok(mut slot) = disconnect_reason.lock() {
-                *slot = Some(reason);
-            }
-            disconnect_notify.notify_waiters();
+    #[cfg(feature = "ancs")]
+    {
+        ancs::init_fake_ancs_service(ble)?;
+        if ancs::mode() == ancs::Mode::Phantom {
+            crate::boot::milestone("ancs_advertising");
         }
-    });
-
-    info!("Connecting...");
-    client.connect(&addr).await?;
-    info!("Connected = {}", client.connected());
-
-    let svc = client
-        .get_service(mi_service)
-        .await
-        .map_err(|_| anyhow::anyhow!("Can't found fe95 service"))?;
-
-    let mut ch_service_flag = None;
-    let mut ch_recv = None;
-    let mut ch_sent = None;
-
-    let chars: Vec<_> = svc.get_characteristics().await?.collect();
+    }
+    Ok(())
+}
 
-    for c in &chars {
-        let u = c.uuid();
-        if ch_recv.is_none() && u == uuid_recv {
-            ch_recv = Some((*c).clone());
-            continue;
-        }
-        if ch_sent.is_none() && u == uuid_sent {
-            ch_sent = Some((*c).clone());
-            continue;
-        }
-        if ch_service_flag.is_none() && u == uuid_service_flag {
-            ch_service_flag = Some((*c).clone());
+/// Keeps one watch session alive: connects to the best enabled target in
+/// range, waits for disconnect, and retries with exponential backoff that
+/// [`reconnect`] cuts short when a known watch advertises. A target whose
+/// key is rejected is skipped until the list is edited.
+/// Expects [`init_ble`] to have finished.
+pub async fn run_supervisor() -> anyhow::Result<()> {
+    let mut backoff = BACKOFF_MIN;
+    let mut sighted = None;
+    errors::register_retry("watch", || RETRY.notify_waiters());
+    loop {
+        let candidates = targets::candidates();
+        if candidates.is_empty() {
+            status::set_phase(ConnectionPhase::Idle);
+            info!("No usable MiWear targets, waiting for an edit");
+            wait_for_targets_change().await;
+            backoff = BACKOFF_MIN;
             continue;
         }
-    }
-
-    if ch_service_flag.is_none() || ch_recv.is_none() || ch_sent.is_none() {
-        for c in &chars {
-            let u = c.uuid();
-            if ch_recv.is_none() && uuid_contains(&u, "005e") {
-                ch_recv = Some((*c).clone());
-                continue;
-            }
-            if ch_sent.is_none() && uuid_contains(&u, "005f") {
-                ch_sent = Some((*c).clone());
-                continue;
+        match connect(&candidates, sighted.take()).await {
+            Ok(()) => {
+                status::set_phase(ConnectionPhase::Idle);
+                backoff = BACKOFF_MIN;
+                time::sleep(BACKOFF_MIN).await;
             }
-            if ch_service_flag.is_none() && uuid_contains(&u, "0050") {
-                ch_service_flag = Some((*c).clone());
-                continue;
+            Err(err) => {
+                let kind = failure_kind(&err);
+                let reason = format!("{err:#}");
+                let severity = match kind {
+                    FailureKind::AuthRejected => Severity::Error,
+                    _ => Severity::Warning,
+                };
+                errors::report("watch", severity, reason.clone());
+                status::set_phase(ConnectionPhase::Failed { kind, reason });
+                if kind == FailureKind::AuthRejected {
+                    // connect() has already set that target aside.
+                    log::error!("Watch rejected the auth key, trying other targets");
+                    backoff = BACKOFF_MIN;
+                    continue;
+                }
+                log::warn!("MiWear session failed ({kind:?}), retrying in {backoff:?}: {err:?}");
+                sighted = tokio::select! {
+                    sighted = reconnect::wait(backoff, &candidates) => sighted,
+                    _ = RETRY.notified() => {
+                        info!("Retry requested, reconnecting now");
+                        None
+                    }
+                };
+                backoff = (backoff * 2).min(BACKOFF_MAX);
             }
         }
     }
+}
 
-    let mut ch_service_flag = ch_service_flag.ok_or_else(|| anyhow::anyhow!("0x0050 not found"))?;
-    let mut ch_recv = ch_recv.ok_or_else(|| anyhow::anyhow!("0x005e not found"))?;
-    let ch_sent = ch_sent.ok_or_else(|| anyhow::anyhow!("0x005f not found"))?;
-
-    if ch_service_flag.can_read() {
-        if let Ok(v) = ch_service_flag.read_value().await {
-            info!("Read 0x0050 = {:02X?}", v);
-        }
+async fn wait_for_targets_change() {
+    let seen = targets::generation();
+    while targets::generation() == seen {
+        time::sleep(TARGETS_POLL).await;
     }
+    info!("MiWear targets changed, retrying");
+}
 
-    let (send_tx, mut rx) =
-        mpsc::unbounded_channel::<(Vec<u8>, oneshot::Sender<Result<(), SendError>>)>();
-    let mut ch_sent_worker = ch_sent;
-    let _send_task = tokio::task::spawn_local(async move {
-        while let Some((data, responder)) = rx.recv().await {
-            let result: Result<(), SendError> = async {
-                if ch_sent_worker.can_write() {
-                    ch_sent_worker
-                        .write_value(&data, true)
-                        .await
-                        .map_err(|e| SendError::Io(e.to_string()))?;
-                } else if ch_sent_worker.can_write_no_response() {
-                    ch_sent_worker
-                        .write_value(&data, false)
-                        .await
-                        .map_err(|e| SendError::Io(e.to_string()))?;
-                } else {
-                    return Err(SendError::Io("0x005F can't write".to_string()));
-                }
-                Ok(())
-            }
-            .await;
-            let _ = responder.send(result);
-        }
-    });
-
-    let send_queue = Arc::new(send_tx);
-    let send_cb = {
-        let tx = Arc::clone(&send_queue);
-        move |data: Vec<u8>| {
-            let tx = Arc::clone(&tx);
-            async move {
-                let (resp_tx, resp_rx) = oneshot::channel();
-                tx.send((data, resp_tx))
-                    .map_err(|_| SendError::Io("send queue closed".to_string()))?;
-                resp_rx
-                    .await
-                    .map_err(|_| SendError::Io("send task dropped".to_string()))?
-            }
-        }
-    };
+fn failure_kind(err: &anyhow::Error) -> FailureKind {
+    err.downcast_ref::<HandshakeFailure>()
+        .map(|failure| failure.kind)
+        .unwrap_or(FailureKind::Link)
+}
 
-    let device_addr = addr.to_string();
-    let device_name = wanted_name.to_string();
-    let auth_key = "fd0ce943010e5112c6a35cb3ea61b968".to_string();
-    let sar_version = 2;
+#[derive(Debug)]
+struct HandshakeFailure {
+    kind: FailureKind,
+    reason: String,
+}
 
-    if ch_recv.can_notify() {
-        let notify_handle = handle.clone();
-        let notify_addr = device_addr.clone();
-        ch_recv.on_notify(move |payload| {
-            //log::info!("Notify(0x005E): {}", corelib::tools::to_hex_string(payload));
-            corelib::device::xiaomi::packet::dispatcher::on_packet(
-                notify_handle.clone(),
-                notify_addr.clone(),
-                payload.to_vec(),
-            );
-        });
-        ch_recv.subscribe_notify(true).await?;
-        info!("Subscribed notify on 0x005E");
-    } else {
-        info!("0x005E doesn't support Notify");
+impl fmt::Display for HandshakeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "handshake failed ({:?}): {}", self.kind, self.reason)
     }
+}
 
-    device::create_miwear_device(
-        handle.clone(),
-        device_name,
-        device_addr.clone(),
-        auth_key,
-        sar_version,
-        ConnectType::BLE,
-        false,
-        move |data| {
-            //log::info!("Write(0x005F): {}", corelib::tools::to_hex_string(&data));
-            let fut = send_cb(data);
-            async move {
-                fut.await.map_err(|err| {
-                    log::error!("send failed: {:?}", err);
-                    err
-                })
-            }
-        },
-    )
-    .await?;
-
-    {
-        let addr_for_launch = device_addr.clone();
-        tokio::task::spawn_local(async move {
-            time::sleep(Duration::from_secs(AUTO_LAUNCH_DELAY_SECS)).await;
-            match launch_watch_app(&addr_for_launch, AUTO_LAUNCH_PACKAGE).await {
-                Ok(_) => log::info!(
-                    "Auto launched {} on {}",
-                    AUTO_LAUNCH_PACKAGE,
-                    addr_for_launch
-                ),
-                Err(err) => {
-                    log::warn!(
-                        "Failed to auto launch {} on {}: {err:?}",
-                        AUTO_LAUNCH_PACKAGE,
-                        addr_for_launch
-                    );
-                }
-            }
-        });
-    }
+impl std::error::Error for HandshakeFailure {}
 
-    info!("MiWear session ready, waiting for disconnect...");
-    disconnect_notify.notified().await;
-    let reason = match disconnect_reason.lock() {
-        Ok(mut guard) => guard.take(),
-        Err(_) => None,
+/// Connects to `sighted` directly when it is still a candidate, otherwise
+/// scans for the best candidate in range first.
+async fn connect(
+    candidates: &[targets::MiWearTarget],
+    sighted: Option<reconnect::Sighted>,
+) -> anyhow::Result<()> {
+    let sighted = sighted.and_then(|sighted| {
+        let rank = candidates
+            .iter()
+            .position(|target| target.id == sighted.target_id)?;
+        Some((rank, sighted))
+    });
+    let (rank, addr, sighted_at) = match &sighted {
+        Some((rank, sighted)) => {
+            targets::clear_connect_request();
+            (*rank, sighted.addr, Some(sighted.at))
+        }
+        None => {
+            let (rank, addr) = scan::scan_for(candidates).await?;
+            (rank, addr, None)
+        }
     };
-    info!("Disconnected from {} (reason: {:?})", device_addr, reason);
-
-    Ok(())
+    let mut session = Session::establish(&candidates[rank], addr, sighted_at).await?;
+    let result = session.run_until_disconnect().await;
+    session.shutdown(result.as_ref().err());
+    result
 }
 
 async fn launch_watch_app(addr: &str, package: &str) -> anyhow::Result<()> {
     let app_info = resolve_app_info(addr, package).await?;
     let addr_owned = addr.to_string();
     let info = app_info.clone();
-    corelib::ecs::with_rt_mut(move |rt| {
+    crate::timed_with_rt_mut!("launch_app", move |rt| {
         let dev = rt
             .find_entity_by_id_mut::<XiaomiDevice>(&addr_owned)
             .ok_or_else(|| anyhow::anyhow!("device {} not found", addr_owned))?;
@@ -285,6 +214,181 @@ async fn launch_watch_app(addr: &str, package: &str) -> anyhow::Result<()> {
     .await
 }
 
+/// Makes the ready watch vibrate and ring (`true`) or stops it, the same
+/// system command Mi Fitness sends. Callers go through [`ring`], which
+/// serializes requests and applies the timeout.
+pub async fn find_device(ring: bool) -> Result<(), RingError> {
+    if demo::enabled() {
+        info!("Demo: find device {}", if ring { "ring" } else { "stop" });
+        return Ok(());
+    }
+    let ConnectionPhase::Ready { addr } = status::phase() else {
+        return Err(RingError::NotConnected);
+    };
+    let rx = crate::timed_with_rt_mut!("find_device", move |rt| {
+        let dev = rt
+            .find_entity_by_id_mut::<XiaomiDevice>(&addr)
+            .ok_or(RingError::NotConnected)?;
+        let component = dev
+            .get_component_as_mut::<SystemComponent>(SystemComponent::ID)
+            .map_err(|_| RingError::Unsupported)?;
+        let system = component
+            .system_mut()
+            .as_any_mut()
+            .downcast_mut::<SystemSystem>()
+            .ok_or(RingError::Unsupported)?;
+        Ok::<_, RingError>(system.find_device(ring))
+    })
+    .await?;
+
+    rx.await
+        .map_err(|_| RingError::Failed("response dropped".to_string()))?
+        .map_err(|err| RingError::Failed(format!("{err:?}")))
+}
+
+/// Sends a music control command to the ready watch. Callers go through
+/// [`media`], which debounces, predicts and applies the timeout.
+pub async fn media_control(command: MediaCommand) -> Result<(), MediaError> {
+    let ConnectionPhase::Ready { addr } = status::phase() else {
+        return Err(MediaError::NotConnected);
+    };
+    let control = match command {
+        MediaCommand::PlayPause => MusicControl::PlayPause,
+        MediaCommand::Previous => MusicControl::Previous,
+        MediaCommand::Next => MusicControl::Next,
+        MediaCommand::Volume(level) => MusicControl::Volume(level),
+    };
+    let rx = crate::timed_with_rt_mut!("media_control", move |rt| {
+        let dev = rt
+            .find_entity_by_id_mut::<XiaomiDevice>(&addr)
+            .ok_or(MediaError::NotConnected)?;
+        let component = dev
+            .get_component_as_mut::<MusicComponent>(MusicComponent::ID)
+            .map_err(|_| MediaError::Unsupported)?;
+        let system = component
+            .system_mut()
+            .as_any_mut()
+            .downcast_mut::<MusicSystem>()
+            .ok_or(MediaError::Unsupported)?;
+        Ok::<_, MediaError>(system.send_control(control))
+    })
+    .await?;
+
+    rx.await
+        .map_err(|_| MediaError::Failed("response dropped".to_string()))?
+        .map_err(|err| MediaError::Failed(format!("{err:?}")))
+}
+
+/// The ready watch's last music status event, or `None` when nothing is
+/// playing or the watch has no music component.
+pub async fn read_playback() -> Option<media::Playback> {
+    let ConnectionPhase::Ready { addr } = status::phase() else {
+        return None;
+    };
+    crate::timed_with_rt_mut!("read_playback", move |rt| {
+        let dev = rt.find_entity_by_id_mut::<XiaomiDevice>(&addr)?;
+        let component = dev
+            .get_component_as_mut::<MusicComponent>(MusicComponent::ID)
+            .ok()?;
+        let info = component.state.as_ref()?;
+        Some(media::Playback {
+            title: info.title.clone(),
+            artist: info.artist.clone(),
+            playing: info.playing,
+            volume: info.volume.min(100),
+        })
+    })
+    .await
+}
+
+/// Asks the ready watch for its alarms and returns them with the most it
+/// holds, when it says. Callers go through [`alarms`], which decodes the
+/// repeat masks and applies the timeout.
+pub async fn read_alarms() -> Result<(Vec<WatchAlarm>, Option<u8>), AlarmError> {
+    let ConnectionPhase::Ready { addr } = status::phase() else {
+        return Err(AlarmError::NotConnected);
+    };
+    let request_addr = addr.clone();
+    let rx = crate::timed_with_rt_mut!("alarm_list", move |rt| {
+        let dev = rt
+            .find_entity_by_id_mut::<XiaomiDevice>(&request_addr)
+            .ok_or(AlarmError::NotConnected)?;
+        let component = dev
+            .get_component_as_mut::<AlarmComponent>(AlarmComponent::ID)
+            .map_err(|_| AlarmError::Unsupported)?;
+        let system = component
+            .system_mut()
+            .as_any_mut()
+            .downcast_mut::<AlarmSystem>()
+            .ok_or(AlarmError::Unsupported)?;
+        Ok::<_, AlarmError>(system.request_alarm_list())
+    })
+    .await?;
+    rx.await
+        .map_err(|_| AlarmError::Failed("response dropped".to_string()))?
+        .map_err(|err| AlarmError::Failed(format!("{err:?}")))?;
+
+    crate::timed_with_rt_mut!("alarm_read", move |rt| {
+        let dev = rt
+            .find_entity_by_id_mut::<XiaomiDevice>(&addr)
+            .ok_or(AlarmError::NotConnected)?;
+        let component = dev
+            .get_component_as_mut::<AlarmComponent>(AlarmComponent::ID)
+            .map_err(|_| AlarmError::Unsupported)?;
+        let alarms = component
+            .alarms
+            .iter()
+            .map(|info| WatchAlarm {
+                id: info.id,
+                hour: info.hour,
+                minute: info.minute,
+                repeat: info.repeat,
+                label: info.name.clone(),
+                enabled: info.enabled,
+            })
+            .collect();
+        Ok((alarms, component.max_alarms))
+    })
+    .await
+}
+
+/// Adds, deletes or switches one alarm on the ready watch.
+pub async fn alarm_call(call: AlarmCall) -> Result<(), AlarmError> {
+    let ConnectionPhase::Ready { addr } = status::phase() else {
+        return Err(AlarmError::NotConnected);
+    };
+    let rx = crate::timed_with_rt_mut!("alarm_call", move |rt| {
+        let dev = rt
+            .find_entity_by_id_mut::<XiaomiDevice>(&addr)
+            .ok_or(AlarmError::NotConnected)?;
+        let component = dev
+            .get_component_as_mut::<AlarmComponent>(AlarmComponent::ID)
+            .map_err(|_| AlarmError::Unsupported)?;
+        let system = component
+            .system_mut()
+            .as_any_mut()
+            .downcast_mut::<AlarmSystem>()
+            .ok_or(AlarmError::Unsupported)?;
+        Ok::<_, AlarmError>(match call {
+            AlarmCall::Add(alarm) => system.add_alarm(AlarmInfo {
+                id: alarm.id,
+                hour: alarm.hour,
+                minute: alarm.minute,
+                repeat: alarm.repeat,
+                name: alarm.label,
+                enabled: alarm.enabled,
+            }),
+            AlarmCall::Delete(id) => system.remove_alarm(id),
+            AlarmCall::SetEnabled(id, enabled) => system.set_alarm_enabled(id, enabled),
+        })
+    })
+    .await?;
+
+    rx.await
+        .map_err(|_| AlarmError::Failed("response dropped".to_string()))?
+        .map_err(|err| AlarmError::Failed(format!("{err:?}")))
+}
+
 async fn resolve_app_info(addr: &str, package: &str) -> anyhow::Result<AppInfo> {
     if let Some(info) = lookup_cached_app_info(addr, package).await? {
         return Ok(info);
@@ -300,7 +404,7 @@ async fn resolve_app_info(addr: &str, package: &str) -> anyhow::Result<AppInfo>
 async fn lookup_cached_app_info(addr: &str, package: &str) -> anyhow::Result<Option<AppInfo>> {
     let addr_owned = addr.to_string();
     let package_owned = package.to_string();
-    corelib::ecs::with_rt_mut(move |rt| {
+    crate::timed_with_rt_mut!("app_lookup", move |rt| {
         let dev = rt
             .find_entity_by_id_mut::<XiaomiDevice>(&addr_owned)
             .ok_or_else(|| anyhow::anyhow!("device {} not found", addr_owned))?;
@@ -322,11 +426,26 @@ async fn lookup_cached_app_info(addr: &str, package: &str) -> anyhow::Result<Opt
 }
 
 async fn refresh_quick_app_list(addr: &str) -> anyhow::Result<()> {
-    let addr_owned = addr.to_string();
-    let rx = corelib::ecs::with_rt_mut(move |rt| {
+    let policy = request::Policy {
+        timeout: QUICK_APP_LIST_TIMEOUT,
+        retries: 1,
+        backoff: Duration::from_millis(500),
+    };
+    request::send_with_retry(&request::QUICK_APP_LIST, policy, || {
+        request_quick_app_list(addr.to_string())
+    })
+    .await
+    .map_err(|err| match err {
+        request::RequestError::TimedOut => anyhow::anyhow!("quick app list: {err}"),
+        request::RequestError::Failed(err) => err,
+    })
+}
+
+async fn request_quick_app_list(addr: String) -> anyhow::Result<()> {
+    let rx = crate::timed_with_rt_mut!("quick_app_list", move |rt| {
         let dev = rt
-            .find_entity_by_id_mut::<XiaomiDevice>(&addr_owned)
-            .ok_or_else(|| anyhow::anyhow!("device {} not found", addr_owned))?;
+            .find_entity_by_id_mut::<XiaomiDevice>(&addr)
+            .ok_or_else(|| anyhow::anyhow!("device {} not found", addr))?;
         let component = dev
             .get_component_as_mut::<ResourceComponent>(ResourceComponent::ID)
             .map_err(|err| anyhow::anyhow!("resource component unavailable: {:?}", err))?;
@@ -0,0 +1,551 @@
+//! Alarms on the watch: listed, added, switched and deleted through the
+//! watch's alarm component. The Alarms page, console and HTTP all queue
+//! requests to one worker on the main thread, which keeps the last list the
+//! watch sent. The watch can change its alarms on its own and does not tell
+//! us, so the list is fetched again whenever the page opens, while it stays
+//! open, and after every change we make.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use tokio::sync::{mpsc, oneshot};
+
+use super::request::{self, Policy, RequestError, Retryable};
+use crate::settings::{self, SettingKey};
+
+/// Which day bit 0 of the watch's repeat mask stands for. Models differ;
+/// `alarms week sun` fixes a watch whose alarms show up a day off.
+pub const WEEK_START: SettingKey<WeekStart> = SettingKey::new("alarm_week", "mon");
+
+const POLICY: Policy = Policy {
+    timeout: Duration::from_secs(5),
+    retries: 1,
+    backoff: Duration::from_millis(500),
+};
+/// Assumed when the watch does not report its own limit.
+pub const FALLBACK_LIMIT: u8 = 10;
+/// Longer labels are cut; watches show about this much.
+pub const MAX_LABEL_CHARS: usize = 24;
+/// A list older than this is fetched again before an add checks the limit.
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+static STATE: Mutex<State> = Mutex::new(State {
+    alarms: Vec::new(),
+    limit: None,
+    fetched: None,
+});
+static REQUESTS: OnceLock<mpsc::UnboundedSender<Request>> = OnceLock::new();
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AlarmTime {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl AlarmTime {
+    pub fn new(hour: u8, minute: u8) -> Result<Self, AlarmError> {
+        if hour > 23 || minute > 59 {
+            return Err(AlarmError::InvalidTime);
+        }
+        Ok(Self { hour, minute })
+    }
+}
+
+impl fmt::Display for AlarmTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl FromStr for AlarmTime {
+    type Err = AlarmError;
+
+    /// `7:30` or `07:30`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hour, minute) = s.split_once(':').ok_or(AlarmError::InvalidTime)?;
+        let hour = hour.trim().parse().map_err(|_| AlarmError::InvalidTime)?;
+        let minute = minute.trim().parse().map_err(|_| AlarmError::InvalidTime)?;
+        Self::new(hour, minute)
+    }
+}
+
+/// Days an alarm repeats on, Monday in bit 0 to Sunday in bit 6; empty
+/// rings once. The watch's own encoding is only used at the edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Weekdays(pub u8);
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+impl Weekdays {
+    pub const ONCE: Weekdays = Weekdays(0);
+    pub const WORKDAYS: Weekdays = Weekdays(0b001_1111);
+    pub const WEEKENDS: Weekdays = Weekdays(0b110_0000);
+    pub const DAILY: Weekdays = Weekdays(0b111_1111);
+
+    pub fn contains(self, day: usize) -> bool {
+        day < 7 && self.0 & (1 << day) != 0
+    }
+
+    pub fn toggled(self, day: usize) -> Self {
+        Weekdays((self.0 ^ (1 << day.min(6))) & Self::DAILY.0)
+    }
+
+    /// The mask as the watch stores it.
+    pub fn encode(self, start: WeekStart) -> u8 {
+        match start {
+            WeekStart::Monday => self.0,
+            // Sunday moves from bit 6 to bit 0, the rest up by one.
+            WeekStart::Sunday => ((self.0 << 1) | (self.0 >> 6)) & Self::DAILY.0,
+        }
+    }
+
+    pub fn decode(raw: u8, start: WeekStart) -> Self {
+        let raw = raw & Self::DAILY.0;
+        match start {
+            WeekStart::Monday => Weekdays(raw),
+            WeekStart::Sunday => Weekdays(((raw >> 1) | (raw << 6)) & Self::DAILY.0),
+        }
+    }
+}
+
+impl fmt::Display for Weekdays {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ONCE => f.write_str("once"),
+            Self::DAILY => f.write_str("daily"),
+            Self::WORKDAYS => f.write_str("workdays"),
+            Self::WEEKENDS => f.write_str("weekends"),
+            days => {
+                let names: Vec<&str> = (0..7)
+                    .filter(|day| days.contains(*day))
+                    .map(|day| DAY_NAMES[day])
+                    .collect();
+                f.write_str(&names.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for Weekdays {
+    type Err = AlarmError;
+
+    /// `once`, `daily`, `workdays`, `weekends`, or day names like
+    /// `mon,wed,fri`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "once" => Ok(Self::ONCE),
+            "daily" => Ok(Self::DAILY),
+            "workdays" => Ok(Self::WORKDAYS),
+            "weekends" => Ok(Self::WEEKENDS),
+            list => list.split(',').try_fold(Self::ONCE, |days, name| {
+                let day = DAY_NAMES
+                    .iter()
+                    .position(|day| *day == name.trim())
+                    .ok_or_else(|| AlarmError::InvalidRepeat(name.trim().to_string()))?;
+                Ok(Weekdays(days.0 | 1 << day))
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeekStart {
+    Monday,
+    Sunday,
+}
+
+impl fmt::Display for WeekStart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WeekStart::Monday => "mon",
+            WeekStart::Sunday => "sun",
+        })
+    }
+}
+
+impl FromStr for WeekStart {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mon" => Ok(WeekStart::Monday),
+            "sun" => Ok(WeekStart::Sunday),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alarm {
+    /// The watch's id for the alarm.
+    pub id: u32,
+    pub time: AlarmTime,
+    pub repeat: Weekdays,
+    pub label: String,
+    pub enabled: bool,
+}
+
+/// An alarm to add; the watch picks the id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewAlarm {
+    pub time: AlarmTime,
+    pub repeat: Weekdays,
+    pub label: String,
+}
+
+/// One alarm as the watch's component holds it, repeat mask still in the
+/// watch's encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchAlarm {
+    pub id: u32,
+    pub hour: u8,
+    pub minute: u8,
+    pub repeat: u8,
+    pub label: String,
+    pub enabled: bool,
+}
+
+impl WatchAlarm {
+    fn decode(&self, start: WeekStart) -> Alarm {
+        Alarm {
+            id: self.id,
+            time: AlarmTime {
+                hour: self.hour.min(23),
+                minute: self.minute.min(59),
+            },
+            repeat: Weekdays::decode(self.repeat, start),
+            label: self.label.clone(),
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// What the worker asks the watch's alarm component to do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlarmCall {
+    Add(WatchAlarm),
+    Delete(u32),
+    SetEnabled(u32, bool),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlarmError {
+    NotConnected,
+    /// The watch has no alarm component.
+    Unsupported,
+    /// The watch already holds its maximum.
+    Full {
+        limit: u8,
+    },
+    NotFound(u32),
+    InvalidTime,
+    InvalidRepeat(String),
+    Timeout,
+    /// The alarm worker is not running.
+    Unavailable,
+    Failed(String),
+}
+
+impl fmt::Display for AlarmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlarmError::NotConnected => f.write_str("watch not connected"),
+            AlarmError::Unsupported => f.write_str("watch has no alarms"),
+            AlarmError::Full { limit } => {
+                write!(f, "watch already has {limit} alarms, its maximum")
+            }
+            AlarmError::NotFound(id) => write!(f, "no alarm {id} on the watch"),
+            AlarmError::InvalidTime => f.write_str("time must be HH:MM, 00:00 to 23:59"),
+            AlarmError::InvalidRepeat(day) => write!(
+                f,
+                "unknown day {day:?}; use once, daily, workdays, weekends or mon..sun"
+            ),
+            AlarmError::Timeout => f.write_str("watch did not answer"),
+            AlarmError::Unavailable => f.write_str("alarm worker not running"),
+            AlarmError::Failed(reason) => write!(f, "alarm request failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for AlarmError {}
+
+impl Retryable for AlarmError {
+    fn retryable(&self) -> bool {
+        matches!(self, AlarmError::Failed(_))
+    }
+}
+
+/// The watch's alarms and the most it holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlarmList {
+    pub alarms: Vec<Alarm>,
+    pub limit: u8,
+}
+
+struct State {
+    alarms: Vec<Alarm>,
+    /// Reported by the watch; `None` until it did.
+    limit: Option<u8>,
+    fetched: Option<Instant>,
+}
+
+enum Op {
+    List,
+    Add(NewAlarm),
+    Delete(u32),
+    SetEnabled(u32, bool),
+}
+
+struct Request {
+    op: Op,
+    reply: oneshot::Sender<Result<AlarmList, AlarmError>>,
+}
+
+/// Serves queued requests; spawn once on the main `LocalSet`.
+pub async fn run() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if REQUESTS.set(tx).is_err() {
+        warn!("Alarm worker already running");
+        return;
+    }
+    while let Some(Request { op, reply }) = rx.recv().await {
+        let result = serve(op).await;
+        if let Err(err) = &result {
+            warn!("Alarm request failed: {err}");
+        }
+        let _ = reply.send(result);
+    }
+}
+
+async fn serve(op: Op) -> Result<AlarmList, AlarmError> {
+    match op {
+        Op::List => {}
+        Op::Add(alarm) => {
+            if fetched_within(STALE_AFTER).is_none() {
+                fetch().await?;
+            }
+            let current = cached();
+            if current.alarms.len() >= usize::from(current.limit) {
+                return Err(AlarmError::Full {
+                    limit: current.limit,
+                });
+            }
+            let start = settings::get(&WEEK_START);
+            let label: String = alarm.label.trim().chars().take(MAX_LABEL_CHARS).collect();
+            info!("Adding alarm {} {} {label:?}", alarm.time, alarm.repeat);
+            call(AlarmCall::Add(WatchAlarm {
+                id: 0,
+                hour: alarm.time.hour,
+                minute: alarm.time.minute,
+                repeat: alarm.repeat.encode(start),
+                label,
+                enabled: true,
+            }))
+            .await?;
+        }
+        Op::Delete(id) => {
+            known(id).await?;
+            info!("Deleting alarm {id}");
+            call(AlarmCall::Delete(id)).await?;
+        }
+        Op::SetEnabled(id, enabled) => {
+            known(id).await?;
+            info!("Alarm {id} {}", if enabled { "on" } else { "off" });
+            call(AlarmCall::SetEnabled(id, enabled)).await?;
+        }
+    }
+    fetch().await?;
+    Ok(cached())
+}
+
+/// Checks `id` against the list, fetching it first if the cache misses it.
+async fn known(id: u32) -> Result<(), AlarmError> {
+    let listed = |id| cached().alarms.iter().any(|alarm| alarm.id == id);
+    if listed(id) {
+        return Ok(());
+    }
+    fetch().await?;
+    if listed(id) {
+        Ok(())
+    } else {
+        Err(AlarmError::NotFound(id))
+    }
+}
+
+async fn call(call: AlarmCall) -> Result<(), AlarmError> {
+    request::send_with_retry(&request::ALARMS, POLICY, || super::alarm_call(call.clone()))
+        .await
+        .map_err(|err| match err {
+            RequestError::TimedOut => AlarmError::Timeout,
+            RequestError::Failed(err) => err,
+        })
+}
+
+async fn fetch() -> Result<(), AlarmError> {
+    let (alarms, limit) = request::send_with_retry(&request::ALARMS, POLICY, super::read_alarms)
+        .await
+        .map_err(|err| match err {
+            RequestError::TimedOut => AlarmError::Timeout,
+            RequestError::Failed(err) => err,
+        })?;
+    let start = settings::get(&WEEK_START);
+    let mut alarms: Vec<Alarm> = alarms.iter().map(|alarm| alarm.decode(start)).collect();
+    alarms.sort_by_key(|alarm| (alarm.time, alarm.id));
+    if let Ok(mut state) = STATE.lock() {
+        state.alarms = alarms;
+        state.limit = limit.or(state.limit);
+        state.fetched = Some(Instant::now());
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The list as last fetched, which may be empty before the first fetch.
+pub fn cached() -> AlarmList {
+    STATE
+        .lock()
+        .map(|state| AlarmList {
+            alarms: state.alarms.clone(),
+            limit: state.limit.unwrap_or(FALLBACK_LIMIT),
+        })
+        .unwrap_or(AlarmList {
+            alarms: Vec::new(),
+            limit: FALLBACK_LIMIT,
+        })
+}
+
+/// How long ago the list was fetched, if within `window`.
+pub fn fetched_within(window: Duration) -> Option<Duration> {
+    let fetched = STATE.lock().ok()?.fetched?;
+    Some(fetched.elapsed()).filter(|age| *age < window)
+}
+
+/// Changes whenever a fetch lands.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Fetches the list from the watch.
+pub async fn list() -> Result<AlarmList, AlarmError> {
+    submit(Op::List).await
+}
+
+pub async fn add(alarm: NewAlarm) -> Result<AlarmList, AlarmError> {
+    submit(Op::Add(alarm)).await
+}
+
+pub async fn delete(id: u32) -> Result<AlarmList, AlarmError> {
+    submit(Op::Delete(id)).await
+}
+
+pub async fn set_enabled(id: u32, enabled: bool) -> Result<AlarmList, AlarmError> {
+    submit(Op::SetEnabled(id, enabled)).await
+}
+
+/// [`list`] for the console and HTTP threads.
+pub fn list_blocking() -> Result<AlarmList, AlarmError> {
+    submit_blocking(Op::List)
+}
+
+pub fn add_blocking(alarm: NewAlarm) -> Result<AlarmList, AlarmError> {
+    submit_blocking(Op::Add(alarm))
+}
+
+pub fn delete_blocking(id: u32) -> Result<AlarmList, AlarmError> {
+    submit_blocking(Op::Delete(id))
+}
+
+async fn submit(op: Op) -> Result<AlarmList, AlarmError> {
+    let reply = enqueue(op)?;
+    reply.await.map_err(|_| AlarmError::Unavailable)?
+}
+
+fn submit_blocking(op: Op) -> Result<AlarmList, AlarmError> {
+    let reply = enqueue(op)?;
+    reply.blocking_recv().map_err(|_| AlarmError::Unavailable)?
+}
+
+fn enqueue(op: Op) -> Result<oneshot::Receiver<Result<AlarmList, AlarmError>>, AlarmError> {
+    let requests = REQUESTS.get().ok_or(AlarmError::Unavailable)?;
+    let (reply, wait) = oneshot::channel();
+    requests
+        .send(Request { op, reply })
+        .map_err(|_| AlarmError::Unavailable)?;
+    Ok(wait)
+}
+
+fn describe(list: &AlarmList) -> String {
+    let mut lines: Vec<String> = list
+        .alarms
+        .iter()
+        .map(|alarm| {
+            format!(
+                "{:>3} {} {:<9} {} {}",
+                alarm.id,
+                alarm.time,
+                alarm.repeat.to_string(),
+                if alarm.enabled { "on " } else { "off" },
+                alarm.label
+            )
+        })
+        .collect();
+    lines.push(format!("{} of {} alarms", list.alarms.len(), list.limit));
+    lines.join("\n")
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "alarms",
+        "[add <HH:MM> [<days>] [<label>] | del <id> | week [mon|sun]]: watch alarms",
+        |args| match args {
+            [] => Ok(describe(&list_blocking()?)),
+            ["add", time, rest @ ..] => {
+                let time: AlarmTime = time.parse()?;
+                let (repeat, label) = match rest {
+                    [] => (Weekdays::ONCE, Vec::new()),
+                    [days, label @ ..] => match days.parse::<Weekdays>() {
+                        Ok(repeat) => (repeat, label.to_vec()),
+                        Err(_) => (Weekdays::ONCE, rest.to_vec()),
+                    },
+                };
+                let list = add_blocking(NewAlarm {
+                    time,
+                    repeat,
+                    label: label.join(" "),
+                })?;
+                Ok(describe(&list))
+            }
+            ["del", id] => {
+                let id: u32 = id
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("not an alarm id: {id}"))?;
+                Ok(describe(&delete_blocking(id)?))
+            }
+            ["week"] => Ok(format!(
+                "repeat masks count from {}",
+                settings::get(&WEEK_START)
+            )),
+            ["week", start] => {
+                let start: WeekStart = start
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("usage: alarms week mon|sun"))?;
+                settings::set(&WEEK_START, &start)?;
+                Ok(format!(
+                    "repeat masks count from {start}; list again to see"
+                ))
+            }
+            _ => anyhow::bail!(
+                "usage: alarms [add <HH:MM> [<days>] [<label>] | del <id> | week [mon|sun]]"
+            ),
+        },
+    );
+}
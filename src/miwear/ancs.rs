@@ -1,12 +1,18 @@
 #![allow(unexpected_cfgs)]
-use std::time::Duration;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 #[cfg(not(esp_idf_bt_nimble_ext_adv))]
 use esp32_nimble::BLEAdvertisementData;
 use esp32_nimble::{
-    enums::{AuthReq, PairKeyDist, SecurityIOCap},
-    uuid128, BLEDevice, NimbleProperties, NimbleSub, OnWriteArgs,
+    enums::PairKeyDist, utilities::BleUuid, uuid128, BLEDevice, NimbleProperties, NimbleSub,
+    OnWriteArgs,
 };
 #[cfg(esp_idf_bt_nimble_ext_adv)]
 use esp32_nimble::{
@@ -14,26 +20,191 @@ use esp32_nimble::{
     BLEExtAdvertisement, BLEExtAdvertising,
 };
 use log::{debug, info, warn};
-use tokio::{
-    task,
-    time::{self, MissedTickBehavior},
+
+use crate::{
+    ble::hid,
+    events::{self, SystemEvent},
+    metrics,
+    settings::{self, SettingKey},
+    timesync, version,
+};
+use advertising::AdvParams;
+
+use protocol::{
+    NotificationContent, DUMMY_APP_IDENTIFIER, DUMMY_MESSAGE_BODY, DUMMY_MESSAGE_SUBTITLE,
+    DUMMY_MESSAGE_TITLE,
 };
 
-const DUMMY_APP_IDENTIFIER: &str = "com.astrobox.ghost";
+pub mod advertising;
+pub mod app_names;
+pub mod canary;
+pub mod clients;
+pub mod lifecycle;
+pub mod nudge;
+pub mod outbox;
+pub mod pacing;
+pub mod pairing;
+pub mod protocol;
+pub mod recovery;
+pub mod server;
+pub mod sessions;
+pub mod source;
+pub mod store;
+#[cfg(feature = "ancs-testmode")]
+pub mod testmode;
+pub mod webhooks;
+
 const DUMMY_APP_DISPLAY_NAME: &str = "AstroBox Phantom";
-const DUMMY_MESSAGE_TITLE: &str = "Phantom Alert";
-const DUMMY_MESSAGE_SUBTITLE: &str = "Faint Signal";
-const DUMMY_MESSAGE_BODY: &str = "Spectral notification with no real content.";
-const DUMMY_DATE: &str = "19700101T000000";
-const ADVERTISED_NAME: &str = "iP";
+const TEST_APP_IDENTIFIER: &str = "com.astrobox.test";
+const TEST_APP_DISPLAY_NAME: &str = "AstroBox Test";
+pub const ADVERTISED_NAME: &str = "iP";
+pub const SERVICE_UUID: &str = "7905f431-b5ce-4e99-a40f-4b1e122d00d0";
 const APPLE_MANUFACTURER_DATA: [u8; 4] = [0x4C, 0x00, 0x02, 0x15];
 
+/// Off leaves the module a plain MiWear central: no GATT service, no
+/// advertising and no security changes at boot.
+pub const ANCS_ENABLED: SettingKey<bool> = SettingKey::new("ancs_enabled", "true");
+
+/// Set once the GATT service is up; pausing before that has nothing to stop.
+static SERVICE_UP: AtomicBool = AtomicBool::new(false);
+/// Outstanding [`pause_advertising`] calls; while non-zero, connection
+/// callbacks leave advertising off.
+static PAUSE_HOLDS: AtomicU32 = AtomicU32::new(0);
+/// Set by the first registration attempt. GATT services cannot be removed,
+/// so a failed attempt is never repeated in the same boot.
+static REGISTERING: AtomicBool = AtomicBool::new(false);
+/// True while [`set_enabled`] holds an advertising pause for a runtime off.
+static SWITCHED_OFF: AtomicBool = AtomicBool::new(false);
+static REBOOT_REQUIRED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Serving and advertising as the phantom phone.
+    Phantom,
+    /// No service, or service up with advertising held off and no clients.
+    Off,
+    /// Turned on, but the service could not be registered in this boot.
+    RebootRequired,
+}
+
+impl Mode {
+    pub fn code(self) -> &'static str {
+        match self {
+            Mode::Phantom => "phantom",
+            Mode::Off => "off",
+            Mode::RebootRequired => "reboot_required",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::Phantom => "On",
+            Mode::Off => "Off",
+            Mode::RebootRequired => "Reboot needed",
+        }
+    }
+}
+
+pub fn mode() -> Mode {
+    if REBOOT_REQUIRED.load(Ordering::Acquire) {
+        Mode::RebootRequired
+    } else if SERVICE_UP.load(Ordering::Acquire) && !SWITCHED_OFF.load(Ordering::Acquire) {
+        Mode::Phantom
+    } else {
+        Mode::Off
+    }
+}
+
+/// Persists a new pairing IO capability. The registered service gets it
+/// right away; before then registration reads the setting, and the stack is
+/// never brought up from here.
+pub fn set_io_capability(capability: pairing::IoCapability) -> Result<()> {
+    pairing::set_io_capability(capability)?;
+    if SERVICE_UP.load(Ordering::SeqCst) {
+        pairing::apply_pending(BLEDevice::take());
+    }
+    Ok(())
+}
+
+/// Persists [`ANCS_ENABLED`] and applies it live. Turning on registers the
+/// service if boot skipped it; turning off stops advertising and drops the
+/// connected clients, clearing bonds only when `forget_bonds` is set.
+pub fn set_enabled(enabled: bool, forget_bonds: bool) -> Result<Mode> {
+    settings::set(&ANCS_ENABLED, &enabled)?;
+    let ble = BLEDevice::take();
+    if enabled {
+        if SWITCHED_OFF.swap(false, Ordering::AcqRel) {
+            lifecycle::restart();
+            resume_advertising()?;
+        } else if !SERVICE_UP.load(Ordering::Acquire) {
+            if let Err(err) = register_service(ble) {
+                REBOOT_REQUIRED.store(true, Ordering::Release);
+                return Err(err.context("start fake ANCS service live; reboot to apply"));
+            }
+        }
+        info!("Fake ANCS service on: {}", mode().code());
+        return Ok(mode());
+    }
+
+    REBOOT_REQUIRED.store(false, Ordering::Release);
+    if SERVICE_UP.load(Ordering::Acquire) && !SWITCHED_OFF.swap(true, Ordering::AcqRel) {
+        lifecycle::stop();
+        pause_advertising()?;
+        let server = ble.get_server();
+        for client in clients::connected() {
+            if let Err(err) = server.disconnect(client.conn_handle) {
+                warn!(
+                    "Failed to disconnect ANCS client {} (conn={}): {err:?}",
+                    client.label(),
+                    client.conn_handle
+                );
+            }
+        }
+    }
+    if forget_bonds {
+        ble.delete_all_bonds()
+            .map_err(|err| anyhow!("clear bonds: {err:?}"))?;
+        advertising::open_pairing_window();
+        warn!("Fake ANCS service off; all bonds cleared");
+    } else {
+        info!("Fake ANCS service off; bonds kept");
+    }
+    Ok(mode())
+}
+
+fn serving() -> bool {
+    mode() == Mode::Phantom
+}
+
+/// Boot entry: loads ANCS state and, unless [`ANCS_ENABLED`] is off,
+/// registers the service and starts advertising.
 pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
+    #[cfg(feature = "ancs-testmode")]
+    testmode::init();
+    clients::load_known();
+    #[cfg(feature = "storage")]
+    if let Err(err) = app_names::load_overrides() {
+        warn!("App name overrides ignored: {err:#}");
+    }
+    if !settings::get(&ANCS_ENABLED) {
+        info!("Fake ANCS service disabled by setting; MiWear central only");
+        return Ok(());
+    }
+    register_service(ble)
+}
+
+fn register_service(ble: &mut BLEDevice) -> Result<()> {
+    if REGISTERING.swap(true, Ordering::AcqRel) {
+        bail!("an earlier registration in this boot did not finish");
+    }
+    let relaxed = test_mode();
+
+    let io_capability = pairing::configured();
     {
         let security = ble.security();
         security
-            .set_auth(AuthReq::Bond | AuthReq::Sc)
-            .set_io_cap(SecurityIOCap::NoInputNoOutput)
+            .set_auth(pairing::auth_req(io_capability))
+            .set_io_cap(io_capability.to_nimble())
             .set_security_init_key(PairKeyDist::ENC | PairKeyDist::ID)
             .set_security_resp_key(PairKeyDist::ENC | PairKeyDist::ID);
     }
@@ -43,37 +214,64 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
 
     let service = server.create_service(uuid128!("7905f431-b5ce-4e99-a40f-4b1e122d00d0"));
 
+    // Test mode drops the _ENC requirements so a plain dongle can talk to us.
+    let (read_enc, write_enc) = if relaxed {
+        (NimbleProperties::empty(), NimbleProperties::empty())
+    } else {
+        (NimbleProperties::READ_ENC, NimbleProperties::WRITE_ENC)
+    };
     let notification_source = service.lock().create_characteristic(
         uuid128!("9fbf120d-6301-42d9-8c58-25e699a21dbd"),
-        NimbleProperties::READ | NimbleProperties::READ_ENC | NimbleProperties::NOTIFY,
+        NimbleProperties::READ | read_enc | NimbleProperties::NOTIFY,
     );
+    refill_store();
     {
         let mut chr = notification_source.lock();
-        chr.set_value(&build_notification_source_payload(0));
+        if let Some(latest) = store::replay().last() {
+            chr.set_value(latest);
+        }
         chr.on_subscribe(|characteristic, desc, sub| {
             if sub.contains(NimbleSub::NOTIFY) {
+                sessions::on_subscribe(desc.conn_handle());
+                pacing::on_subscribe(desc.conn_handle(), desc.mtu());
                 info!(
-                    "ANCS notification source subscribed: conn={} mtu={} encrypted={}",
+                    "ANCS notification source subscribed: client={} conn={} mtu={} encrypted={}",
+                    client_label(desc.conn_handle()),
                     desc.conn_handle(),
                     desc.mtu(),
                     desc.encrypted()
                 );
-                if !desc.encrypted() {
+                if pairing::awaiting_confirmation(desc.conn_handle()) {
                     info!(
-                        "ANCS subscription pending encryption; waiting for security upgrade (conn={})",
+                        "ANCS subscription held until the pairing code is confirmed (conn={})",
                         desc.conn_handle()
                     );
                     return;
                 }
-                let payload = build_notification_source_payload(0);
-                if let Err(err) = characteristic.notify_with(&payload, desc.conn_handle()) {
-                    warn!(
-                        "Failed to send initial ANCS notification to conn {}: {:?}",
-                        desc.conn_handle(),
-                        err
+                if !desc.encrypted() && !test_mode() {
+                    info!(
+                        "ANCS subscription pending encryption; waiting for security upgrade (conn={})",
+                        desc.conn_handle()
                     );
+                    nudge::on_unencrypted_subscribe(desc.conn_handle(), desc.address().to_string());
+                    return;
+                }
+                if store::is_empty() {
+                    refill_store();
+                }
+                for payload in store::replay() {
+                    if let Err(err) = characteristic.notify_with(&payload, desc.conn_handle()) {
+                        sessions::on_notify_failure(desc.conn_handle());
+                        warn!(
+                            "Failed to send initial ANCS notification to conn {}: {:?}",
+                            desc.conn_handle(),
+                            err
+                        );
+                        break;
+                    }
                 }
             } else {
+                pacing::forget(desc.conn_handle());
                 info!(
                     "ANCS notification source unsubscribed: conn={}",
                     desc.conn_handle()
@@ -84,13 +282,14 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
 
     let data_source = service.lock().create_characteristic(
         uuid128!("22eac6e9-24d6-4bb5-be44-b36ace7c7bfb"),
-        NimbleProperties::READ | NimbleProperties::READ_ENC | NimbleProperties::NOTIFY,
+        NimbleProperties::READ | read_enc | NimbleProperties::NOTIFY,
     );
     {
         let mut chr = data_source.lock();
         chr.set_value(&[]);
         chr.on_subscribe(|_, desc, sub| {
             if sub.contains(NimbleSub::NOTIFY) {
+                sessions::on_subscribe(desc.conn_handle());
                 info!("ANCS data source subscribed: conn={}", desc.conn_handle());
             } else {
                 info!("ANCS data source unsubscribed: conn={}", desc.conn_handle());
@@ -100,7 +299,7 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
 
     let control_point = service.lock().create_characteristic(
         uuid128!("69d1d8f3-45e1-49a8-9821-9bbdfdaad9d9"),
-        NimbleProperties::WRITE | NimbleProperties::WRITE_NO_RSP | NimbleProperties::WRITE_ENC,
+        NimbleProperties::WRITE | NimbleProperties::WRITE_NO_RSP | write_enc,
     );
     {
         let data_source_for_cp = data_source.clone();
@@ -109,20 +308,51 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
             .on_write(move |args: &mut OnWriteArgs| {
                 let request = args.recv_data();
                 debug!("ANCS control point got {:02X?}", request);
-                if !args.desc().encrypted() {
-                    warn!(
-                        "Reject ANCS control write without encryption (conn={})",
-                        args.desc().conn_handle()
-                    );
+                let conn_handle = args.desc().conn_handle();
+                if !args.desc().encrypted() && !test_mode() {
+                    warn!("Reject ANCS control write without encryption (conn={conn_handle})");
+                    sessions::on_rejected_unencrypted(conn_handle);
                     args.reject();
                     return;
                 }
-                if let Some(response) = build_control_point_response(request) {
-                    let mut target = data_source_for_cp.lock();
-                    target.set_value(&response);
-                    if target.subscribed_count() > 0 {
-                        target.notify();
+                if pairing::awaiting_confirmation(conn_handle) {
+                    warn!("Reject ANCS control write before the pairing code is confirmed (conn={conn_handle})");
+                    args.reject();
+                    return;
+                }
+                let parsed = protocol::parse(request);
+                sessions::on_request(conn_handle, &parsed);
+                let request = match parsed {
+                    Ok(request) => request,
+                    Err(err) => {
+                        warn!("Refuse ANCS control write (conn={conn_handle}): {err}");
+                        args.reject_with_error_code(err.att_error());
+                        return;
+                    }
+                };
+                let response = protocol::respond(&request, &LiveLookup);
+                let mut target = data_source_for_cp.lock();
+                target.set_value(&response);
+                if target.subscribed_count() > 0 {
+                    // Only the requester is reassembling this response, and
+                    // each fragment must fit its own link's MTU.
+                    let mtu = args.desc().mtu();
+                    let mut sent = 0;
+                    for fragment in protocol::fragments(&response, mtu) {
+                        if let Err(err) = target.notify_with(fragment, conn_handle) {
+                            sessions::on_notify_failure(conn_handle);
+                            warn!("Failed to send ANCS data source fragment to conn {conn_handle}: {err:?}");
+                            break;
+                        }
+                        sent += 1;
                     }
+                    sessions::on_response(conn_handle, sent);
+                    let interval = Duration::from_micros(u64::from(args.desc().interval()) * 1250);
+                    pacing::on_response(conn_handle, mtu, interval, sent);
+                }
+                canary::on_request(&request);
+                if let protocol::Request::PerformAction { uid, action } = request {
+                    webhooks::on_action(uid, action);
                 }
             });
     }
@@ -130,7 +360,6 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
     let notification_for_auth = notification_source.clone();
     let advertising_on_connect = advertising;
     let advertising_on_disconnect = advertising;
-    let advertising_on_auth = advertising;
 
     server
         .on_connect(move |server, desc| {
@@ -139,6 +368,22 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
                 desc.address(),
                 desc.conn_handle()
             );
+            // Raced a runtime off; the advertising stop came too late.
+            if !serving() {
+                if let Err(err) = server.disconnect(desc.conn_handle()) {
+                    warn!(
+                        "Failed to drop ANCS client after off (conn={}): {err:?}",
+                        desc.conn_handle()
+                    );
+                }
+                return;
+            }
+            clients::on_connect(desc.conn_handle(), desc.address().to_string());
+            sessions::on_connect(desc.conn_handle(), desc.address().to_string());
+            if pairing::configured() == pairing::IoCapability::DisplayOnly && !desc.bonded() {
+                let passkey = pairing::rotate_passkey(BLEDevice::take());
+                pairing::show_passkey(desc.conn_handle(), passkey);
+            }
             let max = esp_idf_svc::sys::CONFIG_BT_NIMBLE_MAX_CONNECTIONS as usize;
             if server.connected_count() < max {
                 if let Err(err) = restart_advertising(advertising_on_connect) {
@@ -152,10 +397,16 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
         })
         .on_disconnect(move |desc, reason| {
             info!(
-                "ANCS client disconnected: conn={} reason={:?}",
+                "ANCS client disconnected: client={} conn={} reason={:?}",
+                client_label(desc.conn_handle()),
                 desc.conn_handle(),
                 reason
             );
+            clients::on_disconnect(desc.conn_handle());
+            sessions::on_disconnect(desc.conn_handle(), format!("{reason:?}"));
+            pacing::forget(desc.conn_handle());
+            nudge::forget(desc.conn_handle());
+            pairing::on_link_closed(desc.conn_handle());
             if let Err(err) = restart_advertising(advertising_on_disconnect) {
                 warn!(
                     "Failed to restart ANCS advertising after disconnect (conn={}): {:?}",
@@ -166,31 +417,13 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
         })
         .on_authentication_complete(move |_server, desc, status| match status {
             Ok(()) => {
-                info!(
-                    "ANCS link encrypted: conn={} bonded={} mtu={}",
-                    desc.conn_handle(),
-                    desc.bonded(),
-                    desc.mtu()
-                );
-                if let Err(err) = restart_advertising(advertising_on_auth) {
-                    warn!(
-                        "Failed to keep ANCS advertising after encryption (conn={}): {:?}",
-                        desc.conn_handle(),
-                        err
-                    );
-                }
-                let mut chr = notification_for_auth.lock();
-                if chr.subscribed_count() > 0 {
-                    let payload = build_notification_source_payload(0);
-                    chr.set_value(&payload);
-                    if let Err(err) = chr.notify_with(&payload, desc.conn_handle()) {
-                        warn!(
-                            "Failed to deliver encrypted ANCS notification to conn {}: {:?}",
-                            desc.conn_handle(),
-                            err
-                        );
-                    }
-                }
+                pairing::on_link_finished(desc.conn_handle(), Ok(()));
+                nudge::forget(desc.conn_handle());
+                let (conn_handle, bonded, mtu) = (desc.conn_handle(), desc.bonded(), desc.mtu());
+                let notification = notification_for_auth.clone();
+                pairing::defer_until_confirmed(conn_handle, move || {
+                    on_link_secured(conn_handle, bonded, mtu, &notification)
+                });
             }
             Err(err) => {
                 warn!(
@@ -198,207 +431,268 @@ pub fn init_fake_ancs_service(ble: &mut BLEDevice) -> Result<()> {
                     desc.conn_handle(),
                     err
                 );
+                pairing::on_link_finished(desc.conn_handle(), Err(format!("{err:?}")));
             }
         })
+        .on_confirm_pin(pairing::confirm)
         .advertise_on_disconnect(true);
 
-    server.start().context("start fake ANCS service")?;
-
-    configure_advertising(advertising).context("configure fake ANCS advertising")?;
-
-    let notification_handle = notification_source.clone();
-    task::spawn_local(async move {
-        let mut counter: u32 = 1;
-        let mut ticker = time::interval(Duration::from_secs(120));
-        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
-
-        loop {
-            ticker.tick().await;
-            let payload = build_notification_source_payload(counter);
-            counter = counter.wrapping_add(1);
+    crate::ble::standard_services::init(ble, &version::device_info());
+    if let Err(err) = hid::init(ble) {
+        warn!("HID remote not registered: {err:#}");
+    }
+    if let Err(err) = crate::ble::link::accept_2m_by_default() {
+        warn!("{err:#}; peers stay on 1M");
+    }
 
-            let mut chr = notification_handle.lock();
-            if chr.subscribed_count() > 0 {
-                chr.set_value(&payload);
-                chr.notify();
-            }
-        }
-    });
+    server.start().context("start fake ANCS service")?;
 
+    advertising::open_pairing_window();
+    let preset = advertising::wanted();
+    let applied = preset.params();
+    configure_advertising(advertising, &applied).context("configure fake ANCS advertising")?;
+    advertising::set_active(preset);
+    SERVICE_UP.store(true, Ordering::SeqCst);
+    // A change made while registering, after security was configured.
+    pairing::apply_pending(ble);
+    if advertising_paused() {
+        server.advertise_on_disconnect(false);
+    } else {
+        restart_advertising(advertising).context("begin advertising fake ANCS service")?;
+    }
+    lifecycle::start(
+        Arc::new(lifecycle::CharacteristicSink(notification_source)),
+        relaxed,
+        applied,
+    );
     Ok(())
 }
 
-fn build_notification_source_payload(seq: u32) -> [u8; 8] {
-    let mut payload = [0u8; 8];
-    payload[0] = 0x00; // EventID: Notification Added
-    payload[1] = 0x01; // EventFlags: Silent
-    payload[2] = 0x00; // Category: Other
-    payload[3] = 1; // Category Count
-    payload[4..8].copy_from_slice(&seq.to_le_bytes());
-    payload
+pub fn register_commands() {
+    crate::console::register(
+        "ancs",
+        "fake ANCS service: ancs [on | off [forget]]; forget also clears bonds",
+        |args| {
+            let mode = match args {
+                [] => mode(),
+                ["on"] => set_enabled(true, false)?,
+                ["off"] => set_enabled(false, false)?,
+                ["off", "forget"] => set_enabled(false, true)?,
+                _ => bail!("usage: ancs [on | off [forget]]"),
+            };
+            Ok(format!(
+                "{} (setting {})",
+                mode.code(),
+                if settings::get(&ANCS_ENABLED) {
+                    "on"
+                } else {
+                    "off"
+                }
+            ))
+        },
+    );
 }
 
-fn build_control_point_response(request: &[u8]) -> Option<Vec<u8>> {
-    let command = *request.get(0)?;
-    match command {
-        0x00 => Some(build_notification_attributes_response(request)),
-        0x01 => Some(build_app_attributes_response(request)),
-        0x02 => Some(build_action_ack_response(request)),
-        other => Some(vec![other, 0x00]),
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    /// Notified to at least one subscriber.
+    Delivered,
+    /// Stored only; a watch sees it on its next subscribe replay.
+    NoSubscriber,
 }
 
-fn build_notification_attributes_response(request: &[u8]) -> Vec<u8> {
-    let mut response = Vec::with_capacity(48);
-    if request.len() >= 5 {
-        response.extend_from_slice(&request[0..5]);
-    } else {
-        response.push(0x00);
-        response.extend_from_slice(&[0, 0, 0, 0]);
+impl Delivery {
+    pub fn label(self) -> &'static str {
+        match self {
+            Delivery::Delivered => "delivered",
+            Delivery::NoSubscriber => "no subscriber",
+        }
     }
+}
 
-    let mut offset = 5;
-    let mut appended = false;
-
-    while offset < request.len() {
-        let attr_id = request[offset];
-        offset += 1;
-
-        let requested_len = if attribute_requires_len(attr_id) {
-            if offset + 1 >= request.len() {
-                break;
-            }
-            let len = u16::from_le_bytes([request[offset], request[offset + 1]]);
-            offset += 2;
-            len as usize
-        } else {
-            0
-        };
+#[derive(Clone, Copy, Debug)]
+pub struct Published {
+    pub uid: u32,
+    pub delivery: Delivery,
+}
 
-        let value = dummy_notification_attribute(attr_id, requested_len);
-        response.push(attr_id);
-        response.extend_from_slice(&(value.len() as u16).to_le_bytes());
-        response.extend_from_slice(&value);
-        appended = true;
+/// Adds a notification and reports whether anyone was listening for it. A
+/// display name on `new` is remembered for App Attributes lookups.
+pub fn publish_notification(new: store::NewNotification) -> Published {
+    if let Some(name) = &new.app_name {
+        app_names::learn(&new.app_id, name);
     }
-
-    if !appended {
-        let value = dummy_notification_attribute(0, 0);
-        response.push(0);
-        response.extend_from_slice(&(value.len() as u16).to_le_bytes());
-        response.extend_from_slice(&value);
+    let delivery = if store::has_subscriber() {
+        Delivery::Delivered
+    } else {
+        Delivery::NoSubscriber
+    };
+    metrics::record(metrics::Counter::NotificationsPublished, 1);
+    if delivery == Delivery::Delivered {
+        metrics::record(metrics::Counter::NotificationsDelivered, 1);
     }
-
-    response
+    let (app_id, title) = (new.app_id.clone(), new.title.clone());
+    canary::on_published();
+    let uid = store::add(new);
+    events::publish(SystemEvent::NotificationPublished { uid, app_id, title });
+    Published { uid, delivery }
 }
 
-fn build_app_attributes_response(request: &[u8]) -> Vec<u8> {
-    let mut response = Vec::with_capacity(48);
-    response.push(0x01);
-
-    let (app_id, mut cursor) = extract_app_identifier(request);
-    response.extend_from_slice(app_id);
-    response.push(0);
+/// A connected peer has finished encryption and enabled Notification Source.
+pub fn encrypted_subscriber() -> bool {
+    store::has_subscriber() && clients::connected().iter().any(|client| client.encrypted)
+}
 
-    if cursor >= request.len() {
-        append_app_attribute(&mut response, 0, dummy_app_attribute(0, 0));
-        return response;
+/// Canned payload for on-device testing; the body carries `seq` and the wall
+/// clock so repeated sends are distinguishable on the watch.
+pub fn test_notification(seq: u32) -> store::NewNotification {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let local = timesync::zone().local(seconds as i64);
+    store::NewNotification {
+        app_id: TEST_APP_IDENTIFIER.to_string(),
+        app_name: Some(TEST_APP_DISPLAY_NAME.to_string()),
+        title: format!("Test #{seq}"),
+        subtitle: String::new(),
+        message: format!(
+            "Test notification #{seq} sent at {:02}:{:02}:{:02} {}",
+            local.hour, local.minute, local.second, local.abbr
+        ),
+        category: store::CATEGORY_OTHER,
+        silent: false,
+        ..Default::default()
     }
+}
 
-    while cursor < request.len() {
-        let attr_id = request[cursor];
-        cursor += 1;
-        if cursor + 1 >= request.len() {
-            break;
-        }
-        let requested_len = u16::from_le_bytes([request[cursor], request[cursor + 1]]) as usize;
-        cursor += 2;
+fn client_label(conn_handle: u16) -> String {
+    clients::label_for(conn_handle).unwrap_or_else(|| "?".to_string())
+}
 
-        append_app_attribute(
-            &mut response,
-            attr_id,
-            dummy_app_attribute(attr_id, requested_len),
-        );
+/// The link is encrypted and, when pairing asked for it, confirmed by the
+/// user: count it as secure and replay the store to it.
+fn on_link_secured(
+    conn_handle: u16,
+    bonded: bool,
+    mtu: u16,
+    notification: &Arc<esp32_nimble::utilities::mutex::Mutex<esp32_nimble::BLECharacteristic>>,
+) {
+    clients::on_encrypted(conn_handle);
+    sessions::on_encrypted(conn_handle);
+    info!(
+        "ANCS link encrypted: client={} conn={} bonded={} mtu={}",
+        client_label(conn_handle),
+        conn_handle,
+        bonded,
+        mtu
+    );
+    if bonded {
+        advertising::on_bonded_connection();
     }
-
-    if response.len() == 1 + app_id.len() + 1 {
-        append_app_attribute(&mut response, 0, dummy_app_attribute(0, 0));
+    if let Err(err) = restart_advertising(BLEDevice::take().get_advertising()) {
+        warn!(
+            "Failed to keep ANCS advertising after encryption (conn={}): {:?}",
+            conn_handle, err
+        );
     }
-
-    response
-}
-
-fn build_action_ack_response(request: &[u8]) -> Vec<u8> {
-    let mut response = Vec::with_capacity(6);
-    response.push(0x02);
-    if request.len() >= 5 {
-        response.extend_from_slice(&request[1..5]);
-    } else {
-        response.extend_from_slice(&[0, 0, 0, 0]);
+    let mut chr = notification.lock();
+    if chr.subscribed_count() > 0 {
+        if store::is_empty() {
+            refill_store();
+        }
+        for payload in store::replay() {
+            chr.set_value(&payload);
+            if let Err(err) = chr.notify_with(&payload, conn_handle) {
+                sessions::on_notify_failure(conn_handle);
+                warn!(
+                    "Failed to deliver encrypted ANCS notification to conn {}: {:?}",
+                    conn_handle, err
+                );
+                break;
+            }
+        }
     }
-    response.push(request.get(5).copied().unwrap_or(0));
-    response
 }
 
-fn extract_app_identifier(request: &[u8]) -> (&[u8], usize) {
-    if request.len() <= 1 {
-        return (&[], request.len());
+/// Always false unless built with `ancs-testmode` and switched on.
+pub fn test_mode() -> bool {
+    #[cfg(feature = "ancs-testmode")]
+    {
+        testmode::active()
     }
-    let mut idx = 1;
-    while idx < request.len() && request[idx] != 0 {
-        idx += 1;
+    #[cfg(not(feature = "ancs-testmode"))]
+    {
+        false
     }
-    let app_id = &request[1..idx];
-    let cursor = if idx < request.len() {
-        idx + 1
-    } else {
-        request.len()
-    };
-    (app_id, cursor)
 }
 
-fn append_app_attribute(buffer: &mut Vec<u8>, attr_id: u8, value: Vec<u8>) {
-    buffer.push(attr_id);
-    buffer.extend_from_slice(&(value.len() as u16).to_le_bytes());
-    buffer.extend_from_slice(&value);
+fn refill_store() {
+    #[cfg(feature = "ancs-testmode")]
+    if testmode::active() {
+        testmode::seed();
+        return;
+    }
+    store::insert_quietly(phantom_notification());
 }
 
-fn attribute_requires_len(attr_id: u8) -> bool {
-    matches!(attr_id, 1 | 2 | 3)
-}
+/// Serves Control Point lookups from the live store.
+struct LiveLookup;
+
+impl protocol::Lookup for LiveLookup {
+    fn notification(&self, uid: u32) -> Option<NotificationContent> {
+        store::get(uid).map(|entry| NotificationContent {
+            app_id: entry.app_id,
+            title: entry.title,
+            subtitle: entry.subtitle,
+            message: entry.message,
+            date: entry.date,
+            positive_label: entry.actions.positive,
+            negative_label: entry.actions.negative,
+        })
+    }
 
-fn dummy_notification_attribute(attr_id: u8, requested_len: usize) -> Vec<u8> {
-    match attr_id {
-        0 => truncate_bytes(DUMMY_APP_IDENTIFIER.as_bytes(), requested_len),
-        1 => truncate_bytes(DUMMY_MESSAGE_TITLE.as_bytes(), requested_len),
-        2 => truncate_bytes(DUMMY_MESSAGE_SUBTITLE.as_bytes(), requested_len),
-        3 => truncate_bytes(DUMMY_MESSAGE_BODY.as_bytes(), requested_len),
-        4 => truncate_bytes(b"0", requested_len),
-        5 => truncate_bytes(DUMMY_DATE.as_bytes(), requested_len),
-        6 => truncate_bytes(b"Open", requested_len),
-        7 => truncate_bytes(b"Ignore", requested_len),
-        _ => truncate_bytes(b"", requested_len),
+    fn app_name(&self, app_id: &[u8]) -> Vec<u8> {
+        app_names::display_name(app_id)
     }
 }
 
-fn dummy_app_attribute(attr_id: u8, requested_len: usize) -> Vec<u8> {
-    match attr_id {
-        0 => truncate_bytes(DUMMY_APP_DISPLAY_NAME.as_bytes(), requested_len),
-        _ => truncate_bytes(b"", requested_len),
+/// Silent placeholder that keeps watches expecting ANCS traffic satisfied.
+fn phantom_notification() -> store::NewNotification {
+    store::NewNotification {
+        app_id: DUMMY_APP_IDENTIFIER.to_string(),
+        app_name: Some(DUMMY_APP_DISPLAY_NAME.to_string()),
+        title: DUMMY_MESSAGE_TITLE.to_string(),
+        subtitle: DUMMY_MESSAGE_SUBTITLE.to_string(),
+        message: DUMMY_MESSAGE_BODY.to_string(),
+        category: store::CATEGORY_OTHER,
+        silent: true,
+        ..Default::default()
     }
 }
 
-fn truncate_bytes(data: &[u8], max_len: usize) -> Vec<u8> {
-    if max_len == 0 || data.len() <= max_len {
-        data.to_vec()
-    } else {
-        data[..max_len].to_vec()
+/// Applies new interval and power settings. Advertising stops for the
+/// switch; established links are not touched.
+fn readvertise(params: &AdvParams) -> Result<()> {
+    let ble = BLEDevice::take();
+    let advertising = ble.get_advertising();
+    // Fails harmlessly when advertising is already off.
+    if let Err(err) = stop_advertising(advertising) {
+        debug!("ANCS advertising stop before reconfigure: {err:?}");
+    }
+    configure_advertising(advertising, params).context("reconfigure fake ANCS advertising")?;
+    let max = esp_idf_svc::sys::CONFIG_BT_NIMBLE_MAX_CONNECTIONS as usize;
+    if ble.get_server().connected_count() >= max {
+        return Ok(());
     }
+    restart_advertising(advertising).context("restart fake ANCS advertising")
 }
 
+/// Sets payload and parameters; the caller starts advertising.
 #[cfg(not(esp_idf_bt_nimble_ext_adv))]
 fn configure_advertising(
     advertising: &'static esp32_nimble::utilities::mutex::Mutex<esp32_nimble::BLEAdvertising>,
+    params: &AdvParams,
 ) -> Result<()> {
     let mut adv = advertising.lock();
     adv.reset()
@@ -410,13 +704,27 @@ fn configure_advertising(
     adv_data.manufacturer_data(&APPLE_MANUFACTURER_DATA);
     adv.set_data(&mut adv_data)
         .context("set fake ANCS advertisement payload")?;
-    adv.start().context("begin advertising fake ANCS service")?;
-    Ok(())
+    // The primary payload has no room left; see `ble::hid`.
+    let remote = hid::registered();
+    adv.scan_response(remote);
+    if remote {
+        let mut scan_data = BLEAdvertisementData::new();
+        scan_data
+            .name(hid::DEVICE_NAME)
+            .add_service_uuid(BleUuid::from_uuid16(hid::HID_SERVICE))
+            .appearance(hid::APPEARANCE);
+        adv.set_scan_response_data(&mut scan_data)
+            .context("set HID scan response")?;
+    }
+    adv.min_interval(params.min_units() as u16)
+        .max_interval(params.max_units() as u16);
+    advertising::set_tx_power(params)
 }
 
 #[cfg(esp_idf_bt_nimble_ext_adv)]
 fn configure_advertising(
     advertising: &'static esp32_nimble::utilities::mutex::Mutex<BLEExtAdvertising>,
+    params: &AdvParams,
 ) -> Result<()> {
     let mut adv = advertising.lock();
     let mut payload = BLEExtAdvertisement::new(PrimPhy::Phy1M, SecPhy::Phy1M);
@@ -426,17 +734,85 @@ fn configure_advertising(
     payload.name(ADVERTISED_NAME);
     payload.complete_service(&uuid128!("7905f431-b5ce-4e99-a40f-4b1e122d00d0"));
     payload.manufacturer_data(&APPLE_MANUFACTURER_DATA);
+    payload.min_interval(params.min_units());
+    payload.max_interval(params.max_units());
+    payload.tx_power(params.tx_level_dbm());
     adv.set_instance_data(0, &mut payload)
         .context("set fake ANCS extended advertisement payload")?;
-    adv.start(0)
-        .context("begin advertising fake ANCS service")?;
-    Ok(())
+    // The primary payload has no room left; see `ble::hid`.
+    if hid::registered() {
+        let mut scan = BLEExtAdvertisement::new(PrimPhy::Phy1M, SecPhy::Phy1M);
+        scan.legacy_advertising(true);
+        scan.name(hid::DEVICE_NAME);
+        scan.complete_service(&BleUuid::from_uuid16(hid::HID_SERVICE));
+        scan.appearance(hid::APPEARANCE);
+        adv.set_scan_response_data(0, &mut scan)
+            .context("set HID scan response")?;
+    }
+    advertising::set_tx_power(params)
+}
+
+/// Stops advertising and keeps it off across connects and disconnects until
+/// every pause has been matched by [`resume_advertising`]. Existing links
+/// stay up.
+pub fn pause_advertising() -> Result<()> {
+    if PAUSE_HOLDS.fetch_add(1, Ordering::AcqRel) > 0 || !SERVICE_UP.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    let ble = BLEDevice::take();
+    ble.get_server().advertise_on_disconnect(false);
+    stop_advertising(ble.get_advertising()).context("stop fake ANCS advertising")
+}
+
+pub fn resume_advertising() -> Result<()> {
+    let released = PAUSE_HOLDS.fetch_update(Ordering::AcqRel, Ordering::Acquire, |holds| {
+        holds.checked_sub(1)
+    });
+    if released != Ok(1) || !SERVICE_UP.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    let ble = BLEDevice::take();
+    let server = ble.get_server();
+    server.advertise_on_disconnect(true);
+    let max = esp_idf_svc::sys::CONFIG_BT_NIMBLE_MAX_CONNECTIONS as usize;
+    if server.connected_count() >= max {
+        return Ok(());
+    }
+    restart_advertising(ble.get_advertising()).context("restart fake ANCS advertising")
+}
+
+fn advertising_paused() -> bool {
+    PAUSE_HOLDS.load(Ordering::Acquire) > 0
+}
+
+/// A [`pause_advertising`] hold released on drop, so early returns cannot
+/// leave advertising off.
+pub struct AdvertisingPause(());
+
+impl AdvertisingPause {
+    pub fn hold() -> Self {
+        if let Err(err) = pause_advertising() {
+            warn!("{err:#}");
+        }
+        Self(())
+    }
+}
+
+impl Drop for AdvertisingPause {
+    fn drop(&mut self) {
+        if let Err(err) = resume_advertising() {
+            warn!("{err:#}");
+        }
+    }
 }
 
 #[cfg(not(esp_idf_bt_nimble_ext_adv))]
 fn restart_advertising(
     advertising: &'static esp32_nimble::utilities::mutex::Mutex<esp32_nimble::BLEAdvertising>,
 ) -> Result<(), esp32_nimble::BLEError> {
+    if advertising_paused() {
+        return Ok(());
+    }
     advertising.lock().start()
 }
 
@@ -444,5 +820,22 @@ fn restart_advertising(
 fn restart_advertising(
     advertising: &'static esp32_nimble::utilities::mutex::Mutex<BLEExtAdvertising>,
 ) -> Result<(), esp32_nimble::BLEError> {
+    if advertising_paused() {
+        return Ok(());
+    }
     advertising.lock().start(0)
 }
+
+#[cfg(not(esp_idf_bt_nimble_ext_adv))]
+fn stop_advertising(
+    advertising: &'static esp32_nimble::utilities::mutex::Mutex<esp32_nimble::BLEAdvertising>,
+) -> Result<(), esp32_nimble::BLEError> {
+    advertising.lock().stop()
+}
+
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+fn stop_advertising(
+    advertising: &'static esp32_nimble::utilities::mutex::Mutex<BLEExtAdvertising>,
+) -> Result<(), esp32_nimble::BLEError> {
+    advertising.lock().stop(0)
+}
@@ -0,0 +1,236 @@
+//! Advertising interval and TX power for the fake ANCS service. Phones only
+//! need fast advertising while pairing; a bonded watch reconnects fine from
+//! slow, quiet advertising and the radio time goes to the MiWear link.
+//!
+//! [`ADV_POLICY`] picks a fixed preset, `custom` values, or `auto`: Pairing
+//! for [`PAIRING_WINDOW`] after boot or a re-pair, then Background once a
+//! bonded client has connected this boot, Normal otherwise.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+use crate::settings::{self, SettingKey};
+
+mod limits;
+
+use limits::{INTERVAL_MS, TX_LEVELS_DBM};
+
+pub const ADV_POLICY: SettingKey<AdvPolicy> = SettingKey::new("ancs_adv", "auto");
+/// Used by the `custom` policy. To move the interval past the other bound,
+/// set the bound that is in the way first.
+pub const ADV_MIN_MS: SettingKey<u32> = SettingKey::new("ancs_adv_min", "152")
+    .validated(|min_ms| limits::check_min_interval(*min_ms, settings::get(&ADV_MAX_MS)));
+pub const ADV_MAX_MS: SettingKey<u32> = SettingKey::new("ancs_adv_max", "211")
+    .validated(|max_ms| limits::check_max_interval(*max_ms, settings::get(&ADV_MIN_MS)));
+pub const ADV_TX_DBM: SettingKey<i8> =
+    SettingKey::new("ancs_adv_dbm", "0").validated(|dbm| limits::check_tx_power(*dbm));
+
+pub const PAIRING_WINDOW: Duration = Duration::from_secs(60);
+
+static PAIRING_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+static BONDED_SEEN: AtomicBool = AtomicBool::new(false);
+static ACTIVE: AtomicU8 = AtomicU8::new(AdvPreset::Normal as u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AdvPreset {
+    Pairing,
+    Normal,
+    Background,
+    /// The [`ADV_MIN_MS`], [`ADV_MAX_MS`] and [`ADV_TX_DBM`] values.
+    Custom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdvPolicy {
+    Auto,
+    Fixed(AdvPreset),
+}
+
+/// What the controller is told; intervals in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdvParams {
+    pub min_ms: u32,
+    pub max_ms: u32,
+    pub tx_dbm: i8,
+}
+
+impl AdvPreset {
+    const ALL: [AdvPreset; 4] = [
+        AdvPreset::Pairing,
+        AdvPreset::Normal,
+        AdvPreset::Background,
+        AdvPreset::Custom,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            AdvPreset::Pairing => "pairing",
+            AdvPreset::Normal => "normal",
+            AdvPreset::Background => "background",
+            AdvPreset::Custom => "custom",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AdvPreset::Pairing => "Pairing",
+            AdvPreset::Normal => "Normal",
+            AdvPreset::Background => "Background",
+            AdvPreset::Custom => "Custom",
+        }
+    }
+
+    pub fn params(self) -> AdvParams {
+        match self {
+            AdvPreset::Pairing => AdvParams {
+                min_ms: 20,
+                max_ms: 30,
+                tx_dbm: 0,
+            },
+            // Apple's recommended 152.5 ms, rounded to whole milliseconds.
+            AdvPreset::Normal => AdvParams {
+                min_ms: 152,
+                max_ms: 211,
+                tx_dbm: 0,
+            },
+            AdvPreset::Background => AdvParams {
+                min_ms: 1_000,
+                max_ms: 2_000,
+                tx_dbm: -12,
+            },
+            AdvPreset::Custom => {
+                let min_ms = clamp_interval(settings::get(&ADV_MIN_MS));
+                AdvParams {
+                    min_ms,
+                    max_ms: clamp_interval(settings::get(&ADV_MAX_MS)).max(min_ms),
+                    tx_dbm: settings::get(&ADV_TX_DBM),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for AdvPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdvPolicy::Auto => f.write_str("auto"),
+            AdvPolicy::Fixed(preset) => f.write_str(preset.code()),
+        }
+    }
+}
+
+impl FromStr for AdvPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(AdvPolicy::Auto);
+        }
+        AdvPreset::ALL
+            .into_iter()
+            .find(|preset| preset.code() == s)
+            .map(AdvPolicy::Fixed)
+            .ok_or(())
+    }
+}
+
+impl AdvParams {
+    /// In 0.625 ms controller units.
+    pub fn min_units(&self) -> u32 {
+        self.min_ms * 8 / 5
+    }
+
+    pub fn max_units(&self) -> u32 {
+        self.max_ms * 8 / 5
+    }
+
+    /// The nearest level the controller supports.
+    pub fn tx_level_dbm(&self) -> i8 {
+        TX_LEVELS_DBM
+            .into_iter()
+            .min_by_key(|level| (i16::from(*level) - i16::from(self.tx_dbm)).abs())
+            .unwrap_or(0)
+    }
+}
+
+/// Opens the auto policy's pairing window; called at boot and whenever
+/// bonds are cleared.
+pub fn open_pairing_window() {
+    if let Ok(mut until) = PAIRING_UNTIL.lock() {
+        *until = Some(Instant::now() + PAIRING_WINDOW);
+    }
+}
+
+pub fn on_bonded_connection() {
+    BONDED_SEEN.store(true, Ordering::Relaxed);
+}
+
+/// The preset the policy asks for right now.
+pub fn wanted() -> AdvPreset {
+    match settings::get(&ADV_POLICY) {
+        AdvPolicy::Fixed(preset) => preset,
+        AdvPolicy::Auto => {
+            let pairing = PAIRING_UNTIL
+                .lock()
+                .ok()
+                .and_then(|until| *until)
+                .is_some_and(|until| Instant::now() < until);
+            if pairing {
+                AdvPreset::Pairing
+            } else if BONDED_SEEN.load(Ordering::Relaxed) {
+                AdvPreset::Background
+            } else {
+                AdvPreset::Normal
+            }
+        }
+    }
+}
+
+/// The preset last applied to the controller.
+pub fn active() -> AdvPreset {
+    AdvPreset::ALL
+        .into_iter()
+        .find(|preset| *preset as u8 == ACTIVE.load(Ordering::Relaxed))
+        .unwrap_or(AdvPreset::Normal)
+}
+
+pub fn set_active(preset: AdvPreset) {
+    ACTIVE.store(preset as u8, Ordering::Relaxed);
+}
+
+fn clamp_interval(ms: u32) -> u32 {
+    ms.clamp(*INTERVAL_MS.start(), *INTERVAL_MS.end())
+}
+
+/// Sets the controller's advertising TX power.
+pub fn set_tx_power(params: &AdvParams) -> Result<()> {
+    use esp_idf_svc::sys::{
+        esp, esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV as ADV, esp_ble_tx_power_set,
+        esp_power_level_t_ESP_PWR_LVL_N0 as N0, esp_power_level_t_ESP_PWR_LVL_N12 as N12,
+        esp_power_level_t_ESP_PWR_LVL_N3 as N3, esp_power_level_t_ESP_PWR_LVL_N6 as N6,
+        esp_power_level_t_ESP_PWR_LVL_N9 as N9, esp_power_level_t_ESP_PWR_LVL_P3 as P3,
+        esp_power_level_t_ESP_PWR_LVL_P6 as P6, esp_power_level_t_ESP_PWR_LVL_P9 as P9,
+    };
+    let level = match params.tx_level_dbm() {
+        -12 => N12,
+        -9 => N9,
+        -6 => N6,
+        -3 => N3,
+        3 => P3,
+        6 => P6,
+        9 => P9,
+        _ => N0,
+    };
+    esp!(unsafe { esp_ble_tx_power_set(ADV, level) })
+        .with_context(|| format!("set advertising TX power to {} dBm", params.tx_level_dbm()))
+}
@@ -0,0 +1,43 @@
+//! What the advertising settings accept. A bound is checked against the
+//! other bound's value, passed in, so the rules stay pure.
+
+/// Limits of the BLE advertising interval.
+pub const INTERVAL_MS: std::ops::RangeInclusive<u32> = 20..=10_240;
+/// Levels every ESP32 controller accepts for advertising.
+pub const TX_LEVELS_DBM: [i8; 8] = [-12, -9, -6, -3, 0, 3, 6, 9];
+
+pub fn check_interval(ms: u32) -> Result<(), String> {
+    if INTERVAL_MS.contains(&ms) {
+        Ok(())
+    } else {
+        Err(format!(
+            "interval is {} to {} ms",
+            INTERVAL_MS.start(),
+            INTERVAL_MS.end()
+        ))
+    }
+}
+
+pub fn check_min_interval(min_ms: u32, max_ms: u32) -> Result<(), String> {
+    check_interval(min_ms)?;
+    if min_ms > max_ms {
+        return Err(format!("above the maximum interval of {max_ms} ms"));
+    }
+    Ok(())
+}
+
+pub fn check_max_interval(max_ms: u32, min_ms: u32) -> Result<(), String> {
+    check_interval(max_ms)?;
+    if max_ms < min_ms {
+        return Err(format!("below the minimum interval of {min_ms} ms"));
+    }
+    Ok(())
+}
+
+pub fn check_tx_power(dbm: i8) -> Result<(), String> {
+    if TX_LEVELS_DBM.contains(&dbm) {
+        Ok(())
+    } else {
+        Err(format!("TX power is one of {TX_LEVELS_DBM:?} dBm"))
+    }
+}
@@ -0,0 +1,148 @@
+//! Display names answered for ANCS Get App Attributes. Lookup order:
+//! `/storage/app_names.json` overrides, then names learned from published
+//! notifications, then the built-in table. Unknown identifiers are answered
+//! with the identifier itself.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use log::debug;
+
+/// Bounds RAM spent on names from HTTP/demo notifications; oldest go first.
+const MAX_LEARNED: usize = 64;
+#[cfg(feature = "storage")]
+const OVERRIDES_FILE: &str = "/storage/app_names.json";
+#[cfg(feature = "storage")]
+const OVERRIDES_MAX_BYTES: u64 = 16 * 1024;
+
+const BUILT_IN: &[(&str, &str)] = &[
+    ("com.astrobox.ghost", "AstroBox Phantom"),
+    ("com.astrobox.test", "AstroBox Test"),
+    ("com.astrobox.http", "AstroBox"),
+    ("com.tencent.mm", "WeChat"),
+    ("com.tencent.mobileqq", "QQ"),
+    ("com.tencent.xin", "WeChat"),
+    ("com.tencent.mqq", "QQ"),
+    ("com.alibaba.android.rimet", "DingTalk"),
+    ("com.ss.android.lark", "Lark"),
+    ("com.ss.android.ugc.aweme", "Douyin"),
+    ("com.sina.weibo", "Weibo"),
+    ("com.eg.android.AlipayGphone", "Alipay"),
+    ("com.taobao.taobao", "Taobao"),
+    ("com.xingin.xhs", "Xiaohongshu"),
+    ("tv.danmaku.bili", "Bilibili"),
+    ("org.telegram.messenger", "Telegram"),
+    ("ph.telegra.Telegraph", "Telegram"),
+    ("com.whatsapp", "WhatsApp"),
+    ("net.whatsapp.WhatsApp", "WhatsApp"),
+    ("com.discord", "Discord"),
+    ("com.hammerandchisel.discord", "Discord"),
+    ("com.Slack", "Slack"),
+    ("com.tinyspeck.chatlyio", "Slack"),
+    ("org.thoughtcrime.securesms", "Signal"),
+    ("org.whispersystems.signal", "Signal"),
+    ("jp.naver.line.android", "LINE"),
+    ("com.facebook.orca", "Messenger"),
+    ("com.instagram.android", "Instagram"),
+    ("com.twitter.android", "X"),
+    ("com.google.android.gm", "Gmail"),
+    ("com.google.Gmail", "Gmail"),
+    ("com.google.android.calendar", "Calendar"),
+    ("com.android.mms", "Messages"),
+    ("com.google.android.apps.messaging", "Messages"),
+    ("com.apple.MobileSMS", "Messages"),
+    ("com.apple.mobilemail", "Mail"),
+    ("com.apple.mobilecal", "Calendar"),
+    ("com.android.phone", "Phone"),
+    ("com.apple.mobilephone", "Phone"),
+];
+
+static LEARNED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+static OVERRIDES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// Loads the override file if present. A missing file is normal; a malformed
+/// one is reported and ignored so a typo cannot take ANCS down.
+#[cfg(feature = "storage")]
+pub fn load_overrides() -> anyhow::Result<()> {
+    use anyhow::{bail, Context};
+    use log::info;
+
+    if !crate::storage::mounted() {
+        return Ok(());
+    }
+    let size = match std::fs::metadata(OVERRIDES_FILE) {
+        Ok(meta) => meta.len(),
+        Err(_) => return Ok(()),
+    };
+    if size > OVERRIDES_MAX_BYTES {
+        bail!("{OVERRIDES_FILE} is {size} bytes, limit is {OVERRIDES_MAX_BYTES}");
+    }
+    let text = std::fs::read_to_string(OVERRIDES_FILE)
+        .with_context(|| format!("read {OVERRIDES_FILE}"))?;
+    let table: HashMap<String, String> =
+        serde_json::from_str(&text).with_context(|| format!("parse {OVERRIDES_FILE}"))?;
+    info!("Loaded {} app name overrides", table.len());
+    set_overrides(table);
+    Ok(())
+}
+
+/// Replaces the override table, which wins over every other source.
+pub fn set_overrides(table: HashMap<String, String>) {
+    if let Ok(mut overrides) = OVERRIDES.lock() {
+        *overrides = Some(table);
+    }
+}
+
+/// Remembers the name a notification was published with. Later names for
+/// the same identifier replace earlier ones.
+pub fn learn(app_id: &str, name: &str) {
+    if app_id.is_empty() || name.is_empty() {
+        return;
+    }
+    let Ok(mut learned) = LEARNED.lock() else {
+        return;
+    };
+    if let Some(index) = learned.iter().position(|(id, _)| id == app_id) {
+        if learned[index].1 == name {
+            return;
+        }
+        learned.remove(index);
+    } else if learned.len() == MAX_LEARNED {
+        learned.remove(0);
+    }
+    debug!("Learned app name {app_id} -> {name}");
+    learned.push((app_id.to_string(), name.to_string()));
+}
+
+/// The mapped name for `app_id`, if any source knows it.
+pub fn lookup(app_id: &str) -> Option<String> {
+    let overridden = OVERRIDES
+        .lock()
+        .ok()
+        .and_then(|overrides| overrides.as_ref()?.get(app_id).cloned());
+    if overridden.is_some() {
+        return overridden;
+    }
+    let learned = LEARNED.lock().ok().and_then(|learned| {
+        learned
+            .iter()
+            .find(|(id, _)| id == app_id)
+            .map(|(_, name)| name.clone())
+    });
+    if learned.is_some() {
+        return learned;
+    }
+    BUILT_IN
+        .iter()
+        .find(|(id, _)| *id == app_id)
+        .map(|(_, name)| name.to_string())
+}
+
+/// Name bytes for the identifier the watch sent. Identifiers that are not
+/// UTF-8 cannot be in any table and are echoed back unchanged.
+pub fn display_name(app_id: &[u8]) -> Vec<u8> {
+    std::str::from_utf8(app_id)
+        .ok()
+        .and_then(lookup)
+        .map(String::into_bytes)
+        .unwrap_or_else(|| app_id.to_vec())
+}
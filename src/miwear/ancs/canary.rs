@@ -0,0 +1,313 @@
+//! Relay canary. Every [`CANARY_MINUTES`], while [`CANARY`] is on, a silent
+//! notification goes into the store like any other and the watch gets
+//! [`TIMEOUT`] to ask for its attributes over the Control Point. That
+//! request proves the Notification Source event arrived and was processed.
+//! The entry is removed right after the request (or at the timeout), so
+//! the watch never keeps it.
+//!
+//! Results are counted in `metrics`. From [`DEGRADED_AFTER`] misses in a row,
+//! every further miss publishes `SystemEvent::RelayDegraded`, which
+//! `recovery` answers.
+//!
+//! A canary is put off while a real notification may still be in flight:
+//! one published within [`TIMEOUT`], or events `pacing` is still holding.
+//! It is also put off while no encrypted watch is subscribed, since there
+//! is nothing to test then.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail};
+use log::{debug, info, warn};
+
+use super::{
+    pacing,
+    protocol::Request,
+    store::{self, NewNotification},
+};
+use crate::{
+    events::{self, SystemEvent},
+    metrics::{self, Counter},
+    periodic::{self, JobHandle},
+    settings::{self, SettingKey},
+};
+
+pub const CANARY: SettingKey<bool> = SettingKey::new("ancs_canary", "false");
+pub const CANARY_MINUTES: SettingKey<u32> =
+    SettingKey::new("ancs_canary_min", "60").validated(|minutes| {
+        if (5..=24 * 60).contains(minutes) {
+            Ok(())
+        } else {
+            Err("the canary runs every 5 to 1440 minutes".to_string())
+        }
+    });
+
+pub const CANARY_APP_IDENTIFIER: &str = "com.astrobox.canary";
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEGRADED_AFTER: u32 = 2;
+/// Wait after a canary was put off.
+const RETRY: Duration = Duration::from_secs(60);
+const CHECK: Duration = Duration::from_secs(1);
+
+static STATE: Mutex<State> = Mutex::new(State::new());
+
+struct InFlight {
+    uid: u32,
+    sent: Instant,
+    fetched: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    Failed,
+    /// The watch went away before the timeout; not counted.
+    Abandoned,
+}
+
+struct State {
+    next_due: Option<Instant>,
+    /// Set by `ancscanary run`: the next check sends one even when off.
+    forced: bool,
+    in_flight: Option<InFlight>,
+    last_real: Option<Instant>,
+    /// Misses in a row.
+    failures: u32,
+    last: Option<(Instant, Outcome)>,
+    passed: u32,
+    failed: u32,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            next_due: None,
+            forced: false,
+            in_flight: None,
+            last_real: None,
+            failures: 0,
+            last: None,
+            passed: 0,
+            failed: 0,
+        }
+    }
+
+    /// Why a canary cannot go out now.
+    fn blocker(&self, now: Instant) -> Option<&'static str> {
+        if !super::encrypted_subscriber() {
+            return Some("no encrypted subscriber");
+        }
+        if self
+            .last_real
+            .is_some_and(|at| now.saturating_duration_since(at) < TIMEOUT)
+        {
+            return Some("a notification was just published");
+        }
+        if pacing::holding() {
+            return Some("events are held for pacing");
+        }
+        None
+    }
+}
+
+pub(super) fn start() -> JobHandle {
+    periodic::register("ancs_canary", CHECK, check)
+}
+
+/// A real notification was published; the canary keeps out of its way.
+pub fn on_published() {
+    if let Ok(mut state) = STATE.lock() {
+        state.last_real = Some(Instant::now());
+    }
+}
+
+/// Control Point hook, after the response went out.
+pub fn on_request(request: &Request<'_>) {
+    let Request::NotificationAttributes { uid, .. } = request else {
+        return;
+    };
+    if let Ok(mut state) = STATE.lock() {
+        if let Some(flight) = state.in_flight.as_mut().filter(|flight| flight.uid == *uid) {
+            flight.fetched = true;
+        }
+    }
+}
+
+fn check() {
+    let now = Instant::now();
+    let finished = {
+        let Ok(mut state) = STATE.lock() else {
+            return;
+        };
+        match state.in_flight.as_ref() {
+            Some(flight) if flight.fetched => Some((flight.uid, Outcome::Passed)),
+            Some(flight) if now.saturating_duration_since(flight.sent) >= TIMEOUT => {
+                let outcome = if super::encrypted_subscriber() {
+                    Outcome::Failed
+                } else {
+                    Outcome::Abandoned
+                };
+                Some((flight.uid, outcome))
+            }
+            Some(_) => return,
+            None => None,
+        }
+    };
+    if let Some((uid, outcome)) = finished {
+        // Outside the state lock: removing publishes to the watch.
+        store::remove(uid);
+        finish(now, outcome);
+        return;
+    }
+
+    let Ok(mut state) = STATE.lock() else {
+        return;
+    };
+    if !state.forced && !settings::get(&CANARY) {
+        state.next_due = None;
+        return;
+    }
+    let interval = Duration::from_secs(u64::from(settings::get(&CANARY_MINUTES)) * 60);
+    let due = *state.next_due.get_or_insert(now + interval);
+    if now < due && !state.forced {
+        return;
+    }
+    if let Some(reason) = state.blocker(now) {
+        debug!("Relay canary put off: {reason}");
+        state.next_due = Some(now + RETRY);
+        return;
+    }
+    state.forced = false;
+    state.next_due = Some(now + interval);
+    drop(state);
+
+    let uid = store::add(canary());
+    if uid == 0 {
+        return;
+    }
+    debug!("Relay canary {uid} sent");
+    if let Ok(mut state) = STATE.lock() {
+        state.in_flight = Some(InFlight {
+            uid,
+            sent: now,
+            fetched: false,
+        });
+    }
+}
+
+fn finish(now: Instant, outcome: Outcome) {
+    let failures = {
+        let Ok(mut state) = STATE.lock() else {
+            return;
+        };
+        state.in_flight = None;
+        state.last = Some((now, outcome));
+        match outcome {
+            Outcome::Passed => {
+                if state.failures >= DEGRADED_AFTER {
+                    crate::journal!(
+                        "Notification relay recovered after {} missed canaries",
+                        state.failures
+                    );
+                }
+                state.failures = 0;
+                state.passed += 1;
+                metrics::record(Counter::CanaryPassed, 1);
+            }
+            Outcome::Failed => {
+                state.failures += 1;
+                state.failed += 1;
+                metrics::record(Counter::CanaryFailed, 1);
+            }
+            Outcome::Abandoned => {}
+        }
+        state.failures
+    };
+    match outcome {
+        Outcome::Passed => debug!("Relay canary passed"),
+        Outcome::Abandoned => info!("Relay canary abandoned: the watch went away"),
+        Outcome::Failed => {
+            warn!("Relay canary missed ({failures} in a row)");
+            if failures >= DEGRADED_AFTER {
+                events::publish(SystemEvent::RelayDegraded { failures });
+            }
+        }
+    }
+}
+
+/// Silent, in the lowest category, and without action buttons.
+fn canary() -> NewNotification {
+    NewNotification {
+        app_id: CANARY_APP_IDENTIFIER.to_string(),
+        app_name: Some("AstroBox".to_string()),
+        title: "Relay check".to_string(),
+        category: store::CATEGORY_OTHER,
+        silent: true,
+        ..Default::default()
+    }
+}
+
+fn report() -> String {
+    let Ok(state) = STATE.lock() else {
+        return "canary state poisoned".to_string();
+    };
+    let now = Instant::now();
+    let mut lines = vec![format!(
+        "canary {}, every {} min; passed {}, missed {} since boot; {} missed in a row",
+        if settings::get(&CANARY) { "on" } else { "off" },
+        settings::get(&CANARY_MINUTES),
+        state.passed,
+        state.failed,
+        state.failures
+    )];
+    if let Some(flight) = &state.in_flight {
+        lines.push(format!(
+            "in flight: uid {} sent {} s ago",
+            flight.uid,
+            now.saturating_duration_since(flight.sent).as_secs()
+        ));
+    } else if let Some(due) = state.next_due {
+        lines.push(format!(
+            "next in {} s",
+            due.saturating_duration_since(now).as_secs()
+        ));
+    }
+    if let Some((at, outcome)) = state.last {
+        lines.push(format!(
+            "last: {outcome:?} {} s ago",
+            now.saturating_duration_since(at).as_secs()
+        ));
+    }
+    if let Some(reason) = state.blocker(now) {
+        lines.push(format!("held off: {reason}"));
+    }
+    lines.join("\n")
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "ancscanary",
+        "notification relay canary: ancscanary [on | off | every <minutes> | run]",
+        |args| {
+            match args {
+                [] => {}
+                ["on"] => settings::set(&CANARY, &true)?,
+                ["off"] => settings::set(&CANARY, &false)?,
+                ["every", minutes] => settings::set(
+                    &CANARY_MINUTES,
+                    &minutes
+                        .parse()
+                        .map_err(|_| anyhow!("not a number: {minutes}"))?,
+                )?,
+                ["run"] => {
+                    let mut state = STATE.lock().map_err(|_| anyhow!("canary state poisoned"))?;
+                    state.forced = true;
+                }
+                _ => bail!("usage: ancscanary [on | off | every <minutes> | run]"),
+            }
+            Ok(report())
+        },
+    );
+}
@@ -0,0 +1,311 @@
+use core::ffi::{c_int, c_void};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::{
+        ble_gatt_attr, ble_gatt_error, ble_gattc_read_by_uuid, ble_hs_mbuf_to_flat, ble_uuid16_t,
+        ble_uuid_t, BLE_HS_EDONE, BLE_UUID_TYPE_16,
+    },
+};
+use log::{info, warn};
+
+use crate::{
+    ble::link::{self, Phy},
+    settings,
+};
+
+mod record;
+
+pub use record::KnownClient;
+use record::{KNOWN_BUFFER_LEN, MAX_KNOWN_CLIENTS, MAX_NAME_LEN};
+
+const NVS_NAMESPACE: &str = "ancs";
+const NVS_KNOWN_KEY: &str = "known";
+
+static GAP_DEVICE_NAME_UUID: ble_uuid16_t = ble_uuid16_t {
+    u: ble_uuid_t {
+        type_: BLE_UUID_TYPE_16 as u8,
+    },
+    value: 0x2A00,
+};
+
+static SESSIONS: Mutex<Vec<ClientInfo>> = Mutex::new(Vec::new());
+static KNOWN: Mutex<Vec<KnownClient>> = Mutex::new(Vec::new());
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// A peer currently connected to the fake ANCS peripheral.
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub conn_handle: u16,
+    pub addr: String,
+    pub name: Option<String>,
+    pub encrypted: bool,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub phy: Option<Phy>,
+    pub interval_ms: Option<f32>,
+}
+
+impl ClientInfo {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.addr)
+    }
+}
+
+pub fn load_known() {
+    let loaded = match open_store() {
+        Some(store) => {
+            let mut buf = vec![0u8; KNOWN_BUFFER_LEN];
+            match store.get_str(NVS_KNOWN_KEY, &mut buf) {
+                Ok(Some(raw)) => raw.lines().filter_map(record::decode).collect(),
+                Ok(None) => Vec::new(),
+                Err(err) => {
+                    warn!("ANCS known clients unreadable: {err:?}");
+                    Vec::new()
+                }
+            }
+        }
+        None => Vec::new(),
+    };
+    info!("ANCS known clients loaded: {}", loaded.len());
+    if let Ok(mut known) = KNOWN.lock() {
+        *known = loaded;
+    }
+}
+
+pub fn on_connect(conn_handle: u16, addr: String) {
+    let now = unix_now();
+    let remembered = known_clients()
+        .into_iter()
+        .find(|client| client.addr == addr);
+    let info = ClientInfo {
+        conn_handle,
+        name: remembered.as_ref().and_then(|client| client.name.clone()),
+        first_seen: remembered.map(|client| client.first_seen).unwrap_or(now),
+        last_seen: now,
+        encrypted: false,
+        phy: link::read_phy(conn_handle),
+        interval_ms: link::interval_ms(conn_handle),
+        addr,
+    };
+    if let Ok(mut sessions) = SESSIONS.lock() {
+        sessions.retain(|session| session.conn_handle != conn_handle);
+        sessions.push(info.clone());
+    }
+    remember(&info);
+    GENERATION.fetch_add(1, Ordering::Release);
+    request_device_name(conn_handle);
+}
+
+/// Lists a session without persisting it; used by demo mode.
+pub fn insert_transient(info: ClientInfo) {
+    if let Ok(mut sessions) = SESSIONS.lock() {
+        sessions.retain(|session| session.conn_handle != info.conn_handle);
+        sessions.push(info);
+    }
+    GENERATION.fetch_add(1, Ordering::Release);
+}
+
+pub fn on_encrypted(conn_handle: u16) {
+    let needs_name = update_session(conn_handle, |session| {
+        session.encrypted = true;
+        session.last_seen = unix_now();
+        session.name.is_none()
+    })
+    .unwrap_or(false);
+    // Some peers only expose the GAP name on an encrypted link.
+    if needs_name {
+        request_device_name(conn_handle);
+    }
+}
+
+pub fn on_disconnect(conn_handle: u16) {
+    let removed = SESSIONS.lock().ok().and_then(|mut sessions| {
+        let index = sessions
+            .iter()
+            .position(|session| session.conn_handle == conn_handle)?;
+        Some(sessions.remove(index))
+    });
+    if let Some(mut info) = removed {
+        info.last_seen = unix_now();
+        remember(&info);
+        info!("ANCS client {} ({}) left", info.label(), info.addr);
+    }
+    GENERATION.fetch_add(1, Ordering::Release);
+}
+
+/// Re-reads PHY and connection interval for every session. Peers may change
+/// either at any time and esp32-nimble does not report it, so this is polled.
+pub fn refresh_links() {
+    let Ok(mut sessions) = SESSIONS.lock() else {
+        return;
+    };
+    let mut changed = false;
+    for session in sessions.iter_mut() {
+        let handle = session.conn_handle;
+        if let Some(phy) = link::read_phy(handle).filter(|phy| session.phy != Some(*phy)) {
+            info!(
+                "ANCS client {} ({}) now on {phy} PHY",
+                session.label(),
+                session.addr
+            );
+            session.phy = Some(phy);
+            changed = true;
+        }
+        if let Some(ms) = link::interval_ms(handle).filter(|ms| session.interval_ms != Some(*ms)) {
+            session.interval_ms = Some(ms);
+            changed = true;
+        }
+    }
+    if changed {
+        GENERATION.fetch_add(1, Ordering::Release);
+    }
+}
+
+pub fn connected() -> Vec<ClientInfo> {
+    SESSIONS
+        .lock()
+        .map(|sessions| sessions.clone())
+        .unwrap_or_default()
+}
+
+pub fn known_clients() -> Vec<KnownClient> {
+    KNOWN.lock().map(|known| known.clone()).unwrap_or_default()
+}
+
+pub fn label_for(conn_handle: u16) -> Option<String> {
+    SESSIONS.lock().ok().and_then(|sessions| {
+        sessions
+            .iter()
+            .find(|session| session.conn_handle == conn_handle)
+            .map(|session| session.label().to_string())
+    })
+}
+
+/// Bumped on every session change so the UI can skip redundant model rebuilds.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Acquire)
+}
+
+/// Reads the peer's GAP Device Name (0x2A00) using a client procedure on the
+/// peripheral-side connection. Failures only cost us the name.
+fn request_device_name(conn_handle: u16) {
+    let rc = unsafe {
+        ble_gattc_read_by_uuid(
+            conn_handle,
+            1,
+            0xFFFF,
+            &GAP_DEVICE_NAME_UUID.u,
+            Some(on_device_name_read),
+            core::ptr::null_mut(),
+        )
+    };
+    if rc != 0 {
+        warn!("ANCS GAP name read not started (conn={conn_handle} rc={rc})");
+    }
+}
+
+unsafe extern "C" fn on_device_name_read(
+    conn_handle: u16,
+    error: *const ble_gatt_error,
+    attr: *mut ble_gatt_attr,
+    _arg: *mut c_void,
+) -> c_int {
+    let status = if error.is_null() { 0 } else { (*error).status };
+    if status == 0 && !attr.is_null() {
+        let mut buf = [0u8; MAX_NAME_LEN];
+        let mut len: u16 = 0;
+        let rc = ble_hs_mbuf_to_flat(
+            (*attr).om,
+            buf.as_mut_ptr().cast(),
+            buf.len() as u16,
+            &mut len,
+        );
+        if rc == 0 {
+            let name = String::from_utf8_lossy(&buf[..len as usize]);
+            let name = record::cap_name(name.trim_end_matches('\0').trim()).to_string();
+            if !name.is_empty() {
+                set_name(conn_handle, name);
+            }
+        }
+    } else if status != BLE_HS_EDONE as u16 {
+        info!(
+            "ANCS peer refused GAP name read (conn={conn_handle} status={status}); using address"
+        );
+    }
+    0
+}
+
+fn set_name(conn_handle: u16, name: String) {
+    let updated = update_session(conn_handle, |session| {
+        session.name = Some(name.clone());
+        session.clone()
+    });
+    if let Some(info) = updated {
+        info!(
+            "ANCS client conn={} addr={} is \"{}\"",
+            conn_handle, info.addr, name
+        );
+        remember(&info);
+        GENERATION.fetch_add(1, Ordering::Release);
+    }
+}
+
+fn update_session<R>(conn_handle: u16, f: impl FnOnce(&mut ClientInfo) -> R) -> Option<R> {
+    let mut sessions = SESSIONS.lock().ok()?;
+    sessions
+        .iter_mut()
+        .find(|session| session.conn_handle == conn_handle)
+        .map(f)
+}
+
+fn remember(info: &ClientInfo) {
+    let Ok(mut known) = KNOWN.lock() else {
+        return;
+    };
+    match known.iter_mut().find(|client| client.addr == info.addr) {
+        Some(client) => {
+            client.last_seen = info.last_seen;
+            if info.name.is_some() {
+                client.name = info.name.clone();
+            }
+        }
+        None => known.push(KnownClient {
+            addr: info.addr.clone(),
+            name: info.name.clone(),
+            first_seen: info.first_seen,
+            last_seen: info.last_seen,
+        }),
+    }
+    known.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+    known.truncate(MAX_KNOWN_CLIENTS);
+
+    let encoded: String = known.iter().map(record::encode).collect();
+    drop(known);
+
+    crate::nvs::write(NVS_NAMESPACE, NVS_KNOWN_KEY, encoded);
+}
+
+fn open_store() -> Option<EspNvs<NvsDefault>> {
+    match settings::open_namespace(NVS_NAMESPACE) {
+        Ok(store) => Some(store),
+        Err(err) => {
+            warn!("ANCS client store unavailable: {err:?}");
+            None
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,57 @@
+//! The NVS record of known clients: one tab-separated line per client,
+//! read back in a single buffer sized for the longest possible record.
+
+pub const MAX_KNOWN_CLIENTS: usize = 8;
+/// Bytes of a stored name; longer names are cut on a char boundary.
+pub const MAX_NAME_LEN: usize = 64;
+/// `BLEAddress` in its `xx:xx:xx:xx:xx:xx` display form.
+const ADDR_LEN: usize = 17;
+/// `u64::MAX` in decimal.
+const MAX_SECS_LEN: usize = 20;
+/// Address, two timestamps and a name, three tabs and the newline.
+pub const MAX_LINE_LEN: usize = ADDR_LEN + 2 * MAX_SECS_LEN + MAX_NAME_LEN + 4;
+/// Every line, plus the NUL NVS stores after a string.
+pub const KNOWN_BUFFER_LEN: usize = MAX_KNOWN_CLIENTS * MAX_LINE_LEN + 1;
+
+/// A peer that has connected at least once, persisted in NVS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KnownClient {
+    pub addr: String,
+    pub name: Option<String>,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+pub fn encode(client: &KnownClient) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\n",
+        client.addr,
+        client.first_seen,
+        client.last_seen,
+        cap_name(client.name.as_deref().unwrap_or("")).replace(['\t', '\n'], " ")
+    )
+}
+
+pub fn decode(line: &str) -> Option<KnownClient> {
+    let mut fields = line.splitn(4, '\t');
+    let addr = fields.next()?.to_string();
+    let first_seen = fields.next()?.parse().ok()?;
+    let last_seen = fields.next()?.parse().ok()?;
+    let name = fields.next().filter(|name| !name.is_empty());
+    Some(KnownClient {
+        addr,
+        name: name.map(str::to_string),
+        first_seen,
+        last_seen,
+    })
+}
+
+/// `name` cut to at most [`MAX_NAME_LEN`] bytes without splitting a char.
+/// Lossy decoding can turn 64 bytes off the air into three times as many.
+pub fn cap_name(name: &str) -> &str {
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
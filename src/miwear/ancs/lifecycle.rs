@@ -0,0 +1,146 @@
+//! The parts of the fake ANCS service that come and go with `set_enabled`:
+//! its periodic jobs and the store's Notification Source sink. The GATT
+//! service and its NimBLE callbacks stay registered for the rest of the
+//! boot, since NimBLE cannot remove a service; they check `serving()`.
+//! The store keeps its entries across a stop, so a watch that reconnects
+//! after the next start gets the usual replay.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use esp32_nimble::{utilities::mutex::Mutex as NimbleMutex, BLECharacteristic};
+use log::{info, warn};
+
+use super::{
+    advertising::{self, AdvParams},
+    canary, nudge, pacing, pairing, recovery,
+    server::{AncsServer, Runtime},
+    store::{self, EventPayload, NotifySink},
+};
+use crate::periodic::{self, JobHandle};
+
+/// How often the wanted advertising preset is compared with the applied one.
+const ADVERTISING_CHECK: Duration = Duration::from_secs(1);
+/// Silent placeholder cadence, for watches that expect ANCS traffic.
+const PHANTOM_INTERVAL: Duration = Duration::from_secs(120);
+
+static RUNNING: Mutex<Option<AncsServer>> = Mutex::new(None);
+/// The sink of the last stopped server, for [`restart`].
+static STOPPED: Mutex<Option<Arc<dyn NotifySink>>> = Mutex::new(None);
+/// Advertising parameters the controller has; kept across a stop.
+static APPLIED: Mutex<Option<AdvParams>> = Mutex::new(None);
+
+/// The Notification Source characteristic as the store's sink.
+pub struct CharacteristicSink(pub Arc<NimbleMutex<BLECharacteristic>>);
+
+impl NotifySink for CharacteristicSink {
+    fn set_value(&self, payload: &EventPayload) {
+        self.0.lock().set_value(payload);
+    }
+
+    fn notify(&self, conn_handle: u16, payload: &EventPayload) -> Result<(), String> {
+        self.0
+            .lock()
+            .notify_with(payload, conn_handle)
+            .map_err(|err| format!("{err:?}"))
+    }
+
+    fn subscribed_count(&self) -> usize {
+        self.0.lock().subscribed_count()
+    }
+}
+
+/// The store, `pacing` and the periodic scheduler, as the server drives them.
+struct Firmware;
+
+impl Runtime for Firmware {
+    fn attach(&self, sink: Option<Arc<dyn NotifySink>>) {
+        match sink {
+            Some(sink) => store::attach(sink),
+            None => store::detach(),
+        }
+    }
+
+    fn start_jobs(&self, relaxed: bool) -> Vec<JobHandle> {
+        let mut jobs = vec![
+            periodic::register("ancs_advertising", ADVERTISING_CHECK, check_advertising),
+            pairing::start(),
+        ];
+        if !relaxed {
+            jobs.push(nudge::start());
+            jobs.push(canary::start());
+            jobs.push(recovery::start());
+            jobs.push(periodic::register("ancs_phantom", PHANTOM_INTERVAL, || {
+                if super::serving() {
+                    store::add(super::phantom_notification());
+                }
+            }));
+        }
+        jobs
+    }
+
+    fn cancel(&self, job: JobHandle) {
+        periodic::cancel(job);
+    }
+
+    fn clear_held(&self) {
+        pacing::clear();
+    }
+}
+
+/// First start, from service registration; `applied` is what the
+/// controller was just configured with.
+pub(super) fn start(sink: Arc<dyn NotifySink>, relaxed: bool, applied: AdvParams) {
+    if let Ok(mut slot) = APPLIED.lock() {
+        *slot = Some(applied);
+    }
+    let server = AncsServer::start(&Firmware, sink, relaxed);
+    if let Ok(mut running) = RUNNING.lock() {
+        if let Some(previous) = running.replace(server) {
+            previous.stop();
+        }
+    }
+}
+
+/// Runtime off. A no-op when nothing is running.
+pub(super) fn stop() {
+    let Some(server) = RUNNING.lock().ok().and_then(|mut running| running.take()) else {
+        return;
+    };
+    let sink = server.stop();
+    if let Ok(mut stopped) = STOPPED.lock() {
+        *stopped = Some(sink);
+    }
+    info!("Fake ANCS jobs stopped");
+}
+
+/// Runtime on after [`stop`], with the same sink.
+pub(super) fn restart() {
+    let Some(sink) = STOPPED.lock().ok().and_then(|mut stopped| stopped.take()) else {
+        return;
+    };
+    let server = AncsServer::start(&Firmware, sink, super::test_mode());
+    if let Ok(mut running) = RUNNING.lock() {
+        *running = Some(server);
+    }
+}
+
+fn check_advertising() {
+    let preset = advertising::wanted();
+    let params = preset.params();
+    let Ok(mut applied) = APPLIED.lock() else {
+        return;
+    };
+    if *applied != Some(params) {
+        match super::readvertise(&params) {
+            Ok(()) => {
+                info!("ANCS advertising: {} {params:?}", preset.label());
+                *applied = Some(params);
+            }
+            Err(err) => warn!("{err:#}"),
+        }
+    }
+    advertising::set_active(preset);
+}
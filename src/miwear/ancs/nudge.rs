@@ -0,0 +1,220 @@
+//! Security nudge for watches that subscribe to Notification Source on an
+//! unencrypted link and then never start pairing, which leaves them waiting
+//! on the "pending encryption" branch of the subscribe handler for good.
+//!
+//! [`FIRST_NUDGE`] after such a subscribe, a link that is still plain gets
+//! a peripheral Security Request; [`RETRY_NUDGE`] later it gets one more,
+//! and another [`RETRY_NUDGE`] after that it is written off as a peer that
+//! never encrypted. Peers listed in [`NO_NUDGE`] are left alone: at least
+//! one band model disconnects on a Security Request it did not expect.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use esp_idf_svc::sys::{ble_gap_security_initiate, BLE_HS_EALREADY};
+use log::{info, warn};
+
+use super::{pairing, sessions};
+use crate::{
+    ble::link,
+    periodic::{self, JobHandle},
+    settings::{self, SettingKey},
+};
+
+/// Comma-separated peer addresses never sent a Security Request.
+pub const NO_NUDGE: SettingKey<String> = SettingKey::new("ancs_nonudge", "");
+
+const FIRST_NUDGE: Duration = Duration::from_secs(2);
+const RETRY_NUDGE: Duration = Duration::from_secs(10);
+const NUDGES: u8 = 2;
+const CHECK: Duration = Duration::from_millis(500);
+
+struct Pending {
+    conn_handle: u16,
+    addr: String,
+    due: Instant,
+    nudges: u8,
+}
+
+static PENDING: Mutex<Vec<Pending>> = Mutex::new(Vec::new());
+/// Nudges sent per peer address since boot.
+static PEERS: Mutex<Vec<(String, u32)>> = Mutex::new(Vec::new());
+
+pub(super) fn start() -> JobHandle {
+    periodic::register("ancs_nudge", CHECK, check)
+}
+
+pub fn opted_out(addr: &str) -> bool {
+    settings::get(&NO_NUDGE)
+        .split(',')
+        .any(|entry| entry.trim().eq_ignore_ascii_case(addr))
+}
+
+/// Notification Source was subscribed on a link that is not encrypted.
+pub fn on_unencrypted_subscribe(conn_handle: u16, addr: String) {
+    if opted_out(&addr) {
+        info!("ANCS peer {addr} opted out of security nudges (conn={conn_handle})");
+        return;
+    }
+    let Ok(mut pending) = PENDING.lock() else {
+        return;
+    };
+    if pending.iter().any(|entry| entry.conn_handle == conn_handle) {
+        return;
+    }
+    pending.push(Pending {
+        conn_handle,
+        addr,
+        due: Instant::now() + FIRST_NUDGE,
+        nudges: 0,
+    });
+}
+
+/// The link encrypted or went away; nothing left to nudge.
+pub fn forget(conn_handle: u16) {
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.retain(|entry| entry.conn_handle != conn_handle);
+    }
+}
+
+/// Nudges sent per peer since boot, most first.
+pub fn peers() -> Vec<(String, u32)> {
+    let mut peers = PEERS.lock().map(|peers| peers.clone()).unwrap_or_default();
+    peers.sort_by(|a, b| b.1.cmp(&a.1));
+    peers
+}
+
+fn check() {
+    let now = Instant::now();
+    let due: Vec<(u16, String, u8)> = {
+        let Ok(mut pending) = PENDING.lock() else {
+            return;
+        };
+        let due = pending
+            .iter()
+            .filter(|entry| entry.due <= now)
+            .map(|entry| (entry.conn_handle, entry.addr.clone(), entry.nudges))
+            .collect();
+        pending.retain(|entry| entry.due > now);
+        due
+    };
+    for (conn_handle, addr, nudges) in due {
+        if link::encrypted(conn_handle) != Some(false) {
+            continue;
+        }
+        // A passkey or comparison on screen means pairing is under way.
+        let prompting = pairing::current_prompt().is_some_and(|prompt| {
+            prompt
+                .conn_handle
+                .map_or(true, |handle| handle == conn_handle)
+        });
+        if nudges == NUDGES && !prompting {
+            warn!(
+                "ANCS peer never encrypted: conn={conn_handle} addr={addr}, \
+                 {NUDGES} security requests unanswered"
+            );
+            crate::journal!("ANCS peer {addr} never encrypted after {NUDGES} security requests");
+            continue;
+        }
+        let nudges = if prompting {
+            nudges
+        } else {
+            match unsafe { ble_gap_security_initiate(conn_handle) } {
+                0 => {
+                    info!("ANCS security request sent: conn={conn_handle} addr={addr}");
+                    sessions::on_security_nudge(conn_handle);
+                    count(&addr);
+                    nudges + 1
+                }
+                // Pairing started meanwhile; give it the retry window.
+                rc if rc == BLE_HS_EALREADY as i32 => nudges,
+                rc => {
+                    warn!("ANCS security request failed: conn={conn_handle} rc={rc}");
+                    continue;
+                }
+            }
+        };
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.push(Pending {
+                conn_handle,
+                addr,
+                due: now + RETRY_NUDGE,
+                nudges,
+            });
+        }
+    }
+}
+
+/// One Security Request outside the schedule, for `recovery`. Peers that
+/// opted out are skipped; true when the request went out.
+pub fn request_now(conn_handle: u16, addr: &str) -> bool {
+    if opted_out(addr) {
+        return false;
+    }
+    match unsafe { ble_gap_security_initiate(conn_handle) } {
+        0 => {
+            info!("ANCS security request sent for recovery: conn={conn_handle} addr={addr}");
+            sessions::on_security_nudge(conn_handle);
+            count(addr);
+            true
+        }
+        rc => {
+            warn!("ANCS security request failed: conn={conn_handle} rc={rc}");
+            false
+        }
+    }
+}
+
+fn count(addr: &str) {
+    let Ok(mut peers) = PEERS.lock() else {
+        return;
+    };
+    match peers.iter_mut().find(|(peer, _)| peer == addr) {
+        Some((_, count)) => *count += 1,
+        None => peers.push((addr.to_string(), 1)),
+    }
+}
+
+fn set_opt_out(addr: &str, skip: bool) -> anyhow::Result<()> {
+    let mut list: Vec<String> = settings::get(&NO_NUDGE)
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty() && !entry.eq_ignore_ascii_case(addr))
+        .map(str::to_string)
+        .collect();
+    if skip {
+        list.push(addr.to_ascii_lowercase());
+    }
+    settings::set(&NO_NUDGE, &list.join(","))
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "ancsnudge",
+        "ANCS security nudges: ancsnudge [skip|allow <addr>]; skip opts a peer out",
+        |args| {
+            match args {
+                [] => {}
+                ["skip", addr] => set_opt_out(addr, true)?,
+                ["allow", addr] => set_opt_out(addr, false)?,
+                _ => bail!("usage: ancsnudge [skip|allow <addr>]"),
+            }
+            let opted_out = settings::get(&NO_NUDGE);
+            let mut lines = vec![format!(
+                "opted out: {}",
+                if opted_out.is_empty() {
+                    "none"
+                } else {
+                    &opted_out
+                }
+            )];
+            for (addr, nudges) in peers() {
+                lines.push(format!("{addr}: {nudges} nudges"));
+            }
+            Ok(lines.join("\n"))
+        },
+    );
+}
@@ -0,0 +1,461 @@
+//! Store-and-forward for notifications made while no watch is subscribed.
+//! Entries wait in order, on the `storage` partition when it is mounted and
+//! in NVS (fewer of them) otherwise, until an encrypted ANCS subscriber
+//! appears; they are then published oldest first, a few per second. An
+//! entry whose TTL runs out first is dropped and announced as
+//! [`SystemEvent::OutboxExpired`].
+//!
+//! An entry queued while the clock is set expires at a Unix time. One
+//! queued before that counts its TTL down on uptime and moves onto the
+//! clock once any time source sets it. Time spent powered off is not
+//! counted for such an entry, since nothing measured it.
+
+use std::{
+    fs,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::store::NewNotification;
+use crate::{
+    events::{self, SystemEvent},
+    periodic, settings, timesync,
+};
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+pub const MAX_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const MAX_ENTRIES: usize = 32;
+/// NVS strings top out near 4000 bytes; stay under with the framing.
+const MAX_NVS_ENTRIES: usize = 4;
+const NVS_BUDGET: usize = 3800;
+const NVS_NAMESPACE: &str = "outbox";
+const NVS_KEY: &str = "queue";
+const FILE: &str = "/storage/outbox.json";
+const FILE_TMP: &str = "/storage/outbox.json.tmp";
+const CHECK: Duration = Duration::from_secs(1);
+/// Entries published per check. The outbox lock is not held while they
+/// go out, and a full queue drains over a few checks instead of in one
+/// burst on the main thread.
+const DELIVER_PER_CHECK: usize = 4;
+/// How often uptime-counted TTLs are saved, so a reboot loses at most this.
+const SAVE_UPTIME_TTLS: Duration = Duration::from_secs(10 * 60);
+/// Finished entries kept for `GET /notify/queue`.
+const RECENT: usize = 8;
+
+static OUTBOX: Mutex<Outbox> = Mutex::new(Outbox::new());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Expiry {
+    /// Unix seconds.
+    At(i64),
+    /// Seconds left when last measured, counted on uptime from then.
+    Within(u64),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u32,
+    /// Unix seconds; `None` when the clock was not set.
+    pub queued_at: Option<i64>,
+    expiry: Expiry,
+    pub notification: NewNotification,
+    /// When `Expiry::Within` was measured; load time after a reboot.
+    #[serde(skip)]
+    measured: Option<Instant>,
+}
+
+impl Entry {
+    /// Seconds until it expires; `None` for an absolute expiry while the
+    /// clock is unset.
+    pub fn expires_in(&self, now: Option<i64>, instant: Instant) -> Option<i64> {
+        match self.expiry {
+            Expiry::At(at) => now.map(|now| at - now),
+            Expiry::Within(left) => {
+                let elapsed = self
+                    .measured
+                    .map_or(0, |measured| instant.duration_since(measured).as_secs());
+                Some(left as i64 - elapsed as i64)
+            }
+        }
+    }
+
+    /// Whether its TTL has run out; an absolute expiry cannot before the
+    /// clock is set.
+    fn expired(&self, now: Option<i64>, instant: Instant) -> bool {
+        self.expires_in(now, instant).is_some_and(|left| left <= 0)
+    }
+
+    /// The uptime count brought up to `instant`, or moved onto the clock.
+    fn settle(&mut self, now: Option<i64>, instant: Instant) {
+        let Expiry::Within(_) = self.expiry else {
+            return;
+        };
+        let left = self.expires_in(now, instant).unwrap_or(0).max(0);
+        self.expiry = match now {
+            Some(now) => Expiry::At(now + left),
+            None => Expiry::Within(left as u64),
+        };
+        self.measured = Some(instant);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fate {
+    /// Published with the given store UID.
+    Delivered {
+        uid: u32,
+    },
+    Expired,
+}
+
+impl Fate {
+    pub fn code(self) -> &'static str {
+        match self {
+            Fate::Delivered { .. } => "delivered",
+            Fate::Expired => "expired",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Finished {
+    pub id: u32,
+    pub app_id: String,
+    pub title: String,
+    pub fate: Fate,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    next_id: u32,
+    entries: Vec<Entry>,
+}
+
+struct Outbox {
+    next_id: u32,
+    entries: Vec<Entry>,
+    recent: Vec<Finished>,
+    saved_uptime_ttls: Option<Instant>,
+}
+
+impl Outbox {
+    const fn new() -> Self {
+        Self {
+            next_id: 1,
+            entries: Vec::new(),
+            recent: Vec::new(),
+            saved_uptime_ttls: None,
+        }
+    }
+
+    fn finish(&mut self, entry: Entry, fate: Fate) {
+        if self.recent.len() == RECENT {
+            self.recent.remove(0);
+        }
+        self.recent.push(Finished {
+            id: entry.id,
+            app_id: entry.notification.app_id,
+            title: entry.notification.title,
+            fate,
+        });
+    }
+
+    fn encode(&self) -> Result<String> {
+        let saved = Saved {
+            next_id: self.next_id,
+            entries: self.entries.clone(),
+        };
+        Ok(serde_json::to_string(&saved)?)
+    }
+}
+
+/// Starts the expiry and delivery job, then loads the saved queue. A queue
+/// that does not parse is logged and discarded; the job runs either way.
+pub fn start() -> Result<()> {
+    periodic::register("ancs_outbox", CHECK, check);
+    let Some(raw) = read_saved()? else {
+        return Ok(());
+    };
+    let saved: Saved = match serde_json::from_str(&raw) {
+        Ok(saved) => saved,
+        Err(err) => {
+            warn!("Outbox unreadable, discarding it: {err}");
+            discard_saved();
+            return Ok(());
+        }
+    };
+    let instant = Instant::now();
+    let mut outbox = OUTBOX.lock().map_err(|_| anyhow!("outbox locked"))?;
+    outbox.next_id = saved.next_id;
+    outbox.entries = saved.entries;
+    for entry in &mut outbox.entries {
+        entry.measured = Some(instant);
+    }
+    info!("Outbox loaded: {} pending", outbox.entries.len());
+    Ok(())
+}
+
+fn read_saved() -> Result<Option<String>> {
+    if on_storage() {
+        match fs::read_to_string(FILE) {
+            Ok(raw) => Ok(Some(raw)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).context("reading the outbox"),
+        }
+    } else {
+        let store = settings::open_namespace(NVS_NAMESPACE)?;
+        let mut buf = vec![0u8; NVS_BUDGET + 256];
+        Ok(store
+            .get_str(NVS_KEY, &mut buf)
+            .map_err(|err| anyhow!("reading the outbox: {err:?}"))?
+            .map(str::to_string))
+    }
+}
+
+fn discard_saved() {
+    if on_storage() {
+        if let Err(err) = fs::remove_file(FILE) {
+            warn!("Outbox not discarded: {err}");
+        }
+    } else {
+        crate::nvs::remove(NVS_NAMESPACE, NVS_KEY);
+    }
+}
+
+fn on_storage() -> bool {
+    #[cfg(feature = "storage")]
+    {
+        crate::storage::mounted()
+    }
+    #[cfg(not(feature = "storage"))]
+    {
+        false
+    }
+}
+
+/// Queues `new` for the next encrypted subscriber and returns its id.
+pub fn queue(new: NewNotification, ttl: Duration) -> Result<u32> {
+    if ttl.is_zero() || ttl > MAX_TTL {
+        bail!("ttl is 1 s to {} s", MAX_TTL.as_secs());
+    }
+    let now = timesync::unix_now();
+    let instant = Instant::now();
+    let mut outbox = OUTBOX.lock().map_err(|_| anyhow!("outbox locked"))?;
+    let limit = if on_storage() {
+        MAX_ENTRIES
+    } else {
+        MAX_NVS_ENTRIES
+    };
+    if outbox.entries.len() >= limit {
+        bail!("outbox is full ({limit} entries)");
+    }
+    let id = outbox.next_id;
+    outbox.entries.push(Entry {
+        id,
+        queued_at: now,
+        expiry: match now {
+            Some(now) => Expiry::At(now + ttl.as_secs() as i64),
+            None => Expiry::Within(ttl.as_secs()),
+        },
+        notification: new,
+        measured: Some(instant),
+    });
+    outbox.next_id = id.wrapping_add(1).max(1);
+    if let Err(err) = save(&mut outbox, instant) {
+        outbox.entries.pop();
+        outbox.next_id = id;
+        return Err(err);
+    }
+    info!("Outbox: queued #{id}");
+    Ok(id)
+}
+
+pub fn pending() -> Vec<Entry> {
+    OUTBOX
+        .lock()
+        .map(|outbox| outbox.entries.clone())
+        .unwrap_or_default()
+}
+
+/// Delivered and expired entries, oldest first.
+pub fn recent() -> Vec<Finished> {
+    OUTBOX
+        .lock()
+        .map(|outbox| outbox.recent.clone())
+        .unwrap_or_default()
+}
+
+/// Drops a pending entry without delivering it.
+pub fn remove(id: u32) -> Result<()> {
+    let mut outbox = OUTBOX.lock().map_err(|_| anyhow!("outbox locked"))?;
+    let before = outbox.entries.len();
+    outbox.entries.retain(|entry| entry.id != id);
+    if outbox.entries.len() == before {
+        bail!("no outbox entry #{id}");
+    }
+    save(&mut outbox, Instant::now())
+}
+
+fn check() {
+    let now = timesync::unix_now();
+    let instant = Instant::now();
+    let batch = {
+        let Ok(mut outbox) = OUTBOX.lock() else {
+            return;
+        };
+        if outbox.entries.is_empty() {
+            return;
+        }
+        let dirty = expire(&mut outbox, now, instant);
+        let count = if deliverable() {
+            outbox.entries.len().min(DELIVER_PER_CHECK)
+        } else {
+            0
+        };
+        if count == 0 {
+            save_if_due(&mut outbox, dirty, instant);
+            return;
+        }
+        outbox.entries.drain(..count).collect::<Vec<_>>()
+    };
+    let delivered: Vec<(Entry, u32)> = batch
+        .into_iter()
+        .map(|entry| {
+            let published = super::publish_notification(entry.notification.clone());
+            info!("Outbox: #{} delivered as uid {}", entry.id, published.uid);
+            (entry, published.uid)
+        })
+        .collect();
+    let Ok(mut outbox) = OUTBOX.lock() else {
+        return;
+    };
+    for (entry, uid) in delivered {
+        outbox.finish(entry, Fate::Delivered { uid });
+    }
+    save_if_due(&mut outbox, true, instant);
+}
+
+/// Drops and announces the entries whose TTL ran out and moves uptime TTLs
+/// onto the clock once it is set; `true` when anything changed.
+fn expire(outbox: &mut Outbox, now: Option<i64>, instant: Instant) -> bool {
+    let mut dirty = false;
+    let mut expired = Vec::new();
+    let mut index = 0;
+    while index < outbox.entries.len() {
+        let entry = &mut outbox.entries[index];
+        if entry.expired(now, instant) {
+            expired.push(outbox.entries.remove(index));
+            dirty = true;
+            continue;
+        }
+        if now.is_some() && matches!(entry.expiry, Expiry::Within(_)) {
+            entry.settle(now, instant);
+            dirty = true;
+        }
+        index += 1;
+    }
+    for entry in expired {
+        info!("Outbox: #{} expired", entry.id);
+        events::publish(SystemEvent::OutboxExpired {
+            id: entry.id,
+            app_id: entry.notification.app_id.clone(),
+            title: entry.notification.title.clone(),
+        });
+        outbox.finish(entry, Fate::Expired);
+    }
+    dirty
+}
+
+/// Saves after a change, and now and then while uptime TTLs count down.
+fn save_if_due(outbox: &mut Outbox, dirty: bool, instant: Instant) {
+    let uptime_ttls = outbox
+        .entries
+        .iter()
+        .any(|entry| matches!(entry.expiry, Expiry::Within(_)));
+    let stale = outbox.saved_uptime_ttls.map_or(true, |saved| {
+        instant.duration_since(saved) >= SAVE_UPTIME_TTLS
+    });
+    if dirty || (uptime_ttls && stale) {
+        if let Err(err) = save(outbox, instant) {
+            warn!("{err:#}");
+        }
+    }
+}
+
+/// Delivery waits for the runtime to serve and an encrypted subscriber, and
+/// never runs in test mode, whose traffic must stay deterministic.
+fn deliverable() -> bool {
+    super::serving() && !super::test_mode() && super::encrypted_subscriber()
+}
+
+/// Writes the queue, uptime TTLs measured up to `instant`.
+fn save(outbox: &mut Outbox, instant: Instant) -> Result<()> {
+    for entry in &mut outbox.entries {
+        entry.settle(None, instant);
+    }
+    outbox.saved_uptime_ttls = Some(instant);
+    let encoded = outbox.encode()?;
+    if on_storage() {
+        // Replace by rename so a reset mid-write keeps the previous queue.
+        fs::write(FILE_TMP, &encoded).context("writing the outbox")?;
+        fs::rename(FILE_TMP, FILE).context("writing the outbox")?;
+    } else {
+        if encoded.len() > NVS_BUDGET {
+            bail!("outbox is full ({NVS_BUDGET} bytes without storage)");
+        }
+        crate::nvs::write(NVS_NAMESPACE, NVS_KEY, encoded);
+    }
+    Ok(())
+}
+
+pub fn register_commands() {
+    const USAGE: &str = "usage: outbox [del <id>]";
+    crate::console::register(
+        "outbox",
+        "notifications waiting for a watch: outbox [del <id>]",
+        |args| match args {
+            [] => {
+                let now = timesync::unix_now();
+                let instant = Instant::now();
+                let mut lines: Vec<String> = pending()
+                    .iter()
+                    .map(|entry| {
+                        let left = entry.expires_in(now, instant).map_or_else(
+                            || "expiry unknown until the clock is set".to_string(),
+                            |left| format!("expires in {left} s"),
+                        );
+                        format!(
+                            "#{} {} \"{}\", {left}",
+                            entry.id, entry.notification.app_id, entry.notification.title
+                        )
+                    })
+                    .collect();
+                lines.extend(recent().iter().map(|finished| {
+                    format!(
+                        "#{} {} \"{}\", {}",
+                        finished.id,
+                        finished.app_id,
+                        finished.title,
+                        finished.fate.code()
+                    )
+                }));
+                Ok(if lines.is_empty() {
+                    "outbox empty".to_string()
+                } else {
+                    lines.join("\n")
+                })
+            }
+            ["del", arg] => {
+                let id = arg.parse::<u32>().map_err(|_| anyhow!(USAGE))?;
+                remove(id)?;
+                Ok(format!("removed #{id}"))
+            }
+            _ => bail!(USAGE),
+        },
+    );
+}
@@ -0,0 +1,106 @@
+//! Holds Notification Added events back from a watch that is still taking
+//! in a Data Source response. Some firmwares drop a Notification Source
+//! event that lands mid-reassembly, which at a small MTU loses
+//! notifications in bursts.
+//!
+//! NimBLE reports a notification as sent once the controller has it, not
+//! once it is on air, so the fragments in flight are estimated from the
+//! connection interval: one fragment per connection event, the worst case
+//! for a small-MTU link. A held event waits for that estimate to drain, but
+//! never longer than `source::MAX_HOLD`. Events behind a held one queue
+//! behind it so a watch never sees them out of order. The queues themselves
+//! are `source::Links`; this module owns them and the timer.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use tokio::sync::Notify;
+
+use super::{
+    protocol, sessions,
+    source::{Delivery, EventPayload, Links},
+    store,
+};
+
+static LINKS: Mutex<Links> = Mutex::new(Links::new());
+static WAKE: Notify = Notify::const_new();
+
+pub fn on_subscribe(conn_handle: u16, mtu: u16) {
+    if let Ok(mut links) = LINKS.lock() {
+        links.subscribe(conn_handle, mtu);
+    }
+    sessions::on_mtu(conn_handle, mtu);
+}
+
+/// Unsubscribe or disconnect; held events are dropped with the link.
+pub fn forget(conn_handle: u16) {
+    if let Ok(mut links) = LINKS.lock() {
+        links.forget(conn_handle);
+    }
+}
+
+/// Service stop: every link and its held events go; `run` idles until
+/// the next subscribe.
+pub fn clear() {
+    if let Ok(mut links) = LINKS.lock() {
+        links.clear();
+    }
+    WAKE.notify_one();
+}
+
+/// A Data Source response of `fragments` notifications was just handed to
+/// NimBLE. `mtu` and `interval` are read fresh from the link each time,
+/// which also picks up an MTU exchange after subscribe.
+pub fn on_response(conn_handle: u16, mtu: u16, interval: Duration, fragments: usize) {
+    sessions::on_mtu(conn_handle, mtu);
+    let previous = LINKS.lock().ok().and_then(|mut links| {
+        links.on_response(conn_handle, mtu, interval, fragments, Instant::now())
+    });
+    if let Some(previous) = previous {
+        info!(
+            "ANCS conn {conn_handle}: MTU {previous} -> {mtu}, fragments of {} bytes",
+            protocol::fragment_len(mtu)
+        );
+    }
+}
+
+/// The sends `events` turn into right now; the rest are held.
+pub fn route(events: &[EventPayload]) -> Vec<Delivery> {
+    let Ok(mut links) = LINKS.lock() else {
+        return Vec::new();
+    };
+    let (sends, held_any) = links.route(events, Instant::now());
+    drop(links);
+    if held_any {
+        WAKE.notify_one();
+    }
+    sends
+}
+
+/// Whether any link still has events waiting.
+pub fn holding() -> bool {
+    LINKS.lock().is_ok_and(|links| links.holding())
+}
+
+/// Spawn once on the main `LocalSet`; releases held events on time.
+pub async fn run() {
+    loop {
+        match LINKS.lock().ok().and_then(|links| links.next_release()) {
+            Some(at) => {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(at.into()) => {}
+                    _ = WAKE.notified() => continue,
+                }
+                let due = LINKS
+                    .lock()
+                    .map(|mut links| links.due(Instant::now()))
+                    .unwrap_or_default();
+                store::deliver(&due);
+            }
+            None => WAKE.notified().await,
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use esp32_nimble::{
+    enums::{AuthReq, SecurityIOCap},
+    BLEDevice,
+};
+use esp_idf_svc::sys::{ble_gap_conn_desc, ble_gap_conn_find, ble_gap_unpair, esp_random};
+use log::{info, warn};
+
+use crate::{
+    periodic::{self, JobHandle},
+    settings::{self, SettingKey},
+};
+
+pub const IO_CAPABILITY: SettingKey<IoCapability> = SettingKey::new("ble_iocap", "none");
+
+pub const PROMPT_TIMEOUT: Duration = Duration::from_secs(30);
+const CHECK: Duration = Duration::from_millis(500);
+
+static NEXT_PROMPT_ID: AtomicU32 = AtomicU32::new(1);
+static PROMPT: Mutex<Option<PairingPrompt>> = Mutex::new(None);
+static HELD: Mutex<Option<Held>> = Mutex::new(None);
+static COMPARISON_SEEN: AtomicBool = AtomicBool::new(false);
+/// Set by an IO capability change the stack has not applied yet.
+static CAPABILITY_PENDING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoCapability {
+    /// Just Works; no MITM protection.
+    NoInputNoOutput,
+    /// We show a passkey the peer has to type in.
+    DisplayOnly,
+    /// Numeric comparison when the peer supports it.
+    DisplayYesNo,
+}
+
+impl IoCapability {
+    pub fn to_nimble(self) -> SecurityIOCap {
+        match self {
+            IoCapability::NoInputNoOutput => SecurityIOCap::NoInputNoOutput,
+            IoCapability::DisplayOnly => SecurityIOCap::DisplayOnly,
+            IoCapability::DisplayYesNo => SecurityIOCap::DisplayYesNo,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            IoCapability::NoInputNoOutput => IoCapability::DisplayOnly,
+            IoCapability::DisplayOnly => IoCapability::DisplayYesNo,
+            IoCapability::DisplayYesNo => IoCapability::NoInputNoOutput,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            IoCapability::NoInputNoOutput => "Just Works",
+            IoCapability::DisplayOnly => "Passkey",
+            IoCapability::DisplayYesNo => "Compare",
+        }
+    }
+}
+
+impl fmt::Display for IoCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IoCapability::NoInputNoOutput => "none",
+            IoCapability::DisplayOnly => "display",
+            IoCapability::DisplayYesNo => "yesno",
+        })
+    }
+}
+
+impl FromStr for IoCapability {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(IoCapability::NoInputNoOutput),
+            "display" => Ok(IoCapability::DisplayOnly),
+            "yesno" => Ok(IoCapability::DisplayYesNo),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Show the code until the peer finishes pairing.
+    Display,
+    /// Show the code and wait for Accept/Reject.
+    Confirm,
+}
+
+#[derive(Clone, Debug)]
+pub struct PairingPrompt {
+    pub id: u32,
+    pub kind: PromptKind,
+    pub conn_handle: Option<u16>,
+    pub passkey: u32,
+    pub deadline: Instant,
+}
+
+/// A numeric comparison the user has not answered yet. esp32-nimble injects
+/// whatever `on_confirm_pin` returns as soon as it returns, so the SM step is
+/// accepted straight away and the link is held here instead: nothing is
+/// served on it until the user accepts, and a reject or timeout unpairs it.
+struct Held {
+    id: u32,
+    passkey: u32,
+    deadline: Instant,
+    conn_handle: Option<u16>,
+    /// The user answered before the link finished encrypting.
+    answer: Option<bool>,
+    release: Option<Box<dyn FnOnce() + Send>>,
+}
+
+pub(super) fn start() -> JobHandle {
+    periodic::register("ancs_pairing", CHECK, expire)
+}
+
+pub fn configured() -> IoCapability {
+    settings::get(&IO_CAPABILITY)
+}
+
+/// The SM flags for `capability`: MITM protection needs a display.
+pub fn auth_req(capability: IoCapability) -> AuthReq {
+    let mut auth = AuthReq::Bond | AuthReq::Sc;
+    if capability != IoCapability::NoInputNoOutput {
+        auth |= AuthReq::Mitm;
+    }
+    auth
+}
+
+/// Picks a fresh random passkey for DisplayOnly pairing.
+pub fn rotate_passkey(ble: &mut BLEDevice) -> u32 {
+    let passkey = unsafe { esp_random() } % 1_000_000;
+    ble.security().set_passkey(passkey);
+    passkey
+}
+
+/// Persists a new IO capability for [`apply_pending`] to hand the stack.
+pub fn set_io_capability(capability: IoCapability) -> Result<()> {
+    if capability == configured() {
+        return Ok(());
+    }
+    settings::set(&IO_CAPABILITY, &capability)?;
+    CAPABILITY_PENDING.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Applies a changed IO capability through the stack's own handle. Existing
+/// bonds were negotiated with the old association model, so they are
+/// cleared.
+pub fn apply_pending(ble: &mut BLEDevice) {
+    if !CAPABILITY_PENDING.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let capability = configured();
+    warn!(
+        "BLE IO capability changed to {}; clearing all bonds, peers must pair again",
+        capability.label()
+    );
+    if let Err(err) = ble.delete_all_bonds() {
+        warn!("Failed to clear bonds after IO capability change: {err:?}");
+    }
+    ble.security()
+        .set_auth(auth_req(capability))
+        .set_io_cap(capability.to_nimble());
+    super::advertising::open_pairing_window();
+}
+
+/// Shows the DisplayOnly passkey for an unbonded peer; cleared when the link
+/// is authenticated, drops, or the prompt times out.
+pub fn show_passkey(conn_handle: u16, passkey: u32) {
+    publish(PromptKind::Display, Some(conn_handle), passkey);
+}
+
+/// NimBLE numeric-comparison callback. Shows the code and returns at once so
+/// the host task keeps running; the user's answer is applied to the link by
+/// [`defer_until_confirmed`] and [`respond`].
+pub fn confirm(passkey: u32) -> bool {
+    COMPARISON_SEEN.store(true, Ordering::Relaxed);
+    let id = publish(PromptKind::Confirm, None, passkey);
+    if let Ok(mut held) = HELD.lock() {
+        *held = Some(Held {
+            id,
+            passkey,
+            deadline: Instant::now() + PROMPT_TIMEOUT,
+            conn_handle: None,
+            answer: None,
+            release: None,
+        });
+    }
+    true
+}
+
+/// Runs `release` once the link may be served: right away unless a
+/// comparison on it is still unanswered, in which case it waits for
+/// Accept. A reject, or no answer within `PROMPT_TIMEOUT`, unpairs the peer.
+pub fn defer_until_confirmed(conn_handle: u16, release: impl FnOnce() + Send + 'static) {
+    let Ok(mut slot) = HELD.lock() else {
+        release();
+        return;
+    };
+    let Some(held) = slot.as_mut().filter(|held| {
+        held.conn_handle
+            .map_or(true, |handle| handle == conn_handle)
+    }) else {
+        drop(slot);
+        release();
+        return;
+    };
+    held.conn_handle = Some(conn_handle);
+    match held.answer {
+        Some(accepted) => {
+            *slot = None;
+            drop(slot);
+            settle(conn_handle, accepted, Box::new(release));
+        }
+        None => {
+            held.release = Some(Box::new(release));
+            drop(slot);
+            if let Ok(mut prompt) = PROMPT.lock() {
+                if let Some(prompt) = prompt.as_mut() {
+                    prompt.conn_handle.get_or_insert(conn_handle);
+                }
+            }
+        }
+    }
+}
+
+/// Whether `conn_handle` is encrypted but still waiting on the user.
+pub fn awaiting_confirmation(conn_handle: u16) -> bool {
+    HELD.lock().is_ok_and(|held| {
+        held.as_ref()
+            .is_some_and(|held| held.conn_handle == Some(conn_handle) && held.answer.is_none())
+    })
+}
+
+pub fn respond(id: u32, accepted: bool) {
+    let settled = HELD.lock().ok().and_then(|mut slot| {
+        let held = slot.as_mut().filter(|held| held.id == id)?;
+        match (held.conn_handle, held.release.is_some()) {
+            (Some(_), true) => slot.take(),
+            _ => {
+                held.answer = Some(accepted);
+                None
+            }
+        }
+    });
+    if let Some(held) = settled {
+        info!(
+            "Pairing comparison {:06} {}",
+            held.passkey,
+            if accepted { "accepted" } else { "rejected" }
+        );
+        if let (Some(conn_handle), Some(release)) = (held.conn_handle, held.release) {
+            settle(conn_handle, accepted, release);
+        }
+    }
+    dismiss(id);
+}
+
+pub fn current_prompt() -> Option<PairingPrompt> {
+    let mut slot = PROMPT.lock().ok()?;
+    if slot
+        .as_ref()
+        .is_some_and(|prompt| Instant::now() >= prompt.deadline)
+    {
+        *slot = None;
+    }
+    slot.clone()
+}
+
+/// Encryption finished; a comparison prompt stays up until it is answered.
+pub fn on_link_finished(conn_handle: u16, status: Result<(), String>) {
+    let failed = status.is_err();
+    let compared = COMPARISON_SEEN.swap(false, Ordering::Relaxed);
+    let model = match (configured(), compared) {
+        (_, true) => "numeric comparison",
+        (IoCapability::DisplayOnly, false) | (IoCapability::DisplayYesNo, false) => {
+            "passkey entry or Just Works (peer-limited)"
+        }
+        (IoCapability::NoInputNoOutput, false) => "Just Works",
+    };
+    info!(
+        "Pairing conn={} iocap={} association={} result={:?}",
+        conn_handle,
+        configured().label(),
+        model,
+        status
+    );
+
+    clear_for(conn_handle, failed);
+}
+
+/// Drops any prompt left over for a link that went away mid-pairing.
+pub fn on_link_closed(conn_handle: u16) {
+    clear_for(conn_handle, true);
+}
+
+/// Clears the prompt for `conn_handle`; comparisons only when `all`.
+fn clear_for(conn_handle: u16, all: bool) {
+    let matches = |handle: Option<u16>| handle.map_or(true, |handle| handle == conn_handle);
+    if all {
+        if let Ok(mut held) = HELD.lock() {
+            if held.as_ref().is_some_and(|held| matches(held.conn_handle)) {
+                *held = None;
+            }
+        }
+    }
+    if let Ok(mut slot) = PROMPT.lock() {
+        if slot.as_ref().is_some_and(|prompt| {
+            matches(prompt.conn_handle) && (all || prompt.kind == PromptKind::Display)
+        }) {
+            *slot = None;
+        }
+    }
+}
+
+/// Rejects a comparison nobody answered in time.
+fn expire() {
+    let expired = HELD.lock().ok().and_then(|mut slot| {
+        let held = slot.as_mut()?;
+        if held.answer.is_some() || Instant::now() < held.deadline {
+            return None;
+        }
+        warn!(
+            "Pairing comparison {:06} timed out, rejecting",
+            held.passkey
+        );
+        match held.conn_handle {
+            Some(_) => slot.take(),
+            // Still encrypting; reject once it gets there.
+            None => {
+                held.answer = Some(false);
+                None
+            }
+        }
+    });
+    if let Some(Held {
+        id,
+        conn_handle: Some(conn_handle),
+        release: Some(release),
+        ..
+    }) = expired
+    {
+        settle(conn_handle, false, release);
+        dismiss(id);
+    }
+}
+
+fn settle(conn_handle: u16, accepted: bool, release: Box<dyn FnOnce() + Send>) {
+    if accepted {
+        release();
+        return;
+    }
+    let mut desc = unsafe { core::mem::zeroed::<ble_gap_conn_desc>() };
+    if unsafe { ble_gap_conn_find(conn_handle, &mut desc) } != 0 {
+        return;
+    }
+    // Deletes the bond and terminates the link.
+    let rc = unsafe { ble_gap_unpair(&desc.peer_id_addr) };
+    if rc != 0 {
+        warn!("Failed to unpair rejected peer (conn={conn_handle}, rc={rc})");
+    }
+}
+
+fn publish(kind: PromptKind, conn_handle: Option<u16>, passkey: u32) -> u32 {
+    let id = NEXT_PROMPT_ID.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut slot) = PROMPT.lock() {
+        *slot = Some(PairingPrompt {
+            id,
+            kind,
+            conn_handle,
+            passkey,
+            deadline: Instant::now() + PROMPT_TIMEOUT,
+        });
+    }
+    id
+}
+
+fn dismiss(id: u32) {
+    if let Ok(mut slot) = PROMPT.lock() {
+        if slot.as_ref().is_some_and(|prompt| prompt.id == id) {
+            *slot = None;
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Byte-level ANCS Control Point behaviour, kept free of NimBLE and store
+//! state so it can be checked against captured watch traffic off-device.
+//!
+//! Contract (version [`CONTRACT_VERSION`]); bump it with any change to the
+//! bytes below:
+//! - Only the commands in [`Command`] are served. An empty write, an unknown
+//!   command or one that ends mid-field is refused with the ANCS ATT error
+//!   from [`ParseError::att_error`] and gets no Data Source response.
+//! - Get Notification Attributes (0x00) echoes the command and UID, then one
+//!   `id, u16 LE length, value` tuple per requested attribute. Title,
+//!   subtitle and message honour the requested max length; an empty
+//!   attribute list answers with the app identifier alone. Action labels
+//!   (6, 7) come from the entry when it has them, else the placeholder's.
+//! - Unknown UIDs are answered from the phantom placeholder, never refused,
+//!   because watches stall on a missing response.
+//! - Get App Attributes (0x01) echoes the NUL-terminated app identifier and
+//!   answers the display name, cut on a UTF-8 boundary. The spec gives app
+//!   attributes no max length; one is read when two bytes follow the ID.
+//! - Perform Notification Action (0x02) is acknowledged with the UID and
+//!   action id; action ids other than positive (0) and negative (1) are
+//!   refused.
+//! - No response exceeds [`MAX_RESPONSE`]; the value that would cross it is
+//!   cut and later attributes are left out.
+//! - A response goes out as consecutive Data Source notifications of
+//!   [`fragment_len`] bytes, the last one shorter, to the requesting
+//!   connection only. The watch reassembles them as the ANCS spec describes.
+
+use std::fmt;
+
+pub const CONTRACT_VERSION: u32 = 4;
+
+/// NimBLE's attribute value limit, which the Data Source value must fit.
+pub const MAX_RESPONSE: usize = 512;
+
+/// ATT Handle Value Notification opcode + handle.
+const ATT_NOTIFY_OVERHEAD: usize = 3;
+
+/// ANCS ATT error codes.
+pub const UNKNOWN_COMMAND: u8 = 0xA0;
+pub const INVALID_COMMAND: u8 = 0xA1;
+pub const INVALID_PARAMETER: u8 = 0xA2;
+
+pub const DUMMY_APP_IDENTIFIER: &str = "com.astrobox.ghost";
+pub const DUMMY_MESSAGE_TITLE: &str = "Phantom Alert";
+pub const DUMMY_MESSAGE_SUBTITLE: &str = "Faint Signal";
+pub const DUMMY_MESSAGE_BODY: &str = "Spectral notification with no real content.";
+const DUMMY_DATE: &str = "19700101T000000";
+
+/// What a notification attributes response can reveal about one entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NotificationContent {
+    pub app_id: String,
+    pub title: String,
+    pub subtitle: String,
+    pub message: String,
+    pub date: String,
+    pub positive_label: Option<String>,
+    pub negative_label: Option<String>,
+}
+
+/// Where responses get their content; the firmware reads the live store,
+/// host checks supply fixed data.
+pub trait Lookup {
+    fn notification(&self, uid: u32) -> Option<NotificationContent>;
+    /// Display name for `app_id`, or the identifier itself when unknown.
+    fn app_name(&self, app_id: &[u8]) -> Vec<u8>;
+}
+
+/// The Control Point commands this service answers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    GetNotificationAttributes,
+    GetAppAttributes,
+    PerformNotificationAction,
+}
+
+impl Command {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x00 => Some(Command::GetNotificationAttributes),
+            0x01 => Some(Command::GetAppAttributes),
+            0x02 => Some(Command::PerformNotificationAction),
+            _ => None,
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        match self {
+            Command::GetNotificationAttributes => 0x00,
+            Command::GetAppAttributes => 0x01,
+            Command::PerformNotificationAction => 0x02,
+        }
+    }
+}
+
+/// One requested attribute; `max_len` 0 means no limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttributeRequest {
+    pub id: u8,
+    pub max_len: u16,
+}
+
+/// A well-formed Control Point write.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request<'a> {
+    NotificationAttributes {
+        uid: u32,
+        attributes: Vec<AttributeRequest>,
+    },
+    AppAttributes {
+        app_id: &'a [u8],
+        attributes: Vec<AttributeRequest>,
+    },
+    PerformAction {
+        uid: u32,
+        action: u8,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    UnknownCommand(u8),
+    /// The write ended inside `field`, which started at byte `at`.
+    Truncated {
+        field: &'static str,
+        at: usize,
+    },
+    /// The app identifier has no NUL terminator.
+    UnterminatedAppId,
+    UnknownAction(u8),
+}
+
+impl ParseError {
+    /// The error the spec has the Control Point write fail with.
+    pub fn att_error(self) -> u8 {
+        match self {
+            ParseError::UnknownCommand(_) => UNKNOWN_COMMAND,
+            ParseError::UnknownAction(_) => INVALID_PARAMETER,
+            ParseError::Empty | ParseError::Truncated { .. } | ParseError::UnterminatedAppId => {
+                INVALID_COMMAND
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => f.write_str("empty write"),
+            ParseError::UnknownCommand(command) => write!(f, "unknown command {command:#04x}"),
+            ParseError::Truncated { field, at } => write!(f, "{field} cut short at byte {at}"),
+            ParseError::UnterminatedAppId => f.write_str("app identifier without NUL"),
+            ParseError::UnknownAction(action) => write!(f, "unknown action {action}"),
+        }
+    }
+}
+
+/// Bounds-checked cursor over a write; every read either fits or names the
+/// field it ran out in.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, at: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.at
+    }
+
+    fn take(&mut self, len: usize, field: &'static str) -> Result<&'a [u8], ParseError> {
+        let rest = &self.bytes[self.at..];
+        let taken = rest
+            .get(..len)
+            .ok_or(ParseError::Truncated { field, at: self.at })?;
+        self.at += len;
+        Ok(taken)
+    }
+
+    fn u8(&mut self, field: &'static str) -> Result<u8, ParseError> {
+        Ok(self.take(1, field)?[0])
+    }
+
+    fn u16_le(&mut self, field: &'static str) -> Result<u16, ParseError> {
+        let bytes = self.take(2, field)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32_le(&mut self, field: &'static str) -> Result<u32, ParseError> {
+        let bytes = self.take(4, field)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Bytes up to the next NUL, consuming the NUL too.
+    fn until_nul(&mut self) -> Option<&'a [u8]> {
+        let rest = &self.bytes[self.at..];
+        let end = rest.iter().position(|&byte| byte == 0)?;
+        self.at += end + 1;
+        Some(&rest[..end])
+    }
+}
+
+pub fn parse(request: &[u8]) -> Result<Request<'_>, ParseError> {
+    let mut reader = Reader::new(request);
+    let command = reader.u8("command").map_err(|_| ParseError::Empty)?;
+    match Command::from_byte(command).ok_or(ParseError::UnknownCommand(command))? {
+        Command::GetNotificationAttributes => {
+            let uid = reader.u32_le("notification uid")?;
+            let mut attributes = Vec::new();
+            while reader.remaining() > 0 {
+                let id = reader.u8("attribute id")?;
+                let max_len = if attribute_requires_len(id) {
+                    reader.u16_le("attribute max length")?
+                } else {
+                    0
+                };
+                attributes.push(AttributeRequest { id, max_len });
+            }
+            Ok(Request::NotificationAttributes { uid, attributes })
+        }
+        Command::GetAppAttributes => {
+            let app_id = reader.until_nul().ok_or(ParseError::UnterminatedAppId)?;
+            let mut attributes = Vec::new();
+            while reader.remaining() > 0 {
+                let id = reader.u8("attribute id")?;
+                let max_len = if reader.remaining() >= 2 {
+                    reader.u16_le("attribute max length")?
+                } else {
+                    0
+                };
+                attributes.push(AttributeRequest { id, max_len });
+            }
+            Ok(Request::AppAttributes { app_id, attributes })
+        }
+        Command::PerformNotificationAction => {
+            let uid = reader.u32_le("notification uid")?;
+            let action = reader.u8("action id")?;
+            if action > 1 {
+                return Err(ParseError::UnknownAction(action));
+            }
+            Ok(Request::PerformAction { uid, action })
+        }
+    }
+}
+
+/// Data Source bytes for a parsed Control Point write.
+pub fn respond(request: &Request<'_>, lookup: &impl Lookup) -> Vec<u8> {
+    match request {
+        Request::NotificationAttributes { uid, attributes } => {
+            build_notification_attributes_response(*uid, attributes, lookup)
+        }
+        Request::AppAttributes { app_id, attributes } => {
+            build_app_attributes_response(app_id, attributes, lookup)
+        }
+        Request::PerformAction { uid, action } => build_action_ack_response(*uid, *action),
+    }
+}
+
+/// Response bytes one Data Source notification carries at `mtu`: 20 at
+/// the 23-byte minimum, 155 at 158, 509 at 512.
+pub fn fragment_len(mtu: u16) -> usize {
+    usize::from(mtu).saturating_sub(ATT_NOTIFY_OVERHEAD).max(1)
+}
+
+/// `response` split into the notifications that carry it at `mtu`.
+pub fn fragments(response: &[u8], mtu: u16) -> std::slice::Chunks<'_, u8> {
+    response.chunks(fragment_len(mtu))
+}
+
+/// [`parse`] then [`respond`], for host checks against captured writes.
+pub fn control_point_response(request: &[u8], lookup: &impl Lookup) -> Result<Vec<u8>, ParseError> {
+    parse(request).map(|request| respond(&request, lookup))
+}
+
+fn build_notification_attributes_response(
+    uid: u32,
+    attributes: &[AttributeRequest],
+    lookup: &impl Lookup,
+) -> Vec<u8> {
+    let mut response = Vec::with_capacity(48);
+    response.push(Command::GetNotificationAttributes.byte());
+    response.extend_from_slice(&uid.to_le_bytes());
+
+    let stored = lookup.notification(uid);
+    for attribute in attributes {
+        let value =
+            notification_attribute(stored.as_ref(), attribute.id, attribute.max_len as usize);
+        if !append_attribute(&mut response, attribute.id, value) {
+            break;
+        }
+    }
+
+    if attributes.is_empty() {
+        append_attribute(
+            &mut response,
+            0,
+            notification_attribute(stored.as_ref(), 0, 0),
+        );
+    }
+
+    response
+}
+
+fn build_app_attributes_response(
+    app_id: &[u8],
+    attributes: &[AttributeRequest],
+    lookup: &impl Lookup,
+) -> Vec<u8> {
+    let mut response = Vec::with_capacity(48);
+    response.push(Command::GetAppAttributes.byte());
+    response.extend_from_slice(app_id);
+    response.push(0);
+    let display_name = lookup.app_name(app_id);
+
+    for attribute in attributes {
+        let value = app_attribute(&display_name, attribute.id, attribute.max_len as usize);
+        if !append_attribute(&mut response, attribute.id, value) {
+            break;
+        }
+    }
+
+    if attributes.is_empty() {
+        append_attribute(&mut response, 0, app_attribute(&display_name, 0, 0));
+    }
+
+    response
+}
+
+fn build_action_ack_response(uid: u32, action: u8) -> Vec<u8> {
+    let mut response = Vec::with_capacity(6);
+    response.push(Command::PerformNotificationAction.byte());
+    response.extend_from_slice(&uid.to_le_bytes());
+    response.push(action);
+    response
+}
+
+/// Appends one `id, u16 LE length, value` tuple, cutting the value to what
+/// is left of [`MAX_RESPONSE`]. `false` when not even the header fits.
+fn append_attribute(buffer: &mut Vec<u8>, attr_id: u8, value: Vec<u8>) -> bool {
+    let Some(room) = MAX_RESPONSE.checked_sub(buffer.len() + 3) else {
+        return false;
+    };
+    let value = match room {
+        0 => Vec::new(),
+        room if value.len() > room => truncate_text(&value, room),
+        _ => value,
+    };
+    buffer.push(attr_id);
+    buffer.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buffer.extend_from_slice(&value);
+    true
+}
+
+fn attribute_requires_len(attr_id: u8) -> bool {
+    matches!(attr_id, 1..=3)
+}
+
+/// Serves the stored notification's content, or the phantom placeholder for
+/// UIDs the store does not know.
+fn notification_attribute(
+    stored: Option<&NotificationContent>,
+    attr_id: u8,
+    requested_len: usize,
+) -> Vec<u8> {
+    let Some(entry) = stored else {
+        return dummy_notification_attribute(attr_id, requested_len);
+    };
+    match attr_id {
+        0 => truncate_bytes(entry.app_id.as_bytes(), requested_len),
+        1 => truncate_bytes(entry.title.as_bytes(), requested_len),
+        2 => truncate_bytes(entry.subtitle.as_bytes(), requested_len),
+        3 => truncate_bytes(entry.message.as_bytes(), requested_len),
+        4 => truncate_bytes(entry.message.len().to_string().as_bytes(), requested_len),
+        5 => truncate_bytes(entry.date.as_bytes(), requested_len),
+        6 => action_label(entry.positive_label.as_deref(), 6, requested_len),
+        7 => action_label(entry.negative_label.as_deref(), 7, requested_len),
+        other => dummy_notification_attribute(other, requested_len),
+    }
+}
+
+fn action_label(label: Option<&str>, attr_id: u8, requested_len: usize) -> Vec<u8> {
+    match label {
+        Some(label) => truncate_text(label.as_bytes(), requested_len),
+        None => dummy_notification_attribute(attr_id, requested_len),
+    }
+}
+
+fn dummy_notification_attribute(attr_id: u8, requested_len: usize) -> Vec<u8> {
+    match attr_id {
+        0 => truncate_bytes(DUMMY_APP_IDENTIFIER.as_bytes(), requested_len),
+        1 => truncate_bytes(DUMMY_MESSAGE_TITLE.as_bytes(), requested_len),
+        2 => truncate_bytes(DUMMY_MESSAGE_SUBTITLE.as_bytes(), requested_len),
+        3 => truncate_bytes(DUMMY_MESSAGE_BODY.as_bytes(), requested_len),
+        4 => truncate_bytes(b"0", requested_len),
+        5 => truncate_bytes(DUMMY_DATE.as_bytes(), requested_len),
+        6 => truncate_bytes(b"Open", requested_len),
+        7 => truncate_bytes(b"Ignore", requested_len),
+        _ => truncate_bytes(b"", requested_len),
+    }
+}
+
+fn app_attribute(display_name: &[u8], attr_id: u8, requested_len: usize) -> Vec<u8> {
+    match attr_id {
+        0 => truncate_text(display_name, requested_len),
+        _ => truncate_bytes(b"", requested_len),
+    }
+}
+
+fn truncate_bytes(data: &[u8], max_len: usize) -> Vec<u8> {
+    if max_len == 0 || data.len() <= max_len {
+        data.to_vec()
+    } else {
+        data[..max_len].to_vec()
+    }
+}
+
+/// Like `truncate_bytes`, but backs off to a character boundary when `data`
+/// is UTF-8 so a CJK name does not end in half a character.
+fn truncate_text(data: &[u8], max_len: usize) -> Vec<u8> {
+    match std::str::from_utf8(data) {
+        Ok(text) if max_len != 0 && text.len() > max_len => {
+            let end = (0..=max_len)
+                .rev()
+                .find(|&end| text.is_char_boundary(end))
+                .unwrap_or(0);
+            data[..end].to_vec()
+        }
+        _ => truncate_bytes(data, max_len),
+    }
+}
@@ -0,0 +1,85 @@
+//! Link-stall recovery ladder, climbed one rung per
+//! `SystemEvent::RelayDegraded` (see `canary`), gentlest first:
+//!
+//! 1. replay the store to every subscriber, as a fresh subscribe would;
+//! 2. a Security Request on each encrypted link, which re-encrypts it
+//!    (peers in `nudge::NO_NUDGE` are skipped);
+//! 3. drop the watch's connection so it reconnects and subscribes again.
+//!
+//! The rung follows the canary's misses in a row, so a passing canary
+//! starts the next stall from the bottom; misses past the top repeat it.
+
+use std::time::Duration;
+
+use esp32_nimble::BLEDevice;
+use log::warn;
+use tokio::sync::broadcast::error::TryRecvError;
+
+use super::{canary, clients, nudge, store};
+use crate::{
+    events::{self, SystemEvent},
+    periodic::{self, JobHandle},
+};
+
+const CHECK: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rung {
+    Replay,
+    SecurityNudge,
+    Reconnect,
+}
+
+impl Rung {
+    fn for_failures(failures: u32) -> Self {
+        match failures.saturating_sub(canary::DEGRADED_AFTER) {
+            0 => Rung::Replay,
+            1 => Rung::SecurityNudge,
+            _ => Rung::Reconnect,
+        }
+    }
+}
+
+pub(super) fn start() -> JobHandle {
+    let mut events = events::subscribe();
+    periodic::register("ancs_recovery", CHECK, move || loop {
+        match events.try_recv() {
+            Ok(SystemEvent::RelayDegraded { failures }) => climb(Rung::for_failures(failures)),
+            Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+            Err(_) => return,
+        }
+    })
+}
+
+fn climb(rung: Rung) {
+    let linked: Vec<_> = clients::connected()
+        .into_iter()
+        .filter(|client| client.encrypted)
+        .collect();
+    crate::journal!(
+        "Notification relay degraded: {rung:?} for {} watch link(s)",
+        linked.len()
+    );
+    match rung {
+        Rung::Replay => {
+            store::republish();
+        }
+        Rung::SecurityNudge => {
+            for client in &linked {
+                nudge::request_now(client.conn_handle, &client.addr);
+            }
+        }
+        Rung::Reconnect => {
+            let server = BLEDevice::take().get_server();
+            for client in &linked {
+                if let Err(err) = server.disconnect(client.conn_handle) {
+                    warn!(
+                        "Failed to cycle ANCS client {} (conn={}): {err:?}",
+                        client.label(),
+                        client.conn_handle
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! [`AncsServer`]: what the fake ANCS service holds while it runs, and the
+//! order it lets go of it in. `lifecycle` owns the running server and the
+//! firmware's [`Runtime`]; host tests supply their own.
+
+use std::sync::Arc;
+
+use super::source::NotifySink;
+use crate::periodic::JobHandle;
+
+/// The parts of the firmware a server starts and stops.
+pub trait Runtime: Sync {
+    /// Gives the store `sink` to publish on, or takes it away with `None`.
+    fn attach(&self, sink: Option<Arc<dyn NotifySink>>);
+    /// Registers the service's periodic jobs. `relaxed` (test mode) leaves
+    /// out every job that sends traffic of its own.
+    fn start_jobs(&self, relaxed: bool) -> Vec<JobHandle>;
+    fn cancel(&self, job: JobHandle);
+    /// Drops the events `pacing` still holds.
+    fn clear_held(&self);
+}
+
+pub struct AncsServer {
+    runtime: &'static dyn Runtime,
+    sink: Arc<dyn NotifySink>,
+    jobs: Vec<JobHandle>,
+}
+
+impl AncsServer {
+    /// Attaches `sink` to the store and registers the jobs.
+    pub fn start(runtime: &'static dyn Runtime, sink: Arc<dyn NotifySink>, relaxed: bool) -> Self {
+        runtime.attach(Some(sink.clone()));
+        let jobs = runtime.start_jobs(relaxed);
+        Self {
+            runtime,
+            sink,
+            jobs,
+        }
+    }
+
+    /// Cancels the jobs first, so none runs against a detached store, then
+    /// detaches the sink and drops the held events. Hands the sink back for
+    /// a later start.
+    pub fn stop(self) -> Arc<dyn NotifySink> {
+        for job in self.jobs {
+            self.runtime.cancel(job);
+        }
+        self.runtime.attach(None);
+        self.runtime.clear_held();
+        self.sink
+    }
+}
@@ -0,0 +1,377 @@
+//! Per-connection ANCS counters for triaging watch firmware differences.
+//! Callbacks bump plain integers under one short lock; a session's summary is
+//! journaled at disconnect and kept in a small history.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+
+use super::protocol::{ParseError, Request};
+
+const HISTORY: usize = 5;
+/// Notification attribute IDs 0..=7 are defined by the ANCS spec.
+const NOTIFICATION_ATTRIBUTES: usize = 8;
+
+static ACTIVE: Mutex<Vec<SessionStats>> = Mutex::new(Vec::new());
+static RECENT: Mutex<VecDeque<SessionStats>> = Mutex::new(VecDeque::new());
+static LIFETIME: Mutex<LifetimeStats> = Mutex::new(LifetimeStats::new());
+
+#[derive(Clone, Debug)]
+pub struct SessionStats {
+    pub conn_handle: u16,
+    pub addr: String,
+    connected_at: Instant,
+    encrypted_at: Option<Instant>,
+    pub duration: Option<Duration>,
+    pub disconnect_reason: Option<String>,
+    pub subscribes: u32,
+    pub get_notification_attributes: u32,
+    pub get_app_attributes: u32,
+    pub perform_action: u32,
+    pub unknown_commands: u32,
+    /// Writes refused as malformed, unknown commands included.
+    pub parse_errors: u32,
+    pub last_parse_error: Option<ParseError>,
+    /// Requests per notification attribute ID.
+    pub notification_attributes: [u32; NOTIFICATION_ATTRIBUTES],
+    pub app_display_name: u32,
+    pub other_attributes: u32,
+    pub responses_sent: u32,
+    /// Data Source notifications those responses took.
+    pub fragments_sent: u32,
+    /// Last ATT MTU seen on the link; see `pacing`.
+    pub mtu: Option<u16>,
+    /// Notification Source events held back by `pacing`, and for how long.
+    pub paced_events: u32,
+    pub paced_total: Duration,
+    pub paced_max: Duration,
+    pub rejected_unencrypted: u32,
+    /// Security Requests sent because the peer did not start pairing; see
+    /// `nudge`.
+    pub security_nudges: u32,
+    pub notify_failures: u32,
+    pub connect_to_encrypt: Option<Duration>,
+    pub encrypt_to_first_request: Option<Duration>,
+}
+
+impl SessionStats {
+    fn new(conn_handle: u16, addr: String) -> Self {
+        Self {
+            conn_handle,
+            addr,
+            connected_at: Instant::now(),
+            encrypted_at: None,
+            duration: None,
+            disconnect_reason: None,
+            subscribes: 0,
+            get_notification_attributes: 0,
+            get_app_attributes: 0,
+            perform_action: 0,
+            unknown_commands: 0,
+            parse_errors: 0,
+            last_parse_error: None,
+            notification_attributes: [0; NOTIFICATION_ATTRIBUTES],
+            app_display_name: 0,
+            other_attributes: 0,
+            responses_sent: 0,
+            fragments_sent: 0,
+            mtu: None,
+            paced_events: 0,
+            paced_total: Duration::ZERO,
+            paced_max: Duration::ZERO,
+            rejected_unencrypted: 0,
+            security_nudges: 0,
+            notify_failures: 0,
+            connect_to_encrypt: None,
+            encrypt_to_first_request: None,
+        }
+    }
+
+    pub fn commands(&self) -> u32 {
+        self.get_notification_attributes
+            + self.get_app_attributes
+            + self.perform_action
+            + self.unknown_commands
+    }
+
+    pub fn attribute_requests(&self) -> u32 {
+        self.notification_attributes.iter().sum::<u32>()
+            + self.app_display_name
+            + self.other_attributes
+    }
+
+    fn count_attribute(&mut self, attr_id: u8) {
+        match self.notification_attributes.get_mut(attr_id as usize) {
+            Some(count) => *count += 1,
+            None => self.other_attributes += 1,
+        }
+    }
+}
+
+/// One line of `key=value` pairs, as journaled at disconnect.
+impl fmt::Display for SessionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: Option<Duration>| match duration {
+            Some(duration) => duration.as_millis().to_string(),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "conn={} addr={} dur_ms={} subs={} cmd_na={} cmd_aa={} cmd_pa={} cmd_unk={} \
+             parse_err={} attrs={} attr_app_name={} attr_other={} resp={} frags={} mtu={} paced={} \
+             paced_max_ms={} rej_unenc={} nudges={} notify_fail={} enc_ms={} first_req_ms={}",
+            self.conn_handle,
+            self.addr,
+            ms(self.duration.or_else(|| Some(self.connected_at.elapsed()))),
+            self.subscribes,
+            self.get_notification_attributes,
+            self.get_app_attributes,
+            self.perform_action,
+            self.unknown_commands,
+            self.parse_errors,
+            self.notification_attributes
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            self.app_display_name,
+            self.other_attributes,
+            self.responses_sent,
+            self.fragments_sent,
+            self.mtu
+                .map_or_else(|| "-".to_string(), |mtu| mtu.to_string()),
+            self.paced_events,
+            self.paced_max.as_millis(),
+            self.rejected_unencrypted,
+            self.security_nudges,
+            self.notify_failures,
+            ms(self.connect_to_encrypt),
+            ms(self.encrypt_to_first_request),
+        )?;
+        if let Some(error) = &self.last_parse_error {
+            write!(f, " last_parse_err=\"{error}\"")?;
+        }
+        if let Some(reason) = &self.disconnect_reason {
+            write!(f, " reason={reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Totals over every finished session since boot.
+#[derive(Clone, Copy, Debug)]
+pub struct LifetimeStats {
+    pub sessions: u32,
+    pub subscribes: u32,
+    pub commands: u32,
+    pub attribute_requests: u32,
+    pub parse_errors: u32,
+    pub responses_sent: u32,
+    pub rejected_unencrypted: u32,
+    pub security_nudges: u32,
+    pub notify_failures: u32,
+}
+
+impl LifetimeStats {
+    const fn new() -> Self {
+        Self {
+            sessions: 0,
+            subscribes: 0,
+            commands: 0,
+            attribute_requests: 0,
+            parse_errors: 0,
+            responses_sent: 0,
+            rejected_unencrypted: 0,
+            security_nudges: 0,
+            notify_failures: 0,
+        }
+    }
+
+    fn absorb(&mut self, session: &SessionStats) {
+        self.sessions += 1;
+        self.subscribes += session.subscribes;
+        self.commands += session.commands();
+        self.attribute_requests += session.attribute_requests();
+        self.parse_errors += session.parse_errors;
+        self.responses_sent += session.responses_sent;
+        self.rejected_unencrypted += session.rejected_unencrypted;
+        self.security_nudges += session.security_nudges;
+        self.notify_failures += session.notify_failures;
+    }
+}
+
+fn with_session(conn_handle: u16, f: impl FnOnce(&mut SessionStats)) {
+    if let Ok(mut active) = ACTIVE.lock() {
+        if let Some(session) = active
+            .iter_mut()
+            .find(|session| session.conn_handle == conn_handle)
+        {
+            f(session);
+        }
+    }
+}
+
+pub fn on_connect(conn_handle: u16, addr: String) {
+    if let Ok(mut active) = ACTIVE.lock() {
+        active.retain(|session| session.conn_handle != conn_handle);
+        active.push(SessionStats::new(conn_handle, addr));
+    }
+}
+
+pub fn on_encrypted(conn_handle: u16) {
+    with_session(conn_handle, |session| {
+        if session.encrypted_at.is_none() {
+            let now = Instant::now();
+            session.encrypted_at = Some(now);
+            session.connect_to_encrypt = Some(now - session.connected_at);
+        }
+    });
+}
+
+pub fn on_subscribe(conn_handle: u16) {
+    with_session(conn_handle, |session| session.subscribes += 1);
+}
+
+/// Counts a control point command and the attributes it asks for, or why
+/// it was refused.
+pub fn on_request(conn_handle: u16, request: &Result<Request<'_>, ParseError>) {
+    with_session(conn_handle, |session| {
+        if session.encrypt_to_first_request.is_none() {
+            if let Some(encrypted_at) = session.encrypted_at {
+                session.encrypt_to_first_request = Some(encrypted_at.elapsed());
+            }
+        }
+        match request {
+            Ok(Request::NotificationAttributes { attributes, .. }) => {
+                session.get_notification_attributes += 1;
+                for attribute in attributes {
+                    session.count_attribute(attribute.id);
+                }
+            }
+            Ok(Request::AppAttributes { attributes, .. }) => {
+                session.get_app_attributes += 1;
+                for attribute in attributes {
+                    if attribute.id == 0 {
+                        session.app_display_name += 1;
+                    } else {
+                        session.other_attributes += 1;
+                    }
+                }
+            }
+            Ok(Request::PerformAction { .. }) => session.perform_action += 1,
+            Err(error) => {
+                if let ParseError::UnknownCommand(_) = error {
+                    session.unknown_commands += 1;
+                }
+                session.parse_errors += 1;
+                session.last_parse_error = Some(*error);
+            }
+        }
+    });
+}
+
+pub fn on_response(conn_handle: u16, fragments: usize) {
+    with_session(conn_handle, |session| {
+        session.responses_sent += 1;
+        session.fragments_sent += fragments as u32;
+    });
+}
+
+pub fn on_mtu(conn_handle: u16, mtu: u16) {
+    with_session(conn_handle, |session| session.mtu = Some(mtu));
+}
+
+/// A Notification Source event went out after waiting `held`.
+pub fn on_paced(conn_handle: u16, held: Duration) {
+    with_session(conn_handle, |session| {
+        session.paced_events += 1;
+        session.paced_total += held;
+        session.paced_max = session.paced_max.max(held);
+    });
+}
+
+pub fn on_rejected_unencrypted(conn_handle: u16) {
+    with_session(conn_handle, |session| session.rejected_unencrypted += 1);
+}
+
+pub fn on_security_nudge(conn_handle: u16) {
+    with_session(conn_handle, |session| session.security_nudges += 1);
+}
+
+pub fn on_notify_failure(conn_handle: u16) {
+    with_session(conn_handle, |session| session.notify_failures += 1);
+}
+
+/// Closes the session, journals its summary and rolls it into the totals.
+pub fn on_disconnect(conn_handle: u16, reason: String) {
+    let Some(mut session) = ACTIVE.lock().ok().and_then(|mut active| {
+        let index = active
+            .iter()
+            .position(|session| session.conn_handle == conn_handle)?;
+        Some(active.remove(index))
+    }) else {
+        return;
+    };
+    session.duration = Some(session.connected_at.elapsed());
+    session.disconnect_reason = Some(reason);
+    crate::journal!("ANCS session {session}");
+    if let Ok(mut lifetime) = LIFETIME.lock() {
+        lifetime.absorb(&session);
+    }
+    if let Ok(mut recent) = RECENT.lock() {
+        if recent.len() == HISTORY {
+            recent.pop_front();
+        }
+        recent.push_back(session);
+    }
+}
+
+pub fn active() -> Vec<SessionStats> {
+    ACTIVE
+        .lock()
+        .map(|active| active.clone())
+        .unwrap_or_default()
+}
+
+/// The last finished sessions, oldest first.
+pub fn recent() -> Vec<SessionStats> {
+    RECENT
+        .lock()
+        .map(|recent| recent.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn lifetime() -> LifetimeStats {
+    LIFETIME
+        .lock()
+        .map(|lifetime| *lifetime)
+        .unwrap_or(LifetimeStats::new())
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "ancssessions",
+        "per-connection ANCS counters: open sessions, then the last 5 closed",
+        |args| {
+            if !args.is_empty() {
+                bail!("usage: ancssessions");
+            }
+            let mut lines = Vec::new();
+            for session in active() {
+                lines.push(format!("open   {session}"));
+            }
+            for session in recent().iter().rev() {
+                lines.push(format!("closed {session}"));
+            }
+            if lines.is_empty() {
+                lines.push("no ANCS sessions yet".to_string());
+            }
+            Ok(lines.join("\n"))
+        },
+    );
+}
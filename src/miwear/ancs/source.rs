@@ -0,0 +1,256 @@
+//! Notification Source events from the moment `store` emits them to the
+//! sink: their encoding, the per-link queues `pacing` holds them in, and
+//! the [`Outlet`] they leave through. Std-only and clock-free, so host
+//! tests run the whole path against a mock [`NotifySink`].
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A Notification Source event ready to be sent.
+pub type EventPayload = [u8; 8];
+
+/// Longest an Added event waits behind a Data Source response.
+pub const MAX_HOLD: Duration = Duration::from_millis(500);
+/// Used until a connection reports its interval.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EventId {
+    Added = 0,
+    Modified = 1,
+    Removed = 2,
+}
+
+pub fn encode_event(id: EventId, flags: u8, category: u8, count: u8, uid: u32) -> EventPayload {
+    let mut payload = [0u8; 8];
+    payload[0] = id as u8;
+    payload[1] = flags;
+    payload[2] = category;
+    payload[3] = count;
+    payload[4..8].copy_from_slice(&uid.to_le_bytes());
+    payload
+}
+
+/// Where Notification Source events leave the store. On hardware this is
+/// the NimBLE characteristic (see `lifecycle`); the store, `pacing` and
+/// `sessions` never touch NimBLE themselves.
+pub trait NotifySink: Send + Sync {
+    /// The value a read of the characteristic returns.
+    fn set_value(&self, payload: &EventPayload);
+    fn notify(&self, conn_handle: u16, payload: &EventPayload) -> Result<(), String>;
+    fn subscribed_count(&self) -> usize;
+}
+
+/// One event for one connection; `held` is how long it waited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delivery {
+    pub conn_handle: u16,
+    pub payload: EventPayload,
+    pub held: Duration,
+}
+
+/// The sink events go to while the service runs; nothing is sent while
+/// none is attached.
+pub struct Outlet {
+    sink: Mutex<Option<Arc<dyn NotifySink>>>,
+}
+
+impl Outlet {
+    pub const fn new() -> Self {
+        Self {
+            sink: Mutex::new(None),
+        }
+    }
+
+    /// `None` detaches the sink.
+    pub fn attach(&self, sink: Option<Arc<dyn NotifySink>>) {
+        if let Ok(mut slot) = self.sink.lock() {
+            *slot = sink;
+        }
+    }
+
+    pub fn sink(&self) -> Option<Arc<dyn NotifySink>> {
+        self.sink.lock().ok().and_then(|slot| slot.clone())
+    }
+
+    /// Makes the last of `events` the characteristic value and sends what
+    /// `route` lets out now.
+    pub fn publish(
+        &self,
+        events: &[EventPayload],
+        route: impl FnOnce(&[EventPayload]) -> Vec<Delivery>,
+        sent: impl FnMut(&Delivery, Result<(), String>),
+    ) {
+        let Some(last) = events.last() else {
+            return;
+        };
+        if let Some(sink) = self.sink() {
+            sink.set_value(last);
+        }
+        self.deliver(&route(events), sent);
+    }
+
+    /// Sends each event to its own connection and hands `sent` the result.
+    pub fn deliver(
+        &self,
+        deliveries: &[Delivery],
+        mut sent: impl FnMut(&Delivery, Result<(), String>),
+    ) {
+        if deliveries.is_empty() {
+            return;
+        }
+        let Some(sink) = self.sink() else {
+            return;
+        };
+        for delivery in deliveries {
+            sent(
+                delivery,
+                sink.notify(delivery.conn_handle, &delivery.payload),
+            );
+        }
+    }
+}
+
+impl Default for Outlet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Notification Source subscriber.
+struct Link {
+    conn_handle: u16,
+    mtu: u16,
+    interval: Duration,
+    /// When the last Data Source fragment is expected to be on air.
+    busy_until: Option<Instant>,
+    held: VecDeque<(EventPayload, Instant)>,
+}
+
+impl Link {
+    fn busy(&self, now: Instant) -> bool {
+        self.busy_until.is_some_and(|until| now < until)
+    }
+
+    /// When the front of the queue may go out.
+    fn release_at(&self) -> Option<Instant> {
+        let (_, held_at) = self.held.front()?;
+        Some(
+            self.busy_until
+                .map_or(*held_at, |until| until.min(*held_at + MAX_HOLD)),
+        )
+    }
+}
+
+/// Every subscriber and the events held back for it; see `pacing`.
+#[derive(Default)]
+pub struct Links {
+    links: Vec<Link>,
+}
+
+impl Links {
+    pub const fn new() -> Self {
+        Self { links: Vec::new() }
+    }
+
+    pub fn subscribe(&mut self, conn_handle: u16, mtu: u16) {
+        self.forget(conn_handle);
+        self.links.push(Link {
+            conn_handle,
+            mtu,
+            interval: DEFAULT_INTERVAL,
+            busy_until: None,
+            held: VecDeque::new(),
+        });
+    }
+
+    /// Held events are dropped with the link.
+    pub fn forget(&mut self, conn_handle: u16) {
+        self.links.retain(|link| link.conn_handle != conn_handle);
+    }
+
+    pub fn clear(&mut self) {
+        self.links.clear();
+    }
+
+    /// A Data Source response of `fragments` notifications was handed to
+    /// the stack at `now`. Returns the link's previous MTU when it changed.
+    pub fn on_response(
+        &mut self,
+        conn_handle: u16,
+        mtu: u16,
+        interval: Duration,
+        fragments: usize,
+        now: Instant,
+    ) -> Option<u16> {
+        let link = self
+            .links
+            .iter_mut()
+            .find(|link| link.conn_handle == conn_handle)?;
+        let previous = (link.mtu != mtu).then_some(link.mtu);
+        link.mtu = mtu;
+        if !interval.is_zero() {
+            link.interval = interval;
+        }
+        let start = link.busy_until.filter(|until| *until > now).unwrap_or(now);
+        link.busy_until = Some(start + link.interval * fragments as u32);
+        previous
+    }
+
+    /// The sends `events` turn into at `now`; the rest are held, and the
+    /// flag says whether any were.
+    pub fn route(&mut self, events: &[EventPayload], now: Instant) -> (Vec<Delivery>, bool) {
+        let mut sends = Vec::new();
+        let mut held_any = false;
+        for link in self.links.iter_mut() {
+            for payload in events {
+                let added = payload[0] == EventId::Added as u8;
+                if link.held.is_empty() && !(added && link.busy(now)) {
+                    sends.push(Delivery {
+                        conn_handle: link.conn_handle,
+                        payload: *payload,
+                        held: Duration::ZERO,
+                    });
+                } else {
+                    link.held.push_back((*payload, now));
+                    held_any = true;
+                }
+            }
+        }
+        (sends, held_any)
+    }
+
+    /// Whether any link still has events waiting.
+    pub fn holding(&self) -> bool {
+        self.links.iter().any(|link| !link.held.is_empty())
+    }
+
+    /// Held events whose link drained or whose wait hit [`MAX_HOLD`].
+    pub fn due(&mut self, now: Instant) -> Vec<Delivery> {
+        let mut sends = Vec::new();
+        for link in self.links.iter_mut() {
+            while link.release_at().is_some_and(|at| at <= now) {
+                let Some((payload, held_at)) = link.held.pop_front() else {
+                    break;
+                };
+                sends.push(Delivery {
+                    conn_handle: link.conn_handle,
+                    payload,
+                    held: now.saturating_duration_since(held_at),
+                });
+                // Past the cap the link counts as drained, so the rest of a
+                // burst follows at once instead of each waiting again.
+                link.busy_until = None;
+            }
+        }
+        sends
+    }
+
+    pub fn next_release(&self) -> Option<Instant> {
+        self.links.iter().filter_map(Link::release_at).min()
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    pacing, sessions,
+    source::{Delivery, Outlet},
+};
+use crate::timesync;
+
+pub use super::source::{encode_event, EventId, EventPayload, NotifySink};
+
+const MAX_ENTRIES: usize = 32;
+/// Entries older than this are dropped and their UIDs answer as unknown.
+const TTL: Duration = Duration::from_secs(60 * 60);
+const UNDATED: &str = "19700101T000000";
+
+pub const FLAG_SILENT: u8 = 0x01;
+pub const FLAG_PRE_EXISTING: u8 = 0x04;
+pub const FLAG_POSITIVE_ACTION: u8 = 0x08;
+pub const FLAG_NEGATIVE_ACTION: u8 = 0x10;
+pub const CATEGORY_OTHER: u8 = 0;
+pub const CATEGORY_SCHEDULE: u8 = 5;
+pub const MAX_CATEGORY: u8 = 11;
+
+static STORE: Mutex<NotificationStore> = Mutex::new(NotificationStore::new());
+static OUTLET: Outlet = Outlet::new();
+
+#[derive(Clone, Debug)]
+pub struct StoredNotification {
+    pub uid: u32,
+    pub category: u8,
+    pub flags: u8,
+    pub app_id: String,
+    pub app_name: Option<String>,
+    pub title: String,
+    pub subtitle: String,
+    pub message: String,
+    pub date: String,
+    /// Where the watch's actions on this entry are reported, see `webhooks`.
+    pub callback_url: Option<String>,
+    pub actions: ActionLabels,
+    created: Instant,
+}
+
+/// Button labels the watch shows; a set label also raises the matching
+/// action flag.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionLabels {
+    pub positive: Option<String>,
+    pub negative: Option<String>,
+}
+
+impl ActionLabels {
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.positive.is_some() {
+            flags |= FLAG_POSITIVE_ACTION;
+        }
+        if self.negative.is_some() {
+            flags |= FLAG_NEGATIVE_ACTION;
+        }
+        flags
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NewNotification {
+    pub app_id: String,
+    pub app_name: Option<String>,
+    pub title: String,
+    pub subtitle: String,
+    pub message: String,
+    pub category: u8,
+    pub silent: bool,
+    pub callback_url: Option<String>,
+    pub actions: ActionLabels,
+}
+
+/// Fields to replace on an existing notification; `None` leaves it as is.
+#[derive(Clone, Debug, Default)]
+pub struct NotificationChanges {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub message: Option<String>,
+    pub category: Option<u8>,
+}
+
+pub struct NotificationStore {
+    entries: VecDeque<StoredNotification>,
+    next_uid: u32,
+}
+
+impl NotificationStore {
+    pub const fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            next_uid: 1,
+        }
+    }
+
+    /// `date` is the ANCS Date attribute, local time; `None` while the
+    /// clock is unset.
+    pub fn add(
+        &mut self,
+        new: NewNotification,
+        now: Instant,
+        date: Option<String>,
+    ) -> (u32, Vec<EventPayload>) {
+        let mut events = Vec::new();
+        if self.entries.len() == MAX_ENTRIES {
+            if let Some(evicted) = self.entries.pop_front() {
+                events.push(self.event(EventId::Removed, &evicted));
+            }
+        }
+
+        let uid = self.next_uid;
+        self.next_uid = self.next_uid.wrapping_add(1).max(1);
+        let entry = StoredNotification {
+            uid,
+            category: new.category.min(MAX_CATEGORY),
+            flags: (if new.silent { FLAG_SILENT } else { 0 }) | new.actions.flags(),
+            app_id: new.app_id,
+            app_name: new.app_name,
+            title: new.title,
+            subtitle: new.subtitle,
+            message: new.message,
+            date: date.unwrap_or_else(|| UNDATED.to_string()),
+            callback_url: new.callback_url,
+            actions: new.actions,
+            created: now,
+        };
+        self.entries.push_back(entry);
+        let added = self.event(EventId::Added, self.entries.back().expect("just pushed"));
+        events.push(added);
+        (uid, events)
+    }
+
+    /// Applies `changes` in place, keeping the UID. `None` when the UID is
+    /// unknown, in which case nothing is emitted.
+    pub fn update_notification(
+        &mut self,
+        uid: u32,
+        changes: NotificationChanges,
+    ) -> Option<EventPayload> {
+        let entry = self.entries.iter_mut().find(|entry| entry.uid == uid)?;
+        if let Some(title) = changes.title {
+            entry.title = title;
+        }
+        if let Some(subtitle) = changes.subtitle {
+            entry.subtitle = subtitle;
+        }
+        if let Some(message) = changes.message {
+            entry.message = message;
+        }
+        if let Some(category) = changes.category {
+            entry.category = category.min(MAX_CATEGORY);
+        }
+        let entry = entry.clone();
+        Some(self.event(EventId::Modified, &entry))
+    }
+
+    pub fn remove(&mut self, uid: u32) -> Option<EventPayload> {
+        let index = self.entries.iter().position(|entry| entry.uid == uid)?;
+        let removed = self.entries.remove(index)?;
+        Some(self.event(EventId::Removed, &removed))
+    }
+
+    pub fn get(&self, uid: u32) -> Option<&StoredNotification> {
+        self.entries.iter().find(|entry| entry.uid == uid)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &StoredNotification> {
+        self.entries.iter()
+    }
+
+    pub fn category_count(&self, category: u8) -> u8 {
+        self.entries
+            .iter()
+            .filter(|entry| entry.category == category)
+            .count()
+            .min(u8::MAX as usize) as u8
+    }
+
+    /// Everything currently stored, flagged pre-existing, for a new subscriber.
+    pub fn replay(&self) -> Vec<EventPayload> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                encode_event(
+                    EventId::Added,
+                    entry.flags | FLAG_PRE_EXISTING,
+                    entry.category,
+                    self.category_count(entry.category),
+                    entry.uid,
+                )
+            })
+            .collect()
+    }
+
+    /// Drops entries past their TTL, returning their Removed events.
+    pub fn expire(&mut self, now: Instant) -> Vec<EventPayload> {
+        let mut events = Vec::new();
+        while self
+            .entries
+            .front()
+            .is_some_and(|entry| expired(entry, now))
+        {
+            if let Some(old) = self.entries.pop_front() {
+                events.push(self.event(EventId::Removed, &old));
+            }
+        }
+        events
+    }
+
+    /// Counts reflect the store after the change the event describes.
+    fn event(&self, id: EventId, entry: &StoredNotification) -> EventPayload {
+        encode_event(
+            id,
+            entry.flags,
+            entry.category,
+            self.category_count(entry.category),
+            entry.uid,
+        )
+    }
+}
+
+fn expired(entry: &StoredNotification, now: Instant) -> bool {
+    now.saturating_duration_since(entry.created) >= TTL
+}
+
+/// Gives the store the sink to publish on.
+pub fn attach(sink: Arc<dyn NotifySink>) {
+    OUTLET.attach(Some(sink));
+}
+
+/// Takes the sink away again; entries are kept and events are dropped
+/// until the next [`attach`].
+pub fn detach() {
+    OUTLET.attach(None);
+}
+
+pub fn add(new: NewNotification) -> u32 {
+    let date = timesync::now_local().map(|local| local.ancs_date());
+    with_store(|store, events| {
+        let (uid, added) = store.add(new, Instant::now(), date);
+        events.extend(added);
+        uid
+    })
+    .unwrap_or(0)
+}
+
+/// Returns false for unknown or expired UIDs.
+pub fn update_notification(uid: u32, changes: NotificationChanges) -> bool {
+    with_store(|store, events| {
+        store
+            .update_notification(uid, changes)
+            .map(|event| events.push(event))
+            .is_some()
+    })
+    .unwrap_or(false)
+}
+
+pub fn remove(uid: u32) -> bool {
+    with_store(|store, events| store.remove(uid).map(|event| events.push(event)).is_some())
+        .unwrap_or(false)
+}
+
+pub fn get(uid: u32) -> Option<StoredNotification> {
+    with_store(|store, _| store.get(uid).cloned()).flatten()
+}
+
+pub fn list() -> Vec<StoredNotification> {
+    STORE
+        .lock()
+        .map(|store| store.entries().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Stores `new` without notifying, for callers already holding the
+/// characteristic lock (subscribe callbacks).
+pub fn insert_quietly(new: NewNotification) {
+    let date = timesync::now_local().map(|local| local.ancs_date());
+    if let Ok(mut store) = STORE.lock() {
+        store.expire(Instant::now());
+        store.add(new, Instant::now(), date);
+    }
+}
+
+/// Whether any peer has Notification Source notifications enabled.
+pub fn has_subscriber() -> bool {
+    OUTLET
+        .sink()
+        .is_some_and(|sink| sink.subscribed_count() > 0)
+}
+
+pub fn is_empty() -> bool {
+    STORE
+        .lock()
+        .map(|store| store.entries.is_empty())
+        .unwrap_or(true)
+}
+
+pub fn replay() -> Vec<EventPayload> {
+    STORE.lock().map(|store| store.replay()).unwrap_or_default()
+}
+
+/// Sends the subscribe replay to every current subscriber again, for a
+/// watch that seems to have lost track of the store.
+pub fn republish() {
+    publish(&replay());
+}
+
+/// Runs `f` on the store after expiring stale entries, then publishes the
+/// expiry events plus whatever `f` queued, outside the store lock.
+fn with_store<R>(f: impl FnOnce(&mut NotificationStore, &mut Vec<EventPayload>) -> R) -> Option<R> {
+    let (result, events) = {
+        let mut store = STORE.lock().ok()?;
+        let mut events = store.expire(Instant::now());
+        let result = f(&mut store, &mut events);
+        (result, events)
+    };
+    publish(&events);
+    Some(result)
+}
+
+/// Single exit for every event so delivery rules apply to Added, Modified
+/// and Removed alike. `pacing` decides what goes out now.
+fn publish(events: &[EventPayload]) {
+    OUTLET.publish(events, pacing::route, sent);
+}
+
+/// Sends each event to its own connection.
+pub(super) fn deliver(deliveries: &[Delivery]) {
+    OUTLET.deliver(deliveries, sent);
+}
+
+fn sent(delivery: &Delivery, result: Result<(), String>) {
+    let conn_handle = delivery.conn_handle;
+    match result {
+        Ok(()) => debug!("ANCS event {:02X?} to conn {conn_handle}", delivery.payload),
+        Err(err) => {
+            sessions::on_notify_failure(conn_handle);
+            warn!("Failed to send ANCS event to conn {conn_handle}: {err}");
+        }
+    }
+    if !delivery.held.is_zero() {
+        sessions::on_paced(conn_handle, delivery.held);
+    }
+}
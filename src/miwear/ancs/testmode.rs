@@ -0,0 +1,99 @@
+//! Regression mode for driving the ANCS service from a host script with a
+//! plain BLE dongle: no encryption requirement, no phantom traffic, and a
+//! fixed notification sequence so every boot serves the same bytes.
+//!
+//! Only compiled with the `ancs-testmode` feature, so release images cannot
+//! turn it on. Latched at boot like demo mode.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::bail;
+use log::warn;
+
+use super::{app_names, store};
+use crate::settings::{self, SettingKey};
+
+pub const TEST_MODE: SettingKey<bool> = SettingKey::new("ancs_testmode", "false");
+
+/// `(app id, display name, title, subtitle, message, category)`, served as
+/// UIDs 1.. on a fresh boot.
+const SEQUENCE: [(&str, &str, &str, &str, &str, u8); 3] = [
+    (
+        "com.astrobox.scenario",
+        "Scenario",
+        "First",
+        "",
+        "Deterministic notification one",
+        store::CATEGORY_OTHER,
+    ),
+    (
+        "com.astrobox.scenario",
+        "Scenario",
+        "Second",
+        "Subtitle",
+        "Deterministic notification two",
+        4,
+    ),
+    (
+        "com.astrobox.scenario.cjk",
+        "测试应用",
+        "第三条",
+        "",
+        "确定性通知三",
+        6,
+    ),
+];
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn init() {
+    ACTIVE.store(settings::get(&TEST_MODE), Ordering::Relaxed);
+    if active() {
+        warn!("ANCS test mode: encryption not required, fixed notification sequence");
+        crate::journal!("ANCS test mode active");
+    }
+}
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+pub fn seed() {
+    for (app_id, app_name, title, subtitle, message, category) in SEQUENCE {
+        app_names::learn(app_id, app_name);
+        store::insert_quietly(store::NewNotification {
+            app_id: app_id.to_string(),
+            app_name: Some(app_name.to_string()),
+            title: title.to_string(),
+            subtitle: subtitle.to_string(),
+            message: message.to_string(),
+            category,
+            silent: false,
+            ..Default::default()
+        });
+    }
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "ancs",
+        "testmode on | off | status: relaxed, deterministic ANCS (needs a reboot)",
+        |args| match args {
+            ["testmode", "on"] => {
+                settings::set(&TEST_MODE, &true)?;
+                Ok("ANCS test mode on after reboot".to_string())
+            }
+            ["testmode", "off"] => {
+                settings::set(&TEST_MODE, &false)?;
+                Ok("ANCS test mode off after reboot".to_string())
+            }
+            ["testmode"] | ["testmode", "status"] => Ok(format!(
+                "running: {}, after reboot: {}, contract v{}",
+                active(),
+                settings::get(&TEST_MODE),
+                super::protocol::CONTRACT_VERSION
+            )),
+            _ => bail!("usage: ancs testmode on | off | status"),
+        },
+    );
+}
@@ -0,0 +1,254 @@
+//! Reports the watch's actions on a notification back to whoever posted it.
+//! A `/notify` POST may carry a `callback_url`; when the watch performs the
+//! positive or negative action on that entry, the URL gets a JSON POST with
+//! the uid, the action and a timestamp.
+//!
+//! The Control Point callback only queues the job. A low-priority thread
+//! sends it with a per-attempt timeout and one retry, so BLE never waits on
+//! the network. URLs must be http or https to a host on [`ALLOWED_HOSTS`],
+//! checked when the notification is posted and again before sending, so the
+//! module cannot be pointed at arbitrary LAN hosts.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use esp_idf_svc::{
+    hal::task::thread::ThreadSpawnConfiguration,
+    http::client::{Client, Configuration, EspHttpConnection},
+    io::Write,
+};
+use log::{info, warn};
+use serde_json::json;
+
+use super::store;
+use crate::{
+    metrics::{self, Counter},
+    settings::{self, SettingKey},
+};
+
+/// Comma-separated host names callbacks may target; empty refuses them all.
+pub const ALLOWED_HOSTS: SettingKey<String> = SettingKey::new("hook_hosts", "");
+
+const QUEUE_DEPTH: usize = 8;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+/// Room for a TLS handshake.
+const STACK_SIZE: usize = 10 * 1024;
+/// Below the NimBLE host and the UI.
+const PRIORITY: u8 = 2;
+
+static QUEUE: Mutex<Option<SyncSender<Job>>> = Mutex::new(None);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Positive,
+    Negative,
+}
+
+impl Action {
+    /// ANCS ActionID.
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Action::Positive),
+            1 => Some(Action::Negative),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Action::Positive => "positive",
+            Action::Negative => "negative",
+        }
+    }
+}
+
+struct Job {
+    uid: u32,
+    action: Action,
+    url: String,
+    timestamp: u64,
+}
+
+/// Checks `url` against the scheme and host rules. For `/notify` input.
+pub fn check_url(url: &str) -> Result<()> {
+    let host = host_of(url)?;
+    let allowed = settings::get(&ALLOWED_HOSTS);
+    if !allowed
+        .split(',')
+        .map(str::trim)
+        .any(|entry| !entry.is_empty() && entry.eq_ignore_ascii_case(host))
+    {
+        bail!("callback host {host} is not in {}", ALLOWED_HOSTS.name);
+    }
+    Ok(())
+}
+
+fn host_of(url: &str) -> Result<&str> {
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .ok_or_else(|| anyhow!("callback must be an http or https URL"))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    if authority.contains('@') {
+        bail!("callback URL must not carry credentials");
+    }
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or(""),
+        None => authority.split(':').next().unwrap_or(""),
+    };
+    if host.is_empty() {
+        bail!("callback URL has no host");
+    }
+    Ok(host)
+}
+
+/// Starts the sender thread if it is not running. Called when a callback is
+/// accepted, and at boot when hosts are configured, never from BLE.
+pub fn ensure_started() -> Result<()> {
+    let mut queue = QUEUE
+        .lock()
+        .map_err(|_| anyhow!("webhook queue poisoned"))?;
+    if queue.is_some() {
+        return Ok(());
+    }
+    let (tx, rx) = mpsc::sync_channel(QUEUE_DEPTH);
+    ThreadSpawnConfiguration {
+        priority: PRIORITY,
+        ..Default::default()
+    }
+    .set()?;
+    let spawned = thread::Builder::new()
+        .name("webhooks".into())
+        .stack_size(STACK_SIZE)
+        .spawn(move || run(rx));
+    ThreadSpawnConfiguration::default().set()?;
+    spawned.context("spawn webhook sender")?;
+    *queue = Some(tx);
+    info!("Webhook sender started");
+    Ok(())
+}
+
+/// Boot hook: only spends the thread when callbacks can be accepted at all.
+pub fn start() -> Result<()> {
+    if settings::get(&ALLOWED_HOSTS).trim().is_empty() {
+        return Ok(());
+    }
+    ensure_started()
+}
+
+/// Control Point Perform Notification Action. Queues a callback if the
+/// entry has one; entries without are left alone. Never blocks.
+pub fn on_action(uid: u32, action_id: u8) {
+    let Some(action) = Action::from_id(action_id) else {
+        return;
+    };
+    let Some(url) = store::get(uid).and_then(|entry| entry.callback_url) else {
+        return;
+    };
+    let job = Job {
+        uid,
+        action,
+        url,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0),
+    };
+    let queued = QUEUE
+        .lock()
+        .ok()
+        .and_then(|queue| queue.as_ref().map(|tx| tx.try_send(job)));
+    if !matches!(queued, Some(Ok(()))) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        metrics::record(Counter::WebhookFailures, 1);
+        warn!("Webhook for uid {uid} dropped: sender busy or not running");
+    }
+}
+
+fn run(rx: Receiver<Job>) {
+    while let Ok(job) = rx.recv() {
+        let body = json!({
+            "uid": job.uid,
+            "action": job.action.code(),
+            "timestamp": job.timestamp,
+        })
+        .to_string();
+        let mut result = send(&job.url, &body);
+        if let Err(err) = &result {
+            warn!("Webhook for uid {} failed, retrying: {err:#}", job.uid);
+            thread::sleep(RETRY_AFTER);
+            result = send(&job.url, &body);
+        }
+        match result {
+            Ok(()) => {
+                metrics::record(Counter::WebhooksSent, 1);
+                info!("Webhook sent: uid {} {}", job.uid, job.action.code());
+            }
+            Err(err) => {
+                metrics::record(Counter::WebhookFailures, 1);
+                warn!("Webhook for uid {} gave up: {err:#}", job.uid);
+            }
+        }
+    }
+}
+
+fn send(url: &str, body: &str) -> Result<()> {
+    // The allowlist may have changed since the notification was posted.
+    check_url(url)?;
+    let connection = EspHttpConnection::new(&Configuration {
+        timeout: Some(TIMEOUT),
+        crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+        ..Default::default()
+    })?;
+    let mut client = Client::wrap(connection);
+    let length = body.len().to_string();
+    let headers = [
+        ("Content-Type", "application/json"),
+        ("Content-Length", length.as_str()),
+    ];
+    let mut request = client.post(url, &headers)?;
+    request.write_all(body.as_bytes())?;
+    request.flush()?;
+    let status = request.submit()?.status();
+    if !(200..300).contains(&status) {
+        bail!("HTTP {status}");
+    }
+    Ok(())
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "webhooks",
+        "hosts <a,b> | hosts clear: notification action callbacks",
+        |args| match args {
+            [] => Ok(format!(
+                "hosts: {}; {} dropped since boot",
+                match settings::get(&ALLOWED_HOSTS) {
+                    hosts if hosts.is_empty() => "none, callbacks refused".to_string(),
+                    hosts => hosts,
+                },
+                DROPPED.load(Ordering::Relaxed)
+            )),
+            ["hosts", "clear"] => {
+                settings::set(&ALLOWED_HOSTS, &String::new())?;
+                Ok("callbacks refused".to_string())
+            }
+            ["hosts", hosts] => {
+                settings::set(&ALLOWED_HOSTS, &hosts.to_string())?;
+                ensure_started()?;
+                Ok(format!("callback hosts: {hosts}"))
+            }
+            _ => bail!("usage: webhooks [hosts <a,b> | hosts clear]"),
+        },
+    );
+}
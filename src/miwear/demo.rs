@@ -0,0 +1,227 @@
+//! Fake watch session for UI work and demos. Replaces the supervisor so BLE
+//! is never brought up; all fake state lives in RAM and only the `demo_mode`
+//! flag itself is stored.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::info;
+use tokio::time;
+
+use super::status::{self, ConnectionPhase, TransferProgress, WatchLink, WatchTelemetry};
+use crate::{
+    ble::link::Phy,
+    settings::{self, SettingKey},
+};
+
+pub const DEMO_MODE: SettingKey<bool> = SettingKey::new("demo_mode", "false");
+
+const DEMO_NAME: &str = "Demo Watch";
+const DEMO_ADDR: &str = "02:de:00:00:00:01";
+const DEMO_INTERVAL_MS: f32 = 30.0;
+const PHASE_STEP: Duration = Duration::from_millis(800);
+const TICK: Duration = Duration::from_millis(250);
+const TELEMETRY_EVERY_TICKS: u32 = 4;
+#[cfg(feature = "ancs")]
+const NOTIFY_EVERY_TICKS: u32 = 4 * 45;
+const INSTALL_DURATION: Duration = Duration::from_secs(20);
+
+/// Latched at boot; toggling the setting takes effect after a reboot.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static INSTALL: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+pub fn init() {
+    ACTIVE.store(settings::get(&DEMO_MODE), Ordering::Relaxed);
+    if enabled() {
+        info!("Demo mode: faking a watch session, BLE stays off");
+    }
+}
+
+pub fn enabled() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Queues a fake install; the session task advances it.
+pub fn start_install(package: &str) -> Result<()> {
+    if !enabled() {
+        bail!("installs can only be faked in demo mode");
+    }
+    let mut slot = INSTALL
+        .lock()
+        .map_err(|_| anyhow::anyhow!("install state poisoned"))?;
+    if slot.is_some() {
+        bail!("an install is already running");
+    }
+    *slot = Some((package.to_string(), Instant::now()));
+    Ok(())
+}
+
+/// Drops a running fake install, e.g. before a reboot.
+pub fn cancel_install() {
+    let Ok(mut slot) = INSTALL.lock() else {
+        return;
+    };
+    if let Some((package, _)) = slot.take() {
+        info!("Demo install of {package} cancelled");
+        status::set_transfer(None);
+    }
+}
+
+pub async fn run() {
+    for phase in [
+        ConnectionPhase::Scanning,
+        ConnectionPhase::Connecting,
+        ConnectionPhase::Handshaking,
+    ] {
+        status::set_phase(phase);
+        time::sleep(PHASE_STEP).await;
+    }
+    status::set_link(Some(WatchLink {
+        phy: Some(Phy::TwoM),
+        interval_ms: Some(DEMO_INTERVAL_MS),
+    }));
+    status::set_phase(ConnectionPhase::Ready {
+        addr: DEMO_ADDR.to_string(),
+    });
+    #[cfg(feature = "ancs")]
+    fake_peer();
+
+    let mut rng = 0x2545_F491u32;
+    let mut tick = 0u32;
+    loop {
+        if tick % TELEMETRY_EVERY_TICKS == 0 {
+            status::set_telemetry(Some(telemetry(tick / TELEMETRY_EVERY_TICKS, &mut rng)));
+        }
+        #[cfg(feature = "ancs")]
+        if tick % NOTIFY_EVERY_TICKS == 0 {
+            fake_notification(tick / NOTIFY_EVERY_TICKS);
+        }
+        advance_install();
+        tick = tick.wrapping_add(1);
+        time::sleep(TICK).await;
+    }
+}
+
+/// Battery drains a point a minute and recharges at 20%; traffic wanders
+/// around a few KB/s so the meter has something to animate.
+fn telemetry(second: u32, rng: &mut u32) -> WatchTelemetry {
+    let cycle = second / 60 % 160;
+    let battery = if cycle < 80 { 100 - cycle } else { cycle - 60 };
+    let mut jitter = || {
+        *rng ^= *rng << 13;
+        *rng ^= *rng >> 17;
+        *rng ^= *rng << 5;
+        (*rng % 4096) as f32
+    };
+    let transferring = INSTALL.lock().map(|slot| slot.is_some()).unwrap_or(false);
+    let base = if transferring { 24_576.0 } else { 1_024.0 };
+    // Called once per demo second, so the byte counts double as rates.
+    super::net_meter::record_rx((512.0 + jitter()) as usize);
+    super::net_meter::record_tx((base + jitter()) as usize);
+    WatchTelemetry {
+        name: DEMO_NAME.to_string(),
+        battery: Some(battery.min(100) as u8),
+    }
+}
+
+fn advance_install() {
+    let Ok(mut slot) = INSTALL.lock() else {
+        return;
+    };
+    let Some((package, started)) = slot.as_ref() else {
+        return;
+    };
+    let fraction = started.elapsed().as_secs_f32() / INSTALL_DURATION.as_secs_f32();
+    if fraction < 1.0 {
+        status::set_transfer(Some(TransferProgress {
+            label: format!("Installing {package}"),
+            fraction,
+        }));
+        return;
+    }
+    info!("Demo install of {package} finished");
+    *slot = None;
+    status::set_transfer(None);
+}
+
+#[cfg(feature = "ancs")]
+fn fake_peer() {
+    use super::ancs::clients::{self, ClientInfo};
+
+    clients::insert_transient(ClientInfo {
+        conn_handle: 0,
+        addr: "02:de:00:00:00:02".to_string(),
+        name: Some("Demo Phone".to_string()),
+        encrypted: true,
+        first_seen: 0,
+        last_seen: 0,
+        phy: Some(Phy::TwoM),
+        interval_ms: Some(DEMO_INTERVAL_MS),
+    });
+    super::ancs::pairing::show_passkey(0, 123_456);
+}
+
+#[cfg(feature = "ancs")]
+fn fake_notification(index: u32) {
+    use super::ancs::{self, store::NewNotification};
+
+    const SAMPLES: [(&str, &str, &str, &str); 3] = [
+        (
+            "com.demo.chat",
+            "Chat",
+            "Alex",
+            "Are we still on for lunch?",
+        ),
+        ("com.demo.mail", "Mail", "Build bot", "Nightly build passed"),
+        (
+            "com.demo.calendar",
+            "Calendar",
+            "Standup",
+            "Starts in 10 minutes",
+        ),
+    ];
+    let (app_id, app_name, title, message) = SAMPLES[index as usize % SAMPLES.len()];
+    ancs::publish_notification(NewNotification {
+        app_id: app_id.to_string(),
+        app_name: Some(app_name.to_string()),
+        title: title.to_string(),
+        message: message.to_string(),
+        ..Default::default()
+    });
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "demo",
+        "on | off | install [package]: fake watch session (on/off need a reboot)",
+        |args| match args {
+            ["on"] => {
+                settings::set(&DEMO_MODE, &true)?;
+                Ok("demo mode on after reboot".to_string())
+            }
+            ["off"] => {
+                settings::set(&DEMO_MODE, &false)?;
+                Ok("demo mode off after reboot".to_string())
+            }
+            ["install"] => {
+                start_install("com.demo.app")?;
+                Ok("fake install started".to_string())
+            }
+            ["install", package] => {
+                start_install(package)?;
+                Ok(format!("fake install of {package} started"))
+            }
+            [] => Ok(format!(
+                "demo mode {}",
+                if enabled() { "active" } else { "inactive" }
+            )),
+            _ => bail!("usage: demo on | off | install [package]"),
+        },
+    );
+}
@@ -0,0 +1,177 @@
+//! Stall detection for the 0x005E notify path. Some watch firmwares stop
+//! notifying after a while without dropping the link, leaving corelib
+//! waiting forever. Every 0x005F write is answered by the watch (at least a
+//! SAR ack), so a write followed by [`STALL_WINDOW_SECS`] of silence means
+//! the link is stuck; with nothing written, silence is just an idle watch.
+//!
+//! Recovery climbs three rungs: re-write the CCCD, read 0x005E directly
+//! (some stacks hand over the pending value that way), then drop the link
+//! and let the supervisor reconnect.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use esp32_nimble::BLERemoteCharacteristic;
+use log::{info, warn};
+
+use crate::settings::{self, SettingKey};
+
+/// Silence after an unanswered write before recovery starts.
+pub const STALL_WINDOW_SECS: SettingKey<u64> = SettingKey::new("miwear_stall_s", "10");
+/// How long each rung gets to bring traffic back before the next one.
+const RUNG_WAIT: Duration = Duration::from_secs(3);
+const RUNG_POLL: Duration = Duration::from_millis(100);
+
+/// Uses per rung, indexed by `Rung as usize`, and stalls that cleared
+/// before the disconnect rung. Lifetime counts, not per session.
+static RUNG_USES: [AtomicU32; 3] = [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
+static RECOVERED: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rung {
+    Resubscribe,
+    Read,
+    Disconnect,
+}
+
+impl fmt::Display for Rung {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Rung::Resubscribe => "re-subscribe",
+            Rung::Read => "read 0x005E",
+            Rung::Disconnect => "disconnect",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StallStats {
+    pub resubscribes: u32,
+    pub reads: u32,
+    pub disconnects: u32,
+    /// Stalls cleared by the first two rungs.
+    pub recovered: u32,
+}
+
+/// Per-session traffic clock, shared with the NimBLE notify callback.
+pub struct Watch {
+    base: Instant,
+    /// Milliseconds since `base` (plus one) of the oldest write with no
+    /// notification since; zero while nothing is outstanding.
+    unanswered_since: AtomicU64,
+}
+
+impl Watch {
+    pub fn new() -> Self {
+        Self {
+            base: Instant::now(),
+            unanswered_since: AtomicU64::new(0),
+        }
+    }
+
+    /// Call before handing a batch to 0x005F, so an answer that lands while
+    /// the write is still completing clears it.
+    pub fn on_tx(&self) {
+        let now = self.now_ms();
+        let _ =
+            self.unanswered_since
+                .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    pub fn on_rx(&self) {
+        self.unanswered_since.store(0, Ordering::Relaxed);
+    }
+
+    /// How long the oldest unanswered write has waited.
+    pub fn silent_for(&self) -> Option<Duration> {
+        match self.unanswered_since.load(Ordering::Relaxed) {
+            0 => None,
+            since => Some(Duration::from_millis(self.now_ms().saturating_sub(since))),
+        }
+    }
+
+    pub fn stalled(&self) -> bool {
+        let window = Duration::from_secs(settings::get(&STALL_WINDOW_SECS).max(1));
+        self.silent_for().is_some_and(|silent| silent >= window)
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.base.elapsed().as_millis() as u64 + 1
+    }
+}
+
+/// Tries the non-destructive rungs in turn. Returns the rung that brought
+/// traffic back, or [`Rung::Disconnect`] when the caller must drop the link.
+/// `deliver` feeds a value read back from 0x005E into corelib.
+pub async fn recover(
+    ch_recv: &mut BLERemoteCharacteristic,
+    watch: &Watch,
+    deliver: impl Fn(&[u8]),
+) -> Rung {
+    let silent = watch.silent_for().unwrap_or_default();
+    warn!("0x005E silent for {silent:?} with a write outstanding");
+    crate::journal!("Watch link stalled for {}s", silent.as_secs());
+
+    count(Rung::Resubscribe);
+    if let Err(err) = ch_recv.subscribe_notify(true).await {
+        warn!("Re-subscribing 0x005E failed: {err:?}");
+    }
+    if answered(watch).await {
+        return cleared(Rung::Resubscribe);
+    }
+
+    if ch_recv.can_read() {
+        count(Rung::Read);
+        match ch_recv.read_value().await {
+            Ok(value) if !value.is_empty() => {
+                info!("Read back {} pending bytes from 0x005E", value.len());
+                watch.on_rx();
+                deliver(&value);
+            }
+            Ok(_) => {}
+            Err(err) => warn!("Reading 0x005E failed: {err:?}"),
+        }
+        if answered(watch).await {
+            return cleared(Rung::Read);
+        }
+    }
+
+    count(Rung::Disconnect);
+    crate::journal!("Watch link stall not recovered, reconnecting");
+    Rung::Disconnect
+}
+
+pub fn stats() -> StallStats {
+    let uses = |rung: Rung| RUNG_USES[rung as usize].load(Ordering::Relaxed);
+    StallStats {
+        resubscribes: uses(Rung::Resubscribe),
+        reads: uses(Rung::Read),
+        disconnects: uses(Rung::Disconnect),
+        recovered: RECOVERED.load(Ordering::Relaxed),
+    }
+}
+
+fn count(rung: Rung) {
+    RUNG_USES[rung as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+fn cleared(rung: Rung) -> Rung {
+    RECOVERED.fetch_add(1, Ordering::Relaxed);
+    info!("0x005E traffic back after {rung}");
+    crate::journal!("Watch link stall recovered by {rung}");
+    rung
+}
+
+async fn answered(watch: &Watch) -> bool {
+    let deadline = Instant::now() + RUNG_WAIT;
+    while Instant::now() < deadline {
+        if watch.silent_for().is_none() {
+            return true;
+        }
+        tokio::time::sleep(RUNG_POLL).await;
+    }
+    watch.silent_for().is_none()
+}
@@ -0,0 +1,75 @@
+//! Per-session log context for the MiWear link. Each session gets an id
+//! that counts up from 1 per boot and logs under `miwear::<addr>`, so lines
+//! from overlapping sessions can be told apart and `log miwear::<addr>
+//! trace` turns up a single watch.
+//!
+//! corelib offers no context hook, so its traffic is attributed at the two
+//! places it crosses into this crate: the send callback and the packet
+//! dispatcher entry. Packet dumps are only formatted when their target is
+//! enabled at trace level.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use log::{log_enabled, trace, Level};
+
+pub mod filter;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Clone, Debug)]
+pub struct SessionLog {
+    id: u32,
+    target: Arc<str>,
+}
+
+impl SessionLog {
+    /// Allocates the next session id for a link to `addr`.
+    pub fn begin(addr: &str) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            target: Arc::from(format!("miwear::{addr}")),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The log target for this session's lines.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// A write to 0x005F, as handed over by corelib.
+    pub fn tx(&self, data: &[u8]) {
+        self.packet("tx", data);
+    }
+
+    /// A 0x005E notification, before it reaches corelib's dispatcher.
+    pub fn rx(&self, data: &[u8]) {
+        self.packet("rx", data);
+    }
+
+    fn packet(&self, direction: &str, data: &[u8]) {
+        if log_enabled!(target: self.target(), Level::Trace) {
+            trace!(
+                target: self.target(),
+                "{self} {direction} {} B: {}",
+                data.len(),
+                corelib::tools::to_hex_string(data)
+            );
+        }
+    }
+}
+
+impl fmt::Display for SessionLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s{}", self.id)
+    }
+}
@@ -0,0 +1,20 @@
+//! One session's lines picked out of a log dump by its `s<id>` tag.
+
+/// The lines of `text` carrying session `id`'s tag as a whole word, in
+/// order, each with its newline.
+pub fn session_lines(text: &str, id: u32) -> String {
+    let tag = format!("s{id}");
+    text.split_inclusive('\n')
+        .filter(|line| has_word(line, &tag))
+        .collect()
+}
+
+/// `word` in `line` with no letter or digit on either side, so `s1` does
+/// not match `s12` or `reasons1`.
+fn has_word(line: &str, word: &str) -> bool {
+    line.match_indices(word).any(|(at, _)| {
+        let before = line[..at].chars().next_back();
+        let after = line[at + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
@@ -0,0 +1,356 @@
+//! Media remote: playback state read from the watch's music component, and
+//! play/pause, track and volume commands sent back to it. Commands go
+//! through one worker on the main thread. The UI sees a predicted state
+//! until the watch reports again.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    demo,
+    status::{self, ConnectionPhase},
+};
+
+/// Repeats of the same command inside this window are dropped.
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+/// How long the watch gets to acknowledge a command.
+const ACK_TIMEOUT: Duration = Duration::from_secs(3);
+/// A prediction the watch never confirms or contradicts is dropped after this.
+const PREDICTION_LIMIT: Duration = Duration::from_secs(3);
+/// Weight of the newest sample in the latency average.
+const LATENCY_WEIGHT: f32 = 0.25;
+
+static STATE: Mutex<State> = Mutex::new(State {
+    reported: None,
+    predicted: None,
+    last_sent: None,
+    latency: None,
+});
+static DEMO_PLAYBACK: Mutex<Option<Playback>> = Mutex::new(None);
+static REQUESTS: OnceLock<mpsc::UnboundedSender<Request>> = OnceLock::new();
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// What the watch is playing. `None` from the watch means no active session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Playback {
+    pub title: String,
+    pub artist: String,
+    pub playing: bool,
+    /// 0..=100
+    pub volume: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaCommand {
+    PlayPause,
+    Previous,
+    Next,
+    /// Absolute level, 0..=100.
+    Volume(u8),
+}
+
+impl MediaCommand {
+    pub fn label(self) -> &'static str {
+        match self {
+            MediaCommand::PlayPause => "play/pause",
+            MediaCommand::Previous => "previous",
+            MediaCommand::Next => "next",
+            MediaCommand::Volume(_) => "volume",
+        }
+    }
+
+    /// Commands of one kind debounce together, whatever their volume level.
+    fn same_kind(self, other: MediaCommand) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    /// The state the watch should report once it has acted on the command.
+    fn predict(self, current: &Playback) -> Playback {
+        let mut next = current.clone();
+        match self {
+            MediaCommand::PlayPause => next.playing = !current.playing,
+            // The new title only comes from the watch; keep playing meanwhile.
+            MediaCommand::Previous | MediaCommand::Next => next.playing = true,
+            MediaCommand::Volume(level) => next.volume = level.min(100),
+        }
+        next
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MediaError {
+    NotConnected,
+    /// Nothing is playing on the watch.
+    NoSession,
+    /// The watch has no music component.
+    Unsupported,
+    /// Same command within [`DEBOUNCE`]; nothing was sent.
+    Debounced,
+    Timeout,
+    /// The media worker is not running.
+    Unavailable,
+    Failed(String),
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaError::NotConnected => f.write_str("watch not connected"),
+            MediaError::NoSession => f.write_str("nothing playing on the watch"),
+            MediaError::Unsupported => f.write_str("watch has no music control"),
+            MediaError::Debounced => f.write_str("repeated too quickly"),
+            MediaError::Timeout => f.write_str("watch did not answer"),
+            MediaError::Unavailable => f.write_str("media worker not running"),
+            MediaError::Failed(reason) => write!(f, "music control failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MediaError {}
+
+/// Command round trip, send to watch acknowledgement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Latency {
+    pub last: Duration,
+    /// Exponential average since the watch connected.
+    pub average: Duration,
+    pub samples: u32,
+}
+
+struct State {
+    reported: Option<Playback>,
+    predicted: Option<(Playback, Instant)>,
+    last_sent: Option<(MediaCommand, Instant)>,
+    latency: Option<Latency>,
+}
+
+struct Request {
+    command: MediaCommand,
+    reply: oneshot::Sender<Result<(), MediaError>>,
+}
+
+/// Serves queued commands; spawn once on the main `LocalSet`.
+pub async fn run() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if REQUESTS.set(tx).is_err() {
+        warn!("Media worker already running");
+        return;
+    }
+    while let Some(Request { command, reply }) = rx.recv().await {
+        let sent = Instant::now();
+        let result = match tokio::time::timeout(ACK_TIMEOUT, send(command)).await {
+            Ok(result) => result,
+            Err(_) => Err(MediaError::Timeout),
+        };
+        match &result {
+            Ok(()) => record_latency(sent.elapsed()),
+            Err(err) => {
+                warn!("Media {} failed: {err}", command.label());
+                // Show the watch's last word instead of a guess that failed.
+                drop_prediction();
+            }
+        }
+        let _ = reply.send(result);
+    }
+}
+
+/// Queues `command` right away, so [`playback`] shows the prediction when
+/// this returns; the future resolves with the watch's acknowledgement.
+pub fn request(command: MediaCommand) -> impl Future<Output = Result<(), MediaError>> {
+    let queued = enqueue(command);
+    async move { queued?.await.map_err(|_| MediaError::Unavailable)? }
+}
+
+fn enqueue(command: MediaCommand) -> Result<oneshot::Receiver<Result<(), MediaError>>, MediaError> {
+    if !connected() {
+        return Err(MediaError::NotConnected);
+    }
+    let requests = REQUESTS.get().ok_or(MediaError::Unavailable)?;
+    {
+        let mut state = STATE.lock().map_err(|_| MediaError::Unavailable)?;
+        let now = Instant::now();
+        if state
+            .last_sent
+            .is_some_and(|(last, at)| last.same_kind(command) && now.duration_since(at) < DEBOUNCE)
+        {
+            return Err(MediaError::Debounced);
+        }
+        let current = current_in(&state).ok_or(MediaError::NoSession)?;
+        state.predicted = Some((command.predict(&current), now));
+        state.last_sent = Some((command, now));
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    let (reply, wait) = oneshot::channel();
+    requests
+        .send(Request { command, reply })
+        .map_err(|_| MediaError::Unavailable)?;
+    Ok(wait)
+}
+
+/// Reads the watch's playback state. A changed report replaces the
+/// prediction; so does the prediction outliving [`PREDICTION_LIMIT`].
+pub async fn refresh() {
+    let connected = connected();
+    let reported = if !connected {
+        None
+    } else if demo::enabled() {
+        Some(demo_playback())
+    } else {
+        super::read_playback().await
+    };
+    let Ok(mut state) = STATE.lock() else {
+        return;
+    };
+    if !connected {
+        state.latency = None;
+    }
+    let expired = state
+        .predicted
+        .as_ref()
+        .is_some_and(|(_, at)| at.elapsed() >= PREDICTION_LIMIT);
+    // A command the watch has not acted on yet still reports the old
+    // state, so only a change confirms or overrides the prediction.
+    if state.reported != reported || expired {
+        if state.predicted.is_some() && state.reported != reported {
+            debug!("Media state reconciled with watch report");
+        }
+        state.reported = reported;
+        state.predicted = None;
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Predicted state while a command is unconfirmed, else the watch's report.
+pub fn playback() -> Option<Playback> {
+    STATE.lock().ok().and_then(|state| current_in(&state))
+}
+
+pub fn latency() -> Option<Latency> {
+    STATE.lock().ok().and_then(|state| state.latency)
+}
+
+pub fn connected() -> bool {
+    matches!(status::phase(), ConnectionPhase::Ready { .. })
+}
+
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+fn current_in(state: &State) -> Option<Playback> {
+    match &state.predicted {
+        Some((predicted, _)) => Some(predicted.clone()),
+        None => state.reported.clone(),
+    }
+}
+
+fn drop_prediction() {
+    if let Ok(mut state) = STATE.lock() {
+        if state.predicted.take().is_some() {
+            GENERATION.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn record_latency(sample: Duration) {
+    let Ok(mut state) = STATE.lock() else {
+        return;
+    };
+    state.latency = Some(match state.latency {
+        None => Latency {
+            last: sample,
+            average: sample,
+            samples: 1,
+        },
+        Some(previous) => Latency {
+            last: sample,
+            average: previous.average.mul_f32(1.0 - LATENCY_WEIGHT)
+                + sample.mul_f32(LATENCY_WEIGHT),
+            samples: previous.samples.saturating_add(1),
+        },
+    });
+    debug!("Media command acknowledged in {} ms", sample.as_millis());
+}
+
+async fn send(command: MediaCommand) -> Result<(), MediaError> {
+    if demo::enabled() {
+        if let Ok(mut playback) = DEMO_PLAYBACK.lock() {
+            let current = playback.take().unwrap_or_else(demo_track);
+            *playback = Some(command.predict(&current));
+        }
+        return Ok(());
+    }
+    super::media_control(command).await
+}
+
+fn demo_playback() -> Playback {
+    match DEMO_PLAYBACK.lock() {
+        Ok(mut playback) => playback.get_or_insert_with(demo_track).clone(),
+        Err(_) => demo_track(),
+    }
+}
+
+fn demo_track() -> Playback {
+    Playback {
+        title: "Demo Track".to_string(),
+        artist: "AstroBox".to_string(),
+        playing: false,
+        volume: 50,
+    }
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "media",
+        "play|prev|next|vol <0-100>|status: control the watch's music",
+        |args| {
+            let command = match args {
+                ["play"] => MediaCommand::PlayPause,
+                ["prev"] => MediaCommand::Previous,
+                ["next"] => MediaCommand::Next,
+                ["vol", level] => MediaCommand::Volume(level.parse::<u8>()?.min(100)),
+                ["status"] => {
+                    let latency = latency()
+                        .map(|latency| {
+                            format!(
+                                ", rtt {} ms (avg {} ms)",
+                                latency.last.as_millis(),
+                                latency.average.as_millis()
+                            )
+                        })
+                        .unwrap_or_default();
+                    return Ok(match playback() {
+                        Some(playback) => format!(
+                            "{} - {} [{}] vol {}{latency}",
+                            playback.artist,
+                            playback.title,
+                            if playback.playing {
+                                "playing"
+                            } else {
+                                "paused"
+                            },
+                            playback.volume
+                        ),
+                        None => format!("no active playback{latency}"),
+                    });
+                }
+                _ => anyhow::bail!("usage: media play|prev|next|vol <0-100>|status"),
+            };
+            let reply = enqueue(command)?;
+            reply
+                .blocking_recv()
+                .map_err(|_| MediaError::Unavailable)??;
+            Ok(format!("sent {}", command.label()))
+        },
+    );
+}
@@ -0,0 +1,188 @@
+//! Watch link throughput from our own byte counters. corelib's `last_speed`
+//! covers its own window, which beats against our 1 s ticker and made the
+//! meter jump between 0 and twice the real rate; here the rate is taken over
+//! our sampling interval and smoothed.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use super::status::{self, ConnectionPhase};
+use crate::{
+    metrics,
+    settings::{self, SettingKey},
+};
+
+/// Weight of the newest interval in the smoothed rate, 0 < alpha <= 1.
+pub const SMOOTHING: SettingKey<f32> = SettingKey::new("net_smoothing", "0.3");
+const DEFAULT_SMOOTHING: f32 = 0.3;
+
+/// Raw counters bumped from the write and notify paths; they wrap, and the
+/// sampler only looks at differences.
+static TX_BYTES: AtomicU32 = AtomicU32::new(0);
+static RX_BYTES: AtomicU32 = AtomicU32::new(0);
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+static METER: Mutex<Meter> = Mutex::new(Meter::new());
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Direction {
+    /// Smoothed bytes per second.
+    pub current_bps: f32,
+    /// Highest single-interval rate this session.
+    pub peak_bps: f32,
+    pub total_bytes: u64,
+}
+
+impl Direction {
+    fn add(&mut self, bytes: u32, seconds: f32, alpha: f32, first: bool) {
+        let rate = bytes as f32 / seconds;
+        self.current_bps = if first {
+            rate
+        } else {
+            self.current_bps + (rate - self.current_bps) * alpha
+        };
+        self.peak_bps = self.peak_bps.max(rate);
+        self.total_bytes += bytes as u64;
+    }
+}
+
+/// Counters for one connection to one watch; reset on reconnect.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub addr: String,
+    pub tx: Direction,
+    pub rx: Direction,
+    samples: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct MeterSnapshot {
+    pub session: Option<Session>,
+    pub lifetime_tx_bytes: u64,
+    pub lifetime_rx_bytes: u64,
+    pub smoothing: f32,
+}
+
+struct Meter {
+    last_sample: Option<Instant>,
+    last_tx: u32,
+    last_rx: u32,
+    session: Option<Session>,
+    lifetime_tx: u64,
+    lifetime_rx: u64,
+}
+
+impl Meter {
+    const fn new() -> Self {
+        Self {
+            last_sample: None,
+            last_tx: 0,
+            last_rx: 0,
+            session: None,
+            lifetime_tx: 0,
+            lifetime_rx: 0,
+        }
+    }
+}
+
+/// Bytes handed to the 0x005F characteristic.
+pub fn record_tx(bytes: usize) {
+    TX_BYTES.fetch_add(bytes as u32, Ordering::Relaxed);
+    metrics::record(metrics::Counter::BytesToWatch, bytes as u32);
+}
+
+/// Bytes received as 0x005E notifications.
+pub fn record_rx(bytes: usize) {
+    RX_BYTES.fetch_add(bytes as u32, Ordering::Relaxed);
+    metrics::record(metrics::Counter::BytesFromWatch, bytes as u32);
+}
+
+pub fn smoothing() -> f32 {
+    let alpha = settings::get(&SMOOTHING);
+    if alpha > 0.0 && alpha <= 1.0 {
+        alpha
+    } else {
+        DEFAULT_SMOOTHING
+    }
+}
+
+/// Folds the bytes counted since the last call into the rates. Call on a
+/// steady interval; the first call only sets the baseline.
+pub fn sample() {
+    let now = Instant::now();
+    let tx = TX_BYTES.load(Ordering::Relaxed);
+    let rx = RX_BYTES.load(Ordering::Relaxed);
+    let ready_addr = match status::phase() {
+        ConnectionPhase::Ready { addr } => Some(addr),
+        _ => None,
+    };
+    let alpha = smoothing();
+    let Ok(mut meter) = METER.lock() else {
+        return;
+    };
+    let tx_delta = tx.wrapping_sub(meter.last_tx);
+    let rx_delta = rx.wrapping_sub(meter.last_rx);
+    let elapsed = meter
+        .last_sample
+        .map(|last| now.saturating_duration_since(last).as_secs_f32());
+    meter.last_tx = tx;
+    meter.last_rx = rx;
+    meter.last_sample = Some(now);
+    let Some(seconds) = elapsed.filter(|seconds| *seconds > f32::EPSILON) else {
+        return;
+    };
+    meter.lifetime_tx += tx_delta as u64;
+    meter.lifetime_rx += rx_delta as u64;
+
+    match ready_addr {
+        Some(addr) => {
+            let reconnected = meter
+                .session
+                .as_ref()
+                .map_or(true, |session| session.addr != addr);
+            if reconnected {
+                meter.session = Some(Session {
+                    addr,
+                    tx: Direction::default(),
+                    rx: Direction::default(),
+                    samples: 0,
+                });
+            }
+            if let Some(session) = meter.session.as_mut() {
+                let first = session.samples == 0;
+                session.tx.add(tx_delta, seconds, alpha, first);
+                session.rx.add(rx_delta, seconds, alpha, first);
+                session.samples += 1;
+            }
+        }
+        None => meter.session = None,
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn snapshot() -> MeterSnapshot {
+    let smoothing = smoothing();
+    METER
+        .lock()
+        .map(|meter| MeterSnapshot {
+            session: meter.session.clone(),
+            lifetime_tx_bytes: meter.lifetime_tx,
+            lifetime_rx_bytes: meter.lifetime_rx,
+            smoothing,
+        })
+        .unwrap_or(MeterSnapshot {
+            session: None,
+            lifetime_tx_bytes: 0,
+            lifetime_rx_bytes: 0,
+            smoothing,
+        })
+}
+
+/// Bumped per sample so UI pollers can skip unchanged state.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
@@ -0,0 +1,93 @@
+//! Keeps `status` and `media` in step with the ready watch's corelib entity.
+//! corelib raises no change notifications for the values read here, so they
+//! are polled from this one place at per-value intervals; `status` then
+//! announces whatever changed on the event bus.
+//!
+//! Each poll looks the entity up by the ready address and copies out under
+//! the ECS lock, so nothing holds on to an entity that was dropped on
+//! disconnect.
+
+use std::time::{Duration, Instant};
+
+use corelib::{device::xiaomi::XiaomiDevice, ecs::entity::EntityExt};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{
+    demo, media,
+    status::{self, ConnectionPhase, WatchTelemetry},
+};
+use crate::events::{self, SystemEvent};
+
+#[derive(Clone, Copy, Debug)]
+enum Polled {
+    Name,
+    /// Reads the music component's last report, not the radio.
+    Playback,
+}
+
+/// Everything corelib is polled for, and how often while a watch is ready.
+const POLLS: [(Polled, Duration); 2] = [
+    (Polled::Name, Duration::from_secs(30)),
+    (Polled::Playback, Duration::from_secs(1)),
+];
+/// The shortest interval in [`POLLS`].
+const TICK: Duration = Duration::from_secs(1);
+
+/// Spawn once on the main `LocalSet`. A phase change makes every value due
+/// at once, so a new link is reflected without waiting out the intervals.
+pub async fn run() {
+    let mut events = events::subscribe();
+    let mut ticker = tokio::time::interval(TICK);
+    let mut due = [Instant::now(); POLLS.len()];
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            event = events.recv() => match event {
+                Ok(SystemEvent::WatchStatusChanged) => due = [Instant::now(); POLLS.len()],
+                Err(RecvError::Closed) => return,
+                _ => continue,
+            },
+        }
+        let now = Instant::now();
+        for (next, (value, every)) in due.iter_mut().zip(POLLS) {
+            if now >= *next {
+                poll(value).await;
+                *next = now + every;
+            }
+        }
+    }
+}
+
+async fn poll(value: Polled) {
+    match value {
+        Polled::Name => refresh_name().await,
+        // Also clears the report once the watch is gone.
+        Polled::Playback => media::refresh().await,
+    }
+}
+
+/// Copies the ready watch's name from corelib into `status`.
+async fn refresh_name() {
+    if demo::enabled() {
+        return;
+    }
+    let ConnectionPhase::Ready { addr } = status::phase() else {
+        return;
+    };
+    let name = crate::timed_with_rt_mut!("watch_name", move |rt| {
+        rt.find_entity_by_id_mut::<XiaomiDevice>(&addr)
+            .map(|dev| dev.name().to_string())
+    })
+    .await;
+
+    let Some(name) = name else {
+        return;
+    };
+    let current = status::telemetry();
+    if current.as_ref().map(|watch| watch.name.as_str()) != Some(name.as_str()) {
+        status::set_telemetry(Some(WatchTelemetry {
+            name,
+            battery: current.and_then(|watch| watch.battery),
+        }));
+    }
+}
@@ -0,0 +1,115 @@
+//! Predictive reconnect. While the supervisor waits out its backoff, a
+//! low-duty passive scan listens for the advertisements of watches it has
+//! connected to before, and the first one seen ends the wait so the
+//! supervisor can connect to that address straight away instead of
+//! scanning again.
+//!
+//! Passive scanning sends no scan requests, so it leaves the air to the
+//! ANCS advertiser. At 30 ms every 1.28 s it costs little radio time, but
+//! not nothing; [`WATCH_SCAN`] turns it off on battery-powered builds.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use esp32_nimble::BLEAddress;
+use log::{info, warn};
+use tokio::time;
+
+use super::{
+    scan::{Scan, ScanMode, Verdict},
+    targets::MiWearTarget,
+};
+use crate::settings::{self, SettingKey};
+
+pub const WATCH_SCAN: SettingKey<bool> = SettingKey::new("miwear_watchscan", "true");
+
+/// 1280 ms interval, 30 ms window.
+const PASSIVE: ScanMode = ScanMode {
+    active: false,
+    interval: 2048,
+    window: 48,
+};
+
+/// Last address each target connected from, by target id.
+static PEERS: Mutex<Vec<(u32, String)>> = Mutex::new(Vec::new());
+
+/// A known watch advertised during the backoff.
+#[derive(Clone, Debug)]
+pub struct Sighted {
+    pub target_id: u32,
+    pub addr: BLEAddress,
+    pub at: Instant,
+}
+
+/// Records the address `target_id` connected from, for the next wait.
+pub fn remember(target_id: u32, addr: &BLEAddress) {
+    if let Ok(mut peers) = PEERS.lock() {
+        peers.retain(|(id, _)| *id != target_id);
+        peers.push((target_id, addr.to_string()));
+    }
+}
+
+/// Addresses to listen for: each candidate's configured address, else the
+/// one it last connected from. Candidates never connected are not watched.
+fn watched(candidates: &[MiWearTarget]) -> Vec<(u32, String)> {
+    let peers = PEERS.lock().map(|peers| peers.clone()).unwrap_or_default();
+    candidates
+        .iter()
+        .filter_map(|target| {
+            let addr = target.addr.clone().or_else(|| {
+                peers
+                    .iter()
+                    .find(|(id, _)| *id == target.id)
+                    .map(|(_, addr)| addr.clone())
+            })?;
+            Some((target.id, addr))
+        })
+        .collect()
+}
+
+/// Waits out `backoff`, or less when one of `candidates` is sighted.
+pub async fn wait(backoff: Duration, candidates: &[MiWearTarget]) -> Option<Sighted> {
+    let watched = watched(candidates);
+    if !settings::get(&WATCH_SCAN) || watched.is_empty() {
+        time::sleep(backoff).await;
+        return None;
+    }
+    let deadline = time::Instant::now() + backoff;
+    let judged = watched.clone();
+    let mut scan = Scan::start_with("reconnect", PASSIVE, backoff, None, move |sighting| {
+        let addr = sighting.addr.to_string();
+        if judged
+            .iter()
+            .any(|(_, wanted)| wanted.eq_ignore_ascii_case(&addr))
+        {
+            Verdict::ReportAndStop
+        } else {
+            Verdict::Skip
+        }
+    });
+    let sighting = scan.results_stream().recv().await;
+    let at = Instant::now();
+    if let Err(err) = scan.finish().await {
+        warn!("Reconnect scan failed: {err:#}");
+    }
+    let Some(sighting) = sighting else {
+        // Covers a scan that failed early as well as one that ran out.
+        time::sleep_until(deadline).await;
+        return None;
+    };
+    let addr = sighting.addr.to_string();
+    let (target_id, _) = watched
+        .iter()
+        .find(|(_, wanted)| wanted.eq_ignore_ascii_case(&addr))?;
+    info!(
+        "Watch {addr} sighted, cutting the backoff short by {:?}",
+        deadline.saturating_duration_since(time::Instant::now())
+    );
+    Some(Sighted {
+        target_id: *target_id,
+        addr: sighting.addr,
+        at,
+    })
+}
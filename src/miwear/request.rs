@@ -0,0 +1,217 @@
+//! Request/response round trips to the watch: timeout, retries with
+//! jittered backoff, and per-kind bookkeeping.
+//!
+//! corelib's systems register the response matcher themselves and hand back
+//! a oneshot receiver; the attempt future owns it, so a timed-out attempt or
+//! a dropped caller drops the receiver and the dispatcher discards the
+//! matcher. Requests of one kind hold the kind's lock across all their
+//! attempts, so a retry never races a duplicate.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use esp_idf_svc::sys::esp_random;
+use log::{debug, warn};
+use tokio::time;
+
+use crate::metrics::{self, Counter};
+
+pub static FIND_DEVICE: RequestKind = RequestKind::new("find_device");
+pub static QUICK_APP_LIST: RequestKind = RequestKind::new("quick_app_list");
+pub static ALARMS: RequestKind = RequestKind::new("alarms");
+
+static ALL: [&RequestKind; 3] = [&FIND_DEVICE, &QUICK_APP_LIST, &ALARMS];
+
+pub struct RequestKind {
+    name: &'static str,
+    serial: tokio::sync::Mutex<()>,
+    stats: Mutex<KindStats>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KindStats {
+    pub requests: u32,
+    pub failures: u32,
+    /// Attempts beyond the first.
+    pub retries: u32,
+    /// Callers that went away before an answer.
+    pub abandoned: u32,
+    pub last_latency: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// Per attempt.
+    pub timeout: Duration,
+    /// Attempts after the first.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each further one.
+    pub backoff: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RequestError<E> {
+    /// The last attempt got no answer in time.
+    TimedOut,
+    Failed(E),
+}
+
+/// Which failures are worth another attempt. Timeouts always are.
+pub trait Retryable {
+    fn retryable(&self) -> bool;
+}
+
+/// anyhow errors carry no class, so only their timeouts are retried.
+impl Retryable for anyhow::Error {
+    fn retryable(&self) -> bool {
+        false
+    }
+}
+
+impl RequestKind {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            serial: tokio::sync::Mutex::const_new(()),
+            stats: Mutex::new(KindStats {
+                requests: 0,
+                failures: 0,
+                retries: 0,
+                abandoned: 0,
+                last_latency: None,
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> KindStats {
+        self.stats.lock().map(|stats| *stats).unwrap_or_default()
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for RequestError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::TimedOut => f.write_str("no answer from the watch"),
+            RequestError::Failed(err) => err.fmt(f),
+        }
+    }
+}
+
+/// Records the outcome when the request ends, including by being dropped.
+struct Tracking {
+    kind: &'static RequestKind,
+    started: Instant,
+    attempts: u32,
+    outcome: Option<bool>,
+}
+
+impl Drop for Tracking {
+    fn drop(&mut self) {
+        let retries = self.attempts.saturating_sub(1);
+        let latency = self.started.elapsed();
+        metrics::record(Counter::WatchRequests, 1);
+        metrics::record(Counter::WatchRequestRetries, retries);
+        if let Ok(mut stats) = self.kind.stats.lock() {
+            stats.requests += 1;
+            stats.retries += retries;
+            match self.outcome {
+                Some(true) => stats.last_latency = Some(latency),
+                Some(false) => stats.failures += 1,
+                None => stats.abandoned += 1,
+            }
+        }
+        let outcome = match self.outcome {
+            Some(true) => "ok",
+            Some(false) => "failed",
+            None => "abandoned",
+        };
+        debug!(
+            "Request {} {outcome} after {} attempt(s), {} ms",
+            self.kind.name,
+            self.attempts,
+            latency.as_millis()
+        );
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails for good, or `policy.retries`
+/// is used up. Each attempt gets `policy.timeout`. Cancel-safe: dropping
+/// the returned future drops the attempt in flight and releases the kind.
+pub async fn send_with_retry<T, E, F, Fut>(
+    kind: &'static RequestKind,
+    policy: Policy,
+    mut attempt: F,
+) -> Result<T, RequestError<E>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Retryable + fmt::Display,
+{
+    let _serial = kind.serial.lock().await;
+    let mut tracking = Tracking {
+        kind,
+        started: Instant::now(),
+        attempts: 0,
+        outcome: None,
+    };
+    loop {
+        tracking.attempts += 1;
+        let error = match time::timeout(policy.timeout, attempt()).await {
+            Ok(Ok(value)) => {
+                tracking.outcome = Some(true);
+                return Ok(value);
+            }
+            Ok(Err(err)) if err.retryable() => RequestError::Failed(err),
+            Ok(Err(err)) => {
+                tracking.outcome = Some(false);
+                return Err(RequestError::Failed(err));
+            }
+            Err(_) => RequestError::TimedOut,
+        };
+        if tracking.attempts > policy.retries {
+            tracking.outcome = Some(false);
+            return Err(error);
+        }
+        let wait = backoff(policy.backoff, tracking.attempts);
+        warn!(
+            "Request {} attempt {} failed ({error}), retrying in {} ms",
+            kind.name,
+            tracking.attempts,
+            wait.as_millis()
+        );
+        time::sleep(wait).await;
+    }
+}
+
+/// `base` doubled per earlier retry, then scaled by 75–125 %.
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    let scaled = base.saturating_mul(1 << attempt.saturating_sub(1).min(6));
+    let percent = 75 + unsafe { esp_random() } % 51;
+    scaled * percent / 100
+}
+
+pub fn register_commands() {
+    crate::console::register("requests", "per-kind watch request stats", |args| {
+        if !args.is_empty() {
+            anyhow::bail!("usage: requests");
+        }
+        let lines: Vec<String> = ALL
+            .iter()
+            .map(|kind| {
+                let stats = kind.stats();
+                let latency = stats
+                    .last_latency
+                    .map_or_else(|| "-".to_string(), |at| format!("{} ms", at.as_millis()));
+                format!(
+                    "{}: {} requests, {} failed, {} retries, {} abandoned, last {latency}",
+                    kind.name, stats.requests, stats.failures, stats.retries, stats.abandoned
+                )
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    });
+}
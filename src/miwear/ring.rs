@@ -0,0 +1,221 @@
+//! "Ring watch": the Devices page, console and HTTP all queue requests to one
+//! worker on the main thread, so commands never overlap and the ringing
+//! state has a single writer.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    request::{self, Policy, RequestError, Retryable},
+    status::{self, ConnectionPhase},
+};
+use crate::events::{self, SystemEvent};
+
+/// How long the watch gets to acknowledge a ring or stop command, per
+/// attempt; one retry follows a timeout or a failed answer.
+const POLICY: Policy = Policy {
+    timeout: Duration::from_secs(5),
+    retries: 1,
+    backoff: Duration::from_millis(500),
+};
+/// The watch gives up ringing on its own; stop showing "Stop" after this.
+const RING_LIMIT: Duration = Duration::from_secs(30);
+
+static STATE: Mutex<State> = Mutex::new(State {
+    phase: RingPhase::Idle,
+    since: None,
+});
+static REQUESTS: OnceLock<mpsc::UnboundedSender<Request>> = OnceLock::new();
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RingPhase {
+    Idle,
+    /// A command is waiting for the watch's answer.
+    Sending,
+    Ringing,
+}
+
+impl RingPhase {
+    pub fn label(self) -> &'static str {
+        match self {
+            RingPhase::Idle => "idle",
+            RingPhase::Sending => "sending",
+            RingPhase::Ringing => "ringing",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RingError {
+    NotConnected,
+    /// The watch has no find-device support.
+    Unsupported,
+    Timeout,
+    /// The ring worker is not running.
+    Unavailable,
+    Failed(String),
+}
+
+impl fmt::Display for RingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RingError::NotConnected => f.write_str("watch not connected"),
+            RingError::Unsupported => f.write_str("watch does not support find device"),
+            RingError::Timeout => f.write_str("watch did not answer"),
+            RingError::Unavailable => f.write_str("ring worker not running"),
+            RingError::Failed(reason) => write!(f, "find device failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for RingError {}
+
+impl Retryable for RingError {
+    fn retryable(&self) -> bool {
+        matches!(self, RingError::Failed(_))
+    }
+}
+
+struct State {
+    phase: RingPhase,
+    since: Option<Instant>,
+}
+
+struct Request {
+    ring: bool,
+    reply: oneshot::Sender<Result<(), RingError>>,
+}
+
+/// Serves queued requests; spawn once on the main `LocalSet`.
+pub async fn run() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if REQUESTS.set(tx).is_err() {
+        warn!("Ring worker already running");
+        return;
+    }
+    while let Some(Request { ring, reply }) = rx.recv().await {
+        let previous = phase();
+        set_phase(RingPhase::Sending);
+        let result =
+            request::send_with_retry(&request::FIND_DEVICE, POLICY, || super::find_device(ring))
+                .await
+                .map_err(|err| match err {
+                    RequestError::TimedOut => RingError::Timeout,
+                    RequestError::Failed(err) => err,
+                });
+        match (&result, ring) {
+            (Ok(()), true) => start_ringing(),
+            (Ok(()), false) => set_phase(RingPhase::Idle),
+            // A failed stop leaves the watch as it was.
+            (Err(_), false) => set_phase(previous),
+            (Err(_), true) => set_phase(RingPhase::Idle),
+        }
+        match &result {
+            Ok(()) => info!("Find device: {}", if ring { "ringing" } else { "stopped" }),
+            Err(err) => warn!(
+                "Find device ({}) failed: {err}",
+                if ring { "ring" } else { "stop" }
+            ),
+        }
+        let _ = reply.send(result);
+    }
+}
+
+/// Queues a ring (`true`) or stop (`false`) and waits for the outcome.
+/// Ringing again while already ringing just resends the command.
+pub async fn request(ring: bool) -> Result<(), RingError> {
+    let reply = enqueue(ring)?;
+    reply.await.map_err(|_| RingError::Unavailable)?
+}
+
+/// [`request`] for the console and HTTP threads.
+pub fn request_blocking(ring: bool) -> Result<(), RingError> {
+    let reply = enqueue(ring)?;
+    reply.blocking_recv().map_err(|_| RingError::Unavailable)?
+}
+
+fn enqueue(ring: bool) -> Result<oneshot::Receiver<Result<(), RingError>>, RingError> {
+    let requests = REQUESTS.get().ok_or(RingError::Unavailable)?;
+    let (reply, wait) = oneshot::channel();
+    requests
+        .send(Request { ring, reply })
+        .map_err(|_| RingError::Unavailable)?;
+    Ok(wait)
+}
+
+/// Current phase; `Ringing` lapses to `Idle` after the watch's own limit or
+/// once the watch disconnects.
+pub fn phase() -> RingPhase {
+    let Ok(mut state) = STATE.lock() else {
+        return RingPhase::Idle;
+    };
+    let lapsed = state
+        .since
+        .is_some_and(|since| since.elapsed() >= RING_LIMIT)
+        || !matches!(status::phase(), ConnectionPhase::Ready { .. });
+    if state.phase == RingPhase::Ringing && lapsed {
+        state.phase = RingPhase::Idle;
+        state.since = None;
+        drop(state);
+        changed(RingPhase::Idle);
+        return RingPhase::Idle;
+    }
+    state.phase
+}
+
+/// Keeps the ring start time unless going idle, so restoring `Ringing` after
+/// a failed stop does not extend the limit.
+fn set_phase(phase: RingPhase) {
+    if let Ok(mut state) = STATE.lock() {
+        if phase == RingPhase::Idle {
+            state.since = None;
+        }
+        state.phase = phase;
+    }
+    changed(phase);
+}
+
+/// A resend restarts the watch's ring, and with it the limit.
+fn start_ringing() {
+    if let Ok(mut state) = STATE.lock() {
+        state.phase = RingPhase::Ringing;
+        state.since = Some(Instant::now());
+    }
+    changed(RingPhase::Ringing);
+}
+
+fn changed(phase: RingPhase) {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    events::publish(SystemEvent::RingChanged { phase });
+}
+
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+pub fn register_commands() {
+    crate::console::register("watch", "ring|stop: find the watch", |args| {
+        let ring = match args.first().copied() {
+            Some("ring") => true,
+            Some("stop") => false,
+            _ => anyhow::bail!("usage: watch ring|stop"),
+        };
+        request_blocking(ring)?;
+        Ok(if ring {
+            "watch ringing"
+        } else {
+            "watch stopped"
+        }
+        .to_string())
+    });
+}
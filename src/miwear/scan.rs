@@ -0,0 +1,271 @@
+//! Time-boxed BLE scans whose results arrive while the scan is still
+//! running. Each address is reported once, then again only when its RSSI
+//! changes. A scan ends when its time is up, when the judge asks for it,
+//! after `max_results` addresses, or after [`Scan::stop`].
+//!
+//! NimBLE only calls back on advertisements, so a stop request lands with
+//! the next advertisement seen (or at the end of the time box). The scan is
+//! never torn down mid-flight: its callback must outlive the GAP discovery.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use esp32_nimble::{BLEAddress, BLEDevice, BLEScan};
+use log::{info, warn};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::{
+    status::{self, ConnectionPhase},
+    targets::{self, MiWearTarget},
+};
+use crate::settings;
+
+/// How long [`scan_for`] looks for a watch to connect to.
+const SCAN_DURATION: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub struct Sighting {
+    pub addr: BLEAddress,
+    pub name: Option<String>,
+    pub rssi: i32,
+    /// Reported before in this scan; only the RSSI is new.
+    pub repeat: bool,
+}
+
+/// What to do with one advertisement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Skip,
+    Report,
+    /// Report it and end the scan.
+    ReportAndStop,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Ended {
+    Judge,
+    Full,
+    Stopped,
+}
+
+/// Radio duty of a scan; interval and window in 0.625 ms units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanMode {
+    /// Sends scan requests, which fetches names from scan responses.
+    pub active: bool,
+    pub interval: u16,
+    pub window: u16,
+}
+
+impl ScanMode {
+    /// Half duty with scan requests: finds a watch quickly.
+    pub const SEARCH: ScanMode = ScanMode {
+        active: true,
+        interval: 80,
+        window: 40,
+    };
+}
+
+pub struct Scan {
+    results: mpsc::UnboundedReceiver<Sighting>,
+    stop: Arc<AtomicBool>,
+    task: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl Scan {
+    /// Starts scanning on the current `LocalSet`. `label` names the scan in
+    /// the log line that records its duration and result count.
+    pub fn start(
+        label: &'static str,
+        duration: Duration,
+        max_results: Option<usize>,
+        judge: impl FnMut(&Sighting) -> Verdict + Send + 'static,
+    ) -> Self {
+        Self::start_with(label, ScanMode::SEARCH, duration, max_results, judge)
+    }
+
+    /// [`Scan::start`] at another duty.
+    pub fn start_with(
+        label: &'static str,
+        mode: ScanMode,
+        duration: Duration,
+        max_results: Option<usize>,
+        mut judge: impl FnMut(&Sighting) -> Verdict + Send + 'static,
+    ) -> Self {
+        let (tx, results) = mpsc::unbounded_channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = Arc::clone(&stop);
+        let task = tokio::task::spawn_local(async move {
+            let ble = BLEDevice::take();
+            let own_addr = own_address(ble);
+            let mut scan = BLEScan::new();
+            scan.active_scan(mode.active)
+                .interval(mode.interval)
+                .window(mode.window);
+            let started = Instant::now();
+            let mut seen: HashMap<String, i32> = HashMap::new();
+            let duration_ms = i32::try_from(duration.as_millis()).unwrap_or(i32::MAX);
+            let ended = scan
+                .start(ble, duration_ms, |dev, adv| {
+                    if stop_flag.load(Ordering::Relaxed) {
+                        return Some(Ended::Stopped);
+                    }
+                    let key = dev.addr().to_string();
+                    if own_addr.as_deref() == Some(key.as_str()) {
+                        return None;
+                    }
+                    let rssi = dev.rssi();
+                    let previous = seen.get(&key).copied();
+                    if previous == Some(rssi) {
+                        return None;
+                    }
+                    let sighting = Sighting {
+                        addr: dev.addr(),
+                        name: adv.name().map(|name| name.to_string()),
+                        rssi,
+                        repeat: previous.is_some(),
+                    };
+                    let verdict = judge(&sighting);
+                    if verdict == Verdict::Skip {
+                        return None;
+                    }
+                    seen.insert(key, rssi);
+                    // A consumer that went away still lets the scan run out.
+                    let _ = tx.send(sighting);
+                    if verdict == Verdict::ReportAndStop {
+                        return Some(Ended::Judge);
+                    }
+                    max_results
+                        .filter(|max| seen.len() >= *max)
+                        .map(|_| Ended::Full)
+                })
+                .await;
+            let how = match &ended {
+                Ok(Some(Ended::Judge)) => "target found",
+                Ok(Some(Ended::Full)) => "result limit",
+                Ok(Some(Ended::Stopped)) => "stopped",
+                Ok(None) => "timed out",
+                Err(_) => "failed",
+            };
+            info!(
+                "BLE scan ({label}) took {:?}, {} results, {how}",
+                started.elapsed(),
+                seen.len()
+            );
+            ended
+                .map(|_| ())
+                .map_err(|err| anyhow::anyhow!("BLE scan: {err:?}"))
+        });
+        Self {
+            results,
+            stop,
+            task: Some(task),
+        }
+    }
+
+    /// Sightings as they come in; ends once the scan has.
+    pub fn results_stream(&mut self) -> &mut mpsc::UnboundedReceiver<Sighting> {
+        &mut self.results
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits for the scan to end and returns its error, if any.
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        let Some(task) = self.task.take() else {
+            return Ok(());
+        };
+        task.await
+            .map_err(|err| anyhow::anyhow!("BLE scan task: {err}"))?
+    }
+}
+
+impl Drop for Scan {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Our advertiser shows up in our own scans; loose name filters would
+/// otherwise pick it as a watch.
+/// The best candidate in range and its address.
+pub(super) async fn scan_for(candidates: &[MiWearTarget]) -> anyhow::Result<(usize, BLEAddress)> {
+    status::set_phase(ConnectionPhase::Scanning);
+    info!("Start scanning for {} targets...", candidates.len());
+    // Our own scan requests and advertising responses contend for the radio.
+    #[cfg(feature = "ancs")]
+    let advertising_pause =
+        settings::get(&super::QUIET_SCAN).then(super::ancs::AdvertisingPause::hold);
+    #[cfg(feature = "ancs")]
+    let quiet = advertising_pause.is_some();
+    #[cfg(not(feature = "ancs"))]
+    let quiet = false;
+    // The first candidate ends the scan at once; anything lower keeps it
+    // going in case a better one shows up.
+    let judged = candidates.to_vec();
+    let mut scan = Scan::start(
+        "connect",
+        SCAN_DURATION,
+        None,
+        move |sighting| match rank_of(&judged, sighting) {
+            Some(0) => Verdict::ReportAndStop,
+            Some(_) => Verdict::Report,
+            None => Verdict::Skip,
+        },
+    );
+    let mut best: Option<(usize, BLEAddress)> = None;
+    while let Some(sighting) = scan.results_stream().recv().await {
+        let Some(rank) = rank_of(candidates, &sighting) else {
+            continue;
+        };
+        if best
+            .as_ref()
+            .map_or(true, |(best_rank, _)| rank < *best_rank)
+        {
+            info!(
+                "Found {}: {:?} rssi={}",
+                candidates[rank].label(),
+                sighting.name,
+                sighting.rssi
+            );
+            best = Some((rank, sighting.addr));
+        }
+    }
+    let scanned = scan.finish().await;
+    #[cfg(feature = "ancs")]
+    drop(advertising_pause);
+    scanned?;
+    info!(
+        "MiWear scan {} (ANCS advertising {})",
+        if best.is_some() { "found" } else { "nothing" },
+        if quiet { "paused" } else { "on" }
+    );
+    targets::clear_connect_request();
+    best.ok_or_else(|| anyhow::anyhow!("No target in range"))
+}
+
+pub(super) fn rank_of(candidates: &[MiWearTarget], sighting: &Sighting) -> Option<usize> {
+    targets::selection::rank(
+        candidates,
+        sighting.name.as_deref(),
+        &sighting.addr.to_string(),
+    )
+}
+
+fn own_address(ble: &BLEDevice) -> Option<String> {
+    match ble.get_addr() {
+        Ok(addr) => Some(addr.to_string()),
+        Err(err) => {
+            warn!("Own BLE address unknown, scans cannot skip it: {err:?}");
+            None
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! The per-session send worker: drains the session's [`send_queue`] into
+//! writes on 0x005F, and is restarted a few times if it dies before the
+//! session gives up on the link. [`burst`] decides how many writes go out
+//! before one waits for the watch's acknowledgement.
+
+pub mod burst;
+pub mod watchdog;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use corelib::device::xiaomi::SendError;
+use esp32_nimble::{BLEError, BLERemoteCharacteristic};
+use esp_idf_svc::sys::{BLE_HS_EBUSY, BLE_HS_ENOMEM, BLE_HS_ENOMEM_EVT, BLE_HS_ETIMEOUT};
+use log::{debug, info};
+use tokio::{
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time,
+};
+
+use self::{
+    burst::{Backoff, Controller},
+    watchdog::{Budget, Exit, Verdict},
+};
+use super::{
+    liveness,
+    logging::SessionLog,
+    net_meter,
+    send_queue::{self, SendItem, SendPriority},
+};
+use crate::{
+    ble::resources,
+    metrics::{self, Counter},
+    settings,
+};
+
+/// Flushing a queue sends nothing, so this only matters if a write hangs.
+const DRAIN_WAIT: Duration = Duration::from_secs(2);
+/// Send worker restarts within one session before it is dropped instead.
+const SEND_WORKER_RESTARTS: u32 = 3;
+/// Reading the link RSSI is an HCI round trip; at most this often.
+const RSSI_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Aborts a task when the session ends, however it ends.
+pub(super) struct AbortOnDrop(pub(super) JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// What a send worker needs; its supervisor keeps one to start the next.
+#[derive(Clone)]
+pub(super) struct SendWorker {
+    pub(super) sender: send_queue::SendHandle,
+    pub(super) coalesce: bool,
+    pub(super) ch_sent: BLERemoteCharacteristic,
+    pub(super) conn_handle: u16,
+    pub(super) watch: Arc<liveness::Watch>,
+    pub(super) session: SessionLog,
+}
+
+/// Shuts the queue and waits, up to [`DRAIN_WAIT`], for the worker to fail
+/// what is left in it, so the next session never starts behind a backlog
+/// meant for this one. One line and one metric cover all of them.
+pub(super) async fn finish_sends(
+    sender: &send_queue::SendHandle,
+    send_task: &mut AbortOnDrop,
+    session: &SessionLog,
+) {
+    sender.shut();
+    if time::timeout(DRAIN_WAIT, &mut send_task.0).await.is_err() {
+        log::warn!(target: session.target(), "{session} send queue still draining after {DRAIN_WAIT:?}, dropping it");
+        send_task.0.abort();
+    }
+    let flushed = sender.flushed();
+    if flushed == 0 {
+        return;
+    }
+    info!(target: session.target(), "{session} flushed {flushed} pending sends on disconnect");
+    send_queue::count_flushed(flushed);
+    metrics::record(Counter::SendsFlushed, flushed);
+}
+
+/// Keeps a session's send worker running. A worker that dies gets a fresh
+/// channel and a successor, up to [`SEND_WORKER_RESTARTS`] times; after that
+/// the queue is closed and `failed` has the session drop the link so the
+/// supervisor reconnects. Dying is ending while the session's queue is still
+/// open, or a panic in builds that unwind; see [`watchdog`].
+pub(super) async fn supervise_sends(
+    worker: SendWorker,
+    mut rx: mpsc::UnboundedReceiver<SendItem>,
+    failed: Arc<Notify>,
+) {
+    let session = worker.session.clone();
+    let mut budget = Budget::new(SEND_WORKER_RESTARTS);
+    loop {
+        let queue = send_queue::Coalescer::new(rx, worker.coalesce);
+        let mut task = AbortOnDrop(tokio::task::spawn_local(run_send_worker(
+            worker.clone(),
+            queue,
+        )));
+        let exit = match (&mut task.0).await {
+            Ok(()) => Exit::Returned,
+            Err(err) if err.is_panic() => Exit::Panicked(err.into_panic()),
+            Err(_) => Exit::Cancelled,
+        };
+        let cause = match watchdog::verdict(exit, worker.sender.is_open()) {
+            Verdict::Done => return,
+            Verdict::Died(cause) => cause,
+        };
+        log::error!(target: session.target(), "{session} send worker died: {cause}");
+        let Some(restarts) = budget.restart() else {
+            worker.sender.close();
+            send_queue::count_escalation();
+            crate::journal!(
+                "Send worker gave up ({session}) after {} restarts: {cause}",
+                budget.used()
+            );
+            failed.notify_one();
+            return;
+        };
+        rx = worker.sender.reopen();
+        send_queue::count_restart();
+        metrics::record(Counter::SendWorkerRestarts, 1);
+        crate::journal!(
+            "Send worker restarted ({session}, {restarts}/{SEND_WORKER_RESTARTS}): {cause}"
+        );
+    }
+}
+
+/// Out-of-memory writes name the pools, since the fix is one of their
+/// block counts.
+fn write_error(session: &SessionLog, err: &BLEError) -> SendError {
+    if matches!(err.code(), BLE_HS_ENOMEM | BLE_HS_ENOMEM_EVT) {
+        log::warn!(
+            target: session.target(),
+            "{session} write failed, NimBLE out of memory: {}",
+            resources::summary()
+        );
+    }
+    SendError::Io(err.to_string())
+}
+
+/// Failures that mean the link is sent too much too fast.
+fn backoff_for(err: &BLEError) -> Option<Backoff> {
+    match err.code() {
+        BLE_HS_ENOMEM | BLE_HS_ENOMEM_EVT | BLE_HS_EBUSY => Some(Backoff::Congested),
+        BLE_HS_ETIMEOUT => Some(Backoff::TimedOut),
+        _ => None,
+    }
+}
+
+/// The worker's side of [`burst`]: counts writes into rounds and feeds the
+/// controller what each round ended with.
+struct Pacer {
+    controller: Controller,
+    /// Writes without a response since the last acknowledged one.
+    unacked: u32,
+    rssi_checked: Option<Instant>,
+    conn_handle: u16,
+}
+
+impl Pacer {
+    fn new(conn_handle: u16) -> Self {
+        let controller = Controller::new(
+            settings::get(&burst::BURST),
+            settings::get(&burst::MAX_BURST),
+        );
+        burst::record_burst(controller.burst());
+        Self {
+            controller,
+            unacked: 0,
+            rssi_checked: None,
+            conn_handle,
+        }
+    }
+
+    /// Whether the next write closes the round and waits for the watch.
+    fn wants_ack(&self, priority: SendPriority) -> bool {
+        // Latency probes time one packet's round trip.
+        priority == SendPriority::Immediate || self.unacked + 1 >= self.controller.burst()
+    }
+
+    fn sent(&mut self, acked: bool, session: &SessionLog) {
+        if !acked {
+            self.unacked += 1;
+            return;
+        }
+        self.unacked = 0;
+        if let Some(rssi) = self.weak_rssi() {
+            self.back_off(Backoff::WeakSignal, &format!("{rssi} dBm"), session);
+        } else if let Some(burst) = self.controller.acked() {
+            burst::record_burst(burst);
+            debug!(target: session.target(), "{session} write burst up to {burst}");
+        }
+    }
+
+    fn failed(&mut self, err: &BLEError, session: &SessionLog) {
+        self.unacked = 0;
+        if let Some(why) = backoff_for(err) {
+            self.back_off(why, &err.to_string(), session);
+        }
+    }
+
+    fn back_off(&mut self, why: Backoff, detail: &str, session: &SessionLog) {
+        burst::record_backoff(why);
+        if let Some(burst) = self.controller.back_off() {
+            burst::record_burst(burst);
+            info!(
+                target: session.target(),
+                "{session} write burst down to {burst}: {} ({detail}), {:.0} B/s",
+                why.label(),
+                net_meter::snapshot().tx.current_bps
+            );
+        }
+    }
+
+    /// The link RSSI if it is under the floor, checked at most every
+    /// [`RSSI_INTERVAL`].
+    fn weak_rssi(&mut self) -> Option<i8> {
+        if self
+            .rssi_checked
+            .is_some_and(|at| at.elapsed() < RSSI_INTERVAL)
+        {
+            return None;
+        }
+        self.rssi_checked = Some(Instant::now());
+        let mut rssi = 0i8;
+        if unsafe { esp_idf_svc::sys::ble_gap_conn_rssi(self.conn_handle, &mut rssi) } != 0 {
+            return None;
+        }
+        (i32::from(rssi) < settings::get(&burst::RSSI_FLOOR)).then_some(rssi)
+    }
+}
+
+async fn run_send_worker(worker: SendWorker, mut queue: send_queue::Coalescer) {
+    let SendWorker {
+        sender: worker_sender,
+        mut ch_sent,
+        conn_handle,
+        watch,
+        session,
+        ..
+    } = worker;
+    let mut pacer = Pacer::new(conn_handle);
+    loop {
+        let mtu = unsafe { esp_idf_svc::sys::ble_att_mtu(conn_handle) } as usize;
+        let Some(batch) = queue.next(mtu).await else {
+            break;
+        };
+        if worker_sender.is_disconnected() {
+            worker_sender.flush(batch);
+            continue;
+        }
+        let len = batch.data.len();
+        watch.on_tx();
+        session.tx(&batch.data);
+        // Without both write kinds there is nothing to pace.
+        let acked = match (ch_sent.can_write(), ch_sent.can_write_no_response()) {
+            (true, true) => Some(pacer.wants_ack(batch.priority)),
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        };
+        let result: Result<(), SendError> = match acked {
+            Some(acked) => match ch_sent.write_value(&batch.data, acked).await {
+                Ok(()) => {
+                    pacer.sent(acked, &session);
+                    Ok(())
+                }
+                Err(err) => {
+                    pacer.failed(&err, &session);
+                    Err(write_error(&session, &err))
+                }
+            },
+            None => Err(SendError::Io("0x005F can't write".to_string())),
+        };
+        match result {
+            Ok(()) => {
+                net_meter::record_tx(len);
+                batch.complete(Ok(()));
+            }
+            // The link went down under this write.
+            Err(_) if worker_sender.is_disconnected() => worker_sender.flush(batch),
+            Err(err) => batch.complete(Err(err)),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! How many 0x005F writes go out without a response before one waits for
+//! the watch's ATT acknowledgement. Waiting on every write holds a clean
+//! link to one round trip per packet; never waiting floods a poor one until
+//! NimBLE runs out of buffers.
+//!
+//! [`Controller`] is AIMD with a slow start: the burst doubles from 1 on
+//! every clean round until the first backoff, then grows by one, and halves
+//! on a congestion error, an acknowledgement timing out, or the link RSSI
+//! under [`RSSI_FLOOR`]. [`BURST`] can pin it instead. Chunking and acks
+//! above ATT are corelib's; this only paces the writes the worker makes.
+
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+use crate::settings::SettingKey;
+
+mod controller;
+
+pub use controller::{Backoff, BurstPolicy, Controller, BURST_RANGE};
+
+/// `auto`, or a fixed burst; `1` waits on every write, as before.
+pub const BURST: SettingKey<BurstPolicy> = SettingKey::new("miwear_burst", "auto");
+pub const MAX_BURST: SettingKey<u32> =
+    SettingKey::new("miwear_burstmax", "16").validated(|max| controller::check_max_burst(*max));
+/// Link RSSI in dBm under which the burst backs off.
+pub const RSSI_FLOOR: SettingKey<i32> =
+    SettingKey::new("miwear_rssi_min", "-85").validated(|dbm| controller::check_rssi_floor(*dbm));
+
+static CURRENT: AtomicU32 = AtomicU32::new(1);
+static BACKOFFS: AtomicU32 = AtomicU32::new(0);
+/// `Backoff as u8 + 1`, or 0 before the first.
+static LAST_BACKOFF: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BurstStats {
+    /// The live session's burst, or the last one's.
+    pub burst: u32,
+    /// Since boot.
+    pub backoffs: u32,
+    pub last_backoff: Option<Backoff>,
+}
+
+pub fn stats() -> BurstStats {
+    BurstStats {
+        burst: CURRENT.load(Ordering::Relaxed),
+        backoffs: BACKOFFS.load(Ordering::Relaxed),
+        last_backoff: match LAST_BACKOFF.load(Ordering::Relaxed) {
+            0 => None,
+            n => Backoff::ALL.get(usize::from(n) - 1).copied(),
+        },
+    }
+}
+
+pub(super) fn record_burst(burst: u32) {
+    CURRENT.store(burst, Ordering::Relaxed);
+}
+
+pub(super) fn record_backoff(why: Backoff) {
+    BACKOFFS.fetch_add(1, Ordering::Relaxed);
+    LAST_BACKOFF.store(why as u8 + 1, Ordering::Relaxed);
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "burst",
+        "[auto|<1-64>]: watch write burst, from the next connection",
+        |args| {
+            if let Some(arg) = args.first() {
+                let policy: BurstPolicy = arg
+                    .parse()
+                    .map_err(|()| anyhow::anyhow!("usage: burst [auto|<1-64>]"))?;
+                crate::settings::set(&BURST, &policy)?;
+            }
+            let stats = stats();
+            Ok(format!(
+                "policy {}, burst {}, {} backoffs{}",
+                crate::settings::get(&BURST),
+                stats.burst,
+                stats.backoffs,
+                stats
+                    .last_backoff
+                    .map_or(String::new(), |why| format!(", last {}", why.label()))
+            ))
+        },
+    );
+}
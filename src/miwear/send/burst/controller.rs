@@ -0,0 +1,140 @@
+//! The burst [`Controller`], what it is fed, and what its settings accept.
+//! Settings and counters stay in `burst`, so host tests can drive this with
+//! ack and error traces.
+
+use std::{fmt, str::FromStr};
+
+pub const BURST_RANGE: std::ops::RangeInclusive<u32> = 1..=64;
+pub const RSSI_FLOOR_DBM: std::ops::RangeInclusive<i32> = -127..=0;
+
+pub fn check_max_burst(max: u32) -> Result<(), String> {
+    if BURST_RANGE.contains(&max) {
+        Ok(())
+    } else {
+        Err("burst is 1 to 64 writes".into())
+    }
+}
+
+pub fn check_rssi_floor(dbm: i32) -> Result<(), String> {
+    if RSSI_FLOOR_DBM.contains(&dbm) {
+        Ok(())
+    } else {
+        Err("RSSI floor is -127 to 0 dBm".into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BurstPolicy {
+    Auto,
+    Fixed(u32),
+}
+
+impl fmt::Display for BurstPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BurstPolicy::Auto => f.write_str("auto"),
+            BurstPolicy::Fixed(burst) => write!(f, "{burst}"),
+        }
+    }
+}
+
+impl FromStr for BurstPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(BurstPolicy::Auto);
+        }
+        s.parse()
+            .ok()
+            .filter(|burst| BURST_RANGE.contains(burst))
+            .map(BurstPolicy::Fixed)
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// NimBLE had no buffer or was busy.
+    Congested,
+    /// An acknowledged write timed out.
+    TimedOut,
+    WeakSignal,
+}
+
+impl Backoff {
+    pub const ALL: [Backoff; 3] = [Backoff::Congested, Backoff::TimedOut, Backoff::WeakSignal];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Backoff::Congested => "congested",
+            Backoff::TimedOut => "ack timeout",
+            Backoff::WeakSignal => "weak signal",
+        }
+    }
+}
+
+/// The pacing state for one session; pure, so a trace of acks and errors
+/// fully decides it.
+#[derive(Clone, Debug)]
+pub struct Controller {
+    burst: u32,
+    max: u32,
+    fixed: bool,
+    /// Doubling until the first backoff.
+    slow_start: bool,
+}
+
+impl Controller {
+    pub fn new(policy: BurstPolicy, max: u32) -> Self {
+        let max = max.clamp(*BURST_RANGE.start(), *BURST_RANGE.end());
+        match policy {
+            BurstPolicy::Auto => Self {
+                burst: 1,
+                max,
+                fixed: false,
+                slow_start: true,
+            },
+            BurstPolicy::Fixed(burst) => Self {
+                burst,
+                max: burst,
+                fixed: true,
+                slow_start: false,
+            },
+        }
+    }
+
+    /// Writes per round, the last of them acknowledged.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// A round went out and was acknowledged cleanly. Returns the new burst
+    /// if it changed.
+    pub fn acked(&mut self) -> Option<u32> {
+        if self.fixed || self.burst == self.max {
+            return None;
+        }
+        self.burst = if self.slow_start {
+            self.burst * 2
+        } else {
+            self.burst + 1
+        }
+        .min(self.max);
+        Some(self.burst)
+    }
+
+    /// Halves the burst for any [`Backoff`]. Returns the new burst if it
+    /// changed; a fixed burst never does.
+    pub fn back_off(&mut self) -> Option<u32> {
+        if self.fixed {
+            return None;
+        }
+        self.slow_start = false;
+        let halved = (self.burst / 2).max(1);
+        (halved != self.burst).then(|| {
+            self.burst = halved;
+            halved
+        })
+    }
+}
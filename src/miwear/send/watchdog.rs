@@ -0,0 +1,68 @@
+//! What the send supervisor makes of a worker that stopped. Release images
+//! abort on panic, so a panic never reaches the supervisor there; what it
+//! can always see is the worker's task ending while the session still has
+//! its queue open, and that is treated as a death like a caught panic.
+
+use std::any::Any;
+
+/// How the worker's task ended.
+pub enum Exit {
+    Returned,
+    /// Only in builds that unwind.
+    Panicked(Box<dyn Any + Send>),
+    /// Aborted with the session.
+    Cancelled,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The session is over; nothing to restart.
+    Done,
+    Died(String),
+}
+
+/// `queue_open` is whether the session can still queue sends.
+pub fn verdict(exit: Exit, queue_open: bool) -> Verdict {
+    match exit {
+        Exit::Panicked(payload) => Verdict::Died(panic_message(payload)),
+        Exit::Cancelled => Verdict::Done,
+        Exit::Returned if queue_open => {
+            Verdict::Died("worker stopped with its queue still open".to_string())
+        }
+        Exit::Returned => Verdict::Done,
+    }
+}
+
+/// Restarts left before the session is given up.
+pub struct Budget {
+    used: u32,
+    max: u32,
+}
+
+impl Budget {
+    pub fn new(max: u32) -> Self {
+        Self { used: 0, max }
+    }
+
+    /// Counts one restart; `None` once the budget is spent.
+    pub fn restart(&mut self) -> Option<u32> {
+        (self.used < self.max).then(|| {
+            self.used += 1;
+            self.used
+        })
+    }
+
+    pub fn used(&self) -> u32 {
+        self.used
+    }
+}
+
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or_else(
+            || "non-string panic".to_string(),
+            |message| message.to_string(),
+        ),
+    }
+}
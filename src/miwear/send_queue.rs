@@ -0,0 +1,282 @@
+//! Outgoing 0x005F writes. Optionally merges back-to-back small packets into
+//! one GATT write; off by default until validated per watch model.
+//!
+//! corelib keeps the send callback it was given for the whole session, so
+//! the callback sends through a [`SendHandle`]. When the worker dies the
+//! session supervisor gives the handle a fresh channel and starts a new
+//! worker on it; corelib never sees the swap.
+//!
+//! When the link drops, the handle is shut: new sends fail at once, and the
+//! worker fails what is still queued with one [`disconnected`] error each
+//! instead of writing to a dead link. The session counts them and logs one
+//! line.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+use corelib::device::xiaomi::SendError;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::settings::SettingKey;
+
+pub const COALESCE_WRITES: SettingKey<bool> = SettingKey::new("miwear_coalesce", "false");
+
+/// Only SAR v2 frames carry their own length, so only they can share a write.
+pub const COALESCE_SAR_VERSION: u32 = 2;
+/// SAR v2 frame header magic. Payloads without it are always sent alone.
+const SAR_V2_MAGIC: [u8; 2] = [0xA5, 0xA5];
+/// ATT Write Request opcode + handle.
+const ATT_WRITE_OVERHEAD: usize = 3;
+/// corelib's `SendError` has no disconnect variant, so this message marks it.
+const DISCONNECTED: &str = "link disconnected";
+
+static MERGED_PACKETS: AtomicU32 = AtomicU32::new(0);
+static BYTES_SAVED: AtomicU32 = AtomicU32::new(0);
+static RESTARTS: AtomicU32 = AtomicU32::new(0);
+static ESCALATIONS: AtomicU32 = AtomicU32::new(0);
+static FLUSHED_TOTAL: AtomicU32 = AtomicU32::new(0);
+static FLUSHED_LAST: AtomicU32 = AtomicU32::new(0);
+
+pub type Responder = oneshot::Sender<Result<(), SendError>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendPriority {
+    Normal,
+    /// Never merged or held back; for latency probes that must measure one
+    /// packet's round trip.
+    Immediate,
+}
+
+pub struct SendItem {
+    pub data: Vec<u8>,
+    pub priority: SendPriority,
+    pub responder: Responder,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CoalesceStats {
+    /// Packets that rode along in another packet's write.
+    pub merged_packets: u32,
+    /// ATT overhead avoided by those merges.
+    pub bytes_saved: u32,
+}
+
+pub fn stats() -> CoalesceStats {
+    CoalesceStats {
+        merged_packets: MERGED_PACKETS.load(Ordering::Relaxed),
+        bytes_saved: BYTES_SAVED.load(Ordering::Relaxed),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RestartStats {
+    /// Workers restarted in place since boot.
+    pub restarts: u32,
+    /// Sessions dropped because their worker kept dying.
+    pub escalations: u32,
+}
+
+pub fn restart_stats() -> RestartStats {
+    RestartStats {
+        restarts: RESTARTS.load(Ordering::Relaxed),
+        escalations: ESCALATIONS.load(Ordering::Relaxed),
+    }
+}
+
+pub fn count_restart() {
+    RESTARTS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn count_escalation() {
+    ESCALATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlushStats {
+    /// Sends failed unwritten on disconnect since boot.
+    pub total: u32,
+    /// How many the last session that had any lost.
+    pub last: u32,
+}
+
+pub fn flush_stats() -> FlushStats {
+    FlushStats {
+        total: FLUSHED_TOTAL.load(Ordering::Relaxed),
+        last: FLUSHED_LAST.load(Ordering::Relaxed),
+    }
+}
+
+pub fn count_flushed(count: u32) {
+    FLUSHED_TOTAL.fetch_add(count, Ordering::Relaxed);
+    FLUSHED_LAST.store(count, Ordering::Relaxed);
+}
+
+/// The error every send gets once the link is gone.
+pub fn disconnected() -> SendError {
+    SendError::Io(DISCONNECTED.to_string())
+}
+
+pub fn is_disconnected(err: &SendError) -> bool {
+    matches!(err, SendError::Io(reason) if reason == DISCONNECTED)
+}
+
+/// The sending side of the current worker's channel.
+#[derive(Clone, Default)]
+pub struct SendHandle(Arc<Shared>);
+
+#[derive(Default)]
+struct Shared {
+    sender: Mutex<Option<mpsc::UnboundedSender<SendItem>>>,
+    disconnected: AtomicBool,
+    /// Sends failed by [`SendHandle::flush`] this session.
+    flushed: AtomicU32,
+}
+
+impl SendHandle {
+    pub fn send(&self, item: SendItem) -> Result<(), SendError> {
+        if self.is_disconnected() {
+            return Err(disconnected());
+        }
+        let closed = || SendError::Io("send queue closed".to_string());
+        let sender = self.0.sender.lock().map_err(|_| closed())?;
+        sender
+            .as_ref()
+            .ok_or_else(closed)?
+            .send(item)
+            .map_err(|_| closed())
+    }
+
+    /// Points the handle at a new channel and returns its receiver.
+    /// Items queued to the old one are gone with the worker that held it.
+    /// After [`Self::shut`] the channel is closed from the start.
+    pub fn reopen(&self) -> mpsc::UnboundedReceiver<SendItem> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        if let Ok(mut sender) = self.0.sender.lock() {
+            *sender = (!self.is_disconnected()).then_some(tx);
+        }
+        rx
+    }
+
+    /// Fails every later send, for a session that is being given up.
+    pub fn close(&self) {
+        if let Ok(mut sender) = self.0.sender.lock() {
+            *sender = None;
+        }
+    }
+
+    /// The link is gone: later sends fail with [`disconnected`], and the
+    /// worker's queue ends once it has flushed what is in it. Safe to call
+    /// from the BLE host's disconnect callback, and more than once.
+    pub fn shut(&self) {
+        self.0.disconnected.store(true, Ordering::Relaxed);
+        self.close();
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.0.disconnected.load(Ordering::Relaxed)
+    }
+
+    /// Whether sends can still be queued: neither closed nor shut.
+    pub fn is_open(&self) -> bool {
+        self.0.sender.lock().is_ok_and(|sender| sender.is_some())
+    }
+
+    /// Fails `batch` unwritten because the link is gone.
+    pub fn flush(&self, batch: Batch) {
+        self.0
+            .flushed
+            .fetch_add(batch.responders.len() as u32, Ordering::Relaxed);
+        batch.complete(Err(disconnected()));
+    }
+
+    pub fn flushed(&self) -> u32 {
+        self.0.flushed.load(Ordering::Relaxed)
+    }
+}
+
+/// A write to perform and everyone waiting on it.
+pub struct Batch {
+    pub data: Vec<u8>,
+    /// The first item's; merged followers are always `Normal`.
+    pub priority: SendPriority,
+    responders: Vec<Responder>,
+}
+
+impl Batch {
+    /// Completes every merged item with the write's outcome.
+    pub fn complete(self, result: Result<(), SendError>) {
+        let shared = match &result {
+            Err(err) if is_disconnected(err) => Err(DISCONNECTED.to_string()),
+            other => other.as_ref().map_err(|err| format!("{err:?}")).copied(),
+        };
+        let mut responders = self.responders.into_iter();
+        if let Some(first) = responders.next() {
+            let _ = first.send(result);
+        }
+        for responder in responders {
+            let _ = responder.send(shared.clone().map_err(SendError::Io));
+        }
+    }
+}
+
+/// Pulls the next write off the queue, merging queued followers when
+/// `max_len` allows. An item that does not fit is kept for the next call.
+pub struct Coalescer {
+    rx: mpsc::UnboundedReceiver<SendItem>,
+    carry: Option<SendItem>,
+    enabled: bool,
+}
+
+impl Coalescer {
+    pub fn new(rx: mpsc::UnboundedReceiver<SendItem>, enabled: bool) -> Self {
+        Self {
+            rx,
+            carry: None,
+            enabled,
+        }
+    }
+
+    /// `mtu` is the negotiated ATT MTU for the link.
+    pub async fn next(&mut self, mtu: usize) -> Option<Batch> {
+        let first = match self.carry.take() {
+            Some(item) => item,
+            None => self.rx.recv().await?,
+        };
+        let max_len = mtu.saturating_sub(ATT_WRITE_OVERHEAD);
+        let mut data = first.data;
+        let priority = first.priority;
+        let mut responders = vec![first.responder];
+        if !self.enabled || !mergeable(priority, &data) {
+            return Some(Batch {
+                data,
+                priority,
+                responders,
+            });
+        }
+
+        while let Ok(next) = self.rx.try_recv() {
+            if !mergeable(next.priority, &next.data) || data.len() + next.data.len() > max_len {
+                self.carry = Some(next);
+                break;
+            }
+            data.extend_from_slice(&next.data);
+            responders.push(next.responder);
+        }
+        let merged = responders.len() as u32 - 1;
+        if merged > 0 {
+            MERGED_PACKETS.fetch_add(merged, Ordering::Relaxed);
+            BYTES_SAVED.fetch_add(merged * ATT_WRITE_OVERHEAD as u32, Ordering::Relaxed);
+        }
+        Some(Batch {
+            data,
+            priority,
+            responders,
+        })
+    }
+}
+
+fn mergeable(priority: SendPriority, data: &[u8]) -> bool {
+    priority == SendPriority::Normal && data.starts_with(&SAR_V2_MAGIC)
+}
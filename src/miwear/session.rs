@@ -0,0 +1,632 @@
+//! One watch session, from connecting to a chosen address until the link is
+//! gone: GATT discovery of the 0xFE95 characteristics, the send worker, the
+//! corelib handshake and the ready loop. [`SessionState`] drives the
+//! [`ConnectionPhase`] the rest of the firmware sees.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use corelib::device::{
+    self,
+    xiaomi::{r#type::ConnectType, SendError},
+};
+use esp32_nimble::{
+    utilities::{BleUuid, BleUuid::Uuid16},
+    BLEAddress, BLEClient, BLEDevice, BLERemoteCharacteristic,
+};
+use log::info;
+use tokio::{
+    runtime::Handle,
+    sync::{oneshot, Notify},
+    time,
+};
+
+use super::{
+    failure_kind, launch_watch_app, liveness,
+    logging::SessionLog,
+    net_meter, reconnect,
+    scan::{self, Scan, Verdict},
+    send::{self, AbortOnDrop, SendWorker},
+    send_queue::{self, SendItem, SendPriority},
+    status::{self, ConnectionPhase, FailureKind},
+    targets::{self, MiWearTarget},
+    HandshakeFailure, HANDSHAKE_TIMEOUT_SECS, PREFERRED_PHY,
+};
+use crate::{
+    ble::link::{self, PhyPreference},
+    settings,
+};
+
+mod uuids;
+
+use uuids::SeenUuid;
+
+/// How often a live session checks whether its target was edited away.
+const SESSION_CHECK: Duration = Duration::from_secs(1);
+/// How often a session looks for a higher-priority watch, when switching is on.
+const PREFERRED_SCAN_INTERVAL: Duration = Duration::from_secs(60);
+const PREFERRED_SCAN: Duration = Duration::from_secs(3);
+/// Time allowed for the disconnect callback after we drop a link ourselves.
+const DISCONNECT_WAIT: Duration = Duration::from_secs(3);
+/// Long enough for the PHY update procedure to finish at our connection
+/// interval before reading the result back.
+const PHY_SETTLE: Duration = Duration::from_millis(500);
+const AUTO_LAUNCH_PACKAGE: &str = "com.searchstars.hyperbilibili";
+const AUTO_LAUNCH_DELAY_SECS: u64 = 10;
+
+const MI_SERVICE: u16 = 0xFE95;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    Connecting,
+    /// GATT discovery; still reported as connecting.
+    Discovering,
+    Handshaking,
+    Ready,
+    /// Dropping the link ourselves; the supervisor reports what follows.
+    Closing,
+}
+
+impl SessionState {
+    fn phase(self, addr: &str) -> Option<ConnectionPhase> {
+        match self {
+            SessionState::Connecting => Some(ConnectionPhase::Connecting),
+            SessionState::Handshaking => Some(ConnectionPhase::Handshaking),
+            SessionState::Ready => Some(ConnectionPhase::Ready {
+                addr: addr.to_string(),
+            }),
+            SessionState::Discovering | SessionState::Closing => None,
+        }
+    }
+}
+
+/// A ready session: the link, its characteristics and its send worker.
+pub struct Session {
+    link: Link,
+    io: SessionIo,
+}
+
+/// What the handshake sets up on a connected link.
+struct SessionIo {
+    ch_recv: BLERemoteCharacteristic,
+    inbound: Inbound,
+    watch: Arc<liveness::Watch>,
+    send_failed: Arc<Notify>,
+    send_task: AbortOnDrop,
+}
+
+/// The connected client and what the disconnect callback shares with it.
+struct Link {
+    client: BLEClient,
+    addr: BLEAddress,
+    device_addr: String,
+    target: MiWearTarget,
+    log: SessionLog,
+    down: Arc<LinkDown>,
+    sender: send_queue::SendHandle,
+}
+
+/// Set from the NimBLE disconnect callback. [`LinkDown::wait`] may start at
+/// any point of the session, so a drop during discovery or the handshake is
+/// not lost.
+#[derive(Default)]
+struct LinkDown {
+    down: AtomicBool,
+    reason: Mutex<Option<i32>>,
+    notify: Notify,
+}
+
+impl LinkDown {
+    fn record(&self, reason: i32) {
+        if let Ok(mut slot) = self.reason.lock() {
+            *slot = Some(reason);
+        }
+        self.down.store(true, Ordering::Release);
+        self.notify.notify_waiters();
+    }
+
+    async fn wait(&self) {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.down.load(Ordering::Acquire) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    fn take_reason(&self) -> Option<i32> {
+        self.reason.lock().ok().and_then(|mut slot| slot.take())
+    }
+}
+
+/// Hands 0x005E notifications to corelib's packet dispatcher.
+#[derive(Clone)]
+struct Inbound {
+    handle: Handle,
+    addr: String,
+    log: SessionLog,
+}
+
+impl Inbound {
+    fn deliver(&self, payload: &[u8]) {
+        net_meter::record_rx(payload.len());
+        self.log.rx(payload);
+        corelib::device::xiaomi::packet::dispatcher::on_packet(
+            self.handle.clone(),
+            self.addr.clone(),
+            payload.to_vec(),
+        );
+    }
+}
+
+/// The three 0xFE95 characteristics a session uses.
+struct Characteristics {
+    service_flag: BLERemoteCharacteristic,
+    recv: BLERemoteCharacteristic,
+    sent: BLERemoteCharacteristic,
+}
+
+impl Session {
+    /// Connects to `addr` for `target` and runs discovery and the handshake.
+    /// Once connected, a failure drops the link before it is returned.
+    /// `sighted_at` is when [`reconnect`] saw the watch, if it did.
+    pub async fn establish(
+        target: &MiWearTarget,
+        addr: BLEAddress,
+        sighted_at: Option<Instant>,
+    ) -> anyhow::Result<Session> {
+        let mut link = Link::connect(target, addr, sighted_at).await?;
+        match Session::handshake(&mut link).await {
+            Ok(io) => Ok(Session { link, io }),
+            Err(err) => {
+                link.close(Some(&err));
+                Err(err)
+            }
+        }
+    }
+
+    async fn handshake(link: &mut Link) -> anyhow::Result<SessionIo> {
+        let handle = Handle::current();
+        let down = Arc::clone(&link.down);
+        let session = link.log.clone();
+
+        link.enter(SessionState::Discovering);
+        let svc = link
+            .client
+            .get_service(u16_uuid(MI_SERVICE))
+            .await
+            .map_err(|_| anyhow::anyhow!("Can't found fe95 service"))?;
+        let chars: Vec<_> = svc.get_characteristics().await?.collect();
+        let Characteristics {
+            service_flag: mut ch_service_flag,
+            recv: mut ch_recv,
+            sent: ch_sent,
+        } = find_characteristics(&chars)?;
+
+        if ch_service_flag.can_read() {
+            if let Ok(v) = ch_service_flag.read_value().await {
+                info!("Read 0x0050 = {:02X?}", v);
+            }
+        }
+
+        let sar_version = 2;
+
+        let coalesce = settings::get(&send_queue::COALESCE_WRITES)
+            && sar_version as u32 == send_queue::COALESCE_SAR_VERSION;
+        if coalesce {
+            info!("Coalescing small 0x005F writes");
+        }
+        let watch = Arc::new(liveness::Watch::new());
+        let send_failed = Arc::new(Notify::new());
+        // Opened here so sends queue up before the supervisor first runs.
+        let sender = link.sender.clone();
+        let rx = sender.reopen();
+        let mut send_task = AbortOnDrop(tokio::task::spawn_local(send::supervise_sends(
+            SendWorker {
+                sender: sender.clone(),
+                coalesce,
+                ch_sent,
+                conn_handle: link.client.conn_handle(),
+                watch: Arc::clone(&watch),
+                session: session.clone(),
+            },
+            rx,
+            Arc::clone(&send_failed),
+        )));
+
+        let send_cb = {
+            let sender = sender.clone();
+            move |data: Vec<u8>| {
+                let sender = sender.clone();
+                async move {
+                    let (responder, resp_rx) = oneshot::channel();
+                    sender.send(SendItem {
+                        data,
+                        priority: SendPriority::Normal,
+                        responder,
+                    })?;
+                    resp_rx
+                        .await
+                        .map_err(|_| SendError::Io("send task dropped".to_string()))?
+                }
+            }
+        };
+
+        let inbound = Inbound {
+            handle: handle.clone(),
+            addr: link.device_addr.clone(),
+            log: session.clone(),
+        };
+        // Evidence that the auth exchange itself ran: the watch answered and
+        // none of our writes failed.
+        let replied = Arc::new(AtomicBool::new(false));
+        let write_failed = Arc::new(AtomicBool::new(false));
+        if ch_recv.can_notify() {
+            let notify_watch = Arc::clone(&watch);
+            let notify_inbound = inbound.clone();
+            let notify_replied = Arc::clone(&replied);
+            ch_recv.on_notify(move |payload| {
+                notify_replied.store(true, Ordering::Relaxed);
+                notify_watch.on_rx();
+                notify_inbound.deliver(payload);
+            });
+            ch_recv.subscribe_notify(true).await?;
+            info!(target: session.target(), "{session} subscribed notify on 0x005E");
+        } else {
+            info!(target: session.target(), "{session}: 0x005E doesn't support Notify");
+        }
+
+        link.enter(SessionState::Handshaking);
+        let target = &link.target;
+        let handshake = device::create_miwear_device(
+            handle.clone(),
+            target.label().to_string(),
+            link.device_addr.clone(),
+            target.auth_key.clone(),
+            sar_version,
+            ConnectType::BLE,
+            false,
+            {
+                let session = session.clone();
+                let write_failed = Arc::clone(&write_failed);
+                move |data| {
+                    let fut = send_cb(data);
+                    let session = session.clone();
+                    let write_failed = Arc::clone(&write_failed);
+                    async move {
+                        fut.await.map_err(|err| {
+                            write_failed.store(true, Ordering::Relaxed);
+                            // Counted and logged once by finish_sends instead.
+                            if !send_queue::is_disconnected(&err) {
+                                log::error!(target: session.target(), "{session} send failed: {err:?}");
+                            }
+                            err
+                        })
+                    }
+                }
+            },
+        );
+        let timeout = Duration::from_secs(settings::get(&HANDSHAKE_TIMEOUT_SECS));
+        let outcome = tokio::select! {
+            outcome = time::timeout(timeout, handshake) => outcome,
+            _ = down.wait() => {
+                send::finish_sends(&sender, &mut send_task, &session).await;
+                anyhow::bail!("link dropped during handshake");
+            }
+        };
+        let failure = match outcome {
+            Ok(Ok(_)) => None,
+            Ok(Err(err)) => {
+                // corelib reports auth failures as plain errors, and link
+                // errors such as NimBLE's insufficient authentication mention
+                // "auth" too. Only a handshake the watch took part in, with
+                // every write delivered, can have been refused for its key.
+                let reason = format!("{err:?}");
+                let auth_step_ran =
+                    replied.load(Ordering::Relaxed) && !write_failed.load(Ordering::Relaxed);
+                let kind = if auth_step_ran && reason.to_ascii_lowercase().contains("auth") {
+                    FailureKind::AuthRejected
+                } else {
+                    FailureKind::Link
+                };
+                Some(HandshakeFailure { kind, reason })
+            }
+            Err(_) => Some(HandshakeFailure {
+                kind: FailureKind::Timeout,
+                reason: format!("no response within {timeout:?}"),
+            }),
+        };
+        if let Some(failure) = failure {
+            // Stop feeding packets for this address into corelib. corelib has no
+            // entity removal here; a half-created device is keyed by address and
+            // is recreated by the next create_miwear_device call.
+            ch_recv.on_notify(|_| {});
+            if link.client.connected() {
+                let _ = ch_recv.unsubscribe(true).await;
+            }
+            drop(send_task);
+            return Err(failure.into());
+        }
+        link.enter(SessionState::Ready);
+        targets::set_active(Some(link.target.id));
+        crate::errors::resolve("watch");
+        crate::journal!(
+            "Watch {} ({}) ready ({session})",
+            link.target.label(),
+            link.device_addr
+        );
+
+        {
+            let addr_for_launch = link.device_addr.clone();
+            tokio::task::spawn_local(async move {
+                time::sleep(Duration::from_secs(AUTO_LAUNCH_DELAY_SECS)).await;
+                match launch_watch_app(&addr_for_launch, AUTO_LAUNCH_PACKAGE).await {
+                    Ok(_) => log::info!(
+                        "Auto launched {} on {}",
+                        AUTO_LAUNCH_PACKAGE,
+                        addr_for_launch
+                    ),
+                    Err(err) => {
+                        log::warn!(
+                            "Failed to auto launch {} on {}: {err:?}",
+                            AUTO_LAUNCH_PACKAGE,
+                            addr_for_launch
+                        );
+                    }
+                }
+            });
+        }
+
+        Ok(SessionIo {
+            ch_recv,
+            inbound,
+            watch,
+            send_failed,
+            send_task,
+        })
+    }
+
+    /// The ready loop: returns once the link is gone, after dropping it
+    /// ourselves when the target was edited away, a preferred watch showed
+    /// up, the link stalled or the send worker gave up.
+    pub async fn run_until_disconnect(&mut self) -> anyhow::Result<()> {
+        let Session {
+            link,
+            io:
+                SessionIo {
+                    ch_recv,
+                    inbound,
+                    watch,
+                    send_failed,
+                    send_task,
+                },
+        } = self;
+        let session = link.log.clone();
+        let down = Arc::clone(&link.down);
+        info!(target: session.target(), "{session} ready, waiting for disconnect...");
+        let mut check = time::interval(SESSION_CHECK);
+        let mut last_preferred_scan = time::Instant::now();
+        loop {
+            let reason = tokio::select! {
+                _ = down.wait() => break,
+                _ = send_failed.notified() => "send worker kept failing",
+                _ = check.tick() => {
+                    let stall_reason = if watch.stalled() {
+                        let rung = tokio::select! {
+                            rung = liveness::recover(ch_recv, watch.as_ref(), |payload| inbound.deliver(payload)) => rung,
+                            _ = down.wait() => break,
+                        };
+                        (rung == liveness::Rung::Disconnect).then_some("link stalled")
+                    } else {
+                        None
+                    };
+                    let reason = match stall_reason {
+                        Some(reason) => Some(reason),
+                        None => session_end_reason(&link.target, &mut last_preferred_scan).await,
+                    };
+                    let Some(reason) = reason else {
+                        continue;
+                    };
+                    reason
+                }
+            };
+            crate::journal!(
+                "Watch {} dropped ({session}): {reason}",
+                link.target.label()
+            );
+            link.enter(SessionState::Closing);
+            if let Err(err) = link.client.disconnect() {
+                log::warn!("Failed to drop BLE link: {err:?}");
+            }
+            let _ = time::timeout(DISCONNECT_WAIT, down.wait()).await;
+            break;
+        }
+        send::finish_sends(&link.sender, send_task, &session).await;
+
+        Ok(())
+    }
+
+    /// Ends the session after [`Session::run_until_disconnect`]; `failure`
+    /// is the error it returned, if any.
+    pub fn shutdown(mut self, failure: Option<&anyhow::Error>) {
+        self.link.close(failure);
+    }
+}
+
+impl Link {
+    async fn connect(
+        target: &MiWearTarget,
+        addr: BLEAddress,
+        sighted_at: Option<Instant>,
+    ) -> anyhow::Result<Link> {
+        let ble = BLEDevice::take();
+        let session = SessionLog::begin(&addr.to_string());
+        info!("Target {} addr = {addr} ({session})", target.label());
+
+        let mut client: BLEClient = ble.new_client();
+        client.set_connection_params(12, 24, 0, 400, 16, 16);
+
+        let down = Arc::new(LinkDown::default());
+        let sender = send_queue::SendHandle::default();
+        client.on_disconnect({
+            let down = Arc::clone(&down);
+            let session = session.clone();
+            let sender = sender.clone();
+            move |reason| {
+                // Before anything else, so nothing more is queued for a dead link.
+                sender.shut();
+                log::warn!(target: session.target(), "BLE disconnected ({session}, reason: {reason})");
+                down.record(reason);
+            }
+        });
+
+        let mut link = Link {
+            client,
+            addr,
+            device_addr: addr.to_string(),
+            target: target.clone(),
+            log: session.clone(),
+            down,
+            sender,
+        };
+        link.enter(SessionState::Connecting);
+        info!("Connecting...");
+        link.client.connect(&addr).await?;
+        info!("Connected = {}", link.client.connected());
+        reconnect::remember(target.id, &addr);
+        if let Some(at) = sighted_at {
+            info!(
+                "Watch {addr} connected {:?} after it was sighted",
+                at.elapsed()
+            );
+        }
+        crate::journal!("Watch {addr} connected ({session})");
+        let phy = target.phy.unwrap_or_else(|| settings::get(&PREFERRED_PHY));
+        negotiate_phy(link.client.conn_handle(), phy).await;
+        Ok(link)
+    }
+
+    fn enter(&self, state: SessionState) {
+        if let Some(phase) = state.phase(&self.device_addr) {
+            status::set_phase(phase);
+        }
+    }
+
+    /// Clears the active target, sets a rejected key aside, drops a link
+    /// that is still up after a failure and journals the disconnect.
+    fn close(&mut self, failure: Option<&anyhow::Error>) {
+        targets::set_active(None);
+        if failure.map(failure_kind) == Some(FailureKind::AuthRejected) {
+            targets::mark_rejected(&self.target.auth_key);
+        }
+
+        if failure.is_some() && self.client.connected() {
+            if let Err(err) = self.client.disconnect() {
+                log::warn!("Failed to drop BLE link after session error: {err:?}");
+            }
+        }
+        let reason = self.down.take_reason();
+        let (addr, session) = (self.addr, &self.log);
+        crate::journal!("Watch {addr} disconnected ({session}, reason: {reason:?})");
+    }
+}
+
+fn find_characteristics(chars: &[&mut BLERemoteCharacteristic]) -> anyhow::Result<Characteristics> {
+    let seen: Vec<SeenUuid> = chars
+        .iter()
+        .map(|c| {
+            let uuid = c.uuid();
+            let short = match uuid {
+                Uuid16(short) => Some(short),
+                _ => None,
+            };
+            SeenUuid::new(short, &format!("{uuid:?}"))
+        })
+        .collect();
+    let found =
+        uuids::find(&seen).map_err(|missing| anyhow::anyhow!("{missing:#06x} not found"))?;
+    Ok(Characteristics {
+        service_flag: chars[found.service_flag].clone(),
+        recv: chars[found.recv].clone(),
+        sent: chars[found.sent].clone(),
+    })
+}
+
+fn u16_uuid(u: u16) -> BleUuid {
+    BleUuid::from(Uuid16(u))
+}
+
+async fn negotiate_phy(conn_handle: u16, preference: PhyPreference) {
+    if let Err(err) = link::request_phy(conn_handle, preference) {
+        log::warn!("{err:#}; staying on the current PHY");
+    }
+    time::sleep(PHY_SETTLE).await;
+    let watch_link = status::WatchLink {
+        phy: link::read_phy(conn_handle),
+        interval_ms: link::interval_ms(conn_handle),
+    };
+    info!(
+        "Watch link ({preference}): {}",
+        link::describe(watch_link.phy, watch_link.interval_ms)
+    );
+    status::set_link(Some(watch_link));
+}
+
+/// Why the live session should give way, checked once a second: its target
+/// was deleted or disabled, the user picked another one, or (with
+/// `miwear_switch` on) a higher-priority watch is in range.
+async fn session_end_reason(
+    current: &MiWearTarget,
+    last_preferred_scan: &mut time::Instant,
+) -> Option<&'static str> {
+    match targets::get(current.id) {
+        None => return Some("target deleted"),
+        Some(target) if !target.enabled => return Some("target disabled"),
+        Some(_) => {}
+    }
+    match targets::connect_request() {
+        Some(id) if id == current.id => targets::clear_connect_request(),
+        Some(_) => return Some("another target requested"),
+        None => {}
+    }
+    if !settings::get(&targets::SWITCH_TO_PREFERRED)
+        || last_preferred_scan.elapsed() < PREFERRED_SCAN_INTERVAL
+    {
+        return None;
+    }
+    *last_preferred_scan = time::Instant::now();
+    let preferred: Vec<_> = targets::candidates()
+        .into_iter()
+        .take_while(|target| target.id != current.id)
+        .collect();
+    if preferred.is_empty() {
+        return None;
+    }
+    let judged = preferred.clone();
+    let mut scan =
+        Scan::start(
+            "preferred",
+            PREFERRED_SCAN,
+            None,
+            move |sighting| match scan::rank_of(&judged, sighting) {
+                Some(_) => Verdict::ReportAndStop,
+                None => Verdict::Skip,
+            },
+        );
+    let found = scan.results_stream().recv().await;
+    if let Err(err) = scan.finish().await {
+        log::warn!("Preferred-target scan failed: {err:?}");
+        return None;
+    }
+    let rank = scan::rank_of(&preferred, &found?)?;
+    info!("Preferred target {} is in range", preferred[rank].label());
+    Some("preferred target in range")
+}
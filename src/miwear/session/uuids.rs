@@ -0,0 +1,71 @@
+//! Which discovered characteristics are 0x0050, 0x005E and 0x005F: by exact
+//! 16-bit UUID first, then, for any still missing, by UUID substring, since
+//! some firmwares expose them as 128-bit UUIDs.
+
+pub const SERVICE_FLAG: u16 = 0x0050;
+pub const RECV: u16 = 0x005E;
+pub const SENT: u16 = 0x005F;
+
+/// Claimed in this order when one characteristic could be several.
+const PRIORITY: [u16; 3] = [RECV, SENT, SERVICE_FLAG];
+
+/// A discovered characteristic's UUID, as the lookup compares it.
+#[derive(Clone, Debug)]
+pub struct SeenUuid {
+    /// Set for a 16-bit UUID.
+    short: Option<u16>,
+    /// The UUID as text, without dashes, lowercase.
+    text: String,
+}
+
+impl SeenUuid {
+    /// `text` is the UUID's `{:?}` form.
+    pub fn new(short: Option<u16>, text: &str) -> Self {
+        Self {
+            short,
+            text: text.replace('-', "").to_ascii_lowercase(),
+        }
+    }
+}
+
+/// Indexes into the discovered characteristics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Found {
+    pub service_flag: usize,
+    pub recv: usize,
+    pub sent: usize,
+}
+
+/// The three characteristics among `uuids`, or the first one missing, in
+/// the order 0x0050, 0x005E, 0x005F.
+pub fn find(uuids: &[SeenUuid]) -> Result<Found, u16> {
+    let mut found = [None; PRIORITY.len()];
+    for (index, uuid) in uuids.iter().enumerate() {
+        claim(&mut found, index, |wanted| uuid.short == Some(wanted));
+    }
+    if found.contains(&None) {
+        for (index, uuid) in uuids.iter().enumerate() {
+            claim(&mut found, index, |wanted| {
+                uuid.text.contains(&format!("{wanted:04x}"))
+            });
+        }
+    }
+    let at = |wanted: u16| {
+        let slot = PRIORITY.iter().position(|uuid| *uuid == wanted);
+        slot.and_then(|slot| found[slot]).ok_or(wanted)
+    };
+    Ok(Found {
+        service_flag: at(SERVICE_FLAG)?,
+        recv: at(RECV)?,
+        sent: at(SENT)?,
+    })
+}
+
+/// Gives `index` to the first still-missing characteristic it `is`.
+fn claim(found: &mut [Option<usize>; 3], index: usize, is: impl Fn(u16) -> bool) {
+    if let Some(slot) =
+        (0..PRIORITY.len()).find(|slot| found[*slot].is_none() && is(PRIORITY[*slot]))
+    {
+        found[slot] = Some(index);
+    }
+}
@@ -0,0 +1,186 @@
+//! What the UI and HTTP API know about the watch. Every change is announced
+//! on the event bus, so readers wait for events instead of polling.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use log::info;
+
+use crate::{
+    activity::{self, Activity, ActivityGuard},
+    ble::link::Phy,
+    events::{self, SystemEvent},
+    metrics,
+};
+
+static PHASE: Mutex<ConnectionPhase> = Mutex::new(ConnectionPhase::Idle);
+static TELEMETRY: Mutex<Option<WatchTelemetry>> = Mutex::new(None);
+static TRANSFER: Mutex<Option<TransferProgress>> = Mutex::new(None);
+/// Held while a transfer is reported, so Wi-Fi scans wait for it to end.
+static TRANSFER_ACTIVITY: Mutex<Option<ActivityGuard>> = Mutex::new(None);
+static LINK: Mutex<Option<WatchLink>> = Mutex::new(None);
+/// A watch has been ready at least once this boot; later readies are reconnects.
+static WAS_READY: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The watch refused our auth key; retrying with the same key is pointless.
+    AuthRejected,
+    /// Handshake did not finish in time; worth retrying.
+    Timeout,
+    /// Scan, GATT or link error; worth retrying.
+    Link,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionPhase {
+    Idle,
+    Scanning,
+    Connecting,
+    Handshaking,
+    Ready { addr: String },
+    Failed { kind: FailureKind, reason: String },
+}
+
+/// Live figures for the connected watch; cleared whenever the phase leaves
+/// `Ready`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WatchTelemetry {
+    pub name: String,
+    pub battery: Option<u8>,
+}
+
+/// Link-layer parameters read back after PHY negotiation; cleared once the
+/// link is gone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchLink {
+    pub phy: Option<Phy>,
+    pub interval_ms: Option<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferProgress {
+    pub label: String,
+    /// 0.0 ..= 1.0
+    pub fraction: f32,
+}
+
+impl ConnectionPhase {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConnectionPhase::Idle => "idle",
+            ConnectionPhase::Scanning => "scanning",
+            ConnectionPhase::Connecting => "connecting",
+            ConnectionPhase::Handshaking => "handshaking",
+            ConnectionPhase::Ready { .. } => "ready",
+            ConnectionPhase::Failed { .. } => "failed",
+        }
+    }
+}
+
+impl fmt::Display for ConnectionPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionPhase::Ready { addr } => write!(f, "ready ({addr})"),
+            ConnectionPhase::Failed { kind, reason } => write!(f, "failed ({kind:?}): {reason}"),
+            other => f.write_str(other.label()),
+        }
+    }
+}
+
+pub fn set_phase(phase: ConnectionPhase) {
+    if let Ok(mut slot) = PHASE.lock() {
+        if *slot == phase {
+            return;
+        }
+        info!("MiWear phase: {phase}");
+        if matches!(phase, ConnectionPhase::Ready { .. }) && WAS_READY.swap(true, Ordering::Relaxed)
+        {
+            metrics::record(metrics::Counter::BleReconnects, 1);
+        }
+        if !matches!(phase, ConnectionPhase::Ready { .. }) {
+            if let Ok(mut telemetry) = TELEMETRY.lock() {
+                *telemetry = None;
+            }
+        }
+        if matches!(
+            phase,
+            ConnectionPhase::Idle | ConnectionPhase::Scanning | ConnectionPhase::Failed { .. }
+        ) {
+            if let Ok(mut link) = LINK.lock() {
+                *link = None;
+            }
+        }
+        *slot = phase;
+    }
+    events::publish(SystemEvent::WatchStatusChanged);
+}
+
+pub fn set_telemetry(telemetry: Option<WatchTelemetry>) {
+    let level = telemetry.as_ref().and_then(|watch| watch.battery);
+    let previous = self::telemetry();
+    if !replace(&TELEMETRY, telemetry) {
+        return;
+    }
+    if previous.as_ref().and_then(|watch| watch.battery) != level {
+        events::publish(SystemEvent::WatchBatteryChanged { level });
+    } else {
+        events::publish(SystemEvent::WatchStatusChanged);
+    }
+}
+
+pub fn telemetry() -> Option<WatchTelemetry> {
+    TELEMETRY.lock().ok().and_then(|slot| slot.clone())
+}
+
+pub fn set_link(link: Option<WatchLink>) {
+    if replace(&LINK, link) {
+        events::publish(SystemEvent::WatchStatusChanged);
+    }
+}
+
+pub fn link() -> Option<WatchLink> {
+    LINK.lock().ok().and_then(|slot| *slot)
+}
+
+pub fn set_transfer(transfer: Option<TransferProgress>) {
+    let fraction = transfer.as_ref().map(|transfer| transfer.fraction);
+    if let Ok(mut guard) = TRANSFER_ACTIVITY.lock() {
+        if transfer.is_none() {
+            guard.take();
+        } else if guard.is_none() {
+            *guard = Some(activity::begin(Activity::WatchTransfer));
+        }
+    }
+    if replace(&TRANSFER, transfer) {
+        events::publish(SystemEvent::TransferProgress { fraction });
+    }
+}
+
+pub fn transfer() -> Option<TransferProgress> {
+    TRANSFER.lock().ok().and_then(|slot| slot.clone())
+}
+
+/// Returns whether the stored value changed.
+fn replace<T: PartialEq>(slot: &Mutex<Option<T>>, value: Option<T>) -> bool {
+    let Ok(mut slot) = slot.lock() else {
+        return false;
+    };
+    if *slot == value {
+        return false;
+    }
+    *slot = value;
+    true
+}
+
+pub fn phase() -> ConnectionPhase {
+    PHASE
+        .lock()
+        .map(|phase| phase.clone())
+        .unwrap_or(ConnectionPhase::Idle)
+}
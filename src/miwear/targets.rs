@@ -0,0 +1,434 @@
+//! Watches the supervisor may connect to, in priority order (first wins).
+//! Each carries its own auth key and an optional PHY override, the one
+//! coexistence knob the link has. Persisted as tab-separated lines in the
+//! `miwear` NVS namespace, sealed by [`secrets`] since the lines carry the
+//! keys; the legacy single `miwear_authkey` setting seeds the list on first
+//! boot. A list stored in plaintext by older firmware is sealed on load.
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Mutex,
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::{info, warn};
+
+use super::AUTH_KEY;
+use crate::{
+    ble::link::PhyPreference,
+    secrets,
+    settings::{self, SettingKey},
+};
+
+pub mod selection;
+
+pub const MAX_TARGETS: usize = 8;
+/// `true` drops a lower-priority watch as soon as a preferred one shows up.
+pub const SWITCH_TO_PREFERRED: SettingKey<bool> = SettingKey::new("miwear_switch", "false");
+
+const NVS_NAMESPACE: &str = "miwear";
+const NVS_TARGETS_KEY: &str = "targets";
+/// Longest encoded list; sealing roughly doubles it, and NVS strings stop at
+/// 4000 bytes.
+const TARGETS_BUFFER_LEN: usize = 1920;
+const LEGACY_NAME: &str = "Xiaomi Watch S4";
+const MAX_TEXT_LEN: usize = 32;
+
+static TARGETS: Mutex<Vec<MiWearTarget>> = Mutex::new(Vec::new());
+/// Target the user asked to connect to next, ahead of the priority order.
+static CONNECT_REQUEST: Mutex<Option<u32>> = Mutex::new(None);
+/// Auth keys the watch refused; targets using one are skipped until the next
+/// edit to the list.
+static REJECTED_KEYS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static ACTIVE: Mutex<Option<u32>> = Mutex::new(None);
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MiWearTarget {
+    /// Stable across reorders; assigned on insert.
+    pub id: u32,
+    pub enabled: bool,
+    /// Matched as a substring of the advertised name.
+    pub name: String,
+    /// Exact BLE address; when set it wins over `name`.
+    pub addr: Option<String>,
+    pub nickname: String,
+    pub auth_key: String,
+    /// Falls back to the global `miwear_phy` setting.
+    pub phy: Option<PhyPreference>,
+}
+
+impl MiWearTarget {
+    pub fn label(&self) -> &str {
+        if self.nickname.is_empty() {
+            &self.name
+        } else {
+            &self.nickname
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() && self.addr.is_none() {
+            bail!("target needs a name or an address");
+        }
+        if self.auth_key.len() != 32 || !self.auth_key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("auth key must be 32 hex characters");
+        }
+        for (field, text) in [("name", &self.name), ("nickname", &self.nickname)] {
+            if text.len() > MAX_TEXT_LEN {
+                bail!("{field} longer than {MAX_TEXT_LEN} bytes");
+            }
+            if text.contains(['\t', '\n']) {
+                bail!("{field} contains a tab or newline");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl selection::Target for MiWearTarget {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn auth_key(&self) -> &str {
+        &self.auth_key
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn addr(&self) -> Option<&str> {
+        self.addr.as_deref()
+    }
+}
+
+pub fn load() {
+    let mut plaintext = false;
+    let loaded = match settings::open_namespace(NVS_NAMESPACE) {
+        Ok(store) => {
+            let mut buf = vec![0u8; secrets::sealed_len(TARGETS_BUFFER_LEN)];
+            match store.get_str(NVS_TARGETS_KEY, &mut buf) {
+                Ok(Some(stored)) => {
+                    plaintext = secrets::needs_sealing(stored);
+                    secrets::open(NVS_TARGETS_KEY, stored)
+                        .map(|raw| raw.lines().filter_map(decode).collect::<Vec<_>>())
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    warn!("MiWear targets unreadable: {err:?}");
+                    None
+                }
+            }
+        }
+        Err(err) => {
+            warn!("MiWear target store unavailable: {err:?}");
+            None
+        }
+    };
+    let targets = loaded.unwrap_or_else(|| {
+        info!("No MiWear targets stored, seeding from {}", AUTH_KEY.name);
+        vec![MiWearTarget {
+            id: 1,
+            enabled: true,
+            name: LEGACY_NAME.to_string(),
+            addr: None,
+            nickname: String::new(),
+            auth_key: secrets::get(&AUTH_KEY),
+            phy: None,
+        }]
+    });
+    info!("Loaded {} MiWear targets", targets.len());
+    if plaintext {
+        info!("Sealing the MiWear target list");
+        let encoded: String = targets.iter().map(encode).collect();
+        crate::nvs::write(
+            NVS_NAMESPACE,
+            NVS_TARGETS_KEY,
+            secrets::seal(NVS_TARGETS_KEY, &encoded),
+        );
+    }
+    if let Ok(mut slot) = TARGETS.lock() {
+        *slot = targets;
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn list() -> Vec<MiWearTarget> {
+    TARGETS
+        .lock()
+        .map(|targets| targets.clone())
+        .unwrap_or_default()
+}
+
+pub fn get(id: u32) -> Option<MiWearTarget> {
+    list().into_iter().find(|target| target.id == id)
+}
+
+/// Enabled targets whose key has not been refused, best first, with a
+/// pending connect request moved to the front.
+pub fn candidates() -> Vec<MiWearTarget> {
+    let rejected = REJECTED_KEYS
+        .lock()
+        .map(|keys| keys.clone())
+        .unwrap_or_default();
+    let requested = CONNECT_REQUEST.lock().ok().and_then(|slot| *slot);
+    selection::candidates(list(), &rejected, requested)
+}
+
+/// Replaces the whole list, e.g. from an HTTP import. Entries with id 0 get
+/// a fresh id; order is priority.
+pub fn replace_all(mut targets: Vec<MiWearTarget>) -> Result<()> {
+    if targets.len() > MAX_TARGETS {
+        bail!("at most {MAX_TARGETS} targets");
+    }
+    for target in &targets {
+        target.validate()?;
+    }
+    let mut next_id = targets.iter().map(|target| target.id).max().unwrap_or(0);
+    for target in targets.iter_mut().filter(|target| target.id == 0) {
+        next_id += 1;
+        target.id = next_id;
+    }
+    let mut ids: Vec<_> = targets.iter().map(|target| target.id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.len() != targets.len() {
+        bail!("duplicate target ids");
+    }
+    commit(targets)
+}
+
+/// Appends a target at the lowest priority and returns its id.
+pub fn add(mut target: MiWearTarget) -> Result<u32> {
+    target.validate()?;
+    let mut targets = list();
+    if targets.len() >= MAX_TARGETS {
+        bail!("at most {MAX_TARGETS} targets");
+    }
+    target.id = targets.iter().map(|target| target.id).max().unwrap_or(0) + 1;
+    let id = target.id;
+    targets.push(target);
+    commit(targets)?;
+    Ok(id)
+}
+
+pub fn update(target: MiWearTarget) -> Result<()> {
+    target.validate()?;
+    let mut targets = list();
+    let slot = targets
+        .iter_mut()
+        .find(|existing| existing.id == target.id)
+        .ok_or_else(|| anyhow!("no target {}", target.id))?;
+    *slot = target;
+    commit(targets)
+}
+
+pub fn set_enabled(id: u32, enabled: bool) -> Result<()> {
+    let mut target = get(id).ok_or_else(|| anyhow!("no target {id}"))?;
+    target.enabled = enabled;
+    update(target)
+}
+
+/// The supervisor notices on its next check and drops the link if this was
+/// the connected watch.
+pub fn remove(id: u32) -> Result<MiWearTarget> {
+    let mut targets = list();
+    let index = targets
+        .iter()
+        .position(|target| target.id == id)
+        .ok_or_else(|| anyhow!("no target {id}"))?;
+    let removed = targets.remove(index);
+    commit(targets)?;
+    Ok(removed)
+}
+
+/// Swaps the target with the one above it; no-op at the top.
+pub fn raise(id: u32) -> Result<()> {
+    let mut targets = list();
+    let index = targets
+        .iter()
+        .position(|target| target.id == id)
+        .ok_or_else(|| anyhow!("no target {id}"))?;
+    if index == 0 {
+        return Ok(());
+    }
+    targets.swap(index, index - 1);
+    commit(targets)
+}
+
+/// Asks the supervisor to switch to `id` now, whatever its priority.
+pub fn request_connect(id: u32) -> Result<()> {
+    let target = get(id).ok_or_else(|| anyhow!("no target {id}"))?;
+    if !target.enabled {
+        bail!("{} is disabled", target.label());
+    }
+    if let Ok(mut slot) = CONNECT_REQUEST.lock() {
+        *slot = Some(id);
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn connect_request() -> Option<u32> {
+    CONNECT_REQUEST.lock().ok().and_then(|slot| *slot)
+}
+
+/// Called once the requested target is connected, or given up on.
+pub fn clear_connect_request() {
+    if let Ok(mut slot) = CONNECT_REQUEST.lock() {
+        *slot = None;
+    }
+}
+
+/// The target of the current ready session, if any.
+pub fn active() -> Option<u32> {
+    ACTIVE.lock().ok().and_then(|slot| *slot)
+}
+
+pub fn set_active(id: Option<u32>) {
+    if let Ok(mut slot) = ACTIVE.lock() {
+        *slot = id;
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn mark_rejected(auth_key: &str) {
+    if let Ok(mut keys) = REJECTED_KEYS.lock() {
+        if !keys.iter().any(|key| key == auth_key) {
+            keys.push(auth_key.to_string());
+        }
+    }
+}
+
+/// Auth keys are secrets; show just enough to tell two apart.
+pub fn masked_key(auth_key: &str) -> String {
+    let tail = auth_key
+        .get(auth_key.len().saturating_sub(4)..)
+        .unwrap_or("");
+    format!("…{tail}")
+}
+
+/// Bumped on every edit so the supervisor and UI can notice changes.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+fn commit(targets: Vec<MiWearTarget>) -> Result<()> {
+    let encoded: String = targets.iter().map(encode).collect();
+    if encoded.len() >= TARGETS_BUFFER_LEN {
+        bail!("target list too large to store");
+    }
+    let mut slot = TARGETS
+        .lock()
+        .map_err(|_| anyhow!("target list poisoned"))?;
+    *slot = targets;
+    drop(slot);
+    if let Ok(mut keys) = REJECTED_KEYS.lock() {
+        keys.clear();
+    }
+    crate::nvs::write(
+        NVS_NAMESPACE,
+        NVS_TARGETS_KEY,
+        secrets::seal(NVS_TARGETS_KEY, &encoded),
+    );
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+fn encode(target: &MiWearTarget) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+        target.id,
+        u8::from(target.enabled),
+        target.name,
+        target.addr.as_deref().unwrap_or(""),
+        target.nickname,
+        target.auth_key,
+        target.phy.map(|phy| phy.to_string()).unwrap_or_default()
+    )
+}
+
+fn decode(line: &str) -> Option<MiWearTarget> {
+    let mut fields = line.split('\t');
+    let target = MiWearTarget {
+        id: fields.next()?.parse().ok()?,
+        enabled: fields.next()? == "1",
+        name: fields.next()?.to_string(),
+        addr: Some(fields.next()?)
+            .filter(|addr| !addr.is_empty())
+            .map(str::to_string),
+        nickname: fields.next()?.to_string(),
+        auth_key: fields.next()?.to_string(),
+        phy: fields.next().and_then(|phy| phy.parse().ok()),
+    };
+    Some(target)
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "targets",
+        "list | add <name> <key> | enable|disable|del|up|connect <id>",
+        |args| {
+            let id = || -> Result<u32> {
+                args.get(1)
+                    .and_then(|id| id.parse().ok())
+                    .ok_or_else(|| anyhow!("usage: targets {} <id>", args[0]))
+            };
+            match args.first().copied() {
+                None | Some("list") => {
+                    let active = active();
+                    let lines: Vec<_> = list()
+                        .iter()
+                        .map(|target| {
+                            format!(
+                                "{} {}{} {} match={} key={} phy={}",
+                                target.id,
+                                if active == Some(target.id) { "*" } else { " " },
+                                if target.enabled { "on " } else { "off" },
+                                target.label(),
+                                target.addr.as_deref().unwrap_or(&target.name),
+                                masked_key(&target.auth_key),
+                                target
+                                    .phy
+                                    .map(|phy| phy.to_string())
+                                    .unwrap_or_else(|| "default".to_string())
+                            )
+                        })
+                        .collect();
+                    Ok(if lines.is_empty() {
+                        "no targets".to_string()
+                    } else {
+                        lines.join("\n")
+                    })
+                }
+                Some("add") => {
+                    let [_, name, key] = args else {
+                        bail!("usage: targets add <name> <key>");
+                    };
+                    let id = add(MiWearTarget {
+                        id: 0,
+                        enabled: true,
+                        name: name.to_string(),
+                        addr: None,
+                        nickname: String::new(),
+                        auth_key: key.to_string(),
+                        phy: None,
+                    })?;
+                    Ok(format!("added target {id}"))
+                }
+                Some("enable") => set_enabled(id()?, true).map(|_| "enabled".to_string()),
+                Some("disable") => set_enabled(id()?, false).map(|_| "disabled".to_string()),
+                Some("del") => remove(id()?).map(|target| format!("removed {}", target.label())),
+                Some("up") => raise(id()?).map(|_| "raised".to_string()),
+                Some("connect") => request_connect(id()?).map(|_| "switch requested".to_string()),
+                Some(other) => bail!("unknown subcommand {other}"),
+            }
+        },
+    );
+}
@@ -0,0 +1,46 @@
+//! Which stored targets a scan looks for, in what order, and which of them
+//! an advertisement is.
+
+/// What selection reads from a stored target.
+pub trait Target {
+    fn id(&self) -> u32;
+    fn enabled(&self) -> bool;
+    fn auth_key(&self) -> &str;
+    /// Matched as a substring of the advertised name.
+    fn name(&self) -> &str;
+    /// Exact BLE address; when set it wins over the name.
+    fn addr(&self) -> Option<&str>;
+}
+
+/// Whether an advertisement from `addr`, named `adv_name`, is `target`.
+pub fn matches(target: &impl Target, adv_name: Option<&str>, addr: &str) -> bool {
+    match target.addr() {
+        Some(wanted) => wanted.eq_ignore_ascii_case(addr),
+        None => adv_name.is_some_and(|name| name.contains(target.name())),
+    }
+}
+
+/// Enabled targets whose key has not been refused, best first, with the
+/// `requested` one moved to the front.
+pub fn candidates<T: Target>(
+    targets: Vec<T>,
+    rejected: &[String],
+    requested: Option<u32>,
+) -> Vec<T> {
+    let mut candidates: Vec<_> = targets
+        .into_iter()
+        .filter(|target| target.enabled() && !rejected.iter().any(|key| key == target.auth_key()))
+        .collect();
+    if let Some(index) = requested.and_then(|id| candidates.iter().position(|t| t.id() == id)) {
+        let target = candidates.remove(index);
+        candidates.insert(0, target);
+    }
+    candidates
+}
+
+/// The position in `candidates` of the first one the advertisement is.
+pub fn rank<T: Target>(candidates: &[T], adv_name: Option<&str>, addr: &str) -> Option<usize> {
+    candidates
+        .iter()
+        .position(|target| matches(target, adv_name, addr))
+}
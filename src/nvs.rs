@@ -0,0 +1,239 @@
+//! Single writer for NVS. NVS writes can stall for tens of milliseconds on a
+//! flash erase, and the UI shares the main thread with most callers, so writes
+//! are queued to a dedicated thread that coalesces them per key and commits
+//! in batches. Reads stay direct.
+//!
+//! Anything that writes from a panic hook must open its own handle and write
+//! synchronously: by then this thread may never run again.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError, SyncSender, TrySendError},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use log::{debug, warn};
+use tokio::sync::oneshot;
+
+mod batch;
+
+use batch::Batch;
+
+const QUEUE_DEPTH: usize = 32;
+/// Writes arriving within this window of the first one share a batch.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+const STACK_SIZE: usize = 6 * 1024;
+
+static WRITER: OnceLock<Writer> = OnceLock::new();
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+static COMMITS: AtomicU32 = AtomicU32::new(0);
+static COALESCED: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug)]
+pub struct WriterStats {
+    /// Requests sent but not yet picked up by the writer thread.
+    pub queue_depth: usize,
+    pub commits: u32,
+    /// Writes dropped because a newer value for the same key replaced them.
+    pub coalesced: u32,
+}
+
+pub fn stats() -> WriterStats {
+    WriterStats {
+        queue_depth: QUEUED.load(Ordering::Relaxed),
+        commits: COMMITS.load(Ordering::Relaxed),
+        coalesced: COALESCED.load(Ordering::Relaxed),
+    }
+}
+
+enum Request {
+    /// `None` erases the key.
+    Write {
+        namespace: &'static str,
+        key: &'static str,
+        value: Option<String>,
+    },
+    Flush(oneshot::Sender<Result<()>>),
+}
+
+pub struct Writer {
+    tx: SyncSender<Request>,
+}
+
+impl Writer {
+    /// Spawns the writer thread. Writes issued before this are applied
+    /// synchronously.
+    pub fn start(partition: EspDefaultNvsPartition) -> Result<()> {
+        let (tx, rx) = mpsc::sync_channel(QUEUE_DEPTH);
+        thread::Builder::new()
+            .name("nvs-writer".into())
+            .stack_size(STACK_SIZE)
+            .spawn(move || run(partition, rx))
+            .context("spawn NVS writer")?;
+        WRITER
+            .set(Writer { tx })
+            .map_err(|_| anyhow!("NVS writer already started"))
+    }
+}
+
+/// Queues `value` for `namespace`/`key`. A later write to the same key
+/// replaces this one if both land in the same batch. Falls back to a
+/// synchronous write when the writer is not running yet, and waits for room
+/// when its queue is full so writes to a key stay in order.
+pub fn write(namespace: &'static str, key: &'static str, value: String) {
+    queue(namespace, key, Some(value));
+}
+
+/// Queues erasing `namespace`/`key`, ordered and coalesced with writes to it.
+pub fn remove(namespace: &'static str, key: &'static str) {
+    queue(namespace, key, None);
+}
+
+fn queue(namespace: &'static str, key: &'static str, value: Option<String>) {
+    let Some(writer) = WRITER.get() else {
+        if let Err(err) = write_now(namespace, key, value.as_deref()) {
+            warn!("NVS write {namespace}/{key} failed: {err:#}");
+        }
+        return;
+    };
+    QUEUED.fetch_add(1, Ordering::Relaxed);
+    let request = Request::Write {
+        namespace,
+        key,
+        value,
+    };
+    match writer.tx.try_send(request) {
+        Ok(()) => {}
+        Err(TrySendError::Full(request)) => {
+            // The writer pulls requests off the queue while it batches, so
+            // this only waits out a commit in progress.
+            warn!("NVS write queue full, waiting to queue {namespace}/{key}");
+            if writer.tx.send(request).is_err() {
+                QUEUED.fetch_sub(1, Ordering::Relaxed);
+                warn!("NVS writer gone, dropped write to {namespace}/{key}");
+            }
+        }
+        Err(TrySendError::Disconnected(_)) => {
+            QUEUED.fetch_sub(1, Ordering::Relaxed);
+            warn!("NVS writer gone, dropped write to {namespace}/{key}");
+        }
+    }
+}
+
+/// Resolves once every write queued before the call is committed. For
+/// shutdown paths; nothing else needs to wait.
+pub async fn flush() -> Result<()> {
+    let writer = WRITER
+        .get()
+        .ok_or_else(|| anyhow!("NVS writer not started"))?;
+    let (done, wait) = oneshot::channel();
+    QUEUED.fetch_add(1, Ordering::Relaxed);
+    if writer.tx.send(Request::Flush(done)).is_err() {
+        QUEUED.fetch_sub(1, Ordering::Relaxed);
+        anyhow::bail!("NVS writer gone");
+    }
+    wait.await
+        .map_err(|_| anyhow!("NVS writer dropped flush"))?
+}
+
+fn run(partition: EspDefaultNvsPartition, rx: mpsc::Receiver<Request>) {
+    let mut handles: HashMap<&'static str, EspNvs<NvsDefault>> = HashMap::new();
+    while let Ok(first) = rx.recv() {
+        let mut batch = Batch::default();
+        let mut waiters = Vec::new();
+        let deadline = Instant::now() + DEBOUNCE;
+        let mut next = Some(first);
+        while let Some(request) = next.take() {
+            QUEUED.fetch_sub(1, Ordering::Relaxed);
+            match request {
+                Request::Write {
+                    namespace,
+                    key,
+                    value,
+                } => {
+                    if batch.push(namespace, key, value) {
+                        COALESCED.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Request::Flush(done) => {
+                    waiters.push(done);
+                    break;
+                }
+            }
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(request) => next = Some(request),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {}
+            }
+        }
+
+        let mut failure = None;
+        for (namespace, key, value) in batch.iter() {
+            let result = handle_for(&mut handles, &partition, namespace)
+                .and_then(|nvs| set(nvs, namespace, key, value));
+            match result {
+                Ok(()) => {
+                    COMMITS.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(err) => {
+                    warn!("NVS write {namespace}/{key} failed: {err:#}");
+                    failure = Some(err);
+                }
+            }
+        }
+        if !batch.is_empty() {
+            debug!("NVS writer committed {} keys", batch.len());
+        }
+        for done in waiters {
+            let _ = done.send(match &failure {
+                Some(err) => Err(anyhow!("{err:#}")),
+                None => Ok(()),
+            });
+        }
+    }
+}
+
+fn handle_for<'a>(
+    handles: &'a mut HashMap<&'static str, EspNvs<NvsDefault>>,
+    partition: &EspDefaultNvsPartition,
+    namespace: &'static str,
+) -> Result<&'a mut EspNvs<NvsDefault>> {
+    if !handles.contains_key(namespace) {
+        let nvs = EspNvs::new(partition.clone(), namespace, true)
+            .with_context(|| format!("open NVS namespace {namespace}"))?;
+        handles.insert(namespace, nvs);
+    }
+    handles
+        .get_mut(namespace)
+        .ok_or_else(|| anyhow!("NVS namespace {namespace} vanished"))
+}
+
+fn write_now(namespace: &'static str, key: &'static str, value: Option<&str>) -> Result<()> {
+    let mut nvs = crate::settings::open_namespace(namespace)?;
+    set(&mut nvs, namespace, key, value)?;
+    COMMITS.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+fn set(
+    nvs: &mut EspNvs<NvsDefault>,
+    namespace: &str,
+    key: &str,
+    value: Option<&str>,
+) -> Result<()> {
+    match value {
+        Some(value) => nvs
+            .set_str(key, value)
+            .with_context(|| format!("persist {namespace}/{key}")),
+        None => nvs
+            .remove(key)
+            .map(|_| ())
+            .with_context(|| format!("erase {namespace}/{key}")),
+    }
+}
@@ -0,0 +1,49 @@
+//! The writes one commit applies, coalesced per key.
+
+/// Pending writes in the order their keys were first queued; `None` erases
+/// the key.
+#[derive(Default)]
+pub struct Batch {
+    entries: Vec<((&'static str, &'static str), Option<String>)>,
+}
+
+impl Batch {
+    /// Queues `value` for `namespace`/`key`. A write already pending for
+    /// the key takes the new value in its place; returns whether it did.
+    pub fn push(
+        &mut self,
+        namespace: &'static str,
+        key: &'static str,
+        value: Option<String>,
+    ) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|(slot, _)| *slot == (namespace, key))
+        {
+            Some((_, pending)) => {
+                *pending = value;
+                true
+            }
+            None => {
+                self.entries.push(((namespace, key), value));
+                false
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// `(namespace, key, value)` in commit order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &'static str, Option<&str>)> + '_ {
+        self.entries
+            .iter()
+            .map(|((namespace, key), value)| (*namespace, *key, value.as_deref()))
+    }
+}
@@ -0,0 +1,132 @@
+//! Application partition layout, read once at boot: which slot runs, which
+//! one an update would be written to, and how large each is. `POST /ota`
+//! checks an upload against the update slot with [`check`] before writing.
+
+use std::{ffi::CStr, sync::OnceLock};
+
+use anyhow::{bail, Result};
+use esp_idf_svc::sys::{
+    esp_ota_get_next_update_partition, esp_ota_get_running_partition, esp_partition_find,
+    esp_partition_get, esp_partition_iterator_release, esp_partition_next,
+    esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY, esp_partition_t,
+    esp_partition_type_t_ESP_PARTITION_TYPE_APP,
+};
+use log::info;
+
+pub mod check;
+
+static LAYOUT: OnceLock<Layout> = OnceLock::new();
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub label: String,
+    pub address: u32,
+    pub size: u32,
+}
+
+impl Slot {
+    /// # Safety
+    /// `partition` must come from the IDF partition API, which keeps its
+    /// entries alive for the whole run.
+    unsafe fn read(partition: *const esp_partition_t) -> Option<Self> {
+        let partition = partition.as_ref()?;
+        Some(Self {
+            label: CStr::from_ptr(partition.label.as_ptr())
+                .to_string_lossy()
+                .into_owned(),
+            address: partition.address,
+            size: partition.size,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub running: Slot,
+    /// `None` on a factory-only table, which cannot take updates.
+    pub update: Option<Slot>,
+    /// Every app partition in table order, `running` included.
+    pub apps: Vec<Slot>,
+}
+
+/// Reads the partition table. Run once at boot.
+pub fn init() -> Result<()> {
+    let layout = read_layout()?;
+    info!(
+        "Running from {} (0x{:X}, {} KB); update slot {}",
+        layout.running.label,
+        layout.running.address,
+        layout.running.size / 1024,
+        layout.update.as_ref().map_or_else(
+            || "none".to_string(),
+            |slot| format!("{} ({} KB)", slot.label, slot.size / 1024)
+        )
+    );
+    let _ = LAYOUT.set(layout);
+    Ok(())
+}
+
+/// `None` until [`init`] has run, or if it failed.
+pub fn layout() -> Option<&'static Layout> {
+    LAYOUT.get()
+}
+
+fn read_layout() -> Result<Layout> {
+    // Safety: both calls return entries of the table the bootloader loaded,
+    // or null.
+    let running = unsafe { esp_ota_get_running_partition() };
+    let Some(running_slot) = (unsafe { Slot::read(running) }) else {
+        bail!("no running app partition");
+    };
+    let update = unsafe { Slot::read(esp_ota_get_next_update_partition(running)) };
+    let mut apps = Vec::new();
+    unsafe {
+        let mut it = esp_partition_find(
+            esp_partition_type_t_ESP_PARTITION_TYPE_APP,
+            esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY,
+            core::ptr::null(),
+        );
+        while !it.is_null() {
+            apps.extend(Slot::read(esp_partition_get(it)));
+            it = esp_partition_next(it);
+        }
+        // Null-safe; `esp_partition_next` already freed a finished iterator.
+        esp_partition_iterator_release(it);
+    }
+    Ok(Layout {
+        running: running_slot,
+        update,
+        apps,
+    })
+}
+
+pub fn register_commands() {
+    crate::console::register("ota", "app partitions and the update slot", |args| {
+        if !args.is_empty() {
+            bail!("usage: ota");
+        }
+        let Some(layout) = layout() else {
+            return Ok("partition table not read".to_string());
+        };
+        let lines: Vec<String> = layout
+            .apps
+            .iter()
+            .map(|slot| {
+                let role = if *slot == layout.running {
+                    " running"
+                } else if Some(slot) == layout.update.as_ref() {
+                    " update"
+                } else {
+                    ""
+                };
+                format!(
+                    "{} at 0x{:X}: {} KB{role}",
+                    slot.label,
+                    slot.address,
+                    slot.size / 1024
+                )
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    });
+}
@@ -0,0 +1,77 @@
+//! What an uploaded image must pass before the first byte is written to the
+//! update slot.
+
+use std::fmt;
+
+/// First byte of every ESP application image.
+pub const IMAGE_MAGIC: u8 = 0xE9;
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// Chunked or unannounced bodies cannot be checked up front.
+    NoLength,
+    Empty,
+    TooLarge {
+        len: u64,
+        slot: u32,
+    },
+    Gzip,
+    NotAnImage {
+        first: u8,
+    },
+}
+
+impl Rejection {
+    pub fn status(self) -> u16 {
+        match self {
+            Rejection::NoLength => 411,
+            Rejection::Empty => 400,
+            Rejection::TooLarge { .. } => 413,
+            Rejection::Gzip | Rejection::NotAnImage { .. } => 415,
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::NoLength => f.write_str("send the image with a Content-Length"),
+            Rejection::Empty => f.write_str("the image is empty"),
+            Rejection::TooLarge { len, slot } => write!(
+                f,
+                "the image is {len} bytes but the update slot holds {slot}; \
+                 it is {} bytes over",
+                len - u64::from(*slot)
+            ),
+            Rejection::Gzip => f.write_str("gzip images are not supported; send the plain .bin"),
+            Rejection::NotAnImage { first } => write!(
+                f,
+                "not an ESP app image: starts with 0x{first:02X}, not 0x{IMAGE_MAGIC:02X}"
+            ),
+        }
+    }
+}
+
+/// Checks the announced length against the update slot's size.
+pub fn check_size(len: Option<u64>, slot_size: u32) -> Result<u64, Rejection> {
+    match len {
+        None => Err(Rejection::NoLength),
+        Some(0) => Err(Rejection::Empty),
+        Some(len) if len > u64::from(slot_size) => Err(Rejection::TooLarge {
+            len,
+            slot: slot_size,
+        }),
+        Some(len) => Ok(len),
+    }
+}
+
+/// Checks the first bytes of the body, before the slot is erased.
+pub fn check_start(head: &[u8]) -> Result<(), Rejection> {
+    match head {
+        [] => Err(Rejection::Empty),
+        _ if head.starts_with(&GZIP_MAGIC) => Err(Rejection::Gzip),
+        [IMAGE_MAGIC, ..] => Ok(()),
+        [first, ..] => Err(Rejection::NotAnImage { first: *first }),
+    }
+}
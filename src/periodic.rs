@@ -0,0 +1,147 @@
+//! One timer for the firmware's periodic housekeeping. Jobs register a name,
+//! a period and a callback; each is due on multiples of its period counted
+//! from a common epoch, so a 5 s and a 10 s job fire in the same wakeup
+//! every 10 s. The main thread then wakes once per distinct boundary rather
+//! than once per job.
+//!
+//! Callbacks run one after another on the main `LocalSet` and may
+//! `spawn_local` anything slow. One that takes longer than [`SLOT`] holds up
+//! the UI and everything else due at that boundary, and is logged. A job
+//! that falls behind skips the boundaries it missed instead of firing
+//! several times in a row.
+//!
+//! [`register`] hands back a [`JobHandle`]; [`cancel`] stops the job before
+//! its next run and drops its callback, for services that can be turned
+//! off at runtime.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+mod scheduler;
+
+use scheduler::Scheduler;
+pub use scheduler::{JobHandle, JobStats, SLOT};
+
+/// Longest sleep with nothing registered.
+const IDLE: Duration = Duration::from_secs(60);
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+static CHANGED: Notify = Notify::const_new();
+static WAKEUPS: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub jobs: Vec<JobStats>,
+    /// Timer wakeups since the scheduler started.
+    pub wakeups: u32,
+    pub running_for: Duration,
+}
+
+/// Runs `callback` every `period`, aligned with the other jobs. Callable
+/// from any thread; the callback itself always runs on the main `LocalSet`.
+pub fn register(
+    name: &'static str,
+    period: Duration,
+    callback: impl FnMut() + Send + 'static,
+) -> JobHandle {
+    let Ok(mut scheduler) = SCHEDULER.lock() else {
+        return JobHandle::NONE;
+    };
+    let handle = scheduler.register(name, period, Box::new(callback), Instant::now());
+    drop(scheduler);
+    CHANGED.notify_one();
+    handle
+}
+
+/// Stops a job. A run already under way finishes but there is no further
+/// one; the callback is dropped on the main `LocalSet` by the next wakeup.
+/// Callable from any thread, including from the job's own callback.
+pub fn cancel(handle: JobHandle) {
+    let cancelled = SCHEDULER
+        .lock()
+        .is_ok_and(|mut scheduler| scheduler.cancel(handle));
+    if cancelled {
+        CHANGED.notify_one();
+    }
+}
+
+/// Spawn once on the main `LocalSet`.
+pub async fn run() {
+    loop {
+        let next = SCHEDULER
+            .lock()
+            .ok()
+            .and_then(|scheduler| scheduler.next_due());
+        let wake = next.unwrap_or_else(|| Instant::now() + IDLE);
+        tokio::select! {
+            _ = tokio::time::sleep_until(wake.into()) => {}
+            _ = CHANGED.notified() => continue,
+        }
+        WAKEUPS.fetch_add(1, Ordering::Relaxed);
+        scheduler::run_due(&SCHEDULER, Instant::now());
+    }
+}
+
+pub fn snapshot() -> Snapshot {
+    let (jobs, epoch) = SCHEDULER
+        .lock()
+        .map(|scheduler| {
+            let jobs = scheduler.jobs().cloned().collect();
+            (jobs, scheduler.epoch())
+        })
+        .unwrap_or_default();
+    Snapshot {
+        jobs,
+        wakeups: WAKEUPS.load(Ordering::Relaxed),
+        running_for: epoch.map(|at| at.elapsed()).unwrap_or_default(),
+    }
+}
+
+/// Wakeups per minute since start, against the number of distinct periods
+/// (the floor) and of jobs (one timer each, as before).
+pub fn summary() -> String {
+    let snapshot = snapshot();
+    let minutes = snapshot.running_for.as_secs_f32() / 60.0;
+    let mut periods: Vec<Duration> = snapshot.jobs.iter().map(|job| job.period).collect();
+    periods.sort();
+    periods.dedup();
+    let per_minute = if minutes > 0.0 {
+        snapshot.wakeups as f32 / minutes
+    } else {
+        0.0
+    };
+    format!(
+        "{} jobs, {} periods, {per_minute:.1} wakeups/min",
+        snapshot.jobs.len(),
+        periods.len()
+    )
+}
+
+pub fn register_commands() {
+    crate::console::register("periodic", "periodic jobs and their timings", |args| {
+        if !args.is_empty() {
+            anyhow::bail!("usage: periodic");
+        }
+        let mut lines = vec![summary()];
+        for job in snapshot().jobs {
+            lines.push(format!(
+                "{}: every {} ms, {} runs, last {} us, max {} us, {} overruns, {} skipped",
+                job.name,
+                job.period.as_millis(),
+                job.runs,
+                job.last.as_micros(),
+                job.max.as_micros(),
+                job.overruns,
+                job.skipped
+            ));
+        }
+        Ok(lines.join("\n"))
+    });
+}
@@ -0,0 +1,207 @@
+//! The job table behind `periodic`. The clock is passed in, so host tests
+//! step it instead of sleeping.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+/// Time one callback may take before it counts as an overrun.
+pub const SLOT: Duration = Duration::from_millis(20);
+
+pub type Callback = Box<dyn FnMut() + Send>;
+
+pub struct Scheduler {
+    epoch: Option<Instant>,
+    jobs: Vec<Job>,
+    next_id: u32,
+}
+
+/// Names one registration for [`Scheduler::cancel`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JobHandle(u32);
+
+impl JobHandle {
+    /// Matches no job; for a registration that could not be recorded.
+    pub const NONE: Self = Self(0);
+}
+
+struct Job {
+    id: u32,
+    cancelled: bool,
+    stats: JobStats,
+    next: Instant,
+    /// Taken out while the callback runs, so it can register jobs itself.
+    callback: Option<Callback>,
+}
+
+#[derive(Clone, Debug)]
+pub struct JobStats {
+    pub name: &'static str,
+    pub period: Duration,
+    pub runs: u32,
+    pub last: Duration,
+    pub max: Duration,
+    /// Runs longer than [`SLOT`].
+    pub overruns: u32,
+    /// Boundaries passed without a run because the job was late.
+    pub skipped: u32,
+}
+
+impl Scheduler {
+    pub const fn new() -> Self {
+        Self {
+            epoch: None,
+            jobs: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// When the first job was registered.
+    pub fn epoch(&self) -> Option<Instant> {
+        self.epoch
+    }
+
+    /// First multiple of `period` past `now`, counted from the epoch.
+    fn boundary_after(&mut self, period: Duration, now: Instant) -> Instant {
+        let epoch = *self.epoch.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(epoch).as_millis() as u64;
+        let period_ms = period.as_millis().max(1) as u64;
+        epoch + Duration::from_millis((elapsed / period_ms + 1) * period_ms)
+    }
+
+    pub fn next_due(&self) -> Option<Instant> {
+        self.jobs
+            .iter()
+            .filter(|job| !job.cancelled)
+            .map(|job| job.next)
+            .min()
+    }
+
+    /// Adds a job first due on the boundary of `period` after `now`.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        period: Duration,
+        callback: Callback,
+        now: Instant,
+    ) -> JobHandle {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let next = self.boundary_after(period, now);
+        self.jobs.push(Job {
+            id,
+            cancelled: false,
+            stats: JobStats {
+                name,
+                period,
+                runs: 0,
+                last: Duration::ZERO,
+                max: Duration::ZERO,
+                overruns: 0,
+                skipped: 0,
+            },
+            next,
+            callback: Some(callback),
+        });
+        JobHandle(id)
+    }
+
+    /// Marks the job so it never runs again; the next [`run_due`] drops it.
+    /// `false` when `handle` names no live job.
+    pub fn cancel(&mut self, handle: JobHandle) -> bool {
+        match self
+            .jobs
+            .iter_mut()
+            .find(|job| job.id == handle.0 && !job.cancelled)
+        {
+            Some(job) => {
+                job.cancelled = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stats of the jobs not cancelled, in registration order.
+    pub fn jobs(&self) -> impl Iterator<Item = &JobStats> {
+        self.jobs
+            .iter()
+            .filter(|job| !job.cancelled)
+            .map(|job| &job.stats)
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs the jobs of `scheduler` due at `now`, one after another. The lock
+/// is released around each callback, so a callback may register or cancel
+/// jobs; one it cancels does not run, even when due in this same batch.
+pub fn run_due(scheduler: &Mutex<Scheduler>, now: Instant) {
+    let started = Instant::now();
+    // Nothing runs between batches, so cancelled jobs can go here without
+    // shifting the indices below; the lock is released before the drop.
+    let (due, cancelled): (Vec<usize>, Vec<Job>) = match scheduler.lock() {
+        Ok(mut scheduler) => {
+            let (cancelled, kept) = std::mem::take(&mut scheduler.jobs)
+                .into_iter()
+                .partition(|job| job.cancelled);
+            scheduler.jobs = kept;
+            let due = (0..scheduler.jobs.len())
+                .filter(|&index| scheduler.jobs[index].next <= now)
+                .collect();
+            (due, cancelled)
+        }
+        Err(_) => return,
+    };
+    drop(cancelled);
+    for index in due {
+        let Some(mut callback) = scheduler.lock().ok().and_then(|mut scheduler| {
+            let job = &mut scheduler.jobs[index];
+            if job.cancelled {
+                None
+            } else {
+                job.callback.take()
+            }
+        }) else {
+            continue;
+        };
+        let began = Instant::now();
+        callback();
+        let took = began.elapsed();
+        let Ok(mut scheduler) = scheduler.lock() else {
+            return;
+        };
+        let period = scheduler.jobs[index].stats.period;
+        let next = scheduler.boundary_after(period, now + started.elapsed());
+        let job = &mut scheduler.jobs[index];
+        if job.cancelled {
+            drop(scheduler);
+            drop(callback);
+            continue;
+        }
+        job.callback = Some(callback);
+        let missed = next.saturating_duration_since(job.next).as_millis() as u64
+            / period.as_millis().max(1) as u64;
+        job.stats.skipped += missed.saturating_sub(1) as u32;
+        job.next = next;
+        job.stats.runs += 1;
+        job.stats.last = took;
+        job.stats.max = job.stats.max.max(took);
+        if took > SLOT {
+            job.stats.overruns += 1;
+            warn!(
+                "Periodic job {} took {} ms, over its {} ms slot",
+                job.stats.name,
+                took.as_millis(),
+                SLOT.as_millis()
+            );
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod battery;
+pub mod download_mode;
@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const UNKNOWN: u8 = u8::MAX;
+
+static LEVEL: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Last reported charge in percent, `None` on boards without battery sensing.
+pub fn level() -> Option<u8> {
+    match LEVEL.load(Ordering::Relaxed) {
+        UNKNOWN => None,
+        level => Some(level),
+    }
+}
+
+/// Called by whatever samples the battery on this board.
+pub fn report(percent: u8) {
+    LEVEL.store(percent.min(100), Ordering::Relaxed);
+}
+
+pub fn register_commands() {
+    crate::console::register("battery", "<percent> report a battery level", |args| {
+        let percent: u8 = args
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("usage: battery <percent>"))?
+            .parse()?;
+        report(percent);
+        Ok(format!("battery level set to {}%", percent.min(100)))
+    });
+}
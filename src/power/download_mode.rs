@@ -0,0 +1,154 @@
+//! Reboot into the ROM's USB download mode, for units whose BOOT and EN
+//! buttons are sealed inside an enclosure. The force-download flag lives in
+//! an RTC register, so it survives `esp_restart` but not a power cycle: the
+//! next plug-in after flashing boots normally.
+//!
+//! The settings page and HTTP both queue a request to one worker on the
+//! main thread, which winds the device down before rebooting.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::{info, warn};
+use tokio::{sync::mpsc, time};
+
+use crate::{activity, miwear, nvs, secrets, settings::SettingKey};
+
+/// Shared secret for `POST /maintenance/download-mode`; empty turns the
+/// route off. Set from the serial console, which already implies a cable.
+/// Kept in [`secrets`].
+pub const HTTP_TOKEN: SettingKey<String> = SettingKey::new("maint_token", "");
+
+/// `RTC_CNTL_OPTION1_REG` and `RTC_CNTL_FORCE_DOWNLOAD_BOOT` from the
+/// ESP32-S3 `rtc_cntl_reg.h`; bindgen does not carry the `BIT()` macro.
+const RTC_CNTL_OPTION1_REG: usize = 0x6000_812C;
+const RTC_CNTL_FORCE_DOWNLOAD_BOOT: u32 = 1 << 0;
+/// How long an OTA update or watch transfer gets to wind down.
+const ACTIVITY_WAIT: Duration = Duration::from_secs(3);
+/// Enough for the render loop to put the flashing frame on the panel.
+const FRAME_WAIT: Duration = Duration::from_millis(500);
+const POLL: Duration = Duration::from_millis(50);
+
+static REQUESTS: OnceLock<mpsc::UnboundedSender<&'static str>> = OnceLock::new();
+static ENTERING: AtomicBool = AtomicBool::new(false);
+static FRAME_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Serves the first request and never returns from it; spawn once on the
+/// main `LocalSet`.
+pub async fn run() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if REQUESTS.set(tx).is_err() {
+        warn!("Download-mode worker already running");
+        return;
+    }
+    if let Some(source) = rx.recv().await {
+        enter(source).await;
+    }
+}
+
+/// Queues the reboot. Fails if one is already under way.
+pub fn request(source: &'static str) -> Result<()> {
+    if entering() {
+        bail!("already rebooting into download mode");
+    }
+    REQUESTS
+        .get()
+        .ok_or_else(|| anyhow!("download-mode worker not running"))?
+        .send(source)
+        .map_err(|_| anyhow!("download-mode worker gone"))
+}
+
+/// True from the moment a request is served; the renderer swaps in the
+/// static flashing frame.
+pub fn entering() -> bool {
+    ENTERING.load(Ordering::Acquire)
+}
+
+/// Called by the renderer once the flashing frame is on the panel.
+pub fn mark_frame_shown() {
+    FRAME_SHOWN.store(true, Ordering::Release);
+}
+
+pub fn http_enabled() -> bool {
+    !secrets::get(&HTTP_TOKEN).is_empty()
+}
+
+/// Checks an HTTP bearer token against [`HTTP_TOKEN`] without stopping at
+/// the first differing byte.
+pub fn token_matches(presented: &str) -> bool {
+    let expected = secrets::get(&HTTP_TOKEN);
+    if expected.is_empty() || expected.len() != presented.len() {
+        return false;
+    }
+    expected
+        .bytes()
+        .zip(presented.bytes())
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+async fn enter(source: &'static str) {
+    ENTERING.store(true, Ordering::Release);
+    crate::journal!("Rebooting into USB download mode (from {source})");
+
+    miwear::demo::cancel_install();
+    if !wait_until(ACTIVITY_WAIT, || activity::busy().is_none()).await {
+        if let Some(busy) = activity::busy() {
+            warn!("Rebooting with {} still running", busy.label());
+        }
+    }
+    if let Err(err) = nvs::flush().await {
+        warn!("NVS flush before download mode failed: {err:#}");
+    }
+    #[cfg(feature = "storage")]
+    crate::statlogger::flash_journal::flush();
+    if !wait_until(FRAME_WAIT, || FRAME_SHOWN.load(Ordering::Acquire)).await {
+        warn!("Flashing frame not confirmed, rebooting anyway");
+    }
+
+    info!("Entering USB download mode");
+    unsafe {
+        let option1 = RTC_CNTL_OPTION1_REG as *mut u32;
+        option1.write_volatile(option1.read_volatile() | RTC_CNTL_FORCE_DOWNLOAD_BOOT);
+        esp_idf_svc::sys::esp_restart();
+    }
+}
+
+async fn wait_until(limit: Duration, done: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while !done() {
+        if start.elapsed() >= limit {
+            return false;
+        }
+        time::sleep(POLL).await;
+    }
+    true
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "downloadmode",
+        "now | token <secret> | token clear: reboot into USB download mode",
+        |args| match args {
+            ["now"] => {
+                request("console")?;
+                Ok("rebooting into download mode".to_string())
+            }
+            ["token", "clear"] => {
+                secrets::set(&HTTP_TOKEN, &String::new())?;
+                Ok("HTTP download-mode route disabled".to_string())
+            }
+            ["token", secret] => {
+                secrets::set(&HTTP_TOKEN, &secret.to_string())?;
+                Ok("HTTP download-mode token set".to_string())
+            }
+            _ => bail!("usage: downloadmode now | token <secret> | token clear"),
+        },
+    );
+}
@@ -0,0 +1,543 @@
+//! Reminders created on the module itself ("stand up", "tea is ready"),
+//! fired into the ANCS pipeline like any relayed notification. Up to
+//! [`MAX_REMINDERS`] are kept in NVS, each either once at a wall-clock time
+//! or every so many minutes.
+//!
+//! A time of day needs the clock; an interval runs on uptime until it is
+//! set, and on the clock after that so it survives a reboot. Every firing
+//! is also shown on the module's own screen. While no watch is subscribed
+//! it waits in `ancs::outbox` for [`OUTBOX_TTL`], or is shown only here if
+//! the outbox is full; the reminder's last result says which.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use log::{info, warn};
+
+use crate::{
+    metrics::Date,
+    miwear::ancs::{
+        self, outbox,
+        store::{NewNotification, CATEGORY_SCHEDULE},
+        Delivery,
+    },
+    periodic, settings, timesync,
+};
+
+pub const MAX_REMINDERS: usize = 16;
+pub const APP_IDENTIFIER: &str = "com.astrobox.reminder";
+const APP_DISPLAY_NAME: &str = "Reminders";
+pub const SNOOZE: Duration = Duration::from_secs(5 * 60);
+/// A reminder later than this is noise rather than a reminder.
+const OUTBOX_TTL: Duration = Duration::from_secs(60 * 60);
+pub const MAX_LABEL_LEN: usize = 48;
+/// One day; longer intervals are what a time of day is for.
+pub const MAX_INTERVAL_MINUTES: u32 = 24 * 60;
+const CHECK: Duration = Duration::from_secs(1);
+/// Firings waiting for the UI; older ones are dropped.
+const FIRED_QUEUE: usize = 4;
+const NVS_NAMESPACE: &str = "reminders";
+const NVS_KEY: &str = "list";
+const BUFFER_LEN: usize = 2048;
+
+static REMINDERS: Mutex<Vec<Reminder>> = Mutex::new(Vec::new());
+static FIRED: Mutex<VecDeque<Fired>> = Mutex::new(VecDeque::new());
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// Unix seconds.
+    Once {
+        at: i64,
+    },
+    Every {
+        minutes: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Published to at least one subscribed watch.
+    Watch,
+    /// No watch was listening; only the module's screen showed it.
+    LocalOnly,
+    /// No watch was listening; waiting in the outbox for one.
+    Queued,
+}
+
+impl Outcome {
+    pub fn code(self) -> &'static str {
+        match self {
+            Outcome::Watch => "watch",
+            Outcome::LocalOnly => "local",
+            Outcome::Queued => "queued",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "watch" => Some(Outcome::Watch),
+            "local" => Some(Outcome::LocalOnly),
+            "queued" => Some(Outcome::Queued),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LastFire {
+    /// Unix seconds; `None` when the clock was not set.
+    pub at: Option<i64>,
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Debug)]
+pub struct Reminder {
+    pub id: u32,
+    pub label: String,
+    pub schedule: Schedule,
+    pub enabled: bool,
+    pub last: Option<LastFire>,
+    /// For an interval on the clock, the next firing in Unix seconds.
+    next: Option<i64>,
+    /// For an interval while the clock is unset.
+    due: Option<Instant>,
+    snoozed: Option<Instant>,
+}
+
+/// A firing for the UI to show.
+#[derive(Clone, Debug)]
+pub struct Fired {
+    pub id: u32,
+    pub label: String,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Schedule::Once { at } => {
+                let local = timesync::zone().local(at);
+                write!(f, "at {:02}:{:02}", local.hour, local.minute)?;
+                let today = timesync::now_local().map(|now| now.date);
+                if today != Some(local.date) {
+                    write!(f, " {}", local.date)?;
+                }
+                Ok(())
+            }
+            Schedule::Every { minutes } if minutes % 60 == 0 => {
+                write!(f, "every {} h", minutes / 60)
+            }
+            Schedule::Every { minutes } => write!(f, "every {minutes} min"),
+        }
+    }
+}
+
+impl fmt::Display for Reminder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} \"{}\" {}",
+            self.id,
+            self.schedule,
+            self.label,
+            if self.enabled { "on" } else { "off" }
+        )?;
+        if let Some(last) = self.last {
+            write!(f, ", last {}", last.outcome.code())?;
+        }
+        Ok(())
+    }
+}
+
+impl Reminder {
+    /// Whether it fires at `now`, moving an interval onto the clock once
+    /// the clock is set.
+    fn due(&mut self, now: Option<i64>, instant: Instant) -> bool {
+        match (self.schedule, now) {
+            (Schedule::Once { at }, Some(now)) => now >= at,
+            (Schedule::Once { .. }, None) => false,
+            (Schedule::Every { minutes }, Some(now)) => {
+                let next = *self.next.get_or_insert_with(|| {
+                    let left = self.due.map_or(interval(minutes), |due| {
+                        due.saturating_duration_since(instant)
+                    });
+                    now + left.as_secs() as i64
+                });
+                now >= next
+            }
+            (Schedule::Every { minutes }, None) => {
+                let due = *self.due.get_or_insert_with(|| instant + interval(minutes));
+                instant >= due
+            }
+        }
+    }
+
+    /// Schedules the next firing after one at `now`.
+    fn rearm(&mut self, now: Option<i64>, instant: Instant) {
+        match self.schedule {
+            Schedule::Once { .. } => self.enabled = false,
+            Schedule::Every { minutes } => {
+                self.next = now.map(|now| now + i64::from(minutes) * 60);
+                self.due = Some(instant + interval(minutes));
+            }
+        }
+    }
+}
+
+fn interval(minutes: u32) -> Duration {
+    Duration::from_secs(u64::from(minutes) * 60)
+}
+
+/// Loads the saved reminders and starts the scheduler.
+pub fn start() {
+    let loaded: Vec<Reminder> = match open_store() {
+        Some(store) => {
+            let mut buf = vec![0u8; BUFFER_LEN];
+            match store.get_str(NVS_KEY, &mut buf) {
+                Ok(Some(raw)) => raw.lines().filter_map(decode).collect(),
+                Ok(None) => Vec::new(),
+                Err(err) => {
+                    warn!("Reminders unreadable: {err:?}");
+                    Vec::new()
+                }
+            }
+        }
+        None => Vec::new(),
+    };
+    info!("Reminders loaded: {}", loaded.len());
+    if let Ok(mut reminders) = REMINDERS.lock() {
+        *reminders = loaded;
+    }
+    periodic::register("reminders", CHECK, check);
+}
+
+fn check() {
+    let now = timesync::unix_now();
+    let instant = Instant::now();
+    let mut fired = Vec::new();
+    if let Ok(mut reminders) = REMINDERS.lock() {
+        for reminder in reminders.iter_mut() {
+            if reminder.snoozed.is_some_and(|until| instant >= until) {
+                reminder.snoozed = None;
+                fired.push(reminder.id);
+                continue;
+            }
+            if reminder.enabled && reminder.due(now, instant) {
+                reminder.rearm(now, instant);
+                fired.push(reminder.id);
+            }
+        }
+    }
+    for id in fired {
+        fire(id, now);
+    }
+}
+
+fn fire(id: u32, now: Option<i64>) {
+    let Some(label) = with_reminder(id, |reminder| reminder.label.clone()) else {
+        return;
+    };
+    let new = NewNotification {
+        app_id: APP_IDENTIFIER.to_string(),
+        app_name: Some(APP_DISPLAY_NAME.to_string()),
+        title: label.clone(),
+        category: CATEGORY_SCHEDULE,
+        ..Default::default()
+    };
+    let queued = !ancs::encrypted_subscriber()
+        && match outbox::queue(new.clone(), OUTBOX_TTL) {
+            Ok(_) => true,
+            Err(err) => {
+                warn!("Reminder #{id} not queued: {err:#}");
+                false
+            }
+        };
+    let outcome = if queued {
+        Outcome::Queued
+    } else {
+        match ancs::publish_notification(new).delivery {
+            Delivery::Delivered => Outcome::Watch,
+            Delivery::NoSubscriber => Outcome::LocalOnly,
+        }
+    };
+    info!("Reminder #{id} \"{label}\" fired ({})", outcome.code());
+    with_reminder(id, |reminder| {
+        reminder.last = Some(LastFire { at: now, outcome });
+    });
+    changed();
+    if let Ok(mut queue) = FIRED.lock() {
+        if queue.len() == FIRED_QUEUE {
+            queue.pop_front();
+        }
+        queue.push_back(Fired { id, label, outcome });
+    }
+}
+
+/// The oldest firing not yet shown.
+pub fn take_fired() -> Option<Fired> {
+    FIRED.lock().ok()?.pop_front()
+}
+
+pub fn list() -> Vec<Reminder> {
+    REMINDERS
+        .lock()
+        .map(|reminders| reminders.clone())
+        .unwrap_or_default()
+}
+
+/// Bumped on every change, firings included.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// The next `hour:minute` local time, in Unix seconds.
+pub fn next_at(hour: u8, minute: u8) -> Result<i64> {
+    if hour > 23 || minute > 59 {
+        bail!("{hour}:{minute:02} is not a time of day");
+    }
+    let now = timesync::unix_now().ok_or_else(|| anyhow!("a time of day needs the clock set"))?;
+    let zone = timesync::zone();
+    let local = zone.local(now);
+    let at = |date: Date| {
+        let wall = date.days() * 86_400 + i64::from(hour) * 3_600 + i64::from(minute) * 60;
+        // The offset in force at that time, which differs across DST.
+        let guess = wall - i64::from(local.offset) * 60;
+        wall - i64::from(zone.local(guess).offset) * 60
+    };
+    let today = at(local.date);
+    Ok(if today > now {
+        today
+    } else {
+        at(Date::from_days(local.date.days() + 1))
+    })
+}
+
+fn check_new(label: &str, schedule: Schedule) -> Result<()> {
+    if label.trim().is_empty() {
+        bail!("label is empty");
+    }
+    if label.chars().count() > MAX_LABEL_LEN {
+        bail!("label is longer than {MAX_LABEL_LEN} characters");
+    }
+    if let Schedule::Every { minutes } = schedule {
+        if !(1..=MAX_INTERVAL_MINUTES).contains(&minutes) {
+            bail!("interval is 1 to {MAX_INTERVAL_MINUTES} minutes");
+        }
+    }
+    Ok(())
+}
+
+pub fn add(label: &str, schedule: Schedule) -> Result<Reminder> {
+    check_new(label, schedule)?;
+    let added = {
+        let mut reminders = REMINDERS.lock().map_err(|_| anyhow!("reminders locked"))?;
+        if reminders.len() >= MAX_REMINDERS {
+            bail!("at most {MAX_REMINDERS} reminders");
+        }
+        let id = reminders.iter().map(|r| r.id).max().unwrap_or(0) + 1;
+        let reminder = Reminder {
+            id,
+            label: label.trim().to_string(),
+            schedule,
+            enabled: true,
+            last: None,
+            next: None,
+            due: None,
+            snoozed: None,
+        };
+        reminders.push(reminder.clone());
+        reminder
+    };
+    changed();
+    Ok(added)
+}
+
+/// Replaces the label and schedule and turns it back on.
+pub fn update(id: u32, label: &str, schedule: Schedule) -> Result<()> {
+    check_new(label, schedule)?;
+    with_reminder(id, |reminder| {
+        reminder.label = label.trim().to_string();
+        reminder.schedule = schedule;
+        reminder.enabled = true;
+        reminder.next = None;
+        reminder.due = None;
+    })
+    .ok_or_else(|| anyhow!("no reminder #{id}"))?;
+    changed();
+    Ok(())
+}
+
+pub fn delete(id: u32) -> Result<()> {
+    {
+        let mut reminders = REMINDERS.lock().map_err(|_| anyhow!("reminders locked"))?;
+        let before = reminders.len();
+        reminders.retain(|reminder| reminder.id != id);
+        if reminders.len() == before {
+            bail!("no reminder #{id}");
+        }
+    }
+    changed();
+    Ok(())
+}
+
+pub fn set_enabled(id: u32, enabled: bool) -> Result<()> {
+    with_reminder(id, |reminder| {
+        reminder.enabled = enabled;
+        // An interval restarts from now when turned back on.
+        reminder.next = None;
+        reminder.due = None;
+        if !enabled {
+            reminder.snoozed = None;
+        }
+    })
+    .ok_or_else(|| anyhow!("no reminder #{id}"))?;
+    changed();
+    Ok(())
+}
+
+/// Fires the reminder again [`SNOOZE`] from now, once.
+pub fn snooze(id: u32) -> Result<()> {
+    with_reminder(id, |reminder| {
+        reminder.snoozed = Some(Instant::now() + SNOOZE);
+    })
+    .ok_or_else(|| anyhow!("no reminder #{id}"))?;
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+fn with_reminder<R>(id: u32, f: impl FnOnce(&mut Reminder) -> R) -> Option<R> {
+    REMINDERS
+        .lock()
+        .ok()?
+        .iter_mut()
+        .find(|reminder| reminder.id == id)
+        .map(f)
+}
+
+/// Saves the list and bumps the generation.
+fn changed() {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    let Ok(reminders) = REMINDERS.lock() else {
+        return;
+    };
+    let encoded: String = reminders.iter().map(encode).collect();
+    drop(reminders);
+    crate::nvs::write(NVS_NAMESPACE, NVS_KEY, encoded);
+}
+
+/// `id enabled kind value next last_outcome last_at label`, tab-separated.
+fn encode(reminder: &Reminder) -> String {
+    let (kind, value) = match reminder.schedule {
+        Schedule::Once { at } => ("at", at),
+        Schedule::Every { minutes } => ("every", i64::from(minutes)),
+    };
+    let optional = |value: Option<i64>| value.map_or_else(String::new, |value| value.to_string());
+    format!(
+        "{}\t{}\t{kind}\t{value}\t{}\t{}\t{}\t{}\n",
+        reminder.id,
+        u8::from(reminder.enabled),
+        optional(reminder.next),
+        reminder.last.map_or("", |last| last.outcome.code()),
+        optional(reminder.last.and_then(|last| last.at)),
+        reminder.label.replace(['\t', '\n'], " ")
+    )
+}
+
+fn decode(line: &str) -> Option<Reminder> {
+    let mut fields = line.splitn(8, '\t');
+    let id = fields.next()?.parse().ok()?;
+    let enabled = fields.next()? == "1";
+    let schedule = match (fields.next()?, fields.next()?.parse::<i64>().ok()?) {
+        ("at", at) => Schedule::Once { at },
+        ("every", minutes) => Schedule::Every {
+            minutes: u32::try_from(minutes).ok()?,
+        },
+        _ => return None,
+    };
+    let next = fields.next()?.parse().ok();
+    let outcome = Outcome::from_code(fields.next()?);
+    let at = fields.next()?.parse().ok();
+    let label = fields.next()?.to_string();
+    Some(Reminder {
+        id,
+        label,
+        schedule,
+        enabled,
+        last: outcome.map(|outcome| LastFire { at, outcome }),
+        next,
+        due: None,
+        snoozed: None,
+    })
+}
+
+fn open_store() -> Option<EspNvs<NvsDefault>> {
+    match settings::open_namespace(NVS_NAMESPACE) {
+        Ok(store) => Some(store),
+        Err(err) => {
+            warn!("Reminder store unavailable: {err:?}");
+            None
+        }
+    }
+}
+
+fn parse_schedule(kind: &str, value: &str) -> Result<Schedule> {
+    match kind {
+        "at" => {
+            let (hour, minute) = value
+                .split_once(':')
+                .and_then(|(h, m)| Some((h.parse().ok()?, m.parse().ok()?)))
+                .ok_or_else(|| anyhow!("time is HH:MM"))?;
+            Ok(Schedule::Once {
+                at: next_at(hour, minute)?,
+            })
+        }
+        "every" => Ok(Schedule::Every {
+            minutes: value
+                .parse()
+                .map_err(|_| anyhow!("interval is in minutes"))?,
+        }),
+        _ => bail!("schedule is `at <HH:MM>` or `every <minutes>`"),
+    }
+}
+
+pub fn register_commands() {
+    const USAGE: &str =
+        "usage: reminder [add at <HH:MM>|every <min> <label> | del <id> | on|off|snooze <id>]";
+    crate::console::register(
+        "reminder",
+        "local reminders: reminder [add at <HH:MM>|every <min> <label> | del|on|off|snooze <id>]",
+        |args| {
+            let id = |arg: &str| arg.parse::<u32>().map_err(|_| anyhow!(USAGE));
+            match args {
+                [] => {
+                    let lines: Vec<String> = list().iter().map(Reminder::to_string).collect();
+                    return Ok(if lines.is_empty() {
+                        "no reminders".to_string()
+                    } else {
+                        lines.join("\n")
+                    });
+                }
+                ["add", kind, value, label @ ..] if !label.is_empty() => {
+                    let added = add(&label.join(" "), parse_schedule(kind, value)?)?;
+                    return Ok(format!("added {added}"));
+                }
+                ["del", arg] => delete(id(arg)?)?,
+                ["on", arg] => set_enabled(id(arg)?, true)?,
+                ["off", arg] => set_enabled(id(arg)?, false)?,
+                ["snooze", arg] => snooze(id(arg)?)?,
+                _ => bail!(USAGE),
+            }
+            Ok("ok".to_string())
+        },
+    );
+}
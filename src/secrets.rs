@@ -0,0 +1,331 @@
+//! Secrets kept out of plain NVS: the Wi-Fi password, the maintenance token
+//! and the watch auth keys. Images built with NVS encryption
+//! (`CONFIG_NVS_ENCRYPTION`) store them as-is in the `secrets` namespace,
+//! since the partition itself is encrypted. Otherwise each value is sealed
+//! with ChaCha20-Poly1305 under a device key: the HMAC peripheral's output
+//! when eFuse key block 5 is provisioned for it, else SHA-256 over the
+//! factory MAC. The MAC fallback only keeps secrets from reading as text in
+//! a flash dump; the MAC itself is not secret.
+//!
+//! Values stored by older firmware through `settings` move on first read:
+//! sealed into `secrets`, then the plaintext key is erased. A value that no
+//! longer opens (key changed, flash corrupted) reads as unset, with an
+//! error, a boot note and, once the UI is up, a toast. It never stops boot.
+
+use std::{
+    fmt::Write as _,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::Context;
+use chacha20poly1305::{
+    aead::{AeadInPlace, KeyInit},
+    ChaCha20Poly1305, Key, Nonce, Tag,
+};
+use esp_idf_svc::sys::{
+    esp_efuse_mac_get_default, esp_fill_random, esp_hmac_calculate, hmac_key_id_t_HMAC_KEY5, ESP_OK,
+};
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    boot,
+    settings::{self, SettingKey, SettingValue},
+};
+
+const NAMESPACE: &str = "secrets";
+/// Marks a sealed value: nonce then ciphertext and tag, all hex.
+const PREFIX: &str = "v1:";
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// Longest plaintext [`get`] and [`set`] handle, matching `settings`.
+const MAX_VALUE_LEN: usize = 256;
+/// Input to the HMAC peripheral and the MAC hash; changing it changes the key.
+const KEY_DOMAIN: &[u8] = b"astrobox-secrets-v1";
+
+static DEVICE_KEY: OnceLock<DeviceKey> = OnceLock::new();
+/// Decrypted values, so each secret is read and opened once per boot.
+static CACHE: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+/// Secrets that failed to open this boot.
+static UNREADABLE: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+struct DeviceKey {
+    key: [u8; 32],
+    source: &'static str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The NVS partition is encrypted; values are stored as-is.
+    EncryptedNvs,
+    /// Values are sealed before they reach NVS.
+    Sealed,
+}
+
+pub fn backend() -> Backend {
+    if cfg!(esp_idf_nvs_encryption) {
+        Backend::EncryptedNvs
+    } else {
+        Backend::Sealed
+    }
+}
+
+/// Stored length of a sealed `plain_len`-byte value.
+pub const fn sealed_len(plain_len: usize) -> usize {
+    PREFIX.len() + 2 * (NONCE_LEN + plain_len + TAG_LEN)
+}
+
+/// Reads `key` from the secrets store, moving a plaintext value left in
+/// `settings` by older firmware on first use. Unset or unreadable values
+/// give the key's default.
+pub fn get<T: SettingValue>(key: &SettingKey<T>) -> T {
+    let raw = cached(key.name).or_else(|| load(key.name));
+    match raw.as_deref().map(T::decode) {
+        Some(Some(value)) => value,
+        Some(None) => {
+            warn!(
+                "secrets: stored value for {} is invalid, using default",
+                key.name
+            );
+            key.default_value()
+        }
+        None => key.default_value(),
+    }
+}
+
+/// Updates the cached value immediately; the NVS write is queued.
+pub fn set<T: SettingValue>(key: &SettingKey<T>, value: &T) -> anyhow::Result<()> {
+    let encoded = value.encode();
+    if encoded.len() >= MAX_VALUE_LEN {
+        anyhow::bail!("value for {} is too long", key.name);
+    }
+    crate::nvs::write(NAMESPACE, key.name, seal(key.name, &encoded));
+    remember(key.name, encoded);
+    if let Ok(mut unreadable) = UNREADABLE.lock() {
+        unreadable.retain(|name| *name != key.name);
+    }
+    Ok(())
+}
+
+fn cached(name: &'static str) -> Option<String> {
+    CACHE.lock().ok().and_then(|cache| {
+        cache
+            .iter()
+            .find(|(cached, _)| *cached == name)
+            .map(|(_, value)| value.clone())
+    })
+}
+
+fn remember(name: &'static str, value: String) {
+    if let Ok(mut cache) = CACHE.lock() {
+        match cache.iter_mut().find(|(cached, _)| *cached == name) {
+            Some((_, slot)) => *slot = value,
+            None => cache.push((name, value)),
+        }
+    }
+}
+
+fn load(name: &'static str) -> Option<String> {
+    let stored = match settings::open_namespace(NAMESPACE) {
+        Ok(store) => {
+            let mut buf = vec![0u8; sealed_len(MAX_VALUE_LEN)];
+            match store.get_str(name, &mut buf) {
+                Ok(stored) => stored.map(str::to_string),
+                Err(err) => {
+                    warn!("secrets: failed to read {name}: {err:?}");
+                    None
+                }
+            }
+        }
+        Err(err) => {
+            warn!("secrets: store unavailable: {err:#}");
+            None
+        }
+    };
+    let value = match stored {
+        Some(stored) => {
+            let value = open(name, &stored)?;
+            if needs_sealing(&stored) {
+                crate::nvs::write(NAMESPACE, name, seal(name, &value));
+            }
+            value
+        }
+        None => {
+            // The plaintext goes only once the sealed copy is committed, so
+            // a failed write or a reset in between cannot lose the value.
+            let value = settings::stored(name)?;
+            let sealed = seal(name, &value);
+            let written = settings::open_namespace(NAMESPACE).and_then(|mut store| {
+                store
+                    .set_str(name, &sealed)
+                    .with_context(|| format!("write {NAMESPACE}/{name}"))
+            });
+            match written {
+                Ok(()) => {
+                    settings::forget_stored(name);
+                    crate::journal!("Moved {name} from settings into the secrets store");
+                }
+                Err(err) => warn!("secrets: {name} stays in settings for now: {err:#}"),
+            }
+            value
+        }
+    };
+    remember(name, value.clone());
+    Some(value)
+}
+
+/// Prepares `value` for storage. `label` is bound in as associated data, so
+/// a sealed value only opens under the label it was sealed with.
+pub fn seal(label: &str, value: &str) -> String {
+    if backend() == Backend::EncryptedNvs {
+        return value.to_string();
+    }
+    let mut nonce = [0u8; NONCE_LEN];
+    unsafe { esp_fill_random(nonce.as_mut_ptr().cast(), NONCE_LEN) };
+    let mut buf = value.as_bytes().to_vec();
+    let tag = match cipher().encrypt_in_place_detached(
+        Nonce::from_slice(&nonce),
+        label.as_bytes(),
+        &mut buf,
+    ) {
+        Ok(tag) => tag,
+        // Only fails past 256 GiB of plaintext.
+        Err(_) => unreachable!("ChaCha20-Poly1305 refused a short message"),
+    };
+    let mut sealed = String::with_capacity(sealed_len(buf.len()));
+    sealed.push_str(PREFIX);
+    for byte in nonce.iter().chain(&buf).chain(tag.iter()) {
+        let _ = write!(sealed, "{byte:02x}");
+    }
+    sealed
+}
+
+/// Reverses [`seal`]. A value stored before sealing passes through as-is;
+/// [`needs_sealing`] tells the caller to store it again. `None` when a
+/// sealed value will not open, after reporting it.
+pub fn open(label: &'static str, stored: &str) -> Option<String> {
+    let Some(hex) = stored.strip_prefix(PREFIX) else {
+        return Some(stored.to_string());
+    };
+    let opened = decode_hex(hex).and_then(|mut bytes| {
+        if bytes.len() < NONCE_LEN + TAG_LEN {
+            return None;
+        }
+        let tag = bytes.split_off(bytes.len() - TAG_LEN);
+        let mut buf = bytes.split_off(NONCE_LEN);
+        cipher()
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&bytes),
+                label.as_bytes(),
+                &mut buf,
+                Tag::from_slice(&tag),
+            )
+            .ok()?;
+        String::from_utf8(buf).ok()
+    });
+    if opened.is_none() {
+        report_unreadable(label);
+    }
+    opened
+}
+
+/// `stored` is plaintext but this image seals values.
+pub fn needs_sealing(stored: &str) -> bool {
+    backend() == Backend::Sealed && !stored.starts_with(PREFIX)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+fn report_unreadable(label: &'static str) {
+    error!("secrets: {label} does not open with this device's key; treating it as unset");
+    boot::note(format!(
+        "Secret {label} could not be decrypted and reads as unset until set again"
+    ));
+    if let Ok(mut unreadable) = UNREADABLE.lock() {
+        if !unreadable.contains(&label) {
+            unreadable.push(label);
+        }
+    }
+}
+
+/// Secrets that failed to open this boot and were not set since.
+pub fn unreadable() -> Vec<&'static str> {
+    UNREADABLE
+        .lock()
+        .map(|unreadable| unreadable.clone())
+        .unwrap_or_default()
+}
+
+/// Toasts about secrets that failed to open during boot. UI thread only;
+/// called once the first frame is up.
+pub fn notify_unreadable() {
+    let unreadable = unreadable();
+    if !unreadable.is_empty() {
+        crate::gui::toast::show(crate::i18n::trf(
+            "Stored secrets unreadable, set them again: {}",
+            &[&unreadable.join(", ")],
+        ));
+    }
+}
+
+fn cipher() -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(Key::from_slice(&device_key().key))
+}
+
+fn device_key() -> &'static DeviceKey {
+    DEVICE_KEY.get_or_init(|| {
+        let mut key = [0u8; 32];
+        let err = unsafe {
+            esp_hmac_calculate(
+                hmac_key_id_t_HMAC_KEY5,
+                KEY_DOMAIN.as_ptr().cast(),
+                KEY_DOMAIN.len(),
+                key.as_mut_ptr(),
+            )
+        };
+        if err == ESP_OK {
+            info!("secrets: key from the HMAC peripheral");
+            return DeviceKey {
+                key,
+                source: "HMAC peripheral key",
+            };
+        }
+        let mut mac = [0u8; 6];
+        unsafe { esp_efuse_mac_get_default(mac.as_mut_ptr()) };
+        warn!("secrets: no HMAC key in eFuse (err {err}), deriving from the MAC");
+        DeviceKey {
+            key: Sha256::new()
+                .chain_update(KEY_DOMAIN)
+                .chain_update(mac)
+                .finalize()
+                .into(),
+            source: "MAC-derived key",
+        }
+    })
+}
+
+pub fn register_commands() {
+    crate::console::register("secrets", "secrets store backend and status", |args| {
+        if !args.is_empty() {
+            anyhow::bail!("usage: secrets");
+        }
+        let backend = match backend() {
+            Backend::EncryptedNvs => "encrypted NVS".to_string(),
+            Backend::Sealed => format!("sealed, {}", device_key().source),
+        };
+        let unreadable = unreadable();
+        Ok(if unreadable.is_empty() {
+            format!("secrets: {backend}")
+        } else {
+            format!("secrets: {backend}; unreadable: {}", unreadable.join(", "))
+        })
+    });
+}
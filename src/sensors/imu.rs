@@ -0,0 +1,365 @@
+//! Optional accelerometer on the touch controller's I2C bus, for automatic
+//! screen rotation and the level page. Boards name its address with
+//! `imu_addr`; QMI8658 and LSM6DS-family parts are recognised by their
+//! WHO_AM_I. Transactions go through the IDF driver the touch task already
+//! installed, which serialises them per port, so the touch side is
+//! untouched.
+//!
+//! Gravity is sampled at 5 Hz and sorted into four 90° buckets. A bucket
+//! must hold for [`STABLE_FOR`] before the screen turns, and no turn happens
+//! while a transfer or a dialog is on screen.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU32, AtomicU8, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use esp_idf_svc::{
+    hal::delay::TickType,
+    sys::{esp, i2c_master_write_read_device, i2c_master_write_to_device, i2c_port_t},
+};
+use log::{info, warn};
+
+use crate::{
+    board,
+    events::{self, SystemEvent},
+    gui::slint_ui,
+    miwear::status,
+    settings::{self, SettingKey},
+};
+
+/// Opt-in: the enclosure may be mounted so that the IMU axes are off.
+pub const AUTO_ROTATE: SettingKey<bool> = SettingKey::new("auto_rotate", "false");
+
+/// The touch controller's port, see `touch::spawn_touch_task`.
+const I2C_PORT: i2c_port_t = 0;
+const I2C_TIMEOUT: Duration = Duration::from_millis(20);
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+pub const STABLE_FOR: Duration = Duration::from_secs(2);
+/// Below this much in-plane gravity the panel is lying flat and any bucket
+/// would be noise.
+const MIN_TILT_G: f32 = 0.5;
+/// A new bucket needs this much beyond the 45° boundary.
+const HYSTERESIS_DEG: f32 = 10.0;
+/// Both supported parts at ±2 g.
+const LSB_PER_G: f32 = 16_384.0;
+
+static ROTATION: AtomicU8 = AtomicU8::new(ScreenRotation::Deg0 as u8);
+static PRESENT: AtomicU8 = AtomicU8::new(0);
+/// Bumped on every sample, for pages that show the tilt.
+static SAMPLES: AtomicU32 = AtomicU32::new(0);
+static LATEST: Mutex<Option<Tilt>> = Mutex::new(None);
+
+/// Clockwise turn of the UI on the panel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ScreenRotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl ScreenRotation {
+    const ALL: [ScreenRotation; 4] = [
+        ScreenRotation::Deg0,
+        ScreenRotation::Deg90,
+        ScreenRotation::Deg180,
+        ScreenRotation::Deg270,
+    ];
+
+    pub fn degrees(self) -> u16 {
+        self as u16 * 90
+    }
+
+    fn from_index(index: u8) -> Self {
+        Self::ALL[usize::from(index % 4)]
+    }
+}
+
+impl fmt::Display for ScreenRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}°", self.degrees())
+    }
+}
+
+/// Gravity in g along the panel's axes: x right, y down, z out of the glass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tilt {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Tilt {
+    /// Where gravity points in the panel plane, clockwise from straight
+    /// down; `None` while lying flat.
+    pub fn angle_deg(&self) -> Option<f32> {
+        (self.x.hypot(self.y) >= MIN_TILT_G)
+            .then(|| (-self.x).atan2(self.y).to_degrees().rem_euclid(360.0))
+    }
+
+    /// Off level in degrees: 0 flat on its back, 90 upright.
+    pub fn incline_deg(&self) -> f32 {
+        self.x.hypot(self.y).atan2(self.z).to_degrees()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+enum Chip {
+    Qmi8658 = 1,
+    Lsm6ds = 2,
+}
+
+impl Chip {
+    fn name(self) -> &'static str {
+        match self {
+            Chip::Qmi8658 => "QMI8658",
+            Chip::Lsm6ds => "LSM6DS",
+        }
+    }
+
+    /// First of the six little-endian accelerometer output bytes.
+    fn accel_register(self) -> u8 {
+        match self {
+            Chip::Qmi8658 => 0x35,
+            Chip::Lsm6ds => 0x28,
+        }
+    }
+}
+
+/// The rotation the UI is drawn at.
+pub fn rotation() -> ScreenRotation {
+    ScreenRotation::from_index(ROTATION.load(Ordering::Relaxed))
+}
+
+pub fn present() -> bool {
+    PRESENT.load(Ordering::Relaxed) != 0
+}
+
+pub fn latest() -> Option<Tilt> {
+    LATEST.lock().ok().and_then(|tilt| *tilt)
+}
+
+pub fn samples() -> u32 {
+    SAMPLES.load(Ordering::Relaxed)
+}
+
+/// Probes the board's IMU and starts sampling. Run after touch init, which
+/// installs the bus driver. Without an IMU nothing is registered and the
+/// bus is left as it was.
+pub fn start() -> Result<()> {
+    let Some(addr) = board::CURRENT.imu_addr else {
+        info!("No IMU on this board");
+        return Ok(());
+    };
+    let chip = probe(addr).with_context(|| format!("IMU at 0x{addr:02X}"))?;
+    configure(addr, chip).with_context(|| format!("configure {}", chip.name()))?;
+    PRESENT.store(chip as u8, Ordering::Relaxed);
+    info!("IMU {} at 0x{addr:02X}", chip.name());
+
+    let mut tracker = Tracker::new(rotation());
+    crate::periodic::register("imu", SAMPLE_INTERVAL, move || {
+        match read_tilt(addr, chip) {
+            Ok(tilt) => {
+                if let Ok(mut latest) = LATEST.lock() {
+                    *latest = Some(tilt);
+                }
+                SAMPLES.fetch_add(1, Ordering::Relaxed);
+                tracker.sample(&tilt, Instant::now());
+            }
+            Err(err) => warn!("IMU read failed: {err:#}"),
+        }
+        let wanted = if settings::get(&AUTO_ROTATE) {
+            tracker.settled()
+        } else {
+            ScreenRotation::Deg0
+        };
+        if wanted != rotation() && !busy() {
+            set_rotation(wanted);
+        }
+    });
+    Ok(())
+}
+
+fn set_rotation(rotation: ScreenRotation) {
+    ROTATION.store(rotation as u8, Ordering::Relaxed);
+    info!("Screen rotation {rotation}");
+    events::publish(SystemEvent::OrientationChanged { rotation });
+}
+
+/// A flip now would move the target from under a finger or a progress bar.
+fn busy() -> bool {
+    status::transfer().is_some() || slint_ui::top_overlay().is_some()
+}
+
+/// Debounces buckets: a candidate must last [`STABLE_FOR`] to settle.
+struct Tracker {
+    settled: ScreenRotation,
+    candidate: Option<(ScreenRotation, Instant)>,
+}
+
+impl Tracker {
+    fn new(settled: ScreenRotation) -> Self {
+        Self {
+            settled,
+            candidate: None,
+        }
+    }
+
+    fn sample(&mut self, tilt: &Tilt, now: Instant) {
+        let Some(bucket) = tilt
+            .angle_deg()
+            .and_then(|angle| bucket(angle, self.settled))
+        else {
+            self.candidate = None;
+            return;
+        };
+        if bucket == self.settled {
+            self.candidate = None;
+            return;
+        }
+        match self.candidate {
+            Some((candidate, since)) if candidate == bucket => {
+                if now.duration_since(since) >= STABLE_FOR {
+                    self.settled = bucket;
+                    self.candidate = None;
+                }
+            }
+            _ => self.candidate = Some((bucket, now)),
+        }
+    }
+
+    fn settled(&self) -> ScreenRotation {
+        self.settled
+    }
+}
+
+/// The bucket `angle` falls in. Leaving `current` takes [`HYSTERESIS_DEG`]
+/// past the boundary; near a boundary the answer is `current`.
+fn bucket(angle: f32, current: ScreenRotation) -> Option<ScreenRotation> {
+    let nearest = ScreenRotation::from_index(((angle + 45.0) / 90.0) as u8);
+    if nearest == current {
+        return Some(current);
+    }
+    let center = f32::from(nearest.degrees());
+    let off = (angle - center + 180.0).rem_euclid(360.0) - 180.0;
+    (off.abs() <= 45.0 - HYSTERESIS_DEG)
+        .then_some(nearest)
+        .or(Some(current))
+}
+
+fn probe(addr: u8) -> Result<Chip> {
+    // QMI8658 WHO_AM_I is 0x05 at register 0x00; the LSM6DS parts answer
+    // 0x69/0x6A/0x6B/0x6C at 0x0F.
+    if let Ok([0x05]) = read::<1>(addr, 0x00) {
+        return Ok(Chip::Qmi8658);
+    }
+    match read::<1>(addr, 0x0F) {
+        Ok([0x69..=0x6C]) => Ok(Chip::Lsm6ds),
+        Ok([other]) => bail!("unknown WHO_AM_I 0x{other:02X}"),
+        Err(err) => Err(err.context("no answer")),
+    }
+}
+
+fn configure(addr: u8, chip: Chip) -> Result<()> {
+    match chip {
+        Chip::Qmi8658 => {
+            // CTRL1: register auto-increment. CTRL2: ±2 g, 125 Hz.
+            // CTRL7: accelerometer on.
+            write(addr, 0x02, 0x40)?;
+            write(addr, 0x03, 0x06)?;
+            write(addr, 0x08, 0x01)
+        }
+        Chip::Lsm6ds => {
+            // CTRL3_C: block data update, auto-increment. CTRL1_XL: 26 Hz, ±2 g.
+            write(addr, 0x12, 0x44)?;
+            write(addr, 0x10, 0x20)
+        }
+    }
+}
+
+fn read_tilt(addr: u8, chip: Chip) -> Result<Tilt> {
+    let raw: [u8; 6] = read(addr, chip.accel_register())?;
+    let axis = |index: usize| {
+        f32::from(i16::from_le_bytes([raw[2 * index], raw[2 * index + 1]])) / LSB_PER_G
+    };
+    Ok(Tilt {
+        x: axis(0),
+        y: axis(1),
+        z: axis(2),
+    })
+}
+
+fn read<const N: usize>(addr: u8, register: u8) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    esp!(unsafe {
+        i2c_master_write_read_device(
+            I2C_PORT,
+            addr,
+            &register,
+            1,
+            buf.as_mut_ptr(),
+            N,
+            timeout_ticks(),
+        )
+    })?;
+    Ok(buf)
+}
+
+fn write(addr: u8, register: u8, value: u8) -> Result<()> {
+    let bytes = [register, value];
+    esp!(unsafe {
+        i2c_master_write_to_device(I2C_PORT, addr, bytes.as_ptr(), bytes.len(), timeout_ticks())
+    })?;
+    Ok(())
+}
+
+fn timeout_ticks() -> u32 {
+    TickType::from(I2C_TIMEOUT).ticks()
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "imu",
+        "[autorotate on|off]: accelerometer reading and screen rotation",
+        |args| {
+            match args {
+                [] => {}
+                ["autorotate", "on"] => settings::set(&AUTO_ROTATE, &true)?,
+                ["autorotate", "off"] => settings::set(&AUTO_ROTATE, &false)?,
+                _ => bail!("usage: imu [autorotate on|off]"),
+            }
+            if !present() {
+                return Ok("no IMU".to_string());
+            }
+            let reading = latest().map_or_else(
+                || "no sample yet".to_string(),
+                |tilt| {
+                    format!(
+                        "x {:+.2} y {:+.2} z {:+.2} g, incline {:.0}°",
+                        tilt.x,
+                        tilt.y,
+                        tilt.z,
+                        tilt.incline_deg()
+                    )
+                },
+            );
+            Ok(format!(
+                "{reading}; rotation {} (auto {})",
+                rotation(),
+                if settings::get(&AUTO_ROTATE) {
+                    "on"
+                } else {
+                    "off"
+                }
+            ))
+        },
+    );
+}
@@ -0,0 +1,194 @@
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use esp_idf_svc::sys::{
+    esp_deep_sleep_start, esp_sleep_enable_timer_wakeup,
+    soc_periph_temperature_sensor_clk_src_t_TEMPERATURE_SENSOR_CLK_SRC_DEFAULT,
+    temperature_sensor_config_t, temperature_sensor_enable, temperature_sensor_get_celsius,
+    temperature_sensor_handle_t, temperature_sensor_install, ESP_OK,
+};
+use log::{error, info, warn};
+
+use crate::{
+    events::{self, SystemEvent},
+    settings::{self, SettingKey},
+};
+
+pub const WARNING_CELSIUS: SettingKey<f32> =
+    SettingKey::new("temp_warn_c", "70").validated(|celsius| {
+        let critical = settings::get(&CRITICAL_CELSIUS);
+        if celsius.is_finite() && *celsius < critical {
+            Ok(())
+        } else {
+            Err(format!("warning must be below the critical {critical} C"))
+        }
+    });
+pub const CRITICAL_CELSIUS: SettingKey<f32> =
+    SettingKey::new("temp_crit_c", "85").validated(|celsius| {
+        let warning = settings::get(&WARNING_CELSIUS);
+        if celsius.is_finite() && *celsius > warning {
+            Ok(())
+        } else {
+            Err(format!("critical must be above the warning {warning} C"))
+        }
+    });
+
+/// A level is only left once the die is this much below its threshold.
+const HYSTERESIS_CELSIUS: f32 = 5.0;
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+/// Deep sleep gives the enclosure time to cool before the next boot.
+const COOL_DOWN: Duration = Duration::from_secs(10 * 60);
+const NO_READING: u32 = u32::MAX;
+
+static CURRENT: AtomicU32 = AtomicU32::new(NO_READING);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThermalLevel {
+    Normal,
+    Warning,
+    Critical,
+}
+
+/// Latest reading, `None` while the sensor is disabled or not yet sampled.
+pub fn current_celsius() -> Option<f32> {
+    match CURRENT.load(Ordering::Relaxed) {
+        NO_READING => None,
+        bits => Some(f32::from_bits(bits)),
+    }
+}
+
+/// Installs the on-die sensor and starts sampling. Failing here leaves the
+/// feature disabled; nothing else depends on it.
+pub fn start() -> Result<()> {
+    let config = temperature_sensor_config_t {
+        range_min: 20,
+        range_max: 100,
+        clk_src: soc_periph_temperature_sensor_clk_src_t_TEMPERATURE_SENSOR_CLK_SRC_DEFAULT,
+        ..Default::default()
+    };
+    let mut handle: temperature_sensor_handle_t = core::ptr::null_mut();
+    esp_ok(
+        unsafe { temperature_sensor_install(&config, &mut handle) },
+        "install",
+    )?;
+    esp_ok(unsafe { temperature_sensor_enable(handle) }, "enable")?;
+    // Fail the boot stage now rather than in the first sample.
+    let first = read(handle)?;
+
+    let sensor = Sensor(handle);
+    let mut level = ThermalLevel::Normal;
+    let mut sample = move |reading: Result<f32>| match reading {
+        Ok(celsius) => {
+            CURRENT.store(celsius.to_bits(), Ordering::Relaxed);
+            let next = next_level(
+                level,
+                celsius,
+                settings::get(&WARNING_CELSIUS),
+                settings::get(&CRITICAL_CELSIUS),
+            );
+            if next != level {
+                tokio::task::spawn_local(on_level_change(level, next, celsius));
+                level = next;
+            }
+        }
+        Err(err) => warn!("Temperature read failed: {err:?}"),
+    };
+    sample(Ok(first));
+    crate::periodic::register("temperature", SAMPLE_INTERVAL, move || {
+        sample(sensor.read())
+    });
+    Ok(())
+}
+
+/// The driver handle, moved into the periodic job.
+struct Sensor(temperature_sensor_handle_t);
+
+// The handle only names the driver instance, and after `start` the periodic
+// job is its one user.
+unsafe impl Send for Sensor {}
+
+impl Sensor {
+    fn read(&self) -> Result<f32> {
+        read(self.0)
+    }
+}
+
+/// Thresholds are entered at the configured value and left only below it
+/// minus `HYSTERESIS_CELSIUS`.
+pub fn next_level(
+    current: ThermalLevel,
+    celsius: f32,
+    warning: f32,
+    critical: f32,
+) -> ThermalLevel {
+    let above = |threshold: f32, held: bool| {
+        if held {
+            celsius > threshold - HYSTERESIS_CELSIUS
+        } else {
+            celsius >= threshold
+        }
+    };
+    if above(critical, current == ThermalLevel::Critical) {
+        ThermalLevel::Critical
+    } else if above(warning, current != ThermalLevel::Normal) {
+        ThermalLevel::Warning
+    } else {
+        ThermalLevel::Normal
+    }
+}
+
+async fn on_level_change(from: ThermalLevel, to: ThermalLevel, celsius: f32) {
+    match to {
+        ThermalLevel::Normal => {
+            info!("Temperature back to normal ({celsius:.1} C)");
+            events::publish(SystemEvent::ThermalWarning {
+                active: false,
+                celsius,
+            });
+        }
+        ThermalLevel::Warning => {
+            if from == ThermalLevel::Normal {
+                warn!("Temperature warning ({celsius:.1} C), throttling");
+                events::publish(SystemEvent::ThermalWarning {
+                    active: true,
+                    celsius,
+                });
+            }
+        }
+        ThermalLevel::Critical => {
+            error!("Temperature critical ({celsius:.1} C), shutting down");
+            events::publish(SystemEvent::ShuttingDown { reason: "thermal" });
+            tokio::time::sleep(SHUTDOWN_GRACE).await;
+            if let Err(err) = crate::nvs::flush().await {
+                warn!("NVS flush before shutdown failed: {err:#}");
+            }
+            #[cfg(feature = "storage")]
+            crate::statlogger::flash_journal::flush();
+            unsafe {
+                esp_sleep_enable_timer_wakeup(COOL_DOWN.as_micros() as u64);
+                esp_deep_sleep_start();
+            }
+        }
+    }
+}
+
+fn read(handle: temperature_sensor_handle_t) -> Result<f32> {
+    let mut celsius = 0.0f32;
+    esp_ok(
+        unsafe { temperature_sensor_get_celsius(handle, &mut celsius) },
+        "read",
+    )?;
+    Ok(celsius)
+}
+
+fn esp_ok(code: i32, what: &str) -> Result<()> {
+    if code == ESP_OK as i32 {
+        Ok(())
+    } else {
+        Err(anyhow!("temperature sensor {what} failed: {code}"))
+    }
+}
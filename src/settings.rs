@@ -0,0 +1,398 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    marker::PhantomData,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+
+use crate::events::{self, SystemEvent};
+
+pub mod revision;
+pub mod rollback;
+
+use revision::{Stamped, Update};
+
+/// Each setting as `rev:value`, see [`revision`].
+const NAMESPACE: &str = "settings_v2";
+/// Values and their revisions as kept before they shared a key; read until
+/// the setting is next stored, which erases them.
+const LEGACY_NAMESPACE: &str = "settings";
+const LEGACY_REVISION_NAMESPACE: &str = "settings_rev";
+const MAX_VALUE_LEN: usize = 256;
+/// A `u32` revision and its `:`.
+const MAX_REVISION_LEN: usize = 11;
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+static PARTITION: Mutex<Option<EspDefaultNvsPartition>> = Mutex::new(None);
+/// Bumped on every stored change, for pages showing settings.
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// A typed, NVS-backed setting. Modules declare their own keys as constants;
+/// the default is stored in encoded form so keys stay `const`-constructible.
+pub struct SettingKey<T> {
+    pub name: &'static str,
+    default: &'static str,
+    validate: Option<Validator<T>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+/// Checks a value before [`set`] stores it and returns the reason it is
+/// refused. Plain functions only: a validator may read other settings but
+/// must not block, and the default is never passed through it.
+pub type Validator<T> = fn(&T) -> Result<(), String>;
+
+/// A value refused by its key's validator. [`set`] returns it inside the
+/// `anyhow` error; callers that report per key can `downcast_ref` it.
+#[derive(Clone, Debug)]
+pub struct Rejected {
+    pub key: &'static str,
+    pub reason: String,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.reason)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// A [`set_at_revision`] that lost to a newer write, with what that write
+/// left. Returned inside the `anyhow` error like [`Rejected`].
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub key: &'static str,
+    pub revision: u32,
+    pub value: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is at revision {}", self.key, self.revision)
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+impl<T: SettingValue> SettingKey<T> {
+    pub const fn new(name: &'static str, default: &'static str) -> Self {
+        Self {
+            name,
+            default,
+            validate: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Attaches `validate`, run by every [`set`] of this key.
+    pub const fn validated(self, validate: Validator<T>) -> Self {
+        Self {
+            validate: Some(validate),
+            ..self
+        }
+    }
+
+    /// Runs the validator, if any, on `value`.
+    pub fn check(&self, value: &T) -> Result<(), Rejected> {
+        match self.validate {
+            Some(validate) => validate(value).map_err(|reason| Rejected {
+                key: self.name,
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn default_value(&self) -> T {
+        T::decode(self.default)
+            .unwrap_or_else(|| panic!("invalid default for setting {}", self.name))
+    }
+}
+
+pub trait SettingValue: Sized {
+    fn encode(&self) -> String;
+    fn decode(raw: &str) -> Option<Self>;
+}
+
+impl<T: Display + FromStr> SettingValue for T {
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+
+    fn decode(raw: &str) -> Option<Self> {
+        raw.parse().ok()
+    }
+}
+
+struct Registry {
+    nvs: EspNvs<NvsDefault>,
+    legacy_nvs: EspNvs<NvsDefault>,
+    legacy_revision_nvs: EspNvs<NvsDefault>,
+    /// `None` for a setting never stored.
+    cache: HashMap<&'static str, Option<Stamped>>,
+    /// Loaded from the legacy namespaces, which the next store erases.
+    legacy: HashSet<&'static str>,
+}
+
+impl Registry {
+    fn load(&mut self, name: &'static str) -> Option<Stamped> {
+        if let Some(stamped) = self.cache.get(name) {
+            return stamped.clone();
+        }
+        let stamped = match self.read(name) {
+            Some(stamped) => Some(stamped),
+            None => self.read_legacy(name),
+        };
+        self.cache.insert(name, stamped.clone());
+        stamped
+    }
+
+    fn load_raw(&mut self, name: &'static str) -> Option<String> {
+        self.load(name).map(|stamped| stamped.value)
+    }
+
+    /// 0 for a setting never stored.
+    fn revision(&mut self, name: &'static str) -> u32 {
+        self.load(name).map_or(0, |stamped| stamped.revision)
+    }
+
+    fn read(&mut self, name: &'static str) -> Option<Stamped> {
+        let mut buf = [0u8; MAX_VALUE_LEN + MAX_REVISION_LEN];
+        match self.nvs.get_str(name, &mut buf) {
+            Ok(Some(raw)) => {
+                let stamped = Stamped::decode(raw);
+                if stamped.is_none() {
+                    log::warn!("settings: stored record for {name} is malformed");
+                }
+                stamped
+            }
+            Ok(None) => None,
+            Err(err) => {
+                log::warn!("settings: failed to read {name}: {err:?}");
+                None
+            }
+        }
+    }
+
+    /// A value stored before revisions shared its key, at the revision kept
+    /// beside it, or 0 if there is none.
+    fn read_legacy(&mut self, name: &'static str) -> Option<Stamped> {
+        let mut buf = [0u8; MAX_VALUE_LEN];
+        let value = match self.legacy_nvs.get_str(name, &mut buf) {
+            Ok(Some(value)) => value.to_string(),
+            Ok(None) => return None,
+            Err(err) => {
+                log::warn!("settings: failed to read {name}: {err:?}");
+                return None;
+            }
+        };
+        let mut buf = [0u8; MAX_REVISION_LEN];
+        let revision = match self.legacy_revision_nvs.get_str(name, &mut buf) {
+            Ok(Some(raw)) => raw.parse().unwrap_or(0),
+            Ok(None) => 0,
+            Err(err) => {
+                log::warn!("settings: failed to read revision of {name}: {err:?}");
+                0
+            }
+        };
+        self.legacy.insert(name);
+        Some(Stamped { revision, value })
+    }
+}
+
+pub fn init(partition: EspDefaultNvsPartition) -> Result<()> {
+    if let Ok(mut slot) = PARTITION.lock() {
+        *slot = Some(partition.clone());
+    }
+    crate::nvs::Writer::start(partition.clone())?;
+    let nvs = EspNvs::new(partition.clone(), NAMESPACE, true).context("open settings namespace")?;
+    let legacy_nvs = EspNvs::new(partition.clone(), LEGACY_NAMESPACE, true)
+        .context("open legacy settings namespace")?;
+    let legacy_revision_nvs = EspNvs::new(partition, LEGACY_REVISION_NAMESPACE, true)
+        .context("open legacy settings revision namespace")?;
+    let mut slot = REGISTRY
+        .lock()
+        .map_err(|_| anyhow!("settings registry poisoned"))?;
+    *slot = Some(Registry {
+        nvs,
+        legacy_nvs,
+        legacy_revision_nvs,
+        cache: HashMap::new(),
+        legacy: HashSet::new(),
+    });
+    Ok(())
+}
+
+/// Opens a module-private NVS namespace on the default partition, for state
+/// that is not a user setting (bond bookkeeping, counters, caches).
+pub fn open_namespace(namespace: &str) -> Result<EspNvs<NvsDefault>> {
+    let partition = PARTITION
+        .lock()
+        .map_err(|_| anyhow!("settings registry poisoned"))?
+        .clone()
+        .ok_or_else(|| anyhow!("settings registry not initialized"))?;
+    EspNvs::new(partition, namespace, true)
+        .with_context(|| format!("open NVS namespace {namespace}"))
+}
+
+pub fn get<T: SettingValue>(key: &SettingKey<T>) -> T {
+    let raw = REGISTRY
+        .lock()
+        .ok()
+        .and_then(|mut slot| slot.as_mut().and_then(|reg| reg.load_raw(key.name)));
+
+    match raw {
+        Some(raw) => T::decode(&raw).unwrap_or_else(|| {
+            log::warn!(
+                "settings: stored value for {} is invalid, using default",
+                key.name
+            );
+            key.default_value()
+        }),
+        None => key.default_value(),
+    }
+}
+
+/// Reads `name`'s stored value, if any, and erases it: the cache entry now,
+/// the NVS key through the writer. For values moving to another store.
+pub fn take_stored(name: &'static str) -> Option<String> {
+    let value = stored(name)?;
+    forget_stored(name);
+    Some(value)
+}
+
+/// `name`'s stored value, if any, whether or not a key is registered for it.
+pub fn stored(name: &'static str) -> Option<String> {
+    REGISTRY.lock().ok()?.as_mut()?.load_raw(name)
+}
+
+/// Erases `name`, revision included: the cache entry now, the NVS keys
+/// through the writer.
+pub fn forget_stored(name: &'static str) {
+    if let Ok(mut slot) = REGISTRY.lock() {
+        if let Some(registry) = slot.as_mut() {
+            registry.cache.insert(name, None);
+            registry.legacy.remove(name);
+        }
+    }
+    crate::nvs::remove(NAMESPACE, name);
+    forget_legacy(name);
+}
+
+fn forget_legacy(name: &'static str) {
+    crate::nvs::remove(LEGACY_NAMESPACE, name);
+    crate::nvs::remove(LEGACY_REVISION_NAMESPACE, name);
+}
+
+/// Updates the cached value immediately; the NVS write is queued to the
+/// writer thread. A value the key's validator refuses is not stored and the
+/// error carries a [`Rejected`]. Always wins: the on-device UI and the
+/// console edit through this.
+pub fn set<T: SettingValue>(key: &SettingKey<T>, value: &T) -> Result<()> {
+    key.check(value)?;
+    store(key, value.encode(), None).map(|_| ())
+}
+
+/// [`set`] for an editor that read revision `expected` (HTTP `If-Match`):
+/// when the setting has moved on since, nothing is stored and the error
+/// carries a [`Conflict`]. Returns the new revision.
+pub fn set_at_revision<T: SettingValue>(
+    key: &SettingKey<T>,
+    value: &T,
+    expected: u32,
+) -> Result<u32> {
+    key.check(value)?;
+    store(key, value.encode(), Some(expected))
+}
+
+/// Revision of `name`, bumped by every change that stores a new value.
+pub fn revision(name: &'static str) -> u32 {
+    REGISTRY
+        .lock()
+        .ok()
+        .and_then(|mut slot| slot.as_mut().map(|reg| reg.revision(name)))
+        .unwrap_or(0)
+}
+
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+fn store<T: SettingValue>(
+    key: &SettingKey<T>,
+    encoded: String,
+    expected: Option<u32>,
+) -> Result<u32> {
+    if encoded.len() >= MAX_VALUE_LEN {
+        return Err(anyhow!("value for {} is too long", key.name));
+    }
+
+    let mut slot = REGISTRY
+        .lock()
+        .map_err(|_| anyhow!("settings registry poisoned"))?;
+    let registry = slot
+        .as_mut()
+        .ok_or_else(|| anyhow!("settings registry not initialized"))?;
+
+    let current = registry.load(key.name);
+    let stamped = match revision::update(current.as_ref(), &encoded, expected) {
+        Update::Unchanged(revision) => return Ok(revision),
+        Update::Conflict(revision) => {
+            return Err(Conflict {
+                key: key.name,
+                revision,
+                value: current.map_or_else(|| key.default.to_string(), |current| current.value),
+            }
+            .into())
+        }
+        Update::Store(stamped) => stamped,
+    };
+    let revision = stamped.revision;
+    let migrated = registry.legacy.remove(key.name);
+    registry.cache.insert(key.name, Some(stamped.clone()));
+    drop(slot);
+    crate::nvs::write(NAMESPACE, key.name, stamped.encode());
+    if migrated {
+        // Queued after the record, so a reset in between still finds it.
+        forget_legacy(key.name);
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    events::publish(SystemEvent::SettingChanged {
+        name: key.name,
+        revision,
+    });
+    Ok(revision)
+}
+
+/// A setting edited by name from outside the firmware, as encoded text.
+pub trait Editable: Sync {
+    fn name(&self) -> &'static str;
+    fn encoded(&self) -> String;
+    /// Decodes `raw` and stores it through [`set_at_revision`].
+    fn set_encoded(&self, raw: &str, expected: u32) -> Result<u32>;
+}
+
+impl<T: SettingValue> Editable for SettingKey<T> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn encoded(&self) -> String {
+        get(self).encode()
+    }
+
+    fn set_encoded(&self, raw: &str, expected: u32) -> Result<u32> {
+        let value = T::decode(raw).ok_or_else(|| Rejected {
+            key: self.name,
+            reason: format!("not a valid value: {raw}"),
+        })?;
+        set_at_revision(self, &value, expected)
+    }
+}
@@ -0,0 +1,53 @@
+//! How a setting is kept in NVS: the revision and the value that revision
+//! wrote, under one key as `rev:value`. One key means one write, so a reset
+//! between two writer commits can never pair a value with another write's
+//! revision.
+
+/// A stored value and the revision that wrote it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stamped {
+    pub revision: u32,
+    pub value: String,
+}
+
+/// What storing a value does, given what is stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Update {
+    /// The value is already stored at this revision; nothing to write.
+    Unchanged(u32),
+    Store(Stamped),
+    /// The editor read an older revision than this one.
+    Conflict(u32),
+}
+
+impl Stamped {
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.revision, self.value)
+    }
+
+    /// Splits at the first `:`, so the value may hold more of them.
+    pub fn decode(raw: &str) -> Option<Self> {
+        let (revision, value) = raw.split_once(':')?;
+        Some(Self {
+            revision: revision.parse().ok()?,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Storing `value` over `current` for an editor that read revision
+/// `expected`, or for one that always wins when `None`. Revision 0 is a
+/// setting never stored.
+pub fn update(current: Option<&Stamped>, value: &str, expected: Option<u32>) -> Update {
+    let revision = current.map_or(0, |current| current.revision);
+    if expected.is_some_and(|expected| expected != revision) {
+        return Update::Conflict(revision);
+    }
+    if current.is_some_and(|current| current.value == value) {
+        return Update::Unchanged(revision);
+    }
+    Update::Store(Stamped {
+        revision: revision.wrapping_add(1),
+        value: value.to_string(),
+    })
+}
@@ -0,0 +1,342 @@
+//! Rollback slot for connectivity-critical settings. The previous values are
+//! saved before such a change is applied. If the subsystem is not healthy
+//! within [`VALIDATION_WINDOW`], they are restored and the subsystem retried.
+//!
+//! Only Wi-Fi (SSID, password, country) is covered; no other setting here
+//! can leave the module unreachable. The slot holds the Wi-Fi password, so
+//! it lives in [`secrets`]. It is emptied once a change validates, so an old
+//! snapshot can never fire later.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::SettingKey;
+use crate::{
+    events::{self, SystemEvent},
+    secrets,
+    wifi::{self, country},
+};
+
+/// Sealed JSON of the pending [`Snapshot`]; empty when nothing is pending.
+const SLOT: SettingKey<String> = SettingKey::new("rollback", "");
+pub const VALIDATION_WINDOW: Duration = Duration::from_secs(60);
+const POLL: Duration = Duration::from_secs(1);
+
+static REQUESTS: OnceLock<mpsc::UnboundedSender<Request>> = OnceLock::new();
+static NOTICE: Mutex<Option<RolledBack>> = Mutex::new(None);
+/// Bumped when the slot or the notice changes.
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    Wifi,
+}
+
+impl Subsystem {
+    pub fn code(self) -> &'static str {
+        match self {
+            Subsystem::Wifi => "wifi",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Subsystem::Wifi => "Wi-Fi",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Snapshot {
+    subsystem: Subsystem,
+    /// Unix seconds; 0 when the clock was not set yet.
+    saved_at: u64,
+    /// Setting name and encoded value, for the subsystem's keys only.
+    values: Vec<(String, String)>,
+}
+
+/// The last automatic rollback, until dismissed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RolledBack {
+    pub subsystem: Subsystem,
+    pub at: Instant,
+}
+
+enum Request {
+    Changed,
+    Undo,
+}
+
+/// Call before applying a change to `subsystem`'s settings. Keeps an older
+/// pending snapshot, since that is the last state known to work, and
+/// restarts the validation window. A failure to save is logged, not
+/// returned: the change itself still goes ahead.
+pub fn before_change(subsystem: Subsystem) {
+    if pending().is_none() {
+        let snapshot = Snapshot {
+            subsystem,
+            saved_at: unix_now(),
+            values: capture(subsystem),
+        };
+        match serde_json::to_string(&snapshot)
+            .map_err(anyhow::Error::from)
+            .and_then(|encoded| secrets::set(&SLOT, &encoded))
+        {
+            Ok(()) => {
+                GENERATION.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => warn!("No rollback snapshot for {}: {err:#}", subsystem.label()),
+        }
+    }
+    if let Some(requests) = REQUESTS.get() {
+        let _ = requests.send(Request::Changed);
+    }
+}
+
+/// The subsystem with an unvalidated change and when it was saved.
+pub fn pending() -> Option<(Subsystem, u64)> {
+    load().map(|snapshot| (snapshot.subsystem, snapshot.saved_at))
+}
+
+/// Restores the pending snapshot now and retries the subsystem.
+pub fn undo() -> Result<()> {
+    if pending().is_none() {
+        bail!("no settings change to undo");
+    }
+    REQUESTS
+        .get()
+        .ok_or_else(|| anyhow!("settings rollback worker not running"))?
+        .send(Request::Undo)
+        .map_err(|_| anyhow!("settings rollback worker gone"))
+}
+
+/// For a subsystem that failed to start at boot: restores its pending
+/// snapshot, if any, so the caller can retry. True when something was
+/// restored.
+pub fn restore_at_boot(subsystem: Subsystem) -> bool {
+    let Some(snapshot) = load().filter(|snapshot| snapshot.subsystem == subsystem) else {
+        return false;
+    };
+    if let Err(err) = restore(&snapshot) {
+        warn!("Rollback of {} failed: {err:#}", subsystem.label());
+        return false;
+    }
+    clear();
+    record(subsystem);
+    true
+}
+
+pub fn notice() -> Option<RolledBack> {
+    NOTICE.lock().ok().and_then(|notice| *notice)
+}
+
+pub fn dismiss_notice() {
+    if let Ok(mut notice) = NOTICE.lock() {
+        if notice.take().is_some() {
+            GENERATION.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Spawn once on the main `LocalSet`. A snapshot left from before a reboot
+/// is validated like a fresh one.
+pub async fn run() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if REQUESTS.set(tx).is_err() {
+        return;
+    }
+    let mut deadline = pending().map(|_| Instant::now() + VALIDATION_WINDOW);
+    loop {
+        let request = match deadline {
+            Some(at) => tokio::select! {
+                request = rx.recv() => request,
+                _ = tokio::time::sleep(POLL) => {
+                    deadline = check(at).await;
+                    continue;
+                }
+            },
+            None => rx.recv().await,
+        };
+        match request {
+            None => return,
+            Some(Request::Changed) => deadline = Some(Instant::now() + VALIDATION_WINDOW),
+            Some(Request::Undo) => {
+                if let Some(snapshot) = load() {
+                    info!("Undoing the last {} change", snapshot.subsystem.label());
+                    roll_back(snapshot).await;
+                }
+                deadline = None;
+            }
+        }
+    }
+}
+
+/// The next deadline, or `None` once the change validated or was undone.
+async fn check(deadline: Instant) -> Option<Instant> {
+    let snapshot = load()?;
+    let subsystem = snapshot.subsystem;
+    if healthy(subsystem) {
+        clear();
+        info!(
+            "{} change validated; rollback snapshot cleared",
+            subsystem.label()
+        );
+        return None;
+    }
+    // Off for reasons of its own; the window starts again when it is back.
+    if paused(subsystem) {
+        return Some(Instant::now() + VALIDATION_WINDOW);
+    }
+    if Instant::now() < deadline {
+        return Some(deadline);
+    }
+    warn!(
+        "{} not healthy {} s after a settings change; rolling back",
+        subsystem.label(),
+        VALIDATION_WINDOW.as_secs()
+    );
+    if roll_back(snapshot).await {
+        record(subsystem);
+    }
+    None
+}
+
+/// Restores, clears the slot and retries; false if nothing was restored.
+async fn roll_back(snapshot: Snapshot) -> bool {
+    let subsystem = snapshot.subsystem;
+    if let Err(err) = restore(&snapshot) {
+        warn!("Rollback of {} failed: {err:#}", subsystem.label());
+        return false;
+    }
+    clear();
+    if let Err(err) = retry(subsystem).await {
+        warn!(
+            "{} still down with the previous settings: {err:#}",
+            subsystem.label()
+        );
+    }
+    true
+}
+
+fn record(subsystem: Subsystem) {
+    crate::journal!("Settings rolled back: {}", subsystem.label());
+    if let Ok(mut notice) = NOTICE.lock() {
+        *notice = Some(RolledBack {
+            subsystem,
+            at: Instant::now(),
+        });
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    events::publish(SystemEvent::SettingsRolledBack { subsystem });
+}
+
+fn capture(subsystem: Subsystem) -> Vec<(String, String)> {
+    match subsystem {
+        Subsystem::Wifi => vec![
+            (wifi::SSID.name.to_string(), super::get(&wifi::SSID)),
+            (
+                wifi::PASSWORD.name.to_string(),
+                secrets::get(&wifi::PASSWORD),
+            ),
+            (
+                country::COUNTRY.name.to_string(),
+                super::get(&country::COUNTRY).to_string(),
+            ),
+        ],
+    }
+}
+
+fn restore(snapshot: &Snapshot) -> Result<()> {
+    for (name, value) in &snapshot.values {
+        match name.as_str() {
+            name if name == wifi::SSID.name => super::set(&wifi::SSID, value)?,
+            name if name == wifi::PASSWORD.name => secrets::set(&wifi::PASSWORD, value)?,
+            name if name == country::COUNTRY.name => {
+                let country = value
+                    .parse()
+                    .map_err(|_| anyhow!("stored country {value} is invalid"))?;
+                super::set(&country::COUNTRY, &country)?;
+            }
+            other => warn!("Rollback snapshot has unknown key {other}; skipped"),
+        }
+    }
+    Ok(())
+}
+
+fn healthy(subsystem: Subsystem) -> bool {
+    match subsystem {
+        Subsystem::Wifi => wifi::connected(),
+    }
+}
+
+fn paused(subsystem: Subsystem) -> bool {
+    match subsystem {
+        Subsystem::Wifi => wifi::suspended(),
+    }
+}
+
+async fn retry(subsystem: Subsystem) -> Result<()> {
+    match subsystem {
+        Subsystem::Wifi => wifi::reconnect().await,
+    }
+}
+
+fn load() -> Option<Snapshot> {
+    let encoded = secrets::get(&SLOT);
+    if encoded.is_empty() {
+        return None;
+    }
+    serde_json::from_str(&encoded)
+        .map_err(|err| warn!("Rollback snapshot unreadable, ignored: {err}"))
+        .ok()
+}
+
+fn clear() {
+    if let Err(err) = secrets::set(&SLOT, &String::new()) {
+        warn!("Failed to clear the rollback snapshot: {err:#}");
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "rollback",
+        "[undo]: pending connectivity settings snapshot",
+        |args| match args {
+            [] => Ok(match pending() {
+                Some((subsystem, saved_at)) => format!(
+                    "{} change pending validation (saved at {saved_at})",
+                    subsystem.label()
+                ),
+                None => "no pending settings change".to_string(),
+            }),
+            ["undo"] => {
+                undo().context("undo")?;
+                Ok("restoring the previous settings".to_string())
+            }
+            _ => bail!("usage: rollback [undo]"),
+        },
+    );
+}
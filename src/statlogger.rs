@@ -1,18 +1,181 @@
-use esp_idf_svc::sys::{
-    heap_caps_get_free_size, MALLOC_CAP_8BIT, MALLOC_CAP_DMA, MALLOC_CAP_INTERNAL,
-    MALLOC_CAP_SPIRAM,
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use esp_idf_svc::{
+    log::EspLogger,
+    sys::{
+        esp_timer_get_time, heap_caps_get_free_size, heap_caps_get_largest_free_block,
+        MALLOC_CAP_8BIT, MALLOC_CAP_DMA, MALLOC_CAP_INTERNAL, MALLOC_CAP_SPIRAM,
+    },
 };
-use log::info;
+use log::{info, Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{memory::pressure::PressureLevel, settings};
+
+#[cfg(feature = "storage")]
+pub mod flash_journal;
+pub mod heap_monitor;
+pub mod ring;
+pub mod tail;
+
+/// Records logged with this target are always journaled; see `journal!`.
+pub const JOURNAL_TARGET: &str = "journal";
+
+static ESP_LOGGER: EspLogger = EspLogger::new();
+static LOGGER: Logger = Logger;
+
+/// Logs a milestone (connect, disconnect, update result) at info level and
+/// keeps it in the flash journal alongside warnings and errors.
+#[macro_export]
+macro_rules! journal {
+    ($($arg:tt)+) => {
+        ::log::info!(target: $crate::statlogger::JOURNAL_TARGET, $($arg)+)
+    };
+}
+
+/// Forwards to the ESP-IDF console logger, tees journal-worthy records and
+/// hands everything to the RAM rings and the live tails.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        ESP_LOGGER.enabled(metadata) || tail::wants(metadata.level())
+    }
+
+    fn log(&self, record: &Record) {
+        // Applies the per-target levels, so a raised tail stays off the console.
+        ESP_LOGGER.log(record);
+        #[cfg(feature = "storage")]
+        if flash_journal::should_record(record) {
+            flash_journal::append(record);
+        }
+        ring::append(record);
+        tail::publish(record);
+    }
+
+    fn flush(&self) {
+        ESP_LOGGER.flush();
+    }
+}
 
-pub fn log_heap_info() {
+/// Replaces `EspLogger::initialize_default`.
+pub fn init_logger() {
+    let _ = ring::resize(PressureLevel::Normal);
+    if log::set_logger(&LOGGER).is_ok() {
+        ESP_LOGGER.initialize();
+    }
+}
+
+/// `log <target> <level>` changes one target's level at runtime, e.g.
+/// `miwear::<addr>` for a single watch session; `log tail` shows or sets
+/// the live tail's rate.
+pub fn register_commands() {
+    crate::console::register(
+        "log",
+        "<target> <off|error|warn|info|debug|trace>: set a log target's level; tail [<lines/s>]: live tail rate; ring: RAM log accounting",
+        |args| match args {
+            ["ring"] => {
+                let report = ring::ring_stats();
+                Ok([("main", report.main), ("errors", report.errors)]
+                    .iter()
+                    .map(|(name, stats)| {
+                        format!(
+                            "{name}: {stats}; {}/{} bytes, {} written, {} wraps, E/W/I/D/T {:?}",
+                            stats.used,
+                            stats.capacity,
+                            stats.bytes_written,
+                            stats.wraps,
+                            stats.per_level
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            ["tail"] => {
+                let (clients, raised) = tail::status();
+                let raised = raised
+                    .map(|level| format!(", level raised to {level}"))
+                    .unwrap_or_default();
+                Ok(format!(
+                    "{clients} tail clients at up to {} lines/s{raised}",
+                    tail::rate()
+                ))
+            }
+            ["tail", rate] => {
+                let rate: u32 = rate
+                    .parse()
+                    .ok()
+                    .filter(|rate| *rate > 0)
+                    .ok_or_else(|| anyhow!("not a positive number: {rate}"))?;
+                settings::set(&tail::RATE, &rate)?;
+                Ok(format!("log tails get up to {rate} lines/s"))
+            }
+            [target, level] => {
+                let filter: LevelFilter = level
+                    .parse()
+                    .map_err(|_| anyhow!("unknown level: {level}"))?;
+                ESP_LOGGER
+                    .set_target_level(*target, filter)
+                    .map_err(|err| anyhow!("set level of {target}: {err}"))?;
+                Ok(format!("{target} logs at {filter}"))
+            }
+            _ => bail!("usage: log <target> <level> | log tail [<lines/s>] | log ring"),
+        },
+    );
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HeapSnapshot {
+    pub internal: usize,
+    pub dma: usize,
+    pub eight_bit: usize,
+    pub psram: usize,
+    pub internal_largest: usize,
+    pub psram_largest: usize,
+}
+
+pub fn heap_snapshot() -> HeapSnapshot {
     unsafe {
-        let free_int = heap_caps_get_free_size(MALLOC_CAP_INTERNAL as u32);
-        let free_dma = heap_caps_get_free_size(MALLOC_CAP_DMA as u32);
-        let free_8bit = heap_caps_get_free_size(MALLOC_CAP_8BIT as u32);
-        let free_ps = heap_caps_get_free_size(MALLOC_CAP_SPIRAM as u32);
-        info!(
-            "FREE internal={} dma={} 8bit={} psram={}",
-            free_int, free_dma, free_8bit, free_ps
-        );
+        HeapSnapshot {
+            internal: heap_caps_get_free_size(MALLOC_CAP_INTERNAL as u32),
+            dma: heap_caps_get_free_size(MALLOC_CAP_DMA as u32),
+            eight_bit: heap_caps_get_free_size(MALLOC_CAP_8BIT as u32),
+            psram: heap_caps_get_free_size(MALLOC_CAP_SPIRAM as u32),
+            internal_largest: heap_caps_get_largest_free_block(MALLOC_CAP_INTERNAL as u32),
+            psram_largest: heap_caps_get_largest_free_block(MALLOC_CAP_SPIRAM as u32),
+        }
+    }
+}
+
+pub fn uptime() -> Duration {
+    let micros = unsafe { esp_timer_get_time() };
+    Duration::from_micros(micros.max(0) as u64)
+}
+
+/// Logs the heap line and feeds `heap_monitor`; called once a second.
+pub fn log_heap_info(fps: f32) {
+    let heap = heap_snapshot();
+    heap_monitor::record(heap_monitor::Sample {
+        internal_free: heap.internal,
+        fps,
+    });
+    let temp = crate::sensors::temperature::current_celsius()
+        .map(|celsius| format!("{celsius:.1}C"))
+        .unwrap_or_else(|| "--".to_string());
+    info!(
+        "FREE internal={} dma={} 8bit={} psram={} temp={}",
+        heap.internal, heap.dma, heap.eight_bit, heap.psram, temp
+    );
+}
+
+/// One letter per line in the journal and the rings; `J` marks `journal!`.
+fn level_letter(level: Level, target: &str) -> char {
+    match level {
+        Level::Error => 'E',
+        Level::Warn => 'W',
+        _ if target == JOURNAL_TARGET => 'J',
+        Level::Info => 'I',
+        Level::Debug => 'D',
+        Level::Trace => 'T',
     }
 }
@@ -0,0 +1,252 @@
+//! Warn/error records and `journal!` events kept on LittleFS so they survive
+//! a crash. Lines are buffered in RAM and written in batches by a background
+//! thread into two alternating files. This code runs inside the logger, so it
+//! must never log through `log` itself.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use log::{Level, Record};
+
+use crate::storage;
+
+/// Per file; the journal holds at most two, so 64 KB in total.
+const FILE_CAP: usize = 32 * 1024;
+const ACTIVE_FILE: &str = "/storage/journal0.log";
+const PREVIOUS_FILE: &str = "/storage/journal1.log";
+const FLUSH_BYTES: usize = 4 * 1024;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+/// RAM held until the first flush (or while writes fail); oldest lines go first.
+const PENDING_CAP: usize = 8 * 1024;
+/// Cap and flush threshold while `memory::pressure` has the buffer shed.
+const LOW_MEMORY_CAP: usize = 2 * 1024;
+/// The panic hook writes at most this much, so it cannot hold off the reset
+/// for more than a flash erase or two.
+const PANIC_WRITE_CAP: usize = 2 * 1024;
+/// Left free on the partition for whatever else lives there.
+const FS_RESERVE: usize = 8 * 1024;
+const STACK_SIZE: usize = 4 * 1024;
+
+static PENDING: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static FLUSH_DUE: Condvar = Condvar::new();
+/// Serializes file access between the flusher, readers and the panic hook.
+static FILES: Mutex<()> = Mutex::new(());
+static STARTED: AtomicBool = AtomicBool::new(false);
+static DROPPED_LINES: AtomicU32 = AtomicU32::new(0);
+static WRITE_FAILURES: AtomicU32 = AtomicU32::new(0);
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug)]
+pub struct JournalStats {
+    pub dropped_lines: u32,
+    pub write_failures: u32,
+}
+
+pub fn stats() -> JournalStats {
+    JournalStats {
+        dropped_lines: DROPPED_LINES.load(Ordering::Relaxed),
+        write_failures: WRITE_FAILURES.load(Ordering::Relaxed),
+    }
+}
+
+pub fn should_record(record: &Record) -> bool {
+    record.level() <= Level::Warn || record.target() == super::JOURNAL_TARGET
+}
+
+pub fn append(record: &Record) {
+    let line = format!(
+        "[{:>10.3}] {} {}: {}\n",
+        super::uptime().as_secs_f32(),
+        super::level_letter(record.level(), record.target()),
+        record.target(),
+        record.args()
+    );
+    let Ok(mut pending) = PENDING.lock() else {
+        return;
+    };
+    pending.extend_from_slice(line.as_bytes());
+    trim_front(&mut pending, pending_cap());
+    if pending.len() >= flush_bytes() {
+        FLUSH_DUE.notify_one();
+    }
+}
+
+/// Starts the flusher. Lines logged before this point are already buffered
+/// and go out with the first batch.
+pub fn start() -> Result<()> {
+    if !storage::mounted() {
+        anyhow::bail!("storage is not mounted");
+    }
+    install_panic_hook();
+    thread::Builder::new()
+        .name("journal".into())
+        .stack_size(STACK_SIZE)
+        .spawn(flush_loop)
+        .context("spawn journal thread")?;
+    STARTED.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Both files, oldest first, followed by lines not yet flushed.
+pub fn read_all() -> String {
+    let mut text = String::new();
+    if storage::mounted() {
+        if let Ok(_files) = FILES.lock() {
+            for path in [PREVIOUS_FILE, ACTIVE_FILE] {
+                if let Ok(bytes) = fs::read(path) {
+                    text.push_str(&String::from_utf8_lossy(&bytes));
+                }
+            }
+        }
+    }
+    if let Ok(pending) = PENDING.lock() {
+        text.push_str(&String::from_utf8_lossy(&pending));
+    }
+    text
+}
+
+fn flush_loop() {
+    loop {
+        let batch = {
+            let Ok(pending) = PENDING.lock() else {
+                return;
+            };
+            let Ok((mut pending, _)) =
+                FLUSH_DUE.wait_timeout_while(pending, FLUSH_INTERVAL, |pending| {
+                    pending.len() < flush_bytes()
+                })
+            else {
+                return;
+            };
+            mem::take(&mut *pending)
+        };
+        persist(batch);
+    }
+}
+
+/// Writes buffered lines now, for shutdown paths that do not come back.
+pub fn flush() {
+    if !STARTED.load(Ordering::Acquire) {
+        return;
+    }
+    let batch = PENDING
+        .lock()
+        .map(|mut pending| mem::take(&mut *pending))
+        .unwrap_or_default();
+    persist(batch);
+}
+
+/// Shrinks the RAM buffer to [`LOW_MEMORY_CAP`] and flushes it at that size,
+/// or goes back to the normal limits.
+pub fn set_low_memory(low: bool) {
+    LOW_MEMORY.store(low, Ordering::Relaxed);
+    if low {
+        if let Ok(mut pending) = PENDING.lock() {
+            trim_front(&mut pending, LOW_MEMORY_CAP);
+            pending.shrink_to_fit();
+        }
+    }
+}
+
+fn pending_cap() -> usize {
+    if LOW_MEMORY.load(Ordering::Relaxed) {
+        LOW_MEMORY_CAP
+    } else {
+        PENDING_CAP
+    }
+}
+
+fn flush_bytes() -> usize {
+    FLUSH_BYTES.min(pending_cap())
+}
+
+fn persist(batch: Vec<u8>) {
+    if batch.is_empty() {
+        return;
+    }
+    let written = FILES
+        .lock()
+        .map_err(|_| io::Error::other("journal lock poisoned"))
+        .and_then(|_files| write_batch(&batch));
+    if written.is_err() {
+        WRITE_FAILURES.fetch_add(1, Ordering::Relaxed);
+        // Keep the lines for the next attempt, within the RAM cap.
+        if let Ok(mut pending) = PENDING.lock() {
+            let newer = mem::replace(&mut *pending, batch);
+            pending.extend_from_slice(&newer);
+            trim_front(&mut pending, pending_cap());
+        }
+    }
+}
+
+/// Caller holds `FILES`.
+fn write_batch(batch: &[u8]) -> io::Result<()> {
+    let active_len = fs::metadata(ACTIVE_FILE)
+        .map(|meta| meta.len() as usize)
+        .unwrap_or(0);
+    if active_len + batch.len() > FILE_CAP {
+        let _ = fs::remove_file(PREVIOUS_FILE);
+        fs::rename(ACTIVE_FILE, PREVIOUS_FILE)?;
+    }
+    if storage::usage().is_some_and(|usage| usage.free() < batch.len() + FS_RESERVE) {
+        return Err(io::Error::other("filesystem full"));
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(ACTIVE_FILE)?
+        .write_all(batch)
+}
+
+/// Writes buffered lines plus the panic message before the previous hook
+/// runs. Never waits on a lock: if the flusher is mid-write the panic is only
+/// printed.
+fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if STARTED.load(Ordering::Acquire) {
+            let mut batch = PENDING
+                .try_lock()
+                .map(|mut pending| mem::take(&mut *pending))
+                .unwrap_or_default();
+            batch.extend_from_slice(
+                format!(
+                    "[{:>10.3}] P panic: {info}\n",
+                    super::uptime().as_secs_f32()
+                )
+                .as_bytes(),
+            );
+            trim_front(&mut batch, PANIC_WRITE_CAP);
+            if let Ok(_files) = FILES.try_lock() {
+                let _ = write_batch(&batch);
+            }
+        }
+        previous(info);
+    }));
+}
+
+/// Drops whole lines from the front until `buf` fits in `cap`.
+fn trim_front(buf: &mut Vec<u8>, cap: usize) {
+    if buf.len() <= cap {
+        return;
+    }
+    let excess = buf.len() - cap;
+    let cut = buf[excess..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map(|offset| excess + offset + 1)
+        .unwrap_or(buf.len());
+    let dropped = buf[..cut].iter().filter(|&&byte| byte == b'\n').count();
+    buf.drain(..cut);
+    DROPPED_LINES.fetch_add(dropped as u32, Ordering::Relaxed);
+}
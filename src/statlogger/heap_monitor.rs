@@ -0,0 +1,65 @@
+//! One sample per second of internal heap and FPS, kept for five minutes.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+pub const CAPACITY: usize = 300;
+
+static SAMPLES: Mutex<VecDeque<Sample>> = Mutex::new(VecDeque::new());
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub internal_free: usize,
+    pub fps: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    InternalHeap,
+    Fps,
+}
+
+impl Metric {
+    pub fn next(self) -> Self {
+        match self {
+            Metric::InternalHeap => Metric::Fps,
+            Metric::Fps => Metric::InternalHeap,
+        }
+    }
+
+    fn value(self, sample: &Sample) -> f32 {
+        match self {
+            Metric::InternalHeap => sample.internal_free as f32 / 1024.0,
+            Metric::Fps => sample.fps,
+        }
+    }
+}
+
+pub fn record(sample: Sample) {
+    if let Ok(mut samples) = SAMPLES.lock() {
+        if samples.len() == CAPACITY {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Oldest first, in display units (KB for heap).
+pub fn series(metric: Metric) -> Vec<f32> {
+    SAMPLES
+        .lock()
+        .map(|samples| samples.iter().map(|sample| metric.value(sample)).collect())
+        .unwrap_or_default()
+}
+
+/// Bumped per sample so the chart only redraws when there is new data.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
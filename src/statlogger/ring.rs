@@ -0,0 +1,257 @@
+//! Recent log lines in RAM: a main ring of everything the logger is handed,
+//! and a small errors-only ring that debug spam cannot flush out, so the
+//! last warnings and errors survive any verbosity. Both keep accounting of
+//! what went through them and what they had to drop.
+//!
+//! The byte buffers are single allocations placed in PSRAM when there is
+//! some. Capacities follow `memory::pressure`: each level shrinks
+//! the rings and the way back down restores them. This runs inside the
+//! logger, so it must never log through `log` itself.
+
+use std::{collections::VecDeque, fmt, sync::Mutex, time::Duration};
+
+use anyhow::Result;
+use log::{Level, Record};
+
+use crate::{
+    allocator::{self, Placement},
+    memory::pressure::PressureLevel,
+};
+
+/// Keeps the last this many warnings and errors, space permitting.
+const ERRORS_MAX_LINES: usize = 50;
+const LEVELS: usize = 5;
+
+static MAIN: Mutex<Ring> = Mutex::new(Ring::new(None));
+static ERRORS: Mutex<Ring> = Mutex::new(Ring::new(Some(ERRORS_MAX_LINES)));
+
+/// Main ring capacity in bytes at `level`; off in an emergency.
+fn main_capacity(level: PressureLevel) -> usize {
+    match level {
+        PressureLevel::Normal if allocator::psram_available() => 64 * 1024,
+        PressureLevel::Normal | PressureLevel::Warning => 16 * 1024,
+        PressureLevel::Critical => 4 * 1024,
+        PressureLevel::Emergency => 0,
+    }
+}
+
+fn errors_capacity(level: PressureLevel) -> usize {
+    match level {
+        PressureLevel::Normal | PressureLevel::Warning => 4 * 1024,
+        PressureLevel::Critical => 2 * 1024,
+        PressureLevel::Emergency => 1024,
+    }
+}
+
+/// When one line in the byte ring was logged, and its length.
+struct Line {
+    at: Duration,
+    len: usize,
+}
+
+struct Ring {
+    bytes: VecDeque<u8>,
+    lines: VecDeque<Line>,
+    /// Zero until [`resize`] first runs, and while shed.
+    capacity: usize,
+    max_lines: Option<usize>,
+    stats: Counters,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Counters {
+    bytes_written: u64,
+    lines_written: u32,
+    per_level: [u32; LEVELS],
+    dropped_lines: u32,
+    /// Lines too long for the whole ring, or written while it was off.
+    rejected_lines: u32,
+    wraps: u32,
+    /// Evicted since the last wrap was counted.
+    evicted_bytes: usize,
+}
+
+impl Ring {
+    const fn new(max_lines: Option<usize>) -> Self {
+        Self {
+            bytes: VecDeque::new(),
+            lines: VecDeque::new(),
+            capacity: 0,
+            max_lines,
+            stats: Counters {
+                bytes_written: 0,
+                lines_written: 0,
+                per_level: [0; LEVELS],
+                dropped_lines: 0,
+                rejected_lines: 0,
+                wraps: 0,
+                evicted_bytes: 0,
+            },
+        }
+    }
+
+    fn push(&mut self, level: Level, at: Duration, text: &[u8]) {
+        self.stats.lines_written += 1;
+        self.stats.per_level[level as usize - 1] += 1;
+        if text.len() > self.capacity {
+            self.stats.rejected_lines += 1;
+            return;
+        }
+        self.make_room(text.len());
+        self.bytes.extend(text);
+        self.lines.push_back(Line {
+            at,
+            len: text.len(),
+        });
+        self.stats.bytes_written += text.len() as u64;
+    }
+
+    /// Evicts the oldest lines until `incoming` more bytes and one more
+    /// line fit. A full ring's worth of evicted bytes counts as one wrap.
+    fn make_room(&mut self, incoming: usize) {
+        let max_lines = self.max_lines.unwrap_or(usize::MAX);
+        while self.bytes.len() + incoming > self.capacity || self.lines.len() >= max_lines {
+            let Some(oldest) = self.lines.pop_front() else {
+                break;
+            };
+            self.bytes.drain(..oldest.len);
+            self.stats.dropped_lines += 1;
+            self.stats.evicted_bytes += oldest.len;
+            if self.capacity > 0 && self.stats.evicted_bytes >= self.capacity {
+                self.stats.evicted_bytes -= self.capacity;
+                self.stats.wraps += 1;
+            }
+        }
+    }
+
+    /// Trims to `capacity` and gives back what the old size reserved.
+    fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.make_room(0);
+        self.bytes.shrink_to(capacity);
+        self.lines.shrink_to_fit();
+        if self.bytes.capacity() < capacity {
+            let additional = capacity - self.bytes.len();
+            allocator::with_placement(Placement::Psram, || self.bytes.reserve_exact(additional));
+        }
+    }
+
+    fn text(&self) -> String {
+        let (front, back) = self.bytes.as_slices();
+        let mut text = String::from_utf8_lossy(front).into_owned();
+        text.push_str(&String::from_utf8_lossy(back));
+        text
+    }
+
+    fn stats(&self, now: Duration) -> RingStats {
+        RingStats {
+            capacity: self.capacity,
+            used: self.bytes.len(),
+            lines: self.lines.len(),
+            oldest_age: self.lines.front().map(|line| now.saturating_sub(line.at)),
+            bytes_written: self.stats.bytes_written,
+            lines_written: self.stats.lines_written,
+            per_level: self.stats.per_level,
+            dropped_lines: self.stats.dropped_lines,
+            rejected_lines: self.stats.rejected_lines,
+            wraps: self.stats.wraps,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RingStats {
+    /// Bytes the ring may hold at the current pressure level.
+    pub capacity: usize,
+    pub used: usize,
+    /// Lines held now.
+    pub lines: usize,
+    pub oldest_age: Option<Duration>,
+    pub bytes_written: u64,
+    pub lines_written: u32,
+    /// Lines written per level, error first.
+    pub per_level: [u32; LEVELS],
+    /// Evicted to make room for newer lines.
+    pub dropped_lines: u32,
+    pub rejected_lines: u32,
+    pub wraps: u32,
+}
+
+/// The header line: how far back the ring reaches, e.g.
+/// "last 4m32s, 1.2k lines, 37 dropped".
+impl fmt::Display for RingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.oldest_age.unwrap_or_default().as_secs();
+        f.write_str("last ")?;
+        if secs >= 60 {
+            write!(f, "{}m{:02}s", secs / 60, secs % 60)?;
+        } else {
+            write!(f, "{secs}s")?;
+        }
+        if self.lines >= 1000 {
+            write!(f, ", {:.1}k lines", self.lines as f32 / 1000.0)?;
+        } else {
+            write!(f, ", {} lines", self.lines)?;
+        }
+        write!(f, ", {} dropped", self.dropped_lines)
+    }
+}
+
+/// Main and errors-only ring accounting.
+#[derive(Clone, Copy, Debug)]
+pub struct RingReport {
+    pub main: RingStats,
+    pub errors: RingStats,
+}
+
+pub fn ring_stats() -> RingReport {
+    let now = super::uptime();
+    let stats = |ring: &Mutex<Ring>| ring.lock().map(|ring| ring.stats(now)).ok();
+    let empty = Ring::new(None).stats(now);
+    RingReport {
+        main: stats(&MAIN).unwrap_or(empty),
+        errors: stats(&ERRORS).unwrap_or(empty),
+    }
+}
+
+/// Called by the logger for every record it is handed.
+pub fn append(record: &Record) {
+    let at = super::uptime();
+    let line = format!(
+        "[{:>10.3}] {} {}: {}\n",
+        at.as_secs_f32(),
+        super::level_letter(record.level(), record.target()),
+        record.target(),
+        record.args()
+    );
+    if let Ok(mut ring) = MAIN.lock() {
+        ring.push(record.level(), at, line.as_bytes());
+    }
+    if record.level() <= Level::Warn {
+        if let Ok(mut ring) = ERRORS.lock() {
+            ring.push(record.level(), at, line.as_bytes());
+        }
+    }
+}
+
+/// Sizes both rings for `level`. Runs once at boot with `Normal` and then
+/// from the pressure sheds.
+pub fn resize(level: PressureLevel) -> Result<()> {
+    if let Ok(mut ring) = MAIN.lock() {
+        ring.resize(main_capacity(level));
+    }
+    if let Ok(mut ring) = ERRORS.lock() {
+        ring.resize(errors_capacity(level));
+    }
+    Ok(())
+}
+
+/// The main ring's lines, oldest first.
+pub fn read_recent() -> String {
+    MAIN.lock().map(|ring| ring.text()).unwrap_or_default()
+}
+
+/// The errors-only ring's lines, oldest first.
+pub fn read_errors() -> String {
+    ERRORS.lock().map(|ring| ring.text()).unwrap_or_default()
+}
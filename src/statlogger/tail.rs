@@ -0,0 +1,134 @@
+//! Live copy of log records for `/events` tails, taken after the journal
+//! tee. Nothing is formatted while no tail is connected.
+//!
+//! A tail asking for more detail than `log::max_level()` lets through
+//! raises that ceiling while it lasts, so `debug` works without touching
+//! the per-target levels: the extra records reach the tails only, and the
+//! console shows what it showed before. One tail at a time may do that.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use log::{Level, LevelFilter, Record};
+use tokio::sync::broadcast::{self, error::TryRecvError, Receiver, Sender};
+
+use crate::settings::{self, SettingKey};
+
+/// Lines per second each tail client may receive; `log tail <n>` sets it.
+pub const RATE: SettingKey<u32> = SettingKey::new("log_tail_rate", "20");
+
+/// Records a tail may fall behind by before it loses the oldest.
+const CAPACITY: usize = 64;
+
+static SENDER: OnceLock<Sender<Arc<LogLine>>> = OnceLock::new();
+/// The raising tail's level as a `LevelFilter` index; 0 while none raises.
+static RAISED: AtomicUsize = AtomicUsize::new(0);
+/// `log::max_level()` from before the raise, put back when the tail ends.
+static BASELINE: Mutex<Option<LevelFilter>> = Mutex::new(None);
+
+#[derive(Debug)]
+pub struct LogLine {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    pub at: SystemTime,
+    pub uptime: Duration,
+}
+
+pub fn rate() -> u32 {
+    settings::get(&RATE).max(1)
+}
+
+/// Tails that are connected, and whether one of them raised the level.
+pub fn status() -> (usize, Option<LevelFilter>) {
+    let raised = match RAISED.load(Ordering::Relaxed) {
+        0 => None,
+        level => LevelFilter::iter().nth(level),
+    };
+    (sender().receiver_count(), raised)
+}
+
+fn sender() -> &'static Sender<Arc<LogLine>> {
+    SENDER.get_or_init(|| broadcast::channel(CAPACITY).0)
+}
+
+/// Records below the console's levels that a raising tail still wants.
+pub fn wants(level: Level) -> bool {
+    level as usize <= RAISED.load(Ordering::Relaxed)
+}
+
+/// Called by the logger for every record it is handed.
+pub fn publish(record: &Record) {
+    let sender = sender();
+    if sender.receiver_count() == 0 {
+        return;
+    }
+    let line = LogLine {
+        level: record.level(),
+        target: record.target().to_string(),
+        message: record.args().to_string(),
+        at: SystemTime::now(),
+        uptime: super::uptime(),
+    };
+    // Only fails when the last tail left in the meantime.
+    let _ = sender.send(Arc::new(line));
+}
+
+/// One client's view of the records at `min` and above.
+pub struct Tail {
+    rx: Receiver<Arc<LogLine>>,
+    min: LevelFilter,
+    raises: bool,
+}
+
+impl Tail {
+    /// `None` when `min` would raise the ceiling while another tail already
+    /// does.
+    pub fn open(min: LevelFilter) -> Option<Self> {
+        let rx = sender().subscribe();
+        let raises = min > log::max_level();
+        if raises {
+            RAISED
+                .compare_exchange(0, min as usize, Ordering::Relaxed, Ordering::Relaxed)
+                .ok()?;
+            if let Ok(mut baseline) = BASELINE.lock() {
+                *baseline = Some(log::max_level());
+            }
+            log::set_max_level(min);
+            log::info!("Log tail raised the level to {min}");
+        }
+        Some(Self { rx, min, raises })
+    }
+
+    /// The next record at or above this tail's level. `Lagged` counts the
+    /// records lost to a full channel.
+    pub fn try_recv(&mut self) -> Result<Arc<LogLine>, TryRecvError> {
+        loop {
+            let line = self.rx.try_recv()?;
+            if line.level <= self.min {
+                return Ok(line);
+            }
+        }
+    }
+}
+
+impl Drop for Tail {
+    fn drop(&mut self) {
+        if !self.raises {
+            return;
+        }
+        let baseline = BASELINE
+            .lock()
+            .ok()
+            .and_then(|mut baseline| baseline.take())
+            .unwrap_or(LevelFilter::Info);
+        log::set_max_level(baseline);
+        RAISED.store(0, Ordering::Relaxed);
+        log::info!("Log tail ended, level back to {baseline}");
+    }
+}
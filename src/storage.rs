@@ -0,0 +1,65 @@
+//! LittleFS on the `storage` data partition, mounted at `/storage`. Images
+//! flashed with a partition table that lacks the partition simply run
+//! without it; callers check `mounted()` first.
+
+use std::{
+    ffi::CStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{bail, Result};
+use esp_idf_svc::sys::littlefs::{
+    esp_littlefs_info, esp_vfs_littlefs_conf_t, esp_vfs_littlefs_register,
+};
+use log::info;
+
+pub const MOUNT_POINT: &str = "/storage";
+const MOUNT_POINT_C: &CStr = c"/storage";
+const PARTITION_LABEL: &CStr = c"storage";
+
+static MOUNTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Usage {
+    pub total: usize,
+    pub used: usize,
+}
+
+impl Usage {
+    pub fn free(&self) -> usize {
+        self.total.saturating_sub(self.used)
+    }
+}
+
+/// Mounts the partition, formatting it if it has never held a filesystem.
+pub fn mount() -> Result<()> {
+    let mut conf: esp_vfs_littlefs_conf_t = unsafe { core::mem::zeroed() };
+    conf.base_path = MOUNT_POINT_C.as_ptr();
+    conf.partition_label = PARTITION_LABEL.as_ptr();
+    conf.set_format_if_mount_failed(1);
+    let rc = unsafe { esp_vfs_littlefs_register(&conf) };
+    if rc != 0 {
+        bail!("LittleFS mount on partition \"storage\" failed (err={rc})");
+    }
+    MOUNTED.store(true, Ordering::Release);
+    if let Some(usage) = usage() {
+        info!(
+            "LittleFS mounted at {MOUNT_POINT}: {} / {} bytes used",
+            usage.used, usage.total
+        );
+    }
+    Ok(())
+}
+
+pub fn mounted() -> bool {
+    MOUNTED.load(Ordering::Acquire)
+}
+
+pub fn usage() -> Option<Usage> {
+    if !mounted() {
+        return None;
+    }
+    let (mut total, mut used) = (0usize, 0usize);
+    let rc = unsafe { esp_littlefs_info(PARTITION_LABEL.as_ptr(), &mut total, &mut used) };
+    (rc == 0).then_some(Usage { total, used })
+}
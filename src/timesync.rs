@@ -0,0 +1,313 @@
+//! Where the wall clock comes from. Sources, best first: SNTP over Wi-Fi,
+//! then the last time persisted to NVS, resumed after a power cycle and
+//! stale by however long the unit was off. A source only replaces the
+//! active one if it ranks at least as high.
+//!
+//! SNTP runs in smooth mode, so small corrections are slewed with
+//! `adjtime` and only large ones step the clock; a step is logged and
+//! journaled.
+//!
+//! The clock and everything stored stays in UTC; [`now_local`] applies the
+//! [`TIMEZONE`] rules, DST included, for what the user reads.
+
+pub mod zones;
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use esp_idf_svc::sntp::{EspSntp, SntpConf, SyncMode};
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+
+use self::zones::{LocalTime, Zone};
+use crate::{
+    events::{self, SystemEvent},
+    metrics, periodic,
+    settings::{self, SettingKey},
+};
+
+const NVS_NAMESPACE: &str = "timesync";
+const NVS_LAST_KEY: &str = "last";
+/// The fixed offset setting [`TIMEZONE`] replaced.
+const LEGACY_OFFSET_KEY: &str = "ui_utc_offset";
+const PERSIST_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Typical SNTP accuracy on a home network.
+const SNTP_ERROR: Duration = Duration::from_millis(100);
+/// Worst-case drift of the free-running clock between syncs.
+const DRIFT_PPM: u64 = 50;
+/// Smooth mode steps instead of slewing beyond this (ESP-IDF's limit).
+const SLEW_LIMIT: Duration = Duration::from_secs(35 * 60);
+
+/// IANA name of a zone in the built-in table, see `timezones.txt`.
+pub const TIMEZONE: SettingKey<String> =
+    SettingKey::new("timezone", zones::UTC).validated(|name| {
+        if zones::find(name).is_some() {
+            Ok(())
+        } else {
+            Err(format!("{name} is not a built-in zone, see `tz list`"))
+        }
+    });
+
+static ACTIVE: Mutex<Option<(TimeSource, Instant)>> = Mutex::new(None);
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TimeSource {
+    /// The last persisted time, from before the last power cycle.
+    Resumed,
+    Sntp,
+}
+
+impl fmt::Display for TimeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TimeSource::Resumed => "resumed",
+            TimeSource::Sntp => "sntp",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SourceInfo {
+    /// `None` while the clock is unset.
+    pub source: Option<TimeSource>,
+    /// Time since the source last set the clock.
+    pub age: Option<Duration>,
+    /// Upper bound on the clock error; `None` when it cannot be bounded,
+    /// as for a resumed time.
+    pub error: Option<Duration>,
+}
+
+pub fn source() -> SourceInfo {
+    let active = ACTIVE.lock().ok().and_then(|slot| *slot);
+    let Some((source, at)) = active else {
+        return SourceInfo {
+            source: None,
+            age: None,
+            error: None,
+        };
+    };
+    let age = at.elapsed();
+    let error = match source {
+        TimeSource::Sntp => {
+            let drift = Duration::from_micros(age.as_secs() * DRIFT_PPM);
+            Some(SNTP_ERROR + drift)
+        }
+        TimeSource::Resumed => None,
+    };
+    SourceInfo {
+        source: Some(source),
+        age: Some(age),
+        error,
+    }
+}
+
+/// Bumped whenever a source sets the clock.
+pub fn generation() -> u32 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// The configured zone; UTC when it was left out of this build.
+pub fn zone() -> &'static Zone {
+    zones::find(&settings::get(&TIMEZONE)).unwrap_or_else(zones::utc)
+}
+
+/// Seconds since the epoch; `None` until the clock has been set.
+pub fn unix_now() -> Option<i64> {
+    metrics::today()?;
+    Some(unix_secs() as i64)
+}
+
+/// Local time now; `None` until the clock has been set.
+pub fn now_local() -> Option<LocalTime> {
+    unix_now().map(|now| zone().local(now))
+}
+
+/// Starts SNTP. Keep the returned handle alive; dropping it stops SNTP.
+pub fn start() -> Result<EspSntp<'static>> {
+    let conf = SntpConf {
+        sync_mode: SyncMode::Smooth,
+        ..Default::default()
+    };
+    // Where the wall clock should be if nothing had touched it; a step
+    // shows up as a large difference from it.
+    let mut base = (SystemTime::now(), Instant::now());
+    let sntp = EspSntp::new_with_callback(&conf, move |synced| {
+        let now = SystemTime::now();
+        let expected = base.0 + base.1.elapsed();
+        let jump = now
+            .duration_since(expected)
+            .or_else(|_| expected.duration_since(now));
+        if jump.map_or(true, |jump| jump >= SLEW_LIMIT) {
+            crate::journal!("Clock stepped by SNTP to {}s", synced.as_secs());
+        }
+        base = (now, Instant::now());
+        adopt(TimeSource::Sntp);
+    })?;
+    info!("SNTP started (smooth sync)");
+    Ok(sntp)
+}
+
+/// Persists the clock every [`PERSIST_INTERVAL`] and before shutdown.
+/// Spawn once on the main `LocalSet`.
+pub async fn run() {
+    periodic::register("timesync", PERSIST_INTERVAL, persist);
+    let mut events = events::subscribe();
+    loop {
+        match events.recv().await {
+            Ok(SystemEvent::ShuttingDown { .. }) => persist(),
+            Err(RecvError::Closed) => return,
+            _ => {}
+        }
+    }
+}
+
+fn persist() {
+    if metrics::today().is_some() {
+        crate::nvs::write(NVS_NAMESPACE, NVS_LAST_KEY, unix_secs().to_string());
+    }
+}
+
+/// Sets the clock to the last persisted time if nothing has set it yet.
+/// Call once settings are up and before anything dates its records.
+pub fn resume() {
+    migrate_offset();
+    if metrics::today().is_some() {
+        return;
+    }
+    let store = match settings::open_namespace(NVS_NAMESPACE) {
+        Ok(store) => store,
+        Err(err) => {
+            warn!("Persisted time not restored: {err:#}");
+            return;
+        }
+    };
+    let mut buf = [0u8; 24];
+    let saved = match store.get_str(NVS_LAST_KEY, &mut buf) {
+        Ok(Some(raw)) => raw.parse::<u64>().ok(),
+        Ok(None) => None,
+        Err(err) => {
+            warn!("Persisted time unreadable: {err:?}");
+            None
+        }
+    };
+    let Some(secs) = saved else {
+        info!("No persisted time; clock unset until SNTP");
+        return;
+    };
+    let tv = esp_idf_svc::sys::timeval {
+        tv_sec: secs as _,
+        tv_usec: 0,
+    };
+    if unsafe { esp_idf_svc::sys::settimeofday(&tv, std::ptr::null()) } != 0 {
+        warn!("Could not resume the persisted time");
+        return;
+    }
+    // Behind by however long the unit was off; SNTP steps it forward.
+    crate::journal!("Clock resumed from the last persisted time ({secs}s), stale");
+    adopt(TimeSource::Resumed);
+}
+
+/// `ui_utc_offset`, minutes east of UTC for the `auto` theme, came before
+/// [`TIMEZONE`]. It becomes the first zone with that standard offset,
+/// preferring one without DST, since the offset alone cannot say which.
+fn migrate_offset() {
+    let Some(raw) = settings::take_stored(LEGACY_OFFSET_KEY) else {
+        return;
+    };
+    let minutes = raw.parse::<i32>().unwrap_or(0);
+    if minutes == 0 {
+        return;
+    }
+    let matching = || {
+        zones::ZONES
+            .iter()
+            .filter(move |zone| zone.std_offset() == minutes)
+    };
+    let Some(zone) = matching()
+        .find(|zone| !zone.observes_dst())
+        .or_else(|| matching().next())
+    else {
+        warn!("No built-in zone at UTC offset {minutes} min; time zone left at UTC");
+        return;
+    };
+    match settings::set(&TIMEZONE, &zone.name.to_string()) {
+        Ok(()) => info!(
+            "Time zone {} chosen for the old UTC offset of {minutes} min",
+            zone.name
+        ),
+        Err(err) => warn!("Failed to save the time zone: {err:#}"),
+    }
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "tz",
+        "[list [<region>] | <zone>]: time zone for local times",
+        |args| match args {
+            [] => {
+                let zone = zone();
+                Ok(match unix_now() {
+                    Some(now) => {
+                        let local = zone.local(now);
+                        format!(
+                            "{} ({}), {local}",
+                            zone.name,
+                            zones::offset_label(local.offset)
+                        )
+                    }
+                    None => format!("{}, clock unset", zone.name),
+                })
+            }
+            ["list"] => Ok(zones::regions().join(" ")),
+            ["list", region] => {
+                let names: Vec<&str> = zones::in_region(region).map(|zone| zone.name).collect();
+                if names.is_empty() {
+                    bail!(
+                        "no region {region}; regions: {}",
+                        zones::regions().join(" ")
+                    );
+                }
+                Ok(names.join(" "))
+            }
+            [name] => {
+                let zone = zones::find(name).ok_or_else(|| {
+                    anyhow::anyhow!("{name} is not a built-in zone, see `tz list`")
+                })?;
+                settings::set(&TIMEZONE, &zone.name.to_string())?;
+                Ok(format!("timezone={}", zone.name))
+            }
+            _ => bail!("usage: tz [list [<region>] | <zone>]"),
+        },
+    );
+}
+
+fn adopt(source: TimeSource) {
+    let Ok(mut slot) = ACTIVE.lock() else {
+        return;
+    };
+    match *slot {
+        Some((current, _)) if current > source => {}
+        _ => {
+            if slot.map(|(current, _)| current) != Some(source) {
+                info!("Time source: {source}");
+            }
+            *slot = Some((source, Instant::now()));
+            GENERATION.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
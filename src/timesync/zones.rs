@@ -0,0 +1,213 @@
+//! The built-in time zones and UTC-to-local conversion. build.rs generates
+//! [`ZONES`] from `timezones.txt`, which holds each zone's current POSIX
+//! rule as tzdata ships it; past rule changes are not kept, so only times
+//! after a zone's last change convert correctly.
+
+use std::fmt;
+
+use crate::metrics::Date;
+
+pub const UTC: &str = "UTC";
+
+pub struct Zone {
+    /// IANA name, e.g. `Europe/Berlin`.
+    pub name: &'static str,
+    /// Standard time in minutes east of UTC.
+    std: i16,
+    /// Standard and daylight abbreviations; the same twice without DST.
+    abbr: (&'static str, &'static str),
+    dst: Option<Dst>,
+}
+
+struct Dst {
+    /// Minutes added while DST is on.
+    save: i16,
+    start: Transition,
+    end: Transition,
+}
+
+/// POSIX `Mm.w.d/time`: the `week`th `weekday` of `month` (week 5 is the
+/// last, weekday 0 is Sunday), at `at` minutes of the local time in force
+/// before the change. `at` may be negative or past midnight.
+struct Transition {
+    month: u8,
+    week: u8,
+    weekday: u8,
+    at: i16,
+}
+
+include!(concat!(env!("OUT_DIR"), "/zones.rs"));
+
+/// An instant as a zone shows it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalTime {
+    pub date: Date,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Minutes east of UTC in force, DST included.
+    pub offset: i32,
+    pub dst: bool,
+    pub abbr: &'static str,
+}
+
+impl LocalTime {
+    /// The ANCS Date attribute, `yyyyMMdd'T'HHmmSS`.
+    pub fn ancs_date(&self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}",
+            self.date.year, self.date.month, self.date.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:02}:{:02}:{:02} {}",
+            self.date, self.hour, self.minute, self.second, self.abbr
+        )
+    }
+}
+
+impl Zone {
+    /// `Europe` for `Europe/Berlin`; a zone without a region is its own.
+    pub fn region(&self) -> &'static str {
+        self.name
+            .split_once('/')
+            .map_or(self.name, |(region, _)| region)
+    }
+
+    /// The last name component with spaces, e.g. `Buenos Aires`.
+    pub fn city(&self) -> String {
+        self.name
+            .rsplit('/')
+            .next()
+            .unwrap_or(self.name)
+            .replace('_', " ")
+    }
+
+    /// Standard time in minutes east of UTC.
+    pub fn std_offset(&self) -> i32 {
+        i32::from(self.std)
+    }
+
+    pub fn observes_dst(&self) -> bool {
+        self.dst.is_some()
+    }
+
+    /// `unix`, seconds since the epoch in UTC, in this zone.
+    pub fn local(&self, unix: i64) -> LocalTime {
+        let dst = self.dst.as_ref().filter(|dst| dst.active(self.std, unix));
+        let offset = i32::from(self.std) + dst.map_or(0, |dst| i32::from(dst.save));
+        let local = unix + i64::from(offset) * 60;
+        let secs = local.rem_euclid(86_400);
+        LocalTime {
+            date: Date::from_days(local.div_euclid(86_400)),
+            hour: (secs / 3_600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+            offset,
+            dst: dst.is_some(),
+            abbr: if dst.is_some() {
+                self.abbr.1
+            } else {
+                self.abbr.0
+            },
+        }
+    }
+}
+
+impl Dst {
+    fn active(&self, std: i16, unix: i64) -> bool {
+        let std = i64::from(std) * 60;
+        // Transitions never fall near New Year, so the year in standard
+        // time is the one whose rules apply.
+        let year = Date::from_days((unix + std).div_euclid(86_400)).year;
+        let start = self.start.unix(year, std);
+        let end = self.end.unix(year, std + i64::from(self.save) * 60);
+        if start < end {
+            (start..end).contains(&unix)
+        } else {
+            // Southern hemisphere: DST spans New Year.
+            !(end..start).contains(&unix)
+        }
+    }
+}
+
+impl Transition {
+    /// The change in `year` as a Unix time, given the offset in seconds in
+    /// force just before it.
+    fn unix(&self, year: i32, offset: i64) -> i64 {
+        let first = Date {
+            year,
+            month: self.month,
+            day: 1,
+        }
+        .days();
+        let next = if self.month == 12 {
+            Date {
+                year: year + 1,
+                month: 1,
+                day: 1,
+            }
+        } else {
+            Date {
+                year,
+                month: self.month + 1,
+                day: 1,
+            }
+        }
+        .days();
+        // 1970-01-01 was a Thursday.
+        let first_weekday = (first + 4).rem_euclid(7);
+        let mut day = first
+            + (i64::from(self.weekday) - first_weekday).rem_euclid(7)
+            + 7 * (i64::from(self.week) - 1);
+        while day >= next {
+            day -= 7;
+        }
+        day * 86_400 + i64::from(self.at) * 60 - offset
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static Zone> {
+    ZONES
+        .iter()
+        .find(|zone| zone.name.eq_ignore_ascii_case(name))
+}
+
+/// build.rs keeps UTC in every build.
+pub fn utc() -> &'static Zone {
+    find(UTC).expect("UTC is always built in")
+}
+
+/// Regions in table order.
+pub fn regions() -> Vec<&'static str> {
+    let mut regions: Vec<&'static str> = Vec::new();
+    for zone in ZONES {
+        if !regions.contains(&zone.region()) {
+            regions.push(zone.region());
+        }
+    }
+    regions
+}
+
+pub fn in_region(region: &str) -> impl Iterator<Item = &'static Zone> + '_ {
+    ZONES.iter().filter(move |zone| zone.region() == region)
+}
+
+/// `UTC+5:30`, `UTC-3`, or `UTC` for zero.
+pub fn offset_label(minutes: i32) -> String {
+    if minutes == 0 {
+        return "UTC".to_string();
+    }
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let (hours, minutes) = (minutes.abs() / 60, minutes.abs() % 60);
+    if minutes == 0 {
+        format!("UTC{sign}{hours}")
+    } else {
+        format!("UTC{sign}{hours}:{minutes:02}")
+    }
+}
@@ -1,31 +1,62 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
 use cst816s::{TouchEvent as CstTouchEvent, CST816S};
 use esp_idf_svc::hal::{
     delay::Delay,
-    gpio::{Gpio0, Gpio1, Gpio16, Gpio18, Input, Output, PinDriver, Pull},
+    gpio::{AnyIOPin, AnyOutputPin, Input, Output, PinDriver, Pull},
     i2c::{config::Config as I2cConfig, I2cDriver, I2C0},
     units::Hertz,
 };
 use slint::SharedString;
-
-use crate::gui::slint_ui::{self, PointerAction, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use tokio::sync::Notify;
+
+use crate::{
+    board,
+    errors::{self, Severity},
+    gui::{
+        backlight, fallback, focus,
+        slint_ui::{self, Gesture, PointerAction, DISPLAY_HEIGHT, DISPLAY_WIDTH},
+        touch_trace,
+    },
+    sensors::imu::{self, ScreenRotation},
+};
 
 const POLL_INTERVAL: Duration = Duration::from_millis(10);
 const I2C_FREQUENCY: Hertz = Hertz(400_000);
+const SWIPE_MIN_DISTANCE: f32 = 50.0;
+const SWIPE_MAX_CROSS_DISTANCE: f32 = 40.0;
+/// A rightward swipe starting this close to the left edge means "back",
+/// like swipe-to-dismiss on other watch UIs.
+const BACK_EDGE_WIDTH: f32 = 30.0;
+
+/// Poked by the Errors page's retry after the touch loop has stopped.
+static RETRY: Notify = Notify::const_new();
 
 type TouchController = CST816S<
     I2cDriver<'static>,
-    PinDriver<'static, Gpio1, Input>,
-    PinDriver<'static, Gpio0, Output>,
+    PinDriver<'static, AnyIOPin, Input>,
+    PinDriver<'static, AnyOutputPin, Output>,
 >;
 
 pub struct TouchPins {
-    pub sda: Gpio18,
-    pub scl: Gpio16,
-    pub interrupt: Gpio1,
-    pub reset: Gpio0,
+    pub sda: AnyIOPin,
+    pub scl: AnyIOPin,
+    pub interrupt: AnyIOPin,
+    pub reset: AnyOutputPin,
+}
+
+impl TouchPins {
+    /// None when the board has no touch panel.
+    pub fn from_board() -> Option<Self> {
+        let gpios = board::CURRENT.touch?;
+        Some(Self {
+            sda: board::io_pin(gpios.sda),
+            scl: board::io_pin(gpios.scl),
+            interrupt: board::io_pin(gpios.int),
+            reset: board::output_pin(gpios.rst),
+        })
+    }
 }
 
 pub fn spawn_touch_task(i2c: I2C0, pins: TouchPins) -> Result<()> {
@@ -43,32 +74,76 @@ pub fn spawn_touch_task(i2c: I2C0, pins: TouchPins) -> Result<()> {
     int_pin.set_pull(Pull::Up)?;
     let rst_pin = PinDriver::output(reset)?;
 
-    let mut delay = Delay::new_default();
     let mut controller = CST816S::new(i2c, int_pin, rst_pin);
-    controller
-        .setup(&mut delay)
-        .map_err(|err| anyhow!("touch controller setup failed: {:?}", err))?;
+    setup(&mut controller)?;
 
+    errors::register_retry("touch", || RETRY.notify_waiters());
     tokio::task::spawn_local(async move {
-        if let Err(err) = touch_loop(controller).await {
-            log::error!("touch loop exited: {err:?}");
+        loop {
+            if let Err(err) = touch_loop(&mut controller).await {
+                log::error!("touch loop exited: {err:?}");
+                errors::report("touch", Severity::Error, format!("{err:#}"));
+            }
+            // Stopped until a retry gets the controller set up again.
+            loop {
+                RETRY.notified().await;
+                match setup(&mut controller) {
+                    Ok(()) => break,
+                    Err(err) => {
+                        log::error!("touch re-init failed: {err:?}");
+                        errors::report("touch", Severity::Error, format!("{err:#}"));
+                    }
+                }
+            }
+            errors::resolve("touch");
         }
     });
 
     Ok(())
 }
 
-async fn touch_loop(mut controller: TouchController) -> Result<()> {
-    let mut pointer_active = false;
+/// Resets the controller through its reset line and configures it.
+fn setup(controller: &mut TouchController) -> Result<()> {
+    controller
+        .setup(&mut Delay::new_default())
+        .map_err(|err| anyhow!("touch controller setup failed: {:?}", err))
+}
+
+#[derive(Default)]
+struct TouchState {
+    active: bool,
+    origin: (f32, f32),
+    /// The current touch woke the screen; it is dropped until released.
+    waking: bool,
+}
+
+async fn touch_loop(controller: &mut TouchController) -> Result<()> {
+    let mut state = TouchState::default();
     loop {
         if let Some(event) = controller.read_one_touch_event(true) {
-            pointer_active = handle_touch_event(event, pointer_active)?;
+            handle_touch_event(event, Instant::now(), &mut state)?;
         }
         tokio::time::sleep(POLL_INTERVAL).await;
     }
 }
 
-fn handle_touch_event(event: CstTouchEvent, pointer_active: bool) -> Result<bool> {
+fn handle_touch_event(
+    event: CstTouchEvent,
+    captured_at: Instant,
+    state: &mut TouchState,
+) -> Result<()> {
+    // No Slint app to talk to; the fallback screen only needs hold timing.
+    if fallback::active() {
+        fallback::on_touch(matches!(event.action, 0 | 2), captured_at);
+        return Ok(());
+    }
+
+    if state.waking || backlight::on_input() {
+        state.waking = event.action != 1;
+        return Ok(());
+    }
+
+    let raw = (event.x as f32, event.y as f32);
     let (x, y) = normalize_coordinates(event.x, event.y);
     let action_desc = match event.action {
         0 => "down",
@@ -82,34 +157,83 @@ fn handle_touch_event(event: CstTouchEvent, pointer_active: bool) -> Result<bool
         action = action_desc
     )));
 
-    let mut still_active = pointer_active;
     match event.action {
         0 => {
-            slint_ui::dispatch_pointer_action(PointerAction::Press, (x, y))?;
-            still_active = true;
+            focus::on_touch();
+            touch_trace::record_press(raw, (x, y));
+            slint_ui::dispatch_pointer_action(PointerAction::Press, (x, y), Some(captured_at))?;
+            state.active = true;
+            state.origin = (x, y);
         }
         1 => {
-            if still_active {
-                slint_ui::dispatch_pointer_action(PointerAction::Release, (x, y))?;
+            if state.active {
+                touch_trace::record_release(raw, (x, y));
+                slint_ui::dispatch_pointer_action(
+                    PointerAction::Release,
+                    (x, y),
+                    Some(captured_at),
+                )?;
+                if let Some(gesture) = classify_swipe(state.origin, (x, y)) {
+                    touch_trace::record_gesture(gesture, (x, y));
+                    if gesture == Gesture::SwipeRight && state.origin.0 <= BACK_EDGE_WIDTH {
+                        slint_ui::back();
+                    } else {
+                        slint_ui::dispatch_gesture(gesture);
+                    }
+                }
+            } else {
+                touch_trace::record_rejected(raw, (x, y));
             }
-            still_active = false;
+            state.active = false;
         }
         2 => {
-            if still_active {
-                slint_ui::dispatch_pointer_action(PointerAction::Move, (x, y))?;
+            if state.active {
+                touch_trace::record_move(raw, (x, y));
+                slint_ui::dispatch_pointer_action(PointerAction::Move, (x, y), Some(captured_at))?;
             } else {
-                slint_ui::dispatch_pointer_action(PointerAction::Press, (x, y))?;
-                still_active = true;
+                touch_trace::record_press(raw, (x, y));
+                slint_ui::dispatch_pointer_action(PointerAction::Press, (x, y), Some(captured_at))?;
+                state.active = true;
+                state.origin = (x, y);
             }
         }
-        _ => {}
+        _ => touch_trace::record_rejected(raw, (x, y)),
     }
 
-    Ok(still_active)
+    Ok(())
+}
+
+fn classify_swipe(origin: (f32, f32), end: (f32, f32)) -> Option<Gesture> {
+    let dx = end.0 - origin.0;
+    let dy = end.1 - origin.1;
+    if dy.abs() >= SWIPE_MIN_DISTANCE && dx.abs() <= SWIPE_MAX_CROSS_DISTANCE {
+        Some(if dy > 0.0 {
+            Gesture::SwipeDown
+        } else {
+            Gesture::SwipeUp
+        })
+    } else if dx.abs() >= SWIPE_MIN_DISTANCE && dy.abs() <= SWIPE_MAX_CROSS_DISTANCE {
+        Some(if dx > 0.0 {
+            Gesture::SwipeRight
+        } else {
+            Gesture::SwipeLeft
+        })
+    } else {
+        None
+    }
 }
 
+/// Panel coordinates to UI coordinates, undoing the auto-rotation.
 fn normalize_coordinates(raw_x: i32, raw_y: i32) -> (f32, f32) {
-    let x = raw_x.clamp(0, (DISPLAY_WIDTH.saturating_sub(1)) as i32) as f32;
-    let y = raw_y.clamp(0, (DISPLAY_HEIGHT.saturating_sub(1)) as i32) as f32;
-    (x, y)
+    let max_x = DISPLAY_WIDTH.saturating_sub(1) as f32;
+    let max_y = DISPLAY_HEIGHT.saturating_sub(1) as f32;
+    let x = (raw_x as f32).clamp(0.0, max_x);
+    let y = (raw_y as f32).clamp(0.0, max_y);
+    // The panel is square, so the axes can swap without rescaling.
+    match imu::rotation() {
+        ScreenRotation::Deg0 => (x, y),
+        ScreenRotation::Deg90 => (y, max_x - x),
+        ScreenRotation::Deg180 => (max_x - x, max_y - y),
+        ScreenRotation::Deg270 => (max_y - y, x),
+    }
 }
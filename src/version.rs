@@ -0,0 +1,19 @@
+pub const MANUFACTURER: &str = "AstralSight Studios";
+pub const MODEL: &str = "AstroBox Pocket";
+pub const FIRMWARE: &str = env!("CARGO_PKG_VERSION");
+
+/// Strings this device reports about itself over BLE.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceInfo {
+    pub manufacturer: &'static str,
+    pub model: &'static str,
+    pub firmware: &'static str,
+}
+
+pub const fn device_info() -> DeviceInfo {
+    DeviceInfo {
+        manufacturer: MANUFACTURER,
+        model: MODEL,
+        firmware: FIRMWARE,
+    }
+}
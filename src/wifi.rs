@@ -0,0 +1,341 @@
+use core::convert::TryInto;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, OnceLock,
+};
+
+use anyhow::{anyhow, bail, Result};
+use esp_idf_svc::{
+    eventloop::{EspSubscription, EspSystemEventLoop, System},
+    hal::modem::Modem,
+    nvs::EspDefaultNvsPartition,
+    wifi::{
+        AccessPointInfo, AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi,
+        WifiEvent,
+    },
+};
+use tokio::sync::oneshot;
+
+use crate::{
+    activity, metrics, secrets,
+    settings::{
+        self,
+        rollback::{self, Subsystem},
+        SettingKey,
+    },
+};
+
+pub mod country;
+
+/// Empty until the setup wizard or the Networks page joins one.
+pub const SSID: SettingKey<String> = SettingKey::new("wifi_ssid", "");
+/// Kept in [`secrets`].
+pub const PASSWORD: SettingKey<String> = SettingKey::new("wifi_pass", "");
+
+const WORKER_STACK_SIZE: usize = 8 * 1024;
+
+static WIFI: Mutex<Option<BlockingWifi<EspWifi<'static>>>> = Mutex::new(None);
+/// Kept from `init` so [`resume`] can rebuild the driver.
+static DRIVER_DEPS: OnceLock<(EspSystemEventLoop, EspDefaultNvsPartition)> = OnceLock::new();
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+/// Counts station disconnects for `metrics`; lives as long as the event loop.
+static DISCONNECT_WATCH: Mutex<Option<EspSubscription<'static, System>>> = Mutex::new(None);
+
+#[derive(Clone, Debug)]
+pub struct ScannedNetwork {
+    pub ssid: String,
+    pub rssi: i8,
+    pub channel: u8,
+    pub auth: Option<AuthMethod>,
+}
+
+impl ScannedNetwork {
+    fn from_info(info: &AccessPointInfo) -> Self {
+        Self {
+            ssid: info.ssid.to_string(),
+            rssi: info.signal_strength,
+            channel: info.channel,
+            auth: info.auth_method,
+        }
+    }
+
+    pub fn is_secured(&self) -> bool {
+        !matches!(self.auth, None | Some(AuthMethod::None))
+    }
+}
+
+pub struct ScanOutcome {
+    pub networks: Vec<ScannedNetwork>,
+    /// The STA link had to be dropped to scan and was re-established afterwards.
+    pub reconnected: bool,
+}
+
+pub fn init(modem: Modem, sys_loop: EspSystemEventLoop, nvs: EspDefaultNvsPartition) -> Result<()> {
+    let _ = DRIVER_DEPS.set((sys_loop.clone(), nvs.clone()));
+    match sys_loop.subscribe::<WifiEvent, _>(|event| {
+        if matches!(event, WifiEvent::StaDisconnected(_)) {
+            metrics::record(metrics::Counter::WifiDisconnects, 1);
+        }
+    }) {
+        Ok(subscription) => {
+            if let Ok(mut slot) = DISCONNECT_WATCH.lock() {
+                *slot = Some(subscription);
+            }
+        }
+        Err(err) => log::warn!("Wi-Fi disconnects will not be counted: {err:?}"),
+    }
+    match bring_up(modem, sys_loop.clone(), nvs.clone()) {
+        Err(err) if rollback::restore_at_boot(Subsystem::Wifi) => {
+            log::warn!("Wi-Fi failed after a settings change ({err:#}); retrying the previous one");
+            // Drops the failed driver, and with it the modem.
+            drop(
+                WIFI.lock()
+                    .map_err(|_| anyhow!("Wi-Fi state poisoned"))?
+                    .take(),
+            );
+            bring_up(unsafe { Modem::new() }, sys_loop, nvs)
+        }
+        result => result,
+    }
+}
+
+fn bring_up(modem: Modem, sys_loop: EspSystemEventLoop, nvs: EspDefaultNvsPartition) -> Result<()> {
+    let mut wifi = BlockingWifi::wrap(EspWifi::new(modem, sys_loop.clone(), Some(nvs))?, sys_loop)?;
+
+    let ssid = settings::get(&SSID);
+    let password = secrets::get(&PASSWORD);
+    wifi.set_configuration(&client_configuration(&ssid, &password)?)?;
+    if let Err(err) = country::apply() {
+        log::warn!("Keeping the driver's default Wi-Fi country: {err:#}");
+    }
+    wifi.start()?;
+    log::info!("Wi-Fi started");
+
+    // Kept when joining fails, so scans and new credentials still work.
+    let mut slot = WIFI.lock().map_err(|_| anyhow!("Wi-Fi state poisoned"))?;
+    let wifi = slot.insert(wifi);
+    if ssid.is_empty() {
+        log::info!("No Wi-Fi network configured");
+        return Ok(());
+    }
+
+    wifi.connect()?;
+    log::info!("Wi-Fi connected to {}", ssid);
+    note_connected_channel();
+
+    wifi.wait_netif_up()?;
+    log::info!("Wi-Fi network interface is up");
+    Ok(())
+}
+
+/// Deinitializes the driver, returning its internal-RAM buffers to the heap.
+/// Scans and credential changes fail until [`resume`].
+pub fn suspend() -> Result<()> {
+    let wifi = WIFI
+        .lock()
+        .map_err(|_| anyhow!("Wi-Fi state poisoned"))?
+        .take();
+    if wifi.is_some() {
+        SUSPENDED.store(true, Ordering::Release);
+    }
+    drop(wifi);
+    Ok(())
+}
+
+/// Rebuilds the driver after [`suspend`] and reconnects on a worker thread.
+pub fn resume() -> Result<()> {
+    if !SUSPENDED.swap(false, Ordering::AcqRel) {
+        return Ok(());
+    }
+    let (sys_loop, nvs) = DRIVER_DEPS
+        .get()
+        .cloned()
+        .ok_or_else(|| anyhow!("Wi-Fi was never initialized"))?;
+    std::thread::Builder::new()
+        .name("wifi-resume".to_string())
+        .stack_size(WORKER_STACK_SIZE)
+        .spawn(move || {
+            // The previous driver, and with it the modem, was dropped in `suspend`.
+            let modem = unsafe { Modem::new() };
+            if let Err(err) = bring_up(modem, sys_loop, nvs) {
+                log::error!("Wi-Fi resume failed: {err:?}");
+            }
+        })?;
+    Ok(())
+}
+
+pub fn suspended() -> bool {
+    SUSPENDED.load(Ordering::Acquire)
+}
+
+/// Associated and holding an IP. Never waits: false while another call
+/// has the driver.
+pub fn connected() -> bool {
+    WIFI.try_lock()
+        .ok()
+        .and_then(|wifi| wifi.as_ref().map(|wifi| wifi.is_up().unwrap_or(false)))
+        .unwrap_or(false)
+}
+
+pub fn auth_label(auth: Option<AuthMethod>) -> &'static str {
+    match auth {
+        None | Some(AuthMethod::None) => "Open",
+        Some(AuthMethod::WEP) => "WEP",
+        Some(AuthMethod::WPA) => "WPA",
+        Some(AuthMethod::WPA2Personal) => "WPA2",
+        Some(AuthMethod::WPAWPA2Personal) => "WPA/WPA2",
+        Some(AuthMethod::WPA2Enterprise) => "WPA2-EAP",
+        Some(AuthMethod::WPA3Personal) => "WPA3",
+        Some(AuthMethod::WPA2WPA3Personal) => "WPA2/WPA3",
+        Some(_) => "Other",
+    }
+}
+
+/// Scans for nearby access points, sorted by descending RSSI.
+///
+/// Refuses to run while an OTA update or watch transfer is in progress, since
+/// some IDF versions require dropping the STA link for the duration of a scan.
+pub async fn scan() -> Result<ScanOutcome> {
+    if let Some(activity) = activity::busy() {
+        bail!("scan unavailable while {} is running", activity.label());
+    }
+
+    let mut outcome = run_blocking("wifi-scan", scan_blocking).await?;
+    outcome
+        .networks
+        .sort_by(|a, b| b.rssi.cmp(&a.rssi).then_with(|| a.ssid.cmp(&b.ssid)));
+    let configured = settings::get(&SSID);
+    if let Some(network) = outcome.networks.iter().find(|net| net.ssid == configured) {
+        country::note_ap_channel(network.channel);
+    }
+    Ok(outcome)
+}
+
+/// Remembers the joined AP's channel for [`country::excluded_ap_channel`].
+fn note_connected_channel() {
+    let mut info = esp_idf_svc::sys::wifi_ap_record_t::default();
+    if unsafe { esp_idf_svc::sys::esp_wifi_sta_get_ap_info(&mut info) } == esp_idf_svc::sys::ESP_OK
+    {
+        country::note_ap_channel(info.primary);
+    }
+}
+
+/// Connects with new credentials and persists them once they get an IP, so
+/// a typo is never used again at boot. On failure the stored network is
+/// rejoined. The old credentials are kept for [`rollback`] until the new
+/// ones validate.
+pub async fn apply_credentials(ssid: String, password: String) -> Result<()> {
+    let config = client_configuration(&ssid, &password)?;
+    let attempt = ssid.clone();
+    if let Err(err) =
+        run_blocking("wifi-apply", move || reconnect_blocking(&attempt, &config)).await
+    {
+        if let Err(err) = reconnect().await {
+            log::warn!("Rejoining the stored Wi-Fi network failed: {err:#}");
+        }
+        return Err(err);
+    }
+    rollback::before_change(Subsystem::Wifi);
+    settings::set(&SSID, &ssid)?;
+    secrets::set(&PASSWORD, &password)?;
+    Ok(())
+}
+
+/// Reconnects with the stored credentials and country; for retrying after
+/// a rollback restored them.
+pub async fn reconnect() -> Result<()> {
+    let ssid = settings::get(&SSID);
+    let config = client_configuration(&ssid, &secrets::get(&PASSWORD))?;
+    run_blocking("wifi-reconnect", move || {
+        with_wifi(|_| country::apply())?;
+        reconnect_blocking(&ssid, &config)
+    })
+    .await
+}
+
+fn reconnect_blocking(ssid: &str, config: &Configuration) -> Result<()> {
+    with_wifi(|wifi| {
+        if wifi.is_connected().unwrap_or(false) {
+            let _ = wifi.disconnect();
+        }
+        wifi.set_configuration(config)?;
+        wifi.connect()?;
+        wifi.wait_netif_up()?;
+        log::info!("Wi-Fi reconnected to {}", ssid);
+        note_connected_channel();
+        Ok(())
+    })
+}
+
+fn scan_blocking() -> Result<ScanOutcome> {
+    with_wifi(|wifi| {
+        let connected = wifi.is_connected().unwrap_or(false);
+        match wifi.scan() {
+            Ok(aps) => Ok(ScanOutcome {
+                networks: aps.iter().map(ScannedNetwork::from_info).collect(),
+                reconnected: false,
+            }),
+            Err(err) if connected => {
+                log::warn!(
+                    "Wi-Fi scan failed while connected ({err:?}); disconnecting STA to retry"
+                );
+                let _ = wifi.disconnect();
+                let result = wifi.scan();
+                if let Err(err) = wifi.connect().and_then(|_| wifi.wait_netif_up()) {
+                    log::error!("Wi-Fi reconnect after scan failed: {err:?}");
+                }
+                Ok(ScanOutcome {
+                    networks: result?.iter().map(ScannedNetwork::from_info).collect(),
+                    reconnected: true,
+                })
+            }
+            Err(err) => Err(err.into()),
+        }
+    })
+}
+
+fn with_wifi<R>(f: impl FnOnce(&mut BlockingWifi<EspWifi<'static>>) -> Result<R>) -> Result<R> {
+    let mut guard = WIFI.lock().map_err(|_| anyhow!("Wi-Fi state poisoned"))?;
+    let Some(wifi) = guard.as_mut() else {
+        if SUSPENDED.load(Ordering::Acquire) {
+            bail!("Wi-Fi is off while memory is low");
+        }
+        bail!("Wi-Fi not initialized");
+    };
+    f(wifi)
+}
+
+fn client_configuration(ssid: &str, password: &str) -> Result<Configuration> {
+    let auth_method = if password.is_empty() {
+        AuthMethod::None
+    } else {
+        AuthMethod::WPA2Personal
+    };
+    Ok(Configuration::Client(ClientConfiguration {
+        ssid: ssid
+            .try_into()
+            .map_err(|_| anyhow!("Wi-Fi SSID is too long"))?,
+        password: password
+            .try_into()
+            .map_err(|_| anyhow!("Wi-Fi password is too long"))?,
+        auth_method,
+        ..Default::default()
+    }))
+}
+
+/// Runs blocking Wi-Fi driver calls on a short-lived worker thread so the UI
+/// executor keeps rendering while the driver waits on the radio.
+async fn run_blocking<T: Send + 'static>(
+    name: &str,
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let (tx, rx) = oneshot::channel();
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .stack_size(WORKER_STACK_SIZE)
+        .spawn(move || {
+            let _ = tx.send(f());
+        })?;
+    rx.await
+        .map_err(|_| anyhow!("{name} worker exited unexpectedly"))?
+}
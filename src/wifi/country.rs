@@ -0,0 +1,254 @@
+//! Wi-Fi regulatory domain: the 2.4 GHz channels the station scans and joins
+//! on, and its TX power cap. The IDF default only covers channels 1 to 11,
+//! so an AP on 12 or 13 never shows up in a scan. `auto` starts from the
+//! world-safe domain with 12 and 13 scanned passively and adopts the country
+//! an AP advertises once connected; a code pins one of [`PLANS`].
+//!
+//! Applied before the driver starts and again whenever the setting changes,
+//! so the next scan or connect already uses it.
+
+use std::{
+    ffi::c_char,
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use anyhow::{Context, Result};
+use esp_idf_svc::sys::{
+    esp, esp_wifi_get_country, esp_wifi_set_country,
+    wifi_country_policy_t_WIFI_COUNTRY_POLICY_AUTO,
+    wifi_country_policy_t_WIFI_COUNTRY_POLICY_MANUAL, wifi_country_t,
+};
+
+use crate::settings::{
+    self,
+    rollback::{self, Subsystem},
+    SettingKey,
+};
+
+pub const COUNTRY: SettingKey<Country> = SettingKey::new("wifi_country", "auto");
+
+/// The ESP32-S3's own ceiling; no plan here allows less.
+const MAX_TX_DBM: i8 = 20;
+/// `auto`: world-safe code, with 12 and 13 passive until an AP says more.
+const AUTO_PLAN: Plan = Plan {
+    code: "01",
+    first: 1,
+    channels: 13,
+};
+
+/// Last channel the configured AP was seen or joined on; 0 when unknown.
+static AP_CHANNEL: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Plan {
+    pub code: &'static str,
+    pub first: u8,
+    pub channels: u8,
+}
+
+pub const PLANS: [Plan; 12] = [
+    plan("01", 11),
+    plan("US", 11),
+    plan("CA", 11),
+    plan("TW", 11),
+    plan("CN", 13),
+    plan("EU", 13),
+    plan("GB", 13),
+    plan("AU", 13),
+    plan("KR", 13),
+    plan("IN", 13),
+    plan("RU", 13),
+    plan("JP", 14),
+];
+
+const fn plan(code: &'static str, channels: u8) -> Plan {
+    Plan {
+        code,
+        first: 1,
+        channels,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Country {
+    /// Follow the AP.
+    Auto,
+    Fixed(&'static Plan),
+}
+
+impl Country {
+    fn plan(self) -> &'static Plan {
+        match self {
+            Country::Auto => &AUTO_PLAN,
+            Country::Fixed(plan) => plan,
+        }
+    }
+}
+
+impl fmt::Display for Country {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Country::Auto => f.write_str("auto"),
+            Country::Fixed(plan) => f.write_str(plan.code),
+        }
+    }
+}
+
+impl FromStr for Country {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(Country::Auto);
+        }
+        PLANS
+            .iter()
+            .find(|plan| plan.code.eq_ignore_ascii_case(s))
+            .map(Country::Fixed)
+            .ok_or(())
+    }
+}
+
+/// What the driver is using right now. Under `auto` this is the AP's
+/// country once connected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub code: String,
+    pub first: u8,
+    pub channels: u8,
+    pub max_tx_dbm: i8,
+}
+
+impl Region {
+    pub fn last(&self) -> u8 {
+        self.first + self.channels.saturating_sub(1)
+    }
+
+    pub fn allows(&self, channel: u8) -> bool {
+        (self.first..=self.last()).contains(&channel)
+    }
+}
+
+/// Hands the configured domain to the driver. Needs the driver initialized;
+/// takes effect on the next scan or connect.
+pub fn apply() -> Result<()> {
+    let country = settings::get(&COUNTRY);
+    let plan = country.plan();
+    let mut cc: [c_char; 3] = [0; 3];
+    for (slot, byte) in cc.iter_mut().zip(plan.code.bytes()) {
+        *slot = byte as c_char;
+    }
+    let config = wifi_country_t {
+        cc,
+        schan: plan.first,
+        nchan: plan.channels,
+        max_tx_power: MAX_TX_DBM,
+        policy: match country {
+            Country::Auto => wifi_country_policy_t_WIFI_COUNTRY_POLICY_AUTO,
+            Country::Fixed(_) => wifi_country_policy_t_WIFI_COUNTRY_POLICY_MANUAL,
+        },
+        ..Default::default()
+    };
+    esp!(unsafe { esp_wifi_set_country(&config) })
+        .with_context(|| format!("set Wi-Fi country {country}"))?;
+    log::info!(
+        "Wi-Fi country {country}: channels {}-{}",
+        plan.first,
+        plan.first + plan.channels - 1
+    );
+    Ok(())
+}
+
+/// `None` while the driver is down.
+pub fn active() -> Option<Region> {
+    let mut config = wifi_country_t::default();
+    esp!(unsafe { esp_wifi_get_country(&mut config) }).ok()?;
+    let code: String = config
+        .cc
+        .iter()
+        .take(2)
+        .map(|&byte| byte as u8 as char)
+        .filter(char::is_ascii_alphanumeric)
+        .collect();
+    Some(Region {
+        code,
+        first: config.schan,
+        channels: config.nchan,
+        max_tx_dbm: config.max_tx_power,
+    })
+}
+
+pub fn note_ap_channel(channel: u8) {
+    if channel != 0 {
+        AP_CHANNEL.store(channel, Ordering::Relaxed);
+    }
+}
+
+pub fn ap_channel() -> Option<u8> {
+    match AP_CHANNEL.load(Ordering::Relaxed) {
+        0 => None,
+        channel => Some(channel),
+    }
+}
+
+/// The configured AP's last known channel, when the active region excludes
+/// it: the AP would silently vanish from scans.
+pub fn excluded_ap_channel() -> Option<u8> {
+    let channel = ap_channel()?;
+    (!active()?.allows(channel)).then_some(channel)
+}
+
+/// One line for the console and the Networks page.
+pub fn describe() -> String {
+    let setting = settings::get(&COUNTRY);
+    match active() {
+        Some(region) => format!(
+            "{setting} ({}), ch {}-{}, {} dBm",
+            region.code,
+            region.first,
+            region.last(),
+            region.max_tx_dbm
+        ),
+        None => format!("{setting}, Wi-Fi off"),
+    }
+}
+
+/// Saves `country` and hands it to the driver if it is up; the next scan or
+/// connect uses it, no reboot needed.
+pub fn set(country: Country) -> Result<()> {
+    rollback::before_change(Subsystem::Wifi);
+    settings::set(&COUNTRY, &country)?;
+    match super::with_wifi(|_| apply()) {
+        Ok(()) => Ok(()),
+        // Applied by `bring_up` when the driver comes back.
+        Err(_) if active().is_none() => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn register_commands() {
+    crate::console::register(
+        "wifi",
+        "country [auto|<code>]: Wi-Fi regulatory domain",
+        |args| match args {
+            ["country"] => Ok(describe()),
+            ["country", code] => {
+                let country: Country = code.parse().map_err(|_| {
+                    let codes: Vec<&str> = PLANS.iter().map(|plan| plan.code).collect();
+                    anyhow::anyhow!("unknown country {code}; auto or one of {}", codes.join(" "))
+                })?;
+                set(country)?;
+                Ok(match excluded_ap_channel() {
+                    Some(channel) => format!(
+                        "{}; the configured AP was last on channel {channel}, outside it",
+                        describe()
+                    ),
+                    None => format!("{}; rescan to refresh the list", describe()),
+                })
+            }
+            _ => anyhow::bail!("usage: wifi country [auto|<code>]"),
+        },
+    );
+}